@@ -0,0 +1,170 @@
+//! Benchmarks of the message codec, over payloads typical of an editing session.
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+use tokio_util::codec::{Decoder, Encoder};
+use tower_lsp::codec::LanguageServerCodec;
+use tower_lsp::jsonrpc::{Message, Request, Response};
+
+/// Size of the chunks in which large messages arrive, matching the pipe buffers of most platforms.
+const CHUNK_SIZE: usize = 4096;
+
+/// A keystroke, sent as an incremental `textDocument/didChange` notification.
+fn did_change() -> Message {
+    let params = json!({
+        "textDocument": {"uri": "file:///src/main.rs", "version": 42},
+        "contentChanges": [{
+            "range": {"start": {"line": 10, "character": 4}, "end": {"line": 10, "character": 4}},
+            "text": "x",
+        }],
+    });
+    Request::build("textDocument/didChange")
+        .params(params)
+        .finish()
+        .into()
+}
+
+/// A `textDocument/completion` response listing a few hundred items.
+fn completion() -> Message {
+    let items: Vec<Value> = (0..300)
+        .map(|i| {
+            json!({
+                "label": format!("candidate_{i}"),
+                "kind": 3,
+                "detail": format!("fn candidate_{i}(value: usize) -> Option<String>"),
+                "sortText": format!("{i:05}"),
+                "textEdit": {
+                    "range": {"start": {"line": 10, "character": 4}, "end": {"line": 10, "character": 5}},
+                    "newText": format!("candidate_{i}"),
+                },
+            })
+        })
+        .collect();
+    let result = json!({"isIncomplete": false, "items": items});
+    Response::from_ok(7.into(), result).into()
+}
+
+/// A `textDocument/didOpen` notification carrying a large source file.
+fn did_open() -> Message {
+    let line = "    let value = compute(&input, Options::default()).unwrap_or_default();\n";
+    let params = json!({
+        "textDocument": {
+            "uri": "file:///src/generated.rs",
+            "languageId": "rust",
+            "version": 1,
+            "text": line.repeat(4096),
+        },
+    });
+    Request::build("textDocument/didOpen")
+        .params(params)
+        .finish()
+        .into()
+}
+
+/// A `textDocument/semanticTokens/full` response for a large file.
+fn semantic_tokens() -> Message {
+    let data: Vec<u32> = (0..50_000).map(|i| i % 17).collect();
+    Response::from_ok(8.into(), json!({"resultId": "1", "data": data})).into()
+}
+
+fn payloads() -> Vec<(&'static str, Message)> {
+    vec![
+        ("did_change", did_change()),
+        ("completion", completion()),
+        ("did_open", did_open()),
+        ("semantic_tokens", semantic_tokens()),
+    ]
+}
+
+fn encode(messages: impl IntoIterator<Item = Message>) -> BytesMut {
+    let mut codec = LanguageServerCodec::default();
+    let mut buffer = BytesMut::new();
+    for msg in messages {
+        codec.encode(msg, &mut buffer).unwrap();
+    }
+    buffer
+}
+
+/// Decodes every message in `buffer`, returning how many there were.
+fn decode_all(mut buffer: BytesMut) -> usize {
+    let mut codec = LanguageServerCodec::<Message>::default();
+    let mut decoded = 0;
+    while codec.decode(&mut buffer).unwrap().is_some() {
+        decoded += 1;
+    }
+    decoded
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec/decode");
+    for (name, msg) in payloads() {
+        let frame = encode([msg]);
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frame, |b, frame| {
+            b.iter_batched(|| frame.clone(), decode_all, BatchSize::SmallInput);
+        });
+    }
+
+    // A burst of keystrokes followed by the requests they trigger.
+    let session = encode(
+        std::iter::repeat_with(did_change)
+            .take(50)
+            .chain([completion(), semantic_tokens()]),
+    );
+    group.throughput(Throughput::Bytes(session.len() as u64));
+    group.bench_with_input(
+        BenchmarkId::from_parameter("session"),
+        &session,
+        |b, frame| {
+            b.iter_batched(|| frame.clone(), decode_all, BatchSize::SmallInput);
+        },
+    );
+    group.finish();
+}
+
+fn bench_decode_chunked(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec/decode_chunked");
+    for (name, msg) in payloads() {
+        let frame = encode([msg]);
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frame, |b, frame| {
+            // Decoding is attempted after every chunk, as when reading from a pipe, so the cost of
+            // rescanning partial messages adds up for large payloads.
+            b.iter(|| {
+                let mut codec = LanguageServerCodec::<Message>::default();
+                let mut buffer = BytesMut::with_capacity(frame.len());
+                let mut decoded = None;
+                for chunk in frame.chunks(CHUNK_SIZE) {
+                    buffer.extend_from_slice(chunk);
+                    decoded = codec.decode(&mut buffer).unwrap();
+                }
+                decoded.expect("message should be complete")
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codec/encode");
+    for (name, msg) in payloads() {
+        group.throughput(Throughput::Bytes(encode([msg.clone()]).len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &msg, |b, msg| {
+            let mut codec = LanguageServerCodec::default();
+            let mut buffer = BytesMut::new();
+            b.iter_batched(
+                || msg.clone(),
+                |msg| {
+                    buffer.clear();
+                    codec.encode(msg, &mut buffer).unwrap();
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_decode_chunked, bench_encode);
+criterion_main!(benches);
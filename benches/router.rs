@@ -0,0 +1,129 @@
+//! Benchmarks of the dispatch of messages through an `LspService`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::join_all;
+use serde_json::json;
+use tokio::runtime::{Builder, Runtime};
+use tower::{Service, ServiceExt};
+use tower_lsp::jsonrpc::{Request, Result};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{LanguageServer, LspService};
+
+struct Backend;
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_change(&self, _: DidChangeTextDocumentParams) {}
+
+    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String("docs".into())),
+            range: None,
+        }))
+    }
+}
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().build().unwrap()
+}
+
+/// Returns a service which went through the `initialize` handshake.
+fn initialized_service(rt: &Runtime) -> LspService<Backend> {
+    let (mut service, _) = LspService::new(|_| Backend);
+    let initialize = Request::build("initialize")
+        .params(json!({"capabilities": {}}))
+        .id(0)
+        .finish();
+    let initialized = Request::build("initialized").params(json!({})).finish();
+
+    rt.block_on(async {
+        for req in [initialize, initialized] {
+            service.ready().await.unwrap().call(req).await.unwrap();
+        }
+    });
+    service
+}
+
+fn did_change(version: i32) -> Request {
+    let params = json!({
+        "textDocument": {"uri": "file:///src/main.rs", "version": version},
+        "contentChanges": [{"text": "fn main() {}\n"}],
+    });
+    Request::build("textDocument/didChange")
+        .params(params)
+        .finish()
+}
+
+fn hover(id: i64) -> Request {
+    let params = json!({
+        "textDocument": {"uri": "file:///src/main.rs"},
+        "position": {"line": 0, "character": 3},
+    });
+    Request::build("textDocument/hover")
+        .params(params)
+        .id(id)
+        .finish()
+}
+
+/// Dispatches every request in `batch` before awaiting all of their responses.
+async fn dispatch(service: &mut LspService<Backend>, batch: Vec<Request>) {
+    let mut responses = Vec::with_capacity(batch.len());
+    for req in batch {
+        responses.push(service.ready().await.unwrap().call(req));
+    }
+    for res in join_all(responses).await {
+        res.unwrap();
+    }
+}
+
+fn bench_request(c: &mut Criterion) {
+    let rt = runtime();
+    let mut service = initialized_service(&rt);
+    c.bench_function("router/request/hover", |b| {
+        b.iter(|| rt.block_on(dispatch(&mut service, vec![hover(1)])));
+    });
+}
+
+fn bench_notifications(c: &mut Criterion) {
+    let rt = runtime();
+    let mut service = initialized_service(&rt);
+    let mut group = c.benchmark_group("router/notifications");
+    for count in [1, 16, 64] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                let batch = (0..count).map(did_change).collect();
+                rt.block_on(dispatch(&mut service, batch));
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_mixed(c: &mut Criterion) {
+    let rt = runtime();
+    let mut service = initialized_service(&rt);
+    c.bench_function("router/mixed", |b| {
+        // Keystrokes interleaved with the hover requests they trigger.
+        b.iter(|| {
+            let batch = (0..16)
+                .map(|i| match i % 4 {
+                    3 => hover(i.into()),
+                    _ => did_change(i),
+                })
+                .collect();
+            rt.block_on(dispatch(&mut service, batch));
+        });
+    });
+}
+
+criterion_group!(benches, bench_request, bench_notifications, bench_mixed);
+criterion_main!(benches);
@@ -4,6 +4,7 @@ use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::notification::Notification;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tracing::error;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct CustomNotificationParams {
@@ -85,5 +86,7 @@ async fn main() {
     let (stdin, stdout) = (stdin.compat(), stdout.compat_write());
 
     let (service, socket) = LspService::new(|client| Backend { client });
-    Server::new(stdin, stdout, socket).serve(service).await;
+    if let Err(err) = Server::new(stdin, stdout, socket).serve(service).await {
+        error!("language server stopped unexpectedly: {}", err);
+    }
 }
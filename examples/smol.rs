@@ -0,0 +1,67 @@
+//! A language server running on `smol` instead of `tokio`.
+//!
+//! Build with `cargo run --example smol --no-default-features --features async-net -- --stdio`.
+//! The transport is selected with the standard `--stdio`, `--socket=PORT` and `--pipe=NAME`
+//! arguments, so the same binary also works with `async-std` or any other executor.
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tracing::error;
+
+#[derive(Debug)]
+struct Backend {
+    client: Client,
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            server_info: None,
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::FULL,
+                )),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                ..ServerCapabilities::default()
+            },
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client
+            .log_message(MessageType::INFO, "server initialized on smol!")
+            .await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String("Hello from smol!".into())),
+            range: None,
+        }))
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    smol::block_on(async {
+        let (read, write) = match tower_lsp::transport::from_args(std::env::args().skip(1)).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("failed to connect to the client: {}", err);
+                return;
+            }
+        };
+
+        let (service, socket) = LspService::new(|client| Backend { client });
+        if let Err(err) = Server::new(read, write, socket).serve(service).await {
+            error!("language server stopped unexpectedly: {}", err);
+        }
+    });
+}
@@ -1,14 +1,17 @@
+use std::collections::HashMap;
+
 use serde_json::Value;
-use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tower_lsp::prelude::*;
+use tower_lsp::sync::Shared;
+use tracing::error;
 
 #[derive(Debug)]
 struct Backend {
     client: Client,
+    versions: Shared<HashMap<Url, i32>>,
 }
 
-#[tower_lsp::async_trait]
+#[async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
         Ok(InitializeResult {
@@ -83,13 +86,17 @@ impl LanguageServer for Backend {
         Ok(None)
     }
 
-    async fn did_open(&self, _: DidOpenTextDocumentParams) {
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let doc = params.text_document;
+        self.versions.write().await.insert(doc.uri, doc.version);
         self.client
             .log_message(MessageType::INFO, "file opened!")
             .await;
     }
 
-    async fn did_change(&self, _: DidChangeTextDocumentParams) {
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let doc = params.text_document;
+        self.versions.write().await.insert(doc.uri, doc.version);
         self.client
             .log_message(MessageType::INFO, "file changed!")
             .await;
@@ -101,13 +108,20 @@ impl LanguageServer for Backend {
             .await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.versions.write().await.remove(&uri);
         self.client
             .log_message(MessageType::INFO, "file closed!")
             .await;
     }
 
-    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        if !self.versions.read().await.contains_key(&uri) {
+            return Ok(None);
+        }
+
         Ok(Some(CompletionResponse::Array(vec![
             CompletionItem::new_simple("Hello".to_string(), "Some detail".to_string()),
             CompletionItem::new_simple("Bye".to_string(), "More detail".to_string()),
@@ -126,6 +140,11 @@ async fn main() {
     #[cfg(feature = "runtime-agnostic")]
     let (stdin, stdout) = (stdin.compat(), stdout.compat_write());
 
-    let (service, socket) = LspService::new(|client| Backend { client });
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        versions: Shared::default(),
+    });
+    if let Err(err) = Server::new(stdin, stdout, socket).serve(service).await {
+        error!("language server stopped unexpectedly: {}", err);
+    }
 }
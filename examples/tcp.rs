@@ -3,6 +3,7 @@ use tokio::net::{TcpListener, TcpStream};
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tracing::error;
 
 #[derive(Debug)]
 struct Backend {
@@ -151,5 +152,7 @@ async fn main() {
     let (read, write) = (read.compat(), write.compat_write());
 
     let (service, socket) = LspService::new(|client| Backend { client });
-    Server::new(read, write, socket).serve(service).await;
+    if let Err(err) = Server::new(read, write, socket).serve(service).await {
+        error!("language server stopped unexpectedly: {}", err);
+    }
 }
@@ -4,7 +4,7 @@ use tokio::net::TcpListener;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
-use tracing::info;
+use tracing::{error, info};
 use ws_stream_tungstenite::*;
 
 #[derive(Debug)]
@@ -132,5 +132,7 @@ async fn main() {
     let (read, write) = (read.compat(), write.compat_write());
 
     let (service, socket) = LspService::new(|client| Backend { client });
-    Server::new(read, write, socket).serve(service).await;
+    if let Err(err) = Server::new(read, write, socket).serve(service).await {
+        error!("language server stopped unexpectedly: {}", err);
+    }
 }
@@ -0,0 +1,501 @@
+//! A synchronous facade for writing simple language servers without `async`.
+//!
+//! This module is intended for teaching material, prototypes, and tiny servers where handlers do
+//! little more than look up a value in memory. It spins up a single-threaded [`tokio`] runtime and
+//! reuses the regular [`LspService`] and [`Server`] machinery under the hood, while letting the
+//! backend be written with plain functions returning values instead of futures.
+//!
+//! Handlers are run on a pool of background threads, so they may block without stalling the
+//! transport, and may call into [`Client`] to send messages to the client synchronously.
+//!
+//! [`tokio`]: https://docs.rs/tokio
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use tower_lsp::blocking::{self, Client, LanguageServer};
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::lsp_types::*;
+//!
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! impl LanguageServer for Backend {
+//!     fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult {
+//!             capabilities: ServerCapabilities {
+//!                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+//!                 ..Default::default()
+//!             },
+//!             ..Default::default()
+//!         })
+//!     }
+//!
+//!     fn initialized(&self, _: InitializedParams) {
+//!         self.client.log_message(MessageType::INFO, "server initialized!");
+//!     }
+//!
+//!     fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+//!         Ok(Some(Hover {
+//!             contents: HoverContents::Scalar(MarkedString::String("Hello!".into())),
+//!             range: None,
+//!         }))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     if let Err(err) = blocking::run(|client| Backend { client }) {
+//!         eprintln!("language server stopped unexpectedly: {err}");
+//!     }
+//! }
+//! ```
+
+use std::fmt::Display;
+use std::panic;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use lsp_types::*;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::runtime::{Builder, Handle};
+use tracing::{error, warn};
+
+use crate::jsonrpc::{Error, Result};
+use crate::{LspService, ServeError, Server};
+
+/// Serves the language server created by `init` on standard input and output.
+///
+/// This blocks the current thread until the session ends, returning the same result as
+/// [`Server::serve`].
+///
+/// # Panics
+///
+/// Panics if called from within an asynchronous runtime, or if the runtime cannot be created.
+pub fn run<S, F>(init: F) -> std::result::Result<(), ServeError>
+where
+    S: LanguageServer,
+    F: FnOnce(Client) -> S,
+{
+    run_with(tokio::io::stdin(), tokio::io::stdout(), init)
+}
+
+fn run_with<I, O, S, F>(stdin: I, stdout: O, init: F) -> std::result::Result<(), ServeError>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+    S: LanguageServer,
+    F: FnOnce(Client) -> S,
+{
+    let runtime = Builder::new_current_thread()
+        .build()
+        .expect("failed to start the tokio runtime");
+
+    let handle = runtime.handle().clone();
+    let (service, socket) = LspService::new(|inner| {
+        let client = Client { inner, handle };
+        Blocking {
+            server: Arc::new(init(client)),
+        }
+    });
+
+    runtime.block_on(Server::new(stdin, stdout, socket).serve(service))
+}
+
+/// Handle for synchronously sending messages to the client from within [`LanguageServer`]
+/// handlers.
+///
+/// Every method blocks the calling thread until the message has been sent or, for requests, until
+/// the response has been received. Methods must not be called from within an asynchronous runtime.
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: crate::Client,
+    handle: Handle,
+}
+
+impl Client {
+    /// Notifies the client to log a particular message.
+    ///
+    /// See [`Client::log_message`](crate::Client::log_message) for more.
+    pub fn log_message<M: Display>(&self, typ: MessageType, message: M) {
+        self.handle.block_on(self.inner.log_message(typ, message));
+    }
+
+    /// Notifies the client to display a particular message in the user interface.
+    ///
+    /// See [`Client::show_message`](crate::Client::show_message) for more.
+    pub fn show_message<M: Display>(&self, typ: MessageType, message: M) {
+        self.handle.block_on(self.inner.show_message(typ, message));
+    }
+
+    /// Submits validation diagnostics for an open file with the given URI.
+    ///
+    /// See [`Client::publish_diagnostics`](crate::Client::publish_diagnostics) for more.
+    pub fn publish_diagnostics(&self, uri: Url, diags: Vec<Diagnostic>, version: Option<i32>) {
+        let future = self.inner.publish_diagnostics(uri, diags, version);
+        self.handle.block_on(future);
+    }
+
+    /// Sends a custom notification to the client.
+    ///
+    /// See [`Client::send_notification`](crate::Client::send_notification) for more.
+    pub fn send_notification<N: Notification>(&self, params: N::Params) {
+        self.handle
+            .block_on(self.inner.send_notification::<N>(params));
+    }
+
+    /// Sends a custom request to the client and waits for its response.
+    ///
+    /// See [`Client::send_request`](crate::Client::send_request) for more.
+    pub fn send_request<R: Request>(&self, params: R::Params) -> Result<R::Result> {
+        self.handle.block_on(self.inner.send_request::<R>(params))
+    }
+}
+
+/// Trait implemented by synchronous language server backends.
+///
+/// This is a synchronous counterpart to a commonly used subset of
+/// [`LanguageServer`](crate::LanguageServer). Every method other than `initialize` has a default
+/// implementation, so backends only need to implement the features they advertise in their
+/// [`ServerCapabilities`]. Servers needing methods beyond this subset should implement the
+/// asynchronous trait instead.
+pub trait LanguageServer: Send + Sync + 'static {
+    /// Handles the [`initialize`] request, returning the capabilities of the server.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    fn initialize(&self, params: InitializeParams) -> Result<InitializeResult>;
+
+    /// Handles the [`initialized`] notification.
+    ///
+    /// [`initialized`]: https://microsoft.github.io/language-server-protocol/specification#initialized
+    fn initialized(&self, params: InitializedParams) {
+        let _ = params;
+    }
+
+    /// Handles the [`shutdown`] request.
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Handles the [`textDocument/didOpen`] notification.
+    ///
+    /// [`textDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didOpen
+    fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let _ = params;
+        warn!("Got a textDocument/didOpen notification, but it is not implemented");
+    }
+
+    /// Handles the [`textDocument/didChange`] notification.
+    ///
+    /// [`textDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didChange
+    fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let _ = params;
+        warn!("Got a textDocument/didChange notification, but it is not implemented");
+    }
+
+    /// Handles the [`textDocument/didSave`] notification.
+    ///
+    /// [`textDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didSave
+    fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let _ = params;
+        warn!("Got a textDocument/didSave notification, but it is not implemented");
+    }
+
+    /// Handles the [`textDocument/didClose`] notification.
+    ///
+    /// [`textDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didClose
+    fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let _ = params;
+        warn!("Got a textDocument/didClose notification, but it is not implemented");
+    }
+
+    /// Handles the [`workspace/didChangeConfiguration`] notification.
+    ///
+    /// [`workspace/didChangeConfiguration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
+    fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let _ = params;
+        warn!("Got a workspace/didChangeConfiguration notification, but it is not implemented");
+    }
+
+    /// Handles the [`workspace/didChangeWatchedFiles`] notification.
+    ///
+    /// [`workspace/didChangeWatchedFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeWatchedFiles
+    fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let _ = params;
+        warn!("Got a workspace/didChangeWatchedFiles notification, but it is not implemented");
+    }
+
+    /// Handles the [`textDocument/hover`] request.
+    ///
+    /// [`textDocument/hover`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_hover
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let _ = params;
+        error!("Got a textDocument/hover request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/completion`] request.
+    ///
+    /// [`textDocument/completion`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_completion
+    fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let _ = params;
+        error!("Got a textDocument/completion request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/signatureHelp`] request.
+    ///
+    /// [`textDocument/signatureHelp`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_signatureHelp
+    fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let _ = params;
+        error!("Got a textDocument/signatureHelp request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/definition`] request.
+    ///
+    /// [`textDocument/definition`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_definition
+    fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let _ = params;
+        error!("Got a textDocument/definition request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/references`] request.
+    ///
+    /// [`textDocument/references`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_references
+    fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let _ = params;
+        error!("Got a textDocument/references request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/documentHighlight`] request.
+    ///
+    /// [`textDocument/documentHighlight`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentHighlight
+    fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let _ = params;
+        error!("Got a textDocument/documentHighlight request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/documentSymbol`] request.
+    ///
+    /// [`textDocument/documentSymbol`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentSymbol
+    fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let _ = params;
+        error!("Got a textDocument/documentSymbol request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/codeAction`] request.
+    ///
+    /// [`textDocument/codeAction`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_codeAction
+    fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let _ = params;
+        error!("Got a textDocument/codeAction request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/formatting`] request.
+    ///
+    /// [`textDocument/formatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_formatting
+    fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let _ = params;
+        error!("Got a textDocument/formatting request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`textDocument/rename`] request.
+    ///
+    /// [`textDocument/rename`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rename
+    fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let _ = params;
+        error!("Got a textDocument/rename request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// Handles the [`workspace/executeCommand`] request.
+    ///
+    /// [`workspace/executeCommand`]: https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand
+    fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let _ = params;
+        error!("Got a workspace/executeCommand request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+}
+
+/// Adapts a synchronous [`LanguageServer`] to the asynchronous [`crate::LanguageServer`] trait.
+struct Blocking<S> {
+    server: Arc<S>,
+}
+
+impl<S: LanguageServer> Blocking<S> {
+    /// Runs `f` on the blocking thread pool, resuming any panic on the current task.
+    async fn call<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let server = self.server.clone();
+        match tokio::task::spawn_blocking(move || f(&server)).await {
+            Ok(result) => result,
+            Err(err) => match err.try_into_panic() {
+                Ok(payload) => panic::resume_unwind(payload),
+                Err(err) => panic!("blocking handler did not complete: {err}"),
+            },
+        }
+    }
+}
+
+macro_rules! delegate {
+    (
+        requests { $($req:ident($req_params:ty) -> $res:ty;)* }
+        notifications { $($notif:ident($notif_params:ty);)* }
+    ) => {
+        #[async_trait]
+        impl<S: LanguageServer> crate::LanguageServer for Blocking<S> {
+            async fn shutdown(&self) -> Result<()> {
+                self.call(|server| server.shutdown()).await
+            }
+
+            $(
+                async fn $req(&self, params: $req_params) -> Result<$res> {
+                    self.call(move |server| server.$req(params)).await
+                }
+            )*
+
+            $(
+                async fn $notif(&self, params: $notif_params) {
+                    self.call(move |server| server.$notif(params)).await
+                }
+            )*
+        }
+    };
+}
+
+delegate! {
+    requests {
+        initialize(InitializeParams) -> InitializeResult;
+        hover(HoverParams) -> Option<Hover>;
+        completion(CompletionParams) -> Option<CompletionResponse>;
+        signature_help(SignatureHelpParams) -> Option<SignatureHelp>;
+        goto_definition(GotoDefinitionParams) -> Option<GotoDefinitionResponse>;
+        references(ReferenceParams) -> Option<Vec<Location>>;
+        document_highlight(DocumentHighlightParams) -> Option<Vec<DocumentHighlight>>;
+        document_symbol(DocumentSymbolParams) -> Option<DocumentSymbolResponse>;
+        code_action(CodeActionParams) -> Option<CodeActionResponse>;
+        formatting(DocumentFormattingParams) -> Option<Vec<TextEdit>>;
+        rename(RenameParams) -> Option<WorkspaceEdit>;
+        execute_command(ExecuteCommandParams) -> Option<Value>;
+    }
+    notifications {
+        initialized(InitializedParams);
+        did_open(DidOpenTextDocumentParams);
+        did_change(DidChangeTextDocumentParams);
+        did_save(DidSaveTextDocumentParams);
+        did_close(DidCloseTextDocumentParams);
+        did_change_configuration(DidChangeConfigurationParams);
+        did_change_watched_files(DidChangeWatchedFilesParams);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    struct Mock {
+        client: Client,
+    }
+
+    impl LanguageServer for Mock {
+        fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        fn initialized(&self, _: InitializedParams) {
+            self.client.log_message(MessageType::INFO, "initialized");
+        }
+
+        fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String("hello".into())),
+                range: None,
+            }))
+        }
+    }
+
+    /// Plays the role of the client, sending each message and waiting for its response, if any.
+    async fn mock_client<S, R>(
+        mut stdin: S,
+        mut stdout: R,
+        messages: &[(&str, Option<u32>)],
+    ) -> String
+    where
+        S: AsyncWrite + Unpin,
+        R: AsyncRead + Unpin,
+    {
+        let mut output = String::new();
+        let mut buf = [0; 1024];
+
+        for (message, id) in messages {
+            let framed = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+            stdin.write_all(framed.as_bytes()).await.unwrap();
+
+            if let Some(id) = id {
+                while !output.contains(&format!(r#""id":{id}}}"#)) {
+                    let n = stdout.read(&mut buf).await.unwrap();
+                    output.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+                }
+            }
+        }
+
+        stdout.read_to_string(&mut output).await.unwrap();
+        output
+    }
+
+    #[test]
+    fn serves_synchronous_handlers() {
+        let (client_stdin, stdin) = tokio::io::duplex(1024);
+        let (stdout, client_stdout) = tokio::io::duplex(1024);
+
+        let client = std::thread::spawn(move || {
+            let runtime = Builder::new_current_thread().build().unwrap();
+            runtime.block_on(mock_client(client_stdin, client_stdout, &[
+                (r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#, Some(1)),
+                (r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#, None),
+                (r#"{"jsonrpc":"2.0","method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.rs"},"position":{"line":0,"character":0}},"id":2}"#, Some(2)),
+                (r#"{"jsonrpc":"2.0","method":"textDocument/references","params":{"textDocument":{"uri":"file:///a.rs"},"position":{"line":0,"character":0},"context":{"includeDeclaration":true}},"id":3}"#, Some(3)),
+                (r#"{"jsonrpc":"2.0","method":"shutdown","id":4}"#, Some(4)),
+                (r#"{"jsonrpc":"2.0","method":"exit"}"#, None),
+            ]))
+        });
+
+        let result = run_with(stdin, stdout, |client| Mock { client });
+        assert_eq!(result, Ok(()));
+
+        let output = client.join().unwrap();
+        assert!(output.contains(
+            r#""method":"window/logMessage","params":{"message":"initialized","type":3}"#
+        ));
+        assert!(output.contains(r#"{"jsonrpc":"2.0","result":{"contents":"hello"},"id":2}"#));
+        assert!(output.contains(r#""error":{"code":-32601,"message":"Method not found"},"id":3}"#));
+        assert!(output.contains(r#"{"jsonrpc":"2.0","result":null,"id":4}"#));
+    }
+}
@@ -0,0 +1,490 @@
+//! Support code for the [`server_capabilities!`](crate::server_capabilities) macro.
+//!
+//! The items in this module are implementation details of the macro and are not part of the public
+//! API. They may change at any time.
+
+use lsp_types::*;
+
+/// Builds a [`ServerCapabilities`](lsp_types::ServerCapabilities) value from a concise list of
+/// capability declarations.
+///
+/// Each entry takes the form `name: value`, where `name` is the name of a `ServerCapabilities`
+/// field without its `_provider` suffix (e.g. `hover` for `hover_provider`), and `value` is one of:
+///
+/// * `true` or `false`, to simply advertise whether the capability is supported. For capabilities
+///   which can only be expressed as an options struct, `true` advertises the default options and
+///   `false` leaves the capability unset. `text_document_sync: true` selects full document sync.
+/// * `{ field: value, ... }`, to advertise the capability with the given options. Fields which are
+///   not listed are left empty. Each field value must be a single token tree: a literal, an array
+///   of literals, or an arbitrary expression wrapped in parentheses.
+/// * `(expr)`, an arbitrary expression which is converted with [`Into`] into the field type. This
+///   is the only accepted form for `position_encoding` and `experimental`.
+///
+/// Capabilities which are not listed are left unset.
+///
+/// # Examples
+///
+/// ```rust
+/// use tower_lsp::lsp_types::*;
+/// use tower_lsp::server_capabilities;
+///
+/// let capabilities = server_capabilities! {
+///     text_document_sync: (TextDocumentSyncKind::INCREMENTAL),
+///     hover: true,
+///     completion: {
+///         trigger_characters: [".", ":"],
+///         resolve_provider: true,
+///     },
+///     rename: { prepare_provider: true },
+///     execute_command: { commands: ["example.run"] },
+/// };
+///
+/// assert_eq!(capabilities.hover_provider, Some(HoverProviderCapability::Simple(true)));
+/// assert_eq!(
+///     capabilities.completion_provider.unwrap().trigger_characters,
+///     Some(vec![".".to_string(), ":".to_string()]),
+/// );
+/// assert!(capabilities.definition_provider.is_none());
+/// ```
+#[macro_export]
+macro_rules! server_capabilities {
+    (@set $caps:ident, position_encoding, ($($expr:tt)*)) => {
+        $caps.position_encoding = ::std::option::Option::Some(::std::convert::Into::into($($expr)*));
+    };
+    (@set $caps:ident, experimental, ($($expr:tt)*)) => {
+        $caps.experimental = ::std::option::Option::Some(::std::convert::Into::into($($expr)*));
+    };
+    (@set $caps:ident, text_document_sync, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.text_document_sync, TextDocumentSyncOptions, $value);
+    };
+    (@set $caps:ident, selection_range, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.selection_range_provider, SelectionRangeOptions, $value);
+    };
+    (@set $caps:ident, hover, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.hover_provider, HoverOptions, $value);
+    };
+    (@set $caps:ident, completion, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.completion_provider, CompletionOptions, $value);
+    };
+    (@set $caps:ident, signature_help, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.signature_help_provider, SignatureHelpOptions, $value);
+    };
+    (@set $caps:ident, definition, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.definition_provider, DefinitionOptions, $value);
+    };
+    (@set $caps:ident, type_definition, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.type_definition_provider, StaticTextDocumentRegistrationOptions, $value);
+    };
+    (@set $caps:ident, implementation, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.implementation_provider, StaticTextDocumentRegistrationOptions, $value);
+    };
+    (@set $caps:ident, references, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.references_provider, ReferencesOptions, $value);
+    };
+    (@set $caps:ident, document_highlight, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_highlight_provider, DocumentHighlightOptions, $value);
+    };
+    (@set $caps:ident, document_symbol, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_symbol_provider, DocumentSymbolOptions, $value);
+    };
+    (@set $caps:ident, workspace_symbol, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.workspace_symbol_provider, WorkspaceSymbolOptions, $value);
+    };
+    (@set $caps:ident, code_action, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.code_action_provider, CodeActionOptions, $value);
+    };
+    (@set $caps:ident, code_lens, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.code_lens_provider, CodeLensOptions, $value);
+    };
+    (@set $caps:ident, document_formatting, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_formatting_provider, DocumentFormattingOptions, $value);
+    };
+    (@set $caps:ident, document_range_formatting, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_range_formatting_provider, DocumentRangeFormattingOptions, $value);
+    };
+    (@set $caps:ident, document_on_type_formatting, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_on_type_formatting_provider, DocumentOnTypeFormattingOptions, $value);
+    };
+    (@set $caps:ident, rename, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.rename_provider, RenameOptions, $value);
+    };
+    (@set $caps:ident, document_link, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.document_link_provider, DocumentLinkOptions, $value);
+    };
+    (@set $caps:ident, color, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.color_provider, ColorProviderOptions, $value);
+    };
+    (@set $caps:ident, folding_range, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.folding_range_provider, FoldingProviderOptions, $value);
+    };
+    (@set $caps:ident, declaration, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.declaration_provider, DeclarationOptions, $value);
+    };
+    (@set $caps:ident, execute_command, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.execute_command_provider, ExecuteCommandOptions, $value);
+    };
+    (@set $caps:ident, workspace, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.workspace, WorkspaceServerCapabilities, $value);
+    };
+    (@set $caps:ident, call_hierarchy, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.call_hierarchy_provider, CallHierarchyOptions, $value);
+    };
+    (@set $caps:ident, semantic_tokens, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.semantic_tokens_provider, SemanticTokensOptions, $value);
+    };
+    (@set $caps:ident, moniker, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.moniker_provider, MonikerOptions, $value);
+    };
+    (@set $caps:ident, linked_editing_range, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.linked_editing_range_provider, LinkedEditingRangeOptions, $value);
+    };
+    (@set $caps:ident, inline_value, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.inline_value_provider, InlineValueOptions, $value);
+    };
+    (@set $caps:ident, inlay_hint, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.inlay_hint_provider, InlayHintOptions, $value);
+    };
+    (@set $caps:ident, diagnostic, $value:tt) => {
+        $crate::server_capabilities!(@field $caps.diagnostic_provider, DiagnosticOptions, $value);
+    };
+
+    (@field $caps:ident . $field:ident, $options:ident, true) => {
+        $caps.$field = <_ as $crate::capabilities::Capability<$crate::lsp_types::$options>>::enabled(true);
+    };
+    (@field $caps:ident . $field:ident, $options:ident, false) => {
+        $caps.$field = <_ as $crate::capabilities::Capability<$crate::lsp_types::$options>>::enabled(false);
+    };
+    (@field $caps:ident . $field:ident, $options:ident, { $($name:ident : $value:tt),* $(,)? }) => {{
+        #[allow(unused_mut)]
+        let mut options = <$crate::lsp_types::$options as $crate::capabilities::Empty>::empty();
+        $(options.$name = $crate::capabilities::IntoValue::into_value($value);)*
+        $caps.$field = ::std::option::Option::Some(
+            <_ as $crate::capabilities::Capability<$crate::lsp_types::$options>>::options(options),
+        );
+    }};
+    (@field $caps:ident . $field:ident, $options:ident, ($($expr:tt)*)) => {
+        $caps.$field = ::std::option::Option::Some(::std::convert::Into::into($($expr)*));
+    };
+
+    ($($name:ident : $value:tt),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut capabilities = $crate::lsp_types::ServerCapabilities::default();
+        $($crate::server_capabilities!(@set capabilities, $name, $value);)*
+        capabilities
+    }};
+}
+
+/// A `ServerCapabilities` field type which can be built from a `bool` or from options of type `O`.
+pub trait Capability<O>: Sized {
+    /// Returns the field value advertising whether the capability is supported.
+    fn enabled(enabled: bool) -> Option<Self>;
+
+    /// Returns the field value advertising the capability with the given `options`.
+    fn options(options: O) -> Self;
+}
+
+impl<O: Empty> Capability<O> for OneOf<bool, O> {
+    fn enabled(enabled: bool) -> Option<Self> {
+        Some(OneOf::Left(enabled))
+    }
+
+    fn options(options: O) -> Self {
+        OneOf::Right(options)
+    }
+}
+
+impl Capability<TextDocumentSyncOptions> for TextDocumentSyncCapability {
+    fn enabled(enabled: bool) -> Option<Self> {
+        let kind = if enabled {
+            TextDocumentSyncKind::FULL
+        } else {
+            TextDocumentSyncKind::NONE
+        };
+
+        Some(TextDocumentSyncCapability::Kind(kind))
+    }
+
+    fn options(options: TextDocumentSyncOptions) -> Self {
+        TextDocumentSyncCapability::Options(options)
+    }
+}
+
+/// Implements `Capability` for enums with a `Simple(bool)` variant.
+macro_rules! impl_simple {
+    ($($ty:ident($options:ident) => $variant:ident,)*) => {
+        $(
+            impl Capability<$options> for $ty {
+                fn enabled(enabled: bool) -> Option<Self> {
+                    Some($ty::Simple(enabled))
+                }
+
+                fn options(options: $options) -> Self {
+                    $ty::$variant(options)
+                }
+            }
+        )*
+    };
+}
+
+impl_simple! {
+    SelectionRangeProviderCapability(SelectionRangeOptions) => Options,
+    HoverProviderCapability(HoverOptions) => Options,
+    TypeDefinitionProviderCapability(StaticTextDocumentRegistrationOptions) => Options,
+    ImplementationProviderCapability(StaticTextDocumentRegistrationOptions) => Options,
+    CodeActionProviderCapability(CodeActionOptions) => Options,
+    ColorProviderCapability(ColorProviderOptions) => ColorProvider,
+    FoldingRangeProviderCapability(FoldingProviderOptions) => FoldingProvider,
+    DeclarationCapability(DeclarationOptions) => Options,
+    CallHierarchyServerCapability(CallHierarchyOptions) => Options,
+    LinkedEditingRangeServerCapabilities(LinkedEditingRangeOptions) => Options,
+}
+
+/// Implements `Capability` for fields which can only hold options, possibly wrapped in an enum.
+macro_rules! impl_options {
+    ($($ty:ty => $options:ident $(:: $variant:ident)?,)*) => {
+        $(
+            impl Capability<$options> for $ty {
+                fn enabled(enabled: bool) -> Option<Self> {
+                    if enabled {
+                        Some(Self::options($options::empty()))
+                    } else {
+                        None
+                    }
+                }
+
+                fn options(options: $options) -> Self {
+                    $(let options = <$ty>::$variant(options);)?
+                    options
+                }
+            }
+        )*
+    };
+}
+
+impl_options! {
+    CompletionOptions => CompletionOptions,
+    SignatureHelpOptions => SignatureHelpOptions,
+    CodeLensOptions => CodeLensOptions,
+    DocumentOnTypeFormattingOptions => DocumentOnTypeFormattingOptions,
+    DocumentLinkOptions => DocumentLinkOptions,
+    ExecuteCommandOptions => ExecuteCommandOptions,
+    WorkspaceServerCapabilities => WorkspaceServerCapabilities,
+    SemanticTokensServerCapabilities => SemanticTokensOptions::SemanticTokensOptions,
+    DiagnosticServerCapabilities => DiagnosticOptions::Options,
+}
+
+/// Implements `Capability` for `OneOf<bool, T>` fields whose `T` is an enum of options.
+macro_rules! impl_one_of {
+    ($($ty:ident($options:ident) => $variant:ident,)*) => {
+        $(
+            impl Capability<$options> for OneOf<bool, $ty> {
+                fn enabled(enabled: bool) -> Option<Self> {
+                    Some(OneOf::Left(enabled))
+                }
+
+                fn options(options: $options) -> Self {
+                    OneOf::Right($ty::$variant(options))
+                }
+            }
+        )*
+    };
+}
+
+impl_one_of! {
+    MonikerServerCapabilities(MonikerOptions) => Options,
+    InlineValueServerCapabilities(InlineValueOptions) => Options,
+    InlayHintServerCapabilities(InlayHintOptions) => Options,
+}
+
+/// An options struct which can be created with all of its fields left empty.
+///
+/// This is equivalent to [`Default`], which not every options type in `lsp-types` implements.
+pub trait Empty {
+    /// Returns an instance with all optional fields set to `None`.
+    fn empty() -> Self;
+}
+
+macro_rules! impl_empty {
+    (default: $($ty:ident),*; $($other:ident => $value:expr,)*) => {
+        $(
+            impl Empty for $ty {
+                fn empty() -> Self {
+                    Default::default()
+                }
+            }
+        )*
+        $(
+            impl Empty for $other {
+                fn empty() -> Self {
+                    $value
+                }
+            }
+        )*
+    };
+}
+
+impl_empty! {
+    default: TextDocumentSyncOptions, SelectionRangeOptions, HoverOptions, CompletionOptions,
+        SignatureHelpOptions, CodeActionOptions, DocumentFormattingOptions,
+        DocumentOnTypeFormattingOptions, ExecuteCommandOptions, WorkspaceServerCapabilities,
+        CallHierarchyOptions, SemanticTokensOptions, LinkedEditingRangeOptions, InlineValueOptions,
+        InlayHintOptions, DiagnosticOptions;
+    DefinitionOptions => DefinitionOptions {
+        work_done_progress_options: Default::default(),
+    },
+    StaticTextDocumentRegistrationOptions => StaticTextDocumentRegistrationOptions {
+        document_selector: None,
+        id: None,
+    },
+    ReferencesOptions => ReferencesOptions {
+        work_done_progress_options: Default::default(),
+    },
+    DocumentHighlightOptions => DocumentHighlightOptions {
+        work_done_progress_options: Default::default(),
+    },
+    DocumentSymbolOptions => DocumentSymbolOptions {
+        label: None,
+        work_done_progress_options: Default::default(),
+    },
+    WorkspaceSymbolOptions => WorkspaceSymbolOptions {
+        work_done_progress_options: Default::default(),
+        resolve_provider: None,
+    },
+    CodeLensOptions => CodeLensOptions {
+        resolve_provider: None,
+    },
+    DocumentRangeFormattingOptions => DocumentRangeFormattingOptions {
+        work_done_progress_options: Default::default(),
+    },
+    RenameOptions => RenameOptions {
+        prepare_provider: None,
+        work_done_progress_options: Default::default(),
+    },
+    DocumentLinkOptions => DocumentLinkOptions {
+        resolve_provider: None,
+        work_done_progress_options: Default::default(),
+    },
+    ColorProviderOptions => ColorProviderOptions {},
+    FoldingProviderOptions => FoldingProviderOptions {},
+    DeclarationOptions => DeclarationOptions {
+        work_done_progress_options: Default::default(),
+    },
+    MonikerOptions => MonikerOptions {
+        work_done_progress_options: Default::default(),
+    },
+}
+
+/// Converts a value written in a `server_capabilities!` options block into the field type `T`.
+pub trait IntoValue<T> {
+    /// Performs the conversion.
+    fn into_value(self) -> T;
+}
+
+impl<T> IntoValue<T> for T {
+    fn into_value(self) -> T {
+        self
+    }
+}
+
+impl<T> IntoValue<Option<T>> for T {
+    fn into_value(self) -> Option<T> {
+        Some(self)
+    }
+}
+
+impl IntoValue<String> for &str {
+    fn into_value(self) -> String {
+        self.to_owned()
+    }
+}
+
+impl IntoValue<Option<String>> for &str {
+    fn into_value(self) -> Option<String> {
+        Some(self.to_owned())
+    }
+}
+
+impl<const N: usize> IntoValue<Vec<String>> for [&str; N] {
+    fn into_value(self) -> Vec<String> {
+        self.iter().map(|s| (*s).to_owned()).collect()
+    }
+}
+
+impl<const N: usize> IntoValue<Option<Vec<String>>> for [&str; N] {
+    fn into_value(self) -> Option<Vec<String>> {
+        Some(self.into_value())
+    }
+}
+
+impl<T, const N: usize> IntoValue<Vec<T>> for [T; N] {
+    fn into_value(self) -> Vec<T> {
+        self.into()
+    }
+}
+
+impl<T, const N: usize> IntoValue<Option<Vec<T>>> for [T; N] {
+    fn into_value(self) -> Option<Vec<T>> {
+        Some(self.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::*;
+
+    #[test]
+    fn builds_capabilities() {
+        let capabilities = server_capabilities! {
+            position_encoding: (PositionEncodingKind::UTF16),
+            text_document_sync: true,
+            hover: true,
+            completion: { trigger_characters: [".", ":"] },
+            definition: false,
+            references: {},
+            code_action: { code_action_kinds: [CodeActionKind::QUICKFIX] },
+            code_lens: true,
+            diagnostic: { inter_file_dependencies: true },
+            inlay_hint: { resolve_provider: true },
+        };
+
+        let expected = ServerCapabilities {
+            position_encoding: Some(PositionEncodingKind::UTF16),
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec![".".into(), ":".into()]),
+                ..Default::default()
+            }),
+            definition_provider: Some(OneOf::Left(false)),
+            references_provider: Some(OneOf::Right(ReferencesOptions {
+                work_done_progress_options: Default::default(),
+            })),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                ..Default::default()
+            })),
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: None,
+            }),
+            diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                inter_file_dependencies: true,
+                ..Default::default()
+            })),
+            inlay_hint_provider: Some(OneOf::Right(InlayHintServerCapabilities::Options(
+                InlayHintOptions {
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                },
+            ))),
+            ..Default::default()
+        };
+
+        assert_eq!(capabilities, expected);
+    }
+
+    #[test]
+    fn empty_capabilities() {
+        let capabilities = server_capabilities! {};
+        assert_eq!(capabilities, ServerCapabilities::default());
+    }
+}
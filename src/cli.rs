@@ -0,0 +1,246 @@
+//! Support for diagnostic command-line flags like `--version` and `--capabilities`.
+//!
+//! Many editor integrations probe a language server binary with such flags before launching it,
+//! e.g. to check whether it is recent enough or supports a given feature. The [`handle_args`]
+//! function answers them using the backend itself: it sends an `initialize` request to the
+//! [`LspService`], and prints the resulting capabilities alongside the versions of `tower-lsp` and
+//! of the protocol, as well as the [description](LspService::describe) of the handled methods.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::{cli, LanguageServer, LspService, Server};
+//! #
+//! # struct Backend;
+//! #
+//! # #[tower_lsp::async_trait]
+//! # impl LanguageServer for Backend {
+//! #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//! #         Ok(InitializeResult::default())
+//! #     }
+//! #
+//! #     async fn shutdown(&self) -> Result<()> {
+//! #         Ok(())
+//! #     }
+//! # }
+//! #
+//! #[tokio::main]
+//! async fn main() {
+//!     let (mut service, socket) = LspService::new(|_| Backend);
+//!
+//!     if cli::handle_args(&mut service, std::env::args_os().skip(1)).await {
+//!         return;
+//!     }
+//!
+//!     let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+//!     Server::new(stdin, stdout, socket).serve(service).await;
+//! }
+//! ```
+
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::{InitializeResult, ServerCapabilities, ServerInfo};
+use serde::Serialize;
+use serde_json::json;
+use tower::{Service, ServiceExt};
+
+use crate::jsonrpc::{self, Request};
+use crate::service::{LspService, ServerDescription};
+use crate::LanguageServer;
+
+/// Version of the Language Server Protocol implemented by this crate.
+pub const PROTOCOL_VERSION: &str = "3.17";
+
+/// A diagnostic command-line flag.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Flag {
+    /// `--version` or `-V`: prints the server, `tower-lsp` and protocol versions.
+    Version,
+    /// `--capabilities`: prints the full [`ServerReport`] as pretty-printed JSON.
+    Capabilities,
+}
+
+impl Flag {
+    /// Returns the first diagnostic flag found in `args`, if any.
+    ///
+    /// Arguments are matched exactly; anything else, including arguments which are not valid
+    /// UTF-8, is ignored.
+    pub fn from_args<I, S>(args: I) -> Option<Flag>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        args.into_iter()
+            .find_map(|arg| match arg.as_ref().to_str()? {
+                "--version" | "-V" => Some(Flag::Version),
+                "--capabilities" => Some(Flag::Capabilities),
+                _ => None,
+            })
+    }
+}
+
+/// Capabilities and versions reported by a server, as collected by [`ServerReport::collect`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ServerReport {
+    /// Name and version of the server, as returned from `initialize`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<ServerInfo>,
+    /// Version of `tower-lsp` the server was built with.
+    pub tower_lsp_version: &'static str,
+    /// Version of the Language Server Protocol implemented by `tower-lsp`.
+    pub protocol_version: &'static str,
+    /// Capabilities returned from `initialize`, for a client without any capabilities.
+    pub capabilities: ServerCapabilities,
+    /// Description of the methods handled by the server.
+    pub methods: ServerDescription,
+}
+
+impl ServerReport {
+    /// Collects the report by sending an `initialize` request to `service`.
+    ///
+    /// The request declares no client capabilities. This leaves `service` initialized, so it
+    /// should be dropped afterwards rather than served. Returns `Err` if the backend fails to
+    /// initialize.
+    pub async fn collect<S: LanguageServer>(service: &mut LspService<S>) -> jsonrpc::Result<Self> {
+        let methods = service.describe();
+
+        let request = Request::build("initialize")
+            .params(json!({ "capabilities": {} }))
+            .id(0)
+            .finish();
+
+        let response = match service.ready().await {
+            Ok(service) => service.call(request).await,
+            Err(err) => Err(err),
+        };
+
+        let result = match response {
+            Ok(Some(response)) => response.into_parts().1?,
+            _ => return Err(jsonrpc::Error::internal_error()),
+        };
+
+        let result: InitializeResult =
+            serde_json::from_value(result).map_err(|_| jsonrpc::Error::internal_error())?;
+
+        Ok(ServerReport {
+            server_info: result.server_info,
+            tower_lsp_version: env!("CARGO_PKG_VERSION"),
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: result.capabilities,
+            methods,
+        })
+    }
+
+    /// Returns the text to print in response to `flag`.
+    pub fn render(&self, flag: Flag) -> String {
+        match flag {
+            Flag::Version => self.to_string(),
+            Flag::Capabilities => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+}
+
+/// Formats the versions in the report, one per line.
+impl Display for ServerReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(info) = &self.server_info {
+            match &info.version {
+                Some(version) => writeln!(f, "{} {}", info.name, version)?,
+                None => writeln!(f, "{}", info.name)?,
+            }
+        }
+
+        writeln!(f, "tower-lsp {}", self.tower_lsp_version)?;
+        write!(f, "LSP {}", self.protocol_version)
+    }
+}
+
+/// Prints the output for the first diagnostic [`Flag`] found in `args`, if any.
+///
+/// Returns `true` if a flag was handled, in which case the caller should exit instead of serving
+/// `service`. If the backend fails to initialize, the error is printed to stderr instead.
+///
+/// See the [module-level documentation](self) for an example.
+pub async fn handle_args<S, I, A>(service: &mut LspService<S>, args: I) -> bool
+where
+    S: LanguageServer,
+    I: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let flag = match Flag::from_args(args) {
+        Some(flag) => flag,
+        None => return false,
+    };
+
+    match ServerReport::collect(service).await {
+        Ok(report) => println!("{}", report.render(flag)),
+        Err(err) => eprintln!("failed to initialize server: {err}"),
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{HoverProviderCapability, InitializeParams};
+
+    use super::*;
+    use crate::jsonrpc::Result;
+
+    struct Mock;
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult {
+                capabilities: ServerCapabilities {
+                    hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    ..ServerCapabilities::default()
+                },
+                server_info: Some(ServerInfo {
+                    name: "mock".into(),
+                    version: Some("1.2.3".into()),
+                }),
+                #[cfg(feature = "proposed")]
+                offset_encoding: None,
+            })
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_flags() {
+        assert_eq!(Flag::from_args(["--stdio", "-V"]), Some(Flag::Version));
+        assert_eq!(
+            Flag::from_args(["--capabilities", "--version"]),
+            Some(Flag::Capabilities)
+        );
+        assert_eq!(Flag::from_args(["--stdio"]), None);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn collects_report() {
+        let (mut service, _) = LspService::new(|_| Mock);
+        let report = ServerReport::collect(&mut service).await.unwrap();
+
+        let version = format!(
+            "mock 1.2.3\ntower-lsp {}\nLSP 3.17",
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(report.render(Flag::Version), version);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(Flag::Capabilities)).unwrap();
+        assert_eq!(json["capabilities"]["hoverProvider"], true);
+        assert_eq!(json["protocolVersion"], "3.17");
+        assert!(json["methods"]["methods"].is_array());
+    }
+}
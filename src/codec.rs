@@ -1,32 +1,96 @@
 //! Encoder and decoder for Language Server Protocol messages.
+//!
+//! [`LanguageServerCodec`] frames messages the way the [base protocol] does, and is what
+//! [`Server`](crate::Server) uses internally. It is exposed for custom transports which carry a
+//! byte stream, and implements the `Encoder` and `Decoder` traits of `tokio-util` or
+//! `async-codec-lite`, depending on the selected runtime. Transports which already preserve
+//! message boundaries, such as WebRTC data channels or WebSockets, can instead exchange the JSON
+//! produced by [`Message::to_vec`](crate::jsonrpc::Message::to_vec) directly.
+//!
+//! The codec upholds the following invariants:
+//!
+//! * Encoded messages consist of a `Content-Length` header, an empty line, and a JSON body of
+//!   exactly that many bytes. No `Content-Type` header is written for JSON bodies.
+//! * A `None` result from the decoder means more input is required. The codec keeps track of the
+//!   partial message, so decoding resumes once more bytes were appended to the same buffer.
+//! * On a malformed header section, the offending bytes are skipped and an error is returned, so
+//!   that decoding can continue with the next message. A body which fails to parse is consumed as
+//!   well. Messages with an empty body are skipped.
+//!
+//! [base protocol]: https://microsoft.github.io/language-server-protocol/specification#baseProtocol
+//!
+//! # Example
+//!
+//! ```rust
+//! # #[cfg(feature = "runtime-tokio")]
+//! # fn main() {
+//! use bytes::BytesMut;
+//! use tokio_util::codec::{Decoder, Encoder};
+//! use tower_lsp::codec::LanguageServerCodec;
+//! use tower_lsp::jsonrpc::{Message, Request};
+//!
+//! let mut codec = LanguageServerCodec::<Message>::default();
+//! let mut buffer = BytesMut::new();
+//!
+//! let req = Request::build("initialized").finish();
+//! codec.encode(Message::from(req.clone()), &mut buffer).unwrap();
+//! assert!(buffer.starts_with(b"Content-Length: "));
+//!
+//! let decoded = codec.decode(&mut buffer).unwrap();
+//! assert_eq!(decoded, Some(Message::Request(req)));
+//! assert!(buffer.is_empty());
+//! # }
+//! # #[cfg(not(feature = "runtime-tokio"))]
+//! # fn main() {}
+//! ```
 
 use std::error::Error;
-use std::fmt::{self, Display, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{Error as IoError, Write};
 use std::marker::PhantomData;
 use std::num::ParseIntError;
 use std::str::Utf8Error;
+#[cfg(feature = "msgpack")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use bytes::buf::BufMut;
 use bytes::{Buf, BytesMut};
 use memchr::memmem;
 use serde::{de::DeserializeOwned, Serialize};
-use tracing::{trace, warn};
+use tracing::warn;
 
 #[cfg(feature = "runtime-agnostic")]
 use async_codec_lite::{Decoder, Encoder};
 #[cfg(feature = "runtime-tokio")]
 use tokio_util::codec::{Decoder, Encoder};
 
+use crate::transport::{ConnectionStats, Desync, DesyncReason};
+
+/// Number of headers parsed without allocating; messages with more headers are still accepted.
+const INLINE_HEADERS: usize = 16;
+
+/// Maximum size in bytes of the header section of a single message.
+const MAX_HEADERS_LEN: usize = 8 * 1024;
+
 /// Errors that can occur when processing an LSP message.
+///
+/// New variants may be added in minor releases, or when enabling crate features such as
+/// `msgpack`, so matching on this enum requires a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     /// Failed to parse the JSON body.
     Body(serde_json::Error),
+    /// Failed to parse the MessagePack body.
+    #[cfg(feature = "msgpack")]
+    MessagePack(rmp_serde::decode::Error),
     /// Failed to encode the response.
     Encode(IoError),
     /// Failed to parse headers.
     Headers(httparse::Error),
+    /// The message headers exceed the maximum supported size.
+    HeadersTooLarge,
     /// The media type in the `Content-Type` header is invalid.
     InvalidContentType,
     /// The length value in the `Content-Length` header is invalid.
@@ -41,8 +105,13 @@ impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match *self {
             ParseError::Body(ref e) => write!(f, "unable to parse JSON body: {e}"),
+            #[cfg(feature = "msgpack")]
+            ParseError::MessagePack(ref e) => write!(f, "unable to parse MessagePack body: {e}"),
             ParseError::Encode(ref e) => write!(f, "failed to encode response: {e}"),
             ParseError::Headers(ref e) => write!(f, "failed to parse headers: {e}"),
+            ParseError::HeadersTooLarge => {
+                write!(f, "headers exceed {MAX_HEADERS_LEN} bytes")
+            }
             ParseError::InvalidContentType => write!(f, "unable to parse content type"),
             ParseError::InvalidContentLength(ref e) => {
                 write!(f, "unable to parse content length: {e}")
@@ -59,6 +128,8 @@ impl Error for ParseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             ParseError::Body(ref e) => Some(e),
+            #[cfg(feature = "msgpack")]
+            ParseError::MessagePack(ref e) => Some(e),
             ParseError::Encode(ref e) => Some(e),
             ParseError::InvalidContentLength(ref e) => Some(e),
             ParseError::Utf8(ref e) => Some(e),
@@ -73,6 +144,13 @@ impl From<serde_json::Error> for ParseError {
     }
 }
 
+#[cfg(feature = "msgpack")]
+impl From<rmp_serde::decode::Error> for ParseError {
+    fn from(error: rmp_serde::decode::Error) -> Self {
+        ParseError::MessagePack(error)
+    }
+}
+
 impl From<IoError> for ParseError {
     fn from(error: IoError) -> Self {
         ParseError::Encode(error)
@@ -97,29 +175,256 @@ impl From<Utf8Error> for ParseError {
     }
 }
 
+type HeaderFn = dyn Fn(&[(String, String)]) + Send + Sync;
+
+/// Callback receiving the non-standard headers of an incoming message, as `(name, value)` pairs.
+#[derive(Clone)]
+pub(crate) struct HeaderCallback(Arc<HeaderFn>);
+
+impl HeaderCallback {
+    pub(crate) fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&[(String, String)]) + Send + Sync + 'static,
+    {
+        HeaderCallback(Arc::new(callback))
+    }
+}
+
+impl Debug for HeaderCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(HeaderCallback)).finish()
+    }
+}
+
+type DesyncFn = dyn Fn(&Desync) + Send + Sync;
+
+/// Callback notified whenever the decoder discards garbage to find the next message.
+#[derive(Clone)]
+pub(crate) struct DesyncCallback(Arc<DesyncFn>);
+
+impl DesyncCallback {
+    pub(crate) fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&Desync) + Send + Sync + 'static,
+    {
+        DesyncCallback(Arc::new(callback))
+    }
+}
+
+impl Debug for DesyncCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(DesyncCallback)).finish()
+    }
+}
+
+/// Encoding of a message body, selected by its `Content-Type` header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BodyFormat {
+    /// JSON, the only encoding defined by the specification.
+    Json,
+    /// MessagePack, with structs encoded as maps, for embedders which prefer a binary encoding.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+/// Progress of a [`LanguageServerCodec`] through the message it is currently decoding.
+///
+/// This reflects the state of the decoder as of the last call to `decode`, and is returned by
+/// [`LanguageServerCodec::progress`]. Custom transports may use it to size their reads, or to
+/// report how much of a large message has arrived so far.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeProgress {
+    /// No part of the next message has been received yet.
+    Idle,
+    /// The header section is incomplete.
+    Headers {
+        /// Number of bytes of the header section received so far.
+        received: usize,
+        /// Number of complete header lines received so far.
+        lines: usize,
+    },
+    /// The headers have been decoded, but the body is incomplete.
+    Body {
+        /// Number of body bytes received so far.
+        received: usize,
+        /// Number of body bytes still missing.
+        remaining: usize,
+    },
+}
+
+impl DecodeProgress {
+    /// Returns the number of bytes needed to complete the current message, if known.
+    ///
+    /// This is only known once the headers have been decoded.
+    pub fn bytes_needed(&self) -> Option<usize> {
+        match *self {
+            DecodeProgress::Body { remaining, .. } => Some(remaining),
+            _ => None,
+        }
+    }
+}
+
 /// Encodes and decodes Language Server Protocol messages.
 pub struct LanguageServerCodec<T> {
     content_len: Option<usize>,
+    /// Bytes of the header section, or of the body once `content_len` is known, seen so far.
+    received: usize,
+    /// Number of complete header lines within the `received` bytes of the header section.
+    lines: usize,
+    /// Encoding of the body of the message being decoded.
+    body_format: BodyFormat,
+    /// Whether JSON bodies are checked to be valid UTF-8 as a whole before being parsed.
+    validate_utf8: bool,
+    /// Whether the peer used MessagePack for the last message it sent, shared with the codec
+    /// returned by [`LanguageServerCodec::output`] so replies use the same encoding.
+    #[cfg(feature = "msgpack")]
+    peer_msgpack: Arc<AtomicBool>,
+    custom_headers: Vec<(String, String)>,
+    on_custom_headers: Option<HeaderCallback>,
+    on_desync: Option<DesyncCallback>,
+    /// Traffic counters updated by both this codec and the one returned by
+    /// [`LanguageServerCodec::output`].
+    stats: Option<ConnectionStats>,
     _marker: PhantomData<T>,
 }
 
-impl<T> Default for LanguageServerCodec<T> {
-    fn default() -> Self {
+impl<T> LanguageServerCodec<T> {
+    /// Creates a codec which passes the non-standard headers of each decoded message to `callback`.
+    pub(crate) fn with_header_callback(callback: HeaderCallback) -> Self {
         LanguageServerCodec {
-            content_len: None,
-            _marker: PhantomData,
+            on_custom_headers: Some(callback),
+            ..Default::default()
         }
     }
-}
 
-#[cfg(feature = "runtime-agnostic")]
-impl<T: Serialize> Encoder for LanguageServerCodec<T> {
-    type Item = T;
-    type Error = ParseError;
+    /// Skips the validation of JSON bodies as a whole, parsing their raw bytes directly instead.
+    ///
+    /// Strings within the body are still validated by the JSON parser, so invalid UTF-8 is still
+    /// rejected, albeit as a [`ParseError::Body`] error.
+    pub(crate) fn without_utf8_validation(mut self) -> Self {
+        self.validate_utf8 = false;
+        self
+    }
+
+    /// Notifies `callback` whenever garbage is discarded from the input.
+    pub(crate) fn with_desync_callback(mut self, callback: DesyncCallback) -> Self {
+        self.on_desync = Some(callback);
+        self
+    }
+
+    /// Records the traffic going through this codec in `stats`.
+    pub(crate) fn with_stats(mut self, stats: ConnectionStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Returns a codec for encoding the messages sent in reply to those decoded by `self`.
+    ///
+    /// With the `msgpack` feature enabled, the returned codec encodes messages with MessagePack
+    /// whenever the last message decoded by `self` was encoded with MessagePack.
+    pub(crate) fn output<U>(&self) -> LanguageServerCodec<U> {
+        LanguageServerCodec {
+            #[cfg(feature = "msgpack")]
+            peer_msgpack: self.peer_msgpack.clone(),
+            stats: self.stats.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns how far the decoder got through the message it is currently decoding.
+    ///
+    /// While the header section is incomplete, the decoder only looks for newlines in the bytes
+    /// received since the last call, and parses the header section again from its start whenever
+    /// a new line is complete. Since the header section is limited to 8 KiB, this bounds the work
+    /// done for headers trickling in slowly.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # fn main() {
+    /// use bytes::BytesMut;
+    /// use tokio_util::codec::Decoder;
+    /// use tower_lsp::codec::{DecodeProgress, LanguageServerCodec};
+    /// use tower_lsp::jsonrpc::Message;
+    ///
+    /// let mut codec = LanguageServerCodec::<Message>::default();
+    /// let mut buffer = BytesMut::from(&b"Content-Length: 33\r\n\r\n{\"jsonrpc\""[..]);
+    /// assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    /// assert_eq!(codec.progress().bytes_needed(), Some(23));
+    ///
+    /// buffer.extend_from_slice(br#":"2.0","method":"exit"}"#);
+    /// assert!(codec.decode(&mut buffer).unwrap().is_some());
+    /// assert_eq!(codec.progress(), DecodeProgress::Idle);
+    /// # }
+    /// # #[cfg(not(feature = "runtime-tokio"))]
+    /// # fn main() {}
+    /// ```
+    pub fn progress(&self) -> DecodeProgress {
+        match self.content_len {
+            Some(content_len) => DecodeProgress::Body {
+                received: self.received,
+                remaining: content_len - self.received,
+            },
+            None if self.received == 0 => DecodeProgress::Idle,
+            None => DecodeProgress::Headers {
+                received: self.received,
+                lines: self.lines,
+            },
+        }
+    }
+
+    /// Resets the decoder in preparation for parsing the next message.
+    ///
+    /// This also drops the custom headers collected so far, so that those of a rejected message
+    /// are never reported along with the next one.
+    fn reset(&mut self) {
+        self.content_len = None;
+        self.received = 0;
+        self.lines = 0;
+        self.body_format = BodyFormat::Json;
+        self.custom_headers.clear();
+    }
+
+    /// Writes `item` to `dst` as a complete message, including headers.
+    fn encode_message(&mut self, item: T, dst: &mut BytesMut) -> Result<(), ParseError>
+    where
+        T: Serialize,
+    {
+        let len = dst.len();
+        self.encode_frame(item, dst)?;
+        if let Some(stats) = &self.stats {
+            stats.record_write(dst.len() - len);
+        }
+
+        Ok(())
+    }
+
+    /// Like `encode_message`, without updating the traffic counters.
+    fn encode_frame(&mut self, item: T, dst: &mut BytesMut) -> Result<(), ParseError>
+    where
+        T: Serialize,
+    {
+        #[cfg(feature = "msgpack")]
+        if self.peer_msgpack.load(Ordering::Relaxed) {
+            let msg = rmp_serde::to_vec_named(&item)
+                .map_err(|e| IoError::new(std::io::ErrorKind::InvalidData, e))?;
+
+            const CONTENT_TYPE: &str = "Content-Type: application/msgpack";
+            dst.reserve(msg.len() + number_of_digits(msg.len()) + CONTENT_TYPE.len() + 22);
+            let mut writer = dst.writer();
+            write!(
+                writer,
+                "Content-Length: {}\r\n{CONTENT_TYPE}\r\n\r\n",
+                msg.len()
+            )?;
+            writer.write_all(&msg)?;
+            writer.flush()?;
+
+            return Ok(());
+        }
 
-    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let msg = serde_json::to_string(&item)?;
-        trace!("-> {}", msg);
 
         // Reserve just enough space to hold the `Content-Length: ` and `\r\n\r\n` constants,
         // the length of the message, and the message body.
@@ -132,22 +437,57 @@ impl<T: Serialize> Encoder for LanguageServerCodec<T> {
     }
 }
 
+impl<T> Debug for LanguageServerCodec<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(LanguageServerCodec))
+            .field("content_len", &self.content_len)
+            .field("received", &self.received)
+            .field("lines", &self.lines)
+            .field("body_format", &self.body_format)
+            .field("validate_utf8", &self.validate_utf8)
+            .field("custom_headers", &self.custom_headers)
+            .field("on_custom_headers", &self.on_custom_headers)
+            .field("on_desync", &self.on_desync)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<T> Default for LanguageServerCodec<T> {
+    fn default() -> Self {
+        LanguageServerCodec {
+            content_len: None,
+            received: 0,
+            lines: 0,
+            body_format: BodyFormat::Json,
+            validate_utf8: true,
+            #[cfg(feature = "msgpack")]
+            peer_msgpack: Arc::new(AtomicBool::new(false)),
+            custom_headers: Vec::new(),
+            on_custom_headers: None,
+            on_desync: None,
+            stats: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "runtime-agnostic")]
+impl<T: Serialize> Encoder for LanguageServerCodec<T> {
+    type Item = T;
+    type Error = ParseError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_message(item, dst)
+    }
+}
+
 #[cfg(feature = "runtime-tokio")]
 impl<T: Serialize> Encoder<T> for LanguageServerCodec<T> {
     type Error = ParseError;
 
     fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let msg = serde_json::to_string(&item)?;
-        trace!("-> {}", msg);
-
-        // Reserve just enough space to hold the `Content-Length: ` and `\r\n\r\n` constants,
-        // the length of the message, and the message body.
-        dst.reserve(msg.len() + number_of_digits(msg.len()) + 20);
-        let mut writer = dst.writer();
-        write!(writer, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)?;
-        writer.flush()?;
-
-        Ok(())
+        self.encode_message(item, dst)
     }
 }
 
@@ -167,50 +507,149 @@ impl<T: DeserializeOwned> Decoder for LanguageServerCodec<T> {
     type Error = ParseError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let len = src.len();
+        let result = self.decode_frame(src);
+        if let Some(stats) = &self.stats {
+            stats.record_read(len - src.len(), &result);
+        }
+
+        // Errors in the header section are only returned once the offending bytes were skipped,
+        // and before anything else is consumed.
+        if let Some(reason) = result.as_ref().err().and_then(desync_reason) {
+            let desync = Desync {
+                skipped_bytes: len - src.len(),
+                reason,
+            };
+            if let Some(stats) = &self.stats {
+                stats.record_desync(&desync);
+            }
+            if let Some(callback) = &self.on_desync {
+                (callback.0)(&desync);
+            }
+        }
+
+        result
+    }
+}
+
+impl<T: DeserializeOwned> LanguageServerCodec<T> {
+    /// Like `decode`, without updating the traffic counters.
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<T>, ParseError> {
         if let Some(content_len) = self.content_len {
             if src.len() < content_len {
+                self.received = src.len();
                 return Ok(None);
             }
 
             let bytes = &src[..content_len];
-            let message = std::str::from_utf8(bytes)?;
-
-            let result = if message.is_empty() {
-                Ok(None)
-            } else {
-                trace!("<- {}", message);
-                match serde_json::from_str(message) {
+            let result = match self.body_format {
+                BodyFormat::Json if !self.validate_utf8 => match bytes {
+                    [] => Ok(None),
+                    bytes => match serde_json::from_slice(bytes) {
+                        Ok(parsed) => Ok(Some(parsed)),
+                        Err(err) => Err(err.into()),
+                    },
+                },
+                BodyFormat::Json => match std::str::from_utf8(bytes) {
+                    Ok("") => Ok(None),
+                    Ok(message) => match serde_json::from_str(message) {
+                        Ok(parsed) => Ok(Some(parsed)),
+                        Err(err) => Err(err.into()),
+                    },
+                    Err(err) => Err(err.into()),
+                },
+                #[cfg(feature = "msgpack")]
+                BodyFormat::MessagePack if bytes.is_empty() => Ok(None),
+                #[cfg(feature = "msgpack")]
+                BodyFormat::MessagePack => match rmp_serde::from_slice(bytes) {
                     Ok(parsed) => Ok(Some(parsed)),
                     Err(err) => Err(err.into()),
-                }
+                },
             };
 
+            #[cfg(feature = "msgpack")]
+            if let Ok(Some(_)) = result {
+                let msgpack = self.body_format == BodyFormat::MessagePack;
+                self.peer_msgpack.store(msgpack, Ordering::Relaxed);
+            }
+
             src.advance(content_len);
-            self.content_len = None; // Reset state in preparation for parsing next message.
+            let custom_headers = std::mem::take(&mut self.custom_headers);
+            self.reset();
+
+            if let (Ok(Some(_)), Some(callback)) = (&result, &self.on_custom_headers) {
+                if !custom_headers.is_empty() {
+                    (callback.0)(&custom_headers);
+                }
+            }
 
             result
         } else {
-            let mut dst = [httparse::EMPTY_HEADER; 2];
+            // Bytes already seen were parsed without reaching the end of the header section, which
+            // can only be completed by another newline, so only the new bytes need to be scanned.
+            let fresh = src.get(self.received..).unwrap_or_default();
+            let lines = memchr::memchr_iter(b'\n', fresh).count();
+            if lines == 0 && self.received > 0 && src.len() <= MAX_HEADERS_LEN {
+                self.received = src.len();
+                return Ok(None);
+            }
+
+            let mut dst = [httparse::EMPTY_HEADER; INLINE_HEADERS];
+            let mut dst_large;
+
+            let parsed = match httparse::parse_headers(src, &mut dst) {
+                Err(httparse::Error::TooManyHeaders) => {
+                    // Every header ends with a newline, and the header section cannot be larger
+                    // than `MAX_HEADERS_LEN`, so this is always enough room for a valid message.
+                    let limit = src.len().min(MAX_HEADERS_LEN);
+                    let lines = memchr::memchr_iter(b'\n', &src[..limit]).count();
+                    dst_large = vec![httparse::EMPTY_HEADER; lines];
+                    httparse::parse_headers(src, &mut dst_large)
+                }
+                parsed => parsed,
+            };
 
-            let (headers_len, headers) = match httparse::parse_headers(src, &mut dst)? {
-                httparse::Status::Complete(output) => output,
-                httparse::Status::Partial => return Ok(None),
+            let (headers_len, headers) = match parsed {
+                Ok(httparse::Status::Complete(output)) if output.0 <= MAX_HEADERS_LEN => output,
+                Ok(httparse::Status::Partial) if src.len() <= MAX_HEADERS_LEN => {
+                    self.received = src.len();
+                    self.lines += lines;
+                    return Ok(None);
+                }
+                Ok(_) => {
+                    self.reset();
+                    skip_garbage(src, 1);
+                    return Err(ParseError::HeadersTooLarge);
+                }
+                Err(err) => {
+                    self.reset();
+                    skip_garbage(src, 1);
+                    return Err(err.into());
+                }
             };
 
-            match decode_headers(headers) {
-                Ok(content_len) => {
+            self.reset();
+            let custom_headers = self
+                .on_custom_headers
+                .as_ref()
+                .map(|_| &mut self.custom_headers);
+            match decode_headers(headers, custom_headers) {
+                Ok((content_len, body_format)) => {
                     src.advance(headers_len);
                     self.content_len = Some(content_len);
-                    self.decode(src) // Recurse right back in, now that `Content-Length` is known.
+                    self.body_format = body_format;
+                    self.decode_frame(src) // Recurse right back in, now that `Content-Length` is known.
                 }
                 Err(err) => {
+                    self.reset();
                     match err {
-                        ParseError::MissingContentLength => {}
-                        _ => src.advance(headers_len),
+                        ParseError::MissingContentLength => skip_garbage(src, 1),
+                        _ => {
+                            src.advance(headers_len);
+                            skip_garbage(src, 0);
+                        }
                     }
 
-                    // Skip any garbage bytes by scanning ahead for another potential message.
-                    src.advance(memmem::find(src, b"Content-Length").unwrap_or_default());
                     Err(err)
                 }
             }
@@ -218,8 +657,45 @@ impl<T: DeserializeOwned> Decoder for LanguageServerCodec<T> {
     }
 }
 
-fn decode_headers(headers: &[httparse::Header<'_>]) -> Result<usize, ParseError> {
+/// Skips garbage bytes by scanning ahead for another potential message, starting at `from`.
+///
+/// If no other message is found, all bytes are discarded except for a trailing fragment which
+/// could still turn out to be the start of a `Content-Length` header once more data arrives.
+fn skip_garbage(src: &mut BytesMut, from: usize) {
+    const NEEDLE: &[u8] = b"Content-Length";
+
+    let from = from.min(src.len());
+    let skip = match memmem::find(&src[from..], NEEDLE) {
+        Some(offset) => from + offset,
+        None => src.len().saturating_sub(NEEDLE.len() - 1).max(from),
+    };
+
+    src.advance(skip);
+}
+
+/// Returns why the input was resynchronized after `err`, or `None` if the message boundaries were
+/// not lost, e.g. because only the body of a message failed to parse.
+fn desync_reason(err: &ParseError) -> Option<DesyncReason> {
+    match err {
+        ParseError::Headers(_) => Some(DesyncReason::MalformedHeaders),
+        ParseError::HeadersTooLarge => Some(DesyncReason::HeadersTooLarge),
+        ParseError::MissingContentLength => Some(DesyncReason::MissingContentLength),
+        ParseError::InvalidContentLength(_) | ParseError::InvalidContentType => {
+            Some(DesyncReason::InvalidHeaderValue)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the `Content-Length` and body encoding declared by `headers`.
+///
+/// Non-standard headers are appended to `custom_headers`, if provided, or logged otherwise.
+fn decode_headers(
+    headers: &[httparse::Header<'_>],
+    mut custom_headers: Option<&mut Vec<(String, String)>>,
+) -> Result<(usize, BodyFormat), ParseError> {
     let mut content_len = None;
+    let mut body_format = BodyFormat::Json;
 
     for header in headers {
         match header.name {
@@ -230,28 +706,110 @@ fn decode_headers(headers: &[httparse::Header<'_>]) -> Result<usize, ParseError>
             }
             "Content-Type" => {
                 let string = std::str::from_utf8(header.value)?;
-                let charset = string
-                    .split(';')
-                    .skip(1)
-                    .map(|param| param.trim())
-                    .find_map(|param| param.strip_prefix("charset="));
-
-                match charset {
-                    Some("utf-8") | Some("utf8") => {}
-                    _ => return Err(ParseError::InvalidContentType),
-                }
+                body_format = decode_content_type(string)?;
             }
-            other => warn!("encountered unsupported header: {:?}", other),
+            other => match custom_headers {
+                Some(ref mut custom_headers) => {
+                    let value = String::from_utf8_lossy(header.value).into_owned();
+                    custom_headers.push((other.to_owned(), value));
+                }
+                None => warn!("encountered unsupported header: {:?}", other),
+            },
         }
     }
 
     if let Some(content_len) = content_len {
-        Ok(content_len)
+        Ok((content_len, body_format))
     } else {
         Err(ParseError::MissingContentLength)
     }
 }
 
+/// Returns the body encoding declared by the `Content-Type` header `value`.
+///
+/// JSON bodies are accepted whatever their media type, as long as they are encoded in UTF-8.
+fn decode_content_type(value: &str) -> Result<BodyFormat, ParseError> {
+    let mut params = value.split(';').map(|param| param.trim());
+
+    match params.next() {
+        #[cfg(feature = "msgpack")]
+        Some("application/msgpack" | "application/x-msgpack") => Ok(BodyFormat::MessagePack),
+        _ => match params.find_map(|param| param.strip_prefix("charset=")) {
+            Some("utf-8") | Some("utf8") => Ok(BodyFormat::Json),
+            _ => Err(ParseError::InvalidContentType),
+        },
+    }
+}
+
+/// Feeds arbitrary bytes through the message decoder, for use as a `cargo fuzz` target.
+///
+/// The first byte of `data` selects a chunk size, and the remaining bytes are decoded both all at
+/// once and split into chunks of that size, mimicking partial reads from the underlying transport.
+///
+/// # Panics
+///
+/// Panics if the decoder panics, fails to make progress after reporting an error, or if valid input
+/// decodes to different messages depending on how it was split into chunks.
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub fn fuzz(data: &[u8]) {
+    let (chunk_size, input) = match data.split_first() {
+        Some((first, rest)) => (usize::from(*first).max(1), rest),
+        None => return,
+    };
+
+    let (whole, errors) = decode_chunks(std::iter::once(input));
+    let (chunked, _) = decode_chunks(input.chunks(chunk_size));
+
+    if errors == 0 {
+        assert_eq!(
+            whole, chunked,
+            "decoded messages differ when input is chunked"
+        );
+    }
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
+fn decode_chunks<'a, I>(chunks: I) -> (Vec<serde_json::Value>, usize)
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    let mut codec = LanguageServerCodec::default();
+    let mut buffer = BytesMut::new();
+    let mut messages = Vec::new();
+    let mut errors = 0;
+
+    for chunk in chunks {
+        buffer.extend_from_slice(chunk);
+
+        loop {
+            let len = buffer.len();
+            match codec.decode(&mut buffer) {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) if buffer.len() < len => {}
+                Ok(None) => {
+                    match codec.progress() {
+                        DecodeProgress::Idle => assert!(buffer.is_empty()),
+                        DecodeProgress::Headers { received, .. } => {
+                            assert_eq!(received, buffer.len())
+                        }
+                        DecodeProgress::Body { received, .. } => {
+                            assert_eq!(received, buffer.len())
+                        }
+                    }
+                    break;
+                }
+                Err(_) => {
+                    errors += 1;
+                    assert!(buffer.len() < len, "decoder made no progress after error");
+                }
+            }
+        }
+    }
+
+    (messages, errors)
+}
+
 #[cfg(test)]
 mod tests {
     use bytes::BytesMut;
@@ -298,6 +856,42 @@ mod tests {
         assert_eq!(message, Some(decoded));
     }
 
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn negotiates_msgpack() {
+        let item = serde_json::json!({"jsonrpc":"2.0","method":"exit"});
+        let json = encode_message(None, &item.to_string());
+
+        let body = rmp_serde::to_vec_named(&item).unwrap();
+        let headers = format!(
+            "Content-Length: {}\r\nContent-Type: application/msgpack\r\n\r\n",
+            body.len()
+        );
+        let mut msgpack = BytesMut::from(headers.as_str());
+        msgpack.extend_from_slice(&body);
+
+        let mut codec = LanguageServerCodec::default();
+        let mut output = codec.output();
+
+        let mut buffer = BytesMut::new();
+        output.encode(item.clone(), &mut buffer).unwrap();
+        assert_eq!(buffer, BytesMut::from(json.as_str()));
+
+        let message = codec.decode(&mut msgpack.clone()).unwrap();
+        assert_eq!(message, Some(item.clone()));
+
+        let mut buffer = BytesMut::new();
+        output.encode(item.clone(), &mut buffer).unwrap();
+        assert_eq!(buffer, msgpack);
+
+        let message = codec.decode(&mut BytesMut::from(json.as_str())).unwrap();
+        assert_eq!(message, Some(item.clone()));
+
+        let mut buffer = BytesMut::new();
+        output.encode(item, &mut buffer).unwrap();
+        assert_eq!(buffer, BytesMut::from(json.as_str()));
+    }
+
     #[test]
     fn decodes_optional_content_type() {
         let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
@@ -385,6 +979,44 @@ mod tests {
         assert_eq!(message, None);
     }
 
+    #[test]
+    fn reports_desyncs() {
+        use std::sync::Mutex;
+
+        let encoded = encode_message(None, r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        let invalid = "Content-Length: foobar\r\n\r\n";
+        let mixed = format!("foobar{encoded}{invalid}{encoded}Content-Length: 1\r\n\r\n[");
+
+        let desyncs = Arc::new(Mutex::new(Vec::new()));
+        let reported = desyncs.clone();
+        let stats = ConnectionStats::default();
+        let mut codec = LanguageServerCodec::<Value>::default()
+            .with_stats(stats.clone())
+            .with_desync_callback(DesyncCallback::new(move |desync| {
+                reported.lock().unwrap().push(*desync)
+            }));
+
+        let mut buffer = BytesMut::from(mixed.as_str());
+        while !matches!(codec.decode(&mut buffer), Ok(None)) {}
+
+        let desync = |skipped_bytes, reason| Desync {
+            skipped_bytes,
+            reason,
+        };
+        assert_eq!(
+            *desyncs.lock().unwrap(),
+            [
+                desync(6, DesyncReason::MissingContentLength),
+                desync(invalid.len(), DesyncReason::InvalidHeaderValue),
+            ]
+        );
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.desyncs, 2);
+        assert_eq!(snapshot.skipped_bytes, 6 + invalid.len() as u64);
+        assert_eq!(snapshot.decode_errors, 3);
+    }
+
     #[test]
     fn decodes_small_chunks() {
         let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
@@ -413,4 +1045,178 @@ mod tests {
         let message = codec.decode(&mut buffer).unwrap();
         assert_eq!(message, Some(decoded));
     }
+
+    #[test]
+    fn reports_progress() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let content_type = "application/vscode-jsonrpc; charset=utf-8";
+        let encoded = encode_message(Some(content_type), decoded);
+        let headers_len = encoded.len() - decoded.len();
+
+        let mut codec = LanguageServerCodec::<Value>::default();
+        let mut buffer = BytesMut::new();
+        assert_eq!(codec.progress(), DecodeProgress::Idle);
+
+        for (i, byte) in encoded.bytes().enumerate() {
+            buffer.extend_from_slice(&[byte]);
+            let message = codec.decode(&mut buffer).unwrap();
+
+            let received = i + 1;
+            let progress = codec.progress();
+            if received == encoded.len() {
+                assert!(message.is_some());
+                assert_eq!(progress, DecodeProgress::Idle);
+            } else if received < headers_len {
+                let lines = encoded[..received].matches('\n').count();
+                assert_eq!(progress, DecodeProgress::Headers { received, lines });
+                assert_eq!(progress.bytes_needed(), None);
+            } else {
+                let remaining = encoded.len() - received;
+                let received = received - headers_len;
+                assert_eq!(
+                    progress,
+                    DecodeProgress::Body {
+                        received,
+                        remaining
+                    }
+                );
+                assert_eq!(progress.bytes_needed(), Some(remaining));
+            }
+        }
+
+        buffer.extend_from_slice(b"Content-Length: 2\r\n");
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(b"\x00\r\n");
+        assert_err!(codec.decode(&mut buffer), Err(ParseError::Headers(_)));
+        assert_eq!(codec.progress(), DecodeProgress::Idle);
+    }
+
+    #[test]
+    fn recovers_from_pathological_headers() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let encoded = encode_message(None, decoded);
+        let many_headers = "X-Foo: bar\r\n".repeat(100);
+        let cr_only = "Content-Length: 33\r\r";
+        let mixed = format!("{many_headers}\r\n{cr_only}{decoded}\x00\u{1}{encoded}");
+
+        let mut codec = LanguageServerCodec::default();
+        let mut buffer = BytesMut::from(mixed.as_str());
+        assert_err!(
+            codec.decode(&mut buffer),
+            Err(ParseError::MissingContentLength)
+        );
+        assert_err!(codec.decode(&mut buffer), Err(ParseError::Headers(_)));
+
+        let message: Option<Value> = codec.decode(&mut buffer).unwrap();
+        let valid = serde_json::from_str(decoded).unwrap();
+        assert_eq!(message, Some(valid));
+        assert!(buffer.is_empty());
+
+        let mut buffer = BytesMut::from("X-Foo: ".repeat(MAX_HEADERS_LEN).as_str());
+        assert_err!(codec.decode(&mut buffer), Err(ParseError::HeadersTooLarge));
+        assert!(buffer.len() < "Content-Length".len());
+    }
+
+    #[test]
+    fn recovers_from_invalid_utf8() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let mut bytes = b"Content-Length: 2\r\n\r\n\xff\xfe".to_vec();
+        bytes.extend_from_slice(encode_message(None, decoded).as_bytes());
+
+        let mut codec = LanguageServerCodec::default();
+        let mut buffer = BytesMut::from(&bytes[..]);
+        assert_err!(codec.decode(&mut buffer), Err(ParseError::Utf8(_)));
+
+        let message: Option<Value> = codec.decode(&mut buffer).unwrap();
+        let valid = serde_json::from_str(decoded).unwrap();
+        assert_eq!(message, Some(valid));
+    }
+
+    #[test]
+    fn decodes_without_utf8_validation() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit","params":"héllo"}"#;
+        let mut bytes = b"Content-Length: 4\r\n\r\n\"\xff\"\n".to_vec();
+        bytes.extend_from_slice(encode_message(None, decoded).as_bytes());
+        bytes.extend_from_slice(b"Content-Length: 0\r\n\r\n");
+
+        let mut codec = LanguageServerCodec::default().without_utf8_validation();
+        let mut buffer = BytesMut::from(&bytes[..]);
+        assert_err!(codec.decode(&mut buffer), Err(ParseError::Body(_)));
+
+        let message: Option<Value> = codec.decode(&mut buffer).unwrap();
+        let valid = serde_json::from_str(decoded).unwrap();
+        assert_eq!(message, Some(valid));
+
+        let message: Option<Value> = codec.decode(&mut buffer).unwrap();
+        assert_eq!(message, None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn passes_through_custom_headers() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let extra: String = (0..20).map(|i| format!("X-Extra-{i}: {i}\r\n")).collect();
+        let encoded = format!(
+            "X-Session-Id: abc123\r\n{extra}{}{}",
+            encode_message(None, decoded),
+            encode_message(None, decoded),
+        );
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback = {
+            let received = received.clone();
+            HeaderCallback::new(move |headers| received.lock().unwrap().push(headers.to_vec()))
+        };
+
+        let mut codec = LanguageServerCodec::with_header_callback(callback);
+        let mut buffer = BytesMut::from(encoded.as_str());
+        let expected: Value = serde_json::from_str(decoded).unwrap();
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(expected.clone()));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(expected));
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].len(), 21);
+        assert_eq!(received[0][0], ("X-Session-Id".into(), "abc123".into()));
+        assert_eq!(received[0][20], ("X-Extra-19".into(), "19".into()));
+    }
+
+    #[test]
+    fn drops_custom_headers_of_rejected_messages() {
+        let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let encoded = format!(
+            "X-Session-Id: rejected\r\nContent-Length: nope\r\n\r\n\
+             Content-Length: {}\r\nX-Session-Id: accepted\r\n\r\n{}",
+            decoded.len(),
+            decoded,
+        );
+
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback = {
+            let received = received.clone();
+            HeaderCallback::new(move |headers| received.lock().unwrap().push(headers.to_vec()))
+        };
+
+        let mut codec = LanguageServerCodec::with_header_callback(callback);
+        let mut buffer = BytesMut::from(encoded.as_str());
+        let result: Result<Option<Value>, _> = codec.decode(&mut buffer);
+        assert_err!(result, Err(ParseError::InvalidContentLength(_)));
+
+        let expected: Value = serde_json::from_str(decoded).unwrap();
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(expected));
+
+        let received = received.lock().unwrap();
+        let accepted = ("X-Session-Id".to_owned(), "accepted".to_owned());
+        assert_eq!(*received, [vec![accepted]]);
+    }
+
+    #[test]
+    fn fuzz_regressions() {
+        let corpus_dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/decode");
+        for entry in std::fs::read_dir(corpus_dir).unwrap() {
+            let input = std::fs::read(entry.unwrap().path()).unwrap();
+            fuzz(&input);
+        }
+    }
 }
@@ -0,0 +1,436 @@
+//! Construction of completion lists tailored to the client.
+//!
+//! Completion is the hottest request of most language servers, and sending thousands of items
+//! carrying fields the client ignores can noticeably freeze an editor. [`CompletionBuilder`] caps
+//! the number of items, marking the list as incomplete so the client asks again as the user keeps
+//! typing, and drops the fields the client did not declare support for.
+//!
+//! Payloads can be reduced further with the `itemDefaults` of LSP 3.17, which are enabled with
+//! [`LspServiceBuilder::completion_item_defaults`](crate::LspServiceBuilder::completion_item_defaults).
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::completion::CompletionBuilder;
+//! use tower_lsp::lsp_types::*;
+//!
+//! # let capabilities = ClientCapabilities::default();
+//! let candidates = (0..1000).map(|i| CompletionItem::new_simple(format!("item{i}"), "".into()));
+//!
+//! let response = CompletionBuilder::new(&capabilities)
+//!     .max_items(100)
+//!     .items(candidates)
+//!     .finish();
+//!
+//! match response {
+//!     CompletionResponse::List(list) => {
+//!         assert!(list.is_incomplete);
+//!         assert_eq!(list.items.len(), 100);
+//!     }
+//!     CompletionResponse::Array(_) => unreachable!(),
+//! }
+//! ```
+
+use std::cmp::Ordering;
+
+use lsp_types::{
+    ClientCapabilities, CompletionItem, CompletionItemCapability, CompletionList,
+    CompletionListCapability, CompletionResponse, CompletionTextEdit, Documentation, MarkupKind,
+    TextEdit,
+};
+use serde_json::{Map, Value};
+
+/// Properties of completion items which may be moved into the `itemDefaults` of the list.
+///
+/// `editRange` is handled separately, since it is derived from the `textEdit` of each item.
+const ITEM_DEFAULTS: &[&str] = &[
+    "commitCharacters",
+    "insertTextFormat",
+    "insertTextMode",
+    "data",
+];
+
+/// A builder for `textDocument/completion` responses, tailored to the client capabilities.
+///
+/// To construct a `CompletionBuilder`, refer to [`CompletionBuilder::new`].
+#[derive(Clone, Debug)]
+pub struct CompletionBuilder {
+    capabilities: CompletionItemCapability,
+    items: Vec<CompletionItem>,
+    max_items: Option<usize>,
+    is_incomplete: bool,
+}
+
+impl CompletionBuilder {
+    /// Starts building a completion list for a client with the given `capabilities`.
+    pub fn new(capabilities: &ClientCapabilities) -> Self {
+        let text_document = capabilities.text_document.as_ref();
+        let completion = text_document.and_then(|c| c.completion.as_ref());
+        let item = completion.and_then(|c| c.completion_item.clone());
+
+        CompletionBuilder {
+            capabilities: item.unwrap_or_default(),
+            items: Vec::new(),
+            max_items: None,
+            is_incomplete: false,
+        }
+    }
+
+    /// Limits the list to at most `max` items.
+    ///
+    /// If more candidates were added, only the `max` items which come first by `sort_text`, or by
+    /// `label` if unset, are kept, and the list is marked as incomplete.
+    ///
+    /// The number of items is not limited by default.
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.max_items = Some(max);
+        self
+    }
+
+    /// Marks the list as incomplete, so the client asks for completions again as the user types.
+    ///
+    /// The list is complete by default, unless items were dropped by [`max_items`](Self::max_items).
+    pub fn incomplete(mut self) -> Self {
+        self.is_incomplete = true;
+        self
+    }
+
+    /// Adds a candidate item to the list.
+    pub fn item(mut self, item: CompletionItem) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    /// Adds several candidate items to the list.
+    pub fn items<I>(mut self, items: I) -> Self
+    where
+        I: IntoIterator<Item = CompletionItem>,
+    {
+        self.items.extend(items);
+        self
+    }
+
+    /// Constructs the completion list and returns it.
+    pub fn finish(self) -> CompletionResponse {
+        let CompletionBuilder {
+            capabilities,
+            mut items,
+            max_items,
+            mut is_incomplete,
+        } = self;
+
+        if let Some(max) = max_items.filter(|&max| items.len() > max) {
+            items.sort_by(by_sort_text);
+            items.truncate(max);
+            is_incomplete = true;
+        }
+
+        for item in &mut items {
+            trim(item, &capabilities);
+        }
+
+        CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        })
+    }
+}
+
+fn by_sort_text(a: &CompletionItem, b: &CompletionItem) -> Ordering {
+    let a = a.sort_text.as_ref().unwrap_or(&a.label);
+    let b = b.sort_text.as_ref().unwrap_or(&b.label);
+    a.cmp(b)
+}
+
+/// Removes or downgrades the fields of `item` which the client does not support.
+fn trim(item: &mut CompletionItem, caps: &CompletionItemCapability) {
+    let supports = |flag: Option<bool>| flag == Some(true);
+
+    if !supports(caps.commit_characters_support) {
+        item.commit_characters = None;
+    }
+
+    if !supports(caps.deprecated_support) {
+        #[allow(deprecated)]
+        {
+            item.deprecated = None;
+        }
+    }
+
+    if !supports(caps.preselect_support) {
+        item.preselect = None;
+    }
+
+    if !supports(caps.label_details_support) {
+        item.label_details = None;
+    }
+
+    let tags = caps.tag_support.as_ref().map(|t| &t.value_set[..]);
+    if let Some(item_tags) = &mut item.tags {
+        item_tags.retain(|tag| tags.map_or(false, |tags| tags.contains(tag)));
+        if item_tags.is_empty() {
+            item.tags = None;
+        }
+    }
+
+    let modes = caps.insert_text_mode_support.as_ref();
+    if let Some(mode) = item.insert_text_mode {
+        if !modes.map_or(false, |modes| modes.value_set.contains(&mode)) {
+            item.insert_text_mode = None;
+        }
+    }
+
+    if !supports(caps.insert_replace_support) {
+        if let Some(CompletionTextEdit::InsertAndReplace(edit)) = &mut item.text_edit {
+            let edit = TextEdit::new(edit.insert, std::mem::take(&mut edit.new_text));
+            item.text_edit = Some(CompletionTextEdit::Edit(edit));
+        }
+    }
+
+    let formats = caps.documentation_format.as_deref().unwrap_or_default();
+    if let Some(Documentation::MarkupContent(content)) = &mut item.documentation {
+        if content.kind == MarkupKind::Markdown && !formats.contains(&MarkupKind::Markdown) {
+            content.kind = MarkupKind::PlainText;
+        }
+    }
+}
+
+/// Moves the properties shared by all items of the completion `result` into its `itemDefaults`,
+/// as far as supported by the client.
+pub(crate) fn factor_item_defaults(result: Value, capabilities: &ClientCapabilities) -> Value {
+    let text_document = capabilities.text_document.as_ref();
+    let completion = text_document.and_then(|c| c.completion.as_ref());
+    let supported = match completion.and_then(|c| c.completion_list.as_ref()) {
+        Some(CompletionListCapability {
+            item_defaults: Some(supported),
+        }) if !supported.is_empty() => supported,
+        _ => return result,
+    };
+
+    let (list, items) = match result {
+        Value::Array(items) => (None, items),
+        Value::Object(mut list) => match list.remove("items") {
+            Some(Value::Array(items)) => (Some(list), items),
+            Some(items) => {
+                list.insert("items".into(), items);
+                return Value::Object(list);
+            }
+            None => return Value::Object(list),
+        },
+        result => return result,
+    };
+
+    // Defaults only pay off when shared by several items.
+    if items.len() < 2 || !items.iter().all(Value::is_object) {
+        return match list {
+            Some(mut list) => {
+                list.insert("items".into(), Value::Array(items));
+                Value::Object(list)
+            }
+            None => Value::Array(items),
+        };
+    }
+
+    let mut list = list.unwrap_or_default();
+    let mut items: Vec<Map<String, Value>> = items
+        .into_iter()
+        .filter_map(|item| match item {
+            Value::Object(item) => Some(item),
+            _ => None,
+        })
+        .collect();
+
+    let mut defaults = match list.remove("itemDefaults") {
+        Some(Value::Object(defaults)) => defaults,
+        _ => Map::new(),
+    };
+
+    for &key in ITEM_DEFAULTS {
+        if !supported.iter().any(|s| s == key) || defaults.contains_key(key) {
+            continue;
+        }
+
+        if let Some(value) = common_value(&items, |item| item.get(key).cloned()) {
+            for item in &mut items {
+                item.remove(key);
+            }
+            defaults.insert(key.into(), value);
+        }
+    }
+
+    let supports_edit_range = supported.iter().any(|s| s == "editRange");
+    if supports_edit_range && !defaults.contains_key("editRange") {
+        if let Some(range) = common_value(&items, edit_range) {
+            for item in &mut items {
+                if let Some(Value::Object(mut edit)) = item.remove("textEdit") {
+                    let new_text = edit.remove("newText").unwrap_or_default();
+                    item.insert("textEditText".into(), new_text);
+                }
+            }
+            defaults.insert("editRange".into(), range);
+        }
+    }
+
+    if !defaults.is_empty() {
+        list.insert("itemDefaults".into(), Value::Object(defaults));
+    }
+
+    // Lists built from bare arrays lack the required `isIncomplete` member.
+    list.entry("isIncomplete").or_insert(Value::Bool(false));
+    let items = items.into_iter().map(Value::Object).collect();
+    list.insert("items".into(), Value::Array(items));
+    Value::Object(list)
+}
+
+/// Returns the value `f` extracts from every item, if it is the same for all items.
+fn common_value<F>(items: &[Map<String, Value>], f: F) -> Option<Value>
+where
+    F: Fn(&Map<String, Value>) -> Option<Value>,
+{
+    let (first, rest) = items.split_first()?;
+    let value = f(first)?;
+    rest.iter()
+        .all(|item| f(item).as_ref() == Some(&value))
+        .then_some(value)
+}
+
+/// Returns the `editRange` equivalent to the `textEdit` of `item`, if any.
+fn edit_range(item: &Map<String, Value>) -> Option<Value> {
+    let edit = item.get("textEdit")?.as_object()?;
+    match (edit.get("range"), edit.get("insert"), edit.get("replace")) {
+        (Some(range), _, _) => Some(range.clone()),
+        (None, Some(insert), Some(replace)) => {
+            let mut range = Map::new();
+            range.insert("insert".into(), insert.clone());
+            range.insert("replace".into(), replace.clone());
+            Some(Value::Object(range))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        CompletionClientCapabilities, CompletionItemTag, InsertReplaceEdit, MarkupContent,
+        Position, Range, TagSupport, TextDocumentClientCapabilities,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    fn capabilities(completion: CompletionClientCapabilities) -> ClientCapabilities {
+        ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                completion: Some(completion),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_first_items_by_sort_text() {
+        let items =
+            ["c", "a", "d", "b"].map(|label| CompletionItem::new_simple(label.into(), "".into()));
+        let response = CompletionBuilder::new(&ClientCapabilities::default())
+            .max_items(2)
+            .items(items.clone())
+            .finish();
+
+        let list = match response {
+            CompletionResponse::List(list) => list,
+            CompletionResponse::Array(_) => panic!("expected a list"),
+        };
+        assert!(list.is_incomplete);
+        let labels: Vec<_> = list.items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, ["a", "b"]);
+
+        let response = CompletionBuilder::new(&ClientCapabilities::default())
+            .max_items(4)
+            .items(items)
+            .finish();
+        assert!(matches!(response, CompletionResponse::List(l) if !l.is_incomplete));
+    }
+
+    #[test]
+    fn trims_unsupported_fields() {
+        let range = Range::new(Position::new(0, 0), Position::new(0, 2));
+        let item = CompletionItem {
+            label: "foo".into(),
+            preselect: Some(true),
+            commit_characters: Some(vec![".".into()]),
+            tags: Some(vec![CompletionItemTag::DEPRECATED]),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "`foo`".into(),
+            })),
+            text_edit: Some(CompletionTextEdit::InsertAndReplace(InsertReplaceEdit {
+                new_text: "foo".into(),
+                insert: range,
+                replace: range,
+            })),
+            ..Default::default()
+        };
+
+        let caps = capabilities(CompletionClientCapabilities {
+            completion_item: Some(CompletionItemCapability {
+                preselect_support: Some(true),
+                tag_support: Some(TagSupport {
+                    value_set: vec![CompletionItemTag::DEPRECATED],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let response = CompletionBuilder::new(&caps).item(item).finish();
+        let item = match response {
+            CompletionResponse::List(mut list) => list.items.remove(0),
+            CompletionResponse::Array(_) => panic!("expected a list"),
+        };
+
+        assert_eq!(item.preselect, Some(true));
+        assert_eq!(item.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+        assert_eq!(item.commit_characters, None);
+        let edit = CompletionTextEdit::Edit(TextEdit::new(range, "foo".into()));
+        assert_eq!(item.text_edit, Some(edit));
+        match item.documentation {
+            Some(Documentation::MarkupContent(content)) => {
+                assert_eq!(content.kind, MarkupKind::PlainText)
+            }
+            _ => panic!("expected markup content"),
+        }
+    }
+
+    #[test]
+    fn factors_item_defaults() {
+        let caps = capabilities(CompletionClientCapabilities {
+            completion_list: Some(CompletionListCapability {
+                item_defaults: Some(vec!["editRange".into(), "insertTextFormat".into()]),
+            }),
+            ..Default::default()
+        });
+
+        let range = json!({"start":{"line":0,"character":0},"end":{"line":0,"character":1}});
+        let result = json!([
+            {"label":"a","insertTextFormat":2,"data":1,"textEdit":{"range":range,"newText":"a()"}},
+            {"label":"b","insertTextFormat":2,"data":1,"textEdit":{"range":range,"newText":"b()"}},
+        ]);
+
+        let unsupported = ClientCapabilities::default();
+        assert_eq!(factor_item_defaults(result.clone(), &unsupported), result);
+
+        assert_eq!(
+            factor_item_defaults(result, &caps),
+            json!({
+                "isIncomplete": false,
+                "itemDefaults": {"insertTextFormat": 2, "editRange": range},
+                "items": [
+                    {"label":"a","data":1,"textEditText":"a()"},
+                    {"label":"b","data":1,"textEditText":"b()"},
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Protocol-level conformance checks for language servers.
+//!
+//! The [`run`] function exercises an [`LspService`] with a battery of messages covering lifecycle
+//! ordering, error codes, cancellation semantics, and position encoding negotiation, and returns
+//! a [`Report`] describing which checks passed.
+//!
+//! This is primarily intended for use in the test suites of language servers built on top of
+//! `tower-lsp`, guarding against regressions in both the backend and this library.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::{conformance, LanguageServer, LspService};
+//! #
+//! # struct Backend;
+//! #
+//! # #[tower_lsp::async_trait]
+//! # impl LanguageServer for Backend {
+//! #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//! #         Ok(InitializeResult::default())
+//! #     }
+//! #
+//! #     async fn shutdown(&self) -> Result<()> {
+//! #         Ok(())
+//! #     }
+//! # }
+//! #
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let report = conformance::run(|| LspService::new(|_| Backend)).await;
+//! assert!(report.is_success(), "{report}");
+//! # }
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::stream::StreamExt;
+use serde_json::{json, Value};
+use tower::{Service, ServiceExt};
+
+use crate::jsonrpc::{Error, ErrorCode, Request, Response};
+use crate::service::{ClientSocket, ExitedError, LspService};
+use crate::LanguageServer;
+
+/// The outcome of a single conformance check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The service behaved as the specification requires.
+    Passed,
+    /// The service deviated from the specification, for the given reason.
+    Failed(String),
+}
+
+/// The result of a single named conformance check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckResult {
+    /// Short human-readable description of the check.
+    pub name: &'static str,
+    /// Whether the check passed.
+    pub outcome: Outcome,
+}
+
+/// A report summarizing the results of all conformance checks.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    /// Results of the individual checks, in execution order.
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// Returns `true` if every check passed.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|r| r.outcome == Outcome::Passed)
+    }
+
+    /// Returns an iterator over the checks which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|r| r.outcome != Outcome::Passed)
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for result in &self.results {
+            match &result.outcome {
+                Outcome::Passed => writeln!(f, "[PASS] {}", result.name)?,
+                Outcome::Failed(reason) => writeln!(f, "[FAIL] {}: {}", result.name, reason)?,
+            }
+        }
+
+        let passed = self.results.len() - self.failures().count();
+        write!(f, "{}/{} checks passed", passed, self.results.len())
+    }
+}
+
+type Check<S> = fn(LspService<S>) -> BoxFuture<'static, Result<(), String>>;
+
+/// Runs all conformance checks against services constructed by `make_service`.
+///
+/// A fresh service is constructed for every check, so that lifecycle state does not leak between
+/// them. Server-to-client requests issued by the backend while a check is running are answered
+/// with JSON-RPC error code `-32601` (method not found).
+///
+/// Note that checks have no timeout, so a backend which never responds to a request will cause
+/// this future to never resolve.
+pub async fn run<S, F>(mut make_service: F) -> Report
+where
+    S: LanguageServer,
+    F: FnMut() -> (LspService<S>, ClientSocket),
+{
+    let checks: [(&'static str, Check<S>); 11] = [
+        (
+            "lifecycle: requests before `initialize` are rejected with -32002",
+            |s| request_before_initialize(s).boxed(),
+        ),
+        (
+            "lifecycle: notifications before `initialize` are dropped",
+            |s| notification_before_initialize(s).boxed(),
+        ),
+        ("lifecycle: `initialize` succeeds", |s| {
+            initialize_succeeds(s).boxed()
+        }),
+        (
+            "lifecycle: duplicate `initialize` is rejected with -32600",
+            |s| duplicate_initialize(s).boxed(),
+        ),
+        (
+            "lifecycle: requests after `shutdown` are rejected with -32600",
+            |s| request_after_shutdown(s).boxed(),
+        ),
+        ("lifecycle: `exit` stops the service", |s| {
+            exit_stops_service(s).boxed()
+        }),
+        ("errors: unknown requests are rejected with -32601", |s| {
+            unknown_request(s).boxed()
+        }),
+        ("errors: unknown `$/` notifications are ignored", |s| {
+            unknown_dollar_notification(s).boxed()
+        }),
+        ("errors: malformed params are rejected with -32602", |s| {
+            invalid_params(s).boxed()
+        }),
+        (
+            "cancellation: cancelled requests are still answered with their ID",
+            |s| cancellation_keeps_id(s).boxed(),
+        ),
+        (
+            "positions: UTF-16 is negotiated when it is the only encoding offered",
+            |s| utf16_positions(s).boxed(),
+        ),
+    ];
+
+    let mut report = Report::default();
+
+    for (name, check) in checks {
+        let (service, socket) = make_service();
+        let outcome = match run_check(check, service, socket).await {
+            Ok(()) => Outcome::Passed,
+            Err(reason) => Outcome::Failed(reason),
+        };
+
+        report.results.push(CheckResult { name, outcome });
+    }
+
+    report
+}
+
+async fn run_check<S>(
+    check: Check<S>,
+    service: LspService<S>,
+    socket: ClientSocket,
+) -> Result<(), String>
+where
+    S: LanguageServer,
+{
+    let (requests, responses) = socket.split();
+    let answer_requests = requests
+        .filter_map(|req| {
+            let id = req.id().cloned();
+            future::ready(id.map(|id| Ok(Response::from_error(id, Error::method_not_found()))))
+        })
+        .forward(responses);
+
+    let (answer_requests, abort) = future::abortable(answer_requests);
+    let check = check(service).map(|outcome| {
+        abort.abort();
+        outcome
+    });
+
+    let (outcome, _) = future::join(check, answer_requests).await;
+    outcome
+}
+
+async fn call<S: LanguageServer>(
+    service: &mut LspService<S>,
+    req: Request,
+) -> Result<Option<Response>, String> {
+    let method = req.method().to_owned();
+    let service = service
+        .ready()
+        .await
+        .map_err(|e| format!("service not ready for `{method}`: {e}"))?;
+
+    service
+        .call(req)
+        .await
+        .map_err(|e: ExitedError| format!("service failed on `{method}`: {e}"))
+}
+
+fn expect_error(response: Option<Response>, code: ErrorCode) -> Result<(), String> {
+    match response.as_ref().and_then(|res| res.error()) {
+        Some(err) if err.code == code => Ok(()),
+        Some(err) => Err(format!("expected error code {code}, got {}", err.code)),
+        None => Err(format!("expected error code {code}, got {response:?}")),
+    }
+}
+
+fn expect_none(response: Option<Response>) -> Result<(), String> {
+    match response {
+        None => Ok(()),
+        Some(res) => Err(format!("expected no response, got {res:?}")),
+    }
+}
+
+fn initialize_request(capabilities: Value) -> Request {
+    Request::build("initialize")
+        .params(json!({ "capabilities": capabilities }))
+        .id(0)
+        .finish()
+}
+
+async fn initialize<S: LanguageServer>(service: &mut LspService<S>) -> Result<Response, String> {
+    match call(service, initialize_request(json!({}))).await? {
+        Some(res) if res.is_ok() => Ok(res),
+        other => Err(format!("`initialize` failed: {other:?}")),
+    }
+}
+
+fn hover_request(id: i64) -> Request {
+    Request::build("textDocument/hover")
+        .params(json!({
+            "textDocument": { "uri": "file:///conformance.txt" },
+            "position": { "line": 0, "character": 0 },
+        }))
+        .id(id)
+        .finish()
+}
+
+async fn request_before_initialize<S: LanguageServer>(
+    mut service: LspService<S>,
+) -> Result<(), String> {
+    let response = call(&mut service, hover_request(1)).await?;
+    expect_error(response, ErrorCode::ServerError(-32002))
+}
+
+async fn notification_before_initialize<S: LanguageServer>(
+    mut service: LspService<S>,
+) -> Result<(), String> {
+    let did_open = Request::build("textDocument/didOpen")
+        .params(json!({
+            "textDocument": {
+                "uri": "file:///conformance.txt",
+                "languageId": "plaintext",
+                "version": 0,
+                "text": "",
+            },
+        }))
+        .finish();
+
+    expect_none(call(&mut service, did_open).await?)
+}
+
+async fn initialize_succeeds<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    let response = initialize(&mut service).await?;
+    match response.result().and_then(|r| r.get("capabilities")) {
+        Some(Value::Object(_)) => Ok(()),
+        _ => Err("`initialize` result lacks a `capabilities` object".into()),
+    }
+}
+
+async fn duplicate_initialize<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    initialize(&mut service).await?;
+    let response = call(&mut service, initialize_request(json!({}))).await?;
+    expect_error(response, ErrorCode::InvalidRequest)
+}
+
+async fn request_after_shutdown<S: LanguageServer>(
+    mut service: LspService<S>,
+) -> Result<(), String> {
+    initialize(&mut service).await?;
+
+    let shutdown = Request::build("shutdown").id(1).finish();
+    match call(&mut service, shutdown).await? {
+        Some(res) if res.is_ok() => {}
+        other => return Err(format!("`shutdown` failed: {other:?}")),
+    }
+
+    let response = call(&mut service, hover_request(2)).await?;
+    expect_error(response, ErrorCode::InvalidRequest)
+}
+
+async fn exit_stops_service<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    initialize(&mut service).await?;
+
+    let exit = Request::build("exit").finish();
+    expect_none(call(&mut service, exit).await?)?;
+
+    match future::poll_fn(|cx| service.poll_ready(cx)).await {
+        Err(_) => Ok(()),
+        Ok(()) => Err("service still accepts messages after `exit`".into()),
+    }
+}
+
+async fn unknown_request<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    initialize(&mut service).await?;
+    let unknown = Request::build("conformance/unknownMethod").id(1).finish();
+    let response = call(&mut service, unknown).await?;
+    expect_error(response, ErrorCode::MethodNotFound)
+}
+
+async fn unknown_dollar_notification<S: LanguageServer>(
+    mut service: LspService<S>,
+) -> Result<(), String> {
+    initialize(&mut service).await?;
+    let unknown = Request::build("$/conformanceUnknown").finish();
+    expect_none(call(&mut service, unknown).await?)
+}
+
+async fn invalid_params<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    initialize(&mut service).await?;
+    let hover = Request::build("textDocument/hover")
+        .params(json!("invalid"))
+        .id(1)
+        .finish();
+
+    let response = call(&mut service, hover).await?;
+    expect_error(response, ErrorCode::InvalidParams)
+}
+
+async fn cancellation_keeps_id<S: LanguageServer>(
+    mut service: LspService<S>,
+) -> Result<(), String> {
+    initialize(&mut service).await?;
+
+    let cancel = Request::build("$/cancelRequest")
+        .params(json!({ "id": 1 }))
+        .finish();
+
+    let request_fut = service.ready().await.map_err(|e| e.to_string())?;
+    let request_fut = request_fut.call(hover_request(1));
+    let cancel_fut = service.ready().await.map_err(|e| e.to_string())?;
+    let cancel_fut = cancel_fut.call(cancel);
+
+    let (response, cancel_response) = future::join(request_fut, cancel_fut).await;
+    expect_none(cancel_response.map_err(|e| e.to_string())?)?;
+
+    match response.map_err(|e| e.to_string())? {
+        Some(res) if *res.id() == 1.into() => Ok(()),
+        other => Err(format!("expected a response with ID 1, got {other:?}")),
+    }
+}
+
+async fn utf16_positions<S: LanguageServer>(mut service: LspService<S>) -> Result<(), String> {
+    let capabilities = json!({ "general": { "positionEncodings": ["utf-16"] } });
+    let response = match call(&mut service, initialize_request(capabilities)).await? {
+        Some(res) if res.is_ok() => res,
+        other => return Err(format!("`initialize` failed: {other:?}")),
+    };
+
+    let encoding = response
+        .result()
+        .and_then(|r| r.get("capabilities"))
+        .and_then(|c| c.get("positionEncoding"));
+
+    match encoding {
+        None | Some(Value::Null) => Ok(()),
+        Some(Value::String(s)) if s == "utf-16" => Ok(()),
+        Some(other) => Err(format!(
+            "server chose unsupported position encoding {other}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::*;
+
+    use super::*;
+    use crate::jsonrpc::Result;
+
+    #[derive(Debug)]
+    struct Mock;
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn initialized(&self, _: InitializedParams) {}
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn builtin_layers_conform() {
+        let report = run(|| LspService::new(|_| Mock)).await;
+        assert!(report.is_success(), "{report}");
+    }
+
+    #[test]
+    fn displays_report() {
+        let report = Report {
+            results: vec![
+                CheckResult {
+                    name: "first",
+                    outcome: Outcome::Passed,
+                },
+                CheckResult {
+                    name: "second",
+                    outcome: Outcome::Failed("reason".into()),
+                },
+            ],
+        };
+
+        assert!(!report.is_success());
+        let expected = "[PASS] first\n[FAIL] second: reason\n1/2 checks passed";
+        assert_eq!(report.to_string(), expected);
+    }
+}
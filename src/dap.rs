@@ -0,0 +1,248 @@
+//! Debug Adapter Protocol support, for serving debug adapters alongside language servers.
+//!
+//! The [Debug Adapter Protocol] uses the same `Content-Length` framing as LSP, but with its own
+//! message format. This module reuses the message codec and the [`jsonrpc::Router`] of the
+//! language server implementation to serve a [`DebugAdapter`], dispatching each request to the
+//! trait method of its `command`.
+//!
+//! [Debug Adapter Protocol]: https://microsoft.github.io/debug-adapter-protocol/
+//!
+//! Only the core commands of the protocol are covered by [`DebugAdapter`], and reverse requests,
+//! such as `runInTerminal`, are not supported yet. Events are sent through the [`DebugClient`]
+//! passed to the adapter on creation.
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::dap::types::*;
+//! use tower_lsp::dap::{DebugAdapter, DebugAdapterService, DebugClient, Server};
+//! use tower_lsp::jsonrpc::Result;
+//!
+//! struct Adapter {
+//!     client: DebugClient,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl DebugAdapter for Adapter {
+//!     async fn initialize(&self, _: InitializeRequestArguments) -> Result<Capabilities> {
+//!         self.client.initialized().await;
+//!         Ok(Capabilities {
+//!             supports_configuration_done_request: Some(true),
+//!             ..Capabilities::default()
+//!         })
+//!     }
+//!
+//!     async fn threads(&self) -> Result<ThreadsResponse> {
+//!         let main = Thread { id: 1, name: "main".into() };
+//!         Ok(ThreadsResponse { threads: vec![main] })
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "runtime-tokio")]
+//! # async fn run() {
+//! let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+//!
+//! let (service, socket) = DebugAdapterService::new(|client| Adapter { client });
+//! Server::new(stdin, stdout, socket).serve(service).await.unwrap();
+//! # }
+//! ```
+//!
+//! [`jsonrpc::Router`]: crate::jsonrpc::Router
+
+pub use self::server::Server;
+pub use self::service::{DebugAdapterService, DebugClient, DebugClientSocket};
+
+use auto_impl::auto_impl;
+use tower_lsp_macros::dap;
+use tracing::error;
+
+use self::types::*;
+use crate::async_trait;
+use crate::jsonrpc::{Error, Result};
+
+pub mod types;
+
+mod server;
+mod service;
+
+/// Trait implemented by debug adapter backends.
+///
+/// Every command except `initialize` has a default implementation answering with an error, so
+/// adapters only need to implement the commands they support.
+#[dap]
+#[async_trait]
+#[auto_impl(Arc, Box)]
+pub trait DebugAdapter: Send + Sync + 'static {
+    /// The [`initialize`] request is the first request sent from the client to the adapter, and
+    /// returns the capabilities of the adapter.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Initialize
+    ///
+    /// Once ready to accept configuration requests, the adapter must send the `initialized`
+    /// event with [`DebugClient::initialized`].
+    #[dap(command = "initialize")]
+    async fn initialize(&self, args: InitializeRequestArguments) -> Result<Capabilities>;
+
+    /// The [`configurationDone`] request indicates that the configuration of the debug session,
+    /// such as setting breakpoints, is complete.
+    ///
+    /// [`configurationDone`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_ConfigurationDone
+    #[dap(command = "configurationDone")]
+    async fn configuration_done(&self) -> Result<()> {
+        error!("Got a configurationDone request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`launch`] request starts the debuggee.
+    ///
+    /// [`launch`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Launch
+    #[dap(command = "launch")]
+    async fn launch(&self, args: LaunchRequestArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a launch request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`attach`] request attaches to an already running debuggee.
+    ///
+    /// [`attach`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Attach
+    #[dap(command = "attach")]
+    async fn attach(&self, args: AttachRequestArguments) -> Result<()> {
+        let _ = args;
+        error!("Got an attach request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`disconnect`] request ends the debug session.
+    ///
+    /// [`disconnect`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Disconnect
+    ///
+    /// The [`Server`] stops once this request was answered.
+    #[dap(command = "disconnect")]
+    async fn disconnect(&self, args: DisconnectArguments) -> Result<()> {
+        let _ = args;
+        Ok(())
+    }
+
+    /// The [`terminate`] request asks the debuggee to terminate gracefully.
+    ///
+    /// [`terminate`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Terminate
+    #[dap(command = "terminate")]
+    async fn terminate(&self, args: TerminateArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a terminate request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`setBreakpoints`] request replaces all breakpoints of a source.
+    ///
+    /// [`setBreakpoints`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_SetBreakpoints
+    #[dap(command = "setBreakpoints")]
+    async fn set_breakpoints(
+        &self,
+        args: SetBreakpointsArguments,
+    ) -> Result<SetBreakpointsResponse> {
+        let _ = args;
+        error!("Got a setBreakpoints request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`threads`] request retrieves the threads of the debuggee.
+    ///
+    /// [`threads`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Threads
+    #[dap(command = "threads")]
+    async fn threads(&self) -> Result<ThreadsResponse> {
+        error!("Got a threads request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`stackTrace`] request retrieves the stack frames of a thread.
+    ///
+    /// [`stackTrace`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_StackTrace
+    #[dap(command = "stackTrace")]
+    async fn stack_trace(&self, args: StackTraceArguments) -> Result<StackTraceResponse> {
+        let _ = args;
+        error!("Got a stackTrace request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`scopes`] request retrieves the variable scopes of a stack frame.
+    ///
+    /// [`scopes`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Scopes
+    #[dap(command = "scopes")]
+    async fn scopes(&self, args: ScopesArguments) -> Result<ScopesResponse> {
+        let _ = args;
+        error!("Got a scopes request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`variables`] request retrieves the child variables of a scope or variable.
+    ///
+    /// [`variables`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Variables
+    #[dap(command = "variables")]
+    async fn variables(&self, args: VariablesArguments) -> Result<VariablesResponse> {
+        let _ = args;
+        error!("Got a variables request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`continue`] request resumes the execution of a thread.
+    ///
+    /// [`continue`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Continue
+    #[dap(command = "continue")]
+    async fn resume(&self, args: ContinueArguments) -> Result<ContinueResponse> {
+        let _ = args;
+        error!("Got a continue request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`next`] request executes a thread until the next statement.
+    ///
+    /// [`next`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Next
+    #[dap(command = "next")]
+    async fn next(&self, args: StepArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a next request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`stepIn`] request steps into the function called by the current statement of a thread.
+    ///
+    /// [`stepIn`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_StepIn
+    #[dap(command = "stepIn")]
+    async fn step_in(&self, args: StepArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a stepIn request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`stepOut`] request executes a thread until it returns from the current function.
+    ///
+    /// [`stepOut`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_StepOut
+    #[dap(command = "stepOut")]
+    async fn step_out(&self, args: StepArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a stepOut request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`pause`] request suspends the execution of a thread.
+    ///
+    /// [`pause`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Pause
+    #[dap(command = "pause")]
+    async fn pause(&self, args: PauseArguments) -> Result<()> {
+        let _ = args;
+        error!("Got a pause request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`evaluate`] request evaluates an expression, e.g. for the debug console or a watch.
+    ///
+    /// [`evaluate`]: https://microsoft.github.io/debug-adapter-protocol/specification#Requests_Evaluate
+    #[dap(command = "evaluate")]
+    async fn evaluate(&self, args: EvaluateArguments) -> Result<EvaluateResponse> {
+        let _ = args;
+        error!("Got an evaluate request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+}
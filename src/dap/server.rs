@@ -0,0 +1,258 @@
+//! Server for debug adapters.
+
+#[cfg(feature = "runtime-agnostic")]
+use async_codec_lite::{FramedRead, FramedWrite};
+#[cfg(feature = "runtime-agnostic")]
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime-tokio")]
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::{join, FutureExt, SinkExt, StreamExt, TryFutureExt};
+use tower::Service;
+use tracing::{debug, error};
+
+use super::service::into_response;
+use super::types::{Message, Request, Response};
+use super::DebugClientSocket;
+use crate::codec::LanguageServerCodec;
+use crate::jsonrpc::Error;
+use crate::transport::ServeError;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+const MESSAGE_QUEUE_SIZE: usize = 100;
+
+/// Server for processing Debug Adapter Protocol messages on standard I/O or TCP.
+///
+/// Messages use the same `Content-Length` framing as LSP messages, and are decoded with the same
+/// codec as [`transport::Server`](crate::transport::Server).
+#[derive(Debug)]
+pub struct Server<I, O> {
+    stdin: I,
+    stdout: O,
+    socket: DebugClientSocket,
+    max_concurrency: usize,
+}
+
+impl<I, O> Server<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+{
+    /// Creates a new `Server` with the given `stdin` and `stdout` handles.
+    pub fn new(stdin: I, stdout: O, socket: DebugClientSocket) -> Self {
+        Server {
+            stdin,
+            stdout,
+            socket,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+        }
+    }
+
+    /// Sets the server concurrency limit to `max`.
+    ///
+    /// This setting specifies how many incoming requests may be processed concurrently. If not
+    /// explicitly specified, `max` defaults to 4.
+    pub fn concurrency_level(mut self, max: usize) -> Self {
+        self.max_concurrency = max;
+        self
+    }
+
+    /// Spawns the service with messages read through `stdin` and responses written to `stdout`.
+    ///
+    /// Returns `Ok(())` once the `disconnect` request was answered, and
+    /// [`ServeError::ClientDisconnected`] if the input stream was closed before. Responses sent by
+    /// the client to reverse requests and events are not supported, and are ignored.
+    pub async fn serve<T>(self, mut service: T) -> Result<(), ServeError>
+    where
+        T: Service<Request, Response = Response> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let (mut responses_tx, responses_rx) = mpsc::channel(0);
+        let (mut server_tasks_tx, server_tasks_rx) = mpsc::channel(MESSAGE_QUEUE_SIZE);
+
+        let codec = LanguageServerCodec::<Message>::default();
+        let framed_stdout = FramedWrite::new(self.stdout, codec.output::<Message>());
+        let mut framed_stdin = FramedRead::new(self.stdin, codec);
+
+        let process_server_tasks = server_tasks_rx
+            .buffer_unordered(self.max_concurrency)
+            .map(Ok)
+            .forward(responses_tx.clone().sink_map_err(|_| unreachable!()))
+            .map(|_| ());
+
+        // Responses and events share a single sequence of `seq` numbers, assigned in the order in
+        // which they are written. Events are preferred, so that those sent while handling a request
+        // precede its response. Once all responses were written, the remaining events are drained.
+        let mut socket = self.socket;
+        let mut responses = responses_rx;
+        let print_output = async {
+            futures::pin_mut!(framed_stdout);
+
+            let mut seq = 0;
+            let (mut events_open, mut responses_open) = (true, true);
+            loop {
+                let mut msg = if events_open && responses_open {
+                    match future::select(socket.next(), responses.next()).await {
+                        Either::Left((Some(event), _)) => Message::Event(event),
+                        Either::Left((None, _)) => {
+                            events_open = false;
+                            continue;
+                        }
+                        Either::Right((Some(res), _)) => Message::Response(res),
+                        Either::Right((None, _)) => {
+                            responses_open = false;
+                            socket.close();
+                            continue;
+                        }
+                    }
+                } else if responses_open {
+                    match responses.next().await {
+                        Some(res) => Message::Response(res),
+                        None => break,
+                    }
+                } else {
+                    match socket.next().await {
+                        Some(event) => Message::Event(event),
+                        None => break,
+                    }
+                };
+
+                seq += 1;
+                match &mut msg {
+                    Message::Request(req) => req.seq = seq,
+                    Message::Response(res) => res.seq = seq,
+                    Message::Event(event) => event.seq = seq,
+                }
+
+                if let Err(err) = framed_stdout.send(msg).await {
+                    error!("failed to encode message: {}", err);
+                }
+            }
+        };
+
+        let read_input = async {
+            let result = loop {
+                let msg = match framed_stdin.next().await {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(err)) => {
+                        error!("failed to decode message: {}", err);
+                        continue;
+                    }
+                    None => break Err(ServeError::ClientDisconnected),
+                };
+
+                let req = match msg {
+                    Message::Request(req) => req,
+                    Message::Response(res) => {
+                        debug!("ignoring response to reverse request {}", res.request_seq);
+                        continue;
+                    }
+                    Message::Event(event) => {
+                        debug!("ignoring event sent by the client: {}", event.event);
+                        continue;
+                    }
+                };
+
+                if let Err(err) = future::poll_fn(|cx| service.poll_ready(cx)).await {
+                    let err = err.into().to_string();
+                    error!("{}", err);
+                    break Err(ServeError::ProtocolError(err));
+                }
+
+                let disconnect = req.command == "disconnect";
+                let (seq, command) = (req.seq, req.command.clone());
+                let fut = service.call(req).unwrap_or_else(move |err| {
+                    let message = err.into().to_string();
+                    error!("{}", message);
+                    into_response(seq, command, Err(Error::request_failed(message)))
+                });
+
+                server_tasks_tx.send(fut).await.unwrap();
+
+                if disconnect {
+                    break Ok(());
+                }
+            };
+
+            server_tasks_tx.disconnect();
+            responses_tx.disconnect();
+            result
+        };
+
+        let (result, (), ()) = join!(read_input, process_server_tasks, print_output);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::super::types::{Capabilities, DisconnectArguments, InitializeRequestArguments};
+    use super::super::{DebugAdapter, DebugAdapterService, DebugClient};
+    use super::*;
+    use crate::jsonrpc::Result;
+
+    struct Mock {
+        client: DebugClient,
+    }
+
+    #[crate::async_trait]
+    impl DebugAdapter for Mock {
+        async fn initialize(&self, _: InitializeRequestArguments) -> Result<Capabilities> {
+            Ok(Capabilities::default())
+        }
+
+        async fn disconnect(&self, _: DisconnectArguments) -> Result<()> {
+            self.client.terminated().await;
+            Ok(())
+        }
+    }
+
+    fn frame(msg: Value) -> String {
+        let body = msg.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_until_disconnect() {
+        let input = [
+            json!({"seq": 1, "type": "event", "event": "ignored"}),
+            json!({"seq": 2, "type": "request", "command": "disconnect"}),
+        ];
+        let input: String = input.into_iter().map(frame).collect();
+
+        let (mut stdin, client_out) = tokio::io::duplex(1024);
+        let (server_out, mut stdout) = tokio::io::duplex(1024);
+        stdin.write_all(input.as_bytes()).await.unwrap();
+
+        let (service, socket) = DebugAdapterService::new(|client| Mock { client });
+        let server = Server::new(client_out, server_out, socket);
+        server.serve(service).await.unwrap();
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await.unwrap();
+        let messages: Vec<Value> = output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|msg| serde_json::from_str(msg.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+
+        let terminated = json!({"seq": 1, "type": "event", "event": "terminated"});
+        let disconnect = json!({
+            "seq": 2,
+            "type": "response",
+            "request_seq": 2,
+            "success": true,
+            "command": "disconnect",
+        });
+        assert_eq!(messages, [terminated, disconnect]);
+    }
+}
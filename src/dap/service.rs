@@ -0,0 +1,271 @@
+//! Service abstraction for debug adapters.
+
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc::{self, Receiver, Sender};
+use futures::future::{BoxFuture, FutureExt};
+use futures::{SinkExt, Stream};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower::Service;
+use tracing::error;
+
+use super::generated::register_dap_commands;
+use super::types::{Event, OutputEventBody, Request, Response, StoppedEventBody};
+use super::DebugAdapter;
+use crate::jsonrpc::{self, Router};
+
+/// Debug Adapter Protocol service.
+///
+/// Requests are dispatched to the methods of the [`DebugAdapter`] through the same
+/// [`Router`](crate::jsonrpc::Router) which serves LSP methods. Failed requests are answered with
+/// the message of the [`jsonrpc::Error`] returned by the handler, and its code as the ID of the
+/// error in the response body.
+pub struct DebugAdapterService<A> {
+    inner: Router<A>,
+}
+
+impl<A: DebugAdapter> DebugAdapterService<A> {
+    /// Creates a new `DebugAdapterService` with the given debug adapter backend, also returning a
+    /// channel for events sent by the adapter.
+    pub fn new<F>(init: F) -> (Self, DebugClientSocket)
+    where
+        F: FnOnce(DebugClient) -> A,
+    {
+        let (tx, rx) = mpsc::channel(1);
+        let mut inner = Router::new(init(DebugClient { tx }));
+        register_dap_commands(&mut inner);
+
+        (DebugAdapterService { inner }, DebugClientSocket { rx })
+    }
+
+    /// Returns a shared reference to the inner debug adapter.
+    pub fn inner(&self) -> Arc<A> {
+        self.inner.inner()
+    }
+}
+
+impl<A: DebugAdapter> Service<Request> for DebugAdapterService<A> {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let Request {
+            seq,
+            command,
+            arguments,
+        } = req;
+
+        // Commands without arguments are registered as taking an ignored `Value`, so missing
+        // arguments are passed as an empty object, as for commands with optional arguments.
+        let arguments = arguments.unwrap_or_else(|| json!({}));
+        let rpc = jsonrpc::Request::build(command.clone())
+            .id(seq)
+            .params(arguments)
+            .finish();
+
+        self.inner
+            .call(rpc)
+            .map(move |res| {
+                let result = match res {
+                    Ok(Some(res)) => res.into_parts().1,
+                    Ok(None) => Err(jsonrpc::Error::internal_error()),
+                    Err(never) => match never {},
+                };
+
+                Ok(into_response(seq, command, result))
+            })
+            .boxed()
+    }
+}
+
+impl<A: Debug> Debug for DebugAdapterService<A> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("DebugAdapterService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Converts the outcome of the request `request_seq` into a DAP response.
+pub(super) fn into_response(
+    request_seq: i64,
+    command: String,
+    result: jsonrpc::Result<Value>,
+) -> Response {
+    let (success, message, body) = match result {
+        Ok(Value::Null) => (true, None, None),
+        Ok(body) => (true, None, Some(body)),
+        Err(err) => {
+            let body = json!({ "error": { "id": err.code.code(), "format": err.message } });
+            (false, Some(err.message.into_owned()), Some(body))
+        }
+    };
+
+    Response {
+        seq: 0,
+        request_seq,
+        success,
+        command,
+        message,
+        body,
+    }
+}
+
+/// Handle for sending events to the client of a debug adapter.
+#[derive(Clone, Debug)]
+pub struct DebugClient {
+    tx: Sender<Event>,
+}
+
+impl DebugClient {
+    /// Notifies the client that the adapter is ready to accept configuration requests, such as
+    /// `setBreakpoints`, ended by `configurationDone`.
+    pub async fn initialized(&self) {
+        self.send_event("initialized", None::<()>).await;
+    }
+
+    /// Notifies the client that execution stopped.
+    pub async fn stopped(&self, body: StoppedEventBody) {
+        self.send_event("stopped", Some(body)).await;
+    }
+
+    /// Sends output of the debuggee or of the adapter to the client.
+    pub async fn output(&self, body: OutputEventBody) {
+        self.send_event("output", Some(body)).await;
+    }
+
+    /// Notifies the client that the debuggee exited with `exit_code`.
+    pub async fn exited(&self, exit_code: i64) {
+        let body = json!({ "exitCode": exit_code });
+        self.send_event("exited", Some(body)).await;
+    }
+
+    /// Notifies the client that debugging ended.
+    pub async fn terminated(&self) {
+        self.send_event("terminated", None::<()>).await;
+    }
+
+    /// Sends an arbitrary event to the client.
+    ///
+    /// Events are dropped once the [`Server`](super::Server) has stopped.
+    pub async fn send_event<B: Serialize>(&self, event: &str, body: Option<B>) {
+        let body = match body.map(serde_json::to_value).transpose() {
+            Ok(body) => body,
+            Err(err) => {
+                error!("failed to serialize body of event `{}`: {}", event, err);
+                return;
+            }
+        };
+
+        let event = Event {
+            seq: 0,
+            event: event.to_owned(),
+            body,
+        };
+
+        if self.tx.clone().send(event).await.is_err() {
+            error!("failed to send event: debug adapter has stopped");
+        }
+    }
+}
+
+/// Stream of events sent by a [`DebugClient`], to be passed to [`Server::new`](super::Server::new).
+#[derive(Debug)]
+pub struct DebugClientSocket {
+    rx: Receiver<Event>,
+}
+
+impl DebugClientSocket {
+    /// Stops accepting new events, while still yielding those already sent.
+    pub(super) fn close(&mut self) {
+        self.rx.close();
+    }
+}
+
+impl Stream for DebugClientSocket {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tower::ServiceExt;
+
+    use super::super::types::{Capabilities, InitializeRequestArguments, ThreadsResponse};
+    use super::*;
+    use crate::jsonrpc::Result;
+
+    struct Mock {
+        client: DebugClient,
+    }
+
+    #[crate::async_trait]
+    impl DebugAdapter for Mock {
+        async fn initialize(&self, _: InitializeRequestArguments) -> Result<Capabilities> {
+            self.client.initialized().await;
+            Ok(Capabilities {
+                supports_configuration_done_request: Some(true),
+                ..Capabilities::default()
+            })
+        }
+
+        async fn threads(&self) -> Result<ThreadsResponse> {
+            Ok(ThreadsResponse::default())
+        }
+    }
+
+    fn request(seq: i64, command: &str, arguments: Option<Value>) -> Request {
+        Request {
+            seq,
+            command: command.into(),
+            arguments,
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn dispatches_commands() {
+        let (mut service, mut socket) = DebugAdapterService::new(|client| Mock { client });
+
+        let args = json!({"adapterID": "mock"});
+        let res = service.ready().await.unwrap();
+        let res = res
+            .call(request(1, "initialize", Some(args)))
+            .await
+            .unwrap();
+        assert!(res.success);
+        assert_eq!(res.request_seq, 1);
+        assert_eq!(
+            res.body,
+            Some(json!({"supportsConfigurationDoneRequest": true}))
+        );
+        assert_eq!(socket.next().await.unwrap().event, "initialized");
+
+        let res = service.ready().await.unwrap();
+        let res = res.call(request(2, "threads", None)).await.unwrap();
+        assert_eq!(res.body, Some(json!({"threads": []})));
+
+        let res = service.ready().await.unwrap();
+        let res = res.call(request(3, "pause", Some(json!({"threadId": 1}))));
+        let res = res.await.unwrap();
+        assert!(!res.success);
+        assert_eq!(res.message.as_deref(), Some("Method not found"));
+        assert_eq!(
+            res.body,
+            Some(json!({"error": {"id": -32601, "format": "Method not found"}}))
+        );
+    }
+}
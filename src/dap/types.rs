@@ -0,0 +1,499 @@
+//! Messages of the Debug Adapter Protocol, and the arguments and bodies of its core commands.
+//!
+//! Only the commonly used subset of the protocol is covered. Fields are named after the
+//! [specification], converted to `snake_case`.
+//!
+//! [specification]: https://microsoft.github.io/debug-adapter-protocol/specification
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A message sent between the client and the debug adapter.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Message {
+    /// A request.
+    Request(Request),
+    /// A response to a request.
+    Response(Response),
+    /// An event sent by the debug adapter.
+    Event(Event),
+}
+
+/// A request sent by the client.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Request {
+    /// Sequence number of the message.
+    pub seq: i64,
+    /// The command to execute.
+    pub command: String,
+    /// Arguments of the command, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+}
+
+/// A response to a [`Request`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Response {
+    /// Sequence number of the message, set by the [`Server`](super::Server) when sending it.
+    pub seq: i64,
+    /// Sequence number of the request being answered.
+    pub request_seq: i64,
+    /// Whether the request was successful.
+    pub success: bool,
+    /// The command of the request being answered.
+    pub command: String,
+    /// The error message, if the request failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The result of the request, or details about the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+/// An event sent by the debug adapter.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Event {
+    /// Sequence number of the message, set by the [`Server`](super::Server) when sending it.
+    pub seq: i64,
+    /// Type of the event.
+    pub event: String,
+    /// Event-specific information, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+/// Arguments of the `initialize` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeRequestArguments {
+    /// The ID of the client.
+    #[serde(rename = "clientID", skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// The human-readable name of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// The ID of the debug adapter.
+    #[serde(rename = "adapterID")]
+    pub adapter_id: String,
+    /// The ISO-639 locale of the client, e.g. `en-US`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Whether line numbers are 1-based, which is the default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines_start_at1: Option<bool>,
+    /// Whether column numbers are 1-based, which is the default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns_start_at1: Option<bool>,
+    /// The format of paths, `path` or `uri`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_format: Option<String>,
+    /// Whether the client displays the type of variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_variable_type: Option<bool>,
+    /// Whether the client supports the `runInTerminal` request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_run_in_terminal_request: Option<bool>,
+}
+
+/// Capabilities of a debug adapter, returned by the `initialize` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// The adapter supports the `configurationDone` request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_configuration_done_request: Option<bool>,
+    /// The adapter supports function breakpoints.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_function_breakpoints: Option<bool>,
+    /// The adapter supports conditional breakpoints.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_conditional_breakpoints: Option<bool>,
+    /// The adapter supports breakpoints that break after a number of hits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_hit_conditional_breakpoints: Option<bool>,
+    /// The adapter supports the `evaluate` request for data hovers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_evaluate_for_hovers: Option<bool>,
+    /// The adapter supports logpoints.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_log_points: Option<bool>,
+    /// The adapter supports the `terminate` request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_terminate_request: Option<bool>,
+    /// The adapter supports the `terminateDebuggee` argument of the `disconnect` request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub support_terminate_debuggee: Option<bool>,
+}
+
+/// Arguments of the `launch` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchRequestArguments {
+    /// Launch the program without enabling debugging.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_debug: Option<bool>,
+    /// Adapter-specific launch configuration.
+    #[serde(flatten)]
+    pub configuration: Map<String, Value>,
+}
+
+/// Arguments of the `attach` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AttachRequestArguments {
+    /// Adapter-specific attach configuration.
+    #[serde(flatten)]
+    pub configuration: Map<String, Value>,
+}
+
+/// Arguments of the `disconnect` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisconnectArguments {
+    /// The request is part of a restart sequence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<bool>,
+    /// Whether the debuggee should be terminated when disconnecting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminate_debuggee: Option<bool>,
+}
+
+/// Arguments of the `terminate` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TerminateArguments {
+    /// The request is part of a restart sequence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<bool>,
+}
+
+/// A source file, or a source provided by the debug adapter.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    /// The short name of the source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The path of the source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// A reference for retrieving the content of the source with the `source` request, if it has
+    /// no path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_reference: Option<i64>,
+}
+
+/// A breakpoint requested by the client.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceBreakpoint {
+    /// The line of the breakpoint.
+    pub line: i64,
+    /// The column of the breakpoint, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<i64>,
+    /// An expression which must evaluate to `true` for the breakpoint to be hit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// An expression controlling how many hits of the breakpoint are ignored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_condition: Option<String>,
+    /// A message logged instead of breaking, turning the breakpoint into a logpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_message: Option<String>,
+}
+
+/// Arguments of the `setBreakpoints` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetBreakpointsArguments {
+    /// The source of the breakpoints.
+    pub source: Source,
+    /// All breakpoints of the source, replacing the previous ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakpoints: Option<Vec<SourceBreakpoint>>,
+    /// Whether the source was modified since it was last loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_modified: Option<bool>,
+}
+
+/// A breakpoint as set by the debug adapter.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Breakpoint {
+    /// The ID of the breakpoint, used in events updating it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    /// Whether the breakpoint could be set.
+    pub verified: bool,
+    /// Why the breakpoint could not be set, if it is not verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The source of the breakpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    /// The actual line of the breakpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+    /// The actual column of the breakpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<i64>,
+}
+
+/// Body of the response to the `setBreakpoints` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct SetBreakpointsResponse {
+    /// The breakpoints, in the order of the requested ones.
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+/// A thread of the debuggee.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Thread {
+    /// The ID of the thread.
+    pub id: i64,
+    /// The name of the thread.
+    pub name: String,
+}
+
+/// Body of the response to the `threads` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ThreadsResponse {
+    /// All threads of the debuggee.
+    pub threads: Vec<Thread>,
+}
+
+/// Arguments of the `stackTrace` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackTraceArguments {
+    /// The thread whose stack trace is requested.
+    pub thread_id: i64,
+    /// The index of the first frame to return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_frame: Option<i64>,
+    /// The maximum number of frames to return, or all of them if absent or 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levels: Option<i64>,
+}
+
+/// A frame of a stack trace.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct StackFrame {
+    /// The ID of the frame, used in `scopes` and `evaluate` requests.
+    pub id: i64,
+    /// The name of the frame, typically a function name.
+    pub name: String,
+    /// The source of the frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    /// The line within the source of the frame.
+    pub line: i64,
+    /// The column within the line of the frame.
+    pub column: i64,
+}
+
+/// Body of the response to the `stackTrace` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackTraceResponse {
+    /// The requested frames.
+    pub stack_frames: Vec<StackFrame>,
+    /// The total number of frames available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_frames: Option<i64>,
+}
+
+/// Arguments of the `scopes` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopesArguments {
+    /// The frame whose scopes are requested.
+    pub frame_id: i64,
+}
+
+/// A named container of variables, such as the locals of a frame.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scope {
+    /// The name of the scope.
+    pub name: String,
+    /// A reference for retrieving the variables of the scope with the `variables` request.
+    pub variables_reference: i64,
+    /// Whether retrieving the variables of the scope is expensive.
+    pub expensive: bool,
+}
+
+/// Body of the response to the `scopes` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ScopesResponse {
+    /// The scopes of the frame.
+    pub scopes: Vec<Scope>,
+}
+
+/// Arguments of the `variables` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariablesArguments {
+    /// The reference of the variables to retrieve.
+    pub variables_reference: i64,
+    /// The index of the first variable to return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    /// The number of variables to return, or all of them if absent or 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+}
+
+/// A variable and its value.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Variable {
+    /// The name of the variable.
+    pub name: String,
+    /// The value of the variable, formatted for display.
+    pub value: String,
+    /// The type of the variable.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    /// A reference for retrieving the children of the variable, or 0 if it has none.
+    pub variables_reference: i64,
+}
+
+/// Body of the response to the `variables` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct VariablesResponse {
+    /// The requested variables.
+    pub variables: Vec<Variable>,
+}
+
+/// Arguments of the `continue` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContinueArguments {
+    /// The thread to continue.
+    pub thread_id: i64,
+    /// Only continue this thread, rather than all of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_thread: Option<bool>,
+}
+
+/// Body of the response to the `continue` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContinueResponse {
+    /// Whether all threads were continued, which is assumed if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_threads_continued: Option<bool>,
+}
+
+/// Arguments of the `next`, `stepIn` and `stepOut` requests.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepArguments {
+    /// The thread to step.
+    pub thread_id: i64,
+    /// Only resume this thread, rather than all of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_thread: Option<bool>,
+}
+
+/// Arguments of the `pause` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseArguments {
+    /// The thread to pause.
+    pub thread_id: i64,
+}
+
+/// Arguments of the `evaluate` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateArguments {
+    /// The expression to evaluate.
+    pub expression: String,
+    /// The frame in whose scope the expression is evaluated, or the global scope if absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_id: Option<i64>,
+    /// Where the expression comes from, e.g. `watch`, `repl` or `hover`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// Body of the response to the `evaluate` request.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateResponse {
+    /// The result of the evaluation, formatted for display.
+    pub result: String,
+    /// The type of the result.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    /// A reference for retrieving the children of the result, or 0 if it has none.
+    pub variables_reference: i64,
+}
+
+/// Body of the `stopped` event.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoppedEventBody {
+    /// Why execution stopped, e.g. `step`, `breakpoint` or `exception`.
+    pub reason: String,
+    /// The full reason for the stop, displayed in the user interface.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The thread which stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<i64>,
+    /// Whether all threads stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_threads_stopped: Option<bool>,
+}
+
+/// Body of the `output` event.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct OutputEventBody {
+    /// The category of the output, e.g. `console`, `stdout` or `stderr`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The output to report.
+    pub output: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serializes_messages() {
+        let request = json!({"seq": 1, "type": "request", "command": "threads"});
+        let message: Message = serde_json::from_value(request).unwrap();
+        let expected = Request {
+            seq: 1,
+            command: "threads".into(),
+            arguments: None,
+        };
+        assert_eq!(message, Message::Request(expected));
+
+        let response = Message::Response(Response {
+            seq: 2,
+            request_seq: 1,
+            success: true,
+            command: "threads".into(),
+            message: None,
+            body: Some(json!({"threads": []})),
+        });
+        assert_eq!(
+            serde_json::to_value(response).unwrap(),
+            json!({
+                "seq": 2,
+                "type": "response",
+                "request_seq": 1,
+                "success": true,
+                "command": "threads",
+                "body": {"threads": []},
+            })
+        );
+    }
+}
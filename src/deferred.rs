@@ -0,0 +1,292 @@
+//! Construction of backends from the `initialize` request.
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures::future::{BoxFuture, FutureExt};
+use lsp_types::request::{
+    GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
+    GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
+};
+use lsp_types::*;
+use serde_json::Value;
+
+use crate::jsonrpc::{self, Result};
+use crate::{Client, LanguageServer};
+
+type Factory<S> = dyn Fn(Client, InitializeParams) -> BoxFuture<'static, Result<S>> + Send + Sync;
+
+/// A [`LanguageServer`] whose backend is constructed when the `initialize` request is received.
+///
+/// Many backends need data from [`InitializeParams`], such as the root URI or the client
+/// capabilities, and end up storing it in `Option` or `OnceCell` fields set by their `initialize`
+/// handler. `Deferred` instead calls the given `factory` with the `initialize` params to construct
+/// the backend, which can then store this data in plain fields. The backend's own
+/// [`initialize`](LanguageServer::initialize) handler is called right afterwards with the same
+/// params, and every later message is forwarded to the backend.
+///
+/// If the factory fails, its error is sent to the client in response to `initialize`, and the
+/// client may try initializing again.
+///
+/// # Examples
+///
+/// ```rust
+/// use tower_lsp::jsonrpc::{Error, Result};
+/// use tower_lsp::lsp_types::*;
+/// use tower_lsp::{Client, Deferred, LanguageServer, LspService};
+///
+/// struct Backend {
+///     client: Client,
+///     root: Url,
+///     capabilities: ClientCapabilities,
+/// }
+///
+/// #[tower_lsp::async_trait]
+/// impl LanguageServer for Backend {
+///     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+///         Ok(InitializeResult::default())
+///     }
+///
+///     async fn shutdown(&self) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let (service, socket) = LspService::new(|client| {
+///     Deferred::new(client, |client, params| async move {
+///         let root = params
+///             .root_uri
+///             .ok_or_else(|| Error::invalid_params("a workspace root is required"))?;
+///
+///         Ok(Backend {
+///             client,
+///             root,
+///             capabilities: params.capabilities,
+///         })
+///     })
+/// });
+/// ```
+pub struct Deferred<S> {
+    client: Client,
+    factory: Box<Factory<S>>,
+    backend: RwLock<Option<Arc<S>>>,
+}
+
+impl<S: LanguageServer> Deferred<S> {
+    /// Creates a new `Deferred` which constructs its backend with `factory`.
+    pub fn new<F, Fut>(client: Client, factory: F) -> Self
+    where
+        F: Fn(Client, InitializeParams) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<S>> + Send + 'static,
+    {
+        Deferred {
+            client,
+            factory: Box::new(move |client, params| factory(client, params).boxed()),
+            backend: RwLock::new(None),
+        }
+    }
+
+    /// Returns the backend, or `None` if it was not constructed yet.
+    ///
+    /// This is useful in handlers registered with
+    /// [`LspServiceBuilder::custom_method`](crate::LspServiceBuilder::custom_method), which are
+    /// called with the `Deferred` rather than with the backend.
+    pub fn backend(&self) -> Option<Arc<S>> {
+        self.backend.read().unwrap().clone()
+    }
+}
+
+impl<S> Debug for Deferred<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let constructed = self.backend.read().unwrap().is_some();
+        f.debug_struct(stringify!(Deferred))
+            .field("client", &self.client)
+            .field("constructed", &constructed)
+            .finish_non_exhaustive()
+    }
+}
+
+macro_rules! delegate {
+    (
+        impl { $($manual:tt)* }
+        requests { $($req:ident($req_params:ty) -> $res:ty;)* }
+        notifications { $($notif:ident($notif_params:ty);)* }
+    ) => {
+        #[async_trait]
+        impl<S: LanguageServer> LanguageServer for Deferred<S> {
+            $($manual)*
+
+            $(
+                async fn $req(&self, params: $req_params) -> Result<$res> {
+                    match self.backend() {
+                        Some(backend) => backend.$req(params).await,
+                        None => Err(jsonrpc::not_initialized_error()),
+                    }
+                }
+            )*
+
+            $(
+                async fn $notif(&self, params: $notif_params) {
+                    if let Some(backend) = self.backend() {
+                        backend.$notif(params).await;
+                    }
+                }
+            )*
+        }
+    };
+}
+
+delegate! {
+    impl {
+        async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+            let backend = (self.factory)(self.client.clone(), params.clone()).await?;
+            let result = backend.initialize(params).await?;
+            *self.backend.write().unwrap() = Some(Arc::new(backend));
+            Ok(result)
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            match self.backend() {
+                Some(backend) => backend.shutdown().await,
+                None => Ok(()),
+            }
+        }
+    }
+
+    requests {
+        will_save_wait_until(WillSaveTextDocumentParams) -> Option<Vec<TextEdit>>;
+        goto_declaration(GotoDeclarationParams) -> Option<GotoDeclarationResponse>;
+        goto_definition(GotoDefinitionParams) -> Option<GotoDefinitionResponse>;
+        goto_type_definition(GotoTypeDefinitionParams) -> Option<GotoTypeDefinitionResponse>;
+        goto_implementation(GotoImplementationParams) -> Option<GotoImplementationResponse>;
+        references(ReferenceParams) -> Option<Vec<Location>>;
+        prepare_call_hierarchy(CallHierarchyPrepareParams) -> Option<Vec<CallHierarchyItem>>;
+        incoming_calls(CallHierarchyIncomingCallsParams) -> Option<Vec<CallHierarchyIncomingCall>>;
+        outgoing_calls(CallHierarchyOutgoingCallsParams) -> Option<Vec<CallHierarchyOutgoingCall>>;
+        prepare_type_hierarchy(TypeHierarchyPrepareParams) -> Option<Vec<TypeHierarchyItem>>;
+        supertypes(TypeHierarchySupertypesParams) -> Option<Vec<TypeHierarchyItem>>;
+        subtypes(TypeHierarchySubtypesParams) -> Option<Vec<TypeHierarchyItem>>;
+        document_highlight(DocumentHighlightParams) -> Option<Vec<DocumentHighlight>>;
+        document_link(DocumentLinkParams) -> Option<Vec<DocumentLink>>;
+        document_link_resolve(DocumentLink) -> DocumentLink;
+        hover(HoverParams) -> Option<Hover>;
+        code_lens(CodeLensParams) -> Option<Vec<CodeLens>>;
+        code_lens_resolve(CodeLens) -> CodeLens;
+        folding_range(FoldingRangeParams) -> Option<Vec<FoldingRange>>;
+        selection_range(SelectionRangeParams) -> Option<Vec<SelectionRange>>;
+        document_symbol(DocumentSymbolParams) -> Option<DocumentSymbolResponse>;
+        semantic_tokens_full(SemanticTokensParams) -> Option<SemanticTokensResult>;
+        semantic_tokens_full_delta(SemanticTokensDeltaParams) -> Option<SemanticTokensFullDeltaResult>;
+        semantic_tokens_range(SemanticTokensRangeParams) -> Option<SemanticTokensRangeResult>;
+        inline_value(InlineValueParams) -> Option<Vec<InlineValue>>;
+        inlay_hint(InlayHintParams) -> Option<Vec<InlayHint>>;
+        inlay_hint_resolve(InlayHint) -> InlayHint;
+        moniker(MonikerParams) -> Option<Vec<Moniker>>;
+        completion(CompletionParams) -> Option<CompletionResponse>;
+        completion_resolve(CompletionItem) -> CompletionItem;
+        diagnostic(DocumentDiagnosticParams) -> DocumentDiagnosticReportResult;
+        workspace_diagnostic(WorkspaceDiagnosticParams) -> WorkspaceDiagnosticReportResult;
+        signature_help(SignatureHelpParams) -> Option<SignatureHelp>;
+        code_action(CodeActionParams) -> Option<CodeActionResponse>;
+        code_action_resolve(CodeAction) -> CodeAction;
+        document_color(DocumentColorParams) -> Vec<ColorInformation>;
+        color_presentation(ColorPresentationParams) -> Vec<ColorPresentation>;
+        formatting(DocumentFormattingParams) -> Option<Vec<TextEdit>>;
+        range_formatting(DocumentRangeFormattingParams) -> Option<Vec<TextEdit>>;
+        on_type_formatting(DocumentOnTypeFormattingParams) -> Option<Vec<TextEdit>>;
+        rename(RenameParams) -> Option<WorkspaceEdit>;
+        prepare_rename(TextDocumentPositionParams) -> Option<PrepareRenameResponse>;
+        linked_editing_range(LinkedEditingRangeParams) -> Option<LinkedEditingRanges>;
+        symbol(WorkspaceSymbolParams) -> Option<Vec<SymbolInformation>>;
+        symbol_resolve(WorkspaceSymbol) -> WorkspaceSymbol;
+        will_create_files(CreateFilesParams) -> Option<WorkspaceEdit>;
+        will_rename_files(RenameFilesParams) -> Option<WorkspaceEdit>;
+        will_delete_files(DeleteFilesParams) -> Option<WorkspaceEdit>;
+        execute_command(ExecuteCommandParams) -> Option<Value>;
+    }
+    notifications {
+        initialized(InitializedParams);
+        did_open(DidOpenTextDocumentParams);
+        did_change(DidChangeTextDocumentParams);
+        will_save(WillSaveTextDocumentParams);
+        did_save(DidSaveTextDocumentParams);
+        did_close(DidCloseTextDocumentParams);
+        did_change_configuration(DidChangeConfigurationParams);
+        did_change_workspace_folders(DidChangeWorkspaceFoldersParams);
+        did_create_files(CreateFilesParams);
+        did_rename_files(RenameFilesParams);
+        did_delete_files(DeleteFilesParams);
+        did_change_watched_files(DidChangeWatchedFilesParams);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::Error;
+    use crate::LspService;
+
+    struct Mock {
+        root: Url,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+            assert_eq!(params.root_uri.as_ref(), Some(&self.root));
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(self.root.to_string())),
+                range: None,
+            }))
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn constructs_backend_on_initialize() {
+        let (service, _) = LspService::new(|client| {
+            Deferred::new(client, |_, params| async move {
+                let root = params
+                    .root_uri
+                    .ok_or_else(|| Error::invalid_params("no root"))?;
+                Ok(Mock { root })
+            })
+        });
+        let server = service.inner();
+
+        let hover = || HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(Url::parse("file:///a/main.rs").unwrap()),
+                Position::default(),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+
+        let err = server.hover(hover()).await.unwrap_err();
+        assert_eq!(err, jsonrpc::not_initialized_error());
+
+        let err = server.initialize(InitializeParams::default()).await;
+        assert_eq!(err.unwrap_err(), Error::invalid_params("no root"));
+        assert!(server.backend().is_none());
+
+        let params = InitializeParams {
+            root_uri: Some(Url::parse("file:///a/").unwrap()),
+            ..Default::default()
+        };
+        server.initialize(params).await.unwrap();
+        assert!(server.backend().is_some());
+
+        let contents = server.hover(hover()).await.unwrap().unwrap().contents;
+        let expected = HoverContents::Scalar(MarkedString::String("file:///a/".into()));
+        assert_eq!(contents, expected);
+    }
+}
@@ -0,0 +1,141 @@
+//! Typed access to the `experimental` section of client and server capabilities.
+//!
+//! Protocol experiments are commonly negotiated through the free-form `experimental` field of
+//! [`ClientCapabilities`] and [`ServerCapabilities`], with each side advertising the experimental
+//! methods it supports, e.g. `{ "serverStatusNotification": true }`. The [`Experimental`] trait
+//! reads and writes this section as a user-defined serde type instead of a raw JSON [`Value`].
+//!
+//! Custom methods declared with [`LspExtension`](crate::LspExtension) can also be tied to an
+//! experimental capability, see its `experimental` attribute.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use tower_lsp::experimental::Experimental;
+//! use tower_lsp::lsp_types::ServerCapabilities;
+//!
+//! #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+//! #[serde(rename_all = "camelCase")]
+//! struct MyCaps {
+//!     server_status_notification: bool,
+//! }
+//!
+//! let mut capabilities = ServerCapabilities::default();
+//! capabilities
+//!     .set_experimental(&MyCaps { server_status_notification: true })
+//!     .unwrap();
+//!
+//! let caps: Option<MyCaps> = capabilities.experimental_as();
+//! assert_eq!(caps, Some(MyCaps { server_status_notification: true }));
+//! ```
+
+use lsp_types::{ClientCapabilities, ServerCapabilities};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Capabilities carrying an `experimental` section.
+///
+/// This trait is sealed and implemented for [`ClientCapabilities`] and [`ServerCapabilities`].
+pub trait Experimental: sealed::Sealed {
+    /// Returns the raw `experimental` section, if any.
+    fn experimental(&self) -> Option<&Value>;
+
+    /// Replaces the `experimental` section with `value`.
+    fn set_experimental_value(&mut self, value: Option<Value>);
+
+    /// Deserializes the `experimental` section into `T`.
+    ///
+    /// Returns `None` if the section is missing or cannot be deserialized into `T`, in which case
+    /// a warning is logged.
+    fn experimental_as<T: DeserializeOwned>(&self) -> Option<T> {
+        let value = self.experimental()?.clone();
+        match serde_json::from_value(value) {
+            Ok(caps) => Some(caps),
+            Err(err) => {
+                warn!("ignoring invalid experimental capabilities: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Serializes `caps` into the `experimental` section, replacing any previous value.
+    fn set_experimental<T: Serialize>(&mut self, caps: &T) -> serde_json::Result<()> {
+        let value = serde_json::to_value(caps)?;
+        self.set_experimental_value(Some(value));
+        Ok(())
+    }
+
+    /// Returns `true` if the `experimental` section contains the key `name`, set to anything but
+    /// `null` or `false`.
+    fn has_experimental(&self, name: &str) -> bool {
+        let value = self.experimental().and_then(|caps| caps.get(name));
+        !matches!(value, None | Some(Value::Null) | Some(Value::Bool(false)))
+    }
+}
+
+macro_rules! impl_experimental {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl Experimental for $ty {
+                fn experimental(&self) -> Option<&Value> {
+                    self.experimental.as_ref()
+                }
+
+                fn set_experimental_value(&mut self, value: Option<Value>) {
+                    self.experimental = value;
+                }
+            }
+        )*
+    };
+}
+
+impl_experimental!(ClientCapabilities, ServerCapabilities);
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Caps {
+        status_notification: bool,
+    }
+
+    #[test]
+    fn reads_typed_capabilities() {
+        let caps = ClientCapabilities {
+            experimental: Some(json!({"statusNotification": true, "colorDecorators": null})),
+            ..ClientCapabilities::default()
+        };
+
+        let expected = Caps {
+            status_notification: true,
+        };
+        assert_eq!(caps.experimental_as::<Caps>(), Some(expected));
+        assert!(caps.has_experimental("statusNotification"));
+        assert!(!caps.has_experimental("colorDecorators"));
+        assert!(!caps.has_experimental("missing"));
+
+        let invalid = ClientCapabilities {
+            experimental: Some(json!({"statusNotification": "yes"})),
+            ..ClientCapabilities::default()
+        };
+        assert_eq!(invalid.experimental_as::<Caps>(), None);
+        assert_eq!(
+            ClientCapabilities::default().experimental_as::<Caps>(),
+            None
+        );
+    }
+}
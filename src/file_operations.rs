@@ -0,0 +1,319 @@
+//! Registration options for the `workspace/*Files` file operation events.
+//!
+//! Servers interested in files being created, renamed or deleted by the client, e.g. to update
+//! imports when a module is renamed, must advertise which files they care about through a list of
+//! [`FileOperationFilter`]s. The [`FileOperationFilters`] builder assembles these filters from
+//! plain glob patterns, checking that each pattern is valid beforehand.
+//!
+//! The resulting options can either be advertised statically, through
+//! [`FileOperationFilters::capabilities`], or registered dynamically with
+//! [`Client::register_file_operations`](crate::Client::register_file_operations).
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::file_operations::{FileOperation, FileOperationFilters};
+//!
+//! let filters = FileOperationFilters::new()
+//!     .files("**/*.rs")
+//!     .folders("**/src")
+//!     .scheme("file");
+//!
+//! let capabilities = filters
+//!     .capabilities([FileOperation::WillRename, FileOperation::DidRename])
+//!     .unwrap();
+//!
+//! let options = capabilities.will_rename.unwrap();
+//! assert_eq!(options.filters.len(), 2);
+//! assert_eq!(options.filters[0].scheme.as_deref(), Some("file"));
+//! assert_eq!(options.filters[0].pattern.glob, "**/*.rs");
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::notification::{DidCreateFiles, DidDeleteFiles, DidRenameFiles, Notification};
+use lsp_types::request::{Request, WillCreateFiles, WillDeleteFiles, WillRenameFiles};
+use lsp_types::{
+    FileOperationFilter, FileOperationPattern, FileOperationPatternKind,
+    FileOperationPatternOptions, FileOperationRegistrationOptions,
+    WorkspaceFileOperationsServerCapabilities,
+};
+
+use crate::glob::{GlobError, GlobMatcher};
+
+/// A file operation event which can be subscribed to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FileOperation {
+    /// The `workspace/willCreateFiles` request.
+    WillCreate,
+    /// The `workspace/didCreateFiles` notification.
+    DidCreate,
+    /// The `workspace/willRenameFiles` request.
+    WillRename,
+    /// The `workspace/didRenameFiles` notification.
+    DidRename,
+    /// The `workspace/willDeleteFiles` request.
+    WillDelete,
+    /// The `workspace/didDeleteFiles` notification.
+    DidDelete,
+}
+
+impl FileOperation {
+    /// All file operation events, in the order they are declared.
+    pub const ALL: [FileOperation; 6] = [
+        FileOperation::WillCreate,
+        FileOperation::DidCreate,
+        FileOperation::WillRename,
+        FileOperation::DidRename,
+        FileOperation::WillDelete,
+        FileOperation::DidDelete,
+    ];
+
+    /// Returns the JSON-RPC method name of the event, e.g. `workspace/willRenameFiles`.
+    pub fn method(self) -> &'static str {
+        match self {
+            FileOperation::WillCreate => WillCreateFiles::METHOD,
+            FileOperation::DidCreate => DidCreateFiles::METHOD,
+            FileOperation::WillRename => WillRenameFiles::METHOD,
+            FileOperation::DidRename => DidRenameFiles::METHOD,
+            FileOperation::WillDelete => WillDeleteFiles::METHOD,
+            FileOperation::DidDelete => DidDeleteFiles::METHOD,
+        }
+    }
+}
+
+impl Display for FileOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.method())
+    }
+}
+
+/// Error that occurs when building file operation filters from an invalid glob pattern.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileOperationFilterError {
+    glob: String,
+    error: GlobError,
+}
+
+impl FileOperationFilterError {
+    /// Returns the offending glob pattern.
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+
+    /// Returns the reason why the pattern is invalid.
+    pub fn error(&self) -> &GlobError {
+        &self.error
+    }
+}
+
+impl Display for FileOperationFilterError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "invalid glob pattern `{}`: {}", self.glob, self.error)
+    }
+}
+
+impl std::error::Error for FileOperationFilterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A builder for [`FileOperationRegistrationOptions`].
+///
+/// Each glob pattern added to the builder becomes one [`FileOperationFilter`]. By default, the
+/// filters apply to URIs of any scheme; calling [`scheme`](Self::scheme) one or more times
+/// restricts every filter to the given schemes instead.
+///
+/// See the [module-level documentation](self) for an example.
+#[derive(Clone, Debug, Default)]
+pub struct FileOperationFilters {
+    patterns: Vec<FileOperationPattern>,
+    schemes: Vec<String>,
+    ignore_case: bool,
+}
+
+impl FileOperationFilters {
+    /// Creates a new builder without any filters.
+    pub fn new() -> Self {
+        FileOperationFilters::default()
+    }
+
+    /// Adds a glob pattern matching both files and folders.
+    pub fn glob<G: Into<String>>(self, glob: G) -> Self {
+        self.pattern(glob.into(), None)
+    }
+
+    /// Adds a glob pattern matching files only.
+    pub fn files<G: Into<String>>(self, glob: G) -> Self {
+        self.pattern(glob.into(), Some(FileOperationPatternKind::File))
+    }
+
+    /// Adds a glob pattern matching folders only.
+    pub fn folders<G: Into<String>>(self, glob: G) -> Self {
+        self.pattern(glob.into(), Some(FileOperationPatternKind::Folder))
+    }
+
+    /// Restricts every filter to URIs with the given scheme, e.g. `file` or `untitled`.
+    ///
+    /// Calling this method several times accepts any of the given schemes.
+    pub fn scheme<S: Into<String>>(mut self, scheme: S) -> Self {
+        self.schemes.push(scheme.into());
+        self
+    }
+
+    /// Matches every glob pattern case-insensitively.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    /// Returns the registration options containing every filter.
+    ///
+    /// Returns `Err` if any of the glob patterns is invalid.
+    pub fn finish(&self) -> Result<FileOperationRegistrationOptions, FileOperationFilterError> {
+        for pattern in &self.patterns {
+            if let Err(error) = GlobMatcher::new(pattern.glob.clone()) {
+                let glob = pattern.glob.clone();
+                return Err(FileOperationFilterError { glob, error });
+            }
+        }
+
+        let schemes: Vec<_> = match self.schemes.len() {
+            0 => vec![None],
+            _ => self.schemes.iter().cloned().map(Some).collect(),
+        };
+
+        let options = self.ignore_case.then_some(FileOperationPatternOptions {
+            ignore_case: Some(true),
+        });
+
+        let filters = schemes
+            .iter()
+            .flat_map(|scheme| {
+                self.patterns.iter().map(|pattern| FileOperationFilter {
+                    scheme: scheme.clone(),
+                    pattern: FileOperationPattern {
+                        options: options.clone(),
+                        ..pattern.clone()
+                    },
+                })
+            })
+            .collect();
+
+        Ok(FileOperationRegistrationOptions { filters })
+    }
+
+    /// Returns the server capabilities advertising the given `operations` with every filter.
+    ///
+    /// The result is meant for the `workspace.file_operations` field of
+    /// [`ServerCapabilities`](lsp_types::ServerCapabilities). Returns `Err` if any of the glob
+    /// patterns is invalid.
+    pub fn capabilities<I>(
+        &self,
+        operations: I,
+    ) -> Result<WorkspaceFileOperationsServerCapabilities, FileOperationFilterError>
+    where
+        I: IntoIterator<Item = FileOperation>,
+    {
+        let options = self.finish()?;
+        let mut capabilities = WorkspaceFileOperationsServerCapabilities::default();
+        for operation in operations {
+            let field = match operation {
+                FileOperation::WillCreate => &mut capabilities.will_create,
+                FileOperation::DidCreate => &mut capabilities.did_create,
+                FileOperation::WillRename => &mut capabilities.will_rename,
+                FileOperation::DidRename => &mut capabilities.did_rename,
+                FileOperation::WillDelete => &mut capabilities.will_delete,
+                FileOperation::DidDelete => &mut capabilities.did_delete,
+            };
+            *field = Some(options.clone());
+        }
+
+        Ok(capabilities)
+    }
+
+    fn pattern(mut self, glob: String, matches: Option<FileOperationPatternKind>) -> Self {
+        self.patterns.push(FileOperationPattern {
+            glob,
+            matches,
+            options: None,
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_filters() {
+        let options = FileOperationFilters::new()
+            .glob("**/*.toml")
+            .folders("**/src")
+            .scheme("file")
+            .scheme("untitled")
+            .ignore_case()
+            .finish()
+            .unwrap();
+
+        let filters: Vec<_> = options
+            .filters
+            .iter()
+            .map(|f| {
+                (
+                    f.scheme.as_deref(),
+                    &*f.pattern.glob,
+                    f.pattern.matches.clone(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            filters,
+            [
+                (Some("file"), "**/*.toml", None),
+                (
+                    Some("file"),
+                    "**/src",
+                    Some(FileOperationPatternKind::Folder)
+                ),
+                (Some("untitled"), "**/*.toml", None),
+                (
+                    Some("untitled"),
+                    "**/src",
+                    Some(FileOperationPatternKind::Folder)
+                ),
+            ]
+        );
+
+        let ignore_case = options.filters[0].pattern.options.as_ref().unwrap();
+        assert_eq!(ignore_case.ignore_case, Some(true));
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        let error = FileOperationFilters::new()
+            .files("**/*.rs")
+            .files("**/*.{rs,toml")
+            .finish()
+            .unwrap_err();
+
+        assert_eq!(error.glob(), "**/*.{rs,toml");
+        assert_eq!(error.error(), &GlobError::UnclosedBrace);
+    }
+
+    #[test]
+    fn advertises_capabilities() {
+        let filters = FileOperationFilters::new().files("**/*.rs");
+        let capabilities = filters.capabilities([FileOperation::WillRename]).unwrap();
+
+        assert_eq!(capabilities.will_rename, Some(filters.finish().unwrap()));
+        assert_eq!(capabilities.did_rename, None);
+        assert_eq!(
+            FileOperation::WillRename.to_string(),
+            "workspace/willRenameFiles"
+        );
+    }
+}
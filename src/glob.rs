@@ -0,0 +1,484 @@
+//! Matching of file URIs against LSP glob patterns.
+//!
+//! The [glob syntax] used by the Language Server Protocol, e.g. by `workspace/didChangeWatchedFiles`
+//! registrations, differs subtly from that of common glob crates:
+//!
+//! * `*` matches zero or more characters within a single path segment.
+//! * `?` matches exactly one character within a single path segment.
+//! * `**` matches any number of path segments, including none, but only as a whole segment.
+//! * `{}` groups alternatives, which may themselves contain globs and path separators, e.g.
+//!   `**/*.{ts,js}` or `{src,test}/**`.
+//! * `[]` matches a range of characters within a single path segment, e.g. `example.[0-9]`, while
+//!   `[!...]` negates the range.
+//!
+//! Patterns are always matched against the whole, percent-decoded path of a URI, using `/` as the
+//! path separator. Plain string patterns therefore need a leading `**/` to match files in any
+//! directory, whereas relative patterns are matched against the path below their base URI.
+//!
+//! [glob syntax]: https://microsoft.github.io/language-server-protocol/specification#globPattern
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::glob::GlobMatcher;
+//! use tower_lsp::lsp_types::{DidChangeWatchedFilesParams, Url, WatchKind};
+//!
+//! let matcher = GlobMatcher::new("**/*.{rs,toml}".to_string()).unwrap();
+//!
+//! // Register the watcher with `client.register_capability()`.
+//! let watcher = matcher.watcher(WatchKind::Create | WatchKind::Change);
+//!
+//! // Later, in `did_change_watched_files`...
+//! # let params = DidChangeWatchedFilesParams { changes: Vec::new() };
+//! for event in matcher.filter(&params.changes) {
+//!     println!("{} changed", event.uri);
+//! }
+//!
+//! let uri = Url::parse("file:///project/src/main.rs").unwrap();
+//! assert!(matcher.is_match(&uri));
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::{FileEvent, FileSystemWatcher, GlobPattern, OneOf, Url, WatchKind};
+
+/// Maximum number of alternatives a pattern may expand to, guarding against patterns like
+/// `{a,b}{a,b}{a,b}...` growing exponentially.
+const MAX_ALTERNATIVES: usize = 1024;
+
+/// Error that occurs when compiling an invalid glob pattern.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum GlobError {
+    /// A `{` is never closed by a matching `}`.
+    UnclosedBrace,
+    /// A `}` appears without a preceding `{`.
+    UnopenedBrace,
+    /// A `[` is never closed by a matching `]`.
+    UnclosedBracket,
+    /// The pattern expands to more alternatives than supported.
+    TooManyAlternatives,
+}
+
+impl Display for GlobError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GlobError::UnclosedBrace => f.write_str("unclosed `{` in glob pattern"),
+            GlobError::UnopenedBrace => f.write_str("unmatched `}` in glob pattern"),
+            GlobError::UnclosedBracket => f.write_str("unclosed `[` in glob pattern"),
+            GlobError::TooManyAlternatives => write!(
+                f,
+                "glob pattern expands to more than {MAX_ALTERNATIVES} alternatives"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+/// A compiled [`GlobPattern`], matching file URIs.
+///
+/// See the [module-level documentation](self) for the supported syntax.
+#[derive(Clone, Debug)]
+pub struct GlobMatcher {
+    pattern: GlobPattern,
+    base: Option<Url>,
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl GlobMatcher {
+    /// Compiles the given glob pattern.
+    ///
+    /// Both plain string patterns and [`RelativePattern`](lsp_types::RelativePattern)s are
+    /// accepted.
+    pub fn new<P: Into<GlobPattern>>(pattern: P) -> Result<Self, GlobError> {
+        let pattern = pattern.into();
+        let (base, glob) = match &pattern {
+            GlobPattern::String(glob) => (None, glob),
+            GlobPattern::Relative(relative) => {
+                let base = match &relative.base_uri {
+                    OneOf::Left(folder) => folder.uri.clone(),
+                    OneOf::Right(uri) => uri.clone(),
+                };
+                (Some(base), &relative.pattern)
+            }
+        };
+
+        let alternatives = expand_braces(glob)?
+            .iter()
+            .map(|glob| parse(glob))
+            .collect::<Result<_, _>>()?;
+
+        Ok(GlobMatcher {
+            pattern,
+            base,
+            alternatives,
+        })
+    }
+
+    /// Returns the pattern this matcher was compiled from.
+    pub fn pattern(&self) -> &GlobPattern {
+        &self.pattern
+    }
+
+    /// Returns whether `uri` matches the pattern.
+    pub fn is_match(&self, uri: &Url) -> bool {
+        let path = decode(uri.path());
+        let path = match &self.base {
+            None => &path[..],
+            Some(base) => {
+                let same_origin = uri.scheme() == base.scheme()
+                    && uri.host_str() == base.host_str()
+                    && uri.port() == base.port();
+                if !same_origin {
+                    return false;
+                }
+
+                let base = decode(base.path());
+                let base = base.trim_end_matches('/');
+                match path.strip_prefix(base).and_then(|p| p.strip_prefix('/')) {
+                    Some(relative) => relative,
+                    None => return false,
+                }
+            }
+        };
+
+        self.alternatives.iter().any(|tokens| matches(tokens, path))
+    }
+
+    /// Returns an iterator over the file events in `changes` whose URI matches the pattern.
+    ///
+    /// This is meant for filtering the [`DidChangeWatchedFilesParams`] received by
+    /// [`LanguageServer::did_change_watched_files`](crate::LanguageServer::did_change_watched_files).
+    ///
+    /// [`DidChangeWatchedFilesParams`]: lsp_types::DidChangeWatchedFilesParams
+    pub fn filter<'a>(&'a self, changes: &'a [FileEvent]) -> impl Iterator<Item = &'a FileEvent> {
+        changes
+            .iter()
+            .filter(move |event| self.is_match(&event.uri))
+    }
+
+    /// Returns a file system watcher for the pattern, to be included in the registration options
+    /// of `workspace/didChangeWatchedFiles`.
+    pub fn watcher(&self, kind: WatchKind) -> FileSystemWatcher {
+        FileSystemWatcher {
+            glob_pattern: self.pattern.clone(),
+            kind: Some(kind),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`, matching any character except `/`.
+    AnyChar,
+    /// `*`, matching any run of characters except `/`.
+    AnyChars,
+    /// `**/`, matching any run of whole path segments, including none.
+    AnySegments,
+    /// A trailing `**`, matching anything.
+    AnyPath,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// Expands every `{}` group of `glob`, returning one brace-free pattern per alternative.
+fn expand_braces(glob: &str) -> Result<Vec<String>, GlobError> {
+    let chars: Vec<char> = glob.chars().collect();
+
+    let mut open = None;
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '[' => i = class_end(&chars, i)?,
+            '{' => {
+                open = open.or(Some(i));
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(i),
+            '}' if depth == 0 => return Err(GlobError::UnopenedBrace),
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let open = match open {
+        Some(open) if depth == 0 => open,
+        Some(_) => return Err(GlobError::UnclosedBrace),
+        None => return Ok(vec![glob.to_owned()]),
+    };
+
+    let prefix: String = chars[..open].iter().collect();
+    let suffix: String = chars[i + 1..].iter().collect();
+    let starts = std::iter::once(open).chain(commas.iter().copied());
+    let ends = commas.iter().copied().chain(std::iter::once(i));
+
+    let mut expanded = Vec::new();
+    for (start, end) in starts.zip(ends) {
+        let alternative: String = chars[start + 1..end].iter().collect();
+        for glob in expand_braces(&format!("{prefix}{alternative}{suffix}"))? {
+            if expanded.len() == MAX_ALTERNATIVES {
+                return Err(GlobError::TooManyAlternatives);
+            }
+            expanded.push(glob);
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Returns the position of the `]` closing the character class opened at `start`.
+fn class_end(chars: &[char], start: usize) -> Result<usize, GlobError> {
+    let mut i = start + 1;
+    if chars.get(i) == Some(&'!') {
+        i += 1;
+    }
+
+    // A `]` right after the opening bracket is a literal.
+    if chars.get(i) == Some(&']') {
+        i += 1;
+    }
+
+    match chars[i.min(chars.len())..].iter().position(|&c| c == ']') {
+        Some(end) => Ok(i + end),
+        None => Err(GlobError::UnclosedBracket),
+    }
+}
+
+/// Parses a brace-free glob into a sequence of tokens.
+fn parse(glob: &str) -> Result<Vec<Token>, GlobError> {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let segment_start = i == 0 || chars[i - 1] == '/';
+                let mut end = i + 2;
+                while chars.get(end) == Some(&'*') {
+                    end += 1;
+                }
+
+                match chars.get(end) {
+                    Some('/') if segment_start => {
+                        tokens.push(Token::AnySegments);
+                        end += 1;
+                    }
+                    None if segment_start => tokens.push(Token::AnyPath),
+                    _ => tokens.push(Token::AnyChars),
+                }
+                i = end;
+            }
+            '*' => {
+                tokens.push(Token::AnyChars);
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                i += 1;
+            }
+            '[' => {
+                let end = class_end(&chars, i)?;
+                let mut j = i + 1;
+                let negated = chars[j] == '!';
+                if negated {
+                    j += 1;
+                }
+
+                let mut ranges = Vec::new();
+                while j < end {
+                    if chars.get(j + 1) == Some(&'-') && j + 2 < end {
+                        ranges.push((chars[j], chars[j + 2]));
+                        j += 3;
+                    } else {
+                        ranges.push((chars[j], chars[j]));
+                        j += 1;
+                    }
+                }
+
+                tokens.push(Token::Class { negated, ranges });
+                i = end + 1;
+            }
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Returns whether the whole of `path` matches `tokens`.
+fn matches(tokens: &[Token], path: &str) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return path.is_empty(),
+    };
+
+    match token {
+        Token::Char(c) => path
+            .strip_prefix(*c)
+            .map_or(false, |path| matches(rest, path)),
+        Token::AnyChar => match path.chars().next() {
+            Some(c) if c != '/' => matches(rest, &path[c.len_utf8()..]),
+            _ => false,
+        },
+        Token::AnyChars => {
+            let segment_end = path.find('/').unwrap_or(path.len());
+            path[..segment_end]
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(segment_end))
+                .any(|i| matches(rest, &path[i..]))
+        }
+        Token::AnySegments => {
+            matches(rest, path)
+                || path
+                    .match_indices('/')
+                    .any(|(i, _)| matches(rest, &path[i + 1..]))
+        }
+        Token::AnyPath => true,
+        Token::Class { negated, ranges } => match path.chars().next() {
+            Some(c) if c != '/' => {
+                let in_ranges = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+                in_ranges != *negated && matches(rest, &path[c.len_utf8()..])
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Percent-decodes a URI path, replacing invalid UTF-8 sequences.
+fn decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            let hex = std::str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16).ok()
+        });
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{FileChangeType, RelativePattern, WorkspaceFolder};
+
+    use super::*;
+
+    fn uri(path: &str) -> Url {
+        Url::parse(&format!("file://{path}")).unwrap()
+    }
+
+    fn is_match(glob: &str, path: &str) -> bool {
+        GlobMatcher::new(glob.to_owned())
+            .unwrap()
+            .is_match(&uri(path))
+    }
+
+    #[test]
+    fn matches_spec_syntax() {
+        assert!(is_match("**/*.rs", "/project/src/main.rs"));
+        assert!(is_match("**/*.rs", "/main.rs"));
+        assert!(!is_match("**/*.rs", "/project/src/main.rsx"));
+        assert!(!is_match("*.rs", "/project/main.rs"));
+
+        assert!(is_match("/project/*/lib.rs", "/project/src/lib.rs"));
+        assert!(!is_match("/project/*/lib.rs", "/project/src/nested/lib.rs"));
+        assert!(is_match("/project/**/lib.rs", "/project/lib.rs"));
+        assert!(is_match("/project/**", "/project/src/nested/lib.rs"));
+        assert!(is_match("/project/src*", "/project/src"));
+
+        assert!(is_match("**/file.?s", "/a/file.ts"));
+        assert!(!is_match("**/file.?s", "/a/file.s"));
+
+        assert!(is_match("**/example.[0-9]", "/example.3"));
+        assert!(!is_match("**/example.[0-9]", "/example.a"));
+        assert!(is_match("**/example.[!0-9]", "/example.a"));
+        assert!(!is_match("**/example.[!0-9]", "/example.3"));
+        assert!(!is_match("/a[/]b", "/a/b"));
+    }
+
+    #[test]
+    fn expands_alternatives() {
+        assert!(is_match("**/*.{ts,js}", "/src/index.js"));
+        assert!(is_match("**/*.{ts,js}", "/src/index.ts"));
+        assert!(!is_match("**/*.{ts,js}", "/src/index.rs"));
+
+        assert!(is_match("/{src,test/**}/*.rs", "/test/unit/a.rs"));
+        assert!(is_match("/{src,test/**}/*.rs", "/src/a.rs"));
+        assert!(!is_match("/{src,test/**}/*.rs", "/bench/a.rs"));
+
+        assert!(is_match("**/{Cargo.{toml,lock},[ab],}", "/x/Cargo.lock"));
+        assert!(is_match("**/{Cargo.{toml,lock},[ab],}", "/x/b"));
+        assert!(is_match("**/{Cargo.{toml,lock},[ab],}", "/x/"));
+        assert!(!is_match("**/{Cargo.{toml,lock},[ab],}", "/x/Cargo.json"));
+    }
+
+    #[test]
+    fn matches_relative_patterns() {
+        let folder = WorkspaceFolder {
+            uri: uri("/my%20project/"),
+            name: "project".into(),
+        };
+        let matcher = GlobMatcher::new(RelativePattern {
+            base_uri: OneOf::Left(folder),
+            pattern: "*.toml".into(),
+        })
+        .unwrap();
+
+        assert!(matcher.is_match(&uri("/my%20project/Cargo.toml")));
+        assert!(!matcher.is_match(&uri("/my%20project/nested/Cargo.toml")));
+        assert!(!matcher.is_match(&uri("/my%20projects/Cargo.toml")));
+        assert!(!matcher.is_match(&Url::parse("untitled:/my%20project/a.toml").unwrap()));
+
+        let changes = [
+            FileEvent::new(uri("/my%20project/Cargo.toml"), FileChangeType::CHANGED),
+            FileEvent::new(uri("/my%20project/README.md"), FileChangeType::CHANGED),
+        ];
+        let matched: Vec<_> = matcher.filter(&changes).collect();
+        assert_eq!(matched, [&changes[0]]);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let compile = |glob: &str| GlobMatcher::new(glob.to_owned()).map(|_| ());
+        assert_eq!(compile("**/*.{ts,js"), Err(GlobError::UnclosedBrace));
+        assert_eq!(compile("**/*.ts}"), Err(GlobError::UnopenedBrace));
+        assert_eq!(compile("**/[a-z"), Err(GlobError::UnclosedBracket));
+        assert_eq!(compile("[{]"), Ok(()));
+        assert_eq!(
+            compile(&"{a,b}".repeat(11)),
+            Err(GlobError::TooManyAlternatives)
+        );
+    }
+}
@@ -1,20 +1,24 @@
 //! A subset of JSON-RPC types used by the Language Server Protocol.
 
-pub(crate) use self::error::not_initialized_error;
+pub(crate) use self::error::{not_initialized_error, unsupported_by_client_error};
 pub use self::error::{Error, ErrorCode, Result};
 pub use self::request::{Request, RequestBuilder};
 pub use self::response::Response;
-pub(crate) use self::router::Router;
-pub use self::router::{FromParams, IntoResponse, Method};
+pub use self::router::{
+    FromParams, InnerMut, IntoResponse, Method, MethodFuture, MethodHandler, Router,
+};
 
 use std::borrow::Cow;
 use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
 
 use lsp_types::NumberOrString;
 use serde::de::{self, Deserializer};
 use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
 
+pub mod pending;
+
 mod error;
 mod request;
 mod response;
@@ -109,16 +113,70 @@ impl Serialize for Version {
 }
 
 /// An incoming or outgoing JSON-RPC message.
-#[derive(Deserialize, Serialize)]
-#[cfg_attr(test, derive(Debug, PartialEq))]
+///
+/// Outgoing messages can be inspected and rewritten with
+/// [`Server::map_outgoing`](crate::Server::map_outgoing).
+///
+/// Authors of custom transports, such as WebRTC data channels, can exchange `Message`s directly,
+/// either as JSON with [`Message::to_vec`] and [`Message::from_slice`], or framed with
+/// `Content-Length` headers by the [`LanguageServerCodec`](crate::codec::LanguageServerCodec).
+///
+/// # Invariants
+///
+/// * Every serialized message carries `"jsonrpc": "2.0"`, and messages with any other version are
+///   rejected when deserializing.
+/// * A message is deserialized as a [`Response`] if it is a valid response, that is, if it has an
+///   `id` and exactly one of `result` and `error`, and as a [`Request`] otherwise. Requests without
+///   a `method` are still accepted, so that they can be answered with an error.
+/// * A [`Request`] without an `id` is a notification, and must not be answered.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
-pub(crate) enum Message {
+pub enum Message {
     /// A response message.
     Response(Response),
     /// A request or notification message.
     Request(Request),
 }
 
+impl Message {
+    /// Deserializes a message from the JSON in `bytes`.
+    pub fn from_slice(bytes: &[u8]) -> serde_json::Result<Self> {
+        serde_json::from_slice(bytes)
+    }
+
+    /// Serializes the message as JSON, without any framing headers.
+    pub fn to_vec(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl FromStr for Message {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl From<Request> for Message {
+    fn from(req: Request) -> Self {
+        Message::Request(req)
+    }
+}
+
+impl From<Response> for Message {
+    fn from(res: Response) -> Self {
+        Message::Response(res)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -141,6 +199,19 @@ mod tests {
         assert_eq!(from_str, from_value);
     }
 
+    #[test]
+    fn round_trips_messages() {
+        let req = Request::build("textDocument/hover").id(1).finish();
+        let message = Message::from(req.clone());
+        let bytes = message.to_vec().unwrap();
+        assert_eq!(Message::from_slice(&bytes).unwrap(), Message::Request(req));
+        assert_eq!(message.to_string().parse::<Message>().unwrap(), message);
+
+        let res = Message::from(Response::from_ok(1.into(), json!(null)));
+        assert_eq!(res.to_string(), r#"{"jsonrpc":"2.0","result":null,"id":1}"#);
+        assert!(Message::from_slice(br#"{"jsonrpc":"1.0","id":1}"#).is_err());
+    }
+
     #[test]
     fn parses_incoming_message() {
         let server_request =
@@ -191,6 +262,34 @@ mod tests {
         assert!(matches!(incoming, Message::Request(_)));
     }
 
+    #[test]
+    fn interns_lsp_method_names() {
+        let did_change = r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{}}"#;
+        let message: Message = serde_json::from_slice(did_change.as_bytes()).unwrap();
+        let (method, _, _) = match message {
+            Message::Request(req) => req.into_parts(),
+            Message::Response(_) => panic!("expected a request"),
+        };
+        assert!(matches!(method, Cow::Borrowed("textDocument/didChange")));
+
+        for name in ["exit", "$/cancelRequest", "textDocument/hover"] {
+            let req = serde_json::from_value::<Request>(json!({"jsonrpc":"2.0","method":name}));
+            assert!(matches!(req.unwrap().into_parts().0, Cow::Borrowed(m) if m == name));
+        }
+
+        let custom = json!({"jsonrpc":"2.0","method":"custom/method"});
+        let (method, _, _) = serde_json::from_value::<Request>(custom)
+            .unwrap()
+            .into_parts();
+        assert!(matches!(method, Cow::Owned(m) if m == "custom/method"));
+
+        let near_miss = json!({"jsonrpc":"2.0","method":"textDocument/didChangeX"});
+        let (method, _, _) = serde_json::from_value::<Request>(near_miss)
+            .unwrap()
+            .into_parts();
+        assert!(matches!(method, Cow::Owned(_)));
+    }
+
     #[test]
     fn accepts_null_request_id() {
         let request_id: Id = serde_json::from_value(json!(null)).unwrap();
@@ -40,6 +40,12 @@ pub enum ErrorCode {
     ///
     /// This error code is specific to the Language Server Protocol.
     ContentModified,
+    /// The request was syntactically correct, but the server could not complete it.
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is specific to the Language Server Protocol.
+    RequestFailed,
 }
 
 impl ErrorCode {
@@ -53,6 +59,7 @@ impl ErrorCode {
             ErrorCode::InternalError => -32603,
             ErrorCode::RequestCancelled => -32800,
             ErrorCode::ContentModified => -32801,
+            ErrorCode::RequestFailed => -32803,
             ErrorCode::ServerError(code) => code,
         }
     }
@@ -67,6 +74,7 @@ impl ErrorCode {
             ErrorCode::InternalError => "Internal error",
             ErrorCode::RequestCancelled => "Canceled",
             ErrorCode::ContentModified => "Content modified",
+            ErrorCode::RequestFailed => "Request failed",
             ErrorCode::ServerError(_) => "Server error",
         }
     }
@@ -82,6 +90,7 @@ impl From<i64> for ErrorCode {
             -32603 => ErrorCode::InternalError,
             -32800 => ErrorCode::RequestCancelled,
             -32801 => ErrorCode::ContentModified,
+            -32803 => ErrorCode::RequestFailed,
             code => ErrorCode::ServerError(code),
         }
     }
@@ -100,6 +109,10 @@ impl Display for ErrorCode {
 }
 
 /// A JSON-RPC error object.
+///
+/// Errors whose message is known at compile time, such as those created by [`Error::new`] or the
+/// associated constants like [`Error::METHOD_NOT_FOUND`], borrow their message instead of
+/// allocating it, so they are cheap to construct in hot paths.
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Error {
@@ -113,11 +126,57 @@ pub struct Error {
 }
 
 impl Error {
+    /// A parse error (`-32700`).
+    pub const PARSE_ERROR: Error = Error::new(ErrorCode::ParseError);
+
+    /// An "invalid request" error (`-32600`).
+    pub const INVALID_REQUEST: Error = Error::new(ErrorCode::InvalidRequest);
+
+    /// A "method not found" error (`-32601`).
+    pub const METHOD_NOT_FOUND: Error = Error::new(ErrorCode::MethodNotFound);
+
+    /// An "invalid params" error (`-32602`) with the default message.
+    pub const INVALID_PARAMS: Error = Error::new(ErrorCode::InvalidParams);
+
+    /// An internal error (`-32603`).
+    pub const INTERNAL_ERROR: Error = Error::new(ErrorCode::InternalError);
+
+    /// A "request cancelled" error (`-32800`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    pub const REQUEST_CANCELLED: Error = Error::new(ErrorCode::RequestCancelled);
+
+    /// A "content modified" error (`-32801`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    pub const CONTENT_MODIFIED: Error = Error::new(ErrorCode::ContentModified);
+
     /// Creates a new error from the given `ErrorCode`.
     pub const fn new(code: ErrorCode) -> Self {
+        Error::with_message(code, code.description())
+    }
+
+    /// Creates a new error from the given `ErrorCode` and static `message`.
+    ///
+    /// Unlike building an `Error` from an owned message, this does not allocate and can be used
+    /// to define constants.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tower_lsp::jsonrpc::{Error, ErrorCode};
+    ///
+    /// const INDEX_NOT_READY: Error =
+    ///     Error::with_message(ErrorCode::ServerError(1), "Index is still being built");
+    /// ```
+    pub const fn with_message(code: ErrorCode, message: &'static str) -> Self {
         Error {
             code,
-            message: Cow::Borrowed(code.description()),
+            message: Cow::Borrowed(message),
             data: None,
         }
     }
@@ -171,6 +230,32 @@ impl Error {
     pub const fn content_modified() -> Self {
         Error::new(ErrorCode::ContentModified)
     }
+
+    /// Creates a new "request failed" error (`-32803`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is specific to the Language Server Protocol.
+    pub fn request_failed<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        Error {
+            code: ErrorCode::RequestFailed,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Returns whether this error was returned without sending a request to the client, because
+    /// the client did not advertise support for it.
+    ///
+    /// See the [`Client`](crate::Client#capabilities) documentation for the requests concerned.
+    pub fn is_unsupported_by_client(&self) -> bool {
+        let data = self.data.as_ref();
+        self.code == ErrorCode::MethodNotFound
+            && data.map_or(false, |data| data.get(UNSUPPORTED_BY_CLIENT).is_some())
+    }
 }
 
 impl Display for Error {
@@ -186,10 +271,23 @@ impl std::error::Error for Error {}
 /// See [here](https://microsoft.github.io/language-server-protocol/specification#initialize)
 /// for reference.
 pub(crate) const fn not_initialized_error() -> Error {
+    Error::with_message(ErrorCode::ServerError(-32002), "Server not initialized")
+}
+
+/// Member of the `data` object marking the errors returned by [`unsupported_by_client_error`].
+const UNSUPPORTED_BY_CLIENT: &str = "unsupportedByClient";
+
+/// Error returned for server-to-client requests which the client did not advertise support for.
+///
+/// This mirrors the `-32601` (method not found) error the client would otherwise respond with,
+/// with a marker in `data` so that [`Error::is_unsupported_by_client`] can tell them apart.
+pub(crate) fn unsupported_by_client_error(method: &'static str) -> Error {
+    let mut data = serde_json::Map::new();
+    data.insert(UNSUPPORTED_BY_CLIENT.into(), Value::from(method));
     Error {
-        code: ErrorCode::ServerError(-32002),
-        message: Cow::Borrowed("Server not initialized"),
-        data: None,
+        code: ErrorCode::MethodNotFound,
+        message: Cow::Owned(format!("Client does not support `{method}`")),
+        data: Some(Value::Object(data)),
     }
 }
 
@@ -213,5 +311,27 @@ mod tests {
 
         let deserialized: ErrorCode = serde_json::from_str("-12345").unwrap();
         assert_eq!(deserialized, ErrorCode::ServerError(-12345));
+
+        let deserialized: ErrorCode = serde_json::from_str("-32803").unwrap();
+        assert_eq!(deserialized, ErrorCode::RequestFailed);
+    }
+
+    #[test]
+    fn constants_borrow_their_message() {
+        const NOT_FOUND: Error = Error::METHOD_NOT_FOUND;
+        assert!(matches!(
+            NOT_FOUND.message,
+            Cow::Borrowed("Method not found")
+        ));
+        assert_eq!(NOT_FOUND, Error::method_not_found());
+
+        let serialized = serde_json::to_value(&NOT_FOUND).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!({ "code": -32601, "message": "Method not found" })
+        );
+
+        let deserialized: Error = serde_json::from_value(serialized).unwrap();
+        assert_eq!(deserialized, NOT_FOUND);
     }
 }
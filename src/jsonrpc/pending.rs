@@ -0,0 +1,123 @@
+//! Combinators extending `$/cancelRequest` semantics to arbitrary futures.
+//!
+//! Request handlers are dropped when the client cancels their request, and the client receives a
+//! `RequestCancelled` error in response. [`cancel_on`] lets other work started on behalf of a
+//! request, e.g. a background task spawned by its handler, be canceled along with it.
+
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::{self, AbortHandle, Abortable};
+
+use super::{Error, Id, Result};
+use crate::service::Pending;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Pending>> = const { RefCell::new(None) };
+}
+
+/// Makes `fut` cancelable by a `$/cancelRequest` notification for the request `id`.
+///
+/// The returned future resolves to a `RequestCancelled` [`Error`] if the request is canceled
+/// before `fut` completes, in which case `fut` is dropped. Once the request is no longer pending,
+/// e.g. because its response was sent, canceling it has no effect.
+///
+/// This must be called while a request handler is running, typically from within the handler
+/// itself. Otherwise, or if no request `id` is pending, `fut` cannot be canceled.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tower_lsp::jsonrpc::{self, pending, Result};
+/// # use tower_lsp::MethodContext;
+/// #
+/// # struct Mock;
+/// #
+/// async fn reindex(cx: MethodContext<'_, Mock>) -> Result<()> {
+///     let id = cx.id().cloned().unwrap_or(jsonrpc::Id::Null);
+///     let work = pending::cancel_on(id, async {
+///         // Long-running work, possibly spawned onto another task...
+///     });
+///
+///     work.await
+/// }
+/// ```
+pub fn cancel_on<F: Future>(id: Id, fut: F) -> CancelableFuture<F> {
+    let (abort_handle, registration) = AbortHandle::new_pair();
+    CURRENT.with(|current| {
+        if let Some(pending) = current.borrow().as_ref() {
+            pending.watch(&id, abort_handle);
+        }
+    });
+
+    CancelableFuture {
+        inner: Box::pin(Abortable::new(fut, registration)),
+    }
+}
+
+/// Drives `fut` to completion with `pending` as the registry used by [`cancel_on`].
+pub(crate) async fn scope<F: Future>(pending: Pending, fut: F) -> F::Output {
+    futures::pin_mut!(fut);
+    let mut pending = Some(pending);
+
+    future::poll_fn(|cx| {
+        let _guard = Enter::new(&mut pending);
+        fut.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Makes a registry current until dropped, restoring the previous one afterwards.
+struct Enter<'a> {
+    slot: &'a mut Option<Pending>,
+    previous: Option<Pending>,
+}
+
+impl<'a> Enter<'a> {
+    fn new(slot: &'a mut Option<Pending>) -> Self {
+        let previous = CURRENT.with(|current| current.replace(slot.take()));
+        Enter { slot, previous }
+    }
+}
+
+impl Drop for Enter<'_> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        *self.slot = CURRENT.with(|current| current.replace(previous));
+    }
+}
+
+/// Future returned by [`cancel_on`].
+#[must_use = "futures do nothing unless polled"]
+pub struct CancelableFuture<F> {
+    inner: Pin<Box<Abortable<F>>>,
+}
+
+impl<F> CancelableFuture<F> {
+    /// Returns `true` if the request this future is bound to was canceled.
+    pub fn is_canceled(&self) -> bool {
+        self.inner.is_aborted()
+    }
+}
+
+impl<F: Future> Future for CancelableFuture<F> {
+    type Output = Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner
+            .as_mut()
+            .poll(cx)
+            .map(|result| result.map_err(|_| Error::request_cancelled()))
+    }
+}
+
+impl<F> Debug for CancelableFuture<F> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CancelableFuture")
+            .field("canceled", &self.is_canceled())
+            .finish_non_exhaustive()
+    }
+}
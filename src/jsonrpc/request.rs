@@ -2,6 +2,7 @@ use std::borrow::Cow;
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
+use serde::de::{self, DeserializeOwned, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
@@ -15,11 +16,43 @@ where
     T::deserialize(deserializer).map(Some)
 }
 
+/// Deserializes a method name, borrowing the names of LSP methods instead of allocating them.
+///
+/// Incoming messages are overwhelmingly calls to the standard LSP methods, notably
+/// `textDocument/didChange` on every keystroke, so interning these saves one allocation per
+/// message. The methods known to `register_lsp_methods()` are found with a perfect hash computed
+/// at compile time, which costs two hashes and a single string comparison per message. The names
+/// of custom methods are still allocated.
+fn deserialize_method<'de, D>(deserializer: D) -> Result<Cow<'static, str>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct MethodVisitor;
+
+    impl<'de> Visitor<'de> for MethodVisitor {
+        type Value = Cow<'static, str>;
+
+        fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+            f.write_str("a method name")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(crate::generated::lsp_method(v).map_or_else(|| v.to_owned().into(), Cow::Borrowed))
+        }
+
+        fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+            Ok(crate::generated::lsp_method(&v).map_or(Cow::Owned(v), Cow::Borrowed))
+        }
+    }
+
+    deserializer.deserialize_str(MethodVisitor)
+}
+
 /// A JSON-RPC request or notification.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Request {
     jsonrpc: Version,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_method")]
     method: Cow<'static, str>,
     #[serde(default, deserialize_with = "deserialize_some")]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -87,6 +120,11 @@ impl Request {
         self.method.as_ref()
     }
 
+    /// Returns the name of the method to be invoked, without allocating for LSP methods.
+    pub(crate) fn method_owned(&self) -> Cow<'static, str> {
+        self.method.clone()
+    }
+
     /// Returns the unique ID of this request, if present.
     pub fn id(&self) -> Option<&Id> {
         self.id.as_ref()
@@ -97,6 +135,24 @@ impl Request {
         self.params.as_ref()
     }
 
+    /// Deserializes the `params` field into `T`.
+    ///
+    /// A missing `params` field is treated as `null`, so it deserializes into `()` or `None`.
+    pub fn params_as<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        T::deserialize(self.params.as_ref().unwrap_or(&Value::Null))
+    }
+
+    /// Returns a mutable reference to the `params` field, if present.
+    pub(crate) fn params_mut(&mut self) -> Option<&mut Value> {
+        self.params.as_mut()
+    }
+
+    /// Returns a mutable reference to the `params` field, setting it to an empty object if absent.
+    pub(crate) fn params_or_insert_object(&mut self) -> &mut Value {
+        self.params
+            .get_or_insert_with(|| Value::Object(Default::default()))
+    }
+
     /// Splits this request into the method name, request ID, and the `params` field, if present.
     pub fn into_parts(self) -> (Cow<'static, str>, Option<Id>, Option<Value>) {
         (self.method, self.id, self.params)
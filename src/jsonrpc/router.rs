@@ -1,40 +1,96 @@
 //! Lightweight JSON-RPC router service.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
 use std::fmt::{self, Debug, Formatter};
 use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
 use std::task::{Context, Poll};
 
-use futures::future::{self, BoxFuture, FutureExt};
+use futures::future::{self, BoxFuture, Either, FutureExt};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use tower::{util::BoxService, Layer, Service};
 
 use crate::jsonrpc::ErrorCode;
+use crate::service::{InvalidParamsPolicy, MethodContext};
+use crate::Client;
 
 use super::{Error, Id, Request, Response};
 
 /// A modular JSON-RPC 2.0 request router service.
+///
+/// Methods of other protocols can be registered on a `Router` and served alongside the LSP methods
+/// with [`LspService::build_with_router`](crate::LspService::build_with_router).
 pub struct Router<S, E = Infallible> {
-    server: Arc<S>,
+    /// The server, shared with the method handlers. Each handler clones the inner `Arc` for as
+    /// long as it runs.
+    server: Arc<RwLock<Arc<S>>>,
     methods: HashMap<&'static str, BoxService<Request, Option<Response>, E>>,
+    notifications: HashSet<&'static str>,
+    signatures: HashMap<&'static str, Signature>,
+    invalid_params: Arc<RwLock<HashMap<&'static str, InvalidParamsPolicy>>>,
+}
+
+/// Type names of the params and result of a registered method.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Signature {
+    pub params: Option<&'static str>,
+    pub result: Option<&'static str>,
 }
 
 impl<S: Send + Sync + 'static, E> Router<S, E> {
     /// Creates a new `Router` with the given shared state.
     pub fn new(server: S) -> Self {
         Router {
-            server: Arc::new(server),
+            server: Arc::new(RwLock::new(Arc::new(server))),
             methods: HashMap::new(),
+            notifications: HashSet::new(),
+            signatures: HashMap::new(),
+            invalid_params: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a shared reference to the inner server.
+    pub fn inner(&self) -> Arc<S> {
+        read(&self.server)
+    }
+
+    /// Returns exclusive access to the inner server, if no method calls are in progress.
+    ///
+    /// Every running method handler holds a shared reference to the server until its future
+    /// completes or is dropped, and so does every `Arc` returned by [`Router::inner`]. While any of
+    /// them exists, this method returns `None`. Method handlers started while the returned guard is
+    /// alive wait for it to be dropped.
+    pub fn inner_mut(&mut self) -> Option<InnerMut<'_, S>> {
+        let mut server = self.server.write().unwrap_or_else(|e| e.into_inner());
+        Arc::get_mut(&mut *server)?;
+        Some(InnerMut(server))
+    }
+
+    /// Returns `Some(true)` if `name` is registered as a notification, `Some(false)` if it is
+    /// registered as a request, or `None` if no such method exists.
+    pub fn is_notification(&self, name: &str) -> Option<bool> {
+        if self.methods.contains_key(name) {
+            Some(self.notifications.contains(name))
+        } else {
+            None
         }
     }
 
-    /// Returns a reference to the inner server.
-    pub fn inner(&self) -> &S {
-        self.server.as_ref()
+    /// Returns the name and signature of every registered method, in no particular order.
+    pub(crate) fn signatures(&self) -> impl Iterator<Item = (&'static str, Signature)> + '_ {
+        self.signatures.iter().map(|(name, sig)| (*name, *sig))
+    }
+
+    /// Sets the `policy` applied when the params of `name` fail to deserialize.
+    ///
+    /// This may be called before or after the method is registered.
+    pub fn set_invalid_params_policy(&mut self, name: &'static str, policy: InvalidParamsPolicy) {
+        self.invalid_params.write().unwrap().insert(name, policy);
     }
 
     /// Registers a new RPC method which constructs a response with the given `callback`.
@@ -48,15 +104,83 @@ impl<S: Send + Sync + 'static, E> Router<S, E> {
         L: Layer<MethodHandler<P, R, E>>,
         L::Service: Service<Request, Response = Option<Response>, Error = E> + Send + 'static,
         <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.register(
+            name,
+            move |server: Arc<S>, _, params| {
+                let callback = callback.clone();
+                async move { callback.invoke(&*server, params).await }
+            },
+            layer,
+        )
+    }
+
+    /// Registers a new RPC method like [`Router::method`], but whose `callback` receives a
+    /// [`MethodContext`] instead of a reference to the server.
+    pub fn method_with_context<P, R, F, L>(
+        &mut self,
+        name: &'static str,
+        client: Client,
+        callback: F,
+        layer: L,
+    ) -> &mut Self
+    where
+        P: FromParams,
+        R: IntoResponse,
+        F: for<'a> Method<MethodContext<'a, S>, P, R> + Clone + Send + Sync + 'static,
+        L: Layer<MethodHandler<P, R, E>>,
+        L::Service: Service<Request, Response = Option<Response>, Error = E> + Send + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.register(
+            name,
+            move |server: Arc<S>, id: Option<Id>, params| {
+                let callback = callback.clone();
+                let client = client.clone();
+                async move {
+                    let context = MethodContext::new(&*server, &client, id.as_ref(), name);
+                    callback.invoke(context, params).await
+                }
+            },
+            layer,
+        )
+    }
+
+    fn register<P, R, H, Fut, L>(&mut self, name: &'static str, handler: H, layer: L) -> &mut Self
+    where
+        P: FromParams,
+        R: IntoResponse,
+        H: Fn(Arc<S>, Option<Id>, P) -> Fut + Send + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+        L: Layer<MethodHandler<P, R, E>>,
+        L::Service: Service<Request, Response = Option<Response>, Error = E> + Send + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
     {
         let server = &self.server;
+        let invalid_params = self.invalid_params.clone();
+        let notifications = &mut self.notifications;
+        let signatures = &mut self.signatures;
         self.methods.entry(name).or_insert_with(|| {
+            if R::is_notification() {
+                notifications.insert(name);
+            }
+
+            let signature = Signature {
+                params: P::type_name(),
+                result: R::type_name(),
+            };
+            signatures.insert(name, signature);
+
             let server = server.clone();
-            let handler = MethodHandler::new(move |params| {
-                let callback = callback.clone();
-                let server = server.clone();
-                async move { callback.invoke(&*server, params).await }
-            });
+            let handler = MethodHandler::new(
+                move |id, params| handler(read(&server), id, params),
+                move |id, err| {
+                    let policies = invalid_params.read().unwrap();
+                    let policy = policies.get(name).copied();
+                    let policy = policy.unwrap_or(InvalidParamsPolicy::InvalidParams);
+                    policy.apply(name, id, err)
+                },
+            );
 
             BoxService::new(layer.layer(handler))
         });
@@ -68,7 +192,7 @@ impl<S: Send + Sync + 'static, E> Router<S, E> {
 impl<S: Debug, E> Debug for Router<S, E> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Router")
-            .field("server", &self.server)
+            .field("server", &*read(&self.server))
             .field("methods", &self.methods.keys())
             .finish()
     }
@@ -87,6 +211,9 @@ impl<S, E: Send + 'static> Service<Request> for Router<S, E> {
         if let Some(handler) = self.methods.get_mut(req.method()) {
             handler.call(req)
         } else {
+            // Unknown notifications, including `$/` ones, are ignored as allowed by the
+            // specification, while unknown requests, including `$/` ones, are answered with
+            // `MethodNotFound`.
             let (method, id, _) = req.into_parts();
             future::ok(id.map(|id| {
                 let mut error = Error::method_not_found();
@@ -98,20 +225,68 @@ impl<S, E: Send + 'static> Service<Request> for Router<S, E> {
     }
 }
 
+/// Returns the server shared by a [`Router`] with its method handlers.
+fn read<S>(server: &RwLock<Arc<S>>) -> Arc<S> {
+    // The lock is only held by `InnerMut` while running user code, which cannot leave the server
+    // in an inconsistent state for `Arc::clone`, so poisoning is harmless.
+    server.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Exclusive access to the server of a [`Router`], released when dropped.
+///
+/// This struct is created by [`Router::inner_mut`]. See its documentation for more.
+pub struct InnerMut<'a, S>(RwLockWriteGuard<'a, Arc<S>>);
+
+impl<S> Deref for InnerMut<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S> DerefMut for InnerMut<'_, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        // No handler can clone the server while the write lock is held.
+        Arc::get_mut(&mut self.0).expect("server is only shared through the lock")
+    }
+}
+
+impl<S: Debug> Debug for InnerMut<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        S::fmt(self, f)
+    }
+}
+
+type HandlerFn<P> = dyn Fn(Option<Id>, P) -> BoxFuture<'static, Option<Response>> + Send;
+
 /// Opaque JSON-RPC method handler.
 pub struct MethodHandler<P, R, E> {
-    f: Box<dyn Fn(P) -> BoxFuture<'static, R> + Send>,
-    _marker: PhantomData<E>,
+    f: Box<HandlerFn<P>>,
+    on_invalid_params: Box<dyn Fn(Option<Id>, Error) -> Option<Response> + Send>,
+    _marker: PhantomData<(R, E)>,
+}
+
+impl<P, R, E> Debug for MethodHandler<P, R, E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(MethodHandler))
+            .finish_non_exhaustive()
+    }
 }
 
 impl<P: FromParams, R: IntoResponse, E> MethodHandler<P, R, E> {
-    fn new<F, Fut>(handler: F) -> Self
+    fn new<F, Fut, G>(handler: F, on_invalid_params: G) -> Self
     where
-        F: Fn(P) -> Fut + Send + 'static,
+        F: Fn(Option<Id>, P) -> Fut + Send + 'static,
         Fut: Future<Output = R> + Send + 'static,
+        G: Fn(Option<Id>, Error) -> Option<Response> + Send + 'static,
     {
         MethodHandler {
-            f: Box::new(move |p| handler(p).boxed()),
+            f: Box::new(move |id, p| {
+                let fut = handler(id.clone(), p);
+                async move { fut.await.into_response(id) }.boxed()
+            }),
+            on_invalid_params: Box::new(on_invalid_params),
             _marker: PhantomData,
         }
     }
@@ -125,7 +300,7 @@ where
 {
     type Response = Option<Response>;
     type Error = E;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Future = MethodFuture<E>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
@@ -135,19 +310,53 @@ where
         let (_, id, params) = req.into_parts();
 
         match id {
-            Some(_) if R::is_notification() => return future::ok(().into_response(id)).boxed(),
-            None if !R::is_notification() => return future::ok(None).boxed(),
+            Some(_) if R::is_notification() => return MethodFuture::ready(().into_response(id)),
+            None if !R::is_notification() => return MethodFuture::ready(None),
             _ => {}
         }
 
         let params = match P::from_params(params) {
             Ok(params) => params,
-            Err(err) => return future::ok(id.map(|id| Response::from_error(id, err))).boxed(),
+            Err(err) => return MethodFuture::ready((self.on_invalid_params)(id, err)),
         };
 
-        (self.f)(params)
-            .map(move |r| Ok(r.into_response(id)))
-            .boxed()
+        MethodFuture {
+            inner: Either::Right((self.f)(id, params)),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Response future of a [`MethodHandler`].
+///
+/// Messages which are answered without calling the handler, such as those with invalid params,
+/// resolve immediately without allocating.
+pub struct MethodFuture<E> {
+    inner: Either<future::Ready<Option<Response>>, BoxFuture<'static, Option<Response>>>,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E> MethodFuture<E> {
+    fn ready(response: Option<Response>) -> Self {
+        MethodFuture {
+            inner: Either::Left(future::ready(response)),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<E> Debug for MethodFuture<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(MethodFuture))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E> Future for MethodFuture<E> {
+    type Output = Result<Option<Response>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.poll_unpin(cx).map(Ok)
     }
 }
 
@@ -161,6 +370,10 @@ where
 /// `async fn f(&self, params: P) -> jsonrpc::Result<R>` | Request with required parameters
 /// `async fn f(&self)`                                  | Notification without parameters
 /// `async fn f(&self, params: P)`                       | Notification with parameters
+///
+/// Handlers registered with
+/// [`LspServiceBuilder::custom_method_with_context`](crate::LspServiceBuilder::custom_method_with_context)
+/// take a [`MethodContext`] in place of `&self`.
 pub trait Method<S, P, R>: private::Sealed {
     /// The future response value.
     type Future: Future<Output = R> + Send;
@@ -202,6 +415,12 @@ where
 pub trait FromParams: private::Sealed + Send + Sized + 'static {
     /// Attempts to deserialize `Self` from the `params` value extracted from [`Request`].
     fn from_params(params: Option<Value>) -> super::Result<Self>;
+
+    /// Returns the name of the `params` type, if any.
+    #[doc(hidden)]
+    fn type_name() -> Option<&'static str> {
+        None
+    }
 }
 
 /// Deserialize non-existent JSON-RPC parameters.
@@ -226,6 +445,10 @@ impl<P: DeserializeOwned + Send + 'static> FromParams for (P,) {
             Err(Error::invalid_params("Missing params field"))
         }
     }
+
+    fn type_name() -> Option<&'static str> {
+        Some(std::any::type_name::<P>())
+    }
 }
 
 /// A trait implemented by all JSON-RPC response types.
@@ -235,6 +458,12 @@ pub trait IntoResponse: private::Sealed + Send + 'static {
 
     /// Returns `true` if this is a notification response type.
     fn is_notification() -> bool;
+
+    /// Returns the name of the result type, if any.
+    #[doc(hidden)]
+    fn type_name() -> Option<&'static str> {
+        None
+    }
 }
 
 /// Support JSON-RPC notification methods.
@@ -271,6 +500,10 @@ impl<R: Serialize + Send + 'static> IntoResponse for Result<R, Error> {
     fn is_notification() -> bool {
         false
     }
+
+    fn type_name() -> Option<&'static str> {
+        Some(std::any::type_name::<R>())
+    }
 }
 
 mod private {
@@ -329,6 +562,59 @@ mod tests {
         assert_eq!(response, Ok(Some(Response::from_ok(1.into(), params))));
     }
 
+    #[test]
+    fn reports_method_kind() {
+        let mut router: Router<Mock> = Router::new(Mock);
+        router
+            .method("first", Mock::request, layer_fn(|s| s))
+            .method("second", Mock::notification, layer_fn(|s| s));
+
+        assert_eq!(router.is_notification("first"), Some(false));
+        assert_eq!(router.is_notification("second"), Some(true));
+        assert_eq!(router.is_notification("third"), None);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn get_inner_mut() {
+        let mut router: Router<Mock> = Router::new(Mock);
+        router.method("first", Mock::request, layer_fn(|s| s));
+        assert!(router.inner_mut().is_some());
+
+        let request = Request::build("first").id(0).finish();
+        let pending = router.ready().await.unwrap().call(request);
+        assert!(router.inner_mut().is_none());
+
+        let response = pending.await;
+        assert_eq!(response, Ok(Some(Response::from_ok(0.into(), Value::Null))));
+        assert!(router.inner_mut().is_some());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_rebuilt_requests() {
+        let rebuild = layer_fn(
+            |mut inner: MethodHandler<(), Result<Value, Error>, Infallible>| {
+                tower::service_fn(move |req: Request| {
+                    let (method, id, params) = req.into_parts();
+                    let mut req = Request::build(method);
+                    if let Some(params) = params {
+                        req = req.params(params);
+                    }
+                    if let Some(id) = id {
+                        req = req.id(id);
+                    }
+                    inner.call(req.finish())
+                })
+            },
+        );
+
+        let mut router: Router<Mock> = Router::new(Mock);
+        router.method("first", Mock::request, rebuild);
+
+        let request = Request::build("first").id(0).finish();
+        let response = router.ready().await.unwrap().call(request).await;
+        assert_eq!(response, Ok(Some(Response::from_ok(0.into(), Value::Null))));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn routes_notifications() {
         let mut router: Router<Mock> = Router::new(Mock);
@@ -379,6 +665,22 @@ mod tests {
         assert_eq!(response, Ok(None));
     }
 
+    #[test]
+    fn answers_without_calling_handler() {
+        let mut handler = MethodHandler::<(Params,), Result<Params, Error>, Infallible>::new(
+            |_, _| async { unreachable!("handler should not be called") },
+            |id, err| id.map(|id| Response::from_error(id, err)),
+        );
+
+        let invalid_params = Request::build("request").params(json!(1)).id(0).finish();
+        let response = handler.call(invalid_params).now_or_never().unwrap();
+        assert!(matches!(response, Ok(Some(res)) if res.is_error()));
+
+        let notification = Request::build("request").finish();
+        let response = handler.call(notification).now_or_never();
+        assert_eq!(response, Some(Ok(None)));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn handles_incorrect_request_types() {
         let mut router: Router<Mock> = Router::new(Mock);
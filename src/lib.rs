@@ -69,7 +69,9 @@
 //! #   let (stdin, stdout) = (stdin.compat(), stdout.compat_write());
 //!
 //!     let (service, socket) = LspService::new(|client| Backend { client });
-//!     Server::new(stdin, stdout, socket).serve(service).await;
+//!     if let Err(err) = Server::new(stdin, stdout, socket).serve(service).await {
+//!         eprintln!("language server stopped unexpectedly: {err}");
+//!     }
 //! }
 //! ```
 
@@ -82,11 +84,142 @@ pub extern crate lsp_types;
 /// A re-export of [`async-trait`](https://docs.rs/async-trait) for convenience.
 pub use async_trait::async_trait;
 
+pub use self::deferred::Deferred;
+pub use self::per_folder::PerFolder;
 pub use self::service::progress::{
-    Bounded, Cancellable, NotCancellable, OngoingProgress, Progress, Unbounded,
+    Bounded, Cancellable, NotCancellable, OngoingProgress, Progress, ProgressIter, Unbounded,
+};
+pub use self::service::{
+    Auth, AuthLayer, Cancellation, CapabilityRegistration, Client, ClientSocket, ConcurrencyLimit,
+    CorrelationIdPolicy, DetachedRequest, ExitBehavior, ExitedError, Extensions, InitializeHook,
+    InitializeRejection, InvalidParamsPolicy, LspService, LspServiceBuilder, MethodContext,
+    MethodDescription, MethodMetrics, MetricsSnapshot, ProtocolViolation, RequestContext,
+    RequestIdStrategy, ResponseSizePolicy, RetryPolicy, RetryingClient, ServerDescription,
+    ShutdownHook, State, StateError, TraceContext, WorkspaceDiagnosticStream, WorkspaceTrust,
+};
+pub use self::transport::{
+    is_stdout_protected, run_until_exit, ConnectionStats, ConnectionStatsSnapshot, Desync,
+    DesyncReason, Filter, Loopback, OutputErrorPolicy, ServeError, ServeHandle, Server,
 };
-pub use self::service::{Client, ClientSocket, ExitedError, LspService, LspServiceBuilder};
-pub use self::transport::{Loopback, Server};
+
+/// Declares a set of custom JSON-RPC methods extending the protocol in a single definition.
+///
+/// Deriving `LspExtension` on an enum generates, for an enum named `Foo`:
+///
+/// * A `FooHandler` trait, extending [`LanguageServer`], with one `async` handler method per
+///   variant. Every handler has a default implementation, so backends only need to override the
+///   methods they actually receive from the client.
+/// * A `Foo::register()` function which registers all handlers on an [`LspServiceBuilder`].
+/// * A `FooClient` trait, implemented for [`Client`], with one `async` method per variant for
+///   sending the corresponding request or notification to the client.
+///
+/// Every variant must carry an `#[lsp(request = "...")]` or `#[lsp(notification = "...")]`
+/// attribute with the JSON-RPC method name. Requests are declared as `Variant(Params, Result)`,
+/// while notifications are declared as `Variant(Params)`, or simply `Variant` if they take no
+/// parameters. Handler and client method names are derived from the variant names in
+/// `snake_case`.
+///
+/// The attribute also accepts an optional `invalid_params` key, set to `"invalid_params"`,
+/// `"ignore"` or `"request_failed"`, selecting the [`InvalidParamsPolicy`] of the method.
+///
+/// Methods which are part of a protocol experiment can be tied to an experimental capability with
+/// the `experimental` key, e.g. `#[lsp(notification = "experimental/status", experimental =
+/// "statusNotification")]`. The corresponding `FooClient` method then only sends the message if
+/// the client advertised the capability, as reported by [`Client::has_experimental_capability`].
+/// Otherwise, notifications are silently dropped and requests fail with a `Method not found`
+/// error. `Foo::experimental_capabilities()` returns all such capabilities as a JSON object, ready
+/// to be advertised in [`ServerCapabilities::experimental`]. See also the [`experimental`] module.
+///
+/// The generated code refers to this crate as `::tower_lsp`. Crates which re-export `tower-lsp`
+/// under another path, e.g. to let their users derive `LspExtension` without depending on
+/// `tower-lsp` directly, can override it with an `#[lsp(crate = "...")]` attribute on the enum
+/// itself. The path is resolved from the module declaring the enum:
+///
+/// ```rust
+/// # extern crate tower_lsp as _;
+/// mod wrapper {
+///     pub use tower_lsp as lsp;
+/// }
+///
+/// #[derive(tower_lsp::LspExtension)]
+/// #[lsp(crate = "wrapper::lsp")]
+/// enum Extension {
+///     #[lsp(notification = "custom/reload")]
+///     Reload,
+/// }
+/// ```
+///
+/// # Examples
+///
+/// ```rust
+/// use serde::{Deserialize, Serialize};
+/// use tower_lsp::jsonrpc::Result;
+/// use tower_lsp::lsp_types::*;
+/// use tower_lsp::{Client, LanguageServer, LspExtension, LspService};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct StatusParams {
+///     message: String,
+/// }
+///
+/// #[derive(LspExtension)]
+/// enum Extension {
+///     #[lsp(request = "custom/ping")]
+///     Ping((), String),
+///     #[lsp(notification = "custom/status", invalid_params = "ignore")]
+///     Status(StatusParams),
+///     #[lsp(notification = "experimental/status", experimental = "statusNotification")]
+///     ExperimentalStatus(StatusParams),
+/// }
+///
+/// struct Backend {
+///     client: Client,
+/// }
+///
+/// #[tower_lsp::async_trait]
+/// impl LanguageServer for Backend {
+///     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+///         Ok(InitializeResult {
+///             capabilities: ServerCapabilities {
+///                 experimental: Some(Extension::experimental_capabilities()),
+///                 ..ServerCapabilities::default()
+///             },
+///             ..InitializeResult::default()
+///         })
+///     }
+///
+///     async fn shutdown(&self) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// #[tower_lsp::async_trait]
+/// impl ExtensionHandler for Backend {
+///     async fn ping(&self) -> Result<String> {
+///         let params = StatusParams { message: "pinged".into() };
+///         self.client.status(params).await;
+///         Ok("pong".into())
+///     }
+/// }
+///
+/// let (service, socket) = Extension::register(LspService::build(|client| Backend { client }))
+///     .finish();
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async move {
+/// # use serde_json::json;
+/// # use tower::{Service, ServiceExt};
+/// # use tower_lsp::jsonrpc::{Request, Response};
+/// # let mut service = service;
+/// # let init = Request::build("initialize").params(json!({"capabilities":{}})).id(1).finish();
+/// # let init = service.ready().await.unwrap().call(init).await.unwrap().unwrap();
+/// # let experimental = &init.result().unwrap()["capabilities"]["experimental"];
+/// # assert_eq!(*experimental, json!({"statusNotification": true}));
+/// # let ping = Request::build("custom/ping").id(2).finish();
+/// # let response = service.ready().await.unwrap().call(ping).await.unwrap();
+/// # assert_eq!(response, Some(Response::from_ok(2.into(), json!("pong"))));
+/// # drop(socket);
+/// # });
+/// ```
+pub use tower_lsp_macros::LspExtension;
 
 use auto_impl::auto_impl;
 use lsp_types::request::{
@@ -100,11 +233,34 @@ use tracing::{error, warn};
 
 use self::jsonrpc::{Error, Result};
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[doc(hidden)]
+pub mod capabilities;
+pub mod cli;
+pub mod codec;
+pub mod completion;
+pub mod conformance;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod experimental;
+pub mod file_operations;
+pub mod glob;
 pub mod jsonrpc;
-
-mod codec;
+pub mod lsif;
+pub mod position;
+pub mod prelude;
+pub mod protocol;
+pub mod snapshot;
+pub mod snippet;
+pub mod symbols;
+pub mod sync;
+pub mod telemetry;
+pub mod transport;
+
+mod deferred;
+mod per_folder;
 mod service;
-mod transport;
 
 /// Trait implemented by language server backends.
 ///
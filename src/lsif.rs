@@ -0,0 +1,391 @@
+//! Generation of [LSIF] dumps from data computed by the server.
+//!
+//! The Language Server Index Format describes what a language server knows about a workspace,
+//! such as definitions, references, hovers and monikers, as a graph of vertices and edges, which
+//! code browsers can serve without running the server. A [`Writer`] emits this graph from the same
+//! [`Location`]s, [`Hover`]s and [`Moniker`]s the server returns for `textDocument/definition`,
+//! `textDocument/references`, `textDocument/hover` and `textDocument/moniker` requests, taking
+//! care of assigning IDs and linking vertices together.
+//!
+//! The LSIF types themselves are re-exported from [`lsp_types::lsif`].
+//!
+//! [LSIF]: https://microsoft.github.io/language-server-protocol/specifications/lsif/0.6.0/specification/
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::lsif::{ToolInfo, Writer};
+//! use tower_lsp::lsp_types::*;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let root = Url::parse("file:///project/").unwrap();
+//! let tool = ToolInfo {
+//!     name: "my-server".into(),
+//!     args: vec![],
+//!     version: None,
+//! };
+//! let mut lsif = Writer::new(Vec::new(), root, tool)?;
+//!
+//! let uri = Url::parse("file:///project/main.rs").unwrap();
+//! lsif.document(uri.clone(), "rust")?;
+//!
+//! let main = lsif.symbol()?;
+//! let range = Range::new(Position::new(0, 3), Position::new(0, 7));
+//! lsif.definition(main, &Location::new(uri, range))?;
+//! lsif.hover(main, Hover {
+//!     contents: HoverContents::Scalar(MarkedString::String("fn main()".into())),
+//!     range: None,
+//! })?;
+//!
+//! let dump = lsif.finish()?;
+//! assert_eq!(dump.split(|&b| b == b'\n').filter(|l| !l.is_empty()).count(), 14);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use lsp_types::lsif::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use lsp_types::{Hover, Location, Moniker, NumberOrString, Range, Url};
+
+/// Version of the LSIF specification implemented by [`Writer`].
+const LSIF_VERSION: &str = "0.6.0";
+
+/// A document added to a [`Writer`] with [`Writer::document`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DocumentId(usize);
+
+/// A symbol added to a [`Writer`] with [`Writer::symbol`].
+///
+/// Definitions, references, hovers and monikers are attached to symbols, which are emitted as
+/// LSIF result sets.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SymbolId(usize);
+
+/// Writer of an LSIF dump, emitting one JSON entry per line.
+///
+/// Vertices are written as soon as they are added. The edges linking symbols to their definition
+/// and reference results, and documents to their ranges, are written by [`Writer::finish`].
+#[derive(Debug)]
+pub struct Writer<W> {
+    out: W,
+    next_id: i32,
+    uris: HashMap<Url, DocumentId>,
+    documents: Vec<DocumentState>,
+    symbols: Vec<SymbolState>,
+}
+
+#[derive(Debug)]
+struct DocumentState {
+    id: i32,
+    /// Range vertices within this document, in the order they were written.
+    ranges: Vec<i32>,
+    range_ids: HashMap<RangeKey, i32>,
+}
+
+/// Start and end of a [`Range`], which does not implement `Hash`.
+type RangeKey = (u32, u32, u32, u32);
+
+#[derive(Debug)]
+struct SymbolState {
+    id: i32,
+    definitions: BTreeMap<usize, Vec<i32>>,
+    references: BTreeMap<usize, Vec<i32>>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new writer emitting a dump of the project at `project_root` to `out`.
+    ///
+    /// This writes the `metaData` vertex, describing the tool which created the dump.
+    pub fn new(out: W, project_root: Url, tool_info: ToolInfo) -> io::Result<Self> {
+        let mut writer = Writer {
+            out,
+            next_id: 0,
+            uris: HashMap::new(),
+            documents: Vec::new(),
+            symbols: Vec::new(),
+        };
+
+        writer.vertex(Vertex::MetaData(MetaData {
+            version: LSIF_VERSION.into(),
+            project_root,
+            position_encoding: Encoding::Utf16,
+            tool_info: Some(tool_info),
+        }))?;
+
+        Ok(writer)
+    }
+
+    /// Adds the document at `uri`, written in the language `language_id`.
+    ///
+    /// Documents must be added before any location within them is. Adding a document again
+    /// returns the same `DocumentId`.
+    pub fn document(&mut self, uri: Url, language_id: &str) -> io::Result<DocumentId> {
+        if let Some(&document) = self.uris.get(&uri) {
+            return Ok(document);
+        }
+
+        let id = self.vertex(Vertex::Document(Document {
+            uri: uri.clone(),
+            language_id: language_id.into(),
+        }))?;
+
+        let document = DocumentId(self.documents.len());
+        self.documents.push(DocumentState {
+            id,
+            ranges: Vec::new(),
+            range_ids: HashMap::new(),
+        });
+        self.uris.insert(uri, document);
+        Ok(document)
+    }
+
+    /// Adds a new symbol, to which definitions, references, hovers and monikers can be attached.
+    pub fn symbol(&mut self) -> io::Result<SymbolId> {
+        let id = self.vertex(Vertex::ResultSet(ResultSet { key: None }))?;
+        self.symbols.push(SymbolState {
+            id,
+            definitions: BTreeMap::new(),
+            references: BTreeMap::new(),
+        });
+
+        Ok(SymbolId(self.symbols.len() - 1))
+    }
+
+    /// Records `location` as a definition of `symbol`, e.g. as returned by
+    /// `textDocument/definition`.
+    pub fn definition(&mut self, symbol: SymbolId, location: &Location) -> io::Result<()> {
+        let (document, range) = self.range(symbol, location)?;
+        let definitions = &mut self.symbols[symbol.0].definitions;
+        definitions.entry(document.0).or_default().push(range);
+        Ok(())
+    }
+
+    /// Records `location` as a reference to `symbol`, e.g. as returned by
+    /// `textDocument/references`.
+    ///
+    /// Definitions of `symbol` are also listed among its references, so they should not be
+    /// recorded again here.
+    pub fn reference(&mut self, symbol: SymbolId, location: &Location) -> io::Result<()> {
+        let (document, range) = self.range(symbol, location)?;
+        let references = &mut self.symbols[symbol.0].references;
+        references.entry(document.0).or_default().push(range);
+        Ok(())
+    }
+
+    /// Attaches `hover` to `symbol`, as returned by `textDocument/hover`.
+    pub fn hover(&mut self, symbol: SymbolId, hover: Hover) -> io::Result<()> {
+        let result = self.vertex(Vertex::HoverResult { result: hover })?;
+        let out_v = self.symbols[symbol.0].id;
+        self.edge(Edge::Hover(edge_data(out_v, result)))
+    }
+
+    /// Attaches `moniker` to `symbol`, as returned by `textDocument/moniker`.
+    pub fn moniker(&mut self, symbol: SymbolId, moniker: Moniker) -> io::Result<()> {
+        let moniker = self.vertex(Vertex::Moniker(moniker))?;
+        let out_v = self.symbols[symbol.0].id;
+        self.edge(Edge::Moniker(edge_data(out_v, moniker)))
+    }
+
+    /// Writes the remaining edges and flushes the dump, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        for symbol in std::mem::take(&mut self.symbols) {
+            if !symbol.definitions.is_empty() {
+                let result = self.vertex(Vertex::DefinitionResult)?;
+                self.edge(Edge::Definition(edge_data(symbol.id, result)))?;
+                self.items(result, &symbol.definitions, || None)?;
+            }
+
+            if !symbol.definitions.is_empty() || !symbol.references.is_empty() {
+                let result = self.vertex(Vertex::ReferenceResult)?;
+                self.edge(Edge::References(edge_data(symbol.id, result)))?;
+                self.items(result, &symbol.definitions, || Some(ItemKind::Definitions))?;
+                self.items(result, &symbol.references, || Some(ItemKind::References))?;
+            }
+        }
+
+        for document in std::mem::take(&mut self.documents) {
+            if !document.ranges.is_empty() {
+                self.edge(Edge::Contains(EdgeDataMultiIn {
+                    in_vs: document.ranges.iter().map(|&id| vertex_id(id)).collect(),
+                    out_v: vertex_id(document.id),
+                }))?;
+            }
+        }
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Returns the range vertex at `location`, writing it and linking it to `symbol` if needed.
+    fn range(&mut self, symbol: SymbolId, location: &Location) -> io::Result<(DocumentId, i32)> {
+        let document = match self.uris.get(&location.uri) {
+            Some(&document) => document,
+            None => {
+                let msg = format!("document `{}` was not added to the writer", location.uri);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+        };
+
+        let Range { start, end } = location.range;
+        let key = (start.line, start.character, end.line, end.character);
+        if let Some(&id) = self.documents[document.0].range_ids.get(&key) {
+            return Ok((document, id));
+        }
+
+        let id = self.vertex(Vertex::Range {
+            range: location.range,
+            tag: None,
+        })?;
+        let state = &mut self.documents[document.0];
+        state.ranges.push(id);
+        state.range_ids.insert(key, id);
+        self.edge(Edge::Next(edge_data(id, self.symbols[symbol.0].id)))?;
+        Ok((document, id))
+    }
+
+    /// Writes the `item` edges from `result` to the given ranges, grouped by document.
+    fn items(
+        &mut self,
+        result: i32,
+        ranges: &BTreeMap<usize, Vec<i32>>,
+        property: impl Fn() -> Option<ItemKind>,
+    ) -> io::Result<()> {
+        for (&document, ranges) in ranges {
+            self.edge(Edge::Item(Item {
+                document: vertex_id(self.documents[document].id),
+                property: property(),
+                edge_data: EdgeDataMultiIn {
+                    in_vs: ranges.iter().map(|&id| vertex_id(id)).collect(),
+                    out_v: vertex_id(result),
+                },
+            }))?;
+        }
+
+        Ok(())
+    }
+
+    fn vertex(&mut self, vertex: Vertex) -> io::Result<i32> {
+        self.entry(Element::Vertex(vertex))
+    }
+
+    fn edge(&mut self, edge: Edge) -> io::Result<()> {
+        self.entry(Element::Edge(edge)).map(drop)
+    }
+
+    fn entry(&mut self, data: Element) -> io::Result<i32> {
+        self.next_id += 1;
+        let entry = Entry {
+            id: vertex_id(self.next_id),
+            data,
+        };
+
+        serde_json::to_writer(&mut self.out, &entry)?;
+        self.out.write_all(b"\n")?;
+        Ok(self.next_id)
+    }
+}
+
+fn vertex_id(id: i32) -> Id {
+    NumberOrString::Number(id)
+}
+
+fn edge_data(out_v: i32, in_v: i32) -> EdgeData {
+    EdgeData {
+        in_v: vertex_id(in_v),
+        out_v: vertex_id(out_v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{HoverContents, MarkedString, Position};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn writes_symbol_graph() {
+        let root = Url::parse("file:///project/").unwrap();
+        let tool = ToolInfo {
+            name: "test".into(),
+            args: vec![],
+            version: None,
+        };
+        let mut lsif = Writer::new(Vec::new(), root, tool).unwrap();
+
+        let uri = Url::parse("file:///project/main.rs").unwrap();
+        let document = lsif.document(uri.clone(), "rust").unwrap();
+        assert_eq!(lsif.document(uri.clone(), "rust").unwrap(), document);
+
+        let symbol = lsif.symbol().unwrap();
+        let range = |line| Range::new(Position::new(line, 0), Position::new(line, 4));
+        let definition = Location::new(uri.clone(), range(0));
+        lsif.definition(symbol, &definition).unwrap();
+        lsif.reference(symbol, &Location::new(uri, range(2)))
+            .unwrap();
+        let hover = Hover {
+            contents: HoverContents::Scalar(MarkedString::String("fn main()".into())),
+            range: None,
+        };
+        lsif.hover(symbol, hover).unwrap();
+
+        let unknown = Url::parse("file:///project/other.rs").unwrap();
+        let err = lsif.reference(symbol, &Location::new(unknown, range(0)));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let dump = lsif.finish().unwrap();
+        let entries: Vec<Value> = dump
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        let labels: Vec<_> = entries
+            .iter()
+            .map(|e| e["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "metaData",
+                "document",
+                "resultSet",
+                "range",
+                "next",
+                "range",
+                "next",
+                "hoverResult",
+                "textDocument/hover",
+                "definitionResult",
+                "textDocument/definition",
+                "item",
+                "referenceResult",
+                "textDocument/references",
+                "item",
+                "item",
+                "contains",
+            ]
+        );
+
+        assert_eq!(entries[0]["positionEncoding"], "utf-16");
+        assert_eq!(
+            entries[14],
+            json!({
+                "id": 15,
+                "type": "edge",
+                "label": "item",
+                "outV": 13,
+                "inVs": [4],
+                "document": 2,
+                "property": "definitions",
+            })
+        );
+        assert_eq!(
+            entries[16],
+            json!({ "id": 17, "type": "edge", "label": "contains", "outV": 2, "inVs": [4, 6] })
+        );
+    }
+}
@@ -0,0 +1,929 @@
+//! Routing of requests to one backend instance per workspace folder.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::future;
+use lsp_types::request::{
+    GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
+    GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
+};
+use lsp_types::*;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::jsonrpc::{Error, Result};
+use crate::{Client, LanguageServer};
+
+/// Key under which resolvable items remember the folder whose backend produced them.
+const FOLDER_KEY: &str = "towerLspFolder";
+
+type Factory<S> = dyn Fn(Client, Option<WorkspaceFolder>) -> S + Send + Sync;
+
+/// A [`LanguageServer`] which runs a separate backend for every workspace folder.
+///
+/// Some servers cannot share state across workspace roots, e.g. because each root has its own
+/// build configuration. `PerFolder` creates one backend per folder with the given `factory` and
+/// routes every message to the backend which owns it:
+///
+/// * Requests and notifications about a document go to the backend of the innermost folder
+///   containing the document. Documents outside of all folders go to the first backend.
+/// * Items which are resolved later, such as completion items or code lenses, are resolved by the
+///   backend which produced them. Their `data` field is wrapped for this purpose, and unwrapped
+///   again before reaching the backend.
+/// * Workspace-wide requests, such as `workspace/symbol`, are sent to every backend and their
+///   responses are merged. File operations are split up by folder.
+/// * `workspace/executeCommand` goes to the backend owning the first argument, if it is a URI,
+///   and to the first backend otherwise.
+///
+/// Folders added or removed by the client create and shut down backends accordingly. If the client
+/// opens no folder at all, a single backend is created with a `None` folder.
+///
+/// Every backend receives the `initialize` request with the `workspace_folders` and `root_uri`
+/// narrowed to its own folder. The capabilities returned by the first backend are reported to the
+/// client, so all backends should advertise the same capabilities.
+///
+/// # Examples
+///
+/// ```rust
+/// use tower_lsp::jsonrpc::Result;
+/// use tower_lsp::lsp_types::*;
+/// use tower_lsp::{Client, LanguageServer, LspService, PerFolder};
+///
+/// struct Backend {
+///     client: Client,
+///     root: Option<Url>,
+/// }
+///
+/// #[tower_lsp::async_trait]
+/// impl LanguageServer for Backend {
+///     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+///         Ok(InitializeResult::default())
+///     }
+///
+///     async fn shutdown(&self) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let (service, socket) = LspService::new(|client| {
+///     PerFolder::new(client, |client, folder| Backend {
+///         client,
+///         root: folder.map(|folder| folder.uri),
+///     })
+/// });
+/// ```
+pub struct PerFolder<S> {
+    client: Client,
+    factory: Box<Factory<S>>,
+    params: Mutex<Option<InitializeParams>>,
+    folders: Mutex<Vec<Folder<S>>>,
+}
+
+struct Folder<S> {
+    uri: Option<Url>,
+    server: Arc<S>,
+}
+
+impl<S> Clone for Folder<S> {
+    fn clone(&self) -> Self {
+        Folder {
+            uri: self.uri.clone(),
+            server: self.server.clone(),
+        }
+    }
+}
+
+impl<S: LanguageServer> PerFolder<S> {
+    /// Creates a new `PerFolder` which instantiates backends with `factory`.
+    pub fn new<F>(client: Client, factory: F) -> Self
+    where
+        F: Fn(Client, Option<WorkspaceFolder>) -> S + Send + Sync + 'static,
+    {
+        PerFolder {
+            client,
+            factory: Box::new(factory),
+            params: Mutex::new(None),
+            folders: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the backends of all workspace folders, in the order they were opened.
+    pub fn backends(&self) -> Vec<Arc<S>> {
+        let folders = self.folders.lock().unwrap();
+        folders.iter().map(|f| f.server.clone()).collect()
+    }
+
+    /// Returns the backend owning `uri`.
+    ///
+    /// This is the backend of the innermost folder containing `uri`, or the first backend if no
+    /// folder contains it. Returns `None` before the server is initialized.
+    pub fn backend_for(&self, uri: &Url) -> Option<Arc<S>> {
+        self.owner(uri).map(|f| f.server)
+    }
+
+    fn owner(&self, uri: &Url) -> Option<Folder<S>> {
+        let folders = self.folders.lock().unwrap();
+        let innermost = folders
+            .iter()
+            .filter_map(|f| Some((f, f.uri.as_ref()?)))
+            .filter(|(_, folder)| contains(folder, uri))
+            .max_by_key(|(_, folder)| folder.path().trim_end_matches('/').len())
+            .map(|(f, _)| f);
+
+        innermost.or_else(|| folders.first()).cloned()
+    }
+
+    fn folder(&self, uri: &Option<Url>) -> Option<Folder<S>> {
+        let folders = self.folders.lock().unwrap();
+        folders.iter().find(|f| &f.uri == uri).cloned()
+    }
+
+    fn all(&self) -> Vec<Folder<S>> {
+        self.folders.lock().unwrap().clone()
+    }
+
+    /// Creates and initializes a backend for `folder`.
+    async fn open(&self, folder: Option<WorkspaceFolder>) -> Result<(Folder<S>, InitializeResult)> {
+        let mut params = self.params.lock().unwrap().clone().unwrap_or_default();
+        params.root_uri = folder.as_ref().map(|f| f.uri.clone());
+        params.workspace_folders = folder.clone().map(|f| vec![f]);
+
+        let uri = folder.as_ref().map(|f| f.uri.clone());
+        let server = Arc::new((self.factory)(self.client.clone(), folder));
+        let result = server.initialize(params).await?;
+        Ok((Folder { uri, server }, result))
+    }
+
+    /// Sends a document-scoped message to the backend owning `uri`.
+    async fn route<'a, T, F, Fut>(&'a self, uri: &Url, f: F) -> Result<T>
+    where
+        F: FnOnce(Arc<S>) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        match self.owner(uri) {
+            Some(folder) => f(folder.server).await,
+            None => Err(Error::internal_error()),
+        }
+    }
+
+    /// Sends a message to every backend, returning their results in folder order.
+    async fn broadcast<T, F, Fut>(&self, f: F) -> Vec<T>
+    where
+        F: Fn(Arc<S>) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        future::join_all(self.all().into_iter().map(|folder| f(folder.server))).await
+    }
+
+    /// Splits `items` by owning folder, sending each folder's share to its backend.
+    async fn split<I, T, U, F, Fut>(&self, items: Vec<I>, uri: U, f: F) -> Vec<T>
+    where
+        U: Fn(&I) -> Option<Url>,
+        F: Fn(Arc<S>, Vec<I>) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let mut shares: Vec<(Folder<S>, Vec<I>)> = Vec::new();
+        for item in items {
+            let owner = match uri(&item).and_then(|uri| self.owner(&uri)) {
+                Some(owner) => owner,
+                None => match self.all().into_iter().next() {
+                    Some(first) => first,
+                    None => continue,
+                },
+            };
+
+            match shares.iter_mut().find(|(f, _)| f.uri == owner.uri) {
+                Some((_, share)) => share.push(item),
+                None => shares.push((owner, vec![item])),
+            }
+        }
+
+        let calls = shares
+            .into_iter()
+            .map(|(folder, share)| f(folder.server, share));
+        future::join_all(calls).await
+    }
+
+    /// Resolves an item produced by [`PerFolder::tagged`] with the backend which produced it.
+    async fn resolve<'a, T, F, Fut>(&'a self, mut data: Option<Value>, f: F) -> Result<T>
+    where
+        F: FnOnce(Arc<S>, Option<Value>) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        let folder = match untag(&mut data) {
+            Some(uri) => self.folder(&uri),
+            None => self.all().into_iter().next(),
+        };
+
+        match folder {
+            Some(folder) => f(folder.server, data).await,
+            None => Err(Error::internal_error()),
+        }
+    }
+
+    /// Like [`PerFolder::route`], but tags the `data` of the returned items with their folder.
+    async fn tagged<'a, T, F, Fut, D>(&'a self, uri: &Url, f: F, data: D) -> Result<T>
+    where
+        F: FnOnce(Arc<S>) -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+        D: FnOnce(&mut T, &mut dyn FnMut(&mut Option<Value>)),
+    {
+        let folder = self.owner(uri).ok_or_else(Error::internal_error)?;
+        let mut result = f(folder.server).await?;
+        data(&mut result, &mut |data| tag(data, &folder.uri));
+        Ok(result)
+    }
+}
+
+impl<S> Debug for PerFolder<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let folders = self.folders.lock().unwrap();
+        let uris: Vec<_> = folders.iter().map(|f| &f.uri).collect();
+        f.debug_struct(stringify!(PerFolder))
+            .field("client", &self.client)
+            .field("folders", &uris)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns whether `uri` is located inside the folder at `folder`.
+fn contains(folder: &Url, uri: &Url) -> bool {
+    if uri.scheme() != folder.scheme()
+        || uri.host_str() != folder.host_str()
+        || uri.port() != folder.port()
+    {
+        return false;
+    }
+
+    let folder = folder.path().trim_end_matches('/');
+    match uri.path().strip_prefix(folder) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn tag(data: &mut Option<Value>, folder: &Option<Url>) {
+    let original = data.take().unwrap_or(Value::Null);
+    *data = Some(json!({ FOLDER_KEY: folder, "data": original }));
+}
+
+/// Restores `data` wrapped by [`tag`], returning the folder it was tagged with.
+fn untag(data: &mut Option<Value>) -> Option<Option<Url>> {
+    let folder = match data {
+        Some(Value::Object(map)) if map.contains_key(FOLDER_KEY) => map.remove(FOLDER_KEY)?,
+        _ => return None,
+    };
+
+    let original = data
+        .take()
+        .and_then(|mut d| d.get_mut("data").map(Value::take));
+    *data = original.filter(|d| !d.is_null());
+    serde_json::from_value(folder).ok()
+}
+
+fn first_error<T>(results: Vec<Result<T>>) -> Result<Vec<T>> {
+    results.into_iter().collect()
+}
+
+/// Merges `edits` into a single edit, or `None` if there are none.
+fn merge_edits(edits: Vec<WorkspaceEdit>) -> Option<WorkspaceEdit> {
+    let mut edits = edits.into_iter();
+    let mut merged = edits.next()?;
+
+    for edit in edits {
+        if let Some(changes) = edit.changes {
+            let merged_changes = merged.changes.get_or_insert_with(HashMap::new);
+            for (uri, mut edits) in changes {
+                merged_changes.entry(uri).or_default().append(&mut edits);
+            }
+        }
+
+        if let Some(changes) = edit.document_changes {
+            merged.document_changes = Some(match merged.document_changes.take() {
+                None => changes,
+                Some(DocumentChanges::Edits(mut a)) => match changes {
+                    DocumentChanges::Edits(mut b) => {
+                        a.append(&mut b);
+                        DocumentChanges::Edits(a)
+                    }
+                    DocumentChanges::Operations(b) => {
+                        DocumentChanges::Operations(operations(a).chain(b).collect())
+                    }
+                },
+                Some(DocumentChanges::Operations(mut a)) => {
+                    match changes {
+                        DocumentChanges::Edits(b) => a.extend(operations(b)),
+                        DocumentChanges::Operations(mut b) => a.append(&mut b),
+                    }
+                    DocumentChanges::Operations(a)
+                }
+            });
+        }
+
+        if let Some(annotations) = edit.change_annotations {
+            let merged_annotations = merged.change_annotations.get_or_insert_with(HashMap::new);
+            merged_annotations.extend(annotations);
+        }
+    }
+
+    Some(merged)
+}
+
+fn operations(edits: Vec<TextDocumentEdit>) -> impl Iterator<Item = DocumentChangeOperation> {
+    edits.into_iter().map(DocumentChangeOperation::Edit)
+}
+
+macro_rules! route {
+    (
+        impl { $($manual:tt)* }
+        requests { $($req:ident($req_params:ty) -> $res:ty = $($req_uri:ident).+;)* }
+        notifications { $($notif:ident($notif_params:ty) = $($notif_uri:ident).+;)* }
+    ) => {
+        #[async_trait]
+        impl<S: LanguageServer> LanguageServer for PerFolder<S> {
+            $($manual)*
+
+            $(
+                async fn $req(&self, params: $req_params) -> Result<$res> {
+                    let uri = params.$($req_uri).+.clone();
+                    self.route(&uri, |server| async move { server.$req(params).await })
+                        .await
+                }
+            )*
+
+            $(
+                async fn $notif(&self, params: $notif_params) {
+                    let uri = params.$($notif_uri).+.clone();
+                    let _ = self
+                        .route(&uri, |server| async move {
+                            server.$notif(params).await;
+                            Ok(())
+                        })
+                        .await;
+                }
+            )*
+        }
+    };
+}
+
+route! {
+    impl {
+        async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+            let root = params.root_uri.clone().map(|uri| WorkspaceFolder {
+                name: uri
+                    .path_segments()
+                    .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+                    .unwrap_or_default()
+                    .to_owned(),
+                uri,
+            });
+
+            let folders = match params.workspace_folders.clone() {
+                Some(folders) if !folders.is_empty() => folders.into_iter().map(Some).collect(),
+                _ => vec![root],
+            };
+
+            *self.params.lock().unwrap() = Some(params);
+
+            let opened = future::join_all(folders.into_iter().map(|f| self.open(f))).await;
+            let (folders, results): (Vec<_>, Vec<_>) = first_error(opened)?.into_iter().unzip();
+            *self.folders.lock().unwrap() = folders;
+
+            let mut result = results.into_iter().next().unwrap_or_default();
+            let workspace = result
+                .capabilities
+                .workspace
+                .get_or_insert_with(Default::default);
+            workspace.workspace_folders = Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            });
+
+            Ok(result)
+        }
+
+        async fn initialized(&self, params: InitializedParams) {
+            self.broadcast(|server| async move { server.initialized(params).await })
+                .await;
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            let results = self.broadcast(|server| async move { server.shutdown().await });
+            first_error(results.await).map(|_| ())
+        }
+
+        async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+            let removed: Vec<_> = params
+                .event
+                .removed
+                .into_iter()
+                .map(|f| Some(f.uri))
+                .collect();
+            let closed: Vec<_> = {
+                let mut folders = self.folders.lock().unwrap();
+                let (closed, kept): (Vec<_>, Vec<_>) =
+                    folders.drain(..).partition(|f| removed.contains(&f.uri));
+                *folders = kept;
+                closed
+            };
+
+            for folder in closed {
+                if let Err(err) = folder.server.shutdown().await {
+                    warn!("failed to shut down backend of {:?}: {}", folder.uri, err);
+                }
+            }
+
+            for added in params.event.added {
+                let uri = added.uri.clone();
+                match self.open(Some(added)).await {
+                    Ok((folder, _)) => {
+                        self.folders.lock().unwrap().push(folder.clone());
+                        folder.server.initialized(InitializedParams {}).await;
+                    }
+                    Err(err) => warn!("failed to initialize backend of {}: {}", uri, err),
+                }
+            }
+        }
+
+        async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+            let params = &params;
+            self.broadcast(|server| async move {
+                server.did_change_configuration(params.clone()).await;
+            })
+            .await;
+        }
+
+        async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+            let uri = |event: &FileEvent| Some(event.uri.clone());
+            self.split(params.changes, uri, |server, changes| async move {
+                let params = DidChangeWatchedFilesParams { changes };
+                server.did_change_watched_files(params).await;
+            })
+            .await;
+        }
+
+        async fn will_create_files(
+            &self,
+            params: CreateFilesParams,
+        ) -> Result<Option<WorkspaceEdit>> {
+            let uri = |file: &FileCreate| file.uri.parse().ok();
+            let results = self.split(params.files, uri, |server, files| async move {
+                server.will_create_files(CreateFilesParams { files }).await
+            });
+            let edits = first_error(results.await)?;
+            Ok(merge_edits(edits.into_iter().flatten().collect()))
+        }
+
+        async fn did_create_files(&self, params: CreateFilesParams) {
+            let uri = |file: &FileCreate| file.uri.parse().ok();
+            self.split(params.files, uri, |server, files| async move {
+                server.did_create_files(CreateFilesParams { files }).await;
+            })
+            .await;
+        }
+
+        async fn will_rename_files(
+            &self,
+            params: RenameFilesParams,
+        ) -> Result<Option<WorkspaceEdit>> {
+            let uri = |file: &FileRename| file.old_uri.parse().ok();
+            let results = self.split(params.files, uri, |server, files| async move {
+                server.will_rename_files(RenameFilesParams { files }).await
+            });
+            let edits = first_error(results.await)?;
+            Ok(merge_edits(edits.into_iter().flatten().collect()))
+        }
+
+        async fn did_rename_files(&self, params: RenameFilesParams) {
+            let uri = |file: &FileRename| file.old_uri.parse().ok();
+            self.split(params.files, uri, |server, files| async move {
+                server.did_rename_files(RenameFilesParams { files }).await;
+            })
+            .await;
+        }
+
+        async fn will_delete_files(
+            &self,
+            params: DeleteFilesParams,
+        ) -> Result<Option<WorkspaceEdit>> {
+            let uri = |file: &FileDelete| file.uri.parse().ok();
+            let results = self.split(params.files, uri, |server, files| async move {
+                server.will_delete_files(DeleteFilesParams { files }).await
+            });
+            let edits = first_error(results.await)?;
+            Ok(merge_edits(edits.into_iter().flatten().collect()))
+        }
+
+        async fn did_delete_files(&self, params: DeleteFilesParams) {
+            let uri = |file: &FileDelete| file.uri.parse().ok();
+            self.split(params.files, uri, |server, files| async move {
+                server.did_delete_files(DeleteFilesParams { files }).await;
+            })
+            .await;
+        }
+
+        async fn symbol(
+            &self,
+            params: WorkspaceSymbolParams,
+        ) -> Result<Option<Vec<SymbolInformation>>> {
+            let params = &params;
+            let results =
+                self.broadcast(|server| async move { server.symbol(params.clone()).await });
+            let symbols = first_error(results.await)?;
+            Ok(symbols.into_iter().flatten().reduce(|mut a, mut b| {
+                a.append(&mut b);
+                a
+            }))
+        }
+
+        async fn symbol_resolve(&self, params: WorkspaceSymbol) -> Result<WorkspaceSymbol> {
+            let uri = match &params.location {
+                OneOf::Left(location) => location.uri.clone(),
+                OneOf::Right(location) => location.uri.clone(),
+            };
+            self.route(&uri, |server| async move { server.symbol_resolve(params).await })
+                .await
+        }
+
+        async fn workspace_diagnostic(
+            &self,
+            params: WorkspaceDiagnosticParams,
+        ) -> Result<WorkspaceDiagnosticReportResult> {
+            let params = &params;
+            let results = self.broadcast(|server| async move {
+                server.workspace_diagnostic(params.clone()).await
+            });
+
+            let mut items = Vec::new();
+            for result in first_error(results.await)? {
+                match result {
+                    WorkspaceDiagnosticReportResult::Report(report) => items.extend(report.items),
+                    WorkspaceDiagnosticReportResult::Partial(partial) => {
+                        items.extend(partial.items)
+                    }
+                }
+            }
+
+            Ok(WorkspaceDiagnosticReportResult::Report(
+                WorkspaceDiagnosticReport { items },
+            ))
+        }
+
+        async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+            let uri = params
+                .arguments
+                .first()
+                .and_then(|arg| arg.as_str()?.parse().ok());
+            let folder = match uri {
+                Some(uri) => self.owner(&uri),
+                None => self.all().into_iter().next(),
+            };
+
+            match folder {
+                Some(folder) => folder.server.execute_command(params).await,
+                None => Err(Error::internal_error()),
+            }
+        }
+
+        async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+            let uri = params.text_document_position.text_document.uri.clone();
+            let completion = |server: Arc<S>| async move { server.completion(params).await };
+            self.tagged(&uri, completion, |res, tag| match res {
+                Some(CompletionResponse::Array(items)) => {
+                    items.iter_mut().for_each(|i| tag(&mut i.data))
+                }
+                Some(CompletionResponse::List(list)) => {
+                    list.items.iter_mut().for_each(|i| tag(&mut i.data))
+                }
+                None => {}
+            })
+            .await
+        }
+
+        async fn completion_resolve(&self, mut params: CompletionItem) -> Result<CompletionItem> {
+            let data = params.data.take();
+            self.resolve(data, |server, data| async move {
+                params.data = data;
+                server.completion_resolve(params).await
+            })
+            .await
+        }
+
+        async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+            let uri = params.text_document.uri.clone();
+            let code_lens = |server: Arc<S>| async move { server.code_lens(params).await };
+            self.tagged(&uri, code_lens, |res, tag| {
+                res.iter_mut().flatten().for_each(|l| tag(&mut l.data))
+            })
+            .await
+        }
+
+        async fn code_lens_resolve(&self, mut params: CodeLens) -> Result<CodeLens> {
+            let data = params.data.take();
+            self.resolve(data, |server, data| async move {
+                params.data = data;
+                server.code_lens_resolve(params).await
+            })
+            .await
+        }
+
+        async fn document_link(
+            &self,
+            params: DocumentLinkParams,
+        ) -> Result<Option<Vec<DocumentLink>>> {
+            let uri = params.text_document.uri.clone();
+            let document_link = |server: Arc<S>| async move { server.document_link(params).await };
+            self.tagged(&uri, document_link, |res, tag| {
+                res.iter_mut().flatten().for_each(|l| tag(&mut l.data))
+            })
+            .await
+        }
+
+        async fn document_link_resolve(&self, mut params: DocumentLink) -> Result<DocumentLink> {
+            let data = params.data.take();
+            self.resolve(data, |server, data| async move {
+                params.data = data;
+                server.document_link_resolve(params).await
+            })
+            .await
+        }
+
+        async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+            let uri = params.text_document.uri.clone();
+            let inlay_hint = |server: Arc<S>| async move { server.inlay_hint(params).await };
+            self.tagged(&uri, inlay_hint, |res, tag| {
+                res.iter_mut().flatten().for_each(|h| tag(&mut h.data))
+            })
+            .await
+        }
+
+        async fn inlay_hint_resolve(&self, mut params: InlayHint) -> Result<InlayHint> {
+            let data = params.data.take();
+            self.resolve(data, |server, data| async move {
+                params.data = data;
+                server.inlay_hint_resolve(params).await
+            })
+            .await
+        }
+
+        async fn code_action(
+            &self,
+            params: CodeActionParams,
+        ) -> Result<Option<CodeActionResponse>> {
+            let uri = params.text_document.uri.clone();
+            let code_action = |server: Arc<S>| async move { server.code_action(params).await };
+            self.tagged(&uri, code_action, |res, tag| {
+                for action in res.iter_mut().flatten() {
+                    if let CodeActionOrCommand::CodeAction(action) = action {
+                        tag(&mut action.data);
+                    }
+                }
+            })
+            .await
+        }
+
+        async fn code_action_resolve(&self, mut params: CodeAction) -> Result<CodeAction> {
+            let data = params.data.take();
+            self.resolve(data, |server, data| async move {
+                params.data = data;
+                server.code_action_resolve(params).await
+            })
+            .await
+        }
+    }
+
+    requests {
+        will_save_wait_until(WillSaveTextDocumentParams) -> Option<Vec<TextEdit>>
+            = text_document.uri;
+        goto_declaration(GotoDeclarationParams) -> Option<GotoDeclarationResponse>
+            = text_document_position_params.text_document.uri;
+        goto_definition(GotoDefinitionParams) -> Option<GotoDefinitionResponse>
+            = text_document_position_params.text_document.uri;
+        goto_type_definition(GotoTypeDefinitionParams) -> Option<GotoTypeDefinitionResponse>
+            = text_document_position_params.text_document.uri;
+        goto_implementation(GotoImplementationParams) -> Option<GotoImplementationResponse>
+            = text_document_position_params.text_document.uri;
+        references(ReferenceParams) -> Option<Vec<Location>>
+            = text_document_position.text_document.uri;
+        prepare_call_hierarchy(CallHierarchyPrepareParams) -> Option<Vec<CallHierarchyItem>>
+            = text_document_position_params.text_document.uri;
+        incoming_calls(CallHierarchyIncomingCallsParams) -> Option<Vec<CallHierarchyIncomingCall>>
+            = item.uri;
+        outgoing_calls(CallHierarchyOutgoingCallsParams) -> Option<Vec<CallHierarchyOutgoingCall>>
+            = item.uri;
+        prepare_type_hierarchy(TypeHierarchyPrepareParams) -> Option<Vec<TypeHierarchyItem>>
+            = text_document_position_params.text_document.uri;
+        supertypes(TypeHierarchySupertypesParams) -> Option<Vec<TypeHierarchyItem>> = item.uri;
+        subtypes(TypeHierarchySubtypesParams) -> Option<Vec<TypeHierarchyItem>> = item.uri;
+        document_highlight(DocumentHighlightParams) -> Option<Vec<DocumentHighlight>>
+            = text_document_position_params.text_document.uri;
+        hover(HoverParams) -> Option<Hover> = text_document_position_params.text_document.uri;
+        folding_range(FoldingRangeParams) -> Option<Vec<FoldingRange>> = text_document.uri;
+        selection_range(SelectionRangeParams) -> Option<Vec<SelectionRange>> = text_document.uri;
+        document_symbol(DocumentSymbolParams) -> Option<DocumentSymbolResponse> = text_document.uri;
+        semantic_tokens_full(SemanticTokensParams) -> Option<SemanticTokensResult>
+            = text_document.uri;
+        semantic_tokens_full_delta(SemanticTokensDeltaParams) -> Option<SemanticTokensFullDeltaResult>
+            = text_document.uri;
+        semantic_tokens_range(SemanticTokensRangeParams) -> Option<SemanticTokensRangeResult>
+            = text_document.uri;
+        inline_value(InlineValueParams) -> Option<Vec<InlineValue>> = text_document.uri;
+        moniker(MonikerParams) -> Option<Vec<Moniker>>
+            = text_document_position_params.text_document.uri;
+        diagnostic(DocumentDiagnosticParams) -> DocumentDiagnosticReportResult = text_document.uri;
+        signature_help(SignatureHelpParams) -> Option<SignatureHelp>
+            = text_document_position_params.text_document.uri;
+        document_color(DocumentColorParams) -> Vec<ColorInformation> = text_document.uri;
+        color_presentation(ColorPresentationParams) -> Vec<ColorPresentation> = text_document.uri;
+        formatting(DocumentFormattingParams) -> Option<Vec<TextEdit>> = text_document.uri;
+        range_formatting(DocumentRangeFormattingParams) -> Option<Vec<TextEdit>>
+            = text_document.uri;
+        on_type_formatting(DocumentOnTypeFormattingParams) -> Option<Vec<TextEdit>>
+            = text_document_position.text_document.uri;
+        rename(RenameParams) -> Option<WorkspaceEdit> = text_document_position.text_document.uri;
+        prepare_rename(TextDocumentPositionParams) -> Option<PrepareRenameResponse>
+            = text_document.uri;
+        linked_editing_range(LinkedEditingRangeParams) -> Option<LinkedEditingRanges>
+            = text_document_position_params.text_document.uri;
+    }
+
+    notifications {
+        did_open(DidOpenTextDocumentParams) = text_document.uri;
+        did_change(DidChangeTextDocumentParams) = text_document.uri;
+        will_save(WillSaveTextDocumentParams) = text_document.uri;
+        did_save(DidSaveTextDocumentParams) = text_document.uri;
+        did_close(DidCloseTextDocumentParams) = text_document.uri;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LspService;
+
+    struct Mock {
+        root: Option<Url>,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+            assert_eq!(params.root_uri, self.root);
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+            let root = self.root.as_ref().map_or("none", |uri| uri.path());
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(root.to_owned())),
+                range: None,
+            }))
+        }
+
+        async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
+            let mut item = CompletionItem::new_simple("item".into(), "".into());
+            item.data = self.root.as_ref().map(|uri| json!(uri.path()));
+            Ok(Some(CompletionResponse::Array(vec![item])))
+        }
+
+        async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+            assert_eq!(item.data, self.root.as_ref().map(|uri| json!(uri.path())));
+            item.detail = item.data.as_ref().map(|data| data.to_string());
+            Ok(item)
+        }
+
+        async fn symbol(&self, _: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+            #[allow(deprecated)]
+            let symbol = SymbolInformation {
+                name: self
+                    .root
+                    .as_ref()
+                    .map_or("none", |uri| uri.path())
+                    .to_owned(),
+                kind: SymbolKind::FILE,
+                tags: None,
+                deprecated: None,
+                location: Location::new(self.root.clone().unwrap(), Range::default()),
+                container_name: None,
+            };
+            Ok(Some(vec![symbol]))
+        }
+    }
+
+    fn folder(path: &str) -> WorkspaceFolder {
+        WorkspaceFolder {
+            uri: Url::parse(&format!("file://{path}")).unwrap(),
+            name: path.trim_start_matches('/').into(),
+        }
+    }
+
+    fn position(path: &str) -> TextDocumentPositionParams {
+        let uri = Url::parse(&format!("file://{path}")).unwrap();
+        TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri), Position::default())
+    }
+
+    async fn hover(server: &PerFolder<Mock>, path: &str) -> String {
+        let params = HoverParams {
+            text_document_position_params: position(path),
+            work_done_progress_params: Default::default(),
+        };
+
+        match server.hover(params).await.unwrap().unwrap().contents {
+            HoverContents::Scalar(MarkedString::String(root)) => root,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_by_folder() {
+        let (service, _) = LspService::new(|client| {
+            PerFolder::new(client, |_, folder| Mock {
+                root: folder.map(|f| f.uri),
+            })
+        });
+        let server = service.inner();
+
+        let params = InitializeParams {
+            workspace_folders: Some(vec![folder("/a"), folder("/a/nested"), folder("/b")]),
+            ..Default::default()
+        };
+        let result = server.initialize(params).await.unwrap();
+        assert!(result.capabilities.workspace.is_some());
+        assert_eq!(server.backends().len(), 3);
+
+        assert_eq!(hover(&server, "/a/main.rs").await, "/a");
+        assert_eq!(hover(&server, "/a/nested/main.rs").await, "/a/nested");
+        assert_eq!(hover(&server, "/ab/main.rs").await, "/a");
+        assert_eq!(hover(&server, "/b/main.rs").await, "/b");
+
+        let params = WorkspaceSymbolParams::default();
+        let symbols = server.symbol(params).await.unwrap().unwrap();
+        let names: Vec<_> = symbols.into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["/a", "/a/nested", "/b"]);
+
+        let event = WorkspaceFoldersChangeEvent {
+            added: vec![folder("/c")],
+            removed: vec![folder("/a/nested")],
+        };
+        let params = DidChangeWorkspaceFoldersParams { event };
+        server.did_change_workspace_folders(params).await;
+        assert_eq!(hover(&server, "/a/nested/main.rs").await, "/a");
+        assert_eq!(hover(&server, "/c/main.rs").await, "/c");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn resolves_with_originating_backend() {
+        let (service, _) = LspService::new(|client| {
+            PerFolder::new(client, |_, folder| Mock {
+                root: folder.map(|f| f.uri),
+            })
+        });
+        let server = service.inner();
+
+        let params = InitializeParams {
+            workspace_folders: Some(vec![folder("/a"), folder("/b")]),
+            ..Default::default()
+        };
+        server.initialize(params).await.unwrap();
+
+        let params = CompletionParams {
+            text_document_position: position("/b/main.rs"),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let item = match server.completion(params).await.unwrap() {
+            Some(CompletionResponse::Array(mut items)) => items.remove(0),
+            _ => unreachable!(),
+        };
+        assert_ne!(item.data, Some(json!("/b")));
+
+        let item = server.completion_resolve(item).await.unwrap();
+        assert_eq!(item.data, Some(json!("/b")));
+        assert_eq!(item.detail.as_deref(), Some("\"/b\""));
+    }
+
+    #[test]
+    fn merges_workspace_edits() {
+        let uri = Url::parse("file:///a/main.rs").unwrap();
+        let edit = |text: &str| {
+            let changes = HashMap::from([(
+                uri.clone(),
+                vec![TextEdit::new(Range::default(), text.into())],
+            )]);
+            WorkspaceEdit::new(changes)
+        };
+
+        let merged = merge_edits(vec![edit("foo"), edit("bar")]).unwrap();
+        assert_eq!(merged.changes.unwrap()[&uri].len(), 2);
+        assert_eq!(merge_edits(Vec::new()), None);
+    }
+}
@@ -0,0 +1,308 @@
+//! Validation of positions and ranges sent by the client against the document content.
+//!
+//! Positions in the Language Server Protocol count lines from zero and characters in UTF-16 code
+//! units, which is also the fallback encoding when no other [position encoding] was negotiated.
+//! Clients routinely send positions which do not exist in the server's copy of a document, e.g.
+//! when a request races with a `textDocument/didChange` notification, and slicing a string with an
+//! unchecked offset is a common source of panics in language servers.
+//!
+//! The functions in this module convert positions into byte offsets, either failing with a
+//! [`PositionError`] or clamping out-of-bounds positions into the document, as the specification
+//! prescribes for characters past the end of a line. Lines may be terminated by `\n`, `\r\n` or
+//! `\r`.
+//!
+//! [position encoding]: https://microsoft.github.io/language-server-protocol/specification#positionEncodingKind
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::lsp_types::{Position, Range};
+//! use tower_lsp::position::{self, PositionError};
+//!
+//! let text = "let x = \"😀\";\nx\n";
+//!
+//! // The emoji is two UTF-16 code units long, but four bytes long.
+//! assert_eq!(position::offset_at(text, Position::new(0, 11)), Ok(13));
+//! assert_eq!(
+//!     position::offset_at(text, Position::new(0, 10)),
+//!     Err(PositionError::SplitsCharacter { position: Position::new(0, 10) }),
+//! );
+//!
+//! let range = Range::new(Position::new(1, 0), Position::new(1, 99));
+//! let clamped = position::clamp_range(text, range);
+//! assert_eq!(clamped.end, Position::new(1, 1));
+//! assert_eq!(&text[position::offsets_of(text, clamped).unwrap()], "x");
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use std::ops;
+
+use lsp_types::{Position, Range};
+
+use crate::jsonrpc::Error;
+
+/// Error returned for positions and ranges which do not exist in a document.
+///
+/// Converting it into a [`jsonrpc::Error`](crate::jsonrpc::Error) yields an `invalid params`
+/// error, which handlers can return with the `?` operator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PositionError {
+    /// The line is past the end of the document, which has `line_count` lines.
+    LineOutOfBounds {
+        /// The line requested by the client.
+        line: u32,
+        /// The number of lines in the document.
+        line_count: u32,
+    },
+    /// The character is past the end of its line, which is `line_len` UTF-16 code units long.
+    CharacterOutOfBounds {
+        /// The position requested by the client.
+        position: Position,
+        /// The length of the line, in UTF-16 code units.
+        line_len: u32,
+    },
+    /// The character points between the two halves of a UTF-16 surrogate pair.
+    SplitsCharacter {
+        /// The position requested by the client.
+        position: Position,
+    },
+    /// The end of the range comes before its start.
+    InvertedRange {
+        /// The range requested by the client.
+        range: Range,
+    },
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PositionError::LineOutOfBounds { line, line_count } => write!(
+                f,
+                "line {} is out of bounds, the document has {} lines",
+                line, line_count
+            ),
+            PositionError::CharacterOutOfBounds { position, line_len } => write!(
+                f,
+                "character {} is out of bounds, line {} is {} UTF-16 code units long",
+                position.character, position.line, line_len
+            ),
+            PositionError::SplitsCharacter { position } => write!(
+                f,
+                "position {}:{} splits a UTF-16 surrogate pair",
+                position.line, position.character
+            ),
+            PositionError::InvertedRange { range } => write!(
+                f,
+                "range ends at {}:{} before it starts at {}:{}",
+                range.end.line, range.end.character, range.start.line, range.start.character
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl From<PositionError> for Error {
+    fn from(err: PositionError) -> Self {
+        Error::invalid_params(err.to_string())
+    }
+}
+
+/// Returns the byte offset of `position` in `text`.
+pub fn offset_at(text: &str, position: Position) -> Result<usize, PositionError> {
+    let resolved = resolve(text, position);
+    match resolved.error {
+        Some(err) => Err(err),
+        None => Ok(resolved.offset),
+    }
+}
+
+/// Returns the byte range covered by `range` in `text`.
+pub fn offsets_of(text: &str, range: Range) -> Result<ops::Range<usize>, PositionError> {
+    let start = offset_at(text, range.start)?;
+    let end = offset_at(text, range.end)?;
+    if end < start {
+        return Err(PositionError::InvertedRange { range });
+    }
+
+    Ok(start..end)
+}
+
+/// Returns the position closest to `position` which exists in `text`.
+///
+/// Characters past the end of a line are moved to the end of the line, lines past the end of the
+/// document to the end of the document, and positions splitting a surrogate pair to the start of
+/// the pair.
+pub fn clamp_position(text: &str, position: Position) -> Position {
+    resolve(text, position).position
+}
+
+/// Returns the range closest to `range` which exists in `text`.
+///
+/// Both ends are clamped with [`clamp_position`]. An inverted range is collapsed to its start.
+pub fn clamp_range(text: &str, range: Range) -> Range {
+    let start = resolve(text, range.start);
+    let end = resolve(text, range.end);
+    if end.offset < start.offset {
+        Range::new(start.position, start.position)
+    } else {
+        Range::new(start.position, end.position)
+    }
+}
+
+/// A position resolved against a document, clamped into it if needed.
+struct Resolved {
+    offset: usize,
+    position: Position,
+    error: Option<PositionError>,
+}
+
+fn resolve(text: &str, position: Position) -> Resolved {
+    let mut line_count = 0;
+    let mut last_line = 0..0;
+    for (line, range) in (0..).zip(line_ranges(text)) {
+        if line == position.line {
+            return resolve_in_line(&text[range.clone()], range.start, position);
+        }
+
+        line_count += 1;
+        last_line = range;
+    }
+
+    let line_len = utf16_len(&text[last_line.clone()]);
+    Resolved {
+        offset: last_line.end,
+        position: Position::new(line_count - 1, line_len),
+        error: Some(PositionError::LineOutOfBounds {
+            line: position.line,
+            line_count,
+        }),
+    }
+}
+
+fn resolve_in_line(line: &str, line_start: usize, position: Position) -> Resolved {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units == position.character {
+            return Resolved {
+                offset: line_start + i,
+                position,
+                error: None,
+            };
+        }
+
+        let next = units + c.len_utf16() as u32;
+        if position.character < next {
+            return Resolved {
+                offset: line_start + i,
+                position: Position::new(position.line, units),
+                error: Some(PositionError::SplitsCharacter { position }),
+            };
+        }
+
+        units = next;
+    }
+
+    let error = (units != position.character).then_some(PositionError::CharacterOutOfBounds {
+        position,
+        line_len: units,
+    });
+
+    Resolved {
+        offset: line_start + line.len(),
+        position: Position::new(position.line, units),
+        error,
+    }
+}
+
+/// Returns the byte range of every line in `text`, excluding line terminators.
+fn line_ranges(text: &str) -> impl Iterator<Item = ops::Range<usize>> + '_ {
+    let bytes = text.as_bytes();
+    let mut next_start = Some(0);
+    std::iter::from_fn(move || {
+        let start = next_start?;
+        match bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n' || b == b'\r')
+        {
+            Some(len) => {
+                let end = start + len;
+                let crlf = bytes[end] == b'\r' && bytes.get(end + 1) == Some(&b'\n');
+                next_start = Some(if crlf { end + 2 } else { end + 1 });
+                Some(start..end)
+            }
+            None => {
+                next_start = None;
+                Some(start..bytes.len())
+            }
+        }
+    })
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "a😀b\r\nsecond\rthird\n";
+
+    #[test]
+    fn finds_offsets() {
+        assert_eq!(offset_at(TEXT, Position::new(0, 0)), Ok(0));
+        assert_eq!(offset_at(TEXT, Position::new(0, 3)), Ok(5));
+        assert_eq!(offset_at(TEXT, Position::new(0, 4)), Ok(6));
+        assert_eq!(offset_at(TEXT, Position::new(1, 6)), Ok(14));
+        assert_eq!(offset_at(TEXT, Position::new(2, 0)), Ok(15));
+        assert_eq!(offset_at(TEXT, Position::new(3, 0)), Ok(TEXT.len()));
+        assert_eq!(offset_at("", Position::new(0, 0)), Ok(0));
+
+        let range = Range::new(Position::new(1, 0), Position::new(2, 5));
+        assert_eq!(&TEXT[offsets_of(TEXT, range).unwrap()], "second\rthird");
+    }
+
+    #[test]
+    fn reports_invalid_positions() {
+        let position = Position::new(0, 2);
+        let err = offset_at(TEXT, position).unwrap_err();
+        assert_eq!(err, PositionError::SplitsCharacter { position });
+
+        let position = Position::new(1, 7);
+        let err = offset_at(TEXT, position).unwrap_err();
+        assert_eq!(
+            err,
+            PositionError::CharacterOutOfBounds {
+                position,
+                line_len: 6
+            }
+        );
+
+        let err = offset_at(TEXT, Position::new(4, 0)).unwrap_err();
+        let expected = PositionError::LineOutOfBounds {
+            line: 4,
+            line_count: 4,
+        };
+        assert_eq!(err, expected);
+        assert_eq!(Error::from(err).message, expected.to_string());
+
+        let range = Range::new(Position::new(1, 1), Position::new(1, 0));
+        let err = offsets_of(TEXT, range).unwrap_err();
+        assert_eq!(err, PositionError::InvertedRange { range });
+    }
+
+    #[test]
+    fn clamps_invalid_positions() {
+        let clamp = |line, character| clamp_position(TEXT, Position::new(line, character));
+        assert_eq!(clamp(0, 2), Position::new(0, 1));
+        assert_eq!(clamp(1, 99), Position::new(1, 6));
+        assert_eq!(clamp(9, 9), Position::new(3, 0));
+        assert_eq!(clamp(2, 3), Position::new(2, 3));
+
+        let range = Range::new(Position::new(1, 4), Position::new(1, 1));
+        let collapsed = Range::new(Position::new(1, 4), Position::new(1, 4));
+        assert_eq!(clamp_range(TEXT, range), collapsed);
+    }
+}
@@ -0,0 +1,32 @@
+//! Convenience re-exports of the items needed by most language servers.
+//!
+//! This brings the [`LanguageServer`] trait, the types required to serve it, and all of
+//! [`lsp_types`] into scope with a single glob import.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use tower_lsp::prelude::*;
+//!
+//! #[derive(Debug)]
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! #[async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let (service, socket) = LspService::new(|client| Backend { client });
+//! ```
+
+pub use crate::jsonrpc::{Error, Result};
+pub use crate::lsp_types::*;
+pub use crate::{async_trait, Client, LanguageServer, LspService, Server};
@@ -0,0 +1,415 @@
+//! Serving JSON-RPC protocols with the same lifecycle as LSP, such as the Build Server Protocol.
+//!
+//! Several protocols, most notably the [Build Server Protocol], are structurally identical to LSP:
+//! an initialization handshake, requests and notifications in both directions, cancellation, and a
+//! `shutdown` request followed by an `exit` notification, only under different method names. This
+//! module serves such protocols with the router, lifecycle semantics, [`Client`] and
+//! [`transport::Server`] of this crate, parameterized by a [`MethodTable`] naming the lifecycle
+//! methods.
+//!
+//! [Build Server Protocol]: https://build-server-protocol.github.io/
+//! [`transport::Server`]: crate::transport::Server
+//!
+//! Protocols are declared as traits annotated with [`macro@jsonrpc_protocol`], which generates an
+//! implementation of [`Protocol`] registering each method of the trait, and served with
+//! [`ProtocolService`]. Messages are sent to the client with [`Client::send_request`] and
+//! [`Client::send_notification`], using types implementing the `lsp_types` request and
+//! notification traits.
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde_json::Value;
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::protocol::{jsonrpc_protocol, ProtocolService};
+//! use tower_lsp::Client;
+//!
+//! #[jsonrpc_protocol(methods = tower_lsp::protocol::MethodTable::BSP)]
+//! #[tower_lsp::async_trait]
+//! pub trait BuildServer: Send + Sync + 'static {
+//!     #[rpc(name = "build/initialize")]
+//!     async fn initialize(&self, params: Value) -> Result<Value>;
+//!
+//!     #[rpc(name = "build/initialized")]
+//!     async fn initialized(&self, params: Value);
+//!
+//!     #[rpc(name = "build/shutdown")]
+//!     async fn shutdown(&self) -> Result<()>;
+//!
+//!     #[rpc(name = "workspace/buildTargets")]
+//!     async fn build_targets(&self) -> Result<Value>;
+//! }
+//!
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl BuildServer for Backend {
+//!     // ...
+//! #   async fn initialize(&self, _: Value) -> Result<Value> {
+//! #       Ok(Value::Null)
+//! #   }
+//! #
+//! #   async fn initialized(&self, _: Value) {}
+//! #
+//! #   async fn shutdown(&self) -> Result<()> {
+//! #       Ok(())
+//! #   }
+//! #
+//! #   async fn build_targets(&self) -> Result<Value> {
+//! #       Ok(Value::Null)
+//! #   }
+//! }
+//!
+//! # #[cfg(feature = "runtime-tokio")]
+//! # async fn run() {
+//! use tower_lsp::protocol::MethodTable;
+//! use tower_lsp::Server;
+//!
+//! let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+//!
+//! let (service, socket) = ProtocolService::new(BuildServerProtocol, |client| Backend { client });
+//! Server::new(stdin, stdout, socket)
+//!     .method_table(MethodTable::BSP)
+//!     .serve(service)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! [`Client`]: crate::Client
+//! [`Client::send_request`]: crate::Client::send_request
+//! [`Client::send_notification`]: crate::Client::send_notification
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Ready;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower::layer::util::{Identity, Stack};
+use tower::{Layer, Service};
+
+use crate::jsonrpc::{FromParams, IntoResponse, Method, MethodHandler, Request, Response, Router};
+use crate::service::{layers, Pending, ServerState, State};
+use crate::{Client, ClientSocket, ExitedError};
+
+/// Attribute macro implementing [`Protocol`] for a trait declaring the methods of a protocol.
+///
+/// Each method of the trait must carry an `#[rpc(name = "...")]` attribute with its JSON-RPC
+/// method name. Like [`LanguageServer`](crate::LanguageServer) methods, requests take `&self` and
+/// optional params, and return a [`jsonrpc::Result`](crate::jsonrpc::Result), while
+/// notifications return nothing. The lifecycle methods of the [`MethodTable`] given by the
+/// `methods` key, which defaults to [`MethodTable::LSP`], are recognized by their name.
+///
+/// The trait must be `Send + Sync + 'static`. For a trait named `Foo`, the macro generates a unit struct named `FooProtocol`, with the same
+/// visibility as the trait, which implements [`Protocol`] for every implementation of `Foo`. It
+/// is passed to [`ProtocolService::new`] to serve a backend.
+///
+/// Extra middleware, such as caching or metrics, can be applied to a method with the `layer` key,
+/// e.g. `#[rpc(name = "workspace/buildTargets", layer = "CacheLayer::new")]`. The key holds the
+/// path of a function taking no arguments and returning a [`tower::Layer`], see
+/// [`ProtocolRouter::method_with_layer`].
+///
+/// The generated code refers to this crate as `::tower_lsp`, which can be overridden with a
+/// `crate = "..."` key, like with [`LspExtension`](crate::LspExtension).
+pub use tower_lsp_macros::jsonrpc_protocol;
+
+/// Names of the lifecycle methods of a protocol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MethodTable {
+    /// The request initializing the session, the only one accepted before initialization.
+    pub initialize: &'static str,
+    /// The request shutting the server down, after which only `exit` is accepted.
+    pub shutdown: &'static str,
+    /// The notification stopping the server.
+    pub exit: &'static str,
+    /// The notification canceling a pending request.
+    pub cancel_request: &'static str,
+}
+
+impl MethodTable {
+    /// The lifecycle methods of the Language Server Protocol.
+    pub const LSP: MethodTable = MethodTable {
+        initialize: "initialize",
+        shutdown: "shutdown",
+        exit: "exit",
+        cancel_request: "$/cancelRequest",
+    };
+
+    /// The lifecycle methods of the [Build Server Protocol](https://build-server-protocol.github.io/).
+    pub const BSP: MethodTable = MethodTable {
+        initialize: "build/initialize",
+        shutdown: "build/shutdown",
+        exit: "build/exit",
+        cancel_request: "$/cancelRequest",
+    };
+}
+
+impl Default for MethodTable {
+    fn default() -> Self {
+        MethodTable::LSP
+    }
+}
+
+/// A JSON-RPC protocol served by backends of type `S`.
+///
+/// This is usually implemented with the [`macro@jsonrpc_protocol`] attribute macro.
+pub trait Protocol<S> {
+    /// Returns the names of the lifecycle methods of the protocol.
+    fn methods(&self) -> MethodTable;
+
+    /// Registers the methods of the protocol on `router`.
+    fn register(&self, router: &mut ProtocolRouter<S>);
+}
+
+/// Router on which the methods of a [`Protocol`] are registered.
+///
+/// Registered methods follow the lifecycle of LSP methods: only the `initialize` method of the
+/// [`MethodTable`] is accepted before initialization, and none but `exit` after the `shutdown`
+/// method was called.
+pub struct ProtocolRouter<S> {
+    inner: Router<S, ExitedError>,
+    methods: MethodTable,
+    state: Arc<ServerState>,
+    pending: Arc<Pending>,
+}
+
+impl<S: Send + Sync + 'static> ProtocolRouter<S> {
+    /// Registers a request or notification handler for the method `name`.
+    ///
+    /// Handlers have the same signatures as those registered with
+    /// [`LspServiceBuilder::custom_method`](crate::LspServiceBuilder::custom_method).
+    pub fn method<P, R, F>(&mut self, name: &'static str, callback: F) -> &mut Self
+    where
+        P: FromParams,
+        R: IntoResponse,
+        F: for<'a> Method<&'a S, P, R> + Clone + Send + Sync + 'static,
+    {
+        self.method_with_layer(name, callback, Identity::new())
+    }
+
+    /// Registers a handler for the method `name` like [`ProtocolRouter::method`], wrapped in the
+    /// middleware `layer`.
+    ///
+    /// The middleware runs after the lifecycle checks, so it only sees the messages which reach
+    /// the handler.
+    pub fn method_with_layer<P, R, F, L>(
+        &mut self,
+        name: &'static str,
+        callback: F,
+        layer: L,
+    ) -> &mut Self
+    where
+        P: FromParams,
+        R: IntoResponse,
+        F: for<'a> Method<&'a S, P, R> + Clone + Send + Sync + 'static,
+        L: Layer<MethodHandler<P, R, ExitedError>>,
+        L::Service:
+            Service<Request, Response = Option<Response>, Error = ExitedError> + Send + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let (state, pending) = (self.state.clone(), self.pending.clone());
+        if name == self.methods.initialize {
+            let layer = Stack::new(layer, layers::Initialize::new(state, pending));
+            self.inner.method(name, callback, layer);
+        } else if name == self.methods.shutdown {
+            let layer = Stack::new(layer, layers::Shutdown::new(state, pending));
+            self.inner.method(name, callback, layer);
+        } else {
+            let layer = Stack::new(layer, layers::Normal::new(state, pending));
+            self.inner.method(name, callback, layer);
+        }
+
+        self
+    }
+}
+
+impl<S: Debug> Debug for ProtocolRouter<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ProtocolRouter")
+            .field("inner", &self.inner)
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Service abstraction for protocols other than LSP.
+///
+/// This is the counterpart of [`LspService`](crate::LspService) for backends of a [`Protocol`],
+/// without the features specific to LSP.
+pub struct ProtocolService<S> {
+    inner: Router<S, ExitedError>,
+    state: Arc<ServerState>,
+}
+
+impl<S: Send + Sync + 'static> ProtocolService<S> {
+    /// Creates a new `ProtocolService` serving `protocol` with the given backend, also returning a
+    /// channel for server-to-client communication.
+    pub fn new<P, F>(protocol: P, init: F) -> (Self, ClientSocket)
+    where
+        P: Protocol<S>,
+        F: FnOnce(Client) -> S,
+    {
+        let state = Arc::new(ServerState::new());
+        let pending = Arc::new(Pending::new());
+        let (client, socket) = Client::new(state.clone());
+        let methods = protocol.methods();
+
+        let mut router = ProtocolRouter {
+            inner: Router::new(init(client.clone())),
+            methods,
+            state: state.clone(),
+            pending: pending.clone(),
+        };
+        protocol.register(&mut router);
+
+        let mut inner = router.inner;
+        let p = pending.clone();
+        inner.method(
+            methods.cancel_request,
+            move |_: &S, params| cancel_request(params, &p),
+            tower::layer::util::Identity::new(),
+        );
+        inner.method(
+            methods.exit,
+            |_: &S| std::future::ready(()),
+            layers::Exit::new(state.clone(), pending, client),
+        );
+
+        (ProtocolService { inner, state }, socket)
+    }
+
+    /// Returns a shared reference to the inner server.
+    pub fn inner(&self) -> Arc<S> {
+        self.inner.inner()
+    }
+
+    /// Returns the current state of the server.
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+}
+
+fn cancel_request(params: lsp_types::CancelParams, pending: &Pending) -> Ready<()> {
+    pending.cancel(&params.id.into());
+    std::future::ready(())
+}
+
+impl<S: Send + Sync + 'static> Service<Request> for ProtocolService<S> {
+    type Response = Option<Response>;
+    type Error = ExitedError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.state.poll_initialized(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        match self.state.get() {
+            State::Exited => Poll::Ready(Err(ExitedError(()))),
+            _ => self.inner.poll_ready(cx),
+        }
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+impl<S: Debug> Debug for ProtocolService<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ProtocolService")
+            .field("inner", &self.inner)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::{json, Value};
+    use tower::util::MapRequestLayer;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::jsonrpc::{not_initialized_error, Result};
+
+    #[jsonrpc_protocol(crate = "crate", methods = MethodTable::BSP)]
+    #[crate::async_trait]
+    trait BuildServer: Send + Sync + 'static {
+        #[rpc(name = "build/initialize")]
+        async fn initialize(&self, params: Value) -> Result<Value>;
+
+        #[rpc(name = "build/shutdown")]
+        async fn shutdown(&self) -> Result<()>;
+
+        #[rpc(name = "workspace/buildTargets", layer = "count_calls")]
+        async fn build_targets(&self) -> Result<Value>;
+    }
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_calls() -> MapRequestLayer<fn(Request) -> Request> {
+        MapRequestLayer::new(|req| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            req
+        })
+    }
+
+    #[derive(Debug)]
+    struct Mock;
+
+    #[crate::async_trait]
+    impl BuildServer for Mock {
+        async fn initialize(&self, params: Value) -> Result<Value> {
+            Ok(json!({ "displayName": "mock", "rootUri": params["rootUri"] }))
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn build_targets(&self) -> Result<Value> {
+            Ok(json!({ "targets": [] }))
+        }
+    }
+
+    async fn call(service: &mut ProtocolService<Mock>, req: Request) -> Option<Response> {
+        service.ready().await.unwrap().call(req).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_protocol_lifecycle() {
+        let (mut service, _) = ProtocolService::new(BuildServerProtocol, |_| Mock);
+
+        let targets = Request::build("workspace/buildTargets").id(1).finish();
+        let res = call(&mut service, targets).await.unwrap();
+        assert_eq!(res.error(), Some(&not_initialized_error()));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+
+        let params = json!({ "rootUri": "file:///project" });
+        let initialize = Request::build("build/initialize")
+            .params(params)
+            .id(2)
+            .finish();
+        let res = call(&mut service, initialize).await.unwrap();
+        assert_eq!(res.result().unwrap()["rootUri"], "file:///project");
+        assert_eq!(service.state(), State::Initialized);
+
+        let targets = Request::build("workspace/buildTargets").id(3).finish();
+        let res = call(&mut service, targets).await.unwrap();
+        assert_eq!(res.result(), Some(&json!({ "targets": [] })));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+        let shutdown = Request::build("build/shutdown").id(4).finish();
+        assert!(call(&mut service, shutdown).await.unwrap().is_ok());
+
+        let exit = Request::build("build/exit").finish();
+        assert_eq!(call(&mut service, exit).await, None);
+        assert_eq!(service.ready().await.unwrap_err(), ExitedError(()));
+    }
+}
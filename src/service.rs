@@ -1,32 +1,92 @@
 //! Service abstraction for language servers.
 
-pub use self::client::{progress, Client, ClientSocket, RequestStream, ResponseSink};
+pub use self::auth::{Auth, AuthLayer};
+pub use self::client::{
+    progress, CapabilityRegistration, Client, ClientSocket, DetachedRequest, RequestIdStrategy,
+    RequestStream, ResponseSink, RetryPolicy, RetryingClient, WorkspaceDiagnosticStream,
+};
+pub use self::concurrency::ConcurrencyLimit;
+pub use self::correlation::CorrelationIdPolicy;
+pub use self::describe::{MethodDescription, ServerDescription};
+pub use self::extensions::Extensions;
+pub use self::hooks::{InitializeHook, InitializeRejection, ShutdownHook};
+pub use self::invalid_params::InvalidParamsPolicy;
+pub use self::method_context::MethodContext;
+pub use self::metrics::{MethodMetrics, MetricsSnapshot};
+pub use self::pending::Cancellation;
+pub use self::request_context::RequestContext;
+pub use self::response_limit::ResponseSizePolicy;
+pub use self::state::{ExitBehavior, State, StateError};
+pub use self::strict::ProtocolViolation;
+pub use self::trace_context::TraceContext;
+pub use self::trust::WorkspaceTrust;
 
 pub(crate) use self::pending::Pending;
-pub(crate) use self::state::{ServerState, State};
+pub(crate) use self::state::ServerState;
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Display, Formatter};
+use std::future::Future;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use futures::future::{self, BoxFuture, FutureExt};
-use serde_json::Value;
-use tower::Service;
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::{Stream, StreamExt};
+use lsp_types::notification::Notification;
+use lsp_types::InitializeParams;
+use serde_json::{json, Value};
+use tower::{Layer, Service};
+use tracing::{warn, Instrument};
 
+use self::cache::ResponseCacheLayer;
+use self::concurrency::ConcurrencyLimits;
+use self::correlation::CorrelationIds;
+use self::hooks::{BoundHooks, Hooks};
+use self::metrics::Metrics;
+use self::response_limit::ResponseLimit;
+use self::stale::{document_uri, StaleRequests};
+use self::strict::Strict;
+use self::subscriptions::Subscriptions;
 use crate::jsonrpc::{
-    Error, ErrorCode, FromParams, IntoResponse, Method, Request, Response, Router,
+    Error, FromParams, InnerMut, IntoResponse, Method, Request, Response, Router,
 };
 use crate::LanguageServer;
+use crate::{completion, symbols};
 
 pub(crate) mod layers;
 
+mod auth;
+mod cache;
 mod client;
+mod concurrency;
+mod correlation;
+mod describe;
+mod dev_mode;
+mod extensions;
+#[cfg(feature = "file-watcher")]
+mod file_watcher;
+mod hooks;
+mod invalid_params;
+mod method_context;
+mod metrics;
 mod pending;
+mod request_context;
+mod response_limit;
+mod stale;
 mod state;
+mod strict;
+mod subscriptions;
+mod trace_context;
+mod trust;
+
+/// Name of the built-in request answered by [`LspServiceBuilder::metrics_endpoint`].
+const METRICS_METHOD: &str = "$/metrics";
 
 /// Error that occurs when attempting to call the language server after it has already exited.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct ExitedError(());
+pub struct ExitedError(pub(crate) ());
 
 impl std::error::Error for ExitedError {}
 
@@ -49,13 +109,42 @@ impl Display for ExitedError {
 ///
 /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
 ///
-/// The service shuts down and stops serving requests after the [`exit`] notification is received.
+/// The service shuts down and stops serving requests after the [`exit`] notification is received,
+/// unless configured otherwise with [`LspServiceBuilder::exit_behavior`].
 ///
 /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+///
+/// # Performance
+///
+/// Notifications are forwarded to their handler without wrapping its future, unless they have
+/// to wait for a [concurrency limit](LspServiceBuilder::concurrency_limit). Besides the
+/// deserialized params and the future of the handler itself, a notification thus costs a single
+/// allocation, which matters for sessions dominated by `textDocument/didChange` traffic.
 #[derive(Debug)]
 pub struct LspService<S> {
     inner: Router<S, ExitedError>,
     state: Arc<ServerState>,
+    pending: Arc<Pending>,
+    strict: Option<Strict>,
+    auth: Option<AuthLayer>,
+    kind_mismatches: usize,
+    metrics: Arc<Metrics>,
+    metrics_endpoint: bool,
+    response_limit: Option<ResponseLimit>,
+    custom_methods: HashSet<&'static str>,
+    subscriptions: Subscriptions,
+    sequencer: Option<Sequencer>,
+    stale_requests: Option<StaleRequests>,
+    response_cache: Option<ResponseCacheLayer>,
+    concurrency_limits: ConcurrencyLimits,
+    correlation_ids: Option<CorrelationIds>,
+    adapt_document_symbols: bool,
+    completion_item_defaults: bool,
+    /// Client flushing the notifications buffered before initialization, if enabled.
+    early_notifications: Option<Client>,
+    /// Client receiving the protocol violations committed by the server, in dev mode.
+    dev_mode: Option<Client>,
+    hooks: Option<BoundHooks>,
 }
 
 impl<S: LanguageServer> LspService<S> {
@@ -76,9 +165,155 @@ impl<S: LanguageServer> LspService<S> {
         F: FnOnce(Client) -> S,
     {
         let state = Arc::new(ServerState::new());
+        let (client, socket) = Client::new(state.clone());
+        let server = init(client.clone());
+        LspService::builder(Router::new(server), state, client, socket)
+    }
+
+    /// Starts building a new `LspService` which serves the LSP methods of `S` along with the
+    /// methods already registered on the [`Router`] returned by `init`.
+    ///
+    /// This allows a single connection to carry several protocols, e.g. the Debug Adapter
+    /// Protocol alongside LSP, by registering the handlers of the other protocol on the router.
+    /// Methods already registered on the router take precedence over the LSP methods of the same
+    /// name. Unlike methods added with [`LspServiceBuilder::custom_method`], they are called
+    /// regardless of the state of the LSP session, unless they are registered with a layer which
+    /// checks it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tower::layer::util::Identity;
+    /// use tower_lsp::jsonrpc::{Result, Router};
+    /// use tower_lsp::lsp_types::*;
+    /// use tower_lsp::{LanguageServer, LspService};
+    ///
+    /// struct Backend;
+    ///
+    /// // Implementation of `LanguageServer` omitted...
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Backend {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// impl Backend {
+    ///     async fn launch(&self, program: String) -> Result<u32> {
+    ///         // ...
+    /// #       let _ = program;
+    /// #       Ok(1)
+    ///     }
+    /// }
+    ///
+    /// let (service, socket) = LspService::build_with_router(|_| {
+    ///     let mut router = Router::new(Backend);
+    ///     router.method("dap/launch", Backend::launch, Identity::new());
+    ///     router
+    /// })
+    /// .finish();
+    /// ```
+    pub fn build_with_router<F>(init: F) -> LspServiceBuilder<S>
+    where
+        F: FnOnce(Client) -> Router<S, ExitedError>,
+    {
+        let state = Arc::new(ServerState::new());
+        let (client, socket) = Client::new(state.clone());
+        let router = init(client.clone());
+        LspService::builder(router, state, client, socket)
+    }
+
+    /// Creates a new `LspService` with a server backend constructed asynchronously, also returning
+    /// a channel for server-to-client communication.
+    ///
+    /// This allows backends to be constructed with async, fallible operations, such as opening a
+    /// database or connecting to a build system, which would otherwise need to happen before the
+    /// `Client` is available, or be deferred to the `initialize` handler. If `init` fails, its error
+    /// is returned before anything is served.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::io;
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{Client, LanguageServer, LspService};
+    /// #
+    /// struct Backend {
+    ///     client: Client,
+    ///     index: Index,
+    /// }
+    ///
+    /// struct Index;
+    ///
+    /// impl Index {
+    ///     async fn open(path: &str) -> io::Result<Self> {
+    ///         // ...
+    /// #       let _ = path;
+    /// #       Ok(Index)
+    ///     }
+    /// }
+    ///
+    /// // Implementation of `LanguageServer` omitted...
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Backend {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// # async fn run() -> io::Result<()> {
+    /// let (service, socket) = LspService::try_new(|client| async move {
+    ///     let index = Index::open("index.db").await?;
+    ///     Ok::<_, io::Error>(Backend { client, index })
+    /// })
+    /// .await?;
+    /// # drop((service, socket));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn try_new<F, Fut, E>(init: F) -> Result<(Self, ClientSocket), E>
+    where
+        F: FnOnce(Client) -> Fut,
+        Fut: Future<Output = Result<S, E>>,
+    {
+        Ok(LspService::try_build(init).await?.finish())
+    }
 
+    /// Starts building a new `LspService` with a server backend constructed asynchronously.
+    ///
+    /// This is the fallible, async counterpart of [`LspService::build`]. See
+    /// [`LspService::try_new`] for details.
+    pub async fn try_build<F, Fut, E>(init: F) -> Result<LspServiceBuilder<S>, E>
+    where
+        F: FnOnce(Client) -> Fut,
+        Fut: Future<Output = Result<S, E>>,
+    {
+        let state = Arc::new(ServerState::new());
         let (client, socket) = Client::new(state.clone());
-        let inner = Router::new(init(client.clone()));
+        let server = init(client.clone()).await?;
+        Ok(LspService::builder(
+            Router::new(server),
+            state,
+            client,
+            socket,
+        ))
+    }
+
+    fn builder(
+        inner: Router<S, ExitedError>,
+        state: Arc<ServerState>,
+        client: Client,
+        socket: ClientSocket,
+    ) -> LspServiceBuilder<S> {
         let pending = Arc::new(Pending::new());
 
         LspServiceBuilder {
@@ -86,18 +321,481 @@ impl<S: LanguageServer> LspService<S> {
                 inner,
                 state.clone(),
                 pending.clone(),
-                client,
+                client.clone(),
             ),
             state,
             pending,
+            client,
             socket,
+            strict: None,
+            auth: None,
+            metrics_endpoint: false,
+            response_limit: None,
+            custom_methods: HashSet::new(),
+            test_mode: false,
+            cancel_stale_requests: false,
+            cached_methods: HashSet::new(),
+            concurrency_limits: crate::generated::CONCURRENCY_LIMITS
+                .iter()
+                .map(|&(method, max, latest_wins)| {
+                    let limit = ConcurrencyLimit::new(max);
+                    (
+                        method,
+                        if latest_wins {
+                            limit.latest_wins()
+                        } else {
+                            limit
+                        },
+                    )
+                })
+                .collect(),
+            correlation_ids: None,
+            buffer_early_notifications: false,
+            dev_mode: false,
+            adapt_document_symbols: false,
+            completion_item_defaults: false,
+            hooks: Hooks::default(),
         }
     }
 
-    /// Returns a reference to the inner server.
-    pub fn inner(&self) -> &S {
+    /// Returns a shared reference to the inner server.
+    pub fn inner(&self) -> Arc<S> {
         self.inner.inner()
     }
+
+    /// Returns exclusive access to the inner server, if no requests are being processed.
+    ///
+    /// This is primarily useful for reconfiguring the server between requests in tests, without
+    /// resorting to interior mutability. Every request or notification holds a shared reference to
+    /// the server while its handler runs, and so does every `Arc` returned by
+    /// [`LspService::inner`], so this method returns `None` while any of them is still alive.
+    pub fn inner_mut(&mut self) -> Option<InnerMut<'_, S>> {
+        self.inner.inner_mut()
+    }
+
+    /// Returns the number of messages received so far whose kind did not match their handler.
+    ///
+    /// This counts requests sent to methods handled as notifications (answered with an
+    /// `Invalid request` error) and notifications sent to methods handled as requests (silently
+    /// ignored). A non-zero count in tests usually points to a [`LspServiceBuilder::custom_method`]
+    /// handler with the wrong return type, or to a client sending the wrong kind of message.
+    pub fn kind_mismatches(&self) -> usize {
+        self.kind_mismatches
+    }
+
+    /// Returns the request metrics recorded so far, grouped by method.
+    ///
+    /// Every request which receives a response is counted, along with its latency from the moment
+    /// it was received until its response was ready. Notifications are not recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::{Request, Result};
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService};
+    /// # use tower::{Service, ServiceExt};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (mut service, _socket) = LspService::new(|_| Mock);
+    ///
+    /// let initialize = Request::build("initialize")
+    ///     .params(serde_json::json!({"capabilities":{}}))
+    ///     .id(1)
+    ///     .finish();
+    /// service.ready().await.unwrap().call(initialize).await.unwrap();
+    ///
+    /// for (method, metrics) in &service.metrics_snapshot() {
+    ///     println!("{method}: {} requests, p99 {:?}", metrics.requests, metrics.p99);
+    /// }
+    /// # }
+    /// ```
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Returns the current lifecycle state of the server.
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+
+    /// Returns the typed storage shared by everything serving this connection.
+    ///
+    /// Middleware wrapping the service can keep a clone of the returned handle and store values
+    /// there, which the server backend can then read with [`Client::extensions`]. See
+    /// [`Extensions`] for more details.
+    pub fn extensions(&self) -> Extensions {
+        self.state.extensions().clone()
+    }
+
+    /// Returns a machine-readable description of every method handled by this service.
+    ///
+    /// Each method is listed with the names of its params and result types, and whether it is a
+    /// custom method. The description serializes to JSON, so servers can offer it to editor
+    /// extension authors, e.g. with a `--describe` command-line flag.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::new(|_| Mock);
+    ///
+    /// if std::env::args().any(|arg| arg == "--describe") {
+    ///     let description = serde_json::to_string_pretty(&service.describe()).unwrap();
+    ///     println!("{description}");
+    ///     return;
+    /// }
+    ///
+    /// let hover = service.describe().get("textDocument/hover").cloned().unwrap();
+    /// assert_eq!(hover.params.as_deref(), Some("HoverParams"));
+    /// assert_eq!(hover.result.as_deref(), Some("Option<Hover>"));
+    /// ```
+    pub fn describe(&self) -> ServerDescription {
+        let mut methods: Vec<_> = self
+            .inner
+            .signatures()
+            .map(|(name, signature)| {
+                MethodDescription::new(
+                    name,
+                    self.inner.is_notification(name) == Some(true),
+                    signature.params,
+                    signature.result,
+                    self.custom_methods.contains(name),
+                )
+            })
+            .collect();
+
+        if self.metrics_endpoint {
+            let result = Some(std::any::type_name::<MetricsSnapshot>());
+            methods.push(MethodDescription::new(
+                METRICS_METHOD,
+                false,
+                None,
+                result,
+                false,
+            ));
+        }
+
+        ServerDescription::new(methods)
+    }
+
+    /// Returns a stream of the params of every `N` notification received from now on.
+    ///
+    /// Notifications are still passed to their handler as usual. This allows parts of the server
+    /// to react to notifications such as `textDocument/didChange` on their own, without going
+    /// through the [`LanguageServer`] implementation. Only notifications which reach their handler
+    /// are yielded, i.e. none are received before the server is initialized, and any whose params
+    /// fail to deserialize are skipped.
+    ///
+    /// The stream ends once the `LspService` is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::notification::DidChangeTextDocument;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService};
+    /// # use futures::StreamExt;
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (service, socket) = LspService::new(|_| Mock);
+    ///
+    /// let mut changes = service.notifications::<DidChangeTextDocument>();
+    /// tokio::spawn(async move {
+    ///     while let Some(params) = changes.next().await {
+    ///         println!("{} changed", params.text_document.uri);
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn notifications<N>(&self) -> impl Stream<Item = N::Params> + Send + Unpin + 'static
+    where
+        N: Notification,
+        N::Params: Send + 'static,
+    {
+        self.subscriptions
+            .subscribe(N::METHOD)
+            .filter_map(|params| {
+                let params = params.unwrap_or(Value::Null);
+                future::ready(match serde_json::from_value(params) {
+                    Ok(params) => Some(params),
+                    Err(err) => {
+                        warn!("skipping `{}` with invalid params: {}", N::METHOD, err);
+                        None
+                    }
+                })
+            })
+    }
+
+    /// Returns a stream of the requests canceled by the client from now on.
+    ///
+    /// Canceling a request through [`$/cancelRequest`] drops the future of its handler, which stops
+    /// any work done within it. Backends which hand work off to threads or external processes can
+    /// use this stream to stop that work too. Only requests whose handler was still running are
+    /// yielded.
+    ///
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    ///
+    /// The stream ends once the `LspService` and all of its pending requests are dropped.
+    pub fn cancellations(&self) -> impl Stream<Item = Cancellation> + Send + Unpin + 'static {
+        self.pending.subscribe()
+    }
+
+    fn check_kind(&mut self, req: &Request) {
+        let method = req.method();
+        let violation = match (self.inner.is_notification(method), req.id()) {
+            (Some(true), Some(_)) => {
+                warn!(
+                    "received request `{}`, but its handler returns `()` and can only handle \
+                     notifications; the client will receive an `Invalid request` error",
+                    method
+                );
+                ProtocolViolation::RequestToNotificationHandler {
+                    method: method.to_owned(),
+                }
+            }
+            (Some(false), None) => {
+                warn!(
+                    "received notification `{}`, but its handler returns `jsonrpc::Result<T>` \
+                     and can only handle requests; the notification will be ignored",
+                    method
+                );
+                ProtocolViolation::NotificationToRequestHandler {
+                    method: method.to_owned(),
+                }
+            }
+            _ => return,
+        };
+
+        self.kind_mismatches += 1;
+        if let Some(client) = &self.dev_mode {
+            client.report_violation(&violation);
+        }
+        if let Some(strict) = &self.strict {
+            strict.report(violation);
+        }
+    }
+
+    /// Handles `req`, regardless of the order of previous messages.
+    fn dispatch(
+        &mut self,
+        mut req: Request,
+    ) -> BoxFuture<'static, Result<Option<Response>, ExitedError>> {
+        if self.state.get() == State::Exited {
+            return future::err(ExitedError(())).boxed();
+        }
+
+        self.state.trace_message("<-", &req);
+
+        if let Some(strict) = &mut self.strict {
+            match strict.check(self.state.get(), &req) {
+                Some(ProtocolViolation::RequestAfterShutdown { .. }) => {
+                    let (_, id, _) = req.into_parts();
+                    let res = id.map(|id| Response::from_error(id, Error::invalid_request()));
+                    return future::ok(res).boxed();
+                }
+                Some(ProtocolViolation::NotificationAfterShutdown { .. }) => {
+                    return future::ok(None).boxed();
+                }
+                _ => {}
+            }
+        }
+
+        let trust = self.state.trust();
+        if req.method() != "initialize" || self.state.get() == State::Uninitialized {
+            trust.observe(&req);
+            #[cfg(feature = "file-watcher")]
+            self.state.file_watcher().observe(&req);
+        }
+
+        let allowed = self
+            .auth
+            .as_ref()
+            .map_or(true, |auth| auth.is_allowed(&req));
+        if !allowed || trust.is_gated(req.method()) {
+            return future::ok(auth::reject(&req)).boxed();
+        }
+
+        self.check_kind(&req);
+
+        let mut flush = None;
+        if req.id().is_none() && self.state.get() == State::Initialized {
+            if req.method() == "initialized" {
+                flush = self.early_notifications.clone();
+            }
+
+            self.subscriptions.publish(&req);
+            self.state.observe_document(&req);
+            if let Some(stale_requests) = &self.stale_requests {
+                stale_requests.invalidate(&req);
+            }
+        }
+
+        if self.metrics_endpoint && req.method() == METRICS_METHOD {
+            let (_, id, _) = req.into_parts();
+            let res = id.map(|id| Response::from_ok(id, json!(self.metrics.snapshot())));
+            return future::ok(res).boxed();
+        }
+
+        let mut span = match self.state.trace_context() {
+            Some(ctx) => trace_context::extract(&*ctx, &req),
+            None => None,
+        };
+
+        let mut correlation_id = None;
+        if let (Some(ids), Some(_)) = (&mut self.correlation_ids, req.id()) {
+            let (id, request_span) = ids.next(req.method(), span.as_ref());
+            correlation_id = Some(id);
+            span = Some(request_span);
+        }
+
+        let symbols_uri = match self.adapt_document_symbols {
+            true if req.method() == "textDocument/documentSymbol" => document_uri(&req),
+            _ => None,
+        };
+        let item_defaults =
+            self.completion_item_defaults && req.method() == "textDocument/completion";
+
+        let state = self.state.clone();
+        let dev_mode = self.dev_mode.clone();
+        let metrics = self.metrics.clone();
+        let response_limit = self.response_limit;
+        let method = req.id().map(|_| req.method().to_owned());
+        let started = self.sequencer.is_none().then(Instant::now);
+        let tracked = self.stale_requests.as_ref().and_then(|s| s.track(&req));
+        let queued = self.concurrency_limits.acquire(&req);
+        let after_hooks = match &self.hooks {
+            Some(hooks) => match hooks.before(&mut req, self.state.get()) {
+                Ok(after_hooks) => after_hooks,
+                Err(res) => return future::ok(Some(res)).boxed(),
+            },
+            None => None,
+        };
+        let fut = match &self.response_cache {
+            Some(cache) => match cache.layer(&mut self.inner).call(req) {
+                Either::Left(fut) | Either::Right(fut) => fut,
+            },
+            None => self.inner.call(req),
+        };
+        let acquire = queued.map(|queued| queued.wait());
+        let fut = match span {
+            Some(span) => fut.instrument(span).boxed(),
+            None => fut,
+        };
+
+        // Notifications are never answered, so unless they have to wait for something, the
+        // future of the router can be returned as is, which spares an allocation on the hottest
+        // path of busy sessions.
+        let waits = flush.is_some() || acquire.is_some() || tracked.is_some();
+        if method.is_none() && !waits && after_hooks.is_none() {
+            return fut;
+        }
+
+        Box::pin(async move {
+            if let Some(client) = flush {
+                client.flush_early_notifications().await;
+            }
+
+            let permit = match acquire {
+                Some(acquire) => acquire.await,
+                None => None,
+            };
+
+            let mut response = fut.await?;
+            drop(permit);
+            drop(tracked);
+
+            if let Some(hooks) = after_hooks {
+                response = response.map(|res| hooks.after(res));
+            }
+
+            if let (Some(method), Some(res)) = (method, response.take()) {
+                let res = match state.client_capabilities() {
+                    Some(caps) => match symbols_uri {
+                        Some(uri) => symbols::adapt_response(&uri, res, &caps),
+                        None if item_defaults => {
+                            let (id, result) = res.into_parts();
+                            let result = result.map(|v| completion::factor_item_defaults(v, &caps));
+                            Response::from_parts(id, result)
+                        }
+                        None => res,
+                    },
+                    None => res,
+                };
+
+                if let Some(client) = dev_mode {
+                    if let Some(violation) = dev_mode::check_response(&method, &res) {
+                        client.report_violation(&violation);
+                    }
+                }
+
+                let res = match response_limit {
+                    Some(limit) => limit.apply(&method, res),
+                    None => res,
+                };
+
+                let res = match correlation_id {
+                    Some(id) => id.annotate(res),
+                    None => res,
+                };
+
+                let elapsed = started.map_or(Duration::ZERO, |started| started.elapsed());
+                metrics.record(&method, elapsed, res.is_error());
+                response = Some(res);
+            }
+
+            if let Some(res) = &response {
+                state.trace_message("->", res);
+            }
+
+            Ok(response)
+        })
+    }
 }
 
 impl<S: LanguageServer> Service<Request> for LspService<S> {
@@ -106,32 +804,48 @@ impl<S: LanguageServer> Service<Request> for LspService<S> {
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.state.poll_initialized(cx).is_pending() {
+            return Poll::Pending;
+        }
+
         match self.state.get() {
-            State::Initializing => Poll::Pending,
             State::Exited => Poll::Ready(Err(ExitedError(()))),
             _ => self.inner.poll_ready(cx),
         }
     }
 
     fn call(&mut self, req: Request) -> Self::Future {
-        if self.state.get() == State::Exited {
-            return future::err(ExitedError(())).boxed();
+        let cancel = req.method() == "$/cancelRequest";
+        let fut = self.dispatch(req);
+        match &mut self.sequencer {
+            Some(sequencer) if !cancel => sequencer.sequence(fut).boxed(),
+            _ => fut,
         }
+    }
+}
 
-        let fut = self.inner.call(req);
+/// Runs futures one after the other, in the order they were passed to [`Sequencer::sequence`].
+#[derive(Debug, Default)]
+struct Sequencer {
+    previous: Option<oneshot::Receiver<()>>,
+}
 
-        Box::pin(async move {
-            let response = fut.await?;
-
-            match response.as_ref().and_then(|res| res.error()) {
-                Some(Error {
-                    code: ErrorCode::MethodNotFound,
-                    data: Some(Value::String(m)),
-                    ..
-                }) if m.starts_with("$/") => Ok(None),
-                _ => Ok(response),
+impl Sequencer {
+    /// Returns a future which only starts polling `fut` once every previously sequenced future
+    /// has completed or been dropped.
+    fn sequence<F: Future>(&mut self, fut: F) -> impl Future<Output = F::Output> {
+        let (done, next) = oneshot::channel::<()>();
+        let previous = self.previous.replace(next);
+
+        async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
             }
-        })
+
+            let output = fut.await;
+            drop(done);
+            output
+        }
     }
 }
 
@@ -142,7 +856,23 @@ pub struct LspServiceBuilder<S> {
     inner: Router<S, ExitedError>,
     state: Arc<ServerState>,
     pending: Arc<Pending>,
+    client: Client,
     socket: ClientSocket,
+    strict: Option<Strict>,
+    auth: Option<AuthLayer>,
+    metrics_endpoint: bool,
+    response_limit: Option<ResponseLimit>,
+    custom_methods: HashSet<&'static str>,
+    test_mode: bool,
+    cancel_stale_requests: bool,
+    cached_methods: HashSet<&'static str>,
+    concurrency_limits: HashMap<&'static str, ConcurrencyLimit>,
+    correlation_ids: Option<CorrelationIdPolicy>,
+    buffer_early_notifications: bool,
+    dev_mode: bool,
+    adapt_document_symbols: bool,
+    completion_item_defaults: bool,
+    hooks: Hooks,
 }
 
 impl<S: LanguageServer> LspServiceBuilder<S> {
@@ -221,31 +951,689 @@ impl<S: LanguageServer> LspServiceBuilder<S> {
     {
         let layer = layers::Normal::new(self.state.clone(), self.pending.clone());
         self.inner.method(name, callback, layer);
+        self.custom_methods.insert(name);
         self
     }
 
-    /// Constructs the `LspService` and returns it, along with a channel for server-to-client
-    /// communication.
-    pub fn finish(self) -> (LspService<S>, ClientSocket) {
-        let LspServiceBuilder {
-            inner,
-            state,
-            socket,
-            ..
-        } = self;
-
-        (LspService { inner, state }, socket)
-    }
-}
-
-impl<S: Debug> Debug for LspServiceBuilder<S> {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        f.debug_struct("LspServiceBuilder")
-            .field("inner", &self.inner)
-            .finish_non_exhaustive()
-    }
-}
-
+    /// Defines a custom JSON-RPC request or notification like
+    /// [`custom_method`](Self::custom_method), but whose handler receives a [`MethodContext`] in
+    /// place of `&self`.
+    ///
+    /// The context gives access to the server backend, the [`Client`], and the ID and name of the
+    /// method being called, so handlers may be standalone functions. Handlers may still take an
+    /// optional `params` argument, and their return type determines whether they handle requests
+    /// or notifications.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tower_lsp::jsonrpc::Result;
+    /// use tower_lsp::lsp_types::*;
+    /// use tower_lsp::{LanguageServer, LspService, MethodContext};
+    ///
+    /// struct Mock;
+    ///
+    /// // Implementation of `LanguageServer` omitted...
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// async fn reload(cx: MethodContext<'_, Mock>, files: Vec<Url>) -> Result<usize> {
+    ///     let message = format!("{} reloading {} files", cx.method(), files.len());
+    ///     cx.client().log_message(MessageType::INFO, message).await;
+    ///     Ok(files.len())
+    /// }
+    ///
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .custom_method_with_context("custom/reload", reload)
+    ///     .finish();
+    /// ```
+    pub fn custom_method_with_context<P, R, F>(mut self, name: &'static str, callback: F) -> Self
+    where
+        P: FromParams,
+        R: IntoResponse,
+        F: for<'a> Method<MethodContext<'a, S>, P, R> + Clone + Send + Sync + 'static,
+    {
+        let layer = layers::Normal::new(self.state.clone(), self.pending.clone());
+        let client = self.client.clone();
+        self.inner
+            .method_with_context(name, client, callback, layer);
+        self.custom_methods.insert(name);
+        self
+    }
+
+    /// Enables strict compliance with the message ordering rules of the LSP specification.
+    ///
+    /// Every incoming message which violates these rules (e.g. `textDocument/didOpen` sent before
+    /// the `initialized` notification) is reported to `on_violation` as a [`ProtocolViolation`],
+    /// which is useful for conformance testing of language clients.
+    ///
+    /// Additionally, every request received after `shutdown` is answered with JSON-RPC error code
+    /// `-32600` (invalid request), even if the method is unknown, and every notification other
+    /// than `exit` received after `shutdown` is dropped.
+    ///
+    /// Requests sent to methods handled as notifications, and vice versa, are reported as well.
+    /// These usually indicate a [`custom_method`](Self::custom_method) handler with the wrong
+    /// return type.
+    ///
+    /// Strict mode is disabled by default.
+    pub fn strict<F>(mut self, on_violation: F) -> Self
+    where
+        F: Fn(ProtocolViolation) + Send + Sync + 'static,
+    {
+        self.strict = Some(Strict::new(on_violation));
+        self
+    }
+
+    /// Restricts the methods the client may call to those allowed by `layer`.
+    ///
+    /// Messages denied by the policy never reach the server, so this can be used to disable
+    /// potentially dangerous methods, such as `workspace/executeCommand`, in untrusted
+    /// workspaces. See [`AuthLayer`] for more details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService};
+    /// use tower_lsp::AuthLayer;
+    ///
+    /// # struct Mock;
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .authorize(AuthLayer::allow_all().deny("workspace/executeCommand"))
+    ///     .finish();
+    /// ```
+    ///
+    /// All methods are allowed by default.
+    pub fn authorize(mut self, layer: AuthLayer) -> Self {
+        self.auth = Some(layer);
+        self
+    }
+
+    /// Sets the `policy` applied when the params of the method `name` fail to deserialize.
+    ///
+    /// By default, such requests are answered with an `Invalid params` error and such
+    /// notifications are silently dropped. Some clients treat `Invalid params` as a fatal error,
+    /// so servers may prefer to fail more gracefully for methods whose params are known to vary
+    /// between clients.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{InvalidParamsPolicy, LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .on_invalid_params("textDocument/hover", InvalidParamsPolicy::RequestFailed)
+    ///     .on_invalid_params("workspace/didChangeConfiguration", InvalidParamsPolicy::Ignore)
+    ///     .finish();
+    /// ```
+    pub fn on_invalid_params(mut self, name: &'static str, policy: InvalidParamsPolicy) -> Self {
+        self.inner.set_invalid_params_policy(name, policy);
+        self
+    }
+
+    /// Enables propagation of distributed tracing context through the `_meta` member of params.
+    ///
+    /// Every incoming message carrying a `_meta` object is handled inside the span returned by
+    /// [`TraceContext::extract`], and every message sent through the [`Client`] has its `_meta`
+    /// populated by [`TraceContext::inject`].
+    ///
+    /// Trace context propagation is disabled by default.
+    pub fn trace_context<T: TraceContext>(self, ctx: T) -> Self {
+        self.state.set_trace_context(Arc::new(ctx));
+        self
+    }
+
+    /// Enables tracking of the version of every open document, as reported by the client in
+    /// `textDocument/didOpen` and `textDocument/didChange` notifications.
+    ///
+    /// Versions are recorded as soon as notifications are received, before their handler runs.
+    /// With tracking enabled, [`Client::publish_diagnostics`] tags diagnostics published without a
+    /// version with the latest version of their document, and drops diagnostics computed for a
+    /// version older than the latest one, which would otherwise flicker in the editor while the
+    /// user is typing. The latest version is available through [`Client::document_version`].
+    ///
+    /// Document version tracking is disabled by default.
+    pub fn track_document_versions(self) -> Self {
+        self.state.track_document_versions();
+        self
+    }
+
+    /// Enables tracking whether the user trusts the workspace, as described by `config`.
+    ///
+    /// The trust is available through [`Client::is_workspace_trusted`], and the methods gated by
+    /// `config` are rejected while the workspace is untrusted, without reaching the server. See
+    /// [`WorkspaceTrust`] for more details.
+    ///
+    /// Workspace trust is not tracked by default, in which case the workspace is always trusted.
+    pub fn workspace_trust(self, config: WorkspaceTrust) -> Self {
+        self.state.trust().enable(config);
+        self
+    }
+
+    /// Watches files on the server side for clients which cannot do it themselves.
+    ///
+    /// If the client does not support the dynamic registration of
+    /// `workspace/didChangeWatchedFiles`, the registrations made through [`Client`] for this
+    /// method are handled by a file watcher running in the server instead of being sent to the
+    /// client. Changes of the files matching the registered watchers, inside the workspace folders
+    /// or the base folders of relative patterns, are then delivered to
+    /// [`LanguageServer::did_change_watched_files`] like those reported by other clients.
+    ///
+    /// The notifications are generated by the [`Server`](crate::Server), and thus not delivered
+    /// when driving the `LspService` directly.
+    ///
+    /// This is disabled by default.
+    #[cfg(feature = "file-watcher")]
+    pub fn file_watcher_fallback(self) -> Self {
+        self.state.file_watcher().enable();
+        self
+    }
+
+    /// Sets the action taken when the client sends the `exit` notification.
+    ///
+    /// By default, the service stops for good with [`ExitBehavior::TerminateService`]. Hosts
+    /// serving several sessions in a row, e.g. for one client connection after another, can use
+    /// [`ExitBehavior::CloseSessionOnly`] to keep the service, and the server backend, alive for the
+    /// next `initialize` request.
+    pub fn exit_behavior(self, behavior: ExitBehavior) -> Self {
+        self.state.set_exit_behavior(behavior);
+        self
+    }
+
+    /// Cancels pending requests on a document as soon as the client changes it.
+    ///
+    /// When a `textDocument/didChange` notification is received, every `textDocument/*` request
+    /// targeting the same document which is still being handled, such as completion, hover or
+    /// semantic tokens, is canceled and answered with JSON-RPC error code `-32801` (content
+    /// modified), since its result would be stale anyway. The client is then expected to send the
+    /// request again if it still needs the result.
+    ///
+    /// This is disabled by default.
+    pub fn cancel_stale_requests(mut self) -> Self {
+        self.cancel_stale_requests = true;
+        self
+    }
+
+    /// Caches the responses to requests for the methods in `methods`, such as
+    /// `textDocument/foldingRange`, `textDocument/documentSymbol` or
+    /// `textDocument/semanticTokens/full`, until the document they target changes.
+    ///
+    /// Clients often request the same information again for a document which did not change, e.g.
+    /// when switching between editor tabs. Successful responses to the cached methods are stored
+    /// per method, document and params, along with the version of the document they were computed
+    /// for, and later requests for the same version are answered from the cache without reaching
+    /// the server. Cached responses still go through hooks, metrics and correlation IDs like any
+    /// other. Responses are dropped as soon as their document is changed or closed, and are not
+    /// stored if the document changed while they were computed.
+    ///
+    /// Only requests targeting an open document, through the `textDocument` member of their
+    /// params, are cached, unless they ask for progress reports with a `workDoneToken` or
+    /// `partialResultToken`. The methods must be idempotent, i.e. their result must only depend on the
+    /// content of the document and the params. This enables
+    /// [`track_document_versions`](Self::track_document_versions).
+    ///
+    /// No responses are cached by default.
+    pub fn cache_responses<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = &'static str>,
+    {
+        self.state.track_document_versions();
+        self.cached_methods.extend(methods);
+        self
+    }
+
+    /// Limits the number of requests to the method `name` which are handled concurrently.
+    ///
+    /// Requests exceeding `limit` wait until a running request to the same method completes, while
+    /// requests to other methods are only subject to the global limit set with
+    /// [`Server::concurrency_level`](crate::Server::concurrency_level). Waiting requests still
+    /// count toward the global limit, and can be canceled by the client as usual.
+    ///
+    /// Limits can also be declared on the methods of the `LanguageServer` trait of forks of this
+    /// crate, with the `max_concurrency` and `latest_wins` keys of the `#[rpc]` attribute.
+    ///
+    /// # Examples
+    ///
+    /// Handling at most one `textDocument/semanticTokens/full` request at a time, skipping those
+    /// superseded by a newer request while waiting:
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{ConcurrencyLimit, LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let limit = ConcurrencyLimit::new(1).latest_wins();
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .concurrency_limit("textDocument/semanticTokens/full", limit)
+    ///     .finish();
+    /// ```
+    pub fn concurrency_limit(mut self, name: &'static str, limit: ConcurrencyLimit) -> Self {
+        self.concurrency_limits.insert(name, limit);
+        self
+    }
+
+    /// Assigns a correlation ID to every incoming request, reported according to `policy`.
+    ///
+    /// Each request is handled inside a `request` span with `method` and `correlation_id` fields,
+    /// nested within the span returned by [`trace_context`](Self::trace_context), if any. With
+    /// [`CorrelationIdPolicy::SpanAndErrorData`], the ID is also included in error responses, so
+    /// errors reported by users of an editor can be matched with the server logs.
+    ///
+    /// IDs are sequential numbers starting at 1, unique within a single `LspService`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{CorrelationIdPolicy, LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .correlation_ids(CorrelationIdPolicy::SpanAndErrorData)
+    ///     .finish();
+    /// ```
+    ///
+    /// Correlation IDs are disabled by default.
+    pub fn correlation_ids(mut self, policy: CorrelationIdPolicy) -> Self {
+        self.correlation_ids = Some(policy);
+        self
+    }
+
+    /// Queues notifications sent through [`Client`] before the server is initialized, instead of
+    /// dropping them.
+    ///
+    /// Up to `limit` notifications sent while the server is uninitialized or handling the
+    /// `initialize` request, such as log messages or diagnostics computed while indexing the
+    /// workspace, are kept and sent in order as soon as the client sends the `initialized`
+    /// notification, before its handler is called. Any further notifications are dropped. Requests
+    /// sent to the client before initialization still fail immediately.
+    ///
+    /// Early notifications are dropped by default.
+    pub fn buffer_early_notifications(mut self, limit: usize) -> Self {
+        self.client.buffer_early_notifications(limit);
+        self.buffer_early_notifications = true;
+        self
+    }
+
+    /// Reports the protocol violations committed by the server itself to the client log.
+    ///
+    /// In dev mode, every result returned for a standard request is checked against the shape
+    /// required by the specification, and every message sent through [`Client`] is checked against
+    /// the capabilities advertised by the client. Violations, as well as requests and
+    /// notifications reaching a handler of the wrong kind, are sent to the client as
+    /// `window/logMessage` warnings holding the [`ProtocolViolation`] as JSON, so that integration
+    /// bugs show up in the editor itself:
+    ///
+    /// ```text
+    /// [tower-lsp] protocol violation: {"kind":"unsupportedByClient","method":"workspace/applyEdit",...}
+    /// ```
+    ///
+    /// Messages are still sent and responses left untouched. Dev mode is meant for development
+    /// builds only, since checking every response has a cost, and is disabled by default.
+    pub fn dev_mode(mut self) -> Self {
+        self.client.enable_dev_mode();
+        self.dev_mode = true;
+        self
+    }
+
+    /// Sets the scheme used for the IDs of requests sent to the client through [`Client`].
+    ///
+    /// Request IDs are sequential numbers by default. Servers running behind a proxy which also
+    /// forwards requests of its own to the client can use [`RequestIdStrategy::Prefixed`] to keep
+    /// the IDs from colliding, while [`RequestIdStrategy::Custom`] allows for any other scheme,
+    /// such as random UUIDs.
+    pub fn request_ids(self, strategy: RequestIdStrategy) -> Self {
+        self.client.set_request_id_strategy(strategy);
+        self
+    }
+
+    /// Converts `textDocument/documentSymbol` responses into the form supported by the client.
+    ///
+    /// With this enabled, servers can always answer with nested
+    /// [`DocumentSymbol`](lsp_types::DocumentSymbol)s, which are flattened into
+    /// [`SymbolInformation`](lsp_types::SymbolInformation) for clients lacking
+    /// `hierarchicalDocumentSymbolSupport`,
+    /// as described in [`symbols::for_client`](crate::symbols::for_client).
+    ///
+    /// This is disabled by default.
+    pub fn adapt_document_symbols(mut self) -> Self {
+        self.adapt_document_symbols = true;
+        self
+    }
+
+    /// Moves the properties shared by all items of `textDocument/completion` responses into the
+    /// `itemDefaults` of the completion list, as far as supported by the client.
+    ///
+    /// This applies to the `commitCharacters`, `insertTextFormat`, `insertTextMode` and `data`
+    /// properties, as well as to the range of the `textEdit` of each item, which becomes the
+    /// `editRange` default while the new text of each item is kept as its `textEditText`. Since
+    /// large completion lists often use the same range and format for all items, this can shrink
+    /// responses considerably. See also [`CompletionBuilder`](crate::completion::CompletionBuilder).
+    ///
+    /// This is disabled by default.
+    pub fn completion_item_defaults(mut self) -> Self {
+        self.completion_item_defaults = true;
+        self
+    }
+
+    /// Makes the order in which messages are handled deterministic, for reproducible tests.
+    ///
+    /// In test mode, every incoming message is only handled once the message received before it
+    /// has been handled completely, as if [`Server::concurrency_level`](crate::Server::concurrency_level)
+    /// were set to 1, regardless of how the `LspService` is driven. Responses are thus produced in
+    /// the order the requests were received. `$/cancelRequest` notifications are the only exception,
+    /// so they can still cancel the request being handled.
+    ///
+    /// Additionally, request latencies are recorded as zero, so [`LspService::metrics_snapshot`]
+    /// only depends on the messages received.
+    ///
+    /// Test mode is disabled by default, and should not be enabled in production.
+    pub fn test_mode(mut self) -> Self {
+        self.test_mode = true;
+        self
+    }
+
+    /// Answers `$/metrics` requests with the current [`LspService::metrics_snapshot`].
+    ///
+    /// The response is a JSON object mapping each method name to its request count, error count,
+    /// and latency percentiles in milliseconds, e.g.
+    /// `{"textDocument/hover":{"requests":3,"errors":0,"p50Ms":0.5,...}}`. This allows collecting
+    /// metrics from servers deployed in the field with a simple client-side command.
+    ///
+    /// The `$/metrics` endpoint is disabled by default.
+    pub fn metrics_endpoint(mut self) -> Self {
+        self.metrics_endpoint = true;
+        self
+    }
+
+    /// Limits the size of serialized responses to `max_bytes`, applying `policy` to larger ones.
+    ///
+    /// This protects editors from accidentally huge responses, such as a completion list with
+    /// millions of items, which can otherwise freeze the client for a long time. The size is
+    /// measured on the serialized JSON-RPC response after the handler returns, excluding the
+    /// `Content-Length` header.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, ResponseSizePolicy};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .max_response_size(16 * 1024 * 1024, ResponseSizePolicy::Truncate)
+    ///     .finish();
+    /// ```
+    ///
+    /// Response sizes are not limited by default.
+    pub fn max_response_size(mut self, max_bytes: usize, policy: ResponseSizePolicy) -> Self {
+        self.response_limit = Some(ResponseLimit::new(max_bytes, policy));
+        self
+    }
+
+    /// Registers a `hook` called before and after the backend handles the `initialize` request.
+    ///
+    /// The hook is first called with [`InitializeHook::Before`] and the params of the request,
+    /// right before the backend's [`initialize`](LanguageServer::initialize) handler runs, then
+    /// with [`InitializeHook::After`] once it has returned, with the params and the result, which
+    /// the hook may modify. This allows cross-cutting setup, such as spawning file watchers or
+    /// advertising extra capabilities, in wrappers built on top of a backend without modifying its
+    /// `LanguageServer` implementation.
+    ///
+    /// Hooks are not called for `initialize` requests which are rejected before reaching the
+    /// backend, e.g. because the server is already initialized or the params are invalid. Several
+    /// hooks can be registered, and are called in registration order. They run on the task
+    /// handling the request, so they should return quickly.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{InitializeHook, LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .on_initialize(|_client, hook| match hook {
+    ///         InitializeHook::Before(params) => {
+    ///             tracing::info!("initializing for {:?}", params.client_info);
+    ///         }
+    ///         InitializeHook::After(_, Ok(result)) => {
+    ///             result.capabilities.hover_provider = Some(HoverProviderCapability::Simple(true));
+    ///         }
+    ///         _ => {}
+    ///     })
+    ///     .finish();
+    /// ```
+    pub fn on_initialize<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Client, InitializeHook<'_>) + Send + Sync + 'static,
+    {
+        self.hooks.push_initialize(hook);
+        self
+    }
+
+    /// Registers a `hook` called before and after the backend handles the `shutdown` request.
+    ///
+    /// The hook is first called with [`ShutdownHook::Before`], right before the backend's
+    /// [`shutdown`](LanguageServer::shutdown) handler runs, then with [`ShutdownHook::After`] and
+    /// its result once it has returned, e.g. to flush caches or stop background tasks started by
+    /// [`LspServiceBuilder::on_initialize`].
+    ///
+    /// Hooks are not called for `shutdown` requests which are rejected before reaching the
+    /// backend, e.g. because the server is not initialized. Several hooks can be registered, and
+    /// are called in registration order.
+    pub fn on_shutdown<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Client, ShutdownHook<'_>) + Send + Sync + 'static,
+    {
+        self.hooks.push_shutdown(hook);
+        self
+    }
+
+    /// Registers a `validator` inspecting, and possibly amending, the params of the `initialize`
+    /// request before the backend sees them.
+    ///
+    /// Returning an [`InitializeRejection`] answers the request with a structured error without
+    /// calling the backend's [`initialize`](LanguageServer::initialize) handler, e.g. to enforce a
+    /// minimum client version or capabilities the server cannot work without. The server then
+    /// stays uninitialized, so the client may send another `initialize` request. Otherwise, the
+    /// possibly modified params are passed on to the backend and to the hooks registered with
+    /// [`LspServiceBuilder::on_initialize`], which are not called for rejected requests.
+    ///
+    /// Several validators can be registered, and are called in registration order until one of
+    /// them rejects the request.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{InitializeRejection, LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .validate_initialize(|params| {
+    ///         let workspace = params.capabilities.workspace.as_ref();
+    ///         match workspace.and_then(|w| w.configuration) {
+    ///             Some(true) => Ok(()),
+    ///             _ => Err(InitializeRejection::new("`workspace/configuration` is required")),
+    ///         }
+    ///     })
+    ///     .finish();
+    /// ```
+    pub fn validate_initialize<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&mut InitializeParams) -> Result<(), InitializeRejection> + Send + Sync + 'static,
+    {
+        self.hooks.push_validate(validator);
+        self
+    }
+
+    /// Constructs the `LspService` and returns it, along with a channel for server-to-client
+    /// communication.
+    pub fn finish(self) -> (LspService<S>, ClientSocket) {
+        let LspServiceBuilder {
+            inner,
+            state,
+            pending,
+            client,
+            socket,
+            strict,
+            auth,
+            metrics_endpoint,
+            response_limit,
+            custom_methods,
+            test_mode,
+            cancel_stale_requests,
+            cached_methods,
+            concurrency_limits,
+            correlation_ids,
+            buffer_early_notifications,
+            dev_mode,
+            adapt_document_symbols,
+            completion_item_defaults,
+            hooks,
+        } = self;
+
+        let hooks = (!hooks.is_empty()).then(|| hooks.with_client(client.clone()));
+        let response_cache = (!cached_methods.is_empty())
+            .then(|| ResponseCacheLayer::new(cached_methods, state.clone()));
+
+        (
+            LspService {
+                inner,
+                state,
+                pending: pending.clone(),
+                strict,
+                auth,
+                kind_mismatches: 0,
+                metrics: Arc::new(Metrics::new()),
+                metrics_endpoint,
+                response_limit,
+                custom_methods,
+                subscriptions: Subscriptions::default(),
+                sequencer: test_mode.then(Sequencer::default),
+                stale_requests: cancel_stale_requests.then(|| StaleRequests::new(pending.clone())),
+                response_cache,
+                concurrency_limits: ConcurrencyLimits::new(concurrency_limits, pending),
+                correlation_ids: correlation_ids.map(CorrelationIds::new),
+                adapt_document_symbols,
+                completion_item_defaults,
+                dev_mode: dev_mode.then(|| client.clone()),
+                early_notifications: buffer_early_notifications.then_some(client),
+                hooks,
+            },
+            socket,
+        )
+    }
+}
+
+impl<S: Debug> Debug for LspServiceBuilder<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("LspServiceBuilder")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use async_trait::async_trait;
@@ -254,7 +1642,7 @@ mod tests {
     use tower::ServiceExt;
 
     use super::*;
-    use crate::jsonrpc::Result;
+    use crate::jsonrpc::{ErrorCode, Id, Result};
 
     #[derive(Debug)]
     struct Mock;
@@ -269,57 +1657,645 @@ mod tests {
             Ok(())
         }
 
-        // This handler should never resolve...
-        async fn code_action_resolve(&self, _: CodeAction) -> Result<CodeAction> {
-            future::pending().await
+        // This handler should never resolve...
+        async fn code_action_resolve(&self, _: CodeAction) -> Result<CodeAction> {
+            future::pending().await
+        }
+
+        async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+            future::pending().await
+        }
+
+        async fn symbol(&self, _: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+            let context = RequestContext::current().unwrap();
+            let tokens = [context.work_done_token(), context.partial_result_token()];
+
+            #[allow(deprecated)]
+            let symbols = tokens.iter().flatten().map(|token| SymbolInformation {
+                name: serde_json::to_string(token).unwrap(),
+                kind: SymbolKind::NULL,
+                tags: None,
+                deprecated: None,
+                location: Location::new("file:///".parse().unwrap(), Range::default()),
+                container_name: None,
+            });
+
+            Ok(Some(symbols.collect()))
+        }
+
+        async fn document_symbol(
+            &self,
+            _: DocumentSymbolParams,
+        ) -> Result<Option<DocumentSymbolResponse>> {
+            #[allow(deprecated)]
+            let symbol = DocumentSymbol {
+                name: "main".into(),
+                detail: None,
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                range: Range::default(),
+                selection_range: Range::default(),
+                children: None,
+            };
+
+            Ok(Some(DocumentSymbolResponse::Nested(vec![symbol])))
+        }
+    }
+
+    impl Mock {
+        async fn custom_request(&self, params: i32) -> Result<i32> {
+            Ok(params)
+        }
+
+        async fn custom_error(&self) -> Result<()> {
+            Err(Error::internal_error())
+        }
+    }
+
+    fn initialize_request(id: i64) -> Request {
+        Request::build("initialize")
+            .params(json!({"capabilities":{}}))
+            .id(id)
+            .finish()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn initializes_only_once() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let request = initialize_request(1);
+
+        let response = service.ready().await.unwrap().call(request.clone()).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let response = service.ready().await.unwrap().call(request).await;
+        let err = Response::from_error(1.into(), Error::invalid_request());
+        assert_eq!(response, Ok(Some(err)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn constructs_backend_asynchronously() {
+        let failed = LspService::<Mock>::try_new(|_| async { Err("no database") }).await;
+        assert_eq!(failed.err(), Some("no database"));
+
+        let (mut service, _) = LspService::try_new(|_| async { Ok::<_, ()>(Mock) })
+            .await
+            .unwrap();
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize_request(1))
+            .await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_methods_of_existing_router() {
+        let (mut service, _) = LspService::build_with_router(|_| {
+            let mut router = Router::new(Mock);
+            let identity = tower::layer::util::Identity::new();
+            router.method("custom/request", Mock::custom_request, identity);
+            router
+        })
+        .finish();
+
+        let request = Request::build("custom/request").params(7).id(1).finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        assert_eq!(response, Ok(Some(Response::from_ok(1.into(), json!(7)))));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize_request(2))
+            .await;
+        let ok = Response::from_ok(2.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_concurrent_initialize() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let first = service.ready().await.unwrap().call(initialize_request(1));
+        // `poll_ready` would wait for the first request to complete, so bypass it to race them.
+        let second = service.call(initialize_request(2));
+        let (first, second) = futures::join!(first, second);
+
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(first, Ok(Some(ok)));
+        let err = Response::from_error(2.into(), Error::invalid_request());
+        assert_eq!(second, Ok(Some(err)));
+        assert_eq!(service.state.get(), State::Initialized);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn waits_for_pending_initialize() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = service.ready().await.unwrap().call(initialize_request(1));
+        assert_eq!(service.state.get(), State::Initializing);
+
+        let mut ready = service.ready();
+        assert!(futures::poll!(&mut ready).is_pending());
+
+        let (response, ready) = futures::join!(initialize, ready);
+        assert!(response.unwrap().unwrap().is_ok());
+        assert!(ready.is_ok());
+        assert_eq!(service.state.get(), State::Initialized);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn retries_abandoned_initialize() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let abandoned = service.ready().await.unwrap().call(initialize_request(1));
+        drop(abandoned);
+        assert_eq!(service.state.get(), State::Uninitialized);
+
+        let response = service.ready().await.unwrap().call(initialize_request(2));
+        let ok = Response::from_ok(2.into(), json!({"capabilities":{}}));
+        assert_eq!(response.await, Ok(Some(ok)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn refuses_requests_after_shutdown() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let shutdown = Request::build("shutdown").id(1).finish();
+        let response = service.ready().await.unwrap().call(shutdown.clone()).await;
+        let ok = Response::from_ok(1.into(), json!(null));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let response = service.ready().await.unwrap().call(shutdown).await;
+        let err = Response::from_error(1.into(), Error::invalid_request());
+        assert_eq!(response, Ok(Some(err)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn validates_initialize_params() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .validate_initialize(|params| match params.root_uri.take() {
+                Some(_) => Err(InitializeRejection::new("no root expected").retry()),
+                None => Ok(()),
+            })
+            .on_initialize(|_, hook| {
+                if let InitializeHook::Before(params) = hook {
+                    assert_eq!(params.process_id, Some(1));
+                }
+            })
+            .finish();
+
+        let rejected = Request::build("initialize")
+            .params(json!({"capabilities":{},"rootUri":"file:///root"}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(rejected).await;
+        let error = Error {
+            code: ErrorCode::RequestFailed,
+            message: "no root expected".into(),
+            data: Some(json!({"retry":true})),
+        };
+        assert_eq!(response, Ok(Some(Response::from_error(1.into(), error))));
+        assert_eq!(service.state.get(), State::Uninitialized);
+
+        let accepted = Request::build("initialize")
+            .params(json!({"capabilities":{},"processId":1}))
+            .id(2)
+            .finish();
+        let response = service.ready().await.unwrap().call(accepted).await;
+        let ok = Response::from_ok(2.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn calls_lifecycle_hooks() {
+        use std::sync::Mutex;
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (initialize_calls, shutdown_calls) = (calls.clone(), calls.clone());
+        let (mut service, _) = LspService::build(|_| Mock)
+            .on_initialize(move |_, hook| match hook {
+                InitializeHook::Before(_) => initialize_calls.lock().unwrap().push("before init"),
+                InitializeHook::After(_, result) => {
+                    initialize_calls.lock().unwrap().push("after init");
+                    let result = result.as_mut().unwrap();
+                    result.capabilities.hover_provider =
+                        Some(HoverProviderCapability::Simple(true));
+                }
+            })
+            .on_shutdown(move |_, hook| match hook {
+                ShutdownHook::Before => shutdown_calls.lock().unwrap().push("before shutdown"),
+                ShutdownHook::After(result) => {
+                    assert_eq!(result, &Ok(()));
+                    shutdown_calls.lock().unwrap().push("after shutdown");
+                }
+            })
+            .finish();
+
+        let response = service.ready().await.unwrap().call(initialize_request(1));
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{"hoverProvider":true}}));
+        assert_eq!(response.await, Ok(Some(ok)));
+
+        // Duplicate requests never reach the backend, so the hooks are not called again.
+        let response = service.ready().await.unwrap().call(initialize_request(2));
+        assert!(response.await.unwrap().unwrap().is_error());
+
+        let shutdown = Request::build("shutdown").id(3).finish();
+        let response = service.ready().await.unwrap().call(shutdown);
+        assert_eq!(
+            response.await,
+            Ok(Some(Response::from_ok(3.into(), json!(null))))
+        );
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "before init",
+                "after init",
+                "before shutdown",
+                "after shutdown"
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn exposes_progress_tokens() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let symbol = Request::build("workspace/symbol")
+            .params(json!({"query":"","workDoneToken":1,"partialResultToken":"foo"}))
+            .id(2)
+            .finish();
+        let response = service.ready().await.unwrap().call(symbol).await.unwrap();
+        let (_, result) = response.unwrap().into_parts();
+        let names: Vec<_> = result
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].clone())
+            .collect();
+        assert_eq!(names, [json!("1"), json!("\"foo\"")]);
+        assert_eq!(RequestContext::current(), None);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn applies_invalid_params_policy() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .on_invalid_params("textDocument/hover", InvalidParamsPolicy::RequestFailed)
+            .on_invalid_params("workspace/symbol", InvalidParamsPolicy::Ignore)
+            .finish();
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let invalid = |method: &'static str| {
+            Request::build(method)
+                .params(json!({"foo":"bar"}))
+                .id(2)
+                .finish()
+        };
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(invalid("textDocument/hover"));
+        let error = response.await.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::RequestFailed);
+        assert!(error.message.starts_with("invalid params: "));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(invalid("workspace/symbol"));
+        let ok = Response::from_ok(2.into(), Value::Null);
+        assert_eq!(response.await, Ok(Some(ok)));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(invalid("textDocument/definition"));
+        let error = response.await.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn passes_context_to_custom_methods() {
+        async fn echo(cx: MethodContext<'_, Mock>, params: i32) -> Result<Value> {
+            let doubled = cx.server().custom_request(params * 2).await?;
+            Ok(json!({ "id": cx.id(), "method": cx.method(), "result": doubled }))
+        }
+
+        let (mut service, _) = LspService::build(|_| Mock)
+            .custom_method_with_context("custom/echo", echo)
+            .finish();
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let request = Request::build("custom/echo").params(21).id(2).finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let expected = json!({"id": 2, "method": "custom/echo", "result": 42});
+        assert_eq!(response, Ok(Some(Response::from_ok(2.into(), expected))));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_unauthorized_methods() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .authorize(AuthLayer::allow_all().deny("workspace/symbol"))
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let symbol = Request::build("workspace/symbol")
+            .params(json!({"query":""}))
+            .id(2)
+            .finish();
+        let response = service.ready().await.unwrap().call(symbol).await;
+        let error = response.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::RequestFailed);
+        assert_eq!(error.message, "method `workspace/symbol` is not authorized");
+
+        let shutdown = Request::build("shutdown").id(3).finish();
+        let response = service.ready().await.unwrap().call(shutdown).await;
+        assert_eq!(response, Ok(Some(Response::from_ok(3.into(), json!(null)))));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn gates_methods_in_untrusted_workspaces() {
+        use futures::StreamExt;
+
+        let mut client = None;
+        let (mut service, _) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .workspace_trust(WorkspaceTrust::new().gate("workspace/executeCommand"))
+        .finish();
+        let client = client.unwrap();
+        let mut changes = client.workspace_trust_changes();
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{},"initializationOptions":{"trusted":false}}))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        assert!(!client.is_workspace_trusted());
+
+        let execute = |id: i64| {
+            Request::build("workspace/executeCommand")
+                .params(json!({"command":"run"}))
+                .id(id)
+                .finish()
+        };
+        let response = service.ready().await.unwrap().call(execute(2)).await;
+        let error = response.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::RequestFailed);
+
+        let trusted = Request::build("workspace/didChangeConfiguration")
+            .params(json!({"settings":{"trusted":true}}))
+            .finish();
+        service.ready().await.unwrap().call(trusted).await.unwrap();
+        assert!(client.is_workspace_trusted());
+        assert_eq!(changes.next().await, Some(true));
+
+        let response = service.ready().await.unwrap().call(execute(3)).await;
+        let error = response.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::MethodNotFound);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn responds_to_detached_requests() {
+        async fn proxy(cx: MethodContext<'_, Mock>, _: Value) -> Result<Value> {
+            cx.detach().unwrap().await
+        }
+
+        let mut client = None;
+        let (mut service, _) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .custom_method_with_context("custom/proxy", proxy)
+        .finish();
+        let client = client.unwrap();
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let request = Request::build("custom/proxy")
+            .params(json!({}))
+            .id(2)
+            .finish();
+        let mut response = service.ready().await.unwrap().call(request);
+        assert!(futures::poll!(&mut response).is_pending());
+        assert!(client.respond(&Id::Number(2), Ok(42)));
+        let ok = Response::from_ok(2.into(), json!(42));
+        assert_eq!(response.await, Ok(Some(ok)));
+        assert!(!client.respond(&Id::Number(2), Ok(42)));
+
+        let request = Request::build("custom/proxy")
+            .params(json!({}))
+            .id(3)
+            .finish();
+        let mut response = service.ready().await.unwrap().call(request);
+        assert!(futures::poll!(&mut response).is_pending());
+        let cancel = Request::build("$/cancelRequest")
+            .params(json!({"id": 3}))
+            .finish();
+        service.ready().await.unwrap().call(cancel).await.unwrap();
+        let canceled = Response::from_error(3.into(), Error::request_cancelled());
+        assert_eq!(response.await, Ok(Some(canceled)));
+        assert!(!client.respond(&Id::Number(3), Ok(42)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn streams_notifications() {
+        use futures::StreamExt;
+        use lsp_types::notification::DidOpenTextDocument;
+
+        let (mut service, _) = LspService::new(|_| Mock);
+        let opened = service.notifications::<DidOpenTextDocument>();
+
+        let did_open = |uri: &str| {
+            Request::build("textDocument/didOpen")
+                .params(json!({"textDocument":{"uri":uri,"languageId":"","version":0,"text":""}}))
+                .finish()
+        };
+
+        let early = did_open("file:///early.rs");
+        service.ready().await.unwrap().call(early).await.unwrap();
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let invalid = Request::build("textDocument/didOpen")
+            .params(json!({}))
+            .finish();
+        service.ready().await.unwrap().call(invalid).await.unwrap();
+        let late = did_open("file:///late.rs");
+        service.ready().await.unwrap().call(late).await.unwrap();
+
+        drop(service);
+        let uris: Vec<_> = opened
+            .map(|params| params.text_document.uri.to_string())
+            .collect()
+            .await;
+        assert_eq!(uris, ["file:///late.rs"]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_mode_handles_messages_in_order() {
+        use std::sync::Mutex;
+
+        #[derive(Debug, Default)]
+        struct Recorder(Mutex<Vec<&'static str>>);
+
+        #[async_trait]
+        impl LanguageServer for Recorder {
+            async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+                Ok(InitializeResult::default())
+            }
+
+            async fn shutdown(&self) -> Result<()> {
+                Ok(())
+            }
         }
-    }
 
-    impl Mock {
-        async fn custom_request(&self, params: i32) -> Result<i32> {
-            Ok(params)
+        impl Recorder {
+            async fn slow(&self) -> Result<()> {
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                }
+                self.0.lock().unwrap().push("slow");
+                Ok(())
+            }
+
+            async fn fast(&self) -> Result<()> {
+                self.0.lock().unwrap().push("fast");
+                Ok(())
+            }
         }
-    }
 
-    fn initialize_request(id: i64) -> Request {
-        Request::build("initialize")
-            .params(json!({"capabilities":{}}))
-            .id(id)
-            .finish()
-    }
+        async fn handled_order(test_mode: bool) -> Vec<&'static str> {
+            let mut builder = LspService::build(|_| Recorder::default())
+                .custom_method("custom/slow", Recorder::slow)
+                .custom_method("custom/fast", Recorder::fast);
+            if test_mode {
+                builder = builder.test_mode();
+            }
 
-    #[tokio::test(flavor = "current_thread")]
-    async fn initializes_only_once() {
-        let (mut service, _) = LspService::new(|_| Mock);
+            let (mut service, _) = builder.finish();
+            let initialize = initialize_request(1);
+            service
+                .ready()
+                .await
+                .unwrap()
+                .call(initialize)
+                .await
+                .unwrap();
 
-        let request = initialize_request(1);
+            let slow = Request::build("custom/slow").id(2).finish();
+            let slow = service.ready().await.unwrap().call(slow);
+            let fast = Request::build("custom/fast").id(3).finish();
+            let fast = service.ready().await.unwrap().call(fast);
+            let (slow, fast) = futures::join!(slow, fast);
+            assert!(slow.is_ok() && fast.is_ok());
 
-        let response = service.ready().await.unwrap().call(request.clone()).await;
-        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
-        assert_eq!(response, Ok(Some(ok)));
+            let order = service.inner().0.lock().unwrap().clone();
+            order
+        }
 
-        let response = service.ready().await.unwrap().call(request).await;
-        let err = Response::from_error(1.into(), Error::invalid_request());
-        assert_eq!(response, Ok(Some(err)));
+        assert_eq!(handled_order(false).await, ["fast", "slow"]);
+        assert_eq!(handled_order(true).await, ["slow", "fast"]);
     }
 
     #[tokio::test(flavor = "current_thread")]
-    async fn refuses_requests_after_shutdown() {
-        let (mut service, _) = LspService::new(|_| Mock);
-
-        let initialize = initialize_request(1);
-        let response = service.ready().await.unwrap().call(initialize).await;
-        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
-        assert_eq!(response, Ok(Some(ok)));
+    async fn describes_methods() {
+        let (service, _) = LspService::build(|_| Mock)
+            .custom_method("custom/request", Mock::custom_request)
+            .metrics_endpoint()
+            .finish();
 
-        let shutdown = Request::build("shutdown").id(1).finish();
-        let response = service.ready().await.unwrap().call(shutdown.clone()).await;
-        let ok = Response::from_ok(1.into(), json!(null));
-        assert_eq!(response, Ok(Some(ok)));
+        let description = service.describe();
+        let json = serde_json::to_value(&description).unwrap();
+        let methods = json["methods"].as_array().unwrap();
+        assert!(methods.contains(&json!({
+            "name": "textDocument/didOpen",
+            "notification": true,
+            "params": "DidOpenTextDocumentParams",
+            "custom": false,
+        })));
+        assert!(methods.contains(&json!({
+            "name": "shutdown",
+            "notification": false,
+            "result": "()",
+            "custom": false,
+        })));
+        assert!(methods.contains(&json!({
+            "name": "custom/request",
+            "notification": false,
+            "params": "i32",
+            "result": "i32",
+            "custom": true,
+        })));
 
-        let response = service.ready().await.unwrap().call(shutdown).await;
-        let err = Response::from_error(1.into(), Error::invalid_request());
-        assert_eq!(response, Ok(Some(err)));
+        let metrics = description.get("$/metrics").unwrap();
+        assert_eq!(metrics.result.as_deref(), Some("MetricsSnapshot"));
+        assert!(description.get("custom/unknown").is_none());
     }
 
     #[tokio::test(flavor = "current_thread")]
@@ -335,9 +2311,52 @@ mod tests {
         assert_eq!(service.call(exit).await, Err(ExitedError(())));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn closes_session_on_exit() {
+        let (mut service, _socket) = LspService::build(|_| Mock)
+            .exit_behavior(ExitBehavior::CloseSessionOnly)
+            .finish();
+
+        for id in [1, 3] {
+            let initialize = initialize_request(id);
+            let response = service.ready().await.unwrap().call(initialize).await;
+            let ok = Response::from_ok(id.into(), json!({"capabilities":{}}));
+            assert_eq!(response, Ok(Some(ok)));
+
+            let shutdown = Request::build("shutdown").id(id + 1).finish();
+            let response = service.ready().await.unwrap().call(shutdown).await;
+            assert_eq!(
+                response,
+                Ok(Some(Response::from_ok((id + 1).into(), json!(null))))
+            );
+
+            let exit = Request::build("exit").finish();
+            let response = service.ready().await.unwrap().call(exit).await;
+            assert_eq!(response, Ok(None));
+            assert_eq!(service.state.get(), State::Uninitialized);
+        }
+    }
+
+    #[test]
+    fn shares_extensions_with_client() {
+        #[derive(Debug, PartialEq)]
+        struct User(&'static str);
+
+        let mut client = None;
+        let (service, _) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+
+        service.extensions().insert(User("alice"));
+        let user = client.unwrap().extensions().get::<User>();
+        assert_eq!(user.as_deref(), Some(&User("alice")));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn cancels_pending_requests() {
         let (mut service, _) = LspService::new(|_| Mock);
+        let mut cancellations = service.cancellations();
 
         let initialize = initialize_request(1);
         let response = service.ready().await.unwrap().call(initialize).await;
@@ -360,6 +2379,10 @@ mod tests {
         let canceled = Response::from_error(1.into(), Error::request_cancelled());
         assert_eq!(pending_response, Ok(Some(canceled)));
         assert_eq!(cancel_response, Ok(None));
+
+        let cancellation = cancellations.next().await.unwrap();
+        assert_eq!(cancellation.id(), &Id::Number(1));
+        assert_eq!(cancellation.method(), "codeAction/resolve");
     }
 
     #[tokio::test(flavor = "current_thread")]
@@ -379,6 +2402,250 @@ mod tests {
         assert_eq!(response, Ok(Some(ok)));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn tracks_trace_level() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{},"trace":"verbose"}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+        assert_eq!(service.state.trace(), TraceValue::Verbose);
+
+        let set_trace = Request::build("$/setTrace")
+            .params(json!({"value":"messages"}))
+            .finish();
+        let response = service.ready().await.unwrap().call(set_trace).await;
+        assert_eq!(response, Ok(None));
+        assert_eq!(service.state.trace(), TraceValue::Messages);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn reports_strict_violations() {
+        use std::sync::Mutex;
+
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let violations_ = violations.clone();
+        let (mut service, _) = LspService::build(|_| Mock)
+            .strict(move |v| violations_.lock().unwrap().push(v))
+            .finish();
+
+        let did_open = Request::build("textDocument/didOpen").finish();
+        let response = service.ready().await.unwrap().call(did_open.clone()).await;
+        assert_eq!(response, Ok(None));
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let response = service.ready().await.unwrap().call(did_open).await;
+        assert_eq!(response, Ok(None));
+
+        let shutdown = Request::build("shutdown").id(2).finish();
+        let response = service.ready().await.unwrap().call(shutdown).await;
+        assert_eq!(response, Ok(Some(Response::from_ok(2.into(), json!(null)))));
+
+        let unknown = Request::build("custom/unknown").id(3).finish();
+        let response = service.ready().await.unwrap().call(unknown).await;
+        let err = Response::from_error(3.into(), Error::invalid_request());
+        assert_eq!(response, Ok(Some(err)));
+
+        let method = "textDocument/didOpen".to_owned();
+        assert_eq!(
+            *violations.lock().unwrap(),
+            vec![
+                ProtocolViolation::NotificationBeforeInitialize {
+                    method: method.clone()
+                },
+                ProtocolViolation::MessageBeforeInitialized { method },
+                ProtocolViolation::MessageBeforeInitialized {
+                    method: "shutdown".into()
+                },
+                ProtocolViolation::RequestAfterShutdown {
+                    method: "custom/unknown".into()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn counts_kind_mismatches() {
+        use std::sync::Mutex;
+
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let violations_ = violations.clone();
+        let (mut service, _) = LspService::build(|_| Mock)
+            .custom_method("custom/request", Mock::custom_request)
+            .strict(move |v| violations_.lock().unwrap().push(v))
+            .finish();
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialized)
+            .await
+            .unwrap();
+        assert_eq!(service.kind_mismatches(), 0);
+
+        let notification = Request::build("custom/request").params(json!(1)).finish();
+        let response = service.ready().await.unwrap().call(notification).await;
+        assert_eq!(response, Ok(None));
+
+        let request = Request::build("textDocument/didOpen").id(2).finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let err = Response::from_error(2.into(), Error::invalid_request());
+        assert_eq!(response, Ok(Some(err)));
+
+        assert_eq!(service.kind_mismatches(), 2);
+        assert_eq!(
+            *violations.lock().unwrap(),
+            vec![
+                ProtocolViolation::NotificationToRequestHandler {
+                    method: "custom/request".into()
+                },
+                ProtocolViolation::RequestToNotificationHandler {
+                    method: "textDocument/didOpen".into()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn propagates_trace_context() {
+        use futures::StreamExt;
+        use serde_json::Map;
+        use std::sync::Mutex;
+        use tracing::Span;
+
+        struct Recorder(Arc<Mutex<Vec<Value>>>);
+
+        impl TraceContext for Recorder {
+            fn extract(&self, _: &str, meta: &Map<String, Value>) -> Span {
+                self.0.lock().unwrap().push(Value::Object(meta.clone()));
+                Span::none()
+            }
+
+            fn inject(&self, _: &str, meta: &mut Map<String, Value>) {
+                meta.insert("traceparent".into(), json!("00-abc-def-01"));
+            }
+        }
+
+        let extracted = Arc::new(Mutex::new(Vec::new()));
+        let mut client = None;
+        let (mut service, socket) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .trace_context(Recorder(extracted.clone()))
+        .finish();
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{}, "_meta": {"traceparent": "00-123-456-01"}}))
+            .id(1)
+            .finish();
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+        assert_eq!(
+            *extracted.lock().unwrap(),
+            vec![json!({"traceparent": "00-123-456-01"})]
+        );
+
+        let client = client.unwrap();
+        let (_, messages) = futures::join!(
+            async move {
+                client.log_message(MessageType::INFO, "hi").await;
+                client.telemetry_event(()).await;
+            },
+            socket.take(2).collect::<Vec<_>>()
+        );
+
+        let meta = messages[0].params().unwrap()["_meta"].clone();
+        assert_eq!(meta, json!({"traceparent": "00-abc-def-01"}));
+        assert_eq!(messages[1].params(), Some(&json!(null)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tags_diagnostics_with_document_version() {
+        use futures::StreamExt;
+
+        let mut client = None;
+        let (mut service, socket) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .track_document_versions()
+        .finish();
+
+        let uri = Url::parse("file:///main.rs").unwrap();
+        let messages = [
+            Request::build("initialize")
+                .params(json!({"capabilities":{}}))
+                .id(1)
+                .finish(),
+            Request::build("textDocument/didOpen")
+                .params(json!({"textDocument": {
+                    "uri": uri, "languageId": "rust", "version": 1, "text": ""
+                }}))
+                .finish(),
+            Request::build("textDocument/didChange")
+                .params(json!({
+                    "textDocument": {"uri": uri, "version": 3},
+                    "contentChanges": [{"text": "fn main() {}"}]
+                }))
+                .finish(),
+        ];
+        for message in messages {
+            service.ready().await.unwrap().call(message).await.unwrap();
+        }
+
+        let client = client.unwrap();
+        assert_eq!(client.document_version(&uri), Some(3));
+
+        let (_, published) = futures::join!(
+            async {
+                client
+                    .publish_diagnostics(uri.clone(), vec![], Some(2))
+                    .await;
+                client
+                    .publish_diagnostics(uri.clone(), vec![], Some(3))
+                    .await;
+                client.publish_diagnostics(uri.clone(), vec![], None).await;
+            },
+            socket.take(2).collect::<Vec<_>>()
+        );
+
+        let versions: Vec<_> = published
+            .iter()
+            .map(|n| n.params().unwrap()["version"].clone())
+            .collect();
+        assert_eq!(versions, vec![json!(3), json!(3)]);
+
+        let close = Request::build("textDocument/didClose")
+            .params(json!({"textDocument": {"uri": uri}}))
+            .finish();
+        service.ready().await.unwrap().call(close).await.unwrap();
+        assert_eq!(client.document_version(&uri), None);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn get_inner() {
         let (service, _) = LspService::build(|_| Mock).finish();
@@ -389,4 +2656,280 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn get_inner_mut() {
+        let (mut service, _) = LspService::build(|_| Mock).finish();
+        assert!(service.inner_mut().is_some());
+
+        let initialize = initialize_request(1);
+        let pending = service.ready().await.unwrap().call(initialize);
+        assert!(service.inner_mut().is_none());
+
+        pending.await.unwrap();
+        assert!(service.inner_mut().is_some());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_request_metrics() {
+        let (mut service, _) = LspService::build(|_| Mock).metrics_endpoint().finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        let response = service.ready().await.unwrap().call(initialized).await;
+        assert_eq!(response, Ok(None));
+
+        let unknown = Request::build("custom/unknown").id(2).finish();
+        let response = service.ready().await.unwrap().call(unknown).await;
+        assert!(response.unwrap().unwrap().is_error());
+
+        let snapshot = service.metrics_snapshot();
+        let initialize = snapshot.get("initialize").unwrap();
+        assert_eq!((initialize.requests, initialize.errors), (1, 0));
+        let unknown = snapshot.get("custom/unknown").unwrap();
+        assert_eq!((unknown.requests, unknown.errors), (1, 1));
+        assert!(snapshot.get("initialized").is_none());
+
+        let metrics = Request::build("$/metrics").id(3).finish();
+        let response = service.ready().await.unwrap().call(metrics).await;
+        let (_, result) = response.unwrap().unwrap().into_parts();
+        let result = result.unwrap();
+        assert_eq!(result["initialize"]["requests"], json!(1));
+        assert_eq!(result["custom/unknown"]["errors"], json!(1));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn metrics_endpoint_disabled_by_default() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let metrics = Request::build("$/metrics").id(2).finish();
+        let response = service.ready().await.unwrap().call(metrics).await;
+        let error = response.unwrap().unwrap().error().cloned().unwrap();
+        assert_eq!(error.code, ErrorCode::MethodNotFound);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn answers_unknown_dollar_requests() {
+        let (mut service, _) = LspService::new(|_| Mock);
+
+        let initialize = initialize_request(1);
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize)
+            .await
+            .unwrap();
+
+        let notification = Request::build("$/unknown").params(json!({})).finish();
+        let response = service.ready().await.unwrap().call(notification).await;
+        assert_eq!(response, Ok(None));
+
+        let request = Request::build("$/unknown").params(json!({})).id(2).finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let mut error = Error::method_not_found();
+        error.data = Some(json!("$/unknown"));
+        assert_eq!(response, Ok(Some(Response::from_error(2.into(), error))));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_stale_requests() {
+        let (mut service, _) = LspService::build(|_| Mock).cancel_stale_requests().finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        let response = service.ready().await.unwrap().call(initialized).await;
+        assert_eq!(response, Ok(None));
+
+        let hover = |id: i64, uri: &str| {
+            Request::build("textDocument/hover")
+                .params(json!({"textDocument":{"uri":uri},"position":{"line":0,"character":0}}))
+                .id(id)
+                .finish()
+        };
+
+        let did_change = Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument":{"uri":"file:///a.rs","version":2},
+                "contentChanges":[{"text":""}],
+            }))
+            .finish();
+
+        let stale_fut = service
+            .ready()
+            .await
+            .unwrap()
+            .call(hover(2, "file:///a.rs"));
+        let other_fut = service
+            .ready()
+            .await
+            .unwrap()
+            .call(hover(3, "file:///b.rs"));
+        let change_fut = service.ready().await.unwrap().call(did_change);
+
+        let mut other_fut = Box::pin(other_fut);
+        let (stale_response, change_response) = futures::join!(stale_fut, change_fut);
+
+        let modified = Response::from_error(2.into(), Error::content_modified());
+        assert_eq!(stale_response, Ok(Some(modified)));
+        assert_eq!(change_response, Ok(None));
+        assert!(futures::poll!(other_fut.as_mut()).is_pending());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn limits_method_concurrency() {
+        let limit = ConcurrencyLimit::new(1).latest_wins();
+        let (mut service, _) = LspService::build(|_| Mock)
+            .concurrency_limit("textDocument/hover", limit)
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let hover = |id: i64| {
+            Request::build("textDocument/hover")
+                .params(json!({"textDocument":{"uri":"file:///a.rs"},"position":{"line":0,"character":0}}))
+                .id(id)
+                .finish()
+        };
+        let cancel = |id: i64| {
+            Request::build("$/cancelRequest")
+                .params(json!({ "id": id }))
+                .finish()
+        };
+
+        let mut running = service.ready().await.unwrap().call(hover(2));
+        let superseded = service.ready().await.unwrap().call(hover(3));
+        let mut waiting = service.ready().await.unwrap().call(hover(4));
+        assert!(futures::poll!(running.as_mut()).is_pending());
+
+        let modified = Response::from_error(3.into(), Error::content_modified());
+        assert_eq!(superseded.await, Ok(Some(modified)));
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        // Canceling the running request lets the waiting one run.
+        service
+            .ready()
+            .await
+            .unwrap()
+            .call(cancel(2))
+            .await
+            .unwrap();
+        let cancelled = Response::from_error(2.into(), Error::request_cancelled());
+        assert_eq!(running.await, Ok(Some(cancelled)));
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        let queued = service.ready().await.unwrap().call(hover(5));
+        let _latest = service.ready().await.unwrap().call(hover(6));
+        let modified = Response::from_error(5.into(), Error::content_modified());
+        assert_eq!(queued.await, Ok(Some(modified)));
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn adds_correlation_ids_to_errors() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .custom_method("custom/error", Mock::custom_error)
+            .correlation_ids(CorrelationIdPolicy::SpanAndErrorData)
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().error().is_none());
+
+        let request = Request::build("custom/error").id(2).finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let mut error = Error::internal_error();
+        error.data = Some(json!({ "correlationId": 2 }));
+        assert_eq!(response, Ok(Some(Response::from_error(2.into(), error))));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn replays_early_notifications() {
+        let mut client = None;
+        let (mut service, socket) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .buffer_early_notifications(2)
+        .finish();
+
+        let client = client.unwrap();
+        let uri = Url::parse("file:///main.rs").unwrap();
+        client.publish_diagnostics(uri, Vec::new(), None).await;
+        client.notify_raw("custom/second", json!({})).await;
+        client.notify_raw("custom/dropped", json!({})).await;
+        assert_eq!(
+            client.request_raw("custom/request", json!(null)).await.ok(),
+            None
+        );
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let initialized = Request::build("initialized").params(json!({})).finish();
+        let response = service.ready().await.unwrap().call(initialized);
+        let (response, messages) = futures::join!(response, socket.take(2).collect::<Vec<_>>());
+        assert_eq!(response, Ok(None));
+
+        let methods: Vec<_> = messages.iter().map(|m| m.method().to_owned()).collect();
+        assert_eq!(
+            methods,
+            ["textDocument/publishDiagnostics", "custom/second"]
+        );
+        assert_eq!(messages[0].params().unwrap()["uri"], "file:///main.rs");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn adapts_document_symbols() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .adapt_document_symbols()
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let request = Request::build("textDocument/documentSymbol")
+            .params(json!({"textDocument":{"uri":"file:///main.rs"}}))
+            .id(2)
+            .finish();
+        let response = service.ready().await.unwrap().call(request).await;
+        let result = response.unwrap().unwrap().result().cloned().unwrap();
+        let flat: Vec<SymbolInformation> = serde_json::from_value(result).unwrap();
+        assert_eq!(flat[0].name, "main");
+        assert_eq!(flat[0].location.uri.as_str(), "file:///main.rs");
+    }
+
+    #[test]
+    fn uses_request_id_strategy() {
+        let mut client = None;
+        let (_, _) = LspService::build(|c| {
+            client = Some(c);
+            Mock
+        })
+        .request_ids(RequestIdStrategy::Prefixed("proxy-".into()))
+        .finish();
+
+        let client = client.unwrap();
+        assert_eq!(client.next_request_id(), Id::String("proxy-0".into()));
+        assert_eq!(client.next_request_id(), Id::String("proxy-1".into()));
+    }
 }
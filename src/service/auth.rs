@@ -0,0 +1,211 @@
+//! Per-method authorization of incoming messages.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{self, Either, Ready};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::jsonrpc::{Error, Request, Response};
+
+/// Lifecycle messages which are never rejected, so that the connection can always be set up and
+/// torn down.
+const LIFECYCLE: &[&str] = &[
+    "initialize",
+    "initialized",
+    "shutdown",
+    "exit",
+    "$/cancelRequest",
+];
+
+/// Policy deciding which methods the client may call, e.g. to disable `workspace/executeCommand`
+/// in untrusted workspaces.
+///
+/// Rejected requests are answered with a "request failed" error (`-32803`) naming the method, and
+/// rejected notifications are dropped with a warning. The `initialize`, `initialized`, `shutdown`,
+/// `exit` and `$/cancelRequest` messages are always allowed.
+///
+/// The policy is installed with [`LspServiceBuilder::authorize`], or wrapped around any service
+/// handling [`Request`]s as a [`tower::Layer`].
+///
+/// [`LspServiceBuilder::authorize`]: crate::LspServiceBuilder::authorize
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+///
+/// use tower_lsp::AuthLayer;
+///
+/// let trusted = Arc::new(AtomicBool::new(false));
+///
+/// // Deny a fixed set of methods...
+/// let untrusted = AuthLayer::allow_all().deny("workspace/executeCommand");
+///
+/// // ...or decide at runtime, e.g. once the user trusts the workspace.
+/// let policy = AuthLayer::new(move |req| {
+///     req.method() != "workspace/executeCommand" || trusted.load(Ordering::Relaxed)
+/// });
+/// ```
+#[derive(Clone)]
+pub struct AuthLayer {
+    policy: Arc<dyn Fn(&Request) -> bool + Send + Sync>,
+    rules: HashMap<String, bool>,
+}
+
+impl AuthLayer {
+    /// Creates a policy allowing the messages for which `policy` returns `true`.
+    ///
+    /// Methods configured with [`allow`](Self::allow) or [`deny`](Self::deny) bypass `policy`.
+    pub fn new<F>(policy: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        AuthLayer {
+            policy: Arc::new(policy),
+            rules: HashMap::new(),
+        }
+    }
+
+    /// Creates a policy allowing every method, meant to be combined with [`deny`](Self::deny).
+    pub fn allow_all() -> Self {
+        AuthLayer::new(|_| true)
+    }
+
+    /// Creates a policy denying every method but the lifecycle messages, meant to be combined
+    /// with [`allow`](Self::allow).
+    pub fn deny_all() -> Self {
+        AuthLayer::new(|_| false)
+    }
+
+    /// Always allows the method `name`.
+    pub fn allow<M: Into<String>>(mut self, name: M) -> Self {
+        self.rules.insert(name.into(), true);
+        self
+    }
+
+    /// Always denies the method `name`.
+    pub fn deny<M: Into<String>>(mut self, name: M) -> Self {
+        self.rules.insert(name.into(), false);
+        self
+    }
+
+    /// Returns whether `req` is allowed by this policy.
+    pub fn is_allowed(&self, req: &Request) -> bool {
+        if LIFECYCLE.contains(&req.method()) {
+            return true;
+        }
+
+        match self.rules.get(req.method()) {
+            Some(allowed) => *allowed,
+            None => (self.policy)(req),
+        }
+    }
+}
+
+impl Default for AuthLayer {
+    fn default() -> Self {
+        AuthLayer::allow_all()
+    }
+}
+
+impl Debug for AuthLayer {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("AuthLayer")
+            .field("rules", &self.rules)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = Auth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Auth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service rejecting the messages denied by an [`AuthLayer`] before they reach the inner service.
+#[derive(Clone, Debug)]
+pub struct Auth<S> {
+    inner: S,
+    layer: AuthLayer,
+}
+
+impl<S> Service<Request> for Auth<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if self.layer.is_allowed(&req) {
+            Either::Right(self.inner.call(req))
+        } else {
+            Either::Left(future::ok(reject(&req)))
+        }
+    }
+}
+
+/// Returns the response to the rejected message `req`, if it is a request.
+pub(crate) fn reject(req: &Request) -> Option<Response> {
+    match req.id() {
+        Some(id) => {
+            let message = format!("method `{}` is not authorized", req.method());
+            Some(Response::from_error(
+                id.clone(),
+                Error::request_failed(message),
+            ))
+        }
+        None => {
+            warn!("dropping unauthorized notification `{}`", req.method());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::jsonrpc::{ErrorCode, Id};
+
+    #[test]
+    fn applies_rules_before_policy() {
+        let layer = AuthLayer::new(|req| req.method().starts_with("textDocument/"))
+            .deny("textDocument/formatting")
+            .allow("workspace/symbol");
+
+        let request = |method| Request::build(method).id(1).finish();
+        assert!(layer.is_allowed(&request("textDocument/hover")));
+        assert!(!layer.is_allowed(&request("textDocument/formatting")));
+        assert!(layer.is_allowed(&request("workspace/symbol")));
+        assert!(!layer.is_allowed(&request("workspace/executeCommand")));
+        assert!(AuthLayer::deny_all().is_allowed(&request("shutdown")));
+
+        let denied = Request::build("workspace/executeCommand")
+            .params(json!({"command": "rm"}))
+            .id(2)
+            .finish();
+        let res = reject(&denied).unwrap();
+        assert_eq!(res.id(), &Id::Number(2));
+        assert_eq!(res.error().unwrap().code, ErrorCode::RequestFailed);
+
+        let notification = Request::build("workspace/didChangeWatchedFiles").finish();
+        assert_eq!(reject(&notification), None);
+    }
+}
@@ -0,0 +1,308 @@
+//! Caching of the responses to idempotent requests, enabled with
+//! [`LspServiceBuilder::cache_responses`](crate::LspServiceBuilder::cache_responses).
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use lsp_types::Url;
+use serde_json::Value;
+use tower::{Layer, Service};
+
+use super::stale::document_uri;
+use super::state::{ServerState, State};
+use crate::jsonrpc::{Request, Response};
+
+/// Params through which the client asks for progress reports, which a cached response would not
+/// deliver. Requests carrying either of them are never cached.
+const PROGRESS_TOKENS: &[&str] = &["workDoneToken", "partialResultToken"];
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct Key {
+    method: &'static str,
+    uri: Url,
+    params: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    version: i32,
+    result: Value,
+}
+
+/// Results of the requests to the cached methods, keyed by method, document and params.
+#[derive(Debug)]
+struct Cache {
+    methods: HashSet<&'static str>,
+    entries: Mutex<HashMap<Key, Entry>>,
+    state: Arc<ServerState>,
+}
+
+impl Cache {
+    /// Looks up the response to `req`, if it is a request to a cached method targeting an open
+    /// document.
+    fn lookup(self: &Arc<Self>, req: &Request) -> Option<Lookup> {
+        let id = req.id()?;
+        let method = *self.methods.get(req.method())?;
+        if self.state.get() != State::Initialized {
+            return None;
+        }
+
+        let params = req.params().cloned().unwrap_or_default();
+        if PROGRESS_TOKENS
+            .iter()
+            .any(|token| params.get(token).is_some())
+        {
+            return None;
+        }
+
+        let uri = document_uri(req)?;
+        let version = self.state.document_version(&uri)?;
+        let key = Key {
+            method,
+            uri,
+            params: params.to_string(),
+        };
+
+        let entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.version == version => {
+                let res = Response::from_ok(id.clone(), entry.result.clone());
+                Some(Lookup::Hit(res))
+            }
+            _ => Some(Lookup::Miss(Slot {
+                cache: self.clone(),
+                key,
+                version,
+            })),
+        }
+    }
+
+    /// Drops the responses computed for the document opened, changed or closed by `req`.
+    fn invalidate(&self, req: &Request) {
+        match req.method() {
+            "textDocument/didOpen" | "textDocument/didChange" | "textDocument/didClose" => {}
+            _ => return,
+        }
+
+        if let Some(uri) = document_uri(req) {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|key, _| key.uri != uri);
+        }
+    }
+
+    /// Drops every response, since document versions start over in a new session.
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Outcome of [`Cache::lookup`].
+#[derive(Debug)]
+enum Lookup {
+    /// The response computed for the same version of the document.
+    Hit(Response),
+    /// No response is cached yet, and the slot should be filled once it is computed.
+    Miss(Slot),
+}
+
+/// Place of a response in the cache, returned by [`Cache::lookup`].
+#[derive(Debug)]
+struct Slot {
+    cache: Arc<Cache>,
+    key: Key,
+    version: i32,
+}
+
+impl Slot {
+    /// Caches `res`, unless it is an error or the document changed while it was computed.
+    fn fill(self, res: &Response) {
+        let result = match res.result() {
+            Some(result) => result.clone(),
+            None => return,
+        };
+
+        if self.cache.state.document_version(&self.key.uri) == Some(self.version) {
+            let entry = Entry {
+                version: self.version,
+                result,
+            };
+            self.cache.entries.lock().unwrap().insert(self.key, entry);
+        }
+    }
+}
+
+/// Layer answering requests to idempotent methods with the response computed for the same version
+/// of their document, if any.
+///
+/// Responses are stored as returned by the inner service, so the layer is meant to wrap the
+/// router directly, before any post-processing of the responses.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseCacheLayer {
+    cache: Arc<Cache>,
+}
+
+impl ResponseCacheLayer {
+    /// Creates a layer caching the responses to `methods`, keyed by the document versions
+    /// tracked in `state`.
+    pub fn new(methods: HashSet<&'static str>, state: Arc<ServerState>) -> Self {
+        ResponseCacheLayer {
+            cache: Arc::new(Cache {
+                methods,
+                entries: Mutex::default(),
+                state,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for ResponseCacheLayer {
+    type Service = ResponseCache<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseCache {
+            inner,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+/// Service answering cached requests without calling the inner service, created by
+/// [`ResponseCacheLayer`].
+#[derive(Debug)]
+pub(crate) struct ResponseCache<S> {
+    inner: S,
+    cache: Arc<Cache>,
+}
+
+impl<S> Service<Request> for ResponseCache<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = Either<S::Future, BoxFuture<'static, Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        match req.id() {
+            None => self.cache.invalidate(&req),
+            Some(_) if req.method() == "initialize" => self.cache.clear(),
+            Some(_) => {}
+        }
+
+        match self.cache.lookup(&req) {
+            Some(Lookup::Hit(res)) => Either::Right(future::ok(Some(res)).boxed()),
+            Some(Lookup::Miss(slot)) => {
+                let fut = self.inner.call(req);
+                let fut = async move {
+                    let response = fut.await?;
+                    if let Some(res) = &response {
+                        slot.fill(res);
+                    }
+                    Ok(response)
+                };
+                Either::Right(fut.boxed())
+            }
+            None => Either::Left(self.inner.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::jsonrpc::Id;
+
+    fn open(uri: &str, version: i32) -> Request {
+        Request::build("textDocument/didOpen")
+            .params(json!({"textDocument": {
+                "uri": uri, "languageId": "rust", "version": version, "text": ""
+            }}))
+            .finish()
+    }
+
+    fn change(uri: &str, version: i32) -> Request {
+        Request::build("textDocument/didChange")
+            .params(json!({
+                "textDocument": {"uri": uri, "version": version},
+                "contentChanges": [],
+            }))
+            .finish()
+    }
+
+    fn folding_range(uri: &str, id: i64) -> Request {
+        Request::build("textDocument/foldingRange")
+            .params(json!({"textDocument": {"uri": uri}}))
+            .id(id)
+            .finish()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn caches_responses_per_document_version() {
+        let state = Arc::new(ServerState::new());
+        state.track_document_versions();
+        state.set(State::Initialized);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let methods = ["textDocument/foldingRange"].into_iter().collect();
+        let layer = ResponseCacheLayer::new(methods, state.clone());
+        let mut service = layer.layer(service_fn(move |req: Request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let res = req.id().map(|id| Response::from_ok(id.clone(), json!([])));
+            future::ok::<_, ()>(res)
+        }));
+
+        let mut call = |req: Request| {
+            state.observe_document(&req);
+            service.ready().now_or_never().unwrap().unwrap().call(req)
+        };
+
+        let uri = "file:///main.rs";
+        call(open(uri, 1)).await.unwrap();
+        let res = call(folding_range(uri, 1)).await.unwrap();
+        assert_eq!(res, Some(Response::from_ok(Id::Number(1), json!([]))));
+        let res = call(folding_range(uri, 2)).await.unwrap();
+        assert_eq!(res, Some(Response::from_ok(Id::Number(2), json!([]))));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Requests asking for progress reports are never answered from the cache.
+        let req = Request::build("textDocument/foldingRange")
+            .params(json!({"textDocument": {"uri": uri}, "partialResultToken": "a"}))
+            .id(3)
+            .finish();
+        call(req).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Changing the document drops its responses.
+        call(change(uri, 2)).await.unwrap();
+        call(folding_range(uri, 4)).await.unwrap();
+        call(folding_range(uri, 5)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // Responses computed while the document changed are not cached.
+        call(change(uri, 3)).await.unwrap();
+        let slot = match layer.cache.lookup(&folding_range(uri, 6)) {
+            Some(Lookup::Miss(slot)) => slot,
+            other => panic!("expected a cache miss, got {other:?}"),
+        };
+        state.observe_document(&change(uri, 4));
+        slot.fill(&Response::from_ok(Id::Number(6), json!([])));
+        assert!(matches!(
+            layer.cache.lookup(&folding_range(uri, 7)),
+            Some(Lookup::Miss(_))
+        ));
+    }
+}
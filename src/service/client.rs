@@ -1,39 +1,84 @@
 //! Types for sending data to and from the language client.
 
+pub use self::detached::DetachedRequest;
+pub use self::diagnostics::WorkspaceDiagnosticStream;
+pub use self::registration::CapabilityRegistration;
+pub use self::request_ids::RequestIdStrategy;
+pub use self::retry::{RetryPolicy, RetryingClient};
 pub use self::socket::{ClientSocket, RequestStream, ResponseSink};
 
+use std::collections::HashMap;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures::channel::mpsc::{self, Sender};
-use futures::future::BoxFuture;
-use futures::sink::SinkExt;
+use futures::future::{self, BoxFuture};
+use futures::Stream;
 use lsp_types::*;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use tower::Service;
-use tracing::{error, trace};
+use tracing::{debug, error, trace, warn};
 
+use self::delivery::{Acknowledger, Delivery};
+use self::detached::Detached;
 use self::pending::Pending;
-use self::progress::Progress;
+use self::progress::{Progress, ProgressIter};
+use self::rate_limit::RateLimits;
+use self::request_ids::RequestIds;
+use self::telemetry::Telemetry;
+use super::dev_mode;
+use super::extensions::Extensions;
 use super::state::{ServerState, State};
+use super::strict::ProtocolViolation;
 use super::ExitedError;
+use crate::experimental::Experimental;
+use crate::file_operations::FileOperation;
 use crate::jsonrpc::{self, Error, ErrorCode, Id, Request, Response};
+use crate::telemetry::TelemetryEvent;
 
 pub mod progress;
 
+mod delivery;
+mod detached;
+mod diagnostics;
 mod pending;
+#[cfg(feature = "proposed")]
+mod proposed;
+mod rate_limit;
+mod registration;
+mod request_ids;
+mod retry;
 mod socket;
+mod telemetry;
+
+/// States in which the server may send messages to the client.
+const CAN_SEND: &[State] = &[State::Initialized, State::ShutDown];
 
 struct ClientInner {
     tx: Sender<Request>,
-    request_id: AtomicU32,
+    delivery: Arc<Delivery>,
+    request_ids: RequestIds,
+    registration_id: AtomicU32,
+    registrations: Mutex<Vec<Registration>>,
     pending: Arc<Pending>,
+    detached: Detached,
+    rate_limits: RateLimits,
+    telemetry: Telemetry,
+    early_notifications: Mutex<Option<EarlyNotifications>>,
+    dev_mode: AtomicBool,
     state: Arc<ServerState>,
 }
 
+/// Notifications sent before the server is initialized, replayed once the client is ready.
+struct EarlyNotifications {
+    queue: Vec<Request>,
+    limit: usize,
+}
+
 /// Handle for communicating with the language client.
 ///
 /// This type provides a very cheap implementation of [`Clone`] so API consumers can cheaply clone
@@ -41,26 +86,55 @@ struct ClientInner {
 ///
 /// It also implements [`tower::Service`] in order to remain independent from the underlying
 /// transport and to facilitate further abstraction with middleware.
+///
+/// # Capabilities
+///
+/// Some requests are only sent if the client advertised support for them during initialization,
+/// either always, like [`Client::show_document`], or through the `try_*` variants of other
+/// requests, like [`Client::try_inlay_hint_refresh`]. Otherwise, they immediately return `Err`
+/// instead of sending a request which the client would likely reject. The error has the `-32601`
+/// (method not found) code the client would have answered with, but can be told apart from an
+/// actual answer with [`Error::is_unsupported_by_client`].
+///
+/// [`Error::is_unsupported_by_client`]: jsonrpc::Error::is_unsupported_by_client
 #[derive(Clone)]
 pub struct Client {
     inner: Arc<ClientInner>,
 }
 
 impl Client {
-    pub(super) fn new(state: Arc<ServerState>) -> (Self, ClientSocket) {
+    pub(crate) fn new(state: Arc<ServerState>) -> (Self, ClientSocket) {
         let (tx, rx) = mpsc::channel(1);
         let pending = Arc::new(Pending::new());
+        let delivery = Arc::new(Delivery::new());
 
         let client = Client {
             inner: Arc::new(ClientInner {
                 tx,
-                request_id: AtomicU32::new(0),
+                delivery: delivery.clone(),
+                request_ids: RequestIds::default(),
+                registration_id: AtomicU32::new(0),
+                registrations: Mutex::new(Vec::new()),
                 pending: pending.clone(),
+                detached: Detached::default(),
+                rate_limits: RateLimits::new(),
+                telemetry: Telemetry::new(),
+                early_notifications: Mutex::new(None),
+                dev_mode: AtomicBool::new(false),
                 state: state.clone(),
             }),
         };
 
-        (client, ClientSocket { rx, pending, state })
+        let ack = Acknowledger::new(delivery);
+        (
+            client,
+            ClientSocket {
+                rx,
+                ack,
+                pending,
+                state,
+            },
+        )
     }
 
     /// Disconnects the `Client` from its corresponding `LspService`.
@@ -74,6 +148,81 @@ impl Client {
     pub(crate) fn close(&self) {
         self.inner.tx.clone().close_channel();
     }
+
+    /// Forgets the capabilities registered during the session which just ended.
+    pub(crate) fn reset_session(&self) {
+        self.inner.registrations.lock().unwrap().clear();
+        if let Some(early) = self.inner.early_notifications.lock().unwrap().as_mut() {
+            early.queue.clear();
+        }
+    }
+
+    /// Sets the scheme used for the IDs of requests sent to the client.
+    pub(crate) fn set_request_id_strategy(&self, strategy: RequestIdStrategy) {
+        self.inner.request_ids.set_strategy(strategy);
+    }
+
+    /// Reports the protocol violations committed by the server to the client log.
+    pub(crate) fn enable_dev_mode(&self) {
+        self.inner.dev_mode.store(true, Ordering::SeqCst);
+    }
+
+    /// Reports `violation` to the client through a `window/logMessage` notification.
+    ///
+    /// The notification is sent immediately, bypassing rate limits and the checks of dev mode,
+    /// and dropped if the server may not send messages yet.
+    pub(crate) fn report_violation(&self, violation: &ProtocolViolation) {
+        warn!("protocol violation: {}", violation);
+
+        let msg = dev_mode::log_message(violation);
+        if let Err(err) = self.inner.state.check(msg.method().to_owned(), CAN_SEND) {
+            trace!("{}, suppressing message: {}", err, msg);
+            return;
+        }
+
+        self.inner.state.trace_message("->", &msg);
+        let mut tx = self.inner.tx.clone();
+        if self.inner.delivery.queue(|| tx.try_send(msg)).is_err() {
+            error!("failed to send notification");
+        }
+    }
+
+    /// Queues up to `limit` notifications sent before initialization, instead of dropping them.
+    pub(crate) fn buffer_early_notifications(&self, limit: usize) {
+        let queue = Vec::new();
+        *self.inner.early_notifications.lock().unwrap() = Some(EarlyNotifications { queue, limit });
+    }
+
+    /// Sends every notification queued before initialization, in order.
+    pub(crate) async fn flush_early_notifications(&self) {
+        let queue = match self.inner.early_notifications.lock().unwrap().as_mut() {
+            Some(early) => std::mem::take(&mut early.queue),
+            None => return,
+        };
+
+        for notification in queue {
+            if self.clone().call(notification).await.is_err() {
+                error!("failed to send notification");
+            }
+        }
+    }
+
+    /// Queues `notification` for [`Client::flush_early_notifications`], returning it back if
+    /// early notifications are not buffered.
+    fn queue_early_notification(&self, notification: Request) -> Result<(), Request> {
+        let mut early = self.inner.early_notifications.lock().unwrap();
+        match early.as_mut() {
+            Some(early) if early.queue.len() < early.limit => {
+                early.queue.push(notification);
+                Ok(())
+            }
+            Some(_) => {
+                warn!("too many notifications sent before initialization, dropping {notification}");
+                Ok(())
+            }
+            None => Err(notification),
+        }
+    }
 }
 
 impl Client {
@@ -81,6 +230,10 @@ impl Client {
 
     /// Registers a new capability with the client.
     ///
+    /// Once the client accepts the request, the `registrations` are recorded and can be queried
+    /// with [`Client::registrations`]. Registering an ID which is already recorded replaces the
+    /// previous registration.
+    ///
     /// This corresponds to the [`client/registerCapability`] request.
     ///
     /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
@@ -96,12 +249,190 @@ impl Client {
         registrations: Vec<Registration>,
     ) -> jsonrpc::Result<()> {
         use lsp_types::request::RegisterCapability;
-        self.send_request::<RegisterCapability>(RegistrationParams { registrations })
-            .await
+        let remote = self.register_locally(registrations.clone());
+        if !remote.is_empty() || registrations.is_empty() {
+            let params = RegistrationParams {
+                registrations: remote,
+            };
+            self.send_request::<RegisterCapability>(params).await?;
+        }
+
+        let mut registry = self.inner.registrations.lock().unwrap();
+        for registration in registrations {
+            registry.retain(|r| r.id != registration.id);
+            registry.push(registration);
+        }
+
+        Ok(())
+    }
+
+    /// Registers `method` with the client under a newly generated, unique registration ID.
+    ///
+    /// Returns the generated ID, which can later be passed to [`Client::unregister_capability`].
+    /// See [`Client::register_capability`] for more details.
+    pub async fn register_method<M>(
+        &self,
+        method: M,
+        register_options: Option<Value>,
+    ) -> jsonrpc::Result<String>
+    where
+        M: Into<String>,
+    {
+        let registration = Registration {
+            id: self.next_registration_id(),
+            method: method.into(),
+            register_options,
+        };
+
+        let id = registration.id.clone();
+        self.register_capability(vec![registration]).await?;
+        Ok(id)
+    }
+
+    /// Registers `method` with the client, returning a guard which unregisters it once dropped.
+    ///
+    /// The capability is registered under a newly generated, unique registration ID, like with
+    /// [`Client::register_method`]. See [`CapabilityRegistration`] for how it is unregistered, and
+    /// [`Client::register_capability`] for more details.
+    ///
+    /// If `method` is already registered with the same `register_options`, this returns `Err` with
+    /// JSON-RPC error code `-32602` (invalid params) instead of registering it twice.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tower_lsp::jsonrpc::Result;
+    /// use tower_lsp::{CapabilityRegistration, Client};
+    /// use serde_json::json;
+    ///
+    /// struct WorkspaceFolder {
+    ///     // Unregistered along with the folder.
+    ///     watcher: CapabilityRegistration,
+    /// }
+    ///
+    /// async fn add_folder(client: &Client, path: &str) -> Result<WorkspaceFolder> {
+    ///     let options = json!({ "watchers": [{ "globPattern": format!("{path}/**/*.rs") }] });
+    ///     let method = "workspace/didChangeWatchedFiles";
+    ///     let watcher = client.register_scoped(method, Some(options)).await?;
+    ///     Ok(WorkspaceFolder { watcher })
+    /// }
+    /// ```
+    pub async fn register_scoped<M>(
+        &self,
+        method: M,
+        register_options: Option<Value>,
+    ) -> jsonrpc::Result<CapabilityRegistration>
+    where
+        M: Into<String>,
+    {
+        let method = method.into();
+        let duplicate = {
+            let registry = self.inner.registrations.lock().unwrap();
+            let mut registrations = registry.iter();
+            registrations
+                .find(|r| r.method == method && r.register_options == register_options)
+                .map(|r| r.id.clone())
+        };
+
+        if let Some(id) = duplicate {
+            let message = format!(
+                "`{}` is already registered with the same options, under ID `{}`",
+                method, id
+            );
+            return Err(Error::invalid_params(message));
+        }
+
+        let id = self
+            .register_method(method.clone(), register_options)
+            .await?;
+        Ok(CapabilityRegistration::new(self.clone(), id, method))
+    }
+
+    /// Registers the given file `operations` with the client, in a single request.
+    ///
+    /// Each operation is registered under a newly generated, unique registration ID, using the
+    /// same `options`. These are usually built with
+    /// [`FileOperationFilters`](crate::file_operations::FileOperationFilters).
+    ///
+    /// Returns the generated IDs, in the order of `operations`. See
+    /// [`Client::register_capability`] for more details.
+    ///
+    /// # Capabilities
+    ///
+    /// This [fails early](Client#capabilities) unless the client advertised
+    /// `workspace.fileOperations.dynamicRegistration`. Such clients may still honor file operations
+    /// advertised in the server capabilities, see
+    /// [`FileOperationFilters::capabilities`](crate::file_operations::FileOperationFilters::capabilities).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use tower_lsp::file_operations::{FileOperation, FileOperationFilters};
+    /// use tower_lsp::jsonrpc::Result;
+    /// use tower_lsp::Client;
+    ///
+    /// async fn watch_renames(client: &Client) -> Result<Vec<String>> {
+    ///     let options = FileOperationFilters::new()
+    ///         .files("**/*.rs")
+    ///         .scheme("file")
+    ///         .finish()
+    ///         .expect("valid glob patterns");
+    ///
+    ///     let operations = [FileOperation::WillRename, FileOperation::DidRename];
+    ///     client.register_file_operations(operations, options).await
+    /// }
+    /// ```
+    pub async fn register_file_operations<I>(
+        &self,
+        operations: I,
+        options: FileOperationRegistrationOptions,
+    ) -> jsonrpc::Result<Vec<String>>
+    where
+        I: IntoIterator<Item = FileOperation>,
+    {
+        use lsp_types::request::RegisterCapability;
+
+        self.check_workspace_capability::<RegisterCapability, _>(|workspace| {
+            let file_operations = workspace.file_operations.as_ref();
+            file_operations.and_then(|f| f.dynamic_registration)
+        })?;
+
+        let options = serde_json::to_value(options).unwrap();
+        let registrations: Vec<_> = operations
+            .into_iter()
+            .map(|operation| Registration {
+                id: self.next_registration_id(),
+                method: operation.method().into(),
+                register_options: Some(options.clone()),
+            })
+            .collect();
+
+        let ids = registrations.iter().map(|r| r.id.clone()).collect();
+        self.register_capability(registrations).await?;
+        Ok(ids)
+    }
+
+    /// Returns all capabilities currently registered with the client through this `Client`.
+    ///
+    /// This reflects every successful [`Client::register_capability`] and
+    /// [`Client::unregister_capability`] call, in registration order.
+    pub fn registrations(&self) -> Vec<Registration> {
+        self.inner.registrations.lock().unwrap().clone()
+    }
+
+    /// Returns `true` if at least one capability is currently registered for `method`.
+    ///
+    /// This is useful to avoid registering the same capability twice.
+    pub fn is_registered(&self, method: &str) -> bool {
+        let registry = self.inner.registrations.lock().unwrap();
+        registry.iter().any(|r| r.method == method)
     }
 
     /// Unregisters a capability with the client.
     ///
+    /// Once the client accepts the request, the `unregisterations` are removed from the
+    /// registrations returned by [`Client::registrations`].
+    ///
     /// This corresponds to the [`client/unregisterCapability`] request.
     ///
     /// [`client/unregisterCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability
@@ -117,8 +448,50 @@ impl Client {
         unregisterations: Vec<Unregistration>,
     ) -> jsonrpc::Result<()> {
         use lsp_types::request::UnregisterCapability;
-        self.send_request::<UnregisterCapability>(UnregistrationParams { unregisterations })
-            .await
+        let ids: Vec<_> = unregisterations.iter().map(|u| u.id.clone()).collect();
+        let unregisterations = self.unregister_locally(unregisterations);
+        if !unregisterations.is_empty() || ids.is_empty() {
+            let params = UnregistrationParams { unregisterations };
+            self.send_request::<UnregisterCapability>(params).await?;
+        }
+
+        let mut registry = self.inner.registrations.lock().unwrap();
+        registry.retain(|r| !ids.contains(&r.id));
+        Ok(())
+    }
+
+    /// Notifies the client to log a trace message, honoring the client-requested trace level.
+    ///
+    /// If the trace level is `off`, no notification is sent. If the trace level is `messages`, the
+    /// `verbose` string is omitted even if provided.
+    ///
+    /// This corresponds to the [`$/logTrace`] notification.
+    ///
+    /// [`$/logTrace`]: https://microsoft.github.io/language-server-protocol/specification#logTrace
+    ///
+    /// # Initialization
+    ///
+    /// This notification will only be sent if the server is initialized, unless early notifications
+    /// are buffered with
+    /// [`LspServiceBuilder::buffer_early_notifications`](crate::LspServiceBuilder::buffer_early_notifications).
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.16.0.
+    pub async fn log_trace<M: Display>(&self, message: M, verbose: Option<String>) {
+        use lsp_types::notification::LogTrace;
+
+        let verbose = match self.trace_level() {
+            TraceValue::Off => return,
+            TraceValue::Messages => None,
+            TraceValue::Verbose => verbose,
+        };
+
+        self.send_notification::<LogTrace>(LogTraceParams {
+            message: message.to_string(),
+            verbose,
+        })
+        .await;
     }
 
     // Window Features
@@ -160,6 +533,82 @@ impl Client {
         .await
     }
 
+    /// Requests the client to display a particular message in the user interface, offering a list
+    /// of actions which each carry a custom `T` payload.
+    ///
+    /// Each action is a `(title, data)` pair. If the client advertised
+    /// `window.showMessage.messageActionItem.additionalPropertiesSupport` during initialization,
+    /// `data` is sent along as additional properties of the corresponding [`MessageActionItem`],
+    /// so `T` must serialize to a JSON object. Returns the title and payload of the action selected
+    /// by the user, if any.
+    ///
+    /// The payload is deserialized from the additional properties echoed back by the client. For
+    /// clients lacking support for them, the selection is matched against `actions` by title.
+    ///
+    /// This corresponds to the [`window/showMessageRequest`] request.
+    ///
+    /// [`window/showMessageRequest`]: https://microsoft.github.io/language-server-protocol/specification#window_showMessageRequest
+    pub async fn show_message_request_with<M, T>(
+        &self,
+        typ: MessageType,
+        message: M,
+        mut actions: Vec<(String, T)>,
+    ) -> jsonrpc::Result<Option<(String, T)>>
+    where
+        M: Display,
+        T: Serialize + DeserializeOwned,
+    {
+        let supported = self
+            .inner
+            .state
+            .client_capabilities()
+            .map_or(false, |caps| {
+                let window = caps.window.as_ref();
+                window
+                    .and_then(|w| w.show_message.as_ref())
+                    .and_then(|c| c.message_action_item.as_ref())
+                    .and_then(|c| c.additional_properties_support)
+                    .unwrap_or(false)
+            });
+
+        let mut items = Vec::with_capacity(actions.len());
+        for (title, data) in &actions {
+            let properties = if supported {
+                serde_json::to_value(data)
+                    .and_then(serde_json::from_value)
+                    .map_err(|e| Error {
+                        code: ErrorCode::InternalError,
+                        message: format!("invalid action item properties: {e}").into(),
+                        data: None,
+                    })?
+            } else {
+                HashMap::new()
+            };
+
+            items.push(MessageActionItem {
+                title: title.clone(),
+                properties,
+            });
+        }
+
+        let selected = match self.show_message_request(typ, message, Some(items)).await? {
+            Some(selected) => selected,
+            None => return Ok(None),
+        };
+
+        if !selected.properties.is_empty() {
+            let data = serde_json::to_value(&selected.properties).and_then(serde_json::from_value);
+            if let Ok(data) = data {
+                return Ok(Some((selected.title, data)));
+            }
+        }
+
+        let index = actions
+            .iter()
+            .position(|(title, _)| *title == selected.title);
+        Ok(index.map(|i| actions.swap_remove(i)))
+    }
+
     /// Notifies the client to log a particular message.
     ///
     /// This corresponds to the [`window/logMessage`] notification.
@@ -189,15 +638,57 @@ impl Client {
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
     ///
+    /// # Capabilities
+    ///
+    /// This [fails early](Client#capabilities) unless the client advertised
+    /// `window.showDocument.support`.
+    ///
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.16.0.
     pub async fn show_document(&self, params: ShowDocumentParams) -> jsonrpc::Result<bool> {
         use lsp_types::request::ShowDocument;
+
+        self.check_capability::<ShowDocument, _>(|caps| {
+            let window = caps.window.as_ref();
+            window
+                .and_then(|w| w.show_document.as_ref())
+                .map_or(false, |c| c.support)
+        })?;
+
         let response = self.send_request::<ShowDocument>(params).await?;
         Ok(response.success)
     }
 
+    /// Asks the client to open the document referenced by `uri`, focus it, and select `range`.
+    ///
+    /// This is a convenience wrapper around [`Client::show_document`]. See its documentation for
+    /// more details.
+    pub async fn show_document_at(&self, uri: Url, range: Range) -> jsonrpc::Result<bool> {
+        self.show_document(ShowDocumentParams {
+            uri,
+            external: None,
+            take_focus: Some(true),
+            selection: Some(range),
+        })
+        .await
+    }
+
+    /// Asks the client to open the resource referenced by `uri` in an external program, e.g. a
+    /// web browser for `https://` URIs.
+    ///
+    /// This is a convenience wrapper around [`Client::show_document`]. See its documentation for
+    /// more details.
+    pub async fn open_external(&self, uri: Url) -> jsonrpc::Result<bool> {
+        self.show_document(ShowDocumentParams {
+            uri,
+            external: Some(true),
+            take_focus: None,
+            selection: None,
+        })
+        .await
+    }
+
     // TODO: Add `work_done_progress_create()` here (since 3.15.0) when supported by `tower-lsp`.
     // https://github.com/ebkalderon/tower-lsp/issues/176
 
@@ -220,6 +711,57 @@ impl Client {
         }
     }
 
+    /// Notifies the client of a typed telemetry `event`, timestamped with the current time.
+    ///
+    /// The event may be skipped or held back according to [`Client::set_telemetry_sampling`] and
+    /// [`Client::set_telemetry_batch_size`]. See the [`telemetry`](crate::telemetry) module for
+    /// the format of the payload.
+    ///
+    /// This corresponds to the [`telemetry/event`] notification.
+    ///
+    /// [`telemetry/event`]: https://microsoft.github.io/language-server-protocol/specification#telemetry_event
+    pub async fn send_telemetry<E: TelemetryEvent>(&self, event: &E) {
+        if !self.inner.telemetry.sample(E::NAME) {
+            return;
+        }
+
+        match crate::telemetry::payload(event) {
+            Err(e) => error!("invalid JSON in `{}` telemetry event: {}", E::NAME, e),
+            Ok(payload) => {
+                if let Some(payload) = self.inner.telemetry.push(payload) {
+                    self.telemetry_event(payload).await;
+                }
+            }
+        }
+    }
+
+    /// Only sends one in every `one_in` telemetry events of type `E` with
+    /// [`Client::send_telemetry`], starting with the first one.
+    ///
+    /// Setting `one_in` to `1` or `0` sends every event again.
+    pub fn set_telemetry_sampling<E: TelemetryEvent>(&self, one_in: u32) {
+        self.inner.telemetry.set_sampling(E::NAME, one_in);
+    }
+
+    /// Sends the telemetry events of [`Client::send_telemetry`] in batches of `size` events.
+    ///
+    /// Each batch is sent as a single `telemetry/event` notification holding an array of events,
+    /// as soon as it is full. Since events may otherwise be held back indefinitely, call
+    /// [`Client::flush_telemetry`] to send an incomplete batch, e.g. when shutting down. Setting
+    /// `size` to `1` or `0` sends every event on its own again, after the events batched so far.
+    pub fn set_telemetry_batch_size(&self, size: usize) {
+        self.inner.telemetry.set_batch_size(size);
+    }
+
+    /// Sends the telemetry events batched so far, if any.
+    ///
+    /// See [`Client::set_telemetry_batch_size`] for more details.
+    pub async fn flush_telemetry(&self) {
+        if let Some(batch) = self.inner.telemetry.take() {
+            self.telemetry_event(batch).await;
+        }
+    }
+
     /// Asks the client to refresh the code lenses currently shown in editors. As a result, the
     /// client should ask the server to recompute the code lenses for these editors.
     ///
@@ -248,6 +790,16 @@ impl Client {
         self.send_request::<CodeLensRefresh>(()).await
     }
 
+    /// Like [`Client::code_lens_refresh`], but [fails early](Client#capabilities) unless the client
+    /// advertised `workspace.codeLens.refreshSupport`.
+    pub async fn try_code_lens_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::CodeLensRefresh;
+        self.check_workspace_capability::<CodeLensRefresh, _>(|w| {
+            w.code_lens.as_ref().and_then(|c| c.refresh_support)
+        })?;
+        self.code_lens_refresh().await
+    }
+
     /// Asks the client to refresh the editors for which this server provides semantic tokens. As a
     /// result, the client should ask the server to recompute the semantic tokens for these
     /// editors.
@@ -275,6 +827,16 @@ impl Client {
         self.send_request::<SemanticTokensRefresh>(()).await
     }
 
+    /// Like [`Client::semantic_tokens_refresh`], but [fails early](Client#capabilities) unless the
+    /// client advertised `workspace.semanticTokens.refreshSupport`.
+    pub async fn try_semantic_tokens_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::SemanticTokensRefresh;
+        self.check_workspace_capability::<SemanticTokensRefresh, _>(|w| {
+            w.semantic_tokens.as_ref().and_then(|c| c.refresh_support)
+        })?;
+        self.semantic_tokens_refresh().await
+    }
+
     /// Asks the client to refresh the inline values currently shown in editors. As a result, the
     /// client should ask the server to recompute the inline values for these editors.
     ///
@@ -301,6 +863,16 @@ impl Client {
         self.send_request::<InlineValueRefreshRequest>(()).await
     }
 
+    /// Like [`Client::inline_value_refresh`], but [fails early](Client#capabilities) unless the
+    /// client advertised `workspace.inlineValue.refreshSupport`.
+    pub async fn try_inline_value_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::InlineValueRefreshRequest;
+        self.check_workspace_capability::<InlineValueRefreshRequest, _>(|w| {
+            w.inline_value.as_ref().and_then(|c| c.refresh_support)
+        })?;
+        self.inline_value_refresh().await
+    }
+
     /// Asks the client to refresh the inlay hints currently shown in editors. As a result, the
     /// client should ask the server to recompute the inlay hints for these editors.
     ///
@@ -327,6 +899,16 @@ impl Client {
         self.send_request::<InlayHintRefreshRequest>(()).await
     }
 
+    /// Like [`Client::inlay_hint_refresh`], but [fails early](Client#capabilities) unless the
+    /// client advertised `workspace.inlayHint.refreshSupport`.
+    pub async fn try_inlay_hint_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::InlayHintRefreshRequest;
+        self.check_workspace_capability::<InlayHintRefreshRequest, _>(|w| {
+            w.inlay_hint.as_ref().and_then(|c| c.refresh_support)
+        })?;
+        self.inlay_hint_refresh().await
+    }
+
     /// Asks the client to refresh all needed document and workspace diagnostics.
     ///
     /// This is useful if a server detects a project wide configuration change which requires a
@@ -347,18 +929,75 @@ impl Client {
     ///
     /// This request was introduced in specification version 3.17.0.
     pub async fn workspace_diagnostic_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::WorkspaceDiagnosticRefresh;
         self.send_request::<WorkspaceDiagnosticRefresh>(()).await
     }
 
+    /// Like [`Client::workspace_diagnostic_refresh`], but [fails early](Client#capabilities) unless
+    /// the client advertised `workspace.diagnostic.refreshSupport`.
+    pub async fn try_workspace_diagnostic_refresh(&self) -> jsonrpc::Result<()> {
+        use lsp_types::request::WorkspaceDiagnosticRefresh;
+        self.check_workspace_capability::<WorkspaceDiagnosticRefresh, _>(|w| {
+            w.diagnostic.as_ref().and_then(|c| c.refresh_support)
+        })?;
+        self.workspace_diagnostic_refresh().await
+    }
+
+    /// Asks the client to refresh the folding ranges currently shown in editors. As a result, the
+    /// client should ask the server to recompute the folding ranges for these editors.
+    ///
+    /// This is useful if a server detects a configuration change which requires a re-calculation
+    /// of all folding ranges. Note that the client still has the freedom to delay the
+    /// re-calculation of the folding ranges if for example an editor is currently not visible.
+    ///
+    /// This corresponds to the [`workspace/foldingRange/refresh`] request.
+    ///
+    /// [`workspace/foldingRange/refresh`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.18/specification/#workspace_foldingRange_refresh
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Compatibility
+    ///
+    /// This request was introduced in specification version 3.18.0, and requires the `proposed`
+    /// feature.
+    #[cfg(feature = "proposed")]
+    pub async fn folding_range_refresh(&self) -> jsonrpc::Result<()> {
+        self.send_request::<proposed::FoldingRangeRefresh>(()).await
+    }
+
+    /// Like [`Client::folding_range_refresh`], but [fails early](Client#capabilities) unless the
+    /// client advertised `workspace.foldingRange.refreshSupport`.
+    #[cfg(feature = "proposed")]
+    pub async fn try_folding_range_refresh(&self) -> jsonrpc::Result<()> {
+        use self::proposed::FoldingRangeRefresh;
+        self.check_raw_capability::<FoldingRangeRefresh>("/workspace/foldingRange/refreshSupport")?;
+        self.folding_range_refresh().await
+    }
+
     /// Submits validation diagnostics for an open file with the given URI.
     ///
     /// This corresponds to the [`textDocument/publishDiagnostics`] notification.
     ///
     /// [`textDocument/publishDiagnostics`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_publishDiagnostics
     ///
+    /// # Document versions
+    ///
+    /// If document version tracking is enabled with
+    /// [`LspServiceBuilder::track_document_versions`](crate::LspServiceBuilder::track_document_versions),
+    /// diagnostics published without a `version` are tagged with the latest known version of the
+    /// document, and diagnostics for a version older than the latest known one are silently
+    /// dropped.
+    ///
     /// # Initialization
     ///
-    /// This notification will only be sent if the server is initialized.
+    /// This notification will only be sent if the server is initialized, unless early notifications
+    /// are buffered with
+    /// [`LspServiceBuilder::buffer_early_notifications`](crate::LspServiceBuilder::buffer_early_notifications).
     pub async fn publish_diagnostics(
         &self,
         uri: Url,
@@ -366,6 +1005,19 @@ impl Client {
         version: Option<i32>,
     ) {
         use lsp_types::notification::PublishDiagnostics;
+
+        let version = match (version, self.document_version(&uri)) {
+            (Some(version), Some(latest)) if version < latest => {
+                debug!(
+                    "dropping diagnostics for version {} of {}, latest is {}",
+                    version, uri, latest
+                );
+                return;
+            }
+            (None, latest) => latest,
+            (version, _) => version,
+        };
+
         self.send_notification::<PublishDiagnostics>(PublishDiagnosticsParams::new(
             uri, diags, version,
         ))
@@ -404,6 +1056,17 @@ impl Client {
             .await
     }
 
+    /// Like [`Client::configuration`], but [fails early](Client#capabilities) unless the client
+    /// advertised `workspace.configuration`.
+    pub async fn try_configuration(
+        &self,
+        items: Vec<ConfigurationItem>,
+    ) -> jsonrpc::Result<Vec<Value>> {
+        use lsp_types::request::WorkspaceConfiguration;
+        self.check_workspace_capability::<WorkspaceConfiguration, _>(|w| w.configuration)?;
+        self.configuration(items).await
+    }
+
     /// Fetches the current open list of workspace folders.
     ///
     /// Returns `None` if only a single file is open in the tool. Returns an empty `Vec` if a
@@ -428,6 +1091,14 @@ impl Client {
         self.send_request::<WorkspaceFoldersRequest>(()).await
     }
 
+    /// Like [`Client::workspace_folders`], but [fails early](Client#capabilities) unless the client
+    /// advertised `workspace.workspaceFolders`.
+    pub async fn try_workspace_folders(&self) -> jsonrpc::Result<Option<Vec<WorkspaceFolder>>> {
+        use lsp_types::request::WorkspaceFoldersRequest;
+        self.check_workspace_capability::<WorkspaceFoldersRequest, _>(|w| w.workspace_folders)?;
+        self.workspace_folders().await
+    }
+
     /// Requests a workspace resource be edited on the client side and returns whether the edit was
     /// applied.
     ///
@@ -450,6 +1121,17 @@ impl Client {
             .await
     }
 
+    /// Like [`Client::apply_edit`], but [fails early](Client#capabilities) unless the client
+    /// advertised `workspace.applyEdit`.
+    pub async fn try_apply_edit(
+        &self,
+        edit: WorkspaceEdit,
+    ) -> jsonrpc::Result<ApplyWorkspaceEditResponse> {
+        use lsp_types::request::ApplyWorkspaceEdit;
+        self.check_workspace_capability::<ApplyWorkspaceEdit, _>(|w| w.apply_edit)?;
+        self.apply_edit(edit).await
+    }
+
     /// Starts a stream of `$/progress` notifications for a client-provided [`ProgressToken`].
     ///
     /// This method also takes a `title` argument briefly describing the kind of operation being
@@ -498,55 +1180,378 @@ impl Client {
         Progress::new(self.clone(), token, title.into())
     }
 
-    /// Sends a custom notification to the client.
+    /// Wraps `items` into a stream reporting how many of them were consumed as `$/progress`.
+    ///
+    /// The returned [`ProgressIter`] yields the same items as `items`. It starts a bounded
+    /// progress titled `title` when first polled, reports the percentage of items consumed every
+    /// time it changes, and ends the progress once all items have been consumed. This is a
+    /// shorthand for the common pattern of reporting progress of a loop with [`Client::progress`].
     ///
     /// # Initialization
     ///
-    /// This notification will only be sent if the server is initialized.
-    pub async fn send_notification<N>(&self, params: N::Params)
-    where
-        N: lsp_types::notification::Notification,
-    {
-        if let State::Initialized | State::ShutDown = self.inner.state.get() {
-            self.send_notification_unchecked::<N>(params).await;
-        } else {
-            let msg = Request::from_notification::<N>(params);
-            trace!("server not initialized, supressing message: {}", msg);
-        }
-    }
-
-    async fn send_notification_unchecked<N>(&self, params: N::Params)
+    /// These notifications will only be sent if the server is initialized.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use futures::StreamExt;
+    /// # use tower_lsp::{lsp_types::*, Client};
+    /// #
+    /// # async fn index(client: &Client, token: ProgressToken, files: Vec<Url>) {
+    /// let mut files = client.progress_iter(token, "Indexing", files);
+    /// while let Some(uri) = files.next().await {
+    ///     // Index `uri`...
+    /// }
+    /// # }
+    /// ```
+    pub fn progress_iter<T, I>(
+        &self,
+        token: ProgressToken,
+        title: T,
+        items: I,
+    ) -> ProgressIter<I::IntoIter>
     where
-        N: lsp_types::notification::Notification,
+        T: Into<String>,
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator,
     {
-        let request = Request::from_notification::<N>(params);
-        if self.clone().call(request).await.is_err() {
-            error!("failed to send notification");
-        }
+        ProgressIter::new(self.clone(), token, title.into(), items.into_iter())
     }
 
-    /// Sends a custom request to the client.
+    /// Starts streaming the results of the `workspace/diagnostic` request described by `params`.
+    ///
+    /// The returned [`WorkspaceDiagnosticStream`] handles the bookkeeping of the
+    /// `previousResultIds` sent by the client, as well as the encoding of partial results as
+    /// `$/progress` notifications if the client supports them.
     ///
     /// # Initialization
     ///
-    /// If the request is sent to the client before the server has been initialized, this will
-    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    /// Partial results will only be sent if the server is initialized.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use tower_lsp::{jsonrpc::Result, lsp_types::*, Client};
+    /// #
+    /// # struct Mock {
+    /// #     client: Client,
+    /// # }
+    /// #
+    /// # impl Mock {
+    /// # fn compute(&self, uri: &Url) -> (String, Vec<Diagnostic>) { unimplemented!() }
+    /// # async fn workspace_diagnostic(
+    /// #     &self,
+    /// #     params: WorkspaceDiagnosticParams,
+    /// # ) -> Result<WorkspaceDiagnosticReportResult> {
+    /// # let documents: Vec<Url> = Vec::new();
+    /// let mut stream = self.client.workspace_diagnostics(&params);
+    ///
+    /// for uri in documents {
+    ///     let (result_id, diagnostics) = self.compute(&uri);
+    ///     stream.push(uri, None, Some(result_id), diagnostics).await;
+    /// }
+    ///
+    /// Ok(stream.finish().await)
+    /// # }
+    /// # }
+    /// ```
+    pub fn workspace_diagnostics(
+        &self,
+        params: &WorkspaceDiagnosticParams,
+    ) -> WorkspaceDiagnosticStream {
+        WorkspaceDiagnosticStream::new(self.clone(), params)
+    }
+
+    /// Limits the notification `N` to at most `max` messages sent to the client every `per`.
+    ///
+    /// Notifications exceeding the limit are silently dropped, and a warning summarizing how many
+    /// were dropped is logged once the next interval begins. This protects editors from floods of
+    /// e.g. [`window/logMessage`] notifications while the server is stuck in a pathological loop.
+    ///
+    /// The limit is shared by all clones of this `Client` and replaces any previous limit set on
+    /// `N`. No notifications are limited by default.
+    ///
+    /// [`window/logMessage`]: https://microsoft.github.io/language-server-protocol/specification#window_logMessage
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use tower_lsp::{lsp_types::*, Client};
+    /// #
+    /// # fn configure(client: &Client) {
+    /// use lsp_types::notification::LogMessage;
+    ///
+    /// client.set_rate_limit::<LogMessage>(20, Duration::from_secs(1));
+    /// # }
+    /// ```
+    pub fn set_rate_limit<N>(&self, max: u32, per: Duration)
+    where
+        N: lsp_types::notification::Notification,
+    {
+        self.inner.rate_limits.insert(N::METHOD, max, per);
+    }
+
+    /// Removes the rate limit previously set on the notification `N` with
+    /// [`Client::set_rate_limit`], if any.
+    pub fn remove_rate_limit<N>(&self)
+    where
+        N: lsp_types::notification::Notification,
+    {
+        self.inner.rate_limits.remove(N::METHOD);
+    }
+
+    /// Sends a custom notification to the client.
+    ///
+    /// # Initialization
+    ///
+    /// This notification will only be sent if the server is initialized, unless early notifications
+    /// are buffered with
+    /// [`LspServiceBuilder::buffer_early_notifications`](crate::LspServiceBuilder::buffer_early_notifications).
+    pub async fn send_notification<N>(&self, params: N::Params)
+    where
+        N: lsp_types::notification::Notification,
+    {
+        match self.inner.state.check(N::METHOD, CAN_SEND) {
+            Ok(()) => self.send_notification_unchecked::<N>(params).await,
+            Err(err) => {
+                let msg = Request::from_notification::<N>(params);
+                let msg = if err.is_not_initialized() {
+                    self.queue_early_notification(msg)
+                } else {
+                    Err(msg)
+                };
+
+                if let Err(msg) = msg {
+                    trace!("{}, suppressing message: {}", err, msg);
+                }
+            }
+        }
+    }
+
+    async fn send_notification_unchecked<N>(&self, params: N::Params)
+    where
+        N: lsp_types::notification::Notification,
+    {
+        if !self.inner.rate_limits.check(N::METHOD) {
+            return;
+        }
+
+        let request = Request::from_notification::<N>(params);
+        if self.clone().call(request).await.is_err() {
+            error!("failed to send notification");
+        }
+    }
+
+    /// Waits until all messages previously sent through this `Client`, or any of its clones, have
+    /// been written to the output stream.
+    ///
+    /// Some clients rely on notifications arriving before the response to a related request, e.g.
+    /// expecting fresh diagnostics before a code action response. Since handlers run concurrently
+    /// and responses are written independently of server-to-client messages, calling this method
+    /// before returning from the request handler guarantees that ordering.
+    ///
+    /// When serving through a custom transport instead of [`Server`](crate::Server), a message
+    /// counts as written once the transport asks the [`ClientSocket`] for the next message. This
+    /// resolves early if the socket is dropped, since no more messages can be written then.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::Client;
+    /// # use tower_lsp::lsp_types::*;
+    /// #
+    /// # async fn code_action(client: &Client, uri: Url, diagnostics: Vec<Diagnostic>) {
+    /// client.publish_diagnostics(uri, diagnostics, None).await;
+    /// client.flush().await;
+    /// // The diagnostics have reached the client, so it is now safe to respond.
+    /// # }
+    /// ```
+    pub async fn flush(&self) {
+        self.inner.delivery.flush().await;
+    }
+
+    /// Sends a custom request to the client.
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]). Once the server
+    /// has exited, the error code is `-32600` (invalid request) instead. See [`StateError`] for
+    /// details.
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`StateError`]: crate::StateError
     pub async fn send_request<R>(&self, params: R::Params) -> jsonrpc::Result<R::Result>
     where
         R: lsp_types::request::Request,
     {
-        if let State::Initialized | State::ShutDown = self.inner.state.get() {
-            self.send_request_unchecked::<R>(params).await
-        } else {
-            let id = self.inner.request_id.load(Ordering::SeqCst) as i64 + 1;
-            let msg = Request::from_request::<R>(id.into(), params);
-            trace!("server not initialized, supressing message: {}", msg);
-            Err(jsonrpc::not_initialized_error())
+        if let Err(err) = self.inner.state.check(R::METHOD, CAN_SEND) {
+            let msg = Request::from_request::<R>(Id::Null, params);
+            trace!("{}, suppressing message: {}", err, msg);
+            return Err(err.into());
+        }
+
+        self.send_request_unchecked::<R>(params).await
+    }
+
+    /// Returns a view of this client which retries requests failing with transient errors,
+    /// according to the given `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::{Client, RetryPolicy};
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use serde_json::Value;
+    /// #
+    /// # async fn settings(client: &Client) -> Result<Vec<Value>> {
+    /// let policy = RetryPolicy::new(|delay| async move {
+    ///     smol::Timer::after(delay).await;
+    /// });
+    ///
+    /// let item = ConfigurationItem {
+    ///     scope_uri: None,
+    ///     section: Some("my-server".into()),
+    /// };
+    /// client.with_retry(policy).configuration(vec![item]).await
+    /// # }
+    /// ```
+    pub fn with_retry(&self, policy: RetryPolicy) -> RetryingClient<'_> {
+        RetryingClient::new(self, policy)
+    }
+
+    fn next_registration_id(&self) -> String {
+        let id = self.inner.registration_id.fetch_add(1, Ordering::Relaxed);
+        format!("tower-lsp/{id}")
+    }
+
+    /// Unregisters a capability without waiting for the client to accept the request.
+    ///
+    /// Nothing is sent if the capability is no longer registered, e.g. because it was already
+    /// unregistered with [`Client::unregister_capability`] or the session has ended.
+    fn unregister_detached(&self, unregistration: Unregistration) {
+        use lsp_types::request::{Request as _, UnregisterCapability};
+
+        let mut registry = self.inner.registrations.lock().unwrap();
+        if !registry.iter().any(|r| r.id == unregistration.id) {
+            return;
+        }
+        registry.retain(|r| r.id != unregistration.id);
+        drop(registry);
+
+        let params = UnregistrationParams {
+            unregisterations: vec![unregistration],
+        };
+        if let Err(err) = self
+            .inner
+            .state
+            .check(UnregisterCapability::METHOD, CAN_SEND)
+        {
+            let msg = Request::from_request::<UnregisterCapability>(Id::Null, params);
+            trace!("{}, suppressing message: {}", err, msg);
+            return;
+        }
+
+        let id = self.next_request_id();
+        let mut request = Request::from_request::<UnregisterCapability>(id.clone(), params);
+        self.prepare_outgoing(&mut request);
+
+        // The response is discarded once it arrives.
+        drop(self.inner.pending.wait(id));
+        let mut tx = self.inner.tx.clone();
+        if self.inner.delivery.queue(|| tx.try_send(request)).is_err() {
+            error!("failed to send request");
+        }
+    }
+
+    /// Hands the `registrations` the client can't handle over to the local file watcher, and
+    /// returns the rest.
+    #[cfg(feature = "file-watcher")]
+    fn register_locally(&self, mut registrations: Vec<Registration>) -> Vec<Registration> {
+        let watcher = self.inner.state.file_watcher();
+        if watcher.is_fallback(self.inner.state.client_capabilities().as_deref()) {
+            registrations.retain(|registration| !watcher.register(registration));
+        }
+        registrations
+    }
+
+    #[cfg(not(feature = "file-watcher"))]
+    fn register_locally(&self, registrations: Vec<Registration>) -> Vec<Registration> {
+        registrations
+    }
+
+    /// Removes the `unregisterations` of the local file watcher, and returns the rest.
+    #[cfg(feature = "file-watcher")]
+    fn unregister_locally(&self, mut unregisterations: Vec<Unregistration>) -> Vec<Unregistration> {
+        let watcher = self.inner.state.file_watcher();
+        unregisterations.retain(|u| !watcher.unregister(&u.id));
+        unregisterations
+    }
+
+    #[cfg(not(feature = "file-watcher"))]
+    fn unregister_locally(&self, unregisterations: Vec<Unregistration>) -> Vec<Unregistration> {
+        unregisterations
+    }
+
+    /// Attaches the trace context to the outgoing `req` and traces it.
+    ///
+    /// In dev mode, `req` is also checked against the client capabilities.
+    fn prepare_outgoing(&self, req: &mut Request) {
+        if let Some(ctx) = self.inner.state.trace_context() {
+            super::trace_context::inject(&*ctx, req);
+        }
+
+        if self.inner.dev_mode.load(Ordering::SeqCst) {
+            let caps = self.inner.state.client_capabilities();
+            if let Some(violation) = caps.and_then(|caps| dev_mode::check_outgoing(req, &caps)) {
+                self.report_violation(&violation);
+            }
+        }
+
+        self.inner.state.trace_message("->", &*req);
+    }
+
+    /// Returns `Err` if the client has advertised its capabilities, but `supported` returns `false`
+    /// for them.
+    fn check_capability<R, F>(&self, supported: F) -> jsonrpc::Result<()>
+    where
+        R: lsp_types::request::Request,
+        F: FnOnce(&ClientCapabilities) -> bool,
+    {
+        match self.inner.state.client_capabilities() {
+            Some(caps) if !supported(&caps) => Err(jsonrpc::unsupported_by_client_error(R::METHOD)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like [`Client::check_capability`], but for capabilities unknown to `lsp-types`, which are
+    /// looked up by their JSON `pointer` in the raw capabilities advertised by the client.
+    #[cfg(feature = "proposed")]
+    fn check_raw_capability<R>(&self, pointer: &str) -> jsonrpc::Result<()>
+    where
+        R: lsp_types::request::Request,
+    {
+        match self.inner.state.raw_client_capabilities() {
+            Some(caps) if caps.pointer(pointer) != Some(&Value::Bool(true)) => {
+                Err(jsonrpc::unsupported_by_client_error(R::METHOD))
+            }
+            _ => Ok(()),
         }
     }
 
+    fn check_workspace_capability<R, F>(&self, supported: F) -> jsonrpc::Result<()>
+    where
+        R: lsp_types::request::Request,
+        F: FnOnce(&WorkspaceClientCapabilities) -> Option<bool>,
+    {
+        self.check_capability::<R, _>(|caps| {
+            let workspace = caps.workspace.as_ref();
+            workspace.and_then(supported).unwrap_or(false)
+        })
+    }
+
     async fn send_request_unchecked<R>(&self, params: R::Params) -> jsonrpc::Result<R::Result>
     where
         R: lsp_types::request::Request,
@@ -554,13 +1559,7 @@ impl Client {
         let id = self.next_request_id();
         let request = Request::from_request::<R>(id, params);
 
-        let response = match self.clone().call(request).await {
-            Ok(Some(response)) => response,
-            Ok(None) | Err(_) => return Err(Error::internal_error()),
-        };
-
-        let (_, result) = response.into_parts();
-        result.and_then(|v| {
+        self.call_unchecked(request).await.and_then(|v| {
             serde_json::from_value(v).map_err(|e| Error {
                 code: ErrorCode::ParseError,
                 message: e.to_string().into(),
@@ -568,16 +1567,192 @@ impl Client {
             })
         })
     }
+
+    async fn call_unchecked(&self, request: Request) -> jsonrpc::Result<Value> {
+        let response = match self.clone().call(request).await {
+            Ok(Some(response)) => response,
+            Ok(None) | Err(_) => return Err(Error::internal_error()),
+        };
+
+        let (_, result) = response.into_parts();
+        result
+    }
+
+    /// Sends a notification with an arbitrary method name and untyped `params` to the client.
+    ///
+    /// This is useful for proprietary editor extensions which don't warrant defining a dedicated
+    /// [`Notification`](lsp_types::notification::Notification) type. Prefer
+    /// [`Client::send_notification`] otherwise.
+    ///
+    /// # Initialization
+    ///
+    /// This notification will only be sent if the server is initialized, unless early notifications
+    /// are buffered with
+    /// [`LspServiceBuilder::buffer_early_notifications`](crate::LspServiceBuilder::buffer_early_notifications).
+    pub async fn notify_raw(&self, method: &str, params: Value) {
+        let notification = Request::build(method.to_owned()).params(params).finish();
+
+        if let Err(err) = self.inner.state.check(method.to_owned(), CAN_SEND) {
+            let notification = if err.is_not_initialized() {
+                self.queue_early_notification(notification)
+            } else {
+                Err(notification)
+            };
+
+            if let Err(notification) = notification {
+                trace!("{}, suppressing message: {}", err, notification);
+            }
+
+            return;
+        }
+
+        if !self.inner.rate_limits.check(method) {
+            return;
+        }
+
+        if self.clone().call(notification).await.is_err() {
+            error!("failed to send notification");
+        }
+    }
+
+    /// Sends a request with an arbitrary method name and untyped `params` to the client, returning
+    /// the untyped `result` of the response.
+    ///
+    /// This is useful for proprietary editor extensions which don't warrant defining a dedicated
+    /// [`Request`](lsp_types::request::Request) type. Prefer [`Client::send_request`] otherwise.
+    ///
+    /// # Initialization
+    ///
+    /// This behaves like [`Client::send_request`] before the server has been initialized and after
+    /// it has exited.
+    pub async fn request_raw(&self, method: &str, params: Value) -> jsonrpc::Result<Value> {
+        if let Err(err) = self.inner.state.check(method.to_owned(), CAN_SEND) {
+            trace!("{}, suppressing request", err);
+            return Err(err.into());
+        }
+
+        let id = self.next_request_id();
+        let request = Request::build(method.to_owned())
+            .params(params)
+            .id(id)
+            .finish();
+
+        self.call_unchecked(request).await
+    }
 }
 
 impl Client {
-    /// Increments the internal request ID counter and returns the previous value.
+    /// Returns a fresh request ID, following the configured [`RequestIdStrategy`].
     ///
-    /// This method can be used to build custom [`Request`] objects with numeric IDs that are
-    /// guaranteed to be unique every time.
+    /// This method can be used to build custom [`Request`] objects with IDs that are guaranteed to
+    /// be unique every time. IDs are sequential numbers by default, see
+    /// [`LspServiceBuilder::request_ids`](crate::LspServiceBuilder::request_ids) for other schemes.
     pub fn next_request_id(&self) -> Id {
-        let num = self.inner.request_id.fetch_add(1, Ordering::Relaxed);
-        Id::Number(num as i64)
+        self.inner.request_ids.next()
+    }
+}
+
+impl Client {
+    /// Returns the trace level currently requested by the client.
+    ///
+    /// This value is initially taken from the `trace` field of [`InitializeParams`] and may be
+    /// changed by the client at any time with a [`$/setTrace`] notification. It also gates the
+    /// wire-level `trace!` logs emitted by this library, which are suppressed while it is `off`.
+    ///
+    /// [`$/setTrace`]: https://microsoft.github.io/language-server-protocol/specification#setTrace
+    pub fn trace_level(&self) -> TraceValue {
+        self.inner.state.trace()
+    }
+
+    /// Deserializes the `experimental` section of the capabilities advertised by the client.
+    ///
+    /// Returns `None` if the server is not initialized yet, if the client did not advertise any
+    /// experimental capabilities, or if they cannot be deserialized into `T`.
+    ///
+    /// See the [`experimental`](crate::experimental) module for details.
+    pub fn experimental_capability<T: DeserializeOwned>(&self) -> Option<T> {
+        let caps = self.inner.state.client_capabilities()?;
+        caps.experimental_as()
+    }
+
+    /// Returns `true` if the client advertised the experimental capability `name`, i.e. its
+    /// `experimental` capabilities contain the key `name`, set to anything but `null` or `false`.
+    pub fn has_experimental_capability(&self, name: &str) -> bool {
+        let caps = self.inner.state.client_capabilities();
+        caps.map_or(false, |caps| caps.has_experimental(name))
+    }
+
+    /// Returns the latest version of the open document `uri` reported by the client.
+    ///
+    /// This always returns `None` unless document version tracking is enabled with
+    /// [`LspServiceBuilder::track_document_versions`](crate::LspServiceBuilder::track_document_versions).
+    pub fn document_version(&self, uri: &Url) -> Option<i32> {
+        self.inner.state.document_version(uri)
+    }
+
+    /// Responds to a request detached with [`MethodContext::detach`](crate::MethodContext::detach).
+    ///
+    /// The result is sent to the client as the response to the request with the given `id`, even
+    /// though the handler of the request may have returned long ago. This lets servers proxying
+    /// requests to other processes answer them from wherever the completions arrive.
+    ///
+    /// Returns `false` if the request is not waiting for a response, e.g. because it was already
+    /// answered, was canceled by the client or was never detached in the first place.
+    pub fn respond<R: Serialize>(&self, id: &Id, result: jsonrpc::Result<R>) -> bool {
+        let result = result.and_then(|value| {
+            serde_json::to_value(value).map_err(|e| Error {
+                code: ErrorCode::InternalError,
+                message: e.to_string().into(),
+                data: None,
+            })
+        });
+
+        self.inner.detached.respond(id, result)
+    }
+
+    pub(crate) fn detach(&self, id: &Id) -> Option<DetachedRequest> {
+        self.inner.detached.detach(id.clone())
+    }
+
+    /// Returns whether the user trusts the workspace.
+    ///
+    /// This always returns `true` unless workspace trust is tracked with
+    /// [`LspServiceBuilder::workspace_trust`](crate::LspServiceBuilder::workspace_trust).
+    pub fn is_workspace_trusted(&self) -> bool {
+        self.inner.state.trust().is_trusted()
+    }
+
+    /// Sets whether the user trusts the workspace, for servers learning about it in ways other
+    /// than the settings described by [`WorkspaceTrust`](crate::WorkspaceTrust).
+    ///
+    /// This has no effect unless workspace trust is tracked.
+    pub fn set_workspace_trusted(&self, trusted: bool) {
+        self.inner.state.trust().set_trusted(trusted);
+    }
+
+    /// Returns a stream yielding the new trust of the workspace every time it changes.
+    ///
+    /// Trust is updated before the handler of the message changing it runs, so servers may, for
+    /// example, reload the project configuration they ignored while the workspace was untrusted.
+    pub fn workspace_trust_changes(&self) -> impl Stream<Item = bool> + Send + Unpin + 'static {
+        self.inner.state.trust().watch()
+    }
+
+    /// Returns the typed storage shared by everything serving this connection.
+    ///
+    /// This is the same map as [`LspService::extensions`](crate::LspService::extensions), so
+    /// handlers can read the values stored by middleware wrapping the service. See [`Extensions`]
+    /// for more details.
+    pub fn extensions(&self) -> &Extensions {
+        self.inner.state.extensions()
+    }
+
+    /// Returns the current lifecycle state of the server.
+    ///
+    /// Messages can only be sent to the client while the server is [`State::Initialized`] or
+    /// [`State::ShutDown`].
+    pub fn state(&self) -> State {
+        self.inner.state.get()
     }
 }
 
@@ -585,8 +1760,10 @@ impl Debug for Client {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Client")
             .field("tx", &self.inner.tx)
+            .field("delivery", &self.inner.delivery)
             .field("pending", &self.inner.pending)
-            .field("request_id", &self.inner.request_id)
+            .field("detached", &self.inner.detached)
+            .field("request_ids", &self.inner.request_ids)
             .field("state", &self.inner.state)
             .finish()
     }
@@ -605,12 +1782,19 @@ impl Service<Request> for Client {
             .map_err(|_| ExitedError(()))
     }
 
-    fn call(&mut self, req: Request) -> Self::Future {
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        self.prepare_outgoing(&mut req);
+
         let mut tx = self.inner.tx.clone();
+        let delivery = self.inner.delivery.clone();
         let response_waiter = req.id().cloned().map(|id| self.inner.pending.wait(id));
 
         Box::pin(async move {
-            if tx.send(req).await.is_err() {
+            if future::poll_fn(|cx| tx.poll_ready(cx)).await.is_err() {
+                return Err(ExitedError(()));
+            }
+
+            if delivery.queue(|| tx.start_send(req)).is_err() {
                 return Err(ExitedError(()));
             }
 
@@ -626,8 +1810,13 @@ impl Service<Request> for Client {
 mod tests {
     use std::future::Future;
 
+    use futures::future::Either;
     use futures::stream::StreamExt;
-    use lsp_types::notification::{LogMessage, PublishDiagnostics, ShowMessage, TelemetryEvent};
+    use lsp_types::notification::{
+        LogMessage, LogTrace, Progress, PublishDiagnostics, ShowMessage, TelemetryEvent,
+    };
+    use lsp_types::request::{Request as _, ShowMessageRequest};
+    use serde::Deserialize;
     use serde_json::json;
 
     use super::*;
@@ -669,6 +1858,48 @@ mod tests {
         assert_client_message(|p| async move { p.show_message(typ, msg).await }, expected).await;
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn progress_iter() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let token = ProgressToken::Number(1);
+        let items = client.progress_iter(token.clone(), "Indexing", vec!['a', 'b', 'c', 'd']);
+        let (items, messages) = futures::join!(
+            items.collect::<Vec<_>>(),
+            socket.take(6).collect::<Vec<_>>()
+        );
+        assert_eq!(items, vec!['a', 'b', 'c', 'd']);
+
+        let progress = |value| {
+            Request::from_notification::<Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+        };
+        let report = |percentage| {
+            progress(WorkDoneProgress::Report(WorkDoneProgressReport {
+                percentage: Some(percentage),
+                ..WorkDoneProgressReport::default()
+            }))
+        };
+        let expected = vec![
+            progress(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Indexing".into(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            })),
+            report(25),
+            report(50),
+            report(75),
+            report(100),
+            progress(WorkDoneProgress::End(WorkDoneProgressEnd { message: None })),
+        ];
+        assert_eq!(messages, expected);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn telemetry_event() {
         let null = json!(null);
@@ -689,6 +1920,522 @@ mod tests {
         assert_client_message(|p| async move { p.telemetry_event(other).await }, expected).await;
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn send_telemetry() {
+        #[derive(Serialize, crate::telemetry::TelemetryEvent)]
+        #[telemetry(crate = "crate")]
+        struct IndexCompleted {
+            files: usize,
+        }
+
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        client.set_telemetry_sampling::<IndexCompleted>(2);
+        client.set_telemetry_batch_size(3);
+        for files in 0..5 {
+            client.send_telemetry(&IndexCompleted { files }).await;
+        }
+        client.flush_telemetry().await;
+        client.flush_telemetry().await;
+        drop(client);
+
+        let messages: Vec<_> = socket.collect().await;
+        assert_eq!(messages.len(), 1);
+        let events = messages[0].params().unwrap().as_array().unwrap();
+        let files: Vec<_> = events.iter().map(|e| &e["properties"]["files"]).collect();
+        assert_eq!(files, [0, 2, 4]);
+        assert!(events.iter().all(|e| e["name"] == "index_completed"));
+        assert!(events.iter().all(|e| e["timestamp"].as_u64() > Some(0)));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn log_trace() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state.clone());
+        let send_traces = async move {
+            client.log_trace("off", None).await;
+
+            state.set_trace(TraceValue::Messages);
+            client.log_trace("messages", Some("hidden".into())).await;
+
+            state.set_trace(TraceValue::Verbose);
+            client.log_trace("verbose", Some("shown".into())).await;
+        };
+
+        let ((), messages) = futures::join!(send_traces, socket.collect::<Vec<_>>());
+        let expected: Vec<_> = [("messages", None), ("verbose", Some("shown".into()))]
+            .into_iter()
+            .map(|(message, verbose)| {
+                Request::from_notification::<LogTrace>(LogTraceParams {
+                    message: message.into(),
+                    verbose,
+                })
+            })
+            .collect();
+
+        assert_eq!(messages, expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn show_document_unsupported() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        state.set_client_capabilities(ClientCapabilities::default());
+
+        let (client, socket) = Client::new(state);
+        let uri: Url = "https://example.com/".parse().unwrap();
+        let result = client.open_external(uri).await;
+        drop(client);
+
+        let err = result.unwrap_err();
+        assert_eq!(err.code, ErrorCode::MethodNotFound);
+        assert!(err.is_unsupported_by_client());
+        assert_eq!(
+            err.data,
+            Some(json!({"unsupportedByClient": "window/showDocument"}))
+        );
+
+        let messages: Vec<_> = socket.collect().await;
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn experimental_capability() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        struct Caps {
+            status_notification: bool,
+        }
+
+        let state = Arc::new(ServerState::new());
+        let (client, _) = Client::new(state.clone());
+        assert_eq!(client.experimental_capability::<Caps>(), None);
+        assert!(!client.has_experimental_capability("statusNotification"));
+
+        state.set(State::Initialized);
+        state.set_client_capabilities(ClientCapabilities {
+            experimental: Some(json!({"statusNotification": true})),
+            ..ClientCapabilities::default()
+        });
+
+        let expected = Caps {
+            status_notification: true,
+        };
+        assert_eq!(client.experimental_capability::<Caps>(), Some(expected));
+        assert!(client.has_experimental_capability("statusNotification"));
+        assert!(!client.has_experimental_capability("colorDecorators"));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn try_inlay_hint_refresh() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        state.set_client_capabilities(ClientCapabilities::default());
+
+        let (client, socket) = Client::new(state.clone());
+        let result = client.try_inlay_hint_refresh().await;
+        drop(client);
+
+        let err = result.unwrap_err();
+        assert!(err.is_unsupported_by_client());
+        assert!(!Error::method_not_found().is_unsupported_by_client());
+
+        let messages: Vec<_> = socket.collect().await;
+        assert!(messages.is_empty());
+
+        state.set_client_capabilities(ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                inlay_hint: Some(InlayHintWorkspaceClientCapabilities {
+                    refresh_support: Some(true),
+                }),
+                ..WorkspaceClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        });
+
+        let (client, mut socket) = Client::new(state);
+        let refresh = Box::pin(client.try_inlay_hint_refresh());
+        let request = match futures::future::select(refresh, socket.next()).await {
+            Either::Left((result, _)) => panic!("request finished early: {result:?}"),
+            Either::Right((message, _)) => message.expect("no request was sent"),
+        };
+        assert_eq!(request.method(), "workspace/inlayHint/refresh");
+    }
+
+    #[cfg(feature = "proposed")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn try_folding_range_refresh() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        state.set_raw_client_capabilities(Some(json!({"workspace":{}})));
+
+        let (client, socket) = Client::new(state.clone());
+        let result = client.try_folding_range_refresh().await;
+        drop(client);
+
+        let err = result.unwrap_err();
+        assert!(err.is_unsupported_by_client());
+
+        let messages: Vec<_> = socket.collect().await;
+        assert!(messages.is_empty());
+
+        let caps = json!({"workspace":{"foldingRange":{"refreshSupport":true}}});
+        state.set_raw_client_capabilities(Some(caps));
+
+        let (client, mut socket) = Client::new(state);
+        let refresh = Box::pin(client.try_folding_range_refresh());
+        let request = match futures::future::select(refresh, socket.next()).await {
+            Either::Left((result, _)) => panic!("request finished early: {result:?}"),
+            Either::Right((message, _)) => message.expect("no request was sent"),
+        };
+        assert_eq!(request.method(), "workspace/foldingRange/refresh");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rate_limits_notifications() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        client.set_rate_limit::<LogMessage>(2, Duration::from_secs(3600));
+
+        let send_messages = async move {
+            for i in 0..5 {
+                client.log_message(MessageType::INFO, i).await;
+            }
+            client.show_message(MessageType::INFO, "hello").await;
+        };
+
+        let (_, messages) = futures::join!(send_messages, socket.collect::<Vec<_>>());
+        let methods: Vec<_> = messages.iter().map(|msg| msg.method()).collect();
+        assert_eq!(
+            methods,
+            [
+                "window/logMessage",
+                "window/logMessage",
+                "window/showMessage"
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn workspace_diagnostics() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let unchanged: Url = "file:///unchanged.rs".parse().unwrap();
+        let changed: Url = "file:///changed.rs".parse().unwrap();
+        let params = WorkspaceDiagnosticParams {
+            identifier: None,
+            previous_result_ids: vec![PreviousResultId {
+                uri: unchanged.clone(),
+                value: "1".into(),
+            }],
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams {
+                partial_result_token: Some(ProgressToken::Number(7)),
+            },
+        };
+
+        let (client, socket) = Client::new(state);
+        let stream_reports = async move {
+            let mut stream = client.workspace_diagnostics(&params).with_chunk_size(2);
+            assert_eq!(stream.previous_result_id(&unchanged), Some("1"));
+            stream.push(unchanged, None, Some("1".into()), vec![]).await;
+            stream
+                .push(changed, Some(3), Some("2".into()), vec![])
+                .await;
+            stream.finish().await
+        };
+
+        let (result, messages) = futures::join!(stream_reports, socket.collect::<Vec<_>>());
+        let empty = WorkspaceDiagnosticReport { items: vec![] };
+        assert_eq!(result, WorkspaceDiagnosticReportResult::Report(empty));
+
+        let expected = Request::build("$/progress")
+            .params(json!({
+                "token": 7,
+                "value": {
+                    "items": [
+                        {"kind": "unchanged", "uri": "file:///unchanged.rs", "version": null, "resultId": "1"},
+                        {"kind": "full", "uri": "file:///changed.rs", "version": 3, "resultId": "2", "items": []},
+                    ],
+                },
+            }))
+            .finish();
+        assert_eq!(messages, [expected]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn show_message_request_with() {
+        #[derive(Debug, PartialEq, serde::Deserialize, Serialize)]
+        struct Fix {
+            id: i32,
+        }
+
+        for supported in [false, true] {
+            let state = Arc::new(ServerState::new());
+            state.set(State::Initialized);
+            state.set_client_capabilities(ClientCapabilities {
+                window: Some(WindowClientCapabilities {
+                    show_message: Some(ShowMessageRequestClientCapabilities {
+                        message_action_item: Some(MessageActionItemCapabilities {
+                            additional_properties_support: Some(supported),
+                        }),
+                    }),
+                    ..WindowClientCapabilities::default()
+                }),
+                ..ClientCapabilities::default()
+            });
+
+            let (client, socket) = Client::new(state);
+            let (requests, responses) = socket.split();
+            let answer_requests = requests
+                .map(|req| {
+                    let actions = &req.params().unwrap()["actions"];
+                    let has_properties = actions[1].get("id").is_some();
+                    assert_eq!(has_properties, supported);
+                    let id = req.id().cloned().unwrap();
+                    Ok(Response::from_ok(id, actions[1].clone()))
+                })
+                .forward(responses);
+
+            let actions = vec![
+                ("Ignore".into(), Fix { id: 1 }),
+                ("Fix".into(), Fix { id: 2 }),
+            ];
+            let request = async move {
+                let selected = client
+                    .show_message_request_with(MessageType::INFO, "?", actions)
+                    .await;
+                drop(client);
+                selected
+            };
+            let (selected, _) = futures::join!(request, answer_requests);
+
+            assert_eq!(selected, Ok(Some(("Fix".into(), Fix { id: 2 }))));
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tracks_registrations() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let (requests, responses) = socket.split();
+        let answer_requests = requests
+            .map(|req| Ok(Response::from_ok(req.id().cloned().unwrap(), json!(null))))
+            .forward(responses);
+
+        let register = async move {
+            let first = client
+                .register_method("workspace/didChangeWatchedFiles", None)
+                .await
+                .unwrap();
+            let second = client
+                .register_method("textDocument/formatting", None)
+                .await
+                .unwrap();
+            assert_ne!(first, second);
+            assert!(client.is_registered("workspace/didChangeWatchedFiles"));
+
+            let unregistration = Unregistration {
+                id: first,
+                method: "workspace/didChangeWatchedFiles".into(),
+            };
+            client
+                .unregister_capability(vec![unregistration])
+                .await
+                .unwrap();
+            assert!(!client.is_registered("workspace/didChangeWatchedFiles"));
+
+            let registrations = client.registrations();
+            drop(client);
+            registrations
+        };
+
+        let (registrations, _) = futures::join!(register, answer_requests);
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[0].method, "textDocument/formatting");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn retries_transient_errors() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let (requests, responses) = socket.split();
+        let sent = Mutex::new(Vec::new());
+        let answer_requests = requests
+            .map(|req| {
+                let id = req.id().cloned().unwrap();
+                let mut sent = sent.lock().unwrap();
+                sent.push(req.method().to_owned());
+                let response = match (req.method(), sent.len()) {
+                    ("workspace/configuration", 1) => {
+                        Response::from_error(id, Error::new(ErrorCode::ContentModified))
+                    }
+                    ("workspace/configuration", _) => Response::from_ok(id, json!([1])),
+                    _ => Response::from_error(id, Error::internal_error()),
+                };
+                Ok(response)
+            })
+            .forward(responses);
+
+        let delays = Arc::new(Mutex::new(Vec::new()));
+        let recorded = delays.clone();
+        let policy = RetryPolicy::new(move |delay| {
+            recorded.lock().unwrap().push(delay);
+            async {}
+        });
+
+        let request = async move {
+            let retrying = client.with_retry(policy);
+            let config = retrying.configuration(vec![]).await;
+            assert_eq!(config, Ok(vec![json!(1)]));
+
+            let folders = retrying.workspace_folders().await;
+            assert_eq!(folders, Err(Error::internal_error()));
+            drop(retrying);
+            drop(client);
+        };
+
+        let ((), res) = futures::join!(request, answer_requests);
+        res.unwrap();
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(
+            sent,
+            [
+                "workspace/configuration",
+                "workspace/configuration",
+                "workspace/workspaceFolders"
+            ]
+        );
+        assert_eq!(*delays.lock().unwrap(), [Duration::from_millis(50)]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn unregisters_scoped_registrations_on_drop() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let (requests, responses) = socket.split();
+        let sent = Mutex::new(Vec::new());
+        let answer_requests = requests
+            .inspect(|req| sent.lock().unwrap().push(req.method().to_owned()))
+            .map(|req| Ok(Response::from_ok(req.id().cloned().unwrap(), json!(null))))
+            .forward(responses);
+
+        let method = "workspace/didChangeWatchedFiles";
+        let options = Some(json!({ "watchers": [] }));
+        let register = async move {
+            let watcher = client.register_scoped(method, options.clone()).await;
+            let watcher = watcher.unwrap();
+            assert_eq!(watcher.method(), method);
+
+            let duplicate = client.register_scoped(method, options.clone()).await;
+            assert_eq!(duplicate.unwrap_err().code, ErrorCode::InvalidParams);
+
+            let kept = client.register_scoped(method, None).await.unwrap().keep();
+            drop(watcher);
+
+            let registrations = client.registrations();
+            drop(client);
+            (kept, registrations)
+        };
+
+        let ((kept, registrations), _) = futures::join!(register, answer_requests);
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[0].id, kept);
+        assert_eq!(
+            *sent.lock().unwrap(),
+            [
+                "client/registerCapability",
+                "client/registerCapability",
+                "client/unregisterCapability",
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn registers_file_operations() {
+        use crate::file_operations::FileOperationFilters;
+
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let (requests, responses) = socket.split();
+        let answer_requests = requests
+            .map(|req| Ok(Response::from_ok(req.id().cloned().unwrap(), json!(null))))
+            .forward(responses);
+
+        let options = FileOperationFilters::new().files("**/*.rs").finish();
+        let options = options.unwrap();
+        let expected = options.clone();
+
+        let register = async move {
+            let operations = [FileOperation::WillRename, FileOperation::DidRename];
+            let ids = client.register_file_operations(operations, options).await;
+            let registrations = client.registrations();
+            drop(client);
+            (ids.unwrap(), registrations)
+        };
+
+        let ((ids, registrations), _) = futures::join!(register, answer_requests);
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+
+        let methods: Vec<_> = registrations.iter().map(|r| &*r.method).collect();
+        assert_eq!(
+            methods,
+            ["workspace/willRenameFiles", "workspace/didRenameFiles"]
+        );
+        let options = registrations[0].register_options.clone().unwrap();
+        assert_eq!(serde_json::from_value(options).ok(), Some(expected));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn notify_raw() {
+        let params = json!({"foo": "bar"});
+        let expected = Request::build("custom/notification")
+            .params(params.clone())
+            .finish();
+
+        assert_client_message(
+            |p| async move { p.notify_raw("custom/notification", params).await },
+            expected,
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn request_raw_before_initialization() {
+        let state = Arc::new(ServerState::new());
+        let (client, _) = Client::new(state);
+
+        let result = client.request_raw("custom/request", json!(null)).await;
+        assert_eq!(result, Err(jsonrpc::not_initialized_error()));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn request_raw_after_exit() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Exited);
+        let (client, _) = Client::new(state);
+        assert_eq!(client.state(), State::Exited);
+
+        let result = client.request_raw("custom/request", json!(null)).await;
+        assert_eq!(result, Err(Error::invalid_request()));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn publish_diagnostics() {
         let uri: Url = "file:///path/to/file".parse().unwrap();
@@ -703,4 +2450,56 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn flush_waits_for_delivery() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, mut socket) = Client::new(state);
+        client.flush().await;
+
+        client.log_message(MessageType::INFO, "foo").await;
+        let mut flush = Box::pin(client.flush());
+        assert!(futures::poll!(&mut flush).is_pending());
+
+        // The message is only written once the transport asks for the next one.
+        assert!(socket.next().await.is_some());
+        assert!(futures::poll!(&mut flush).is_pending());
+        assert!(futures::poll!(socket.next()).is_pending());
+        flush.await;
+
+        client.log_message(MessageType::INFO, "bar").await;
+        drop(socket);
+        client.flush().await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn responds_through_socket() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, mut socket) = Client::new(state.clone());
+        let request = client.show_message_request(MessageType::INFO, "foo", None);
+        let respond = async {
+            let request = socket.next_request().await.unwrap();
+            assert_eq!(request.method(), ShowMessageRequest::METHOD);
+            let params: ShowMessageRequestParams = request.params_as().unwrap();
+            assert_eq!(params.message, "foo");
+
+            let item = MessageActionItem {
+                title: "bar".into(),
+                properties: HashMap::new(),
+            };
+            let id = request.id().cloned().unwrap();
+            socket.respond(id, Ok(Some(item))).unwrap();
+        };
+
+        let (response, ()) = futures::join!(request, respond);
+        assert_eq!(response.unwrap().unwrap().title, "bar");
+
+        state.set(State::Exited);
+        assert!(socket.respond(Id::Number(0), Ok(())).is_err());
+        assert!(socket.next_request().await.is_none());
+    }
 }
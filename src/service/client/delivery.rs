@@ -0,0 +1,155 @@
+//! Types for tracking the delivery of server-to-client messages.
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+
+/// Counts the messages queued by the [`Client`](super::Client) and those written by the transport.
+pub struct Delivery {
+    queued: Mutex<u64>,
+    written: Mutex<Written>,
+}
+
+struct Written {
+    count: u64,
+    closed: bool,
+    waiters: Vec<(u64, oneshot::Sender<()>)>,
+}
+
+impl Delivery {
+    pub fn new() -> Self {
+        Delivery {
+            queued: Mutex::new(0),
+            written: Mutex::new(Written {
+                count: 0,
+                closed: false,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// Queues a message with `send`, counting it if successful.
+    ///
+    /// Messages are counted in the order they are queued, so that concurrent senders can't make a
+    /// flush wait on too few messages.
+    pub fn queue<E, F: FnOnce() -> Result<(), E>>(&self, send: F) -> Result<(), E> {
+        let mut queued = self.queued.lock().unwrap();
+        send()?;
+        *queued += 1;
+        Ok(())
+    }
+
+    /// Waits until every message queued so far has been written, or the connection was closed.
+    pub fn flush(&self) -> impl Future<Output = ()> + Send + 'static {
+        let target = *self.queued.lock().unwrap();
+
+        let mut written = self.written.lock().unwrap();
+        let rx = if written.closed || written.count >= target {
+            None
+        } else {
+            let (tx, rx) = oneshot::channel();
+            written.waiters.push((target, tx));
+            Some(rx)
+        };
+
+        async {
+            if let Some(rx) = rx {
+                // The sender is dropped without sending if the connection closes first.
+                let _ = rx.await;
+            }
+        }
+    }
+
+    fn acknowledge(&self, count: u64) {
+        let mut written = self.written.lock().unwrap();
+        written.count = count;
+        written.waiters.retain(|(target, _)| *target > count);
+    }
+
+    fn close(&self) {
+        let mut written = self.written.lock().unwrap();
+        written.closed = true;
+        written.waiters.clear();
+    }
+}
+
+impl Debug for Delivery {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let written = self.written.lock().unwrap();
+        f.debug_struct(stringify!(Delivery))
+            .field("queued", &*self.queued.lock().unwrap())
+            .field("written", &written.count)
+            .field("closed", &written.closed)
+            .finish()
+    }
+}
+
+/// Acknowledges messages on behalf of the stream yielding them to the transport.
+///
+/// Transports only poll for the next message once the previous one has been written, so every
+/// message yielded before the stream is polled again counts as delivered.
+#[derive(Debug)]
+pub struct Acknowledger {
+    delivery: Arc<Delivery>,
+    yielded: u64,
+}
+
+impl Acknowledger {
+    pub fn new(delivery: Arc<Delivery>) -> Self {
+        Acknowledger {
+            delivery,
+            yielded: 0,
+        }
+    }
+
+    /// Marks all messages yielded so far as written.
+    pub fn acknowledge(&self) {
+        self.delivery.acknowledge(self.yielded);
+    }
+
+    /// Records that the stream yielded another message.
+    pub fn yielded(&mut self) {
+        self.yielded += 1;
+    }
+}
+
+impl Drop for Acknowledger {
+    fn drop(&mut self) {
+        self.delivery.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn resolves_once_written() {
+        let delivery = Arc::new(Delivery::new());
+        let mut ack = Acknowledger::new(delivery.clone());
+        assert_eq!(delivery.flush().now_or_never(), Some(()));
+
+        let send = || Ok::<_, ()>(());
+        delivery.queue(send).unwrap();
+        delivery.queue(send).unwrap();
+        let mut flush = Box::pin(delivery.flush());
+        delivery.queue(send).unwrap();
+        assert_eq!(delivery.queue(|| Err(())), Err(()));
+
+        ack.yielded();
+        ack.acknowledge();
+        assert!((&mut flush).now_or_never().is_none());
+
+        ack.yielded();
+        ack.acknowledge();
+        assert_eq!(flush.now_or_never(), Some(()));
+        assert!(delivery.flush().now_or_never().is_none());
+
+        drop(ack);
+        assert_eq!(delivery.flush().now_or_never(), Some(()));
+    }
+}
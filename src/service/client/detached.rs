@@ -0,0 +1,111 @@
+//! Types for answering server requests outside of their handler.
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures::channel::oneshot;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::jsonrpc::{Error, Id, Result};
+
+/// A hashmap containing detached server requests, keyed by request ID.
+#[derive(Clone, Default)]
+pub(super) struct Detached(Arc<DashMap<Id, oneshot::Sender<Result<Value>>>>);
+
+impl Detached {
+    /// Takes ownership of the request with the given ID.
+    ///
+    /// Returns `None` if the request was already detached.
+    pub fn detach(&self, id: Id) -> Option<DetachedRequest> {
+        match self.0.entry(id.clone()) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => {
+                let (tx, rx) = oneshot::channel();
+                entry.insert(tx);
+                Some(DetachedRequest {
+                    id,
+                    rx,
+                    detached: self.clone(),
+                })
+            }
+        }
+    }
+
+    /// Completes the detached request with the given ID.
+    ///
+    /// Returns `false` if no request with this ID is waiting for a response.
+    pub fn respond(&self, id: &Id, result: Result<Value>) -> bool {
+        match self.0.remove(id) {
+            Some((_, tx)) => tx.send(result).is_ok(),
+            None => {
+                debug!(
+                    "discarding response to request {}, which is not detached",
+                    id
+                );
+                false
+            }
+        }
+    }
+}
+
+impl Debug for Detached {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_set()
+            .entries(self.0.iter().map(|entry| entry.key().clone()))
+            .finish()
+    }
+}
+
+/// A server request whose response is provided later through [`Client::respond`].
+///
+/// Created by [`MethodContext::detach`], this future resolves to the result passed to
+/// [`Client::respond`] and should be returned from the handler. It stays registered as long as it
+/// is alive, so when the request is canceled by the client or the server shuts down, the future is
+/// dropped and any later response to the request is discarded.
+///
+/// [`Client::respond`]: super::Client::respond
+/// [`MethodContext::detach`]: crate::MethodContext::detach
+#[must_use = "futures do nothing unless polled"]
+pub struct DetachedRequest {
+    id: Id,
+    rx: oneshot::Receiver<Result<Value>>,
+    detached: Detached,
+}
+
+impl DetachedRequest {
+    /// Returns the ID of the request, to be passed to [`Client::respond`].
+    ///
+    /// [`Client::respond`]: super::Client::respond
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+}
+
+impl Future for DetachedRequest {
+    type Output = Result<Value>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(Error::internal_error())))
+    }
+}
+
+impl Drop for DetachedRequest {
+    fn drop(&mut self) {
+        self.detached.0.remove(&self.id);
+    }
+}
+
+impl Debug for DetachedRequest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("DetachedRequest")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,153 @@
+//! Types for streaming `workspace/diagnostic` reports to the client.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+use lsp_types::{
+    Diagnostic, FullDocumentDiagnosticReport, ProgressToken, UnchangedDocumentDiagnosticReport,
+    Url, WorkspaceDiagnosticParams, WorkspaceDiagnosticReport,
+    WorkspaceDiagnosticReportPartialResult, WorkspaceDiagnosticReportResult,
+    WorkspaceDocumentDiagnosticReport, WorkspaceFullDocumentDiagnosticReport,
+    WorkspaceUnchangedDocumentDiagnosticReport,
+};
+use serde_json::json;
+
+use super::Client;
+
+/// A helper for incrementally reporting the results of a `workspace/diagnostic` request.
+///
+/// If the client provided a `partialResultToken`, reports are streamed to the client as partial
+/// results via `$/progress` notifications, in chunks of [`with_chunk_size`] reports each.
+/// Otherwise, reports are collected and returned all at once by [`finish`].
+///
+/// This struct is created by [`Client::workspace_diagnostics`]. See its documentation for more.
+///
+/// [`with_chunk_size`]: WorkspaceDiagnosticStream::with_chunk_size
+/// [`finish`]: WorkspaceDiagnosticStream::finish
+#[must_use = "buffered reports are not sent until `.finish()` is called"]
+pub struct WorkspaceDiagnosticStream {
+    client: Client,
+    token: Option<ProgressToken>,
+    previous_result_ids: HashMap<Url, String>,
+    chunk_size: usize,
+    buffer: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+impl WorkspaceDiagnosticStream {
+    pub(crate) fn new(client: Client, params: &WorkspaceDiagnosticParams) -> Self {
+        let previous_result_ids = params
+            .previous_result_ids
+            .iter()
+            .map(|prev| (prev.uri.clone(), prev.value.clone()))
+            .collect();
+
+        WorkspaceDiagnosticStream {
+            client,
+            token: params.partial_result_params.partial_result_token.clone(),
+            previous_result_ids,
+            chunk_size: 1,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sends partial results to the client in chunks of `size` document reports.
+    ///
+    /// Larger chunks result in fewer `$/progress` notifications at the expense of latency. If not
+    /// explicitly specified, every report is sent as soon as it is pushed.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Returns the result ID the client last received for the document at `uri`, if any.
+    ///
+    /// Servers may use this to skip recomputing diagnostics for documents which have not changed
+    /// since the client's last `workspace/diagnostic` request.
+    pub fn previous_result_id(&self, uri: &Url) -> Option<&str> {
+        self.previous_result_ids.get(uri).map(|id| id.as_str())
+    }
+
+    /// Reports the diagnostics `items` computed for the document at `uri`.
+    ///
+    /// If `result_id` matches the result ID the client last received for this document, an
+    /// `unchanged` report is sent in place of the full diagnostics list.
+    pub async fn push(
+        &mut self,
+        uri: Url,
+        version: Option<i64>,
+        result_id: Option<String>,
+        items: Vec<Diagnostic>,
+    ) {
+        let report = match result_id {
+            Some(result_id) if self.previous_result_id(&uri) == Some(result_id.as_str()) => {
+                WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri,
+                        version,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id,
+                        },
+                    },
+                )
+            }
+            result_id => {
+                WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id,
+                        items,
+                    },
+                })
+            }
+        };
+
+        self.push_report(report).await;
+    }
+
+    /// Reports a pre-built diagnostic report for a single document.
+    pub async fn push_report(&mut self, report: WorkspaceDocumentDiagnosticReport) {
+        self.buffer.push(report);
+        if self.token.is_some() && self.buffer.len() >= self.chunk_size {
+            self.flush().await;
+        }
+    }
+
+    /// Sends any remaining partial results and returns the final response to the request.
+    ///
+    /// If partial results were streamed to the client, the final response contains no reports,
+    /// as required by the specification.
+    pub async fn finish(mut self) -> WorkspaceDiagnosticReportResult {
+        if self.token.is_some() {
+            self.flush().await;
+        }
+
+        WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport {
+            items: std::mem::take(&mut self.buffer),
+        })
+    }
+
+    async fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let partial = WorkspaceDiagnosticReportPartialResult {
+            items: std::mem::take(&mut self.buffer),
+        };
+
+        let params = json!({ "token": self.token, "value": partial });
+        self.client.notify_raw("$/progress", params).await;
+    }
+}
+
+impl Debug for WorkspaceDiagnosticStream {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(WorkspaceDiagnosticStream))
+            .field("token", &self.token)
+            .field("previous_result_ids", &self.previous_result_ids)
+            .field("chunk_size", &self.chunk_size)
+            .field("buffer", &self.buffer)
+            .finish()
+    }
+}
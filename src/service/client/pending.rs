@@ -5,7 +5,7 @@ use std::future::Future;
 
 use dashmap::{mapref::entry::Entry, DashMap};
 use futures::channel::oneshot;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use crate::jsonrpc::{Id, Response};
 
@@ -32,7 +32,9 @@ impl Pending {
                         _ => entry.get_mut().remove(0),
                     };
 
-                    tx.send(r).expect("receiver already dropped");
+                    if let Err(r) = tx.send(r) {
+                        debug!("discarding response to request {}", r.id());
+                    }
                 }
             },
         }
@@ -2,10 +2,15 @@
 
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
 use lsp_types::{
     notification::Progress as ProgressNotification, ProgressParams, ProgressParamsValue,
-    ProgressToken, WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressReport,
+    ProgressToken, WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressEnd,
+    WorkDoneProgressReport,
 };
 
 use super::Client;
@@ -349,9 +354,9 @@ impl<B, C> OngoingProgress<B, C> {
         self.client
             .send_notification::<ProgressNotification>(ProgressParams {
                 token: self.token,
-                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(
-                    lsp_types::WorkDoneProgressEnd { message },
-                )),
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message,
+                })),
             })
             .await;
     }
@@ -369,3 +374,130 @@ impl<B, C> Debug for OngoingProgress<B, C> {
             .finish()
     }
 }
+
+/// A stream of items reporting the percentage of them consumed so far as `$/progress`.
+///
+/// The `begin` notification is sent when the stream is first polled, a `report` notification is
+/// sent whenever the percentage of consumed items changes, and the `end` notification is sent once
+/// the wrapped iterator is exhausted. An item counts as consumed once the next one is requested.
+///
+/// Dropping the stream before it is exhausted does not send the `end` notification.
+///
+/// This struct is created by [`Client::progress_iter`]. See its documentation for more.
+#[must_use = "streams do nothing unless polled"]
+pub struct ProgressIter<I> {
+    client: Client,
+    token: ProgressToken,
+    title: Option<String>,
+    items: I,
+    total: usize,
+    consumed: usize,
+    reported: u32,
+    done: bool,
+    notification: Option<BoxFuture<'static, ()>>,
+}
+
+impl<I: ExactSizeIterator> ProgressIter<I> {
+    pub(crate) fn new(client: Client, token: ProgressToken, title: String, items: I) -> Self {
+        ProgressIter {
+            client,
+            token,
+            title: Some(title),
+            total: items.len(),
+            items,
+            consumed: 0,
+            reported: 0,
+            done: false,
+            notification: None,
+        }
+    }
+
+    fn percentage(&self) -> u32 {
+        match self.total {
+            0 => 100,
+            total => (self.consumed.min(total) * 100 / total) as u32,
+        }
+    }
+
+    fn send(&mut self, progress: WorkDoneProgress) {
+        let client = self.client.clone();
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(progress),
+        };
+
+        let notification = async move {
+            client
+                .send_notification::<ProgressNotification>(params)
+                .await
+        };
+        self.notification = Some(notification.boxed());
+    }
+}
+
+impl<I> Stream for ProgressIter<I>
+where
+    I: ExactSizeIterator + Unpin,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(notification) = this.notification.as_mut() {
+                futures::ready!(notification.poll_unpin(cx));
+                this.notification = None;
+            }
+
+            if let Some(title) = this.title.take() {
+                this.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title,
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                }));
+                continue;
+            }
+
+            let percentage = this.percentage();
+            if this.consumed > 0 && percentage != this.reported {
+                this.reported = percentage;
+                this.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+                    percentage: Some(percentage),
+                    ..Default::default()
+                }));
+                continue;
+            }
+
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match this.items.next() {
+                Some(item) => {
+                    this.consumed += 1;
+                    return Poll::Ready(Some(item));
+                }
+                None => {
+                    this.done = true;
+                    this.send(WorkDoneProgress::End(WorkDoneProgressEnd { message: None }));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl<I> Debug for ProgressIter<I> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(ProgressIter))
+            .field("token", &self.token)
+            .field("total", &self.total)
+            .field("consumed", &self.consumed)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,14 @@
+//! Marker types for requests of upcoming protocol versions which `lsp-types` does not define yet.
+//!
+//! These should be replaced with their `lsp-types` counterparts once available.
+
+use lsp_types::request::Request;
+
+/// The `workspace/foldingRange/refresh` request, introduced in specification version 3.18.0.
+pub enum FoldingRangeRefresh {}
+
+impl Request for FoldingRangeRefresh {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "workspace/foldingRange/refresh";
+}
@@ -0,0 +1,118 @@
+//! Types for limiting the rate of outgoing server-to-client notifications.
+
+use std::fmt::{self, Debug, Formatter};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tracing::warn;
+
+/// A fixed time window in which at most `max` notifications may be sent.
+struct Window {
+    max: u32,
+    per: Duration,
+    start: Option<Instant>,
+    sent: u32,
+    dropped: u32,
+}
+
+/// A hashmap containing outgoing notification rate limits, keyed by method name.
+pub struct RateLimits(DashMap<&'static str, Window>);
+
+impl RateLimits {
+    /// Creates a new empty set of rate limits.
+    pub fn new() -> Self {
+        RateLimits(DashMap::new())
+    }
+
+    /// Allows at most `max` notifications of `method` to be sent every `per` interval.
+    pub fn insert(&self, method: &'static str, max: u32, per: Duration) {
+        let window = Window {
+            max,
+            per,
+            start: None,
+            sent: 0,
+            dropped: 0,
+        };
+
+        self.0.insert(method, window);
+    }
+
+    /// Removes the rate limit on `method`, if any.
+    pub fn remove(&self, method: &str) {
+        self.0.remove(method);
+    }
+
+    /// Returns `true` if a notification of `method` may be sent now, counting it towards the limit.
+    pub fn check(&self, method: &str) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+
+        let mut window = match self.0.get_mut(method) {
+            Some(window) => window,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        match window.start {
+            Some(start) if now.duration_since(start) < window.per => {}
+            _ => {
+                if window.dropped > 0 {
+                    warn!(
+                        "dropped {} `{}` notifications exceeding the rate limit",
+                        window.dropped, method
+                    );
+                }
+
+                window.start = Some(now);
+                window.sent = 0;
+                window.dropped = 0;
+            }
+        }
+
+        if window.sent < window.max {
+            window.sent += 1;
+            true
+        } else {
+            window.dropped += 1;
+            false
+        }
+    }
+}
+
+impl Debug for RateLimits {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let methods: Vec<_> = self.0.iter().map(|entry| *entry.key()).collect();
+        f.debug_struct("RateLimits")
+            .field("methods", &methods)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_per_method() {
+        let limits = RateLimits::new();
+        assert!(limits.check("window/logMessage"));
+
+        limits.insert("window/logMessage", 2, Duration::from_secs(3600));
+        assert!(limits.check("window/logMessage"));
+        assert!(limits.check("window/logMessage"));
+        assert!(!limits.check("window/logMessage"));
+        assert!(limits.check("window/showMessage"));
+
+        limits.remove("window/logMessage");
+        assert!(limits.check("window/logMessage"));
+    }
+
+    #[test]
+    fn resets_after_interval() {
+        let limits = RateLimits::new();
+        limits.insert("window/logMessage", 1, Duration::ZERO);
+        assert!(limits.check("window/logMessage"));
+        assert!(limits.check("window/logMessage"));
+    }
+}
@@ -0,0 +1,81 @@
+//! Guards for capabilities registered with [`Client::register_scoped`].
+
+use std::fmt::{self, Debug, Formatter};
+
+use lsp_types::Unregistration;
+
+use super::Client;
+use crate::jsonrpc;
+
+/// A capability registered with the client, which is unregistered once dropped.
+///
+/// This is returned from [`Client::register_scoped`], and ties the lifetime of a dynamic
+/// registration to a value owned by the server, e.g. a file watcher registered for one workspace
+/// folder, which goes away along with the folder.
+///
+/// Dropping the guard sends the `client/unregisterCapability` request without waiting for the
+/// client to answer it. Use [`CapabilityRegistration::unregister`] to wait for the answer instead,
+/// or [`CapabilityRegistration::keep`] to leave the capability registered.
+pub struct CapabilityRegistration {
+    client: Client,
+    /// The registration, or `None` once it was unregistered or kept.
+    unregistration: Option<Unregistration>,
+}
+
+impl CapabilityRegistration {
+    pub(super) fn new(client: Client, id: String, method: String) -> Self {
+        CapabilityRegistration {
+            client,
+            unregistration: Some(Unregistration { id, method }),
+        }
+    }
+
+    /// Returns the ID of the registration.
+    pub fn id(&self) -> &str {
+        &self.registration().id
+    }
+
+    /// Returns the method for which the capability is registered.
+    pub fn method(&self) -> &str {
+        &self.registration().method
+    }
+
+    /// Unregisters the capability and waits for the client to accept the request.
+    ///
+    /// See [`Client::unregister_capability`] for more details.
+    pub async fn unregister(mut self) -> jsonrpc::Result<()> {
+        let unregistration = self.unregistration.take().expect("already unregistered");
+        self.client
+            .unregister_capability(vec![unregistration])
+            .await
+    }
+
+    /// Leaves the capability registered, returning the ID of the registration.
+    ///
+    /// It can still be unregistered later with [`Client::unregister_capability`].
+    pub fn keep(mut self) -> String {
+        let unregistration = self.unregistration.take().expect("already unregistered");
+        unregistration.id
+    }
+
+    fn registration(&self) -> &Unregistration {
+        self.unregistration.as_ref().expect("already unregistered")
+    }
+}
+
+impl Drop for CapabilityRegistration {
+    fn drop(&mut self) {
+        if let Some(unregistration) = self.unregistration.take() {
+            self.client.unregister_detached(unregistration);
+        }
+    }
+}
+
+impl Debug for CapabilityRegistration {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CapabilityRegistration")
+            .field("id", &self.id())
+            .field("method", &self.method())
+            .finish_non_exhaustive()
+    }
+}
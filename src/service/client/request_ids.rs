@@ -0,0 +1,109 @@
+//! Generation of IDs for requests sent to the client.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::jsonrpc::Id;
+
+/// Scheme used for the IDs of requests sent through the [`Client`](crate::Client), set with
+/// [`LspServiceBuilder::request_ids`](crate::LspServiceBuilder::request_ids).
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub enum RequestIdStrategy {
+    /// Sequential numbers, starting at 0.
+    #[default]
+    Number,
+    /// Sequential numbers formatted as strings after the given prefix, e.g. `"proxy-0"`.
+    ///
+    /// This is useful when the server sits behind a proxy which also forwards requests from other
+    /// sources to the client, as a distinct prefix keeps the IDs from colliding. An empty prefix
+    /// yields plain string IDs.
+    Prefixed(String),
+    /// IDs returned by a custom function, e.g. random UUIDs.
+    ///
+    /// The function must never return the ID of a request which is still pending.
+    Custom(Arc<dyn Fn() -> Id + Send + Sync>),
+}
+
+impl RequestIdStrategy {
+    /// Creates a [`RequestIdStrategy::Custom`] strategy calling `f` for every request.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Id;
+    /// # use tower_lsp::RequestIdStrategy;
+    /// # fn uuid_v4() -> String { String::new() }
+    /// let strategy = RequestIdStrategy::custom(|| Id::String(uuid_v4()));
+    /// ```
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: Fn() -> Id + Send + Sync + 'static,
+    {
+        RequestIdStrategy::Custom(Arc::new(f))
+    }
+}
+
+impl Debug for RequestIdStrategy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RequestIdStrategy::Number => f.write_str("Number"),
+            RequestIdStrategy::Prefixed(prefix) => f.debug_tuple("Prefixed").field(prefix).finish(),
+            RequestIdStrategy::Custom(_) => {
+                f.debug_tuple("Custom").field(&format_args!("_")).finish()
+            }
+        }
+    }
+}
+
+/// Generator of request IDs, following the configured [`RequestIdStrategy`].
+#[derive(Debug, Default)]
+pub(super) struct RequestIds {
+    counter: AtomicU32,
+    strategy: RwLock<RequestIdStrategy>,
+}
+
+impl RequestIds {
+    pub fn set_strategy(&self, strategy: RequestIdStrategy) {
+        *self.strategy.write().unwrap() = strategy;
+    }
+
+    /// Returns a fresh request ID.
+    pub fn next(&self) -> Id {
+        let strategy = self.strategy.read().unwrap();
+        match &*strategy {
+            RequestIdStrategy::Number => Id::Number(self.next_number() as i64),
+            RequestIdStrategy::Prefixed(prefix) => {
+                Id::String(format!("{}{}", prefix, self.next_number()))
+            }
+            RequestIdStrategy::Custom(f) => {
+                let f = f.clone();
+                drop(strategy);
+                f()
+            }
+        }
+    }
+
+    fn next_number(&self) -> u32 {
+        self.counter.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_strategy() {
+        let ids = RequestIds::default();
+        assert_eq!(ids.next(), Id::Number(0));
+        assert_eq!(ids.next(), Id::Number(1));
+
+        ids.set_strategy(RequestIdStrategy::Prefixed("proxy-".into()));
+        assert_eq!(ids.next(), Id::String("proxy-2".into()));
+
+        ids.set_strategy(RequestIdStrategy::custom(|| Id::String("fixed".into())));
+        assert_eq!(ids.next(), Id::String("fixed".into()));
+    }
+}
@@ -0,0 +1,191 @@
+//! Retrying requests sent with [`Client::with_retry`].
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use lsp_types::{ConfigurationItem, WorkspaceFolder};
+use serde_json::Value;
+use tracing::debug;
+
+use super::Client;
+use crate::jsonrpc::{self, Error, ErrorCode};
+
+type SleepFn = dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync;
+type RetryIfFn = dyn Fn(&Error) -> bool + Send + Sync;
+
+/// Retry schedule for requests which failed with a transient error, used by
+/// [`Client::with_retry`].
+///
+/// Failed requests are sent again up to `max_attempts` times in total. The delay between attempts
+/// starts at `initial_delay` and doubles after every failed attempt, up to `max_delay`.
+///
+/// By default, only errors indicating that the client could not answer the request yet are
+/// retried: `-32801` (content modified), `-32802` (server cancelled, used by clients too busy to
+/// answer) and `-32002` (server not initialized). This can be changed with
+/// [`RetryPolicy::retry_if`].
+///
+/// # Timers
+///
+/// Like the rest of this crate, retries do not depend on a particular async runtime, so the
+/// policy is created with a `sleep` function provided by the runtime, e.g. `tokio::time::sleep`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tower_lsp::RetryPolicy;
+///
+/// let policy = RetryPolicy::new(|delay| async move {
+///     smol::Timer::after(delay).await;
+/// })
+/// .max_attempts(3)
+/// .initial_delay(Duration::from_millis(100));
+/// # drop(policy);
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    sleep: Arc<SleepFn>,
+    retry_if: Option<Arc<RetryIfFn>>,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy waiting between attempts with the given `sleep` function.
+    ///
+    /// The policy makes 5 attempts at most, starting with a delay of 50 ms, up to 2 s.
+    pub fn new<F, Fut>(sleep: F) -> Self
+    where
+        F: Fn(Duration) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            sleep: Arc::new(move |delay| sleep(delay).boxed()),
+            retry_if: None,
+        }
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    ///
+    /// Values lower than 1 are treated as 1, which disables retries.
+    pub fn max_attempts(mut self, max: u32) -> Self {
+        self.max_attempts = max.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the maximum delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Only retries requests failing with errors for which `predicate` returns `true`, instead of
+    /// the default transient errors.
+    pub fn retry_if<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.retry_if = Some(Arc::new(predicate));
+        self
+    }
+
+    fn is_transient(&self, err: &Error) -> bool {
+        match &self.retry_if {
+            Some(predicate) => predicate(err),
+            None => matches!(
+                err.code,
+                ErrorCode::ContentModified | ErrorCode::ServerError(-32802 | -32002)
+            ),
+        }
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(RetryPolicy))
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A [`Client`] retrying failed requests according to a [`RetryPolicy`].
+///
+/// This is returned from [`Client::with_retry`].
+#[derive(Clone, Debug)]
+pub struct RetryingClient<'a> {
+    client: &'a Client,
+    policy: RetryPolicy,
+}
+
+impl<'a> RetryingClient<'a> {
+    pub(super) fn new(client: &'a Client, policy: RetryPolicy) -> Self {
+        RetryingClient { client, policy }
+    }
+
+    /// Calls `request` with the client until it succeeds, fails with an error which is not
+    /// transient, or the maximum number of attempts is reached.
+    ///
+    /// This allows retrying any request method of [`Client`], or several of them at once.
+    pub async fn call<F, Fut, T>(&self, mut request: F) -> jsonrpc::Result<T>
+    where
+        F: FnMut(&'a Client) -> Fut,
+        Fut: Future<Output = jsonrpc::Result<T>>,
+    {
+        let policy = &self.policy;
+        let mut delay = policy.initial_delay;
+        let mut attempt = 1;
+
+        loop {
+            match request(self.client).await {
+                Err(err) if attempt < policy.max_attempts && policy.is_transient(&err) => {
+                    debug!("request failed with transient error, retrying: {}", err);
+                }
+                result => return result,
+            }
+
+            (policy.sleep)(delay).await;
+            delay = (delay * 2).min(policy.max_delay);
+            attempt += 1;
+        }
+    }
+
+    /// Like [`Client::send_request`], retrying transient failures.
+    pub async fn send_request<R>(&self, params: R::Params) -> jsonrpc::Result<R::Result>
+    where
+        R: lsp_types::request::Request,
+        R::Params: Clone,
+    {
+        self.call(|client| client.send_request::<R>(params.clone()))
+            .await
+    }
+
+    /// Like [`Client::configuration`], retrying transient failures.
+    pub async fn configuration(
+        &self,
+        items: Vec<ConfigurationItem>,
+    ) -> jsonrpc::Result<Vec<Value>> {
+        self.call(|client| client.configuration(items.clone()))
+            .await
+    }
+
+    /// Like [`Client::workspace_folders`], retrying transient failures.
+    pub async fn workspace_folders(&self) -> jsonrpc::Result<Option<Vec<WorkspaceFolder>>> {
+        self.call(|client| client.workspace_folders()).await
+    }
+}
@@ -6,15 +6,18 @@ use std::task::{Context, Poll};
 
 use futures::channel::mpsc::Receiver;
 use futures::sink::Sink;
-use futures::stream::{FusedStream, Stream, StreamExt};
+use futures::stream::{BoxStream, FusedStream, Stream, StreamExt};
+use serde::Serialize;
 
+use super::delivery::Acknowledger;
 use super::{ExitedError, Pending, ServerState, State};
-use crate::jsonrpc::{Request, Response};
+use crate::jsonrpc::{self, Error, ErrorCode, Id, Request, Response};
 
 /// A loopback channel for server-to-client communication.
 #[derive(Debug)]
 pub struct ClientSocket {
     pub(super) rx: Receiver<Request>,
+    pub(super) ack: Acknowledger,
     pub(super) pending: Arc<Pending>,
     pub(super) state: Arc<ServerState>,
 }
@@ -27,14 +30,107 @@ impl ClientSocket {
     /// [`Stream`]: futures::Stream
     /// [`Sink`]: futures::Sink
     pub fn split(self) -> (RequestStream, ResponseSink) {
-        let ClientSocket { rx, pending, state } = self;
+        let ClientSocket {
+            rx,
+            ack,
+            pending,
+            state,
+        } = self;
         let state_ = state.clone();
 
         (
-            RequestStream { rx, state: state_ },
+            RequestStream {
+                rx,
+                ack,
+                state: state_,
+            },
             ResponseSink { pending, state },
         )
     }
+
+    /// Takes the stream of notifications generated by the server itself, which are handled as if
+    /// they were sent by the client.
+    pub(crate) fn take_local_requests(&mut self) -> Option<BoxStream<'static, Request>> {
+        #[cfg(feature = "file-watcher")]
+        if let Some(notifications) = self.state.file_watcher().take_notifications() {
+            return Some(notifications.boxed());
+        }
+
+        None
+    }
+
+    /// Waits for the next request or notification sent by the server.
+    ///
+    /// Returns `None` once the server has exited. The parameters of the message can be
+    /// deserialized with [`Request::params_as`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::request::{Request as _, ShowMessageRequest};
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # async fn example() {
+    /// let (service, mut socket) = LspService::new(|_| Mock);
+    ///
+    /// while let Some(request) = socket.next_request().await {
+    ///     if let (ShowMessageRequest::METHOD, Some(id)) = (request.method(), request.id()) {
+    ///         let params: ShowMessageRequestParams = request.params_as().unwrap();
+    ///         let choice = params.actions.and_then(|actions| actions.into_iter().next());
+    ///         socket.respond(id.clone(), Ok(choice)).unwrap();
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn next_request(&mut self) -> Option<Request> {
+        self.next().await
+    }
+
+    /// Sends the `result` of the server request with the given `id` back to the server.
+    ///
+    /// This is equivalent to sending a [`Response`] through the [`Sink`] implementation, except
+    /// that `result` is serialized automatically.
+    ///
+    /// Returns `Err` if the server has already exited.
+    ///
+    /// [`Sink`]: futures::Sink
+    pub fn respond<T: Serialize>(
+        &self,
+        id: Id,
+        result: jsonrpc::Result<T>,
+    ) -> Result<(), ExitedError> {
+        if self.state.get() == State::Exited || self.rx.is_terminated() {
+            return Err(ExitedError(()));
+        }
+
+        let result = result.and_then(|value| {
+            serde_json::to_value(value).map_err(|e| Error {
+                code: ErrorCode::InternalError,
+                message: e.to_string().into(),
+                data: None,
+            })
+        });
+
+        let response = Response::from_parts(id, result);
+        self.state.trace_message("<-", &response);
+        self.pending.insert(response);
+        Ok(())
+    }
 }
 
 /// Yields a stream of pending server-to-client requests.
@@ -43,10 +139,16 @@ impl Stream for ClientSocket {
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.state.get() == State::Exited || self.rx.is_terminated() {
-            Poll::Ready(None)
-        } else {
-            self.rx.poll_next_unpin(cx)
+            return Poll::Ready(None);
         }
+
+        self.ack.acknowledge();
+        let next = self.rx.poll_next_unpin(cx);
+        if let Poll::Ready(Some(_)) = next {
+            self.ack.yielded();
+        }
+
+        next
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -73,6 +175,7 @@ impl Sink<Response> for ClientSocket {
     }
 
     fn start_send(self: Pin<&mut Self>, item: Response) -> Result<(), Self::Error> {
+        self.state.trace_message("<-", &item);
         self.pending.insert(item);
         Ok(())
     }
@@ -91,6 +194,7 @@ impl Sink<Response> for ClientSocket {
 #[must_use = "streams do nothing unless polled"]
 pub struct RequestStream {
     rx: Receiver<Request>,
+    ack: Acknowledger,
     state: Arc<ServerState>,
 }
 
@@ -99,10 +203,16 @@ impl Stream for RequestStream {
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.state.get() == State::Exited || self.rx.is_terminated() {
-            Poll::Ready(None)
-        } else {
-            self.rx.poll_next_unpin(cx)
+            return Poll::Ready(None);
         }
+
+        self.ack.acknowledge();
+        let next = self.rx.poll_next_unpin(cx);
+        if let Poll::Ready(Some(_)) = next {
+            self.ack.yielded();
+        }
+
+        next
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -135,6 +245,7 @@ impl Sink<Response> for ResponseSink {
     }
 
     fn start_send(self: Pin<&mut Self>, item: Response) -> Result<(), Self::Error> {
+        self.state.trace_message("<-", &item);
         self.pending.insert(item);
         Ok(())
     }
@@ -0,0 +1,116 @@
+//! Types for sampling and batching outgoing telemetry events.
+
+use std::fmt::{self, Debug, Formatter};
+use std::mem;
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use serde_json::Value;
+
+/// Sampling and batching settings of the events sent with
+/// [`Client::send_telemetry`](super::Client::send_telemetry).
+pub struct Telemetry {
+    /// Sampled event names, mapped to the sampling rate and the number of events seen so far.
+    sampling: DashMap<&'static str, (u32, u32)>,
+    batch: Mutex<Batch>,
+}
+
+struct Batch {
+    size: usize,
+    events: Vec<Value>,
+}
+
+impl Telemetry {
+    /// Creates new settings, which send every event on its own.
+    pub fn new() -> Self {
+        Telemetry {
+            sampling: DashMap::new(),
+            batch: Mutex::new(Batch {
+                size: 1,
+                events: Vec::new(),
+            }),
+        }
+    }
+
+    /// Only lets through one in every `one_in` events named `name`, starting with the first one.
+    pub fn set_sampling(&self, name: &'static str, one_in: u32) {
+        if one_in > 1 {
+            self.sampling.insert(name, (one_in, 0));
+        } else {
+            self.sampling.remove(name);
+        }
+    }
+
+    /// Returns `true` if the event named `name` should be sent, counting it towards its sampling.
+    pub fn sample(&self, name: &str) -> bool {
+        match self.sampling.get_mut(name) {
+            Some(mut sampling) => {
+                let (one_in, seen) = &mut *sampling;
+                let sampled = *seen == 0;
+                *seen = (*seen + 1) % *one_in;
+                sampled
+            }
+            None => true,
+        }
+    }
+
+    /// Sends events in batches of `size`.
+    pub fn set_batch_size(&self, size: usize) {
+        self.batch.lock().unwrap().size = size.max(1);
+    }
+
+    /// Adds `event` to the current batch, returning the payload to send if the batch is full.
+    ///
+    /// Events are returned as is if they are not batched.
+    pub fn push(&self, event: Value) -> Option<Value> {
+        let mut batch = self.batch.lock().unwrap();
+        if batch.size == 1 && batch.events.is_empty() {
+            return Some(event);
+        }
+
+        batch.events.push(event);
+        (batch.events.len() >= batch.size).then(|| Value::Array(mem::take(&mut batch.events)))
+    }
+
+    /// Returns the events batched so far, if any.
+    pub fn take(&self) -> Option<Value> {
+        let mut batch = self.batch.lock().unwrap();
+        let events = mem::take(&mut batch.events);
+        (!events.is_empty()).then_some(Value::Array(events))
+    }
+}
+
+impl Debug for Telemetry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let batch = self.batch.lock().unwrap();
+        f.debug_struct("Telemetry")
+            .field("sampled", &self.sampling.len())
+            .field("batch_size", &batch.size)
+            .field("batched", &batch.events.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn samples_and_batches_events() {
+        let telemetry = Telemetry::new();
+        telemetry.set_sampling("a", 3);
+        let sampled: Vec<_> = (0..7).map(|_| telemetry.sample("a")).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false, true]);
+        assert!(telemetry.sample("b"));
+
+        assert_eq!(telemetry.push(json!(1)), Some(json!(1)));
+        telemetry.set_batch_size(2);
+        assert_eq!(telemetry.push(json!(1)), None);
+        assert_eq!(telemetry.push(json!(2)), Some(json!([1, 2])));
+        assert_eq!(telemetry.push(json!(3)), None);
+        assert_eq!(telemetry.take(), Some(json!([3])));
+        assert_eq!(telemetry.take(), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Per-method concurrency limits, configured with
+//! [`LspServiceBuilder::concurrency_limit`](crate::LspServiceBuilder::concurrency_limit).
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use futures::future::{self, FutureExt};
+use tracing::debug;
+
+use super::Pending;
+use crate::jsonrpc::{Error, Id, Request};
+
+/// Maximum number of concurrent executions of a method, set with
+/// [`LspServiceBuilder::concurrency_limit`](crate::LspServiceBuilder::concurrency_limit).
+///
+/// Requests exceeding the limit wait until a running request to the same method completes, in
+/// order of arrival. With [`ConcurrencyLimit::latest_wins`], only the most recent waiting request
+/// is kept instead, and the requests it supersedes are answered with JSON-RPC error code `-32801`
+/// (content modified).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConcurrencyLimit {
+    max: usize,
+    latest_wins: bool,
+}
+
+impl ConcurrencyLimit {
+    /// Allows up to `max` concurrent executions of the method.
+    ///
+    /// Values lower than 1 are treated as 1.
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimit {
+            max: max.max(1),
+            latest_wins: false,
+        }
+    }
+
+    /// Only keeps the most recent request waiting for a running one to complete.
+    ///
+    /// This suits requests whose result is superseded by newer ones, such as
+    /// `textDocument/semanticTokens/full`, which clients send again on every change.
+    pub fn latest_wins(mut self) -> Self {
+        self.latest_wins = true;
+        self
+    }
+}
+
+/// Concurrency limits of the methods which have one, keyed by method name.
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimits {
+    pending: Arc<Pending>,
+    limiters: HashMap<&'static str, Arc<Limiter>>,
+}
+
+impl ConcurrencyLimits {
+    pub fn new(limits: HashMap<&'static str, ConcurrencyLimit>, pending: Arc<Pending>) -> Self {
+        let limiters = limits
+            .into_iter()
+            .map(|(method, limit)| (method, Arc::new(Limiter::new(limit))))
+            .collect();
+
+        ConcurrencyLimits { pending, limiters }
+    }
+
+    /// Queues `req` if it is a request to a limited method.
+    pub fn acquire(&self, req: &Request) -> Option<Queued> {
+        let id = req.id()?;
+        let limiter = self.limiters.get(req.method())?;
+
+        let mut queue = limiter.queue.lock().unwrap();
+        let slot = if queue.running < limiter.limit.max {
+            queue.running += 1;
+            Slot::Running(Permit(limiter.clone()))
+        } else {
+            if limiter.limit.latest_wins {
+                for (superseded, tx) in queue.waiting.drain(..) {
+                    if !tx.is_canceled() {
+                        debug!("request {} superseded by request {}", superseded, id);
+                        self.pending
+                            .cancel_with(&superseded, Error::content_modified());
+                    }
+                }
+            }
+
+            let (tx, rx) = oneshot::channel();
+            queue.waiting.push_back((id.clone(), tx));
+            Slot::Waiting(Waiter {
+                rx,
+                limiter: limiter.clone(),
+            })
+        };
+        drop(queue);
+
+        Some(Queued {
+            id: id.clone(),
+            slot,
+            pending: self.pending.clone(),
+        })
+    }
+}
+
+/// A request queued by [`ConcurrencyLimits::acquire`].
+#[derive(Debug)]
+pub(crate) struct Queued {
+    id: Id,
+    slot: Slot,
+    pending: Arc<Pending>,
+}
+
+impl Queued {
+    /// Returns a future which resolves once the request may run.
+    ///
+    /// This must be called after the request was registered as pending, so that the future is
+    /// aborted if the request is canceled while waiting. It then resolves to `None`, and so does it
+    /// if the request was superseded by a newer one.
+    pub fn wait(self) -> impl Future<Output = Option<Permit>> {
+        let Queued { id, slot, pending } = self;
+        let acquire = async move {
+            match slot {
+                Slot::Running(permit) => Some(permit),
+                Slot::Waiting(mut waiter) => {
+                    (&mut waiter.rx).await.ok()?;
+                    Some(Permit(waiter.limiter.clone()))
+                }
+            }
+        };
+
+        let (acquire, handle) = future::abortable(acquire);
+        pending.watch(&id, handle);
+        acquire.map(|acquired| acquired.ok().flatten())
+    }
+}
+
+/// Number of running requests to a method, and the requests waiting to run.
+#[derive(Debug)]
+struct Limiter {
+    limit: ConcurrencyLimit,
+    queue: Mutex<Queue>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    running: usize,
+    waiting: VecDeque<(Id, oneshot::Sender<()>)>,
+}
+
+impl Limiter {
+    fn new(limit: ConcurrencyLimit) -> Self {
+        Limiter {
+            limit,
+            queue: Mutex::default(),
+        }
+    }
+
+    /// Hands the slot of a completed request over to the oldest waiting request, if any.
+    fn release(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        while let Some((_, tx)) = queue.waiting.pop_front() {
+            if tx.send(()).is_ok() {
+                return;
+            }
+        }
+
+        queue.running -= 1;
+    }
+}
+
+#[derive(Debug)]
+enum Slot {
+    Running(Permit),
+    Waiting(Waiter),
+}
+
+/// A request waiting for a slot.
+#[derive(Debug)]
+struct Waiter {
+    rx: oneshot::Receiver<()>,
+    limiter: Arc<Limiter>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        // The slot was handed over, but the request was dropped before taking it.
+        if let Ok(Some(())) = self.rx.try_recv() {
+            self.limiter.release();
+        }
+    }
+}
+
+/// A slot held by a running request, released when dropped.
+#[derive(Debug)]
+pub(crate) struct Permit(Arc<Limiter>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-request correlation IDs, matching client-side errors with server logs.
+
+use serde_json::{json, Value};
+use tracing::{info_span, Span};
+
+use crate::jsonrpc::Response;
+
+/// Name of the member added to the `data` of error responses.
+const DATA_KEY: &str = "correlationId";
+
+/// Where the correlation IDs enabled with
+/// [`LspServiceBuilder::correlation_ids`](crate::LspServiceBuilder::correlation_ids) are reported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CorrelationIdPolicy {
+    /// Records the ID in the `correlation_id` field of the `request` span only.
+    Span,
+    /// Also adds the ID to the `data` of error responses, as a `correlationId` member.
+    ///
+    /// If the error already carries `data` which is not a JSON object, it is left untouched.
+    SpanAndErrorData,
+}
+
+/// Generator of correlation IDs, unique within a single `LspService`.
+#[derive(Debug)]
+pub(crate) struct CorrelationIds {
+    next: u64,
+    policy: CorrelationIdPolicy,
+}
+
+impl CorrelationIds {
+    pub fn new(policy: CorrelationIdPolicy) -> Self {
+        CorrelationIds { next: 1, policy }
+    }
+
+    /// Returns a fresh ID for the request `method`, along with the span it should be handled in.
+    ///
+    /// The span is created as a child of `parent`, if any, or of the current span otherwise.
+    pub fn next(&mut self, method: &str, parent: Option<&Span>) -> (CorrelationId, Span) {
+        let id = self.next;
+        self.next += 1;
+
+        let span = match parent {
+            Some(parent) => info_span!(parent: parent, "request", method, correlation_id = id),
+            None => info_span!("request", method, correlation_id = id),
+        };
+
+        (CorrelationId(id, self.policy), span)
+    }
+}
+
+/// A correlation ID, along with the policy it was generated under.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CorrelationId(u64, CorrelationIdPolicy);
+
+impl CorrelationId {
+    /// Adds the ID to `res` if it is an error and the policy asks for it.
+    pub fn annotate(self, res: Response) -> Response {
+        if self.1 != CorrelationIdPolicy::SpanAndErrorData || res.is_ok() {
+            return res;
+        }
+
+        let (id, result) = res.into_parts();
+        let mut error = result.unwrap_err();
+        match &mut error.data {
+            Some(Value::Object(data)) => {
+                data.insert(DATA_KEY.to_owned(), json!(self.0));
+            }
+            Some(_) => {}
+            data @ None => *data = Some(json!({ DATA_KEY: self.0 })),
+        }
+
+        Response::from_error(id, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{Error, Id};
+
+    #[test]
+    fn annotates_errors() {
+        let mut ids = CorrelationIds::new(CorrelationIdPolicy::SpanAndErrorData);
+        let (first, _) = ids.next("textDocument/hover", None);
+        let (second, _) = ids.next("textDocument/hover", None);
+
+        let res = second.annotate(Response::from_error(Id::Number(1), Error::internal_error()));
+        assert_eq!(
+            res.error().unwrap().data,
+            Some(json!({ "correlationId": 2 }))
+        );
+
+        let mut error = Error::invalid_params("missing field");
+        error.data = Some(json!({ "field": "uri" }));
+        let res = first.annotate(Response::from_error(Id::Number(1), error));
+        let data = json!({ "field": "uri", "correlationId": 1 });
+        assert_eq!(res.error().unwrap().data, Some(data));
+
+        let res = first.annotate(Response::from_ok(Id::Number(1), json!(null)));
+        assert_eq!(res.result(), Some(&json!(null)));
+    }
+
+    #[test]
+    fn leaves_errors_untouched_for_spans_only() {
+        let mut ids = CorrelationIds::new(CorrelationIdPolicy::Span);
+        let (id, _) = ids.next("textDocument/hover", None);
+
+        let res = id.annotate(Response::from_error(Id::Number(1), Error::internal_error()));
+        assert_eq!(res.error().unwrap().data, None);
+    }
+}
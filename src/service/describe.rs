@@ -0,0 +1,116 @@
+//! Machine-readable descriptions of the methods handled by [`LspService`](crate::LspService).
+
+use std::slice;
+
+use serde::Serialize;
+
+/// Description of a single JSON-RPC method handled by the server.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct MethodDescription {
+    /// Name of the method, e.g. `textDocument/hover`.
+    pub name: String,
+    /// Whether the method is handled as a notification rather than a request.
+    pub notification: bool,
+    /// Name of the Rust type the params are deserialized into, if the method takes params.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<String>,
+    /// Name of the Rust type the result is serialized from, if the method is a request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// Whether the method was registered with
+    /// [`LspServiceBuilder::custom_method`](crate::LspServiceBuilder::custom_method).
+    pub custom: bool,
+}
+
+impl MethodDescription {
+    pub(crate) fn new(
+        name: &str,
+        notification: bool,
+        params: Option<&str>,
+        result: Option<&str>,
+        custom: bool,
+    ) -> Self {
+        MethodDescription {
+            name: name.to_owned(),
+            notification,
+            params: params.map(short_type_name),
+            result: result.map(short_type_name),
+            custom,
+        }
+    }
+}
+
+/// A machine-readable description of the methods handled by an [`LspService`](crate::LspService).
+///
+/// This struct is created by [`LspService::describe`](crate::LspService::describe). See its
+/// documentation for more.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ServerDescription {
+    methods: Vec<MethodDescription>,
+}
+
+impl ServerDescription {
+    pub(crate) fn new(mut methods: Vec<MethodDescription>) -> Self {
+        methods.sort_by(|a, b| a.name.cmp(&b.name));
+        ServerDescription { methods }
+    }
+
+    /// Returns the description of `method`, if the server handles it.
+    pub fn get(&self, method: &str) -> Option<&MethodDescription> {
+        let index = self.methods.binary_search_by(|m| m.name[..].cmp(method));
+        index.ok().map(|i| &self.methods[i])
+    }
+
+    /// Returns an iterator over the descriptions of every method, sorted by method name.
+    pub fn iter(&self) -> slice::Iter<'_, MethodDescription> {
+        self.methods.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ServerDescription {
+    type Item = &'a MethodDescription;
+    type IntoIter = slice::Iter<'a, MethodDescription>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.methods.iter()
+    }
+}
+
+/// Strips the module paths from a type name returned by [`std::any::type_name`].
+///
+/// For example, `core::option::Option<lsp_types::hover::Hover>` becomes `Option<Hover>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut path_start = 0;
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(path_start);
+        } else {
+            short.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                path_start = short.len();
+            }
+        }
+    }
+
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_type_names() {
+        let name = std::any::type_name::<Option<Vec<lsp_types::Location>>>();
+        assert_eq!(short_type_name(name), "Option<Vec<Location>>");
+
+        let name = std::any::type_name::<(serde_json::Value, std::collections::HashMap<i32, u8>)>();
+        assert_eq!(short_type_name(name), "(Value, HashMap<i32, u8>)");
+    }
+}
@@ -0,0 +1,237 @@
+//! Self-checks of the server's own messages, enabled with
+//! [`LspServiceBuilder::dev_mode`](crate::LspServiceBuilder::dev_mode).
+
+use lsp_types::notification::{LogMessage, Notification};
+use lsp_types::request::{self as req, Request as _};
+use lsp_types::{ClientCapabilities, LogMessageParams, MessageType};
+use serde_json::Value;
+
+use super::strict::ProtocolViolation;
+use crate::jsonrpc::{Request, Response};
+
+/// Messages which may only be sent to clients advertising a capability, as JSON pointers into the
+/// client capabilities.
+const REQUIRED_CAPABILITIES: &[(&str, &str)] = &[
+    ("window/workDoneProgress/create", "/window/workDoneProgress"),
+    ("window/showDocument", "/window/showDocument/support"),
+    ("workspace/applyEdit", "/workspace/applyEdit"),
+    ("workspace/configuration", "/workspace/configuration"),
+    ("workspace/workspaceFolders", "/workspace/workspaceFolders"),
+    (
+        "workspace/codeLens/refresh",
+        "/workspace/codeLens/refreshSupport",
+    ),
+    (
+        "workspace/semanticTokens/refresh",
+        "/workspace/semanticTokens/refreshSupport",
+    ),
+    (
+        "workspace/inlayHint/refresh",
+        "/workspace/inlayHint/refreshSupport",
+    ),
+    (
+        "workspace/inlineValue/refresh",
+        "/workspace/inlineValue/refreshSupport",
+    ),
+    (
+        "workspace/diagnostic/refresh",
+        "/workspace/diagnostic/refreshSupport",
+    ),
+    (
+        "textDocument/publishDiagnostics",
+        "/textDocument/publishDiagnostics",
+    ),
+];
+
+/// Returns the violation committed by sending `msg` to a client advertising `caps`, if any.
+pub(crate) fn check_outgoing(
+    msg: &Request,
+    caps: &ClientCapabilities,
+) -> Option<ProtocolViolation> {
+    let (method, pointer) = REQUIRED_CAPABILITIES
+        .iter()
+        .find(|(method, _)| *method == msg.method())?;
+
+    let caps = serde_json::to_value(caps).ok()?;
+    match caps.pointer(pointer) {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => {
+            Some(ProtocolViolation::UnsupportedByClient {
+                method: (*method).to_owned(),
+                capability: pointer[1..].replace('/', "."),
+            })
+        }
+        Some(_) => None,
+    }
+}
+
+macro_rules! check_results {
+    ($method:expr, $result:expr, [$($request:ty),* $(,)?]) => {
+        match $method {
+            $(
+                <$request>::METHOD => {
+                    serde_json::from_value::<<$request as req::Request>::Result>($result.clone())
+                        .err()
+                }
+            )*
+            _ => None,
+        }
+    };
+}
+
+/// Returns the violation committed by answering a `method` request with `res`, if any.
+///
+/// Only the results of standard requests are checked, since error responses and the results of
+/// custom methods have no fixed shape.
+pub(crate) fn check_response(method: &str, res: &Response) -> Option<ProtocolViolation> {
+    let result = res.result()?;
+    let error = check_results!(
+        method,
+        result,
+        [
+            req::Initialize,
+            req::Shutdown,
+            req::WillSaveWaitUntil,
+            req::GotoDeclaration,
+            req::GotoDefinition,
+            req::GotoTypeDefinition,
+            req::GotoImplementation,
+            req::References,
+            req::CallHierarchyPrepare,
+            req::CallHierarchyIncomingCalls,
+            req::CallHierarchyOutgoingCalls,
+            req::TypeHierarchyPrepare,
+            req::TypeHierarchySupertypes,
+            req::TypeHierarchySubtypes,
+            req::DocumentHighlightRequest,
+            req::DocumentLinkRequest,
+            req::DocumentLinkResolve,
+            req::HoverRequest,
+            req::CodeLensRequest,
+            req::CodeLensResolve,
+            req::FoldingRangeRequest,
+            req::SelectionRangeRequest,
+            req::DocumentSymbolRequest,
+            req::SemanticTokensFullRequest,
+            req::SemanticTokensFullDeltaRequest,
+            req::SemanticTokensRangeRequest,
+            req::InlayHintRequest,
+            req::InlayHintResolveRequest,
+            req::InlineValueRequest,
+            req::MonikerRequest,
+            req::Completion,
+            req::ResolveCompletionItem,
+            req::SignatureHelpRequest,
+            req::CodeActionRequest,
+            req::CodeActionResolveRequest,
+            req::DocumentColor,
+            req::ColorPresentationRequest,
+            req::Formatting,
+            req::RangeFormatting,
+            req::OnTypeFormatting,
+            req::Rename,
+            req::PrepareRenameRequest,
+            req::LinkedEditingRange,
+            req::DocumentDiagnosticRequest,
+            req::WorkspaceDiagnosticRequest,
+            req::WorkspaceSymbolRequest,
+            req::WorkspaceSymbolResolve,
+            req::ExecuteCommand,
+            req::WillCreateFiles,
+            req::WillRenameFiles,
+            req::WillDeleteFiles,
+        ]
+    )?;
+
+    Some(ProtocolViolation::InvalidResponse {
+        method: method.to_owned(),
+        reason: error.to_string(),
+    })
+}
+
+/// Returns the `window/logMessage` notification reporting `violation` to the client.
+///
+/// The message holds the violation as a JSON object with a `kind` and a human-readable `message`,
+/// so that it can be picked out of the log by tools.
+pub(crate) fn log_message(violation: &ProtocolViolation) -> Request {
+    let mut details = serde_json::to_value(violation).unwrap_or_default();
+    if let Value::Object(fields) = &mut details {
+        fields.insert("message".into(), violation.to_string().into());
+    }
+
+    let params = LogMessageParams {
+        typ: MessageType::WARNING,
+        message: format!("[tower-lsp] protocol violation: {details}"),
+    };
+    Request::build(LogMessage::METHOD)
+        .params(serde_json::to_value(params).unwrap())
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        PublishDiagnosticsClientCapabilities, TextDocumentClientCapabilities,
+        WindowClientCapabilities,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::jsonrpc::Id;
+
+    #[test]
+    fn checks_client_capabilities() {
+        let progress = Request::build("window/workDoneProgress/create")
+            .params(json!({"token": 1}))
+            .id(1)
+            .finish();
+        let diagnostics = Request::build("textDocument/publishDiagnostics").finish();
+        let log = Request::build("window/logMessage").finish();
+
+        let caps = ClientCapabilities::default();
+        assert_eq!(
+            check_outgoing(&progress, &caps),
+            Some(ProtocolViolation::UnsupportedByClient {
+                method: "window/workDoneProgress/create".into(),
+                capability: "window.workDoneProgress".into(),
+            })
+        );
+        assert!(check_outgoing(&diagnostics, &caps).is_some());
+        assert_eq!(check_outgoing(&log, &caps), None);
+
+        let caps = ClientCapabilities {
+            window: Some(WindowClientCapabilities {
+                work_done_progress: Some(true),
+                ..Default::default()
+            }),
+            text_document: Some(TextDocumentClientCapabilities {
+                publish_diagnostics: Some(PublishDiagnosticsClientCapabilities::default()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(check_outgoing(&progress, &caps), None);
+        assert_eq!(check_outgoing(&diagnostics, &caps), None);
+    }
+
+    #[test]
+    fn checks_response_shapes() {
+        let hover = Response::from_ok(Id::Number(1), json!({"contents": "docs"}));
+        assert_eq!(check_response("textDocument/hover", &hover), None);
+
+        let hover = Response::from_ok(Id::Number(1), json!({"content": "docs"}));
+        let violation = check_response("textDocument/hover", &hover).unwrap();
+        assert!(matches!(
+            violation,
+            ProtocolViolation::InvalidResponse { ref method, .. } if method == "textDocument/hover"
+        ));
+
+        let custom = Response::from_ok(Id::Number(1), json!(42));
+        assert_eq!(check_response("custom/method", &custom), None);
+
+        let msg = log_message(&violation);
+        let text = msg.params().unwrap()["message"].as_str().unwrap();
+        let details: Value = serde_json::from_str(text.split_once(": ").unwrap().1).unwrap();
+        assert_eq!(details["kind"], "invalidResponse");
+        assert_eq!(details["method"], "textDocument/hover");
+    }
+}
@@ -0,0 +1,124 @@
+//! Typed storage shared by everything serving a single connection.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, RwLock};
+
+type AnyMap = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+/// A map of values keyed by their type, shared by everything serving a single connection.
+///
+/// Every [`LspService`](crate::LspService) owns one such map, available to the server backend
+/// through [`Client::extensions`](crate::Client::extensions) and to middleware wrapping the service
+/// through [`LspService::extensions`](crate::LspService::extensions). This allows middleware to
+/// share data it computed, e.g. the identity of an authenticated user or a mapping between client
+/// and server URIs, with the backend without resorting to global statics.
+///
+/// The map holds at most one value per type, so it is best to store dedicated newtypes rather than
+/// common types like `String`. Values are kept until the `LspService` and all of its clients are
+/// dropped, including across sessions when using
+/// [`ExitBehavior::CloseSessionOnly`](crate::ExitBehavior::CloseSessionOnly).
+///
+/// This handle is cheap to clone, and all clones refer to the same map.
+///
+/// # Examples
+///
+/// ```rust
+/// use tower_lsp::Extensions;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct User(String);
+///
+/// let extensions = Extensions::default();
+/// extensions.insert(User("alice".into()));
+///
+/// let user = extensions.get::<User>().unwrap();
+/// assert_eq!(*user, User("alice".into()));
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions(Arc<RwLock<AnyMap>>);
+
+impl Extensions {
+    /// Inserts `value` into the map, returning the value of the same type previously stored, if
+    /// any.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) -> Option<Arc<T>> {
+        let mut map = self.0.write().unwrap();
+        let previous = map.insert(TypeId::of::<T>(), Arc::new(value));
+        previous.and_then(downcast)
+    }
+
+    /// Returns the value of type `T` stored in the map, if any.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let map = self.0.read().unwrap();
+        map.get(&TypeId::of::<T>()).cloned().and_then(downcast)
+    }
+
+    /// Returns the value of type `T` stored in the map, inserting the result of `f` first if the
+    /// map holds no such value.
+    pub fn get_or_insert_with<T, F>(&self, f: F) -> Arc<T>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> T,
+    {
+        let mut map = self.0.write().unwrap();
+        let value = map
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(f()))
+            .clone();
+        downcast(value).expect("value stored under the `TypeId` of another type")
+    }
+
+    /// Removes the value of type `T` from the map, returning it if it was present.
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let mut map = self.0.write().unwrap();
+        map.remove(&TypeId::of::<T>()).and_then(downcast)
+    }
+
+    /// Returns `true` if the map holds a value of type `T`.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.0.read().unwrap().contains_key(&TypeId::of::<T>())
+    }
+}
+
+fn downcast<T: Send + Sync + 'static>(value: Arc<dyn Any + Send + Sync>) -> Option<Arc<T>> {
+    value.downcast().ok()
+}
+
+impl Debug for Extensions {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let len = self.0.read().unwrap().len();
+        f.debug_struct("Extensions").field("len", &len).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Token(&'static str);
+
+    #[test]
+    fn stores_values_by_type() {
+        let extensions = Extensions::default();
+        assert!(!extensions.contains::<Token>());
+        assert_eq!(extensions.insert(Token("first")), None);
+
+        let shared = extensions.clone();
+        let previous = shared.insert(Token("second"));
+        assert_eq!(previous.as_deref(), Some(&Token("first")));
+        assert_eq!(extensions.get::<Token>().as_deref(), Some(&Token("second")));
+        assert_eq!(extensions.get::<u32>(), None);
+
+        let count = extensions.get_or_insert_with(|| 1u32);
+        assert_eq!(*count, 1);
+        assert_eq!(*extensions.get_or_insert_with(|| 2u32), 1);
+
+        assert_eq!(
+            extensions.remove::<Token>().as_deref(),
+            Some(&Token("second"))
+        );
+        assert!(!shared.contains::<Token>());
+    }
+}
@@ -0,0 +1,348 @@
+//! Server-side watching of files, for clients which cannot watch files on behalf of the server.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use lsp_types::notification::{DidChangeWatchedFiles, Notification};
+use lsp_types::*;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::glob::GlobMatcher;
+use crate::jsonrpc::Request;
+
+/// Watchers of a `workspace/didChangeWatchedFiles` registration, keyed by registration ID.
+type Registrations = Arc<Mutex<Vec<(String, Vec<(GlobMatcher, WatchKind)>)>>>;
+
+/// Watches files for the `workspace/didChangeWatchedFiles` registrations the client can't handle.
+///
+/// File system events matching the registered watchers are turned into
+/// `workspace/didChangeWatchedFiles` notifications, which the [`Server`](crate::Server) handles as
+/// if they were sent by the client.
+#[derive(Debug)]
+pub(crate) struct FileWatcher {
+    enabled: AtomicBool,
+    registrations: Registrations,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    watcher: Option<RecommendedWatcher>,
+    /// Workspace folders of the current session.
+    roots: Vec<PathBuf>,
+    /// Paths currently watched recursively.
+    watched: Vec<PathBuf>,
+    tx: UnboundedSender<Request>,
+    rx: Option<UnboundedReceiver<Request>>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        FileWatcher {
+            enabled: AtomicBool::new(false),
+            registrations: Registrations::default(),
+            inner: Mutex::new(Inner {
+                watcher: None,
+                roots: Vec::new(),
+                watched: Vec::new(),
+                tx,
+                rx: Some(rx),
+            }),
+        }
+    }
+
+    /// Enables watching files for clients without support for dynamic registration of
+    /// `workspace/didChangeWatchedFiles`.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether `workspace/didChangeWatchedFiles` registrations should be handled locally
+    /// for a client with the given capabilities.
+    pub fn is_fallback(&self, caps: Option<&ClientCapabilities>) -> bool {
+        let dynamic_registration = caps
+            .and_then(|caps| caps.workspace.as_ref())
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched_files| watched_files.dynamic_registration);
+
+        self.enabled.load(Ordering::SeqCst) && dynamic_registration != Some(true)
+    }
+
+    /// Starts watching the files described by `registration`.
+    ///
+    /// Returns `false` if `registration` is not a `workspace/didChangeWatchedFiles` registration.
+    pub fn register(&self, registration: &Registration) -> bool {
+        if registration.method != DidChangeWatchedFiles::METHOD {
+            return false;
+        }
+
+        let options = registration.register_options.clone().unwrap_or(Value::Null);
+        let watchers = match serde_json::from_value(options) {
+            Ok(DidChangeWatchedFilesRegistrationOptions { watchers }) => watchers,
+            Err(err) => {
+                warn!("invalid file watcher registration options: {}", err);
+                Vec::new()
+            }
+        };
+
+        let matchers = watchers
+            .into_iter()
+            .filter_map(|watcher| match GlobMatcher::new(watcher.glob_pattern) {
+                Ok(matcher) => Some((matcher, watcher.kind.unwrap_or(WatchKind::all()))),
+                Err(err) => {
+                    warn!("ignoring invalid file watcher glob pattern: {}", err);
+                    None
+                }
+            })
+            .collect();
+
+        let mut registrations = self.registrations.lock().unwrap();
+        registrations.retain(|(id, _)| *id != registration.id);
+        registrations.push((registration.id.clone(), matchers));
+        drop(registrations);
+
+        self.sync();
+        true
+    }
+
+    /// Stops watching the files of the registration with the given `id`.
+    ///
+    /// Returns `false` if no such registration is handled locally.
+    pub fn unregister(&self, id: &str) -> bool {
+        let mut registrations = self.registrations.lock().unwrap();
+        let len = registrations.len();
+        registrations.retain(|(registered, _)| registered != id);
+        let removed = registrations.len() != len;
+        drop(registrations);
+
+        if removed {
+            self.sync();
+        }
+
+        removed
+    }
+
+    /// Updates the workspace folders from the `initialize` request or the
+    /// `workspace/didChangeWorkspaceFolders` notification `req`.
+    pub fn observe(&self, req: &Request) {
+        let mut inner = self.inner.lock().unwrap();
+        match req.method() {
+            "initialize" => {
+                let params = req.params_as::<InitializeParams>().ok();
+                #[allow(deprecated)]
+                let roots = params.map(|p| match p.workspace_folders {
+                    Some(folders) => folders.into_iter().map(|f| f.uri).collect(),
+                    None => p.root_uri.into_iter().collect::<Vec<_>>(),
+                });
+                inner.roots = roots
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|uri| uri.to_file_path().ok())
+                    .collect();
+            }
+            "workspace/didChangeWorkspaceFolders" => {
+                let event = match req.params_as::<DidChangeWorkspaceFoldersParams>() {
+                    Ok(params) => params.event,
+                    Err(_) => return,
+                };
+
+                let path = |folder: WorkspaceFolder| folder.uri.to_file_path().ok();
+                let removed: Vec<_> = event.removed.into_iter().filter_map(path).collect();
+                inner.roots.retain(|root| !removed.contains(root));
+                inner.roots.extend(event.added.into_iter().filter_map(path));
+            }
+            _ => return,
+        }
+
+        drop(inner);
+        self.sync();
+    }
+
+    /// Takes the stream of notifications generated from file system events.
+    pub fn take_notifications(&self) -> Option<UnboundedReceiver<Request>> {
+        self.inner.lock().unwrap().rx.take()
+    }
+
+    /// Stops watching files and forgets the registrations and folders of the current session.
+    pub fn reset(&self) {
+        self.registrations.lock().unwrap().clear();
+        self.inner.lock().unwrap().roots.clear();
+        self.sync();
+    }
+
+    /// Watches the workspace folders and the base folders of relative patterns, as long as any
+    /// registration is active.
+    fn sync(&self) {
+        let mut wanted = Vec::new();
+        let registrations = self.registrations.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
+        if !registrations.is_empty() {
+            wanted.extend(inner.roots.iter().cloned());
+            let matchers = registrations.iter().flat_map(|(_, matchers)| matchers);
+            wanted.extend(matchers.filter_map(|(matcher, _)| base_path(matcher)));
+        }
+        wanted.sort();
+        wanted.dedup();
+        drop(registrations);
+
+        let inner = &mut *inner;
+        if inner.watcher.is_none() && !wanted.is_empty() {
+            let registrations = self.registrations.clone();
+            let tx = inner.tx.clone();
+            let handler = move |event: notify::Result<Event>| match event {
+                Ok(event) => notify(&event, &registrations, &tx),
+                Err(err) => warn!("failed to watch files: {}", err),
+            };
+
+            match notify::recommended_watcher(handler) {
+                Ok(watcher) => inner.watcher = Some(watcher),
+                Err(err) => warn!("failed to start file watcher: {}", err),
+            }
+        }
+
+        let watcher = match inner.watcher.as_mut() {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        for path in inner.watched.iter().filter(|path| !wanted.contains(path)) {
+            if let Err(err) = watcher.unwatch(path) {
+                debug!("failed to unwatch {}: {}", path.display(), err);
+            }
+        }
+
+        for path in wanted.iter().filter(|path| !inner.watched.contains(path)) {
+            if let Err(err) = watcher.watch(path, RecursiveMode::Recursive) {
+                warn!("failed to watch {}: {}", path.display(), err);
+            }
+        }
+
+        inner.watched = wanted;
+    }
+}
+
+/// Returns the local path of the base URI of `matcher`, if it is a relative pattern.
+fn base_path(matcher: &GlobMatcher) -> Option<PathBuf> {
+    match matcher.pattern() {
+        GlobPattern::String(_) => None,
+        GlobPattern::Relative(pattern) => match &pattern.base_uri {
+            OneOf::Left(folder) => folder.uri.to_file_path().ok(),
+            OneOf::Right(uri) => uri.to_file_path().ok(),
+        },
+    }
+}
+
+/// Sends a `workspace/didChangeWatchedFiles` notification with the changes of `event` matching
+/// any of the `registrations`.
+fn notify(event: &Event, registrations: &Registrations, tx: &UnboundedSender<Request>) {
+    use FileChangeType as Change;
+
+    let changes: Vec<_> = match (&event.kind, &event.paths[..]) {
+        (EventKind::Create(_), paths) => paths.iter().map(|p| (p, Change::CREATED)).collect(),
+        (EventKind::Modify(ModifyKind::Name(RenameMode::From)), paths) => {
+            paths.iter().map(|p| (p, Change::DELETED)).collect()
+        }
+        (EventKind::Modify(ModifyKind::Name(RenameMode::To)), paths) => {
+            paths.iter().map(|p| (p, Change::CREATED)).collect()
+        }
+        (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => {
+            vec![(from, Change::DELETED), (to, Change::CREATED)]
+        }
+        (EventKind::Modify(_), paths) => paths.iter().map(|p| (p, Change::CHANGED)).collect(),
+        (EventKind::Remove(_), paths) => paths.iter().map(|p| (p, Change::DELETED)).collect(),
+        _ => return,
+    };
+
+    let registrations = registrations.lock().unwrap();
+    let changes: Vec<_> = changes
+        .into_iter()
+        .filter_map(|(path, typ)| Some(FileEvent::new(Url::from_file_path(path).ok()?, typ)))
+        .filter(|event| {
+            let kind = match event.typ {
+                Change::CREATED => WatchKind::Create,
+                Change::DELETED => WatchKind::Delete,
+                _ => WatchKind::Change,
+            };
+
+            let mut matchers = registrations.iter().flat_map(|(_, matchers)| matchers);
+            matchers.any(|(matcher, kinds)| kinds.contains(kind) && matcher.is_match(&event.uri))
+        })
+        .collect();
+
+    if !changes.is_empty() {
+        let params = DidChangeWatchedFilesParams { changes };
+        let notification = Request::build(DidChangeWatchedFiles::METHOD)
+            .params(serde_json::to_value(params).unwrap())
+            .finish();
+        let _ = tx.unbounded_send(notification);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{FutureExt, StreamExt};
+    use notify::event::CreateKind;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn synthesizes_watched_file_notifications() {
+        let watcher = FileWatcher::new();
+        watcher.enable();
+        assert!(watcher.is_fallback(Some(&ClientCapabilities::default())));
+
+        // Without workspace folders or relative patterns, nothing is watched on disk, and events
+        // are fed to the handler directly.
+        let options = json!({"watchers":[{"globPattern":"**/*.rs","kind":1}]});
+        let registration = Registration {
+            id: "watch".into(),
+            method: DidChangeWatchedFiles::METHOD.into(),
+            register_options: Some(options),
+        };
+        assert!(watcher.register(&registration));
+        assert!(watcher.inner.lock().unwrap().watcher.is_none());
+
+        let mut notifications = watcher.take_notifications().unwrap();
+        let tx = watcher.inner.lock().unwrap().tx.clone();
+        let root = std::env::temp_dir().join("tower-lsp-watch");
+        let send = |kind, paths: &[&str]| {
+            let event = paths.iter().fold(Event::new(kind), |event, path| {
+                event.add_path(root.join(path))
+            });
+            notify(&event, &watcher.registrations, &tx);
+        };
+        let change =
+            |path: &str, typ| FileEvent::new(Url::from_file_path(root.join(path)).unwrap(), typ);
+        let mut next = || -> Option<Vec<FileEvent>> {
+            let notification = notifications.next().now_or_never()?.unwrap();
+            let params: DidChangeWatchedFilesParams = notification.params_as().unwrap();
+            Some(params.changes)
+        };
+
+        send(EventKind::Create(CreateKind::File), &["ignored.txt"]);
+        send(EventKind::Create(CreateKind::File), &["main.rs"]);
+        assert_eq!(
+            next(),
+            Some(vec![change("main.rs", FileChangeType::CREATED)])
+        );
+
+        // Only the creation half of a rename is watched.
+        let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        send(rename, &["main.rs", "lib.rs"]);
+        assert_eq!(
+            next(),
+            Some(vec![change("lib.rs", FileChangeType::CREATED)])
+        );
+
+        assert!(watcher.unregister("watch"));
+        send(EventKind::Create(CreateKind::File), &["main.rs"]);
+        assert_eq!(next(), None);
+    }
+}
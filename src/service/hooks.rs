@@ -0,0 +1,256 @@
+//! Lifecycle hooks registered with
+//! [`LspServiceBuilder::on_initialize`](crate::LspServiceBuilder::on_initialize),
+//! [`LspServiceBuilder::on_shutdown`](crate::LspServiceBuilder::on_shutdown) and
+//! [`LspServiceBuilder::validate_initialize`](crate::LspServiceBuilder::validate_initialize).
+
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use lsp_types::{InitializeError, InitializeParams, InitializeResult};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use super::client::Client;
+use super::state::State;
+use crate::jsonrpc::{self, Error, ErrorCode, Request, Response};
+
+/// Stage of the `initialize` request at which a hook registered with
+/// [`LspServiceBuilder::on_initialize`](crate::LspServiceBuilder::on_initialize) is called.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InitializeHook<'a> {
+    /// The backend's `initialize` handler is about to be called with these params.
+    Before(&'a InitializeParams),
+    /// The backend's `initialize` handler returned, with these params and this result.
+    ///
+    /// The result may be modified, e.g. to advertise additional capabilities, before being sent
+    /// to the client.
+    After(
+        &'a InitializeParams,
+        &'a mut jsonrpc::Result<InitializeResult>,
+    ),
+}
+
+/// Stage of the `shutdown` request at which a hook registered with
+/// [`LspServiceBuilder::on_shutdown`](crate::LspServiceBuilder::on_shutdown) is called.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ShutdownHook<'a> {
+    /// The backend's `shutdown` handler is about to be called.
+    Before,
+    /// The backend's `shutdown` handler returned this result.
+    After(&'a jsonrpc::Result<()>),
+}
+
+/// Reason for refusing an `initialize` request, returned by a validator registered with
+/// [`LspServiceBuilder::validate_initialize`](crate::LspServiceBuilder::validate_initialize).
+///
+/// The client receives a "request failed" error (`-32803`) carrying the message, with an
+/// [`InitializeError`] as its `data`, which tells the client whether to offer retrying.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InitializeRejection {
+    message: Cow<'static, str>,
+    retry: bool,
+}
+
+impl InitializeRejection {
+    /// Creates a new rejection with the given `message`, shown to the user by most clients.
+    pub fn new<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        InitializeRejection {
+            message: message.into(),
+            retry: false,
+        }
+    }
+
+    /// Asks the client to offer the user to retry initializing, e.g. after fixing its setup.
+    pub fn retry(mut self) -> Self {
+        self.retry = true;
+        self
+    }
+
+    /// Returns the message describing why initialization was refused.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<InitializeRejection> for Error {
+    fn from(rejection: InitializeRejection) -> Self {
+        let data = InitializeError {
+            retry: rejection.retry,
+        };
+
+        Error {
+            code: ErrorCode::RequestFailed,
+            message: rejection.message,
+            data: serde_json::to_value(data).ok(),
+        }
+    }
+}
+
+type InitializeFn = dyn Fn(&Client, InitializeHook<'_>) + Send + Sync;
+type ShutdownFn = dyn Fn(&Client, ShutdownHook<'_>) + Send + Sync;
+type ValidateFn = dyn Fn(&mut InitializeParams) -> Result<(), InitializeRejection> + Send + Sync;
+
+/// Hooks called around the `initialize` and `shutdown` handlers, in registration order.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    validate: Vec<Box<ValidateFn>>,
+    initialize: Vec<Box<InitializeFn>>,
+    shutdown: Vec<Box<ShutdownFn>>,
+}
+
+impl Hooks {
+    pub fn push_validate<F>(&mut self, validate: F)
+    where
+        F: Fn(&mut InitializeParams) -> Result<(), InitializeRejection> + Send + Sync + 'static,
+    {
+        self.validate.push(Box::new(validate));
+    }
+
+    pub fn push_initialize<F>(&mut self, hook: F)
+    where
+        F: Fn(&Client, InitializeHook<'_>) + Send + Sync + 'static,
+    {
+        self.initialize.push(Box::new(hook));
+    }
+
+    pub fn push_shutdown<F>(&mut self, hook: F)
+    where
+        F: Fn(&Client, ShutdownHook<'_>) + Send + Sync + 'static,
+    {
+        self.shutdown.push(Box::new(hook));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validate.is_empty() && self.initialize.is_empty() && self.shutdown.is_empty()
+    }
+
+    /// Binds these hooks to the `client` they are called with.
+    pub fn with_client(self, client: Client) -> BoundHooks {
+        BoundHooks(Arc::new((self, client)))
+    }
+}
+
+/// [`Hooks`] along with the [`Client`] passed to them.
+#[derive(Clone)]
+pub(crate) struct BoundHooks(Arc<(Hooks, Client)>);
+
+impl BoundHooks {
+    /// Calls the hooks preceding the handling of `req`, if it is an `initialize` or `shutdown`
+    /// request which the backend is about to handle in the given server `state`.
+    ///
+    /// Returns the hooks to call with the response, once the backend has handled `req`. The
+    /// params of `initialize` requests are replaced with those amended by the validators, or the
+    /// response to send instead of calling the backend is returned if a validator rejected them.
+    pub fn before(&self, req: &mut Request, state: State) -> Result<Option<AfterHooks>, Response> {
+        let (hooks, client) = &*self.0;
+        let id = match req.id() {
+            Some(id) => id.clone(),
+            None => return Ok(None),
+        };
+
+        match (req.method(), state) {
+            ("initialize", State::Uninitialized) => {
+                if hooks.validate.is_empty() && hooks.initialize.is_empty() {
+                    return Ok(None);
+                }
+
+                // Invalid params are rejected by the backend's handler before it runs.
+                let mut params: InitializeParams = match req.params_as() {
+                    Ok(params) => params,
+                    Err(_) => return Ok(None),
+                };
+
+                if !hooks.validate.is_empty() {
+                    for validate in &hooks.validate {
+                        if let Err(rejection) = validate(&mut params) {
+                            info!("rejecting `initialize` request: {}", rejection.message());
+                            return Err(Response::from_error(id, rejection.into()));
+                        }
+                    }
+
+                    if let (Some(raw), Ok(value)) =
+                        (req.params_mut(), serde_json::to_value(&params))
+                    {
+                        *raw = value;
+                    }
+                }
+
+                for hook in &hooks.initialize {
+                    hook(client, InitializeHook::Before(&params));
+                }
+
+                Ok(Some(AfterHooks::Initialize(self.clone(), Box::new(params))))
+            }
+            ("shutdown", State::Initialized) if !hooks.shutdown.is_empty() => {
+                for hook in &hooks.shutdown {
+                    hook(client, ShutdownHook::Before);
+                }
+
+                Ok(Some(AfterHooks::Shutdown(self.clone())))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Debug for BoundHooks {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (hooks, _) = &*self.0;
+        f.debug_struct(stringify!(Hooks))
+            .field("validate", &hooks.validate.len())
+            .field("initialize", &hooks.initialize.len())
+            .field("shutdown", &hooks.shutdown.len())
+            .finish()
+    }
+}
+
+/// Hooks to call once the backend has handled a lifecycle request, returned by
+/// [`BoundHooks::before`].
+pub(crate) enum AfterHooks {
+    Initialize(BoundHooks, Box<InitializeParams>),
+    Shutdown(BoundHooks),
+}
+
+impl AfterHooks {
+    /// Calls the hooks with the result carried by `res`, returning the response to send.
+    pub fn after(self, res: Response) -> Response {
+        let (id, result) = res.into_parts();
+
+        let result = match self {
+            AfterHooks::Initialize(bound, params) => {
+                let (hooks, client) = &*bound.0;
+                let mut result = result.and_then(|value| {
+                    serde_json::from_value(value).map_err(|err| {
+                        warn!("`initialize` returned an invalid result: {}", err);
+                        Error::internal_error()
+                    })
+                });
+
+                for hook in &hooks.initialize {
+                    hook(client, InitializeHook::After(&params, &mut result));
+                }
+
+                result.and_then(|result| {
+                    serde_json::to_value(result).map_err(|_| Error::internal_error())
+                })
+            }
+            AfterHooks::Shutdown(bound) => {
+                let (hooks, client) = &*bound.0;
+                let result = result.map(drop);
+                for hook in &hooks.shutdown {
+                    hook(client, ShutdownHook::After(&result));
+                }
+
+                result.map(|()| Value::Null)
+            }
+        };
+
+        Response::from_parts(id, result)
+    }
+}
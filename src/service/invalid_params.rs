@@ -0,0 +1,52 @@
+//! Handling of messages whose params fail to deserialize.
+
+use serde_json::Value;
+use tracing::warn;
+
+use crate::jsonrpc::{Error, Id, Response};
+
+/// Action taken when the params of a message cannot be deserialized, configured per method with
+/// [`LspServiceBuilder::on_invalid_params`](crate::LspServiceBuilder::on_invalid_params).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum InvalidParamsPolicy {
+    /// Answers requests with a JSON-RPC error with code `-32602` (Invalid params), and silently
+    /// drops notifications.
+    ///
+    /// This is the default for all methods.
+    InvalidParams,
+    /// Logs a warning and treats the message as a no-op.
+    ///
+    /// Notifications are dropped, and requests are answered with a `null` result.
+    Ignore,
+    /// Answers requests with a JSON-RPC error with code `-32803` (Request failed) describing the
+    /// problem, and logs a warning for notifications.
+    ///
+    /// Some clients treat `Invalid params` errors as fatal, while `Request failed` is merely
+    /// reported to the user.
+    RequestFailed,
+}
+
+impl InvalidParamsPolicy {
+    /// Returns the response to the message `method` with the given `id`, whose params were
+    /// rejected with `err`.
+    pub(crate) fn apply(self, method: &str, id: Option<Id>, err: Error) -> Option<Response> {
+        match self {
+            InvalidParamsPolicy::InvalidParams => id.map(|id| Response::from_error(id, err)),
+            InvalidParamsPolicy::Ignore => {
+                warn!("ignoring `{}` with invalid params: {}", method, err.message);
+                id.map(|id| Response::from_ok(id, Value::Null))
+            }
+            InvalidParamsPolicy::RequestFailed => match id {
+                Some(id) => {
+                    let message = format!("invalid params: {}", err.message);
+                    Some(Response::from_error(id, Error::request_failed(message)))
+                }
+                None => {
+                    warn!("dropping `{}` with invalid params: {}", method, err.message);
+                    None
+                }
+            },
+        }
+    }
+}
@@ -4,16 +4,16 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use futures::future::{self, BoxFuture, FutureExt};
+use futures::future::{self, BoxFuture, Either, FutureExt};
 use tower::{Layer, Service};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 use super::ExitedError;
-use crate::jsonrpc::{not_initialized_error, Error, Id, Request, Response};
+use crate::jsonrpc::{Error, Request, Response};
 
 use super::client::Client;
 use super::pending::Pending;
-use super::state::{ServerState, State};
+use super::state::{ExitBehavior, ServerState, State, StateError};
 
 /// Middleware which implements `initialize` request semantics.
 ///
@@ -62,24 +62,69 @@ where
     }
 
     fn call(&mut self, req: Request) -> Self::Future {
-        if self.state.get() == State::Uninitialized {
-            let state = self.state.clone();
-            let fut = self.inner.call(req);
+        // Claiming the `Initializing` state atomically guarantees that only one of several
+        // concurrent `initialize` requests reaches the server, even if the caller did not wait
+        // for `poll_ready` in between.
+        let claimed = self
+            .state
+            .compare_exchange(State::Uninitialized, State::Initializing);
+
+        if let Err(state) = claimed {
+            match state {
+                State::Initializing => warn!("received concurrent `initialize` request, rejecting"),
+                _ => warn!("received duplicate `initialize` request, ignoring"),
+            }
 
-            Box::pin(async move {
-                let response = fut.await?;
+            let (_, id, _) = req.into_parts();
+            return future::ok(id.map(|id| Response::from_error(id, Error::invalid_request())))
+                .boxed();
+        }
 
-                match &response {
-                    Some(res) if res.is_ok() => state.set(State::Initialized),
-                    _ => state.set(State::Uninitialized),
-                }
+        let params = req.params();
+        let trace = params.and_then(|p| p.get("trace")).cloned();
+        if let Some(value) = trace.and_then(|v| serde_json::from_value(v).ok()) {
+            self.state.set_trace(value);
+        }
 
-                Ok(response)
-            })
-        } else {
-            warn!("received duplicate `initialize` request, ignoring");
-            let (_, id, _) = req.into_parts();
-            future::ok(id.map(|id| Response::from_error(id, Error::invalid_request()))).boxed()
+        let capabilities = params.and_then(|p| p.get("capabilities")).cloned();
+        #[cfg(feature = "proposed")]
+        self.state.set_raw_client_capabilities(capabilities.clone());
+        if let Some(caps) = capabilities.and_then(|v| serde_json::from_value(v).ok()) {
+            self.state.set_client_capabilities(caps);
+        }
+
+        let guard = InitializingGuard(Some(self.state.clone()));
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?;
+
+            match &response {
+                Some(res) if res.is_ok() => guard.finish(State::Initialized),
+                _ => guard.finish(State::Uninitialized),
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Returns the server to the `Uninitialized` state if the `initialize` request is dropped or fails
+/// before completing, so that the client may try again.
+struct InitializingGuard(Option<Arc<ServerState>>);
+
+impl InitializingGuard {
+    fn finish(mut self, state: State) {
+        if let Some(server) = self.0.take() {
+            server.set(state);
+        }
+    }
+}
+
+impl Drop for InitializingGuard {
+    fn drop(&mut self) {
+        if let Some(server) = self.0.take() {
+            server.set(State::Uninitialized);
         }
     }
 }
@@ -120,11 +165,11 @@ pub struct ShutdownService<S> {
 impl<S> Service<Request> for ShutdownService<S>
 where
     S: Service<Request, Response = Option<Response>, Error = ExitedError>,
-    S::Future: Into<BoxFuture<'static, Result<Option<Response>, S::Error>>> + Send + 'static,
+    S::Future: Send + 'static,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Future = Either<CancellableFuture<S>, future::Ready<Result<Self::Response, Self::Error>>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
@@ -135,12 +180,9 @@ where
             State::Initialized => {
                 info!("shutdown request received, shutting down");
                 self.state.set(State::ShutDown);
-                self.inner.call(req)
-            }
-            cur_state => {
-                let (_, id, _) = req.into_parts();
-                future::ok(not_initialized_response(id, cur_state)).boxed()
+                Either::Left(self.inner.call(req))
             }
+            cur_state => Either::Right(future::ok(state_error_response(req, cur_state))),
         }
     }
 }
@@ -201,10 +243,21 @@ impl<S> Service<Request> for ExitService<S> {
     }
 
     fn call(&mut self, _: Request) -> Self::Future {
-        info!("exit notification received, stopping");
-        self.state.set(State::Exited);
-        self.pending.cancel_all();
-        self.client.close();
+        match self.state.exit_behavior() {
+            ExitBehavior::TerminateService => {
+                info!("exit notification received, stopping");
+                self.state.set(State::Exited);
+                self.pending.cancel_all();
+                self.client.close();
+            }
+            ExitBehavior::CloseSessionOnly => {
+                info!("exit notification received, closing session");
+                self.pending.cancel_all();
+                self.client.reset_session();
+                self.state.reset_session();
+            }
+        }
+
         future::ok(None)
     }
 }
@@ -241,11 +294,11 @@ pub struct NormalService<S> {
 impl<S> Service<Request> for NormalService<S>
 where
     S: Service<Request, Response = Option<Response>, Error = ExitedError>,
-    S::Future: Into<BoxFuture<'static, Result<Option<Response>, S::Error>>> + Send + 'static,
+    S::Future: Send + 'static,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Future = Either<CancellableFuture<S>, future::Ready<Result<Self::Response, Self::Error>>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
@@ -253,11 +306,8 @@ where
 
     fn call(&mut self, req: Request) -> Self::Future {
         match self.state.get() {
-            State::Initialized => self.inner.call(req),
-            cur_state => {
-                let (_, id, _) = req.into_parts();
-                future::ok(not_initialized_response(id, cur_state)).boxed()
-            }
+            State::Initialized => Either::Left(self.inner.call(req)),
+            cur_state => Either::Right(future::ok(state_error_response(req, cur_state))),
         }
     }
 }
@@ -285,7 +335,7 @@ where
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Future = CancellableFuture<S>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.poll_ready(cx)
@@ -293,20 +343,29 @@ where
 
     fn call(&mut self, req: Request) -> Self::Future {
         match req.id().cloned() {
-            Some(id) => self.pending.execute(id, self.inner.call(req)).boxed(),
-            None => self.inner.call(req).boxed(),
+            Some(id) => {
+                let method = req.method_owned();
+                let fut = self.pending.execute(id, method, self.inner.call(req));
+                Either::Left(fut.boxed())
+            }
+            // Notifications cannot be canceled, so their futures are passed through as they are.
+            None => Either::Right(self.inner.call(req)),
         }
     }
 }
 
-fn not_initialized_response(id: Option<Id>, server_state: State) -> Option<Response> {
-    let id = id?;
-    let error = match server_state {
-        State::Uninitialized | State::Initializing => not_initialized_error(),
-        _ => Error::invalid_request(),
-    };
-
-    Some(Response::from_error(id, error))
+/// Response future of [`Cancellable`], which only allocates for requests.
+pub type CancellableFuture<S> = Either<
+    BoxFuture<'static, Result<Option<Response>, ExitedError>>,
+    <S as Service<Request>>::Future,
+>;
+
+/// Rejects `req`, which is not allowed in the current `server_state`.
+fn state_error_response(req: Request, server_state: State) -> Option<Response> {
+    let (method, id, _) = req.into_parts();
+    let error = StateError::new(method, server_state);
+    debug!("{}", error);
+    Some(Response::from_error(id?, error.into()))
 }
 
 // TODO: Add some `tower-test` middleware tests for each middleware.
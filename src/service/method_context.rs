@@ -0,0 +1,99 @@
+//! Context passed to custom method handlers.
+
+use std::fmt::{self, Debug, Formatter};
+
+use super::{Client, DetachedRequest};
+use crate::jsonrpc::Id;
+
+/// Context of a call to a handler registered with
+/// [`LspServiceBuilder::custom_method_with_context`](crate::LspServiceBuilder::custom_method_with_context).
+///
+/// It takes the place of the `&self` receiver, giving handlers access to the [`Client`] and the
+/// raw request, so they don't have to be methods of the server backend.
+pub struct MethodContext<'a, S> {
+    server: &'a S,
+    client: &'a Client,
+    id: Option<&'a Id>,
+    method: &'static str,
+}
+
+impl<'a, S> MethodContext<'a, S> {
+    pub(crate) fn new(
+        server: &'a S,
+        client: &'a Client,
+        id: Option<&'a Id>,
+        method: &'static str,
+    ) -> Self {
+        MethodContext {
+            server,
+            client,
+            id,
+            method,
+        }
+    }
+
+    /// Returns a reference to the language server backend.
+    pub fn server(&self) -> &'a S {
+        self.server
+    }
+
+    /// Returns a handle for sending messages to the client.
+    pub fn client(&self) -> &'a Client {
+        self.client
+    }
+
+    /// Returns the ID of the request, or `None` if the method was called as a notification.
+    pub fn id(&self) -> Option<&'a Id> {
+        self.id
+    }
+
+    /// Returns the name of the method being called.
+    pub fn method(&self) -> &'static str {
+        self.method
+    }
+
+    /// Takes ownership of the request, so it can be answered later with [`Client::respond`].
+    ///
+    /// The handler should return the result of awaiting the returned [`DetachedRequest`], which
+    /// keeps the request pending, and thus cancellable, until a response is provided. Returns
+    /// `None` if the method was called as a notification or the request was already detached.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use serde_json::Value;
+    /// # use tower_lsp::jsonrpc::{Error, Result};
+    /// # use tower_lsp::MethodContext;
+    /// # struct Backend;
+    /// # impl Backend {
+    /// #     fn forward(&self, _: tower_lsp::jsonrpc::Id, _: Value) {}
+    /// # }
+    /// async fn proxy(cx: MethodContext<'_, Backend>, params: Value) -> Result<Value> {
+    ///     let request = cx.detach().ok_or_else(Error::invalid_request)?;
+    ///
+    ///     // The external process later answers with `client.respond(&id, result)`.
+    ///     cx.server().forward(request.id().clone(), params);
+    ///     request.await
+    /// }
+    /// ```
+    pub fn detach(&self) -> Option<DetachedRequest> {
+        self.id.and_then(|id| self.client.detach(id))
+    }
+}
+
+impl<S> Clone for MethodContext<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for MethodContext<'_, S> {}
+
+impl<S> Debug for MethodContext<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MethodContext")
+            .field("id", &self.id)
+            .field("method", &self.method)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,181 @@
+//! Per-method request metrics recorded by [`LspService`](crate::LspService).
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde::{Serialize, Serializer};
+
+/// Number of histogram buckets. Bucket `i` counts latencies below `2^i` microseconds, so the last
+/// bucket also absorbs everything above roughly 35 minutes.
+const BUCKETS: usize = 32;
+
+/// Latency histogram and counters of a single method.
+#[derive(Clone, Debug)]
+struct Histogram {
+    requests: u64,
+    errors: u64,
+    buckets: [u64; BUCKETS],
+    max: Duration,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            requests: 0,
+            errors: 0,
+            buckets: [0; BUCKETS],
+            max: Duration::ZERO,
+        }
+    }
+
+    fn record(&mut self, latency: Duration, is_error: bool) {
+        let micros = latency.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+
+        self.requests += 1;
+        self.errors += u64::from(is_error);
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.max = self.max.max(latency);
+    }
+
+    /// Returns an upper bound on the latency of the given fraction `q` of requests.
+    fn percentile(&self, q: f64) -> Duration {
+        let target = ((self.requests as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Duration::from_micros(1 << i).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    fn snapshot(&self) -> MethodMetrics {
+        MethodMetrics {
+            requests: self.requests,
+            errors: self.errors,
+            p50: self.percentile(0.5),
+            p90: self.percentile(0.9),
+            p99: self.percentile(0.99),
+            max: self.max,
+        }
+    }
+}
+
+/// Registry of request metrics, keyed by method name.
+#[derive(Debug)]
+pub(crate) struct Metrics(DashMap<String, Histogram>);
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics(DashMap::new())
+    }
+
+    /// Records a request to `method` which took `latency` to complete.
+    pub fn record(&self, method: &str, latency: Duration, is_error: bool) {
+        match self.0.get_mut(method) {
+            Some(mut histogram) => histogram.record(latency, is_error),
+            None => {
+                let mut histogram = self
+                    .0
+                    .entry(method.to_owned())
+                    .or_insert_with(Histogram::new);
+                histogram.record(latency, is_error);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let methods = self
+            .0
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().snapshot()))
+            .collect();
+
+        MetricsSnapshot(methods)
+    }
+}
+
+/// Request metrics of a single method, as of the time the snapshot was taken.
+///
+/// Latency percentiles are approximated by a histogram with exponentially sized buckets, so they
+/// describe an upper bound that is at most twice the true value.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct MethodMetrics {
+    /// Number of requests which received a response.
+    pub requests: u64,
+    /// Number of requests which received an error response.
+    pub errors: u64,
+    /// Median latency.
+    #[serde(rename = "p50Ms", serialize_with = "as_millis")]
+    pub p50: Duration,
+    /// 90th percentile latency.
+    #[serde(rename = "p90Ms", serialize_with = "as_millis")]
+    pub p90: Duration,
+    /// 99th percentile latency.
+    #[serde(rename = "p99Ms", serialize_with = "as_millis")]
+    pub p99: Duration,
+    /// Highest latency observed.
+    #[serde(rename = "maxMs", serialize_with = "as_millis")]
+    pub max: Duration,
+}
+
+fn as_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// A point-in-time copy of the request metrics recorded by an [`LspService`](crate::LspService).
+///
+/// This struct is created by [`LspService::metrics_snapshot`](crate::LspService::metrics_snapshot).
+/// See its documentation for more.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct MetricsSnapshot(BTreeMap<String, MethodMetrics>);
+
+impl MetricsSnapshot {
+    /// Returns the metrics recorded for `method`, if it has received any requests.
+    pub fn get(&self, method: &str) -> Option<&MethodMetrics> {
+        self.0.get(method)
+    }
+
+    /// Returns an iterator over the metrics of every method, sorted by method name.
+    pub fn iter(&self) -> btree_map::Iter<'_, String, MethodMetrics> {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MetricsSnapshot {
+    type Item = (&'a String, &'a MethodMetrics);
+    type IntoIter = btree_map::Iter<'a, String, MethodMetrics>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_percentiles() {
+        let metrics = Metrics::new();
+        for ms in 1..=100 {
+            metrics.record("textDocument/hover", Duration::from_millis(ms), ms > 98);
+        }
+
+        let snapshot = metrics.snapshot();
+        let hover = snapshot.get("textDocument/hover").unwrap();
+        assert_eq!(hover.requests, 100);
+        assert_eq!(hover.errors, 2);
+        assert!(hover.p50 >= Duration::from_millis(50) && hover.p50 <= Duration::from_millis(100));
+        assert!(hover.p99 >= Duration::from_millis(99));
+        assert_eq!(hover.max, Duration::from_millis(100));
+        assert!(snapshot.get("textDocument/completion").is_none());
+    }
+}
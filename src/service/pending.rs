@@ -1,42 +1,105 @@
 //! Types for tracking cancelable client-to-server JSON-RPC requests.
 
+use std::borrow::Cow;
 use std::fmt::{self, Debug, Formatter};
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use dashmap::{mapref::entry::Entry, DashMap};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use futures::future::{self, Either};
 use tracing::{debug, info};
 
 use super::ExitedError;
-use crate::jsonrpc::{Error, Id, Response};
+use crate::jsonrpc::{self, Error, Id, Response};
+
+/// A request canceled by the client through a [`$/cancelRequest`] notification.
+///
+/// These are yielded by [`LspService::cancellations`](crate::LspService::cancellations).
+///
+/// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cancellation {
+    id: Id,
+    method: Cow<'static, str>,
+}
+
+impl Cancellation {
+    /// Returns the ID of the canceled request.
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Returns the name of the method called by the canceled request.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+}
 
 /// A hashmap containing pending server requests, keyed by request ID.
-pub struct Pending(Arc<DashMap<Id, future::AbortHandle>>);
+#[derive(Clone)]
+pub struct Pending {
+    requests: Arc<DashMap<Id, Handles>>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<Cancellation>>>>,
+}
+
+/// Handles of a pending request.
+struct Handles {
+    /// Name of the method called by the request.
+    method: Cow<'static, str>,
+    /// Abort handle of the request handler, followed by the handles of the futures bound to it
+    /// with [`cancel_on`](crate::jsonrpc::pending::cancel_on).
+    abort: Vec<future::AbortHandle>,
+    /// Error the request is answered with if canceled, other than `RequestCancelled`.
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+impl Handles {
+    fn abort(&self) {
+        self.abort.iter().for_each(|handle| handle.abort());
+    }
+}
 
 impl Pending {
     /// Creates a new pending server requests map.
     pub fn new() -> Self {
-        Pending(Arc::new(DashMap::new()))
+        Pending {
+            requests: Arc::new(DashMap::new()),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns a receiver of every request canceled through [`Pending::cancel`] from now on.
+    pub fn subscribe(&self) -> UnboundedReceiver<Cancellation> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
     }
 
-    /// Executes the given async request handler, keyed by the given request ID.
+    /// Executes the given async request handler for `method`, keyed by the given request ID.
     ///
     /// If a cancel request is issued before the future is finished resolving, this will resolve to
     /// a "canceled" error response, and the pending request handler future will be dropped.
     pub fn execute<F>(
         &self,
         id: Id,
+        method: Cow<'static, str>,
         fut: F,
     ) -> impl Future<Output = Result<Option<Response>, ExitedError>> + Send + 'static
     where
         F: Future<Output = Result<Option<Response>, ExitedError>> + Send + 'static,
     {
-        if let Entry::Vacant(entry) = self.0.entry(id.clone()) {
+        if let Entry::Vacant(entry) = self.requests.entry(id.clone()) {
+            let fut = jsonrpc::pending::scope(self.clone(), fut);
             let (handler_fut, abort_handle) = future::abortable(fut);
-            entry.insert(abort_handle);
-
-            let requests = self.0.clone();
+            let error = Arc::new(Mutex::new(None));
+            entry.insert(Handles {
+                method,
+                abort: vec![abort_handle],
+                error: error.clone(),
+            });
+
+            let requests = self.requests.clone();
             Either::Left(async move {
                 let abort_result = handler_fut.await;
                 requests.remove(&id); // Remove abort handle now to avoid double cancellation.
@@ -44,7 +107,9 @@ impl Pending {
                 if let Ok(handler_result) = abort_result {
                     handler_result
                 } else {
-                    Ok(Some(Response::from_error(id, Error::request_cancelled())))
+                    let error = error.lock().unwrap().take();
+                    let error = error.unwrap_or_else(Error::request_cancelled);
+                    Ok(Some(Response::from_error(id, error)))
                 }
             })
         } else {
@@ -57,9 +122,16 @@ impl Pending {
     /// This will force the future to resolve to a "canceled" error response. If the future has
     /// already completed, this method call will do nothing.
     pub fn cancel(&self, id: &Id) {
-        if let Some((_, handle)) = self.0.remove(id) {
-            handle.abort();
+        if let Some((id, handles)) = self.requests.remove(id) {
+            handles.abort();
             info!("successfully cancelled request with ID: {}", id);
+
+            let cancellation = Cancellation {
+                id,
+                method: handles.method,
+            };
+            let mut subscribers = self.subscribers.lock().unwrap();
+            subscribers.retain(|tx| tx.unbounded_send(cancellation.clone()).is_ok());
         } else {
             debug!(
                 "client asked to cancel request {}, but no such pending request exists, ignoring",
@@ -68,19 +140,42 @@ impl Pending {
         }
     }
 
+    /// Cancels the running request handler corresponding to this ID, answering the request with
+    /// `error` instead of a "canceled" error response.
+    ///
+    /// If the future has already completed, this method call will do nothing.
+    pub fn cancel_with(&self, id: &Id, error: Error) {
+        if let Some((_, handles)) = self.requests.remove(id) {
+            info!("cancelling request with ID {}: {}", id, error.message);
+            *handles.error.lock().unwrap() = Some(error);
+            handles.abort();
+        }
+    }
+
     /// Cancels all pending request handlers, if any.
     pub fn cancel_all(&self) {
-        self.0.retain(|_, handle| {
-            handle.abort();
+        self.requests.retain(|_, handles| {
+            handles.abort();
             false
         });
     }
+
+    /// Aborts `handle` along with the handler of request `id`, if it is still pending.
+    pub fn watch(&self, id: &Id, handle: future::AbortHandle) {
+        match self.requests.get_mut(id) {
+            Some(mut handles) => handles.abort.push(handle),
+            None => debug!(
+                "request {} is not pending, so the future cannot be canceled",
+                id
+            ),
+        }
+    }
 }
 
 impl Debug for Pending {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_set()
-            .entries(self.0.iter().map(|entry| entry.key().clone()))
+            .entries(self.requests.iter().map(|entry| entry.key().clone()))
             .finish()
     }
 }
@@ -98,7 +193,7 @@ mod tests {
         let id = Id::Number(1);
         let id2 = id.clone();
         let response = pending
-            .execute(id.clone(), async {
+            .execute(id.clone(), "test".into(), async {
                 Ok(Some(Response::from_ok(id2, json!({}))))
             })
             .await;
@@ -106,12 +201,44 @@ mod tests {
         assert_eq!(response, Ok(Some(Response::from_ok(id, json!({})))));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_bound_futures() {
+        use futures::channel::oneshot;
+
+        use crate::jsonrpc::pending::cancel_on;
+
+        let pending = Pending::new();
+
+        let id = Id::Number(1);
+        let (tx, rx) = oneshot::channel();
+        let handler_fut = pending.execute(id.clone(), "test".into(), async {
+            let work = cancel_on(Id::Number(1), future::pending::<()>());
+            tx.send(tokio::spawn(work)).unwrap();
+            future::pending().await
+        });
+        let handler_fut = tokio::spawn(handler_fut);
+
+        let work = rx.await.unwrap();
+        pending.cancel(&id);
+
+        assert_eq!(work.await.unwrap(), Err(Error::request_cancelled()));
+        let res = handler_fut.await.expect("task panicked");
+        assert_eq!(
+            res,
+            Ok(Some(Response::from_error(id, Error::request_cancelled())))
+        );
+
+        let unbound = cancel_on(Id::Number(1), future::ready(1));
+        assert_eq!(unbound.await, Ok(1));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn cancels_server_request() {
         let pending = Pending::new();
 
         let id = Id::Number(1);
-        let handler_fut = tokio::spawn(pending.execute(id.clone(), future::pending()));
+        let handler_fut =
+            tokio::spawn(pending.execute(id.clone(), "test".into(), future::pending()));
 
         pending.cancel(&id);
 
@@ -0,0 +1,140 @@
+//! Per-request context available to [`LanguageServer`](crate::LanguageServer) handlers.
+
+use std::cell::RefCell;
+use std::future::Future;
+
+use futures::future;
+use lsp_types::ProgressToken;
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+/// Context of the request currently being handled.
+///
+/// The progress tokens of a request are embedded in its params in various ways, depending on the
+/// request type. Before a [`LanguageServer`](crate::LanguageServer) method is called, the
+/// `workDoneToken` and `partialResultToken` of its params are copied here, so handlers can find
+/// them in one place. The params passed to the handler are left untouched.
+///
+/// The context is only available while the handler future is being polled, and not from tasks
+/// spawned by it.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::{Client, RequestContext};
+/// #
+/// # struct Mock {
+/// #     client: Client,
+/// # }
+/// #
+/// # impl Mock {
+/// async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+///     let context = RequestContext::current().unwrap_or_default();
+///     if let Some(token) = context.work_done_token() {
+///         let progress = self
+///             .client
+///             .progress(token.clone(), "Finding references")
+///             .begin()
+///             .await;
+///         progress.finish().await;
+///     }
+///
+///     Ok(None)
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RequestContext {
+    work_done_token: Option<ProgressToken>,
+    partial_result_token: Option<ProgressToken>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        work_done_token: Option<ProgressToken>,
+        partial_result_token: Option<ProgressToken>,
+    ) -> Self {
+        RequestContext {
+            work_done_token,
+            partial_result_token,
+        }
+    }
+
+    /// Returns the context of the request currently being handled, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Returns the token the client provided for reporting work done progress, if any.
+    pub fn work_done_token(&self) -> Option<&ProgressToken> {
+        self.work_done_token.as_ref()
+    }
+
+    /// Returns the token the client provided for streaming partial results, if any.
+    pub fn partial_result_token(&self) -> Option<&ProgressToken> {
+        self.partial_result_token.as_ref()
+    }
+
+    /// Drives `fut` to completion with `self` as the current context.
+    pub(crate) async fn scope<F: Future>(self, fut: F) -> F::Output {
+        futures::pin_mut!(fut);
+        let mut context = Some(self);
+
+        future::poll_fn(|cx| {
+            let _guard = Enter::new(&mut context);
+            fut.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+/// Makes a context current until dropped, restoring the previous one afterwards.
+struct Enter<'a> {
+    slot: &'a mut Option<RequestContext>,
+    previous: Option<RequestContext>,
+}
+
+impl<'a> Enter<'a> {
+    fn new(slot: &'a mut Option<RequestContext>) -> Self {
+        let previous = CURRENT.with(|current| current.replace(slot.take()));
+        Enter { slot, previous }
+    }
+}
+
+impl Drop for Enter<'_> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        *self.slot = CURRENT.with(|current| current.replace(previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn available_while_polled() {
+        assert_eq!(RequestContext::current(), None);
+
+        let outer = RequestContext::new(Some(ProgressToken::Number(1)), None);
+        let inner = RequestContext::new(None, Some(ProgressToken::String("2".into())));
+
+        let (outer_seen, inner_seen) = outer
+            .clone()
+            .scope(async {
+                tokio::task::yield_now().await;
+                let inner_seen = inner.clone().scope(async { RequestContext::current() });
+                let inner_seen = inner_seen.await;
+                (RequestContext::current(), inner_seen)
+            })
+            .await;
+
+        assert_eq!(outer_seen, Some(outer));
+        assert_eq!(inner_seen, Some(inner));
+        assert_eq!(RequestContext::current(), None);
+    }
+}
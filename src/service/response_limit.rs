@@ -0,0 +1,202 @@
+//! Enforcement of a maximum serialized response size.
+
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::jsonrpc::{Error, Id, Response};
+
+/// Action taken when a response exceeds the size configured with
+/// [`LspServiceBuilder::max_response_size`](crate::LspServiceBuilder::max_response_size).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ResponseSizePolicy {
+    /// Drops trailing elements of array results until the response fits.
+    ///
+    /// This applies to results which are JSON arrays (e.g. `textDocument/references`), as well as
+    /// to completion lists, which are also marked with `isIncomplete: true` so the client asks for
+    /// the remaining items as the user keeps typing. Any other oversized result is replaced with a
+    /// `RequestFailed` error, as with [`ResponseSizePolicy::Error`].
+    Truncate,
+    /// Replaces the oversized response with a JSON-RPC error with code `-32803` (Request failed).
+    Error,
+}
+
+/// Maximum serialized size of a response, along with the policy applied to larger responses.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResponseLimit {
+    max: usize,
+    policy: ResponseSizePolicy,
+}
+
+impl ResponseLimit {
+    pub fn new(max: usize, policy: ResponseSizePolicy) -> Self {
+        ResponseLimit { max, policy }
+    }
+
+    /// Returns `res` unchanged if it fits within the limit, or truncated or replaced otherwise.
+    pub fn apply(&self, method: &str, res: Response) -> Response {
+        let size = serialized_len(&res);
+        if size <= self.max {
+            return res;
+        }
+
+        let (id, result) = res.into_parts();
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => return Response::from_error(id, err),
+        };
+
+        if self.policy == ResponseSizePolicy::Truncate {
+            let result = match result {
+                Value::Array(items) if method == "textDocument/completion" => {
+                    truncate_list(&id, Map::new(), items, self.max)
+                }
+                Value::Array(items) => truncate_array(&id, items, self.max).map(Value::Array),
+                Value::Object(mut list) if method == "textDocument/completion" => {
+                    match list.remove("items") {
+                        Some(Value::Array(items)) => truncate_list(&id, list, items, self.max),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(result) = result {
+                warn!(
+                    "truncated `{}` response of {} bytes to the maximum of {} bytes",
+                    method, size, self.max
+                );
+                return Response::from_ok(id, result);
+            }
+        }
+
+        warn!(
+            "`{}` response of {} bytes exceeds the maximum of {} bytes",
+            method, size, self.max
+        );
+
+        let message = format!("response exceeds the maximum size of {} bytes", self.max);
+        Response::from_error(id, Error::request_failed(message))
+    }
+}
+
+fn serialized_len<T: serde::Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(usize::MAX, |bytes| bytes.len())
+}
+
+/// Keeps the longest prefix of `items` whose response to `id` fits within `max` bytes.
+fn truncate_array(id: &Id, mut items: Vec<Value>, max: usize) -> Option<Vec<Value>> {
+    let mut size = serialized_len(&Response::from_ok(id.clone(), json!([])));
+    if size > max {
+        return None;
+    }
+
+    let mut len = 0;
+
+    for item in &items {
+        let item_size = serialized_len(item) + usize::from(len > 0);
+        if size + item_size > max {
+            break;
+        }
+
+        size += item_size;
+        len += 1;
+    }
+
+    items.truncate(len);
+    Some(items)
+}
+
+/// Truncates the `items` of a completion list, marking the list as incomplete.
+fn truncate_list(
+    id: &Id,
+    mut list: Map<String, Value>,
+    items: Vec<Value>,
+    max: usize,
+) -> Option<Value> {
+    list.insert("isIncomplete".into(), Value::Bool(true));
+    list.insert("items".into(), json!([]));
+
+    // The list takes the place of the bare `[]` measured by `truncate_array`.
+    let overhead = serialized_len(&list) - "[]".len();
+    let items = truncate_array(id, items, max.checked_sub(overhead)?)?;
+    list.insert("items".into(), Value::Array(items));
+    Some(Value::Object(list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(n: usize) -> Value {
+        Value::Array(
+            (0..n)
+                .map(|i| json!({ "label": format!("item{i}") }))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn leaves_small_responses_alone() {
+        let limit = ResponseLimit::new(1024, ResponseSizePolicy::Error);
+        let res = Response::from_ok(1.into(), items(3));
+        assert_eq!(limit.apply("textDocument/references", res.clone()), res);
+    }
+
+    #[test]
+    fn truncates_arrays() {
+        let limit = ResponseLimit::new(200, ResponseSizePolicy::Truncate);
+        let res = limit.apply(
+            "textDocument/references",
+            Response::from_ok(1.into(), items(100)),
+        );
+        assert!(serialized_len(&res) <= 200);
+
+        let (_, result) = res.into_parts();
+        let result = result.unwrap();
+        assert!(!result.as_array().unwrap().is_empty());
+        assert_eq!(result[0], json!({ "label": "item0" }));
+    }
+
+    #[test]
+    fn marks_truncated_completions_incomplete() {
+        let limit = ResponseLimit::new(300, ResponseSizePolicy::Truncate);
+
+        let list = json!({ "isIncomplete": false, "items": items(100) });
+        for result in [items(100), list] {
+            let res = limit.apply(
+                "textDocument/completion",
+                Response::from_ok(1.into(), result),
+            );
+            assert!(serialized_len(&res) <= 300);
+
+            let (_, result) = res.into_parts();
+            let result = result.unwrap();
+            assert_eq!(result["isIncomplete"], json!(true));
+            assert!(!result["items"].as_array().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn fails_oversized_responses() {
+        let limit = ResponseLimit::new(100, ResponseSizePolicy::Truncate);
+        let large = json!({ "contents": "x".repeat(200) });
+        let res = limit.apply("textDocument/hover", Response::from_ok(1.into(), large));
+        let (_, result) = res.into_parts();
+        assert_eq!(
+            result.unwrap_err().code,
+            crate::jsonrpc::ErrorCode::RequestFailed
+        );
+
+        let limit = ResponseLimit::new(100, ResponseSizePolicy::Error);
+        let res = limit.apply(
+            "textDocument/references",
+            Response::from_ok(1.into(), items(100)),
+        );
+        let (_, result) = res.into_parts();
+        assert_eq!(
+            result.unwrap_err().code,
+            crate::jsonrpc::ErrorCode::RequestFailed
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Cancellation of requests made stale by document changes, enabled with
+//! [`LspServiceBuilder::cancel_stale_requests`](crate::LspServiceBuilder::cancel_stale_requests).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use lsp_types::Url;
+use serde_json::Value;
+
+use super::Pending;
+use crate::jsonrpc::{Error, Id, Request};
+
+type Documents = Arc<Mutex<HashMap<Url, Vec<Id>>>>;
+
+/// Pending requests targeting a document, keyed by document URI.
+#[derive(Debug)]
+pub(crate) struct StaleRequests {
+    pending: Arc<Pending>,
+    documents: Documents,
+}
+
+impl StaleRequests {
+    pub fn new(pending: Arc<Pending>) -> Self {
+        StaleRequests {
+            pending,
+            documents: Arc::default(),
+        }
+    }
+
+    /// Starts tracking `req` if it is a `textDocument/*` request, until the returned guard is
+    /// dropped.
+    pub fn track(&self, req: &Request) -> Option<Tracked> {
+        let id = req.id()?;
+        if !req.method().starts_with("textDocument/") {
+            return None;
+        }
+
+        let uri = document_uri(req)?;
+        let mut documents = self.documents.lock().unwrap();
+        documents.entry(uri.clone()).or_default().push(id.clone());
+
+        Some(Tracked {
+            documents: self.documents.clone(),
+            uri,
+            id: id.clone(),
+        })
+    }
+
+    /// Cancels every tracked request targeting the document changed by `req`, if it is a
+    /// `textDocument/didChange` notification.
+    pub fn invalidate(&self, req: &Request) {
+        if req.method() != "textDocument/didChange" {
+            return;
+        }
+
+        let ids = match document_uri(req) {
+            Some(uri) => self.documents.lock().unwrap().remove(&uri),
+            None => return,
+        };
+
+        for id in ids.into_iter().flatten() {
+            self.pending.cancel_with(&id, Error::content_modified());
+        }
+    }
+}
+
+/// Guard returned by [`StaleRequests::track`].
+#[derive(Debug)]
+pub(crate) struct Tracked {
+    documents: Documents,
+    uri: Url,
+    id: Id,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut documents = self.documents.lock().unwrap();
+        if let Some(ids) = documents.get_mut(&self.uri) {
+            ids.retain(|id| *id != self.id);
+            if ids.is_empty() {
+                documents.remove(&self.uri);
+            }
+        }
+    }
+}
+
+/// Returns the URI of the `textDocument` targeted by `req`, if any.
+pub(super) fn document_uri(req: &Request) -> Option<Url> {
+    let document = req.params()?.get("textDocument")?;
+    document.get("uri").and_then(Value::as_str)?.parse().ok()
+}
@@ -1,11 +1,34 @@
 //! Types representing the current state of the language server.
 
-use std::fmt::{self, Debug, Formatter};
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use futures::task::AtomicWaker;
+
+use lsp_types::{ClientCapabilities, TraceValue, Url};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::trace;
+
+use super::extensions::Extensions;
+#[cfg(feature = "file-watcher")]
+use super::file_watcher::FileWatcher;
+use super::trace_context::TraceContext;
+use super::trust::Trust;
+use crate::jsonrpc::{self, Error, Request};
 
 /// A list of possible states the language server can be in.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// The current state is available through [`LspService::state`](crate::LspService::state) and
+/// [`Client::state`](crate::Client::state), and is reported by [`StateError`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u8)]
+#[non_exhaustive]
 pub enum State {
     /// Server has not received an `initialize` request.
     Uninitialized = 0,
@@ -19,20 +42,175 @@ pub enum State {
     Exited = 4,
 }
 
-/// Atomic value which represents the current state of the server.
-pub struct ServerState(AtomicU8);
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Uninitialized => "uninitialized",
+            State::Initializing => "initializing",
+            State::Initialized => "initialized",
+            State::ShutDown => "shut down",
+            State::Exited => "exited",
+        })
+    }
+}
+
+/// Error that occurs when a message is sent or received while the server is in a [`State`] which
+/// does not allow it.
+///
+/// For instance, the server may not send requests to the client before it is initialized, and
+/// the client may not send requests to the server after the `shutdown` request. This error
+/// describes which message was refused and in which state the server was at the time, telling
+/// apart servers which were shut down from those which already exited.
+///
+/// It converts into the JSON-RPC error mandated by the specification: code `-32002` (server not
+/// initialized) before initialization, and `-32600` (invalid request) otherwise.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateError {
+    method: Cow<'static, str>,
+    state: State,
+}
+
+impl StateError {
+    pub(crate) fn new<M: Into<Cow<'static, str>>>(method: M, state: State) -> Self {
+        StateError {
+            method: method.into(),
+            state,
+        }
+    }
+
+    /// Returns the method of the refused message.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the state of the server when the message was refused.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns `true` if the message was refused because the server is not initialized yet.
+    pub fn is_not_initialized(&self) -> bool {
+        matches!(self.state, State::Uninitialized | State::Initializing)
+    }
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "`{}` is not allowed while the server is ", self.method)?;
+        match self.state {
+            State::Initialized => f.write_str("running"),
+            state => write!(f, "{}", state),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<StateError> for Error {
+    fn from(error: StateError) -> Self {
+        if error.is_not_initialized() {
+            jsonrpc::not_initialized_error()
+        } else {
+            Error::invalid_request()
+        }
+    }
+}
+
+/// Action taken by [`LspService`](crate::LspService) when the client sends the `exit`
+/// notification.
+///
+/// This is configured with
+/// [`LspServiceBuilder::exit_behavior`](crate::LspServiceBuilder::exit_behavior).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ExitBehavior {
+    /// Stops the service for good, which then fails every call with [`ExitedError`].
+    ///
+    /// This is the default, and makes [`Server`](crate::Server) return once the session ends.
+    ///
+    /// [`ExitedError`]: crate::ExitedError
+    TerminateService,
+    /// Only ends the current session, leaving the service ready for a new `initialize` request.
+    ///
+    /// Pending requests are canceled, and the state negotiated during the session, such as the
+    /// client capabilities and the registered capabilities, is discarded. The channel used by
+    /// [`Client`](crate::Client) stays open.
+    CloseSessionOnly,
+}
+
+/// Shared value which represents the current state of the server and its negotiated settings.
+pub struct ServerState {
+    state: AtomicU8,
+    /// Task waiting for an ongoing `initialize` request to complete, if any.
+    initializing: AtomicWaker,
+    close_session_on_exit: AtomicBool,
+    trace: AtomicU8,
+    client_capabilities: RwLock<Option<Arc<ClientCapabilities>>>,
+    /// Capabilities advertised by the client, including those unknown to `lsp-types`.
+    #[cfg(feature = "proposed")]
+    raw_client_capabilities: RwLock<Option<Arc<Value>>>,
+    trace_context: RwLock<Option<Arc<dyn TraceContext>>>,
+    /// Whether `trace_context` was set, so that every message can skip its lock otherwise.
+    has_trace_context: AtomicBool,
+    /// Latest version of every open document, if document version tracking is enabled.
+    document_versions: RwLock<Option<HashMap<Url, i32>>>,
+    trust: Trust,
+    #[cfg(feature = "file-watcher")]
+    file_watcher: FileWatcher,
+    extensions: Extensions,
+}
 
 impl ServerState {
-    pub const fn new() -> Self {
-        ServerState(AtomicU8::new(State::Uninitialized as u8))
+    pub fn new() -> Self {
+        ServerState {
+            state: AtomicU8::new(State::Uninitialized as u8),
+            initializing: AtomicWaker::new(),
+            close_session_on_exit: AtomicBool::new(false),
+            trace: AtomicU8::new(0),
+            client_capabilities: RwLock::new(None),
+            #[cfg(feature = "proposed")]
+            raw_client_capabilities: RwLock::new(None),
+            trace_context: RwLock::new(None),
+            has_trace_context: AtomicBool::new(false),
+            document_versions: RwLock::new(None),
+            trust: Trust::default(),
+            #[cfg(feature = "file-watcher")]
+            file_watcher: FileWatcher::new(),
+            extensions: Extensions::default(),
+        }
     }
 
     pub fn set(&self, state: State) {
-        self.0.store(state as u8, Ordering::SeqCst);
+        self.state.store(state as u8, Ordering::SeqCst);
+        self.initializing.wake();
+    }
+
+    /// Switches to the `new` state only if the server is still in the `current` state.
+    ///
+    /// Returns the actual state of the server if it differs from `current`.
+    pub fn compare_exchange(&self, current: State, new: State) -> Result<(), State> {
+        self.state
+            .compare_exchange(current as u8, new as u8, Ordering::SeqCst, Ordering::SeqCst)
+            .map(drop)
+            .map_err(|_| self.get())
+    }
+
+    /// Returns `Poll::Pending` while an `initialize` request is being handled, waking the task
+    /// once the server leaves the `Initializing` state.
+    pub fn poll_initialized(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.get() != State::Initializing {
+            return Poll::Ready(());
+        }
+
+        self.initializing.register(cx.waker());
+        match self.get() {
+            State::Initializing => Poll::Pending,
+            _ => Poll::Ready(()),
+        }
     }
 
     pub fn get(&self) -> State {
-        match self.0.load(Ordering::SeqCst) {
+        match self.state.load(Ordering::SeqCst) {
             0 => State::Uninitialized,
             1 => State::Initializing,
             2 => State::Initialized,
@@ -41,10 +219,275 @@ impl ServerState {
             _ => unreachable!(),
         }
     }
+
+    /// Returns `Err` if the server is not in one of the `allowed` states to handle `method`.
+    pub fn check<M>(&self, method: M, allowed: &[State]) -> Result<(), StateError>
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        let state = self.get();
+        if allowed.contains(&state) {
+            Ok(())
+        } else {
+            Err(StateError::new(method, state))
+        }
+    }
+
+    /// Returns the trust of the workspace, if tracked.
+    pub fn trust(&self) -> &Trust {
+        &self.trust
+    }
+
+    /// Returns the server-side file watcher.
+    #[cfg(feature = "file-watcher")]
+    pub fn file_watcher(&self) -> &FileWatcher {
+        &self.file_watcher
+    }
+
+    /// Returns the typed storage shared by everything serving this connection.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Sets the action taken when the `exit` notification is received.
+    pub fn set_exit_behavior(&self, behavior: ExitBehavior) {
+        let close_session = behavior == ExitBehavior::CloseSessionOnly;
+        self.close_session_on_exit
+            .store(close_session, Ordering::SeqCst);
+    }
+
+    /// Returns the action taken when the `exit` notification is received.
+    pub fn exit_behavior(&self) -> ExitBehavior {
+        if self.close_session_on_exit.load(Ordering::SeqCst) {
+            ExitBehavior::CloseSessionOnly
+        } else {
+            ExitBehavior::TerminateService
+        }
+    }
+
+    /// Discards the settings negotiated during the current session and returns to the
+    /// `Uninitialized` state, ready for a new session.
+    pub fn reset_session(&self) {
+        self.trace.store(0, Ordering::SeqCst);
+        *self.client_capabilities.write().unwrap() = None;
+        #[cfg(feature = "proposed")]
+        self.set_raw_client_capabilities(None);
+        if let Some(versions) = self.document_versions.write().unwrap().as_mut() {
+            versions.clear();
+        }
+        self.trust.reset();
+        #[cfg(feature = "file-watcher")]
+        self.file_watcher.reset();
+
+        self.set(State::Uninitialized);
+    }
+
+    /// Sets the trace level requested by the client via `InitializeParams` or `$/setTrace`.
+    pub fn set_trace(&self, value: TraceValue) {
+        let value = match value {
+            TraceValue::Off => 0,
+            TraceValue::Messages => 1,
+            TraceValue::Verbose => 2,
+        };
+
+        self.trace.store(value, Ordering::SeqCst);
+    }
+
+    /// Returns the trace level currently requested by the client.
+    pub fn trace(&self) -> TraceValue {
+        match self.trace.load(Ordering::SeqCst) {
+            0 => TraceValue::Off,
+            1 => TraceValue::Messages,
+            2 => TraceValue::Verbose,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Stores the capabilities advertised by the client in its `initialize` request.
+    pub fn set_client_capabilities(&self, capabilities: ClientCapabilities) {
+        let mut guard = self.client_capabilities.write().unwrap();
+        *guard = Some(Arc::new(capabilities));
+    }
+
+    /// Returns the capabilities advertised by the client, if the server has been initialized.
+    pub fn client_capabilities(&self) -> Option<Arc<ClientCapabilities>> {
+        self.client_capabilities.read().unwrap().clone()
+    }
+
+    /// Stores the capabilities advertised by the client as raw JSON, so that capabilities of
+    /// upcoming protocol versions can be checked before `lsp-types` supports them.
+    #[cfg(feature = "proposed")]
+    pub fn set_raw_client_capabilities(&self, capabilities: Option<Value>) {
+        *self.raw_client_capabilities.write().unwrap() = capabilities.map(Arc::new);
+    }
+
+    /// Returns the capabilities advertised by the client as raw JSON, if the server has been
+    /// initialized.
+    #[cfg(feature = "proposed")]
+    pub fn raw_client_capabilities(&self) -> Option<Arc<Value>> {
+        self.raw_client_capabilities.read().unwrap().clone()
+    }
+
+    /// Sets the hook used for propagating distributed tracing context through `_meta`.
+    pub fn set_trace_context(&self, ctx: Arc<dyn TraceContext>) {
+        *self.trace_context.write().unwrap() = Some(ctx);
+        self.has_trace_context.store(true, Ordering::Release);
+    }
+
+    /// Returns the hook used for propagating distributed tracing context, if any.
+    pub fn trace_context(&self) -> Option<Arc<dyn TraceContext>> {
+        if !self.has_trace_context.load(Ordering::Acquire) {
+            return None;
+        }
+
+        self.trace_context.read().unwrap().clone()
+    }
+
+    /// Starts recording the latest version of every open document.
+    pub fn track_document_versions(&self) {
+        let mut guard = self.document_versions.write().unwrap();
+        guard.get_or_insert_with(HashMap::new);
+    }
+
+    /// Records the document version carried by the `didOpen`, `didChange` or `didClose`
+    /// notification `req`, if document version tracking is enabled.
+    pub fn observe_document(&self, req: &Request) {
+        let closed = match req.method() {
+            "textDocument/didOpen" | "textDocument/didChange" => false,
+            "textDocument/didClose" => true,
+            _ => return,
+        };
+
+        let mut guard = self.document_versions.write().unwrap();
+        let versions = match guard.as_mut() {
+            Some(versions) => versions,
+            None => return,
+        };
+
+        let document = req.params().and_then(|params| params.get("textDocument"));
+        let uri = document
+            .and_then(|doc| doc.get("uri"))
+            .and_then(Value::as_str)
+            .and_then(|uri| Url::parse(uri).ok());
+        let version = document
+            .and_then(|doc| doc.get("version"))
+            .and_then(Value::as_i64)
+            .and_then(|version| i32::try_from(version).ok());
+
+        match (uri, version) {
+            (Some(uri), _) if closed => {
+                versions.remove(&uri);
+            }
+            (Some(uri), Some(version)) => {
+                versions.insert(uri, version);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the latest version of the open document `uri`, if document version tracking is
+    /// enabled.
+    pub fn document_version(&self, uri: &Url) -> Option<i32> {
+        let guard = self.document_versions.read().unwrap();
+        guard.as_ref()?.get(uri).copied()
+    }
+
+    /// Emits a wire-level `trace!` log for `msg`, honoring the client-requested trace level.
+    ///
+    /// The trace level gates these logs on top of the `tracing` subscriber: nothing is logged
+    /// while it is `off`, which is the default until the client asks otherwise. At the `messages`
+    /// level, the `params` and `result` members are omitted from the log line.
+    pub fn trace_message<M: Serialize>(&self, direction: &str, msg: &M) {
+        if !tracing::enabled!(tracing::Level::TRACE) {
+            return;
+        }
+
+        if let Some(value) = self.traced_value(msg) {
+            trace!("{} {}", direction, value);
+        }
+    }
+
+    /// Returns the part of `msg` to log at the current trace level, if any.
+    fn traced_value<M: Serialize>(&self, msg: &M) -> Option<Value> {
+        let verbose = match self.trace() {
+            TraceValue::Off => return None,
+            TraceValue::Messages => false,
+            TraceValue::Verbose => true,
+        };
+
+        match serde_json::to_value(msg).ok()? {
+            Value::Object(mut obj) if !verbose => {
+                obj.remove("params");
+                obj.remove("result");
+                Some(Value::Object(obj))
+            }
+            value => Some(value),
+        }
+    }
 }
 
 impl Debug for ServerState {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        self.get().fmt(f)
+        Debug::fmt(&self.get(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn stores_trace_value() {
+        let state = ServerState::new();
+        assert_eq!(state.trace(), TraceValue::Off);
+
+        for value in [TraceValue::Messages, TraceValue::Verbose, TraceValue::Off] {
+            state.set_trace(value);
+            assert_eq!(state.trace(), value);
+        }
+    }
+
+    #[test]
+    fn gates_wire_traces_by_trace_value() {
+        let state = ServerState::new();
+        let msg = json!({"jsonrpc": "2.0", "method": "initialized", "params": {}});
+
+        assert_eq!(state.traced_value(&msg), None);
+
+        state.set_trace(TraceValue::Messages);
+        let expected = json!({"jsonrpc": "2.0", "method": "initialized"});
+        assert_eq!(state.traced_value(&msg), Some(expected));
+
+        state.set_trace(TraceValue::Verbose);
+        assert_eq!(state.traced_value(&msg), Some(msg.clone()));
+
+        state.set_trace(TraceValue::Off);
+        assert_eq!(state.traced_value(&msg), None);
+    }
+
+    #[test]
+    fn reports_state_errors() {
+        let state = ServerState::new();
+        let allowed = [State::Initialized, State::ShutDown];
+
+        let error = state.check("textDocument/hover", &allowed).unwrap_err();
+        assert_eq!(error.method(), "textDocument/hover");
+        assert_eq!(error.state(), State::Uninitialized);
+        assert!(error.is_not_initialized());
+        assert_eq!(Error::from(error), jsonrpc::not_initialized_error());
+
+        state.set(State::ShutDown);
+        assert_eq!(state.check("textDocument/hover", &allowed), Ok(()));
+
+        state.set(State::Exited);
+        let error = state.check("textDocument/hover", &allowed).unwrap_err();
+        assert_eq!(error.state(), State::Exited);
+        assert_eq!(
+            error.to_string(),
+            "`textDocument/hover` is not allowed while the server is exited"
+        );
+        assert_eq!(Error::from(error), Error::invalid_request());
     }
 }
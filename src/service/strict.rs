@@ -0,0 +1,205 @@
+//! Optional validation of the message ordering rules defined by the LSP specification.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::state::State;
+use crate::jsonrpc::Request;
+
+/// A violation of the message ordering rules defined by the Language Server Protocol.
+///
+/// These are reported by [`LspServiceBuilder::strict`](crate::LspServiceBuilder::strict).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum ProtocolViolation {
+    /// A request other than `initialize` was received before the server was initialized.
+    RequestBeforeInitialize {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A notification other than `exit` was received before the server was initialized.
+    NotificationBeforeInitialize {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A message was received before the client sent the `initialized` notification.
+    MessageBeforeInitialized {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A second `initialize` request was received.
+    DuplicateInitialize,
+    /// A second `initialized` notification was received.
+    DuplicateInitialized,
+    /// A request was received after the `shutdown` request.
+    RequestAfterShutdown {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A notification other than `exit` was received after the `shutdown` request.
+    NotificationAfterShutdown {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A request was received for a method whose handler is a notification handler.
+    RequestToNotificationHandler {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// A notification was received for a method whose handler is a request handler.
+    NotificationToRequestHandler {
+        /// Name of the offending method.
+        method: String,
+    },
+    /// The server answered a request with a result which does not match the specification.
+    ///
+    /// Only reported in [dev mode](crate::LspServiceBuilder::dev_mode).
+    InvalidResponse {
+        /// Name of the request.
+        method: String,
+        /// Why the result does not match the specification.
+        reason: String,
+    },
+    /// The server sent a message which the client did not advertise support for.
+    ///
+    /// Only reported in [dev mode](crate::LspServiceBuilder::dev_mode).
+    UnsupportedByClient {
+        /// Name of the offending method.
+        method: String,
+        /// Path of the missing client capability, e.g. `window.workDoneProgress`.
+        capability: String,
+    },
+}
+
+impl Display for ProtocolViolation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ProtocolViolation::RequestBeforeInitialize { method } => {
+                write!(f, "request `{method}` received before `initialize`")
+            }
+            ProtocolViolation::NotificationBeforeInitialize { method } => {
+                write!(f, "notification `{method}` received before `initialize`")
+            }
+            ProtocolViolation::MessageBeforeInitialized { method } => {
+                write!(f, "message `{method}` received before `initialized`")
+            }
+            ProtocolViolation::DuplicateInitialize => {
+                f.write_str("received duplicate `initialize` request")
+            }
+            ProtocolViolation::DuplicateInitialized => {
+                f.write_str("received duplicate `initialized` notification")
+            }
+            ProtocolViolation::RequestAfterShutdown { method } => {
+                write!(f, "request `{method}` received after `shutdown`")
+            }
+            ProtocolViolation::NotificationAfterShutdown { method } => {
+                write!(f, "notification `{method}` received after `shutdown`")
+            }
+            ProtocolViolation::RequestToNotificationHandler { method } => {
+                write!(
+                    f,
+                    "request `{method}` received, but it is handled as a notification"
+                )
+            }
+            ProtocolViolation::NotificationToRequestHandler { method } => {
+                write!(
+                    f,
+                    "notification `{method}` received, but it is handled as a request"
+                )
+            }
+            ProtocolViolation::InvalidResponse { method, reason } => {
+                write!(f, "invalid response to `{method}` sent: {reason}")
+            }
+            ProtocolViolation::UnsupportedByClient { method, capability } => {
+                write!(
+                    f,
+                    "`{method}` sent, but the client does not support `{capability}`"
+                )
+            }
+        }
+    }
+}
+
+/// Tracks message ordering and reports [`ProtocolViolation`]s to a user-provided callback.
+#[derive(Clone)]
+pub(crate) struct Strict {
+    on_violation: Arc<dyn Fn(ProtocolViolation) + Send + Sync>,
+    received_initialized: bool,
+}
+
+impl Strict {
+    pub fn new<F>(on_violation: F) -> Self
+    where
+        F: Fn(ProtocolViolation) + Send + Sync + 'static,
+    {
+        Strict {
+            on_violation: Arc::new(on_violation),
+            received_initialized: false,
+        }
+    }
+
+    /// Validates `req` against the current server `state`, reporting any violations found.
+    ///
+    /// Returns the violation, if any, so the caller may adjust its response accordingly.
+    pub fn check(&mut self, state: State, req: &Request) -> Option<ProtocolViolation> {
+        let method = req.method();
+        let is_request = req.id().is_some();
+
+        let violation = match (state, method) {
+            (_, "exit") | (_, "$/cancelRequest") => None,
+            (State::Uninitialized, "initialize") => None,
+            (State::Uninitialized | State::Initializing, _) if is_request => {
+                Some(ProtocolViolation::RequestBeforeInitialize {
+                    method: method.to_owned(),
+                })
+            }
+            (State::Uninitialized | State::Initializing, _) => {
+                Some(ProtocolViolation::NotificationBeforeInitialize {
+                    method: method.to_owned(),
+                })
+            }
+            (State::Initialized, "initialized") if self.received_initialized => {
+                Some(ProtocolViolation::DuplicateInitialized)
+            }
+            (State::Initialized, "initialized") => {
+                self.received_initialized = true;
+                None
+            }
+            (_, "initialize") => Some(ProtocolViolation::DuplicateInitialize),
+            (State::Initialized, _) if !self.received_initialized => {
+                Some(ProtocolViolation::MessageBeforeInitialized {
+                    method: method.to_owned(),
+                })
+            }
+            (State::ShutDown, _) if is_request => Some(ProtocolViolation::RequestAfterShutdown {
+                method: method.to_owned(),
+            }),
+            (State::ShutDown, _) => Some(ProtocolViolation::NotificationAfterShutdown {
+                method: method.to_owned(),
+            }),
+            _ => None,
+        };
+
+        if let Some(violation) = &violation {
+            self.report(violation.clone());
+        }
+
+        violation
+    }
+
+    /// Reports `violation` to the user-provided callback.
+    pub fn report(&self, violation: ProtocolViolation) {
+        (self.on_violation)(violation);
+    }
+}
+
+impl Debug for Strict {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Strict")
+            .field("received_initialized", &self.received_initialized)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,39 @@
+//! Streams of incoming notifications, returned by
+//! [`LspService::notifications`](crate::LspService::notifications).
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde_json::Value;
+
+use crate::jsonrpc::Request;
+
+/// Subscribers to incoming notifications, keyed by method name.
+#[derive(Debug, Default)]
+pub(crate) struct Subscriptions {
+    senders: Mutex<HashMap<&'static str, Vec<UnboundedSender<Option<Value>>>>>,
+}
+
+impl Subscriptions {
+    /// Returns a receiver of the params of every future `method` notification.
+    pub fn subscribe(&self, method: &'static str) -> UnboundedReceiver<Option<Value>> {
+        let (tx, rx) = mpsc::unbounded();
+        let mut senders = self.senders.lock().unwrap();
+        senders.entry(method).or_default().push(tx);
+        rx
+    }
+
+    /// Sends the params of the notification `req` to its subscribers, if any.
+    ///
+    /// Subscribers whose receiver was dropped are removed.
+    pub fn publish(&self, req: &Request) {
+        let mut senders = self.senders.lock().unwrap();
+        if let Some(subscribers) = senders.get_mut(req.method()) {
+            subscribers.retain(|tx| tx.unbounded_send(req.params().cloned()).is_ok());
+            if subscribers.is_empty() {
+                senders.remove(req.method());
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Propagation of distributed tracing context through the `_meta` field of JSON-RPC params.
+
+use serde_json::{Map, Value};
+use tracing::Span;
+
+use crate::jsonrpc::Request;
+
+/// Name of the reserved params member carrying out-of-band metadata, such as trace context.
+const META_KEY: &str = "_meta";
+
+/// Extracts and injects distributed tracing context from and into JSON-RPC messages.
+///
+/// Some LSP ecosystems reserve a `_meta` member on the `params` object of requests and
+/// notifications for out-of-band metadata, such as a W3C [`traceparent`] header. Implementing this
+/// trait (e.g. on top of [`tracing-opentelemetry`]) and registering it with
+/// [`LspServiceBuilder::trace_context`](crate::LspServiceBuilder::trace_context) allows traces to
+/// span the boundary between the editor and the language server in both directions.
+///
+/// [`traceparent`]: https://www.w3.org/TR/trace-context/#traceparent-header
+/// [`tracing-opentelemetry`]: https://docs.rs/tracing-opentelemetry
+pub trait TraceContext: Send + Sync + 'static {
+    /// Returns the span in which the incoming message `method` should be handled.
+    ///
+    /// This is only called for messages whose `params` contain a `_meta` object, which is passed
+    /// in as `meta`. The returned span is typically linked to, or a child of, the remote context
+    /// described by `meta`.
+    fn extract(&self, method: &str, meta: &Map<String, Value>) -> Span;
+
+    /// Inserts the current trace context into the `meta` of the outgoing message `method`.
+    ///
+    /// This is only called for messages whose `params` are a JSON object or absent, and whose
+    /// `_meta` member, if any, is an object as well. If `meta` is left empty, no `_meta` member is
+    /// added to the message.
+    fn inject(&self, method: &str, meta: &mut Map<String, Value>);
+}
+
+/// Returns the span `ctx` associates with the incoming `req`, if it carries a `_meta` object.
+pub(crate) fn extract(ctx: &dyn TraceContext, req: &Request) -> Option<Span> {
+    match req.params().and_then(|p| p.get(META_KEY)) {
+        Some(Value::Object(meta)) => Some(ctx.extract(req.method(), meta)),
+        _ => None,
+    }
+}
+
+/// Injects the trace context provided by `ctx` into the `_meta` object of the outgoing `req`.
+///
+/// Messages without `params` are given an object holding only `_meta`, while messages whose
+/// `params` or `_meta` are not objects are left untouched.
+pub(crate) fn inject(ctx: &dyn TraceContext, req: &mut Request) {
+    let mut meta = match req.params() {
+        None => Map::new(),
+        Some(Value::Object(params)) => match params.get(META_KEY) {
+            None => Map::new(),
+            Some(Value::Object(meta)) => meta.clone(),
+            Some(_) => return,
+        },
+        Some(_) => return,
+    };
+
+    ctx.inject(req.method(), &mut meta);
+
+    if !meta.is_empty() {
+        if let Value::Object(params) = req.params_or_insert_object() {
+            params.insert(META_KEY.to_owned(), Value::Object(meta));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    struct Traceparent;
+
+    impl TraceContext for Traceparent {
+        fn extract(&self, _: &str, _: &Map<String, Value>) -> Span {
+            Span::none()
+        }
+
+        fn inject(&self, _: &str, meta: &mut Map<String, Value>) {
+            meta.insert("traceparent".into(), json!("00-abc-def-01"));
+        }
+    }
+
+    fn injected(params: Option<Value>) -> Option<Value> {
+        let mut req = Request::build("$/custom");
+        if let Some(params) = params {
+            req = req.params(params);
+        }
+
+        let mut req = req.finish();
+        inject(&Traceparent, &mut req);
+        req.params().cloned()
+    }
+
+    #[test]
+    fn injects_into_meta() {
+        let meta = json!({"traceparent": "00-abc-def-01"});
+        assert_eq!(injected(None), Some(json!({ "_meta": meta })));
+        assert_eq!(
+            injected(Some(json!({"a": 1}))),
+            Some(json!({"a": 1, "_meta": meta}))
+        );
+        assert_eq!(
+            injected(Some(json!({"_meta": {"b": 2}}))),
+            Some(json!({"_meta": {"b": 2, "traceparent": "00-abc-def-01"}}))
+        );
+
+        // Params and metadata that are not objects belong to someone else.
+        assert_eq!(injected(Some(json!(null))), Some(json!(null)));
+        assert_eq!(injected(Some(json!([1]))), Some(json!([1])));
+        assert_eq!(
+            injected(Some(json!({"_meta": "opaque"}))),
+            Some(json!({"_meta": "opaque"}))
+        );
+    }
+}
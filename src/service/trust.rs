@@ -0,0 +1,220 @@
+//! Workspace trust, enabled with
+//! [`LspServiceBuilder::workspace_trust`](crate::LspServiceBuilder::workspace_trust).
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde_json::Value;
+use tracing::info;
+
+use crate::jsonrpc::Request;
+
+/// Where the server learns whether the user trusts the workspace, and which methods to disable
+/// while it is untrusted.
+///
+/// The Language Server Protocol has no notion of workspace trust, so editors which support it
+/// forward it to servers as a boolean setting. This setting is looked up with a [JSON pointer]
+/// in:
+///
+/// 1. the `initializationOptions` of the `initialize` request,
+/// 2. the `experimental` client capabilities of the `initialize` request,
+/// 3. the `settings` of every `workspace/didChangeConfiguration` notification.
+///
+/// Messages without the setting leave the trust unchanged. Servers learning about trust in
+/// another way can update it with [`Client::set_workspace_trusted`].
+///
+/// [JSON pointer]: https://datatracker.ietf.org/doc/html/rfc6901
+/// [`Client::set_workspace_trusted`]: crate::Client::set_workspace_trusted
+///
+/// # Example
+///
+/// ```rust
+/// use tower_lsp::WorkspaceTrust;
+///
+/// // Reads `{"security": {"workspaceTrusted": true}}` and disables commands until trusted.
+/// let trust = WorkspaceTrust::new()
+///     .setting("/security/workspaceTrusted")
+///     .gate("workspace/executeCommand");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WorkspaceTrust {
+    pointer: Cow<'static, str>,
+    gated: Vec<Cow<'static, str>>,
+    default: bool,
+}
+
+impl WorkspaceTrust {
+    /// Creates a new configuration reading the `/trusted` setting, where the workspace is
+    /// untrusted until the client says otherwise and no method is gated.
+    pub fn new() -> Self {
+        WorkspaceTrust {
+            pointer: Cow::Borrowed("/trusted"),
+            gated: Vec::new(),
+            default: false,
+        }
+    }
+
+    /// Sets the JSON pointer of the boolean setting holding whether the workspace is trusted.
+    pub fn setting<P: Into<Cow<'static, str>>>(mut self, pointer: P) -> Self {
+        self.pointer = pointer.into();
+        self
+    }
+
+    /// Sets whether the workspace is trusted as long as the client did not send the setting.
+    pub fn trusted_by_default(mut self, trusted: bool) -> Self {
+        self.default = trusted;
+        self
+    }
+
+    /// Rejects the method `name` while the workspace is untrusted.
+    ///
+    /// Rejected requests are answered like those denied by an [`AuthLayer`](crate::AuthLayer).
+    pub fn gate<M: Into<Cow<'static, str>>>(mut self, name: M) -> Self {
+        self.gated.push(name.into());
+        self
+    }
+
+    /// Returns the trust setting found in `req`, if any.
+    fn read(&self, req: &Request) -> Option<bool> {
+        let params = req.params()?;
+        let sources = match req.method() {
+            "initialize" => [
+                params.get("initializationOptions"),
+                params.pointer("/capabilities/experimental"),
+            ],
+            "workspace/didChangeConfiguration" => [params.get("settings"), None],
+            _ => return None,
+        };
+
+        sources
+            .iter()
+            .flatten()
+            .find_map(|source| source.pointer(&self.pointer).and_then(Value::as_bool))
+    }
+}
+
+impl Default for WorkspaceTrust {
+    fn default() -> Self {
+        WorkspaceTrust::new()
+    }
+}
+
+/// Current trust of the workspace, shared by the service and the client.
+#[derive(Debug, Default)]
+pub(crate) struct Trust {
+    config: RwLock<Option<WorkspaceTrust>>,
+    trusted: AtomicBool,
+    watchers: Mutex<Vec<UnboundedSender<bool>>>,
+}
+
+impl Trust {
+    /// Starts tracking the trust of the workspace as described by `config`.
+    pub fn enable(&self, config: WorkspaceTrust) {
+        self.trusted.store(config.default, Ordering::SeqCst);
+        *self.config.write().unwrap() = Some(config);
+    }
+
+    /// Returns whether the workspace is trusted, which is always the case if trust is not tracked.
+    pub fn is_trusted(&self) -> bool {
+        self.config.read().unwrap().is_none() || self.trusted.load(Ordering::SeqCst)
+    }
+
+    /// Sets whether the workspace is trusted, notifying watchers if this changed.
+    pub fn set_trusted(&self, trusted: bool) {
+        if self.config.read().unwrap().is_none() {
+            return;
+        }
+
+        if self.trusted.swap(trusted, Ordering::SeqCst) != trusted {
+            info!(
+                "workspace is now {}",
+                if trusted { "trusted" } else { "untrusted" }
+            );
+            let mut watchers = self.watchers.lock().unwrap();
+            watchers.retain(|tx| tx.unbounded_send(trusted).is_ok());
+        }
+    }
+
+    /// Returns a receiver of every future change of trust.
+    pub fn watch(&self) -> UnboundedReceiver<bool> {
+        let (tx, rx) = mpsc::unbounded();
+        self.watchers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Updates the trust from the setting carried by `req`, if any.
+    pub fn observe(&self, req: &Request) {
+        let trusted = match self.config.read().unwrap().as_ref() {
+            Some(config) => config.read(req),
+            None => return,
+        };
+
+        if let Some(trusted) = trusted {
+            self.set_trusted(trusted);
+        }
+    }
+
+    /// Returns whether `method` must be rejected because the workspace is untrusted.
+    pub fn is_gated(&self, method: &str) -> bool {
+        match self.config.read().unwrap().as_ref() {
+            Some(config) => {
+                !self.trusted.load(Ordering::SeqCst) && config.gated.iter().any(|m| m == method)
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets the trust established during the current session.
+    pub fn reset(&self) {
+        let default = self.config.read().unwrap().as_ref().map(|c| c.default);
+        if let Some(default) = default {
+            self.set_trusted(default);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{FutureExt, StreamExt};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_trust_from_settings() {
+        let trust = Trust::default();
+        assert!(trust.is_trusted());
+
+        trust.enable(WorkspaceTrust::new().gate("workspace/executeCommand"));
+        assert!(!trust.is_trusted());
+        assert!(trust.is_gated("workspace/executeCommand"));
+        assert!(!trust.is_gated("textDocument/hover"));
+
+        let mut changes = trust.watch();
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{"experimental":{"trusted":true}}}))
+            .id(1)
+            .finish();
+        trust.observe(&initialize);
+        assert!(trust.is_trusted());
+        assert!(!trust.is_gated("workspace/executeCommand"));
+
+        let unrelated = Request::build("workspace/didChangeConfiguration")
+            .params(json!({"settings":{"other":1}}))
+            .finish();
+        trust.observe(&unrelated);
+        assert!(trust.is_trusted());
+
+        let revoked = Request::build("workspace/didChangeConfiguration")
+            .params(json!({"settings":{"trusted":false}}))
+            .finish();
+        trust.observe(&revoked);
+        assert!(!trust.is_trusted());
+
+        assert_eq!(changes.next().now_or_never(), Some(Some(true)));
+        assert_eq!(changes.next().now_or_never(), Some(Some(false)));
+        assert_eq!(changes.next().now_or_never(), None);
+    }
+}
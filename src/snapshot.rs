@@ -0,0 +1,265 @@
+//! Snapshot-based concurrency for incremental backends.
+//!
+//! Servers built on incremental computation frameworks such as [salsa] usually follow the
+//! execution model of [rust-analyzer]: a single mutable database is changed by notifications like
+//! `textDocument/didChange`, while requests read from cheap, immutable snapshots of it. Since the
+//! answer to a request computed from an old snapshot is useless once the state has changed, every
+//! mutation cancels the reads still running on older snapshots.
+//!
+//! [`Snapshots`] packages this model for backends implementing [`SnapshotProvider`]. Reads
+//! canceled by a mutation resolve to a "content modified" error (`-32801`), which tells the client
+//! to retry the request later if it still needs the result.
+//!
+//! [salsa]: https://github.com/salsa-rs/salsa
+//! [rust-analyzer]: https://github.com/rust-lang/rust-analyzer
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use std::sync::Arc;
+//!
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::snapshot::{SnapshotProvider, Snapshots};
+//! use tower_lsp::LanguageServer;
+//!
+//! #[derive(Default)]
+//! struct Database {
+//!     documents: Arc<HashMap<Url, String>>,
+//! }
+//!
+//! impl SnapshotProvider for Database {
+//!     type Snapshot = Arc<HashMap<Url, String>>;
+//!
+//!     fn snapshot(&self) -> Self::Snapshot {
+//!         self.documents.clone()
+//!     }
+//! }
+//!
+//! struct Backend {
+//!     db: Snapshots<Database>,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//!
+//!     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+//!         let doc = params.text_document;
+//!         self.db.mutate(|db| Arc::make_mut(&mut db.documents).insert(doc.uri, doc.text));
+//!     }
+//!
+//!     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+//!         let uri = params.text_document_position_params.text_document.uri;
+//!         self.db
+//!             .read(|documents| async move {
+//!                 let len = match documents.get(&uri) {
+//!                     Some(text) => text.len(),
+//!                     None => return Ok(None),
+//!                 };
+//!
+//!                 Ok(Some(Hover {
+//!                     contents: HoverContents::Scalar(MarkedString::String(format!("{len} bytes"))),
+//!                     range: None,
+//!                 }))
+//!             })
+//!             .await
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{AbortHandle, Abortable};
+
+use crate::jsonrpc::{Error, Result};
+
+/// Backend state which hands out immutable snapshots of itself.
+///
+/// Snapshots are read concurrently with mutations, so they must not observe later mutations. This
+/// is usually achieved by cloning the state, or by sharing persistent data structures through
+/// [`Arc`]s.
+pub trait SnapshotProvider: Send + 'static {
+    /// Immutable view of the state.
+    type Snapshot: Send + 'static;
+
+    /// Returns a snapshot of the current state.
+    ///
+    /// This is called while holding the lock on the state, so it should be cheap.
+    fn snapshot(&self) -> Self::Snapshot;
+}
+
+/// Mutable backend state, read through snapshots which are canceled by later mutations.
+///
+/// See the [module-level documentation](self) for more details.
+pub struct Snapshots<P> {
+    provider: Mutex<P>,
+    readers: Arc<Mutex<Readers>>,
+}
+
+#[derive(Debug, Default)]
+struct Readers {
+    next_id: u64,
+    revision: u64,
+    running: HashMap<u64, AbortHandle>,
+}
+
+impl<P: SnapshotProvider> Snapshots<P> {
+    /// Creates a new `Snapshots` managing `provider`.
+    pub fn new(provider: P) -> Self {
+        Snapshots {
+            provider: Mutex::new(provider),
+            readers: Arc::default(),
+        }
+    }
+
+    /// Runs `read` against a snapshot of the current state.
+    ///
+    /// The snapshot is taken immediately, rather than when the returned future is first polled.
+    /// If the state is mutated before `read` completes, `read` is dropped at its next `.await`
+    /// point and this returns a "content modified" error (`-32801`). CPU-bound reads should run
+    /// on a separate thread pool, or check for cancellation themselves.
+    pub fn read<F, Fut, T>(&self, read: F) -> impl Future<Output = Result<T>> + Send + 'static
+    where
+        F: FnOnce(P::Snapshot) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let (handle, registration) = AbortHandle::new_pair();
+        let (snapshot, id) = {
+            let provider = self.provider.lock().unwrap();
+            let mut readers = self.readers.lock().unwrap();
+            let id = readers.next_id;
+            readers.next_id += 1;
+            readers.running.insert(id, handle);
+            (provider.snapshot(), id)
+        };
+
+        let reader = Reader {
+            readers: self.readers.clone(),
+            id,
+        };
+        let fut = Abortable::new(read(snapshot), registration);
+
+        async move {
+            let result = fut.await;
+            drop(reader);
+            result.unwrap_or_else(|_| Err(Error::content_modified()))
+        }
+    }
+
+    /// Mutates the state with `mutate`, canceling every read running on an older snapshot.
+    ///
+    /// Mutations are applied immediately, in the order this method is called, so handlers of
+    /// notifications should call it before their first `.await` point to preserve the order in
+    /// which the client sent them.
+    pub fn mutate<F, R>(&self, mutate: F) -> R
+    where
+        F: FnOnce(&mut P) -> R,
+    {
+        let mut provider = self.provider.lock().unwrap();
+        let mut readers = self.readers.lock().unwrap();
+        readers.revision += 1;
+        readers
+            .running
+            .drain()
+            .for_each(|(_, handle)| handle.abort());
+        drop(readers);
+
+        mutate(&mut provider)
+    }
+
+    /// Returns the number of mutations applied so far.
+    pub fn revision(&self) -> u64 {
+        self.readers.lock().unwrap().revision
+    }
+
+    /// Consumes the `Snapshots`, returning the state.
+    pub fn into_inner(self) -> P {
+        self.provider
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<P: SnapshotProvider + Default> Default for Snapshots<P> {
+    fn default() -> Self {
+        Snapshots::new(P::default())
+    }
+}
+
+impl<P> Debug for Snapshots<P> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let readers = self.readers.lock().unwrap();
+        f.debug_struct("Snapshots")
+            .field("revision", &readers.revision)
+            .field("running", &readers.running.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Stops tracking a read once it completes or is dropped.
+struct Reader {
+    readers: Arc<Mutex<Readers>>,
+    id: u64,
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.readers.lock().unwrap().running.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::oneshot;
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::jsonrpc::ErrorCode;
+
+    #[derive(Default)]
+    struct Counter(u32);
+
+    impl SnapshotProvider for Counter {
+        type Snapshot = u32;
+
+        fn snapshot(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn cancels_reads_on_mutation() {
+        let snapshots = Snapshots::new(Counter(1));
+
+        let read = snapshots.read(|n| async move { Ok(n) });
+        assert_eq!(read.now_or_never(), Some(Ok(1)));
+
+        let (tx, rx) = oneshot::channel::<()>();
+        let mut stale = Box::pin(snapshots.read(|n| async move {
+            rx.await.unwrap();
+            Ok(n)
+        }));
+        assert_eq!((&mut stale).now_or_never(), None);
+
+        snapshots.mutate(|counter| counter.0 += 1);
+        let _ = tx.send(());
+        let error = stale.now_or_never().unwrap().unwrap_err();
+        assert_eq!(error.code, ErrorCode::ContentModified);
+
+        let read = snapshots.read(|n| async move { Ok(n) });
+        assert_eq!(read.now_or_never(), Some(Ok(2)));
+        assert_eq!(snapshots.revision(), 1);
+        assert_eq!(snapshots.into_inner().0, 2);
+    }
+}
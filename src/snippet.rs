@@ -0,0 +1,273 @@
+//! Snippets which degrade to plain text for clients lacking snippet support.
+//!
+//! Completion items and code actions may insert [snippets] with tab stops and placeholders, but
+//! only if the client declares support for them; otherwise the snippet syntax would be inserted
+//! literally. A [`Snippet`] is built once and rendered either as a snippet or as the equivalent
+//! plain text, depending on the [`ClientCapabilities`]:
+//!
+//! * Completion items use snippets if the client advertises `snippetSupport`.
+//! * Code action edits use snippets if the client advertises the widespread `snippetTextEdit`
+//!   experimental capability, since the protocol has no standard capability for them yet.
+//!
+//! [snippets]: https://microsoft.github.io/language-server-protocol/specification#snippet_syntax
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::snippet::Snippet;
+//!
+//! let snippet = Snippet::new()
+//!     .text("fn ")
+//!     .placeholder(1, "name")
+//!     .text("() {\n    ")
+//!     .final_tabstop()
+//!     .text("\n}");
+//!
+//! let plain = snippet.completion_item("fn", &ClientCapabilities::default());
+//! assert_eq!(plain.insert_text.as_deref(), Some("fn name() {\n    \n}"));
+//! assert_eq!(plain.insert_text_format, Some(InsertTextFormat::PLAIN_TEXT));
+//!
+//! # let capabilities: ClientCapabilities = serde_json::from_value(serde_json::json!({
+//! #     "textDocument": { "completion": { "completionItem": { "snippetSupport": true } } }
+//! # })).unwrap();
+//! let item = snippet.completion_item("fn", &capabilities);
+//! assert_eq!(item.insert_text.as_deref(), Some("fn ${1:name}() {\n    $0\n}"));
+//! assert_eq!(item.insert_text_format, Some(InsertTextFormat::SNIPPET));
+//! ```
+
+use lsp_types::{
+    ClientCapabilities, CompletionItem, CompletionTextEdit, InsertTextFormat, Range, TextEdit,
+};
+use serde::Serialize;
+
+/// Name of the experimental client capability enabling snippets in code action edits.
+const SNIPPET_TEXT_EDIT: &str = "snippetTextEdit";
+
+/// Returns whether the client supports snippets in completion items.
+pub fn supports_completion_snippets(capabilities: &ClientCapabilities) -> bool {
+    let text_document = capabilities.text_document.as_ref();
+    let completion = text_document.and_then(|c| c.completion.as_ref());
+    let item = completion.and_then(|c| c.completion_item.as_ref());
+    item.and_then(|c| c.snippet_support) == Some(true)
+}
+
+/// Returns whether the client supports snippets in the text edits of code actions.
+pub fn supports_edit_snippets(capabilities: &ClientCapabilities) -> bool {
+    let experimental = capabilities.experimental.as_ref();
+    let flag = experimental.and_then(|e| e.get(SNIPPET_TEXT_EDIT));
+    flag.and_then(|v| v.as_bool()) == Some(true)
+}
+
+/// A snippet, along with its plain text equivalent.
+///
+/// Literal text is escaped as needed. In the plain text rendering, tab stops are left empty and
+/// placeholders and choices are replaced with their default text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snippet {
+    snippet: String,
+    plain: String,
+}
+
+impl Snippet {
+    /// Creates an empty snippet.
+    pub fn new() -> Self {
+        Snippet::default()
+    }
+
+    /// Appends literal `text`.
+    pub fn text(mut self, text: &str) -> Self {
+        escape(&mut self.snippet, text, &['$', '\\']);
+        self.plain.push_str(text);
+        self
+    }
+
+    /// Appends the tab stop `$index`.
+    pub fn tabstop(mut self, index: u32) -> Self {
+        self.snippet.push_str(&format!("${}", index));
+        self
+    }
+
+    /// Appends the final tab stop `$0`, where the cursor ends up.
+    pub fn final_tabstop(self) -> Self {
+        self.tabstop(0)
+    }
+
+    /// Appends the tab stop `index` with the default `text`, i.e. `${index:text}`.
+    pub fn placeholder(mut self, index: u32, text: &str) -> Self {
+        self.snippet.push_str(&format!("${{{}:", index));
+        escape(&mut self.snippet, text, &['$', '}', '\\']);
+        self.snippet.push('}');
+        self.plain.push_str(text);
+        self
+    }
+
+    /// Appends the tab stop `index` offering a choice between `options`, i.e. `${index|a,b|}`.
+    ///
+    /// The first option is used in the plain text rendering.
+    pub fn choice<S: AsRef<str>>(mut self, index: u32, options: &[S]) -> Self {
+        self.snippet.push_str(&format!("${{{}|", index));
+        for (i, option) in options.iter().enumerate() {
+            if i > 0 {
+                self.snippet.push(',');
+            }
+            escape(
+                &mut self.snippet,
+                option.as_ref(),
+                &['$', '}', '\\', ',', '|'],
+            );
+        }
+        self.snippet.push_str("|}");
+
+        if let Some(first) = options.first() {
+            self.plain.push_str(first.as_ref());
+        }
+        self
+    }
+
+    /// Returns the snippet syntax.
+    pub fn as_snippet(&self) -> &str {
+        &self.snippet
+    }
+
+    /// Returns the plain text equivalent of the snippet.
+    pub fn as_plain_text(&self) -> &str {
+        &self.plain
+    }
+
+    /// Returns the snippet if `snippets` is `true`, or the plain text otherwise, along with the
+    /// corresponding format.
+    pub fn render(&self, snippets: bool) -> (String, InsertTextFormat) {
+        match snippets {
+            true => (self.snippet.clone(), InsertTextFormat::SNIPPET),
+            false => (self.plain.clone(), InsertTextFormat::PLAIN_TEXT),
+        }
+    }
+
+    /// Creates a completion item with the given `label`, inserting this snippet.
+    pub fn completion_item(
+        &self,
+        label: &str,
+        capabilities: &ClientCapabilities,
+    ) -> CompletionItem {
+        let (text, format) = self.render(supports_completion_snippets(capabilities));
+        CompletionItem {
+            label: label.to_owned(),
+            insert_text: Some(text),
+            insert_text_format: Some(format),
+            ..CompletionItem::default()
+        }
+    }
+
+    /// Creates a completion item with the given `label`, replacing `range` with this snippet.
+    pub fn completion_edit(
+        &self,
+        label: &str,
+        range: Range,
+        capabilities: &ClientCapabilities,
+    ) -> CompletionItem {
+        let (text, format) = self.render(supports_completion_snippets(capabilities));
+        CompletionItem {
+            label: label.to_owned(),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, text))),
+            insert_text_format: Some(format),
+            ..CompletionItem::default()
+        }
+    }
+
+    /// Creates a code action edit replacing `range` with this snippet.
+    pub fn text_edit(&self, range: Range, capabilities: &ClientCapabilities) -> SnippetTextEdit {
+        let snippets = supports_edit_snippets(capabilities);
+        let (text, _) = self.render(snippets);
+        SnippetTextEdit {
+            edit: TextEdit::new(range, text),
+            insert_text_format: snippets.then_some(InsertTextFormat::SNIPPET),
+        }
+    }
+}
+
+/// A [`TextEdit`] which may contain a snippet, as understood by clients advertising the
+/// `snippetTextEdit` experimental capability.
+///
+/// Since [`WorkspaceEdit`](lsp_types::WorkspaceEdit) only holds plain text edits, code actions
+/// using snippets need to be built from their JSON representation, into which this type
+/// serializes. [`SnippetTextEdit::into_text_edit`] returns the plain edit for other uses.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetTextEdit {
+    /// The edit, whose new text may be a snippet.
+    #[serde(flatten)]
+    pub edit: TextEdit,
+    /// Set to [`InsertTextFormat::SNIPPET`] if the new text is a snippet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<InsertTextFormat>,
+}
+
+impl SnippetTextEdit {
+    /// Returns whether the new text is a snippet.
+    pub fn is_snippet(&self) -> bool {
+        self.insert_text_format == Some(InsertTextFormat::SNIPPET)
+    }
+
+    /// Discards the format, returning the underlying edit.
+    pub fn into_text_edit(self) -> TextEdit {
+        self.edit
+    }
+}
+
+/// Appends `text` to `out`, escaping `special` characters with a backslash.
+fn escape(out: &mut String, text: &str, special: &[char]) {
+    for c in text.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn escapes_literals() {
+        let snippet = Snippet::new()
+            .text("${x}")
+            .placeholder(1, "a}b")
+            .choice(2, &["x,y", "z|"])
+            .tabstop(3);
+
+        assert_eq!(snippet.as_snippet(), r"\${x}${1:a\}b}${2|x\,y,z\||}$3");
+        assert_eq!(snippet.as_plain_text(), "${x}a}bx,y");
+    }
+
+    #[test]
+    fn degrades_code_action_edits() {
+        let snippet = Snippet::new()
+            .text("let ")
+            .placeholder(1, "x")
+            .text(" = 1;");
+        let range = Range::new(Position::new(0, 0), Position::new(0, 0));
+
+        let edit = snippet.text_edit(range, &ClientCapabilities::default());
+        assert!(!edit.is_snippet());
+        assert_eq!(edit.edit.new_text, "let x = 1;");
+
+        let capabilities = ClientCapabilities {
+            experimental: Some(json!({ "snippetTextEdit": true })),
+            ..ClientCapabilities::default()
+        };
+        let edit = snippet.text_edit(range, &capabilities);
+        assert_eq!(
+            serde_json::to_value(&edit).unwrap(),
+            json!({
+                "range": range,
+                "newText": "let ${1:x} = 1;",
+                "insertTextFormat": 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Conversion between hierarchical and flat document symbols.
+//!
+//! `textDocument/documentSymbol` may be answered either with a tree of [`DocumentSymbol`]s or with
+//! a flat list of [`SymbolInformation`], but only clients which advertise the
+//! `hierarchicalDocumentSymbolSupport` capability understand the former. Servers can produce
+//! nested symbols internally and convert them with [`for_client`], or let the service do it with
+//! [`LspServiceBuilder::adapt_document_symbols`](crate::LspServiceBuilder::adapt_document_symbols).
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::symbols;
+//!
+//! # #[allow(deprecated)]
+//! let field = DocumentSymbol {
+//!     name: "field".into(),
+//!     detail: None,
+//!     kind: SymbolKind::FIELD,
+//!     tags: None,
+//!     deprecated: None,
+//!     range: Range::new(Position::new(1, 4), Position::new(1, 14)),
+//!     selection_range: Range::new(Position::new(1, 4), Position::new(1, 9)),
+//!     children: None,
+//! };
+//! # #[allow(deprecated)]
+//! let parent = DocumentSymbol {
+//!     name: "Struct".into(),
+//!     kind: SymbolKind::STRUCT,
+//!     range: Range::new(Position::new(0, 0), Position::new(2, 1)),
+//!     selection_range: Range::new(Position::new(0, 7), Position::new(0, 13)),
+//!     children: Some(vec![field.clone()]),
+//!     ..field
+//! };
+//!
+//! let uri = Url::parse("file:///main.rs").unwrap();
+//! let flat = symbols::flatten(&uri, vec![parent]);
+//! assert_eq!(flat.len(), 2);
+//! assert_eq!(flat[1].container_name.as_deref(), Some("Struct"));
+//! ```
+
+use lsp_types::{
+    ClientCapabilities, DocumentSymbol, DocumentSymbolResponse, Location, Range, SymbolInformation,
+    Url,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::jsonrpc::Response;
+
+/// Returns whether the client supports hierarchical document symbols.
+pub fn supports_hierarchy(capabilities: &ClientCapabilities) -> bool {
+    let text_document = capabilities.text_document.as_ref();
+    let document_symbol = text_document.and_then(|c| c.document_symbol.as_ref());
+    document_symbol.and_then(|c| c.hierarchical_document_symbol_support) == Some(true)
+}
+
+/// Converts `response` for the document `uri` into the form supported by the client.
+///
+/// Nested symbols are flattened with [`flatten`] if the client does not support hierarchical
+/// document symbols. Flat symbols are returned unchanged, since they are understood by all clients.
+pub fn for_client(
+    uri: &Url,
+    response: DocumentSymbolResponse,
+    capabilities: &ClientCapabilities,
+) -> DocumentSymbolResponse {
+    match response {
+        DocumentSymbolResponse::Nested(symbols) if !supports_hierarchy(capabilities) => {
+            DocumentSymbolResponse::Flat(flatten(uri, symbols))
+        }
+        response => response,
+    }
+}
+
+/// Flattens the tree of `symbols` in the document `uri` into a list, parents first.
+///
+/// The name of the parent of each symbol is kept as its `container_name`.
+pub fn flatten(uri: &Url, symbols: Vec<DocumentSymbol>) -> Vec<SymbolInformation> {
+    fn visit(
+        uri: &Url,
+        container: Option<&str>,
+        symbols: Vec<DocumentSymbol>,
+        flat: &mut Vec<SymbolInformation>,
+    ) {
+        for symbol in symbols {
+            #[allow(deprecated)]
+            flat.push(SymbolInformation {
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                tags: symbol.tags,
+                deprecated: symbol.deprecated,
+                location: Location::new(uri.clone(), symbol.range),
+                container_name: container.map(str::to_owned),
+            });
+
+            if let Some(children) = symbol.children {
+                visit(uri, Some(&symbol.name), children, flat);
+            }
+        }
+    }
+
+    let mut flat = Vec::new();
+    visit(uri, None, symbols, &mut flat);
+    flat
+}
+
+/// Nests the list of `symbols` into a tree, according to their ranges.
+///
+/// Each symbol becomes a child of the smallest preceding symbol whose range contains its own, and
+/// its whole range is used as its `selection_range`. Locations in other documents than the first
+/// symbol's are not checked, so `symbols` should all belong to the same document.
+pub fn nest(symbols: Vec<SymbolInformation>) -> Vec<DocumentSymbol> {
+    // Ancestors of the symbol being visited, outermost first.
+    let mut stack: Vec<DocumentSymbol> = Vec::new();
+    let mut roots = Vec::new();
+
+    for symbol in symbols {
+        let range = symbol.location.range;
+        while let Some(parent) = stack.last() {
+            if contains(parent.range, range) {
+                break;
+            }
+
+            let done = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, done);
+        }
+
+        #[allow(deprecated)]
+        stack.push(DocumentSymbol {
+            name: symbol.name,
+            detail: None,
+            kind: symbol.kind,
+            tags: symbol.tags,
+            deprecated: symbol.deprecated,
+            range,
+            selection_range: range,
+            children: None,
+        });
+    }
+
+    while let Some(done) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+
+    roots
+}
+
+/// Adds `symbol` as the last child of the innermost symbol on the `stack`, or as a root.
+fn attach(stack: &mut [DocumentSymbol], roots: &mut Vec<DocumentSymbol>, symbol: DocumentSymbol) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => roots.push(symbol),
+    }
+}
+
+fn contains(outer: Range, inner: Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// Applies [`for_client`] to the `textDocument/documentSymbol` response `res`.
+pub(crate) fn adapt_response(
+    uri: &Url,
+    res: Response,
+    capabilities: &ClientCapabilities,
+) -> Response {
+    let (id, result) = res.into_parts();
+    let symbols = match result {
+        Ok(Value::Null) | Err(_) => return Response::from_parts(id, result),
+        Ok(value) => value,
+    };
+
+    match DocumentSymbolResponse::deserialize(&symbols) {
+        Ok(symbols) => {
+            let symbols = for_client(uri, symbols, capabilities);
+            let value = serde_json::to_value(symbols).expect("symbols must be serializable");
+            Response::from_ok(id, value)
+        }
+        Err(_) => Response::from_ok(id, symbols),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{
+        DocumentSymbolClientCapabilities, Position, SymbolKind, TextDocumentClientCapabilities,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::jsonrpc::Id;
+
+    #[allow(deprecated)]
+    fn symbol(name: &str, range: Range, children: Vec<DocumentSymbol>) -> DocumentSymbol {
+        DocumentSymbol {
+            name: name.into(),
+            detail: None,
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range: range,
+            children: (!children.is_empty()).then_some(children),
+        }
+    }
+
+    fn range(start: u32, end: u32) -> Range {
+        Range::new(Position::new(start, 0), Position::new(end, 0))
+    }
+
+    fn hierarchical(support: bool) -> ClientCapabilities {
+        ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                document_symbol: Some(DocumentSymbolClientCapabilities {
+                    hierarchical_document_symbol_support: Some(support),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flattens_and_nests_symbols() {
+        let uri = Url::parse("file:///main.rs").unwrap();
+        let tree = vec![
+            symbol(
+                "outer",
+                range(0, 10),
+                vec![
+                    symbol(
+                        "first",
+                        range(1, 3),
+                        vec![symbol("inner", range(2, 3), vec![])],
+                    ),
+                    symbol("second", range(4, 5), vec![]),
+                ],
+            ),
+            symbol("next", range(11, 12), vec![]),
+        ];
+
+        let flat = flatten(&uri, tree.clone());
+        let names: Vec<_> = flat.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["outer", "first", "inner", "second", "next"]);
+        let containers: Vec<_> = flat.iter().map(|s| s.container_name.as_deref()).collect();
+        assert_eq!(
+            containers,
+            [None, Some("outer"), Some("first"), Some("outer"), None]
+        );
+
+        assert_eq!(nest(flat), tree);
+    }
+
+    #[test]
+    fn adapts_responses_to_client() {
+        let uri = Url::parse("file:///main.rs").unwrap();
+        let tree = vec![symbol("outer", range(0, 2), vec![])];
+        let res = Response::from_ok(Id::Number(1), json!(tree));
+
+        let res = adapt_response(&uri, res.clone(), &hierarchical(true));
+        assert_eq!(res.result(), Some(&json!(tree)));
+
+        let res = adapt_response(&uri, res, &hierarchical(false));
+        assert_eq!(res.result(), Some(&json!(flatten(&uri, tree))));
+
+        let res = Response::from_ok(Id::Number(1), json!(null));
+        let res = adapt_response(&uri, res, &hierarchical(false));
+        assert_eq!(res.result(), Some(&json!(null)));
+    }
+}
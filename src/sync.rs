@@ -0,0 +1,341 @@
+//! Synchronization primitives for sharing state between handlers.
+//!
+//! Every [`LanguageServer`](crate::LanguageServer) method receives `&self`, and requests are
+//! processed concurrently, so mutable server state needs interior mutability. Reaching for
+//! [`std::sync::Mutex`] is tempting, but holding its guard across an `.await` blocks the executor
+//! thread and usually fails to compile anyway, since the guard is not `Send`. [`Shared`] is a
+//! runtime-agnostic alternative whose guards may be held across `.await` points.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//!
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::sync::Shared;
+//! use tower_lsp::{Client, LanguageServer};
+//!
+//! struct Backend {
+//!     client: Client,
+//!     documents: Shared<HashMap<Url, String>>,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//!
+//!     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+//!         let doc = params.text_document;
+//!         self.documents.write().await.insert(doc.uri, doc.text);
+//!     }
+//!
+//!     async fn did_close(&self, params: DidCloseTextDocumentParams) {
+//!         self.documents.write().await.remove(&params.text_document.uri);
+//!     }
+//!
+//!     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+//!         let uri = params.text_document_position_params.text_document.uri;
+//!         let documents = self.documents.read().await;
+//!         let len = match documents.get(&uri) {
+//!             Some(text) => text.len(),
+//!             None => return Ok(None),
+//!         };
+//!
+//!         // Other requests may read `documents` while this one waits on the client.
+//!         let message = format!("{len} bytes");
+//!         self.client.log_message(MessageType::INFO, &message).await;
+//!
+//!         Ok(Some(Hover {
+//!             contents: HoverContents::Scalar(MarkedString::String(message)),
+//!             range: None,
+//!         }))
+//!     }
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+use futures::future::poll_fn;
+
+/// An asynchronous reader-writer lock for server state.
+///
+/// Any number of [`read`](Shared::read) guards may be held at the same time, while a
+/// [`write`](Shared::write) guard grants exclusive access. Both kinds of guards may be held across
+/// `.await` points without blocking the executor, although long-lived write guards still delay
+/// every other handler touching the same state. Pending writers take priority over new readers, so
+/// a steady stream of reads cannot starve a write.
+///
+/// Use [`snapshot`](Shared::snapshot) to work on a copy of the state without holding any guard at
+/// all, e.g. when computing an expensive response.
+///
+/// Acquiring a write guard while the same task holds any other guard of the same `Shared` never
+/// completes.
+///
+/// See the [module-level documentation](self) for an example.
+pub struct Shared<T> {
+    state: Mutex<State<T>>,
+    writers: futures::lock::Mutex<()>,
+}
+
+struct State<T> {
+    /// The current value, taken out of the lock by the active writer.
+    value: Option<Arc<T>>,
+    /// Whether a writer is waiting for readers to finish, or is holding the value.
+    writing: bool,
+    waiters: Vec<Waker>,
+}
+
+impl<T> State<T> {
+    fn wake_all(&mut self) {
+        self.waiters.drain(..).for_each(Waker::wake);
+    }
+}
+
+impl<T> Shared<T> {
+    /// Creates a new `Shared` holding `value`.
+    pub fn new(value: T) -> Self {
+        Shared {
+            state: Mutex::new(State {
+                value: Some(Arc::new(value)),
+                writing: false,
+                waiters: Vec::new(),
+            }),
+            writers: futures::lock::Mutex::new(()),
+        }
+    }
+
+    /// Locks the state for reading, waiting for any pending writer to finish first.
+    pub async fn read(&self) -> ReadGuard<'_, T> {
+        let value = poll_fn(|cx| {
+            let mut state = self.lock();
+            match state.value.as_ref() {
+                Some(value) if !state.writing => Poll::Ready(value.clone()),
+                _ => {
+                    state.waiters.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        ReadGuard {
+            shared: self,
+            value: Some(value),
+        }
+    }
+
+    /// Locks the state for writing, waiting for all other guards to be dropped first.
+    pub async fn write(&self) -> WriteGuard<'_, T> {
+        let writer = self.writers.lock().await;
+
+        // Readers are held back from here on, so the writer only needs to outlive existing ones.
+        let pending = PendingWrite(self);
+        let value = poll_fn(|cx| {
+            let mut state = self.lock();
+            state.writing = true;
+            match state.value.take().map(Arc::try_unwrap) {
+                Some(Ok(value)) => Poll::Ready(value),
+                Some(Err(value)) => {
+                    state.value = Some(value);
+                    state.waiters.push(cx.waker().clone());
+                    Poll::Pending
+                }
+                None => unreachable!("writers are serialized"),
+            }
+        })
+        .await;
+        std::mem::forget(pending);
+
+        WriteGuard {
+            shared: self,
+            value: Some(value),
+            _writer: writer,
+        }
+    }
+
+    /// Returns a copy of the current state, waiting for any pending writer to finish first.
+    pub async fn snapshot(&self) -> T
+    where
+        T: Clone,
+    {
+        T::clone(&*self.read().await)
+    }
+
+    /// Consumes the `Shared`, returning the state.
+    pub fn into_inner(self) -> T {
+        let state = self.state.into_inner().unwrap_or_else(|e| e.into_inner());
+        match state.value.map(Arc::try_unwrap) {
+            Some(Ok(value)) => value,
+            _ => unreachable!("guards borrow the `Shared`"),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // The lock is never held while running user code, so poisoning is harmless.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: Default> Default for Shared<T> {
+    fn default() -> Self {
+        Shared::new(T::default())
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Shared::new(value)
+    }
+}
+
+impl<T> Debug for Shared<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct(stringify!(Shared))
+            .field("writing", &state.writing)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lets readers through again if a call to [`Shared::write`] is cancelled while waiting.
+struct PendingWrite<'a, T>(&'a Shared<T>);
+
+impl<T> Drop for PendingWrite<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.writing = false;
+        state.wake_all();
+    }
+}
+
+/// Shared access to the state of a [`Shared`], released when dropped.
+///
+/// This struct is created by [`Shared::read`]. See its documentation for more.
+pub struct ReadGuard<'a, T> {
+    shared: &'a Shared<T>,
+    value: Option<Arc<T>>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("value is only taken on drop")
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.value = None;
+
+        let mut state = self.shared.lock();
+        if state.writing {
+            state.wake_all();
+        }
+    }
+}
+
+impl<T: Debug> Debug for ReadGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+/// Exclusive access to the state of a [`Shared`], released when dropped.
+///
+/// This struct is created by [`Shared::write`]. See its documentation for more.
+pub struct WriteGuard<'a, T> {
+    shared: &'a Shared<T>,
+    value: Option<T>,
+    _writer: futures::lock::MutexGuard<'a, ()>,
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("value is only taken on drop")
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("value is only taken on drop")
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        let value = self.value.take().map(Arc::new);
+
+        let mut state = self.shared.lock();
+        state.value = value;
+        state.writing = false;
+        state.wake_all();
+    }
+}
+
+impl<T: Debug> Debug for WriteGuard<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn allows_concurrent_readers() {
+        let shared = Shared::new(vec![1]);
+        let first = shared.read().await;
+        let second = shared.read().await;
+        assert_eq!(*first, *second);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn writer_waits_for_readers() {
+        let shared = Shared::new(0);
+        let reader = shared.read().await;
+
+        let mut write = Box::pin(shared.write());
+        assert!((&mut write).now_or_never().is_none());
+
+        // New readers queue up behind the pending writer.
+        assert!(shared.read().now_or_never().is_none());
+
+        drop(reader);
+        *write.await += 1;
+        assert_eq!(*shared.read().await, 1);
+        assert_eq!(shared.snapshot().await, 1);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancelled_writer_releases_readers() {
+        let shared = Shared::new(String::from("foo"));
+        let reader = shared.read().await;
+
+        let mut write = Box::pin(shared.write());
+        assert!((&mut write).now_or_never().is_none());
+        drop(write);
+
+        let next = shared.read().now_or_never().expect("writer was cancelled");
+        assert_eq!(*next, *reader);
+        drop((reader, next));
+
+        shared.write().await.push_str("bar");
+        assert_eq!(shared.into_inner(), "foobar");
+    }
+}
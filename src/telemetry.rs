@@ -0,0 +1,89 @@
+//! Typed payloads for the `telemetry/event` notification.
+//!
+//! The [`telemetry/event`] notification carries arbitrary JSON, which tends to make telemetry
+//! inconsistent across a code base. Types implementing [`TelemetryEvent`], usually through its
+//! derive macro, are instead sent with [`Client::send_telemetry`] in a uniform shape:
+//!
+//! ```json
+//! { "name": "index/completed", "timestamp": 1700000000000, "properties": { "files": 42 } }
+//! ```
+//!
+//! where `timestamp` is the time the event was sent, in milliseconds since the Unix epoch, and
+//! `properties` holds the fields of the event.
+//!
+//! The [`Client`] can also reduce the volume of telemetry: [`Client::set_telemetry_sampling`]
+//! only sends one in every `n` events of a given type, while [`Client::set_telemetry_batch_size`]
+//! sends events in batches, as a JSON array, until [`Client::flush_telemetry`] is called.
+//!
+//! [`telemetry/event`]: https://microsoft.github.io/language-server-protocol/specification#telemetry_event
+//! [`Client`]: crate::Client
+//! [`Client::send_telemetry`]: crate::Client::send_telemetry
+//! [`Client::set_telemetry_sampling`]: crate::Client::set_telemetry_sampling
+//! [`Client::set_telemetry_batch_size`]: crate::Client::set_telemetry_batch_size
+//! [`Client::flush_telemetry`]: crate::Client::flush_telemetry
+//!
+//! # Example
+//!
+//! ```rust
+//! use serde::Serialize;
+//! use tower_lsp::telemetry::TelemetryEvent;
+//! use tower_lsp::Client;
+//!
+//! #[derive(Serialize, TelemetryEvent)]
+//! #[telemetry(name = "index/completed")]
+//! struct IndexCompleted {
+//!     files: usize,
+//!     duration_ms: u64,
+//! }
+//!
+//! async fn report(client: &Client) {
+//!     // Only send one in every ten of these events.
+//!     client.set_telemetry_sampling::<IndexCompleted>(10);
+//!
+//!     let event = IndexCompleted { files: 42, duration_ms: 1200 };
+//!     client.send_telemetry(&event).await;
+//! }
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Derive macro implementing [`TelemetryEvent`](trait@TelemetryEvent) for a struct.
+///
+/// The struct must have named fields, which become the `properties` of the event, or no fields at
+/// all, and must implement [`Serialize`] too. The name of the event is given by the
+/// `#[telemetry(name = "...")]` attribute, and defaults to the name of the struct in `snake_case`.
+///
+/// The generated code refers to this crate as `::tower_lsp`, which can be overridden with a
+/// `#[telemetry(crate = "...")]` attribute, like with [`LspExtension`](crate::LspExtension).
+pub use tower_lsp_macros::TelemetryEvent;
+
+/// An event sent to the client through the `telemetry/event` notification.
+///
+/// This is usually derived, see the [module documentation](self) for an example.
+pub trait TelemetryEvent: Serialize {
+    /// The name of the event.
+    const NAME: &'static str;
+}
+
+/// Returns the payload sent for `event`, timestamped with the current time.
+///
+/// Events which do not serialize into a JSON object are sent with empty `properties`.
+pub(crate) fn payload<E: TelemetryEvent>(event: &E) -> serde_json::Result<Value> {
+    let properties = match serde_json::to_value(event)? {
+        Value::Object(properties) => properties,
+        _ => Map::new(),
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+
+    Ok(json!({
+        "name": E::NAME,
+        "timestamp": timestamp,
+        "properties": properties,
+    }))
+}
@@ -1,24 +1,52 @@
 //! Generic server for multiplexing bidirectional streams through a transport.
 
-#[cfg(feature = "runtime-agnostic")]
-use async_codec_lite::{FramedRead, FramedWrite};
 #[cfg(feature = "runtime-agnostic")]
 use futures::io::{AsyncRead, AsyncWrite};
 
 #[cfg(feature = "runtime-tokio")]
 use tokio::io::{AsyncRead, AsyncWrite};
-#[cfg(feature = "runtime-tokio")]
-use tokio_util::codec::{FramedRead, FramedWrite};
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
 
 use futures::channel::mpsc;
+use futures::stream::{AbortHandle, AbortRegistration, Abortable, BoxStream};
 use futures::{future, join, stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryFutureExt};
 use tower::Service;
-use tracing::error;
+use tracing::{debug, error};
 
-use crate::codec::{LanguageServerCodec, ParseError};
+use crate::codec::{DesyncCallback, HeaderCallback, LanguageServerCodec, ParseError};
 use crate::jsonrpc::{Error, Id, Message, Request, Response};
+use crate::protocol::MethodTable;
 use crate::service::{ClientSocket, RequestStream, ResponseSink};
 
+use self::parts::{BoxError, Input, Output};
+use self::stdio::StdoutGuard;
+
+#[cfg(any(feature = "runtime-tokio", feature = "async-net"))]
+pub use self::args::{from_args, BoxedReader, BoxedWriter};
+pub use self::multiplex::Multiplexer;
+pub use self::parts::{MessageInput, MessageOutput};
+pub use self::stats::{ConnectionStats, ConnectionStatsSnapshot, Desync, DesyncReason};
+#[doc(hidden)]
+pub use self::stdio::_print;
+pub use self::stdio::is_stdout_protected;
+
+#[cfg(feature = "http-gateway")]
+pub mod http;
+#[cfg(all(windows, feature = "named-pipe"))]
+pub mod named_pipe;
+
+#[cfg(any(feature = "runtime-tokio", feature = "async-net"))]
+mod args;
+mod multiplex;
+mod parts;
+mod stats;
+mod stdio;
+
 const DEFAULT_MAX_CONCURRENCY: usize = 4;
 const MESSAGE_QUEUE_SIZE: usize = 100;
 
@@ -35,6 +63,15 @@ pub trait Loopback {
     ///
     /// The two halves returned implement the [`Stream`] and [`Sink`] traits, respectively.
     fn split(self) -> (Self::RequestStream, Self::ResponseSink);
+
+    /// Takes the stream of messages generated on the server side, which are handled as if they
+    /// were received from the client, such as the file events of the server-side file watcher
+    /// enabled by the `file-watcher` feature.
+    ///
+    /// This is called once before serving starts, and returns `None` by default.
+    fn take_local_requests(&mut self) -> Option<BoxStream<'static, Request>> {
+        None
+    }
 }
 
 impl Loopback for ClientSocket {
@@ -45,6 +82,83 @@ impl Loopback for ClientSocket {
     fn split(self) -> (Self::RequestStream, Self::ResponseSink) {
         self.split()
     }
+
+    #[inline]
+    fn take_local_requests(&mut self) -> Option<BoxStream<'static, Request>> {
+        self.take_local_requests()
+    }
+}
+
+/// Action taken by a [`Server`] when writing a message to its output stream fails.
+///
+/// This is returned from the callback registered with [`Server::on_output_error`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum OutputErrorPolicy {
+    /// Stops reading input immediately and resolves to [`ServeError::OutputError`] once all
+    /// in-flight requests have finished.
+    Abort,
+    /// Like [`OutputErrorPolicy::Abort`], but first sends an `exit` notification to the service,
+    /// as if the client had disconnected abruptly.
+    ///
+    /// For an [`LspService`](crate::LspService), this moves the server into the exited state, so
+    /// further attempts to use the [`Client`](crate::Client) fail instead of queueing messages
+    /// which can never be written. This is the default.
+    Exit,
+    /// Discards the message and keeps serving.
+    Continue,
+}
+
+type OutputErrorFn = dyn Fn(&dyn std::error::Error) -> OutputErrorPolicy + Send + Sync;
+
+#[derive(Clone)]
+struct OutputErrorCallback(Arc<OutputErrorFn>);
+
+impl Debug for OutputErrorCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(OutputErrorCallback))
+            .field(&format_args!("{:p}", self.0))
+            .finish()
+    }
+}
+
+/// Decision returned by the callback registered with [`Server::filter_incoming`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Filter {
+    /// Passes the message on to the service.
+    Forward,
+    /// Discards the message without passing it to the service.
+    ///
+    /// Notifications are dropped silently, while requests are answered with a JSON-RPC error with
+    /// code `-32803` (Request failed) so the client does not wait for a response forever.
+    Drop,
+}
+
+type IncomingFn = dyn Fn(&mut Request) -> Filter + Send + Sync;
+
+#[derive(Clone)]
+struct IncomingCallback(Arc<IncomingFn>);
+
+impl Debug for IncomingCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(IncomingCallback))
+            .field(&format_args!("{:p}", self.0))
+            .finish()
+    }
+}
+
+type OutgoingFn = dyn Fn(Message) -> Message + Send + Sync;
+
+#[derive(Clone)]
+struct OutgoingCallback(Arc<OutgoingFn>);
+
+impl Debug for OutgoingCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple(stringify!(OutgoingCallback))
+            .field(&format_args!("{:p}", self.0))
+            .finish()
+    }
 }
 
 /// Server for processing requests and responses on standard I/O or TCP.
@@ -54,6 +168,15 @@ pub struct Server<I, O, L = ClientSocket> {
     stdout: O,
     loopback: L,
     max_concurrency: usize,
+    custom_headers: Option<HeaderCallback>,
+    on_desync: Option<DesyncCallback>,
+    on_output_error: Option<OutputErrorCallback>,
+    filter_incoming: Option<IncomingCallback>,
+    map_outgoing: Option<OutgoingCallback>,
+    validate_utf8: bool,
+    protect_stdout: bool,
+    methods: MethodTable,
+    stats: ConnectionStats,
 }
 
 impl<I, O, L> Server<I, O, L>
@@ -65,11 +188,106 @@ where
 {
     /// Creates a new `Server` with the given `stdin` and `stdout` handles.
     pub fn new(stdin: I, stdout: O, socket: L) -> Self {
+        Server::with_io(stdin, stdout, socket)
+    }
+}
+
+impl<S, K, L, E> Server<MessageInput<S>, MessageOutput<K>, L>
+where
+    S: Stream<Item = Result<Message, E>>,
+    E: Into<BoxError>,
+    K: Sink<Message>,
+    K::Error: Into<BoxError>,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    /// Creates a new `Server` reading messages from `input` and writing messages to `output`.
+    ///
+    /// Unlike [`Server::new`], the two halves of the connection need not come from the same
+    /// transport, which allows reading from standard input while writing to a socket, or teeing
+    /// the output to a log. They may be any [`Stream`] and [`Sink`] of messages, such as a
+    /// [`FramedRead`] and a [`FramedWrite`] built with a [`LanguageServerCodec`] over separate
+    /// byte streams.
+    ///
+    /// Since the messages are decoded and encoded outside of the server, the options of the
+    /// server concerning the wire format, i.e. [`Server::custom_headers`],
+    /// [`Server::on_desync`] and [`Server::skip_utf8_validation`], have no effect, and the
+    /// [`ConnectionStats`] of the server are not updated. Errors yielded by `input` are answered
+    /// like messages which failed to be decoded.
+    ///
+    /// [`FramedRead`]: https://docs.rs/tokio-util/latest/tokio_util/codec/struct.FramedRead.html
+    /// [`FramedWrite`]: https://docs.rs/tokio-util/latest/tokio_util/codec/struct.FramedWrite.html
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// use futures::channel::mpsc;
+    /// use futures::{stream, SinkExt, StreamExt};
+    /// use tower_lsp::jsonrpc::{Message, Request};
+    ///
+    /// let initialize = Request::build("initialize")
+    ///     .params(serde_json::json!({"capabilities": {}}))
+    ///     .id(1)
+    ///     .finish();
+    /// let input = stream::iter([Ok::<_, std::io::Error>(Message::from(initialize))]);
+    ///
+    /// // Keep a copy of everything the server writes, e.g. for a protocol inspector.
+    /// let (output, mut written) = mpsc::unbounded::<Message>();
+    ///
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::from_parts(input, output, socket);
+    /// assert!(server.serve(service).await.is_err());
+    ///
+    /// let response = written.next().await.unwrap();
+    /// assert!(matches!(response, Message::Response(_)));
+    /// # }
+    /// ```
+    pub fn from_parts(input: S, output: K, socket: L) -> Self {
+        Server::with_io(MessageInput::new(input), MessageOutput::new(output), socket)
+    }
+}
+
+impl<I, O, L> Server<I, O, L>
+where
+    I: Input,
+    O: Output,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    fn with_io(stdin: I, stdout: O, socket: L) -> Self {
         Server {
             stdin,
             stdout,
             loopback: socket,
             max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            custom_headers: None,
+            on_desync: None,
+            on_output_error: None,
+            filter_incoming: None,
+            map_outgoing: None,
+            validate_utf8: true,
+            protect_stdout: false,
+            methods: MethodTable::LSP,
+            stats: ConnectionStats::default(),
         }
     }
 
@@ -98,20 +316,568 @@ where
         self
     }
 
+    /// Registers a `callback` which receives any non-standard headers sent by the client.
+    ///
+    /// The base protocol only defines the `Content-Length` and `Content-Type` headers, but some
+    /// deployments tunnel extra metadata, such as authentication tokens or session IDs, through
+    /// additional headers. For every incoming message carrying such headers, `callback` is called
+    /// with their names and values in the order they were received, just before the message is
+    /// dispatched. Values which are not valid UTF-8 are converted lossily.
+    ///
+    /// The callback runs on the task reading from `stdin`, so it should return quickly. If no
+    /// callback is registered, non-standard headers are logged and discarded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::new(stdin, stdout, socket).on_custom_headers(|headers| {
+    ///     for (name, value) in headers {
+    ///         if name.eq_ignore_ascii_case("X-Session-Id") {
+    ///             tracing::info!("received message for session {}", value);
+    ///         }
+    ///     }
+    /// });
+    /// # drop((service, server));
+    /// # }
+    /// ```
+    pub fn on_custom_headers<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&[(String, String)]) + Send + Sync + 'static,
+    {
+        self.custom_headers = Some(HeaderCallback::new(callback));
+        self
+    }
+
+    /// Registers a `callback` notified whenever garbage is discarded from `stdin`.
+    ///
+    /// When the input is not made of well-formed messages, e.g. because something else writes to
+    /// the same pipe, the decoder discards bytes until the next `Content-Length` header. Each time,
+    /// `callback` is called with a [`Desync`] describing how many bytes were discarded and why.
+    /// Deployments can use this to detect chronically corrupted transports and raise an alert,
+    /// rather than letting requests silently go missing. The same events are also counted in the
+    /// [`ConnectionStats`].
+    ///
+    /// The callback runs on the task reading from `stdin`, so it should return quickly.
+    pub fn on_desync<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Desync) + Send + Sync + 'static,
+    {
+        self.on_desync = Some(DesyncCallback::new(callback));
+        self
+    }
+
+    /// Registers a `callback` deciding what to do when writing to `stdout` fails.
+    ///
+    /// Writes usually fail because the client disconnected, e.g. when the pipe connected to
+    /// `stdout` breaks. For every message which cannot be written, `callback` is called with the
+    /// error and returns the [`OutputErrorPolicy`] to apply. Unless the policy is
+    /// [`OutputErrorPolicy::Continue`], the server then stops and [`Server::serve`] returns
+    /// [`ServeError::OutputError`].
+    ///
+    /// If no callback is registered, [`OutputErrorPolicy::Exit`] is applied.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, OutputErrorPolicy, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::new(stdin, stdout, socket).on_output_error(|err| {
+    ///     tracing::error!("client went away: {}", err);
+    ///     OutputErrorPolicy::Abort
+    /// });
+    /// # drop((service, server));
+    /// # }
+    /// ```
+    pub fn on_output_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&dyn std::error::Error) -> OutputErrorPolicy + Send + Sync + 'static,
+    {
+        self.on_output_error = Some(OutputErrorCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Registers a `callback` which may rewrite or drop every request and notification read from
+    /// `stdin`.
+    ///
+    /// The callback receives each incoming request or notification right after it is decoded and
+    /// before it reaches the service, and returns whether to pass it on with [`Filter::Forward`]
+    /// or discard it with [`Filter::Drop`]. Since dropped messages never reach the service, this
+    /// is a cheap way of shedding floods of unwanted notifications at the edge, such as telemetry
+    /// or noisy `workspace/didChangeWatchedFiles` notifications. Responses to requests sent
+    /// through [`Client`](crate::Client) are not filtered.
+    ///
+    /// The callback runs on the task reading from `stdin`, so it should return quickly.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// use tower_lsp::Filter;
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::new(stdin, stdout, socket).filter_incoming(|req| {
+    ///     match req.method() {
+    ///         "workspace/didChangeWatchedFiles" => Filter::Drop,
+    ///         _ => Filter::Forward,
+    ///     }
+    /// });
+    /// # drop((service, server));
+    /// # }
+    /// ```
+    pub fn filter_incoming<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&mut Request) -> Filter + Send + Sync + 'static,
+    {
+        self.filter_incoming = Some(IncomingCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Registers a `callback` which may inspect and rewrite every message written to `stdout`.
+    ///
+    /// The callback receives each outgoing message, be it a response from the service or a request
+    /// or notification sent through [`Client`](crate::Client), right before it is encoded, and
+    /// returns the message to write in its place. This allows post-processing messages without
+    /// wrapping the service, e.g. to strip fields which a known-buggy client fails to parse, or
+    /// to inject experimental fields.
+    ///
+    /// The callback runs on the task writing to `stdout`, so it should return quickly. Changing
+    /// the ID of a message is possible, but will confuse the client.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// use tower_lsp::jsonrpc::{Message, Response};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::new(stdin, stdout, socket).map_outgoing(|msg| match msg {
+    ///     // This client chokes on the `data` member of error responses.
+    ///     Message::Response(res) => {
+    ///         let (id, result) = res.into_parts();
+    ///         let result = result.map_err(|mut err| {
+    ///             err.data = None;
+    ///             err
+    ///         });
+    ///         Message::Response(Response::from_parts(id, result))
+    ///     }
+    ///     msg => msg,
+    /// });
+    /// # drop((service, server));
+    /// # }
+    /// ```
+    pub fn map_outgoing<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(Message) -> Message + Send + Sync + 'static,
+    {
+        self.map_outgoing = Some(OutgoingCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Skips the UTF-8 validation pass over incoming message bodies.
+    ///
+    /// By default, every JSON body is checked to be valid UTF-8 as a whole before being parsed,
+    /// which is measurable for multi-megabyte payloads. With this option, the raw bytes are handed
+    /// to the JSON parser directly. Invalid UTF-8 within strings is still rejected by the parser,
+    /// but it is reported as a JSON syntax error, so this is only meant for trusted,
+    /// high-throughput transports.
+    pub fn skip_utf8_validation(mut self) -> Self {
+        self.validate_utf8 = false;
+        self
+    }
+
+    /// Reserves the standard output of the process for the protocol while this server is running.
+    ///
+    /// This is meant for servers communicating over standard I/O, where any stray write to
+    /// standard output, such as a debugging `println!`, corrupts the stream of messages. While
+    /// the server is running, the [`print!`](crate::print) and [`println!`](crate::println)
+    /// macros of this crate write to standard error instead, logging a warning the first time so
+    /// that the offending call can be found. Import them wherever the standard macros were used:
+    ///
+    /// ```rust
+    /// use tower_lsp::println;
+    /// ```
+    ///
+    /// Writes bypassing these macros, e.g. through [`std::io::stdout`] or from dependencies,
+    /// cannot be redirected, since that would require swapping file descriptors with `unsafe`
+    /// code. Use [`eprintln!`] or [`tracing`](https://docs.rs/tracing) for diagnostics instead.
+    pub fn protect_stdout(mut self) -> Self {
+        self.protect_stdout = true;
+        self
+    }
+
+    /// Sets the names of the `shutdown` and `exit` methods which end a session to those of
+    /// `methods`, for serving a [`ProtocolService`](crate::protocol::ProtocolService).
+    ///
+    /// If not explicitly specified, this defaults to [`MethodTable::LSP`].
+    pub fn method_table(mut self, methods: MethodTable) -> Self {
+        self.methods = methods;
+        self
+    }
+
+    /// Returns a handle to the traffic counters of this server.
+    ///
+    /// The handle keeps being updated once the server is started, so it can be obtained before
+    /// calling [`Server::serve`] and polled from another task, e.g. to feed a health dashboard.
+    /// See [`ConnectionStats`] for details.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.stats.clone()
+    }
+
     /// Spawns the service with messages read through `stdin` and responses written to `stdout`.
-    pub async fn serve<T>(self, mut service: T)
+    ///
+    /// Resolves to `Ok(())` once the client has sent the `exit` notification following a
+    /// `shutdown` request, or once the server was stopped through a [`ServeHandle`]. Otherwise,
+    /// returns a [`ServeError`] describing why the session ended unexpectedly.
+    pub async fn serve<T>(self, service: T) -> Result<(), ServeError>
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let (serve, _handle) = self.into_future(service);
+        serve.await
+    }
+
+    /// Converts this `Server` into a future which serves `service` until the input stream closes,
+    /// along with a [`ServeHandle`] which can stop it early.
+    ///
+    /// Unlike [`Server::serve`], this allows embedders (e.g. GUI applications or test harnesses)
+    /// to drive the server loop from their own `select!` or shutdown logic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let (serve, handle) = Server::new(stdin, stdout, socket).into_future(service);
+    ///
+    /// // Stop reading from `stdin` as soon as the host application decides to.
+    /// handle.abort();
+    /// assert!(serve.await.is_ok());
+    /// # }
+    /// # }
+    /// ```
+    pub fn into_future<T>(
+        self,
+        mut service: T,
+    ) -> (impl Future<Output = Result<(), ServeError>>, ServeHandle)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let (handle, registrations) = ServeHandle::new(self.stats.clone());
+        let aborted = handle.clone();
+
+        let serve = async move {
+            let (stdin, stdout, mut loopback, settings) = self.into_parts();
+            let _guard = settings.protect_stdout.then(StdoutGuard::new);
+            let mut local_requests = loopback.take_local_requests();
+            let (client_requests, mut client_responses) = loopback.split();
+            futures::pin_mut!(client_requests);
+
+            let io = (stdin, stdout);
+            let client = (client_requests, &mut client_responses);
+            let local = local_requests.as_mut();
+            settings
+                .serve_connection(io, client, local, &mut service, &aborted, registrations)
+                .await
+        };
+
+        (serve, handle)
+    }
+
+    /// Serves `service` like [`Server::serve`], but keeps the session alive across connections.
+    ///
+    /// When the input stream closes, or fails to be read or written, before the client has sent
+    /// the `exit` notification, `reconnect` is called to wait for a new `(input, output)` pair of
+    /// streams, e.g. by accepting the next connection on a TCP listener or by dialing the client
+    /// again. Serving then resumes with the same service, so the state of the language server
+    /// and its [`Client`](crate::Client) survives flaky connections, as is common with embedded
+    /// devices and remote development setups. Returning `None` from `reconnect` gives up and
+    /// resolves to the error which ended the last connection.
+    ///
+    /// Messages sent by the server while no connection is available are queued and written to the
+    /// next connection, while messages which were in flight when the connection dropped are lost.
+    /// The [`OutputErrorPolicy::Exit`] policy, the default, is treated like
+    /// [`OutputErrorPolicy::Abort`], since the client may come back.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # async fn run() -> std::io::Result<()> {
+    /// # #[cfg(feature = "runtime-tokio")]
+    /// # {
+    /// let listener = tokio::net::TcpListener::bind("127.0.0.1:9257").await?;
+    /// let listener = &listener;
+    /// let accept = move || async move {
+    ///     let (stream, _) = listener.accept().await.ok()?;
+    ///     Some(tokio::io::split(stream))
+    /// };
+    ///
+    /// let (input, output) = accept().await.expect("failed to accept connection");
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let server = Server::new(input, output, socket);
+    /// if let Err(err) = server.serve_reconnecting(service, accept).await {
+    ///     eprintln!("language server stopped unexpectedly: {err}");
+    /// }
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn serve_reconnecting<T, F, Fut>(
+        self,
+        mut service: T,
+        mut reconnect: F,
+    ) -> Result<(), ServeError>
     where
         T: Service<Request, Response = Option<Response>> + Send + 'static,
         T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
         T::Future: Send,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Option<(I, O)>>,
     {
-        let (client_requests, mut client_responses) = self.loopback.split();
+        let (stdin, stdout, mut loopback, mut settings) = self.into_parts();
+        settings.reconnecting = true;
+        let _guard = settings.protect_stdout.then(StdoutGuard::new);
+
+        let mut local_requests = loopback.take_local_requests();
+        let (client_requests, mut client_responses) = loopback.split();
+        futures::pin_mut!(client_requests);
+
+        let mut io = (stdin, stdout);
+        loop {
+            let (handle, registrations) = ServeHandle::new(settings.stats.clone());
+            let client = (client_requests.as_mut(), &mut client_responses);
+            let local = local_requests.as_mut();
+            let result = settings
+                .serve_connection(io, client, local, &mut service, &handle, registrations)
+                .await;
+
+            let err = match result {
+                Err(
+                    err @ (ServeError::ClientDisconnected
+                    | ServeError::ProtocolError(_)
+                    | ServeError::OutputError(_)),
+                ) => err,
+                result => return result,
+            };
+
+            // The service may have stopped for good, e.g. after an `exit` notification.
+            if future::poll_fn(|cx| service.poll_ready(cx)).await.is_err() {
+                return Err(err);
+            }
+
+            debug!(
+                "connection lost, waiting for the client to reconnect: {}",
+                err
+            );
+            io = match reconnect().await {
+                Some(io) => io,
+                None => return Err(err),
+            };
+        }
+    }
+
+    fn into_parts(self) -> (I, O, L, Settings) {
+        let settings = Settings {
+            max_concurrency: self.max_concurrency,
+            custom_headers: self.custom_headers,
+            on_desync: self.on_desync,
+            on_output_error: self.on_output_error,
+            filter_incoming: self.filter_incoming,
+            map_outgoing: self.map_outgoing,
+            validate_utf8: self.validate_utf8,
+            protect_stdout: self.protect_stdout,
+            methods: self.methods,
+            stats: self.stats,
+            reconnecting: false,
+        };
+
+        (self.stdin, self.stdout, self.loopback, settings)
+    }
+}
+
+/// Options of a [`Server`] which apply to every connection it serves.
+struct Settings {
+    max_concurrency: usize,
+    custom_headers: Option<HeaderCallback>,
+    on_desync: Option<DesyncCallback>,
+    on_output_error: Option<OutputErrorCallback>,
+    filter_incoming: Option<IncomingCallback>,
+    map_outgoing: Option<OutgoingCallback>,
+    validate_utf8: bool,
+    protect_stdout: bool,
+    methods: MethodTable,
+    stats: ConnectionStats,
+    reconnecting: bool,
+}
+
+impl Settings {
+    /// Serves `service` over the `(stdin, stdout)` pair of streams until the input stream closes.
+    async fn serve_connection<I, O, S, R, T>(
+        &self,
+        (stdin, stdout): (I, O),
+        (client_requests, client_responses): (S, &mut R),
+        mut local_requests: Option<&mut BoxStream<'static, Request>>,
+        service: &mut T,
+        handle: &ServeHandle,
+        registrations: AbortRegistrations,
+    ) -> Result<(), ServeError>
+    where
+        I: Input,
+        O: Output,
+        S: Stream<Item = Request> + Unpin,
+        R: Sink<Response> + Unpin,
+        R::Error: std::error::Error,
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        // Whether the server accepted a `shutdown` request. This is only known once its response
+        // is ready, which may be after the `exit` notification was read. The futures of the
+        // responses borrow it, so it must outlive the channel holding them.
+        let shutdown_accepted = AtomicBool::new(false);
+
         let (client_requests, client_abort) = stream::abortable(client_requests);
         let (mut responses_tx, responses_rx) = mpsc::channel(0);
         let (mut server_tasks_tx, server_tasks_rx) = mpsc::channel(MESSAGE_QUEUE_SIZE);
 
-        let mut framed_stdin = FramedRead::new(self.stdin, LanguageServerCodec::default());
-        let framed_stdout = FramedWrite::new(self.stdout, LanguageServerCodec::default());
+        let codec = match self.custom_headers.clone() {
+            Some(callback) => LanguageServerCodec::with_header_callback(callback),
+            None => LanguageServerCodec::default(),
+        };
+        let codec = codec.with_stats(self.stats.clone());
+        let codec = if self.validate_utf8 {
+            codec
+        } else {
+            codec.without_utf8_validation()
+        };
+        let codec = match self.on_desync.clone() {
+            Some(callback) => codec.with_desync_callback(callback),
+            None => codec,
+        };
+
+        let (output_abort, output_registration) = AbortHandle::new_pair();
+        let framed_stdout = stdout.into_messages(codec.output());
+        let framed_stdin = stdin.into_messages(codec);
+        let framed_stdin = Abortable::new(framed_stdin, output_registration);
+        let framed_stdin = Abortable::new(framed_stdin, registrations.input);
+        futures::pin_mut!(framed_stdin);
 
         let process_server_tasks = server_tasks_rx
             .buffer_unordered(self.max_concurrency)
@@ -120,50 +886,331 @@ where
             .forward(responses_tx.clone().sink_map_err(|_| unreachable!()))
             .map(|_| ());
 
-        let print_output = stream::select(responses_rx, client_requests.map(Message::Request))
-            .map(Ok)
-            .forward(framed_stdout.sink_map_err(|e| error!("failed to encode message: {}", e)))
-            .map(|_| ());
+        let output_error = Mutex::new(None);
+        let on_output_error = &self.on_output_error;
+        let map_outgoing = &self.map_outgoing;
 
+        // Each message is flushed before polling for the next one, which `Client::flush` relies
+        // on to tell when its messages were written. Once writing has failed for good or the
+        // output was closed through the `ServeHandle`, the remaining messages are drained so
+        // that the other tasks can finish.
+        let print_output = async {
+            let messages = stream::select(responses_rx, client_requests.map(Message::Request));
+            futures::pin_mut!(messages, framed_stdout);
+
+            let mut failed = false;
+            let mut open_messages = Abortable::new(messages.as_mut(), registrations.close_output);
+            while let Some(msg) = open_messages.next().await {
+                if failed {
+                    continue;
+                }
+
+                let msg = match map_outgoing {
+                    Some(callback) => (callback.0)(msg),
+                    None => msg,
+                };
+
+                if let Err(err) = framed_stdout.send(msg).await {
+                    error!("failed to encode message: {}", err);
+                    let policy = match on_output_error {
+                        Some(callback) => (callback.0)(&*err),
+                        None => OutputErrorPolicy::Exit,
+                    };
+
+                    if policy != OutputErrorPolicy::Continue {
+                        failed = true;
+                        *output_error.lock().unwrap() = Some((policy, err.to_string()));
+                        output_abort.abort();
+                    }
+                }
+            }
+
+            if open_messages.is_aborted() {
+                if let Err(err) = framed_stdout.close().await {
+                    error!("failed to close output: {}", err);
+                }
+                while messages.next().await.is_some() {}
+            }
+        };
+
+        let shutdown_accepted = &shutdown_accepted;
+        let filter_incoming = &self.filter_incoming;
         let read_input = async {
-            while let Some(msg) = framed_stdin.next().await {
+            let mut decode_error = None;
+
+            let result = loop {
+                let next_msg = future::poll_fn(|cx| {
+                    if let Some(Poll::Ready(Some(req))) =
+                        local_requests.as_mut().map(|s| s.poll_next_unpin(cx))
+                    {
+                        return Poll::Ready(Some(Ok(Message::Request(req))));
+                    }
+
+                    framed_stdin.poll_next_unpin(cx)
+                });
+
+                let msg = match next_msg.await {
+                    Some(msg) => msg,
+                    None if handle.is_aborted() => break Ok(()),
+                    None if output_abort.is_aborted() => {
+                        let (policy, err) = output_error.lock().unwrap().take().unwrap();
+                        if policy == OutputErrorPolicy::Exit && !self.reconnecting {
+                            let exit = Request::build(self.methods.exit).finish();
+                            if future::poll_fn(|cx| service.poll_ready(cx)).await.is_ok() {
+                                let _ = service.call(exit).await;
+                            }
+                        }
+
+                        break Err(ServeError::OutputError(err));
+                    }
+                    None => match decode_error.take() {
+                        Some(err) => break Err(ServeError::ProtocolError(err)),
+                        None => break Err(ServeError::ClientDisconnected),
+                    },
+                };
+
                 match msg {
-                    Ok(Message::Request(req)) => {
+                    Ok(Message::Request(mut req)) => {
+                        decode_error = None;
+
+                        let filter = match filter_incoming {
+                            Some(callback) => (callback.0)(&mut req),
+                            None => Filter::Forward,
+                        };
+
+                        if filter == Filter::Drop {
+                            debug!("dropping incoming message: {}", req.method());
+                            if let Some(id) = req.id().cloned() {
+                                let error = Error::request_failed("message was filtered");
+                                let res = Response::from_error(id, error);
+                                responses_tx.send(Message::Response(res)).await.unwrap();
+                            }
+                            continue;
+                        }
+
                         if let Err(err) = future::poll_fn(|cx| service.poll_ready(cx)).await {
-                            error!("{}", display_sources(err.into().as_ref()));
-                            return;
+                            let err = display_sources(err.into().as_ref());
+                            error!("{}", err);
+                            break Err(ServeError::ProtocolError(err));
                         }
 
-                        let fut = service.call(req).unwrap_or_else(|err| {
-                            error!("{}", display_sources(err.into().as_ref()));
-                            None
-                        });
+                        let exit = req.method() == self.methods.exit && req.id().is_none();
+                        let shutdown = req.method() == self.methods.shutdown && req.id().is_some();
+
+                        let fut = service
+                            .call(req)
+                            .unwrap_or_else(|err| {
+                                error!("{}", display_sources(err.into().as_ref()));
+                                None
+                            })
+                            .inspect(move |res| {
+                                if shutdown && res.as_ref().map_or(false, Response::is_ok) {
+                                    shutdown_accepted.store(true, Ordering::SeqCst);
+                                }
+                            });
 
                         server_tasks_tx.send(fut).await.unwrap();
+
+                        if exit {
+                            break Err(ServeError::Exited);
+                        }
                     }
                     Ok(Message::Response(res)) => {
+                        decode_error = None;
+
                         if let Err(err) = client_responses.send(res).await {
-                            error!("{}", display_sources(&err));
-                            return;
+                            let err = display_sources(&err);
+                            error!("{}", err);
+                            break Err(ServeError::ProtocolError(err));
                         }
                     }
                     Err(err) => {
                         error!("failed to decode message: {}", err);
+                        decode_error = Some(err.to_string());
                         let res = Response::from_error(Id::Null, to_jsonrpc_error(err));
                         responses_tx.send(Message::Response(res)).await.unwrap();
                     }
                 }
-            }
+            };
 
             server_tasks_tx.disconnect();
             responses_tx.disconnect();
             client_abort.abort();
+            result
         };
 
-        join!(print_output, read_input, process_server_tasks);
+        let (_, result, _) = join!(print_output, read_input, process_server_tasks);
+        match result {
+            Err(ServeError::Exited) if shutdown_accepted.load(Ordering::SeqCst) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Describes why a [`Server`] stopped serving before the session ended cleanly.
+///
+/// A session ends cleanly when the client sends the `exit` notification after a `shutdown`
+/// request, as mandated by the specification. Supervisors may use this error to decide whether the
+/// language server should be restarted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ServeError {
+    /// The input stream was closed before the client sent the `exit` notification.
+    ClientDisconnected,
+    /// The input stream could not be decoded, or the service failed irrecoverably.
+    ProtocolError(String),
+    /// The client sent the `exit` notification without a prior `shutdown` request, or the server
+    /// failed the `shutdown` request.
+    Exited,
+    /// Writing to the output stream failed, and the [`OutputErrorPolicy`] stopped the server.
+    OutputError(String),
+}
+
+impl Display for ServeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ServeError::ClientDisconnected => f.write_str("client disconnected before `exit`"),
+            ServeError::ProtocolError(err) => write!(f, "protocol error: {err}"),
+            ServeError::Exited => f.write_str("received `exit` without prior `shutdown` request"),
+            ServeError::OutputError(err) => write!(f, "failed to write to output: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ServeError {}
+
+/// A handle for stopping a [`Server`] started with [`Server::into_future`].
+///
+/// This type is cheap to clone, so it can be handed to any task which needs to stop the server.
+#[derive(Clone, Debug)]
+pub struct ServeHandle {
+    input_abort: AbortHandle,
+    close_output: AbortHandle,
+    stats: ConnectionStats,
+}
+
+impl ServeHandle {
+    fn new(stats: ConnectionStats) -> (Self, AbortRegistrations) {
+        let (input_abort, input) = AbortHandle::new_pair();
+        let (close_output, close_output_registration) = AbortHandle::new_pair();
+        let handle = ServeHandle {
+            input_abort,
+            close_output,
+            stats,
+        };
+
+        let registrations = AbortRegistrations {
+            input,
+            close_output: close_output_registration,
+        };
+
+        (handle, registrations)
+    }
+
+    /// Stops reading incoming messages from the input stream.
+    ///
+    /// Requests which are already being processed are allowed to finish and have their responses
+    /// written to the output stream before the server future resolves.
+    ///
+    /// This is equivalent to [`ServeHandle::abort_input`].
+    pub fn abort(&self) {
+        self.abort_input();
+    }
+
+    /// Stops reading incoming messages from the input stream.
+    ///
+    /// Requests which are already being processed are allowed to finish and have their responses
+    /// written to the output stream before the server future resolves.
+    pub fn abort_input(&self) {
+        self.input_abort.abort();
+    }
+
+    /// Closes the output stream, while the input stream keeps being read.
+    ///
+    /// Pending writes are flushed before the output stream is closed, and every message the server
+    /// would have written afterwards is dropped. This half-close is mostly useful for testing how
+    /// clients and servers cope with a peer which stopped listening.
+    pub fn abort_output(&self) {
+        self.close_output.abort();
+    }
+
+    /// Returns `true` if [`ServeHandle::abort`] or [`ServeHandle::abort_input`] has been called.
+    pub fn is_aborted(&self) -> bool {
+        self.input_abort.is_aborted()
+    }
+
+    /// Returns `true` if [`ServeHandle::abort_output`] has been called.
+    pub fn is_output_aborted(&self) -> bool {
+        self.close_output.is_aborted()
+    }
+
+    /// Returns a handle to the traffic counters of the server.
+    ///
+    /// This is the same handle as returned by [`Server::connection_stats`].
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.stats.clone()
     }
 }
 
+/// Registrations through which a [`ServeHandle`] stops a connection.
+struct AbortRegistrations {
+    input: AbortRegistration,
+    close_output: AbortRegistration,
+}
+
+/// Serves `service` over the `(input, output)` pair of streams until the session ends.
+///
+/// See [`Server::serve`] for a description of the returned value.
+///
+/// This is a shorthand for `Server::new(input, output, socket).serve(service)`, which accepts the
+/// tuple returned from [`LspService::new`](crate::LspService::new) directly.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::{run_until_exit, LanguageServer, LspService};
+/// #
+/// # struct Mock;
+/// #
+/// # #[tower_lsp::async_trait]
+/// # impl LanguageServer for Mock {
+/// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+/// #         Ok(InitializeResult::default())
+/// #     }
+/// #
+/// #     async fn shutdown(&self) -> Result<()> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # async fn run() {
+/// # #[cfg(feature = "runtime-tokio")]
+/// # {
+/// let io = (tokio::io::stdin(), tokio::io::stdout());
+/// if let Err(err) = run_until_exit(LspService::new(|_| Mock), io).await {
+///     eprintln!("language server stopped unexpectedly: {err}");
+/// }
+/// # }
+/// # }
+/// ```
+pub async fn run_until_exit<I, O, L, T>(
+    (service, socket): (T, L),
+    (input, output): (I, O),
+) -> Result<(), ServeError>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+    T: Service<Request, Response = Option<Response>> + Send + 'static,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    T::Future: Send,
+{
+    Server::new(input, output, socket).serve(service).await
+}
+
 fn display_sources(error: &dyn std::error::Error) -> String {
     if let Some(source) = error.source() {
         format!("{}: {}", error, display_sources(source))
@@ -172,17 +1219,9 @@ fn display_sources(error: &dyn std::error::Error) -> String {
     }
 }
 
-#[cfg(feature = "runtime-tokio")]
-fn to_jsonrpc_error(err: ParseError) -> Error {
-    match err {
-        ParseError::Body(err) if err.is_data() => Error::invalid_request(),
-        _ => Error::parse_error(),
-    }
-}
-
-#[cfg(feature = "runtime-agnostic")]
-fn to_jsonrpc_error(err: impl std::error::Error) -> Error {
-    match err.source().and_then(|e| e.downcast_ref()) {
+fn to_jsonrpc_error(err: BoxError) -> Error {
+    let err: &(dyn std::error::Error + 'static) = &*err;
+    match err.downcast_ref().or_else(|| err.source()?.downcast_ref()) {
         Some(ParseError::Body(err)) if err.is_data() => Error::invalid_request(),
         _ => Error::parse_error(),
     }
@@ -249,41 +1288,316 @@ mod tests {
     #[tokio::test(flavor = "current_thread")]
     async fn serves_on_stdio() {
         let (mut stdin, mut stdout) = mock_stdio();
-        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
             .serve(MockService)
             .await;
 
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
         assert_eq!(stdin.position(), 80);
         assert_eq!(stdout, mock_response());
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_from_parts() {
+        let request: Message = REQUEST.parse().unwrap();
+        let malformed = ParseError::Body(serde_json::from_str::<Message>("{").unwrap_err());
+        let input = stream::iter([Ok(request), Err(malformed)]);
+        let (output, written) = mpsc::unbounded();
+
+        let result = Server::from_parts(input, output, MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+
+        assert!(matches!(result, Err(ServeError::ProtocolError(_))));
+        let written: Vec<Message> = written.collect().await;
+        assert_eq!(written.len(), 2);
+        assert!(written.contains(&RESPONSE.parse().unwrap()));
+        let error = Response::from_error(Id::Null, Error::parse_error());
+        assert!(written.contains(&Message::Response(error)));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn interleaves_messages() {
         let socket = MockLoopback(vec![serde_json::from_str(REQUEST).unwrap()]);
 
         let (mut stdin, mut stdout) = mock_stdio();
-        Server::new(&mut stdin, &mut stdout, socket)
+        let result = Server::new(&mut stdin, &mut stdout, socket)
             .serve(MockService)
             .await;
 
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
         assert_eq!(stdin.position(), 80);
         let output: Vec<_> = mock_request().into_iter().chain(mock_response()).collect();
         assert_eq!(stdout, output);
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn resumes_on_reconnect() {
+        let (mut stdin, mut stdout) = mock_stdio();
+
+        let mut input = Vec::new();
+        for msg in [
+            r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ] {
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg).into_bytes());
+        }
+        let (mut next_stdin, mut next_stdout) = (Cursor::new(input), Vec::new());
+
+        let mut connections = vec![(&mut next_stdin, &mut next_stdout)].into_iter();
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve_reconnecting(MockService, || future::ready(connections.next()))
+            .await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(stdout, mock_response());
+        assert_eq!(next_stdout, [mock_response(), mock_response()].concat());
+
+        let (mut stdin, mut stdout) = mock_stdio();
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve_reconnecting(MockService, || future::ready(None))
+            .await;
+
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn stops_when_aborted() {
+        let (stdin, _client_end) = tokio::io::duplex(64);
+        let mut stdout = Vec::new();
+
+        let (serve, handle) =
+            Server::new(stdin, &mut stdout, MockLoopback(vec![])).into_future(MockService);
+        handle.abort();
+        assert_eq!(serve.await, Ok(()));
+
+        assert!(handle.is_aborted());
+        assert!(stdout.is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn keeps_reading_after_output_aborted() {
+        let socket = MockLoopback(vec![serde_json::from_str(REQUEST).unwrap()]);
+
+        let (mut stdin, mut stdout) = mock_stdio();
+        let (serve, handle) = Server::new(&mut stdin, &mut stdout, socket).into_future(MockService);
+        handle.abort_output();
+        assert_eq!(serve.await, Err(ServeError::ClientDisconnected));
+
+        assert!(handle.is_output_aborted());
+        assert!(!handle.is_aborted());
+        assert_eq!(stdin.position(), 80);
+        assert!(stdout.is_empty());
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn handles_invalid_json() {
         let invalid = r#"{"jsonrpc":"2.0","method":"#;
         let message = format!("Content-Length: {}\r\n\r\n{}", invalid.len(), invalid).into_bytes();
         let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
 
-        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
             .serve(MockService)
             .await;
 
+        assert!(matches!(result, Err(ServeError::ProtocolError(_))));
         assert_eq!(stdin.position(), 48);
         let err = r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
         let output = format!("Content-Length: {}\r\n\r\n{}", err.len(), err).into_bytes();
         assert_eq!(stdout, output);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_connection_stats() {
+        let invalid = r#"{"jsonrpc":"2.0","method":"#;
+        let mut stdin = mock_messages(&[REQUEST, invalid]);
+        let mut stdout = Vec::new();
+        let input_len = stdin.get_ref().len() as u64;
+
+        let server = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]));
+        let stats = server.connection_stats();
+        assert_eq!(stats.snapshot(), ConnectionStatsSnapshot::default());
+
+        let result = server.serve(MockService).await;
+        assert!(matches!(result, Err(ServeError::ProtocolError(_))));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.bytes_read, input_len);
+        assert_eq!(snapshot.frames_read, 1);
+        assert_eq!(snapshot.decode_errors, 1);
+        assert_eq!(snapshot.bytes_written, stdout.len() as u64);
+        assert_eq!(snapshot.frames_written, 2);
+        assert!(snapshot.last_read.is_some());
+        assert!(snapshot.last_activity() >= snapshot.last_read);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn filters_incoming_messages() {
+        let noisy = r#"{"jsonrpc":"2.0","method":"telemetry/noise","params":{}}"#;
+        let dropped = r#"{"jsonrpc":"2.0","method":"initialize","params":{},"id":2}"#;
+
+        let mut stdin = mock_messages(&[noisy, dropped, REQUEST]);
+        let mut stdout = Vec::new();
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .filter_incoming(|req| match req.id() {
+                Some(Id::Number(1)) => Filter::Forward,
+                _ => Filter::Drop,
+            })
+            .serve(MockService)
+            .await;
+
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
+        let error = Error::request_failed("message was filtered");
+        let rejected = serde_json::to_string(&Response::from_error(2.into(), error)).unwrap();
+        assert_eq!(stdout, mock_messages(&[&rejected, RESPONSE]).into_inner());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn maps_outgoing_messages() {
+        let (mut stdin, mut stdout) = mock_stdio();
+        let result = Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .map_outgoing(|msg| match msg {
+                Message::Response(res) => {
+                    let (id, _) = res.into_parts();
+                    Message::Response(Response::from_ok(id, serde_json::json!(null)))
+                }
+                msg => msg,
+            })
+            .serve(MockService)
+            .await;
+
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
+        let mapped = r#"{"jsonrpc":"2.0","result":null,"id":1}"#;
+        assert_eq!(stdout, mock_messages(&[mapped]).into_inner());
+    }
+
+    fn mock_messages(messages: &[&str]) -> Cursor<Vec<u8>> {
+        let mut input = Vec::new();
+        for message in messages {
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", message.len(), message).bytes());
+        }
+        Cursor::new(input)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn reports_how_session_ended() {
+        let shutdown = r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#;
+        let exit = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let trailing = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+
+        let mut stdin = mock_messages(&[REQUEST, shutdown, exit, trailing]);
+        let result = Server::new(&mut stdin, Vec::new(), MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+        assert_eq!(result, Ok(()));
+
+        let mut stdin = mock_messages(&[REQUEST, exit]);
+        let result = Server::new(&mut stdin, Vec::new(), MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+        assert_eq!(result, Err(ServeError::Exited));
+
+        // A `shutdown` request which the server failed is not enough for a clean exit.
+        let failing = tower::service_fn(|req: Request| {
+            let res = req
+                .id()
+                .cloned()
+                .map(|id| Response::from_error(id, Error::internal_error()));
+            future::ok::<_, String>(res)
+        });
+        let mut stdin = mock_messages(&[REQUEST, shutdown, exit]);
+        let result = Server::new(&mut stdin, Vec::new(), MockLoopback(vec![]))
+            .serve(failing)
+            .await;
+        assert_eq!(result, Err(ServeError::Exited));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    struct BrokenPipe;
+
+    #[cfg(feature = "runtime-tokio")]
+    impl AsyncWrite for BrokenPipe {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Records the methods of all requests it receives.
+    #[cfg(feature = "runtime-tokio")]
+    #[derive(Clone, Default)]
+    struct RecordingService(Arc<Mutex<Vec<String>>>);
+
+    #[cfg(feature = "runtime-tokio")]
+    impl Service<Request> for RecordingService {
+        type Response = Option<Response>;
+        type Error = String;
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request) -> Self::Future {
+            self.0.lock().unwrap().push(req.method().to_owned());
+            let response = req.id().map(|_| serde_json::from_str(RESPONSE).unwrap());
+            future::ok(response)
+        }
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn exits_when_output_fails() {
+        use tokio::io::AsyncWriteExt;
+
+        let (stdin, mut client_end) = tokio::io::duplex(1024);
+        client_end.write_all(&mock_request()).await.unwrap();
+
+        let service = RecordingService::default();
+        let result = Server::new(stdin, BrokenPipe, MockLoopback(vec![]))
+            .serve(service.clone())
+            .await;
+
+        assert!(matches!(result, Err(ServeError::OutputError(_))));
+        assert_eq!(*service.0.lock().unwrap(), ["initialize", "exit"]);
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn applies_output_error_policy() {
+        let errors = Arc::new(Mutex::new(0));
+        let errors_ = errors.clone();
+
+        let service = RecordingService::default();
+        let stdin = mock_messages(&[REQUEST, REQUEST]);
+        let result = Server::new(stdin, BrokenPipe, MockLoopback(vec![]))
+            .on_output_error(move |_| {
+                *errors_.lock().unwrap() += 1;
+                OutputErrorPolicy::Continue
+            })
+            .serve(service.clone())
+            .await;
+
+        assert_eq!(result, Err(ServeError::ClientDisconnected));
+        assert_eq!(*errors.lock().unwrap(), 2);
+        assert_eq!(*service.0.lock().unwrap(), ["initialize", "initialize"]);
+    }
 }
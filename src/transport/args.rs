@@ -0,0 +1,278 @@
+//! Selection of the client connection from command-line arguments.
+
+use std::io::{self, ErrorKind};
+
+#[cfg(feature = "async-net")]
+use async_net::TcpStream;
+#[cfg(feature = "async-net")]
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime-tokio")]
+use tokio::net::TcpStream;
+
+/// Reading half of a connection to the client, returned by [`from_args`].
+pub type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Writing half of a connection to the client, returned by [`from_args`].
+pub type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Connects to the client through the transport selected by the command-line arguments `args`.
+///
+/// The following arguments, defined by the [LSP specification], are recognized:
+///
+/// * `--stdio`: communicate over standard input and standard output.
+/// * `--socket=PORT` (or `--port=PORT`): connect to TCP port `PORT` on `127.0.0.1`.
+/// * `--pipe=NAME`: connect to the Unix domain socket at path `NAME`, or on Windows, to the named
+///   pipe `NAME`. Named pipes require the `named-pipe` feature.
+///
+/// Per the specification, the client is the one listening on the socket or pipe and the server
+/// connects to it. All other arguments are ignored, so the server is free to define its own. If
+/// none of the above is given, standard I/O is used.
+///
+/// [LSP specification]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#implementationConsiderations
+///
+/// # Runtimes
+///
+/// This function is available with either the default `runtime-tokio` feature, or the `async-net`
+/// feature, which implies `runtime-agnostic`. In the latter case, sockets are provided by the
+/// [`async-net`] crate and standard I/O is moved to a thread pool with [`blocking::Unblock`], so
+/// the returned halves can be polled by any executor, such as `smol` or `async-std`. Named pipes
+/// are not supported with `async-net`.
+///
+/// [`async-net`]: https://docs.rs/async-net
+/// [`blocking::Unblock`]: https://docs.rs/blocking/latest/blocking/struct.Unblock.html
+///
+/// # Errors
+///
+/// Returns an error of kind [`ErrorKind::InvalidInput`] if a port is malformed or more than one
+/// transport is selected, or any error encountered while connecting.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::{LanguageServer, LspService, Server};
+/// #
+/// # struct Backend;
+/// #
+/// # #[tower_lsp::async_trait]
+/// # impl LanguageServer for Backend {
+/// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+/// #         Ok(InitializeResult::default())
+/// #     }
+/// #
+/// #     async fn shutdown(&self) -> Result<()> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # async fn run() -> std::io::Result<()> {
+/// let (read, write) = tower_lsp::transport::from_args(std::env::args().skip(1)).await?;
+///
+/// let (service, socket) = LspService::new(|_| Backend);
+/// Server::new(read, write, socket).serve(service).await;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn from_args<I>(args: I) -> io::Result<(BoxedReader, BoxedWriter)>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    match Endpoint::parse(args)? {
+        Endpoint::Stdio => Ok(stdio()),
+        Endpoint::Socket(port) => {
+            let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+            stream.set_nodelay(true)?;
+            Ok(split(stream))
+        }
+        Endpoint::Pipe(name) => connect_pipe(&name).await,
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+fn stdio() -> (BoxedReader, BoxedWriter) {
+    (Box::new(tokio::io::stdin()), Box::new(tokio::io::stdout()))
+}
+
+#[cfg(feature = "async-net")]
+fn stdio() -> (BoxedReader, BoxedWriter) {
+    let stdin = blocking::Unblock::new(std::io::stdin());
+    let stdout = blocking::Unblock::new(std::io::stdout());
+    (Box::new(stdin), Box::new(stdout))
+}
+
+#[cfg(feature = "runtime-tokio")]
+fn split(stream: TcpStream) -> (BoxedReader, BoxedWriter) {
+    let (read, write) = stream.into_split();
+    (Box::new(read), Box::new(write))
+}
+
+/// Streams from `async-net` are reference-counted handles, so each half is simply a clone.
+#[cfg(feature = "async-net")]
+fn split<S>(stream: S) -> (BoxedReader, BoxedWriter)
+where
+    S: AsyncRead + AsyncWrite + Clone + Send + Unpin + 'static,
+{
+    (Box::new(stream.clone()), Box::new(stream))
+}
+
+#[cfg(all(unix, feature = "runtime-tokio"))]
+async fn connect_pipe(name: &str) -> io::Result<(BoxedReader, BoxedWriter)> {
+    let stream = tokio::net::UnixStream::connect(name).await?;
+    let (read, write) = stream.into_split();
+    Ok((Box::new(read), Box::new(write)))
+}
+
+#[cfg(all(unix, feature = "async-net"))]
+async fn connect_pipe(name: &str) -> io::Result<(BoxedReader, BoxedWriter)> {
+    let stream = async_net::unix::UnixStream::connect(name).await?;
+    Ok(split(stream))
+}
+
+#[cfg(all(windows, feature = "named-pipe"))]
+async fn connect_pipe(name: &str) -> io::Result<(BoxedReader, BoxedWriter)> {
+    let client = super::named_pipe::connect(name).await?;
+    let (read, write) = tokio::io::split(client);
+    Ok((Box::new(read), Box::new(write)))
+}
+
+#[cfg(not(any(unix, all(windows, feature = "named-pipe"))))]
+async fn connect_pipe(name: &str) -> io::Result<(BoxedReader, BoxedWriter)> {
+    let reason = if cfg!(all(windows, feature = "async-net")) {
+        "named pipes require the `runtime-tokio` feature"
+    } else if cfg!(windows) {
+        "the `named-pipe` feature is disabled"
+    } else {
+        "unsupported on this platform"
+    };
+
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        format!("cannot connect to pipe `{}`: {}", name, reason),
+    ))
+}
+
+/// Transport selected on the command line.
+#[derive(Debug, Eq, PartialEq)]
+enum Endpoint {
+    Stdio,
+    Socket(u16),
+    Pipe(String),
+}
+
+impl Endpoint {
+    fn parse<I>(args: I) -> io::Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut selected = None;
+
+        for arg in args {
+            let arg = arg.as_ref();
+            let endpoint = if arg == "--stdio" {
+                Endpoint::Stdio
+            } else if let Some(port) = strip_value(arg, "--socket").or(strip_value(arg, "--port")) {
+                let port = port.parse().map_err(|_| {
+                    let message = format!("invalid port in `{}`", arg);
+                    io::Error::new(ErrorKind::InvalidInput, message)
+                })?;
+                Endpoint::Socket(port)
+            } else if let Some(name) = strip_value(arg, "--pipe") {
+                Endpoint::Pipe(name.to_owned())
+            } else {
+                continue;
+            };
+
+            match selected {
+                Some(ref previous) if *previous != endpoint => {
+                    let message =
+                        format!("conflicting transports {:?} and {:?}", previous, endpoint);
+                    return Err(io::Error::new(ErrorKind::InvalidInput, message));
+                }
+                _ => selected = Some(endpoint),
+            }
+        }
+
+        Ok(selected.unwrap_or(Endpoint::Stdio))
+    }
+}
+
+/// Returns the value of `arg` if it has the form `{name}={value}`.
+fn strip_value<'a>(arg: &'a str, name: &str) -> Option<&'a str> {
+    arg.strip_prefix(name)?.strip_prefix('=')
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async-net")]
+    use async_net::TcpListener;
+    #[cfg(feature = "async-net")]
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[cfg(feature = "runtime-tokio")]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    #[cfg(feature = "runtime-tokio")]
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn parses_transport_args() {
+        let parse = |args: &[&str]| Endpoint::parse(args).map_err(|e| e.kind());
+
+        assert_eq!(parse(&[]), Ok(Endpoint::Stdio));
+        assert_eq!(parse(&["--verbose", "--stdio"]), Ok(Endpoint::Stdio));
+        assert_eq!(parse(&["--socket=5007"]), Ok(Endpoint::Socket(5007)));
+        assert_eq!(
+            parse(&["--port=5007", "--clientProcessId=1"]),
+            Ok(Endpoint::Socket(5007))
+        );
+        assert_eq!(
+            parse(&["--pipe=/tmp/lsp.sock"]),
+            Ok(Endpoint::Pipe("/tmp/lsp.sock".into()))
+        );
+        assert_eq!(parse(&["--socket", "--pipes=x"]), Ok(Endpoint::Stdio));
+
+        assert_eq!(parse(&["--socket=http"]), Err(ErrorKind::InvalidInput));
+        assert_eq!(
+            parse(&["--stdio", "--socket=1"]),
+            Err(ErrorKind::InvalidInput)
+        );
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn connects_to_socket() {
+        ping_pong().await;
+    }
+
+    #[cfg(feature = "async-net")]
+    #[test]
+    fn connects_to_socket() {
+        smol::block_on(ping_pong());
+    }
+
+    async fn ping_pong() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let arg = format!("--socket={}", port);
+
+        let (connected, accepted) = futures::join!(from_args([arg]), listener.accept());
+        let (mut read, mut write) = connected.unwrap();
+        let (mut client, _) = accepted.unwrap();
+
+        write.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        client.write_all(b"pong").await.unwrap();
+        read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+}
@@ -0,0 +1,574 @@
+//! Experimental gateway exposing a language server over HTTP.
+//!
+//! Web dashboards and other non-editor tooling may want to query a language server, e.g. for
+//! hover or symbol information, without speaking the `Content-Length` framed protocol used over
+//! stdio. [`HttpGateway`] serves an `LspService` on a [`TcpListener`] instead:
+//!
+//! * `POST /rpc` takes a single JSON-RPC message as its body, sent as `application/json`.
+//!   Requests are answered with the JSON-RPC response as `200 OK`, while notifications and
+//!   responses to server-to-client requests are acknowledged with `204 No Content`.
+//! * `GET /events` opens a [server-sent events] stream, on which every message sent by the server
+//!   to the client is delivered as a `data:` event. Requests sent by the server are answered by
+//!   posting the response to `/rpc`.
+//!
+//! The HTTP client drives the session like any LSP client would, starting with the `initialize`
+//! request. Connections are kept alive between requests, unless asked otherwise.
+//!
+//! Requests carrying an `Origin` header are rejected with `403 Forbidden`, unless the origin was
+//! allowed with [`HttpGateway::allow_origin`]. Together with the `Content-Type` requirement, which
+//! forces browsers to send a CORS preflight first, this keeps arbitrary web pages from driving a
+//! local language server.
+//!
+//! This module is only available with the `http-gateway` feature enabled. It is experimental and
+//! may change in backwards-incompatible ways.
+//!
+//! [server-sent events]: https://html.spec.whatwg.org/multipage/server-sent-events.html
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use tokio::net::TcpListener;
+//! use tower_lsp::transport::http::HttpGateway;
+//! use tower_lsp::LspService;
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::LanguageServer;
+//! #
+//! # struct Backend;
+//! #
+//! # #[tower_lsp::async_trait]
+//! # impl LanguageServer for Backend {
+//! #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//! #         Ok(InitializeResult::default())
+//! #     }
+//! #
+//! #     async fn shutdown(&self) -> Result<()> {
+//! #         Ok(())
+//! #     }
+//! # }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:9257").await?;
+//! let (service, socket) = LspService::new(|_| Backend);
+//! HttpGateway::new()
+//!     .allow_origin("http://localhost:3000")
+//!     .serve(listener, service, socket)
+//!     .await
+//! # }
+//! ```
+
+use std::fmt::Write as _;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc;
+use futures::lock::Mutex as AsyncMutex;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tower::{Service, ServiceExt};
+use tracing::{debug, warn};
+
+use crate::jsonrpc::{Error, Id, Message, Request, Response};
+use crate::service::{ClientSocket, RequestStream, ResponseSink};
+
+const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+const MAX_HEADERS_SIZE: usize = 16 * 1024;
+const MAX_HEADERS: usize = 32;
+/// Number of server-to-client messages queued for each event stream before it is disconnected.
+const EVENT_QUEUE_SIZE: usize = 100;
+
+/// Serves an `LspService` over HTTP.
+///
+/// To construct an `HttpGateway`, refer to [`HttpGateway::new`].
+#[derive(Clone, Debug)]
+pub struct HttpGateway {
+    allow_origin: Option<String>,
+    max_body_size: usize,
+}
+
+impl HttpGateway {
+    /// Creates a new `HttpGateway` with the default settings.
+    pub fn new() -> Self {
+        HttpGateway {
+            allow_origin: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Allows web pages served from `origin` to call the gateway, through [CORS].
+    ///
+    /// Cross-origin requests are not allowed by default, and are rejected with `403 Forbidden`.
+    ///
+    /// [CORS]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
+    pub fn allow_origin<O: Into<String>>(mut self, origin: O) -> Self {
+        self.allow_origin = Some(origin.into());
+        self
+    }
+
+    /// Sets the maximum size of request bodies, in bytes.
+    ///
+    /// Larger requests are rejected with `413 Payload Too Large`. Defaults to 4 MiB.
+    pub fn max_body_size(mut self, max_bytes: usize) -> Self {
+        self.max_body_size = max_bytes;
+        self
+    }
+
+    /// Accepts connections on `listener`, forwarding messages to and from `service`, until
+    /// accepting a connection fails.
+    ///
+    /// `socket` is the channel returned alongside `service` by
+    /// [`LspService::new`](crate::LspService::new).
+    pub async fn serve<T>(
+        self,
+        listener: TcpListener,
+        service: T,
+        socket: ClientSocket,
+    ) -> io::Result<()>
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Future: Send,
+    {
+        let (requests, responses) = socket.split();
+        let shared = Arc::new(Shared {
+            service: AsyncMutex::new(service),
+            responses: AsyncMutex::new(responses),
+            subscribers: Mutex::new(Vec::new()),
+            config: self,
+        });
+
+        tokio::spawn(fan_out(requests, shared.clone()));
+
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection(stream, shared).await {
+                    debug!("HTTP connection from {} failed: {}", addr, err);
+                }
+            });
+        }
+    }
+}
+
+impl Default for HttpGateway {
+    fn default() -> Self {
+        HttpGateway::new()
+    }
+}
+
+/// State shared by all connections to the gateway.
+struct Shared<T> {
+    service: AsyncMutex<T>,
+    responses: AsyncMutex<ResponseSink>,
+    subscribers: Mutex<Vec<mpsc::Sender<Arc<str>>>>,
+    config: HttpGateway,
+}
+
+/// Delivers every server-to-client message to all connected event streams.
+async fn fan_out<T>(mut requests: RequestStream, shared: Arc<Shared<T>>) {
+    while let Some(req) = requests.next().await {
+        let message: Arc<str> = req.to_string().into();
+        let mut subscribers = shared.subscribers.lock().unwrap();
+        subscribers.retain_mut(|tx| match tx.try_send(message.clone()) {
+            Ok(()) => true,
+            Err(err) if err.is_full() => {
+                warn!("event stream is not keeping up, disconnecting it");
+                false
+            }
+            Err(_) => false,
+        });
+    }
+}
+
+async fn handle_connection<T>(mut stream: TcpStream, shared: Arc<Shared<T>>) -> io::Result<()>
+where
+    T: Service<Request, Response = Option<Response>> + Send + 'static,
+    T::Future: Send,
+{
+    let cors = shared.config.allow_origin.as_deref();
+    let mut buf = Vec::new();
+
+    loop {
+        let req = match read_request(&mut stream, &mut buf, shared.config.max_body_size).await? {
+            Some(Ok(req)) => req,
+            Some(Err(status)) => {
+                return write_response(&mut stream, status, None, cors, true).await
+            }
+            None => return Ok(()),
+        };
+
+        // The event stream takes over the connection until it is closed.
+        let close = req.close || (req.method == "GET" && req.path == "/events");
+        if req.origin.is_some() && req.origin.as_deref() != cors {
+            debug!("rejecting request from disallowed origin {:?}", req.origin);
+            write_response(&mut stream, "403 Forbidden", None, cors, close).await?;
+        } else {
+            route(&mut stream, req, &shared).await?;
+        }
+
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+/// Dispatches `req` to the handler for its method and path.
+async fn route<T>(stream: &mut TcpStream, req: HttpRequest, shared: &Shared<T>) -> io::Result<()>
+where
+    T: Service<Request, Response = Option<Response>> + Send + 'static,
+    T::Future: Send,
+{
+    let cors = shared.config.allow_origin.as_deref();
+    let close = req.close;
+    match (req.method.as_str(), req.path.as_str()) {
+        ("POST", "/rpc") if !is_json(req.content_type.as_deref()) => {
+            write_response(stream, "415 Unsupported Media Type", None, cors, close).await
+        }
+        ("POST", "/rpc") => {
+            let (status, body) = handle_rpc(&req.body, shared).await;
+            let body = body.as_ref().map(|b| ("application/json", b.as_bytes()));
+            write_response(stream, status, body, cors, close).await
+        }
+        ("GET", "/events") => stream_events(stream, shared).await,
+        ("OPTIONS", _) => write_response(stream, "204 No Content", None, cors, close).await,
+        (_, "/rpc") | (_, "/events") => {
+            write_response(stream, "405 Method Not Allowed", None, cors, close).await
+        }
+        _ => write_response(stream, "404 Not Found", None, cors, close).await,
+    }
+}
+
+/// Returns whether `content_type` is `application/json`, ignoring any parameters.
+fn is_json(content_type: Option<&str>) -> bool {
+    content_type
+        .and_then(|value| value.split(';').next())
+        .map_or(false, |mime| {
+            mime.trim().eq_ignore_ascii_case("application/json")
+        })
+}
+
+/// Forwards the JSON-RPC message `body` to the service, returning the HTTP status and body.
+async fn handle_rpc<T>(body: &[u8], shared: &Shared<T>) -> (&'static str, Option<String>)
+where
+    T: Service<Request, Response = Option<Response>>,
+{
+    let message = match serde_json::from_slice(body) {
+        Ok(message) => message,
+        Err(err) => {
+            debug!("invalid JSON-RPC message: {}", err);
+            let res = Response::from_error(Id::Null, Error::parse_error());
+            return ("400 Bad Request", serde_json::to_string(&res).ok());
+        }
+    };
+
+    let req = match message {
+        Message::Request(req) => req,
+        Message::Response(res) => {
+            return match shared.responses.lock().await.send(res).await {
+                Ok(()) => ("204 No Content", None),
+                Err(_) => ("503 Service Unavailable", None),
+            };
+        }
+    };
+
+    // Only hold the lock while submitting the request, so requests are handled concurrently.
+    let fut = {
+        let mut service = shared.service.lock().await;
+        match service.ready().await {
+            Ok(service) => service.call(req),
+            Err(_) => return ("503 Service Unavailable", None),
+        }
+    };
+
+    match fut.await {
+        Ok(Some(res)) => ("200 OK", serde_json::to_string(&res).ok()),
+        Ok(None) => ("204 No Content", None),
+        Err(_) => ("503 Service Unavailable", None),
+    }
+}
+
+/// Streams server-to-client messages as server-sent events, until the connection closes.
+async fn stream_events<T>(stream: &mut TcpStream, shared: &Shared<T>) -> io::Result<()> {
+    let (tx, mut rx) = mpsc::channel(EVENT_QUEUE_SIZE);
+    shared.subscribers.lock().unwrap().push(tx);
+
+    let mut head = String::from("HTTP/1.1 200 OK\r\n");
+    head.push_str("Content-Type: text/event-stream\r\nCache-Control: no-cache\r\n");
+    push_cors(&mut head, shared.config.allow_origin.as_deref());
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+
+    while let Some(message) = rx.next().await {
+        stream
+            .write_all(format!("data: {}\n\n", message).as_bytes())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// An HTTP request, with its body.
+struct HttpRequest {
+    method: String,
+    path: String,
+    origin: Option<String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+    close: bool,
+}
+
+/// Reads the next request from `stream`, returning `None` once the connection is closed.
+///
+/// `buf` holds the bytes read past the end of the previous request. Malformed requests are
+/// returned as `Some(Err(status))`, after which the connection should be closed.
+async fn read_request(
+    stream: &mut TcpStream,
+    buf: &mut Vec<u8>,
+    max_body_size: usize,
+) -> io::Result<Option<Result<HttpRequest, &'static str>>> {
+    let (head_len, mut req, content_len) = loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        match parsed.parse(buf) {
+            Ok(httparse::Status::Complete(head_len)) => {
+                let mut content_len = 0;
+                let mut close = parsed.version == Some(0);
+                let mut origin = None;
+                let mut content_type = None;
+                for header in parsed.headers.iter() {
+                    let value = String::from_utf8_lossy(header.value);
+                    if header.name.eq_ignore_ascii_case("content-length") {
+                        match value.trim().parse() {
+                            Ok(len) => content_len = len,
+                            Err(_) => return Ok(Some(Err("400 Bad Request"))),
+                        }
+                    } else if header.name.eq_ignore_ascii_case("connection") {
+                        close = value.trim().eq_ignore_ascii_case("close");
+                    } else if header.name.eq_ignore_ascii_case("origin") {
+                        origin = Some(value.trim().to_owned());
+                    } else if header.name.eq_ignore_ascii_case("content-type") {
+                        content_type = Some(value.trim().to_owned());
+                    }
+                }
+
+                let req = HttpRequest {
+                    method: parsed.method.unwrap_or_default().to_owned(),
+                    path: parsed.path.unwrap_or_default().to_owned(),
+                    origin,
+                    content_type,
+                    body: Vec::new(),
+                    close,
+                };
+                break (head_len, req, content_len);
+            }
+            Ok(httparse::Status::Partial) if buf.len() > MAX_HEADERS_SIZE => {
+                return Ok(Some(Err("431 Request Header Fields Too Large")));
+            }
+            Ok(httparse::Status::Partial) => {}
+            Err(_) => return Ok(Some(Err("400 Bad Request"))),
+        }
+
+        if read_more(stream, buf).await? == 0 {
+            return match buf.is_empty() {
+                true => Ok(None),
+                false => Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+        }
+    };
+
+    if content_len > max_body_size {
+        return Ok(Some(Err("413 Payload Too Large")));
+    }
+
+    while buf.len() < head_len + content_len {
+        if read_more(stream, buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    req.body = buf[head_len..head_len + content_len].to_vec();
+    buf.drain(..head_len + content_len);
+    Ok(Some(Ok(req)))
+}
+
+async fn read_more(stream: &mut TcpStream, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut chunk = [0; 4096];
+    let n = stream.read(&mut chunk).await?;
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(n)
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    body: Option<(&str, &[u8])>,
+    cors: Option<&str>,
+    close: bool,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    match body {
+        Some((content_type, body)) => {
+            let _ = write!(head, "Content-Type: {}\r\n", content_type);
+            let _ = write!(head, "Content-Length: {}\r\n", body.len());
+        }
+        None if status.starts_with("204") => {}
+        None => head.push_str("Content-Length: 0\r\n"),
+    }
+    push_cors(&mut head, cors);
+    if close {
+        head.push_str("Connection: close\r\n");
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    if let Some((_, body)) = body {
+        stream.write_all(body).await?;
+    }
+    stream.flush().await
+}
+
+fn push_cors(head: &mut String, origin: Option<&str>) {
+    if let Some(origin) = origin {
+        let _ = write!(head, "Access-Control-Allow-Origin: {}\r\n", origin);
+        head.push_str("Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n");
+        head.push_str("Access-Control-Allow-Headers: Content-Type\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    use super::*;
+    use crate::jsonrpc::Result;
+    use crate::{Client, LanguageServer, LspService};
+
+    struct Mock {
+        client: Client,
+    }
+
+    #[crate::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn initialized(&self, _: InitializedParams) {
+            self.client.log_message(MessageType::INFO, "ready").await;
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn start() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (service, socket) = LspService::new(|client| Mock { client });
+        tokio::spawn(HttpGateway::new().serve(listener, service, socket));
+        addr
+    }
+
+    async fn post(addr: std::net::SocketAddr, body: &str) -> String {
+        let req = format!(
+            "POST /rpc HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        send(addr, &req).await
+    }
+
+    async fn send(addr: std::net::SocketAddr, req: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(req.as_bytes()).await.unwrap();
+
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+        res
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_requests_and_events() {
+        let addr = start().await;
+
+        let mut events = TcpStream::connect(addr).await.unwrap();
+        events
+            .write_all(b"GET /events HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut events = BufReader::new(events);
+        let mut line = String::new();
+        events.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+
+        let initialize =
+            r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#;
+        let res = post(addr, initialize).await;
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(res.ends_with(r#"{"jsonrpc":"2.0","result":{"capabilities":{}},"id":1}"#));
+
+        let initialized = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let res = post(addr, initialized).await;
+        assert!(res.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", res);
+
+        loop {
+            line.clear();
+            events.read_line(&mut line).await.unwrap();
+            if let Some(data) = line.strip_prefix("data: ") {
+                let message: Request = data.trim_end().parse().unwrap();
+                assert_eq!(message.method(), "window/logMessage");
+                break;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_invalid_requests() {
+        let addr = start().await;
+
+        let res = post(addr, "not json").await;
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+        assert!(res.contains(r#""code":-32700"#));
+
+        let res = send(addr, "GET /missing HTTP/1.1\r\nConnection: close\r\n\r\n").await;
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", res);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_cross_site_requests() {
+        let addr = start().await;
+        let body = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+
+        let req = format!(
+            "POST /rpc HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let res = send(addr, &req).await;
+        assert!(
+            res.starts_with("HTTP/1.1 415 Unsupported Media Type\r\n"),
+            "{}",
+            res
+        );
+
+        let req = format!(
+            "POST /rpc HTTP/1.1\r\nOrigin: http://evil.example\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let res = send(addr, &req).await;
+        assert!(res.starts_with("HTTP/1.1 403 Forbidden\r\n"), "{}", res);
+
+        let req = "GET /events HTTP/1.1\r\nOrigin: http://evil.example\r\n\r\n";
+        let res = send(addr, req).await;
+        assert!(res.starts_with("HTTP/1.1 403 Forbidden\r\n"), "{}", res);
+    }
+}
@@ -0,0 +1,694 @@
+//! Serving several language server sessions over a single connection.
+
+#[cfg(feature = "runtime-agnostic")]
+use async_codec_lite::{Decoder, Encoder, FramedRead, FramedWrite};
+#[cfg(feature = "runtime-agnostic")]
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(feature = "runtime-tokio")]
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytes::{BufMut, BytesMut};
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture, Either};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Sink, SinkExt, StreamExt};
+use serde_json::Value;
+use tower::Service;
+use tracing::{debug, error, warn};
+
+use super::{Loopback, ServeError, Server};
+use crate::codec::{HeaderCallback, LanguageServerCodec, ParseError};
+use crate::jsonrpc::{Error, Id, Request, Response};
+
+/// Name of the header carrying the session ID, unless overridden.
+const DEFAULT_SESSION_HEADER: &str = "X-Session-Id";
+
+/// Maximum number of concurrent sessions, unless overridden.
+const DEFAULT_MAX_SESSIONS: usize = 64;
+
+/// Number of bytes buffered in the input of a session before reading from `stdin` pauses.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Number of messages queued for `stdout` before the sessions writing them are paused.
+const OUTPUT_QUEUE_SIZE: usize = 64;
+
+/// Serves several independent language server sessions over a single pair of streams.
+///
+/// Some remote and multi-root setups tunnel several logical LSP sessions through one physical
+/// transport, tagging every message with a session ID header:
+///
+/// ```text
+/// Content-Length: 52\r\n
+/// X-Session-Id: workspace-a\r\n
+/// \r\n
+/// {"jsonrpc":"2.0","method":"initialize","params":{},"id":1}
+/// ```
+///
+/// The first message of an unknown session creates a new service for it, which is then served by
+/// its own [`Server`], with its own state, concurrency limit and `initialize`/`exit` lifecycle.
+/// Messages sent by that service carry the same header, so the client can route them back.
+/// Messages without the header belong to a default session, whose replies carry no header either,
+/// so regular clients keep working.
+///
+/// Once a session has exited, its next message creates a fresh session with the same ID. Bodies
+/// are always encoded as JSON.
+///
+/// Reading from `stdin` pauses while a session is not keeping up with its input, and sessions
+/// pause while `stdout` is not keeping up with their output, so memory usage stays bounded. The
+/// number of concurrent sessions is limited as well, see [`Multiplexer::max_sessions`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::{LanguageServer, LspService};
+/// # use tower_lsp::transport::Multiplexer;
+/// #
+/// # struct Backend { session: Option<String> }
+/// #
+/// # #[tower_lsp::async_trait]
+/// # impl LanguageServer for Backend {
+/// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+/// #         Ok(InitializeResult::default())
+/// #     }
+/// #
+/// #     async fn shutdown(&self) -> Result<()> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # async fn run() {
+/// # #[cfg(feature = "runtime-tokio")]
+/// # {
+/// let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+/// Multiplexer::new(stdin, stdout)
+///     .serve(|session| {
+///         let session = session.map(str::to_owned);
+///         LspService::new(move |_| Backend { session })
+///     })
+///     .await
+///     .unwrap();
+/// # }
+/// # }
+/// ```
+pub struct Multiplexer<I, O> {
+    stdin: I,
+    stdout: O,
+    session_header: Cow<'static, str>,
+    max_sessions: usize,
+}
+
+impl<I, O> Multiplexer<I, O>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+{
+    /// Creates a new `Multiplexer` with the given `stdin` and `stdout` handles.
+    pub fn new(stdin: I, stdout: O) -> Self {
+        Multiplexer {
+            stdin,
+            stdout,
+            session_header: Cow::Borrowed(DEFAULT_SESSION_HEADER),
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        }
+    }
+
+    /// Sets the name of the header carrying the session ID of each message.
+    ///
+    /// Header names are compared case-insensitively. If not explicitly specified, the header is
+    /// named `X-Session-Id`.
+    pub fn session_header<N>(mut self, name: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.session_header = name.into();
+        self
+    }
+
+    /// Sets the maximum number of sessions served at the same time.
+    ///
+    /// Messages creating a session past this limit are discarded, with requests being answered
+    /// with a [`RequestFailed`](crate::jsonrpc::ErrorCode::RequestFailed) error. The default
+    /// session counts towards the limit too. If not explicitly specified, up to 64 sessions are
+    /// served at the same time.
+    pub fn max_sessions(mut self, max: usize) -> Self {
+        self.max_sessions = max;
+        self
+    }
+
+    /// Serves every session with the service returned by `new_session` for its ID.
+    ///
+    /// `new_session` is called with `None` for the default session, made of the messages which do
+    /// not carry a session ID. Resolves to `Ok(())` once the input stream closes and every session
+    /// has stopped, or returns [`ServeError::OutputError`] if writing to `stdout` fails. Sessions
+    /// ending on their own, e.g. after the `exit` notification, do not affect the others.
+    pub async fn serve<F, T, L>(self, mut new_session: F) -> Result<(), ServeError>
+    where
+        F: FnMut(Option<&str>) -> (T, L),
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+        L: Loopback + Send + 'static,
+        L::RequestStream: Send,
+        L::ResponseSink: Send,
+        <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+    {
+        let (mut messages_tx, messages_rx) = mpsc::channel(OUTPUT_QUEUE_SIZE);
+
+        let codec = SessionCodec::new(self.session_header.clone());
+        let write_output = messages_rx
+            .map(Ok)
+            .forward(FramedWrite::new(self.stdout, codec));
+
+        let session_header = self.session_header;
+        let max_sessions = self.max_sessions;
+        let read_input = async move {
+            let codec = SessionCodec::new(session_header);
+            let mut framed_stdin = FramedRead::new(self.stdin, codec).fuse();
+            let mut sessions: HashMap<Option<String>, PipeWriter> = HashMap::new();
+            let mut tasks = FuturesUnordered::new();
+            let mut stopped = Vec::new();
+
+            loop {
+                futures::select! {
+                    msg = framed_stdin.next() => match msg {
+                        Some(Ok((session, body))) => {
+                            if !sessions.contains_key(&session) && sessions.len() >= max_sessions {
+                                warn!("too many sessions, rejecting session {:?}", session);
+                                if let Some(res) = reject(&body) {
+                                    let _ = messages_tx.send((session, res)).await;
+                                }
+                                continue;
+                            }
+
+                            let mut frame = BytesMut::new();
+                            if let Err(err) = encode_frame(None, &body, &mut frame) {
+                                error!("failed to encode message: {}", err);
+                                continue;
+                            }
+
+                            let input = sessions.entry(session.clone()).or_insert_with(|| {
+                                debug!("starting session {:?}", session);
+                                let (service, socket) = new_session(session.as_deref());
+                                let (input, task) =
+                                    serve_session(session, service, socket, messages_tx.clone());
+                                tasks.push(task);
+                                input
+                            });
+
+                            // Keep driving the sessions while waiting for room in this input, as
+                            // the session may only make room once it has been polled again.
+                            let result = {
+                                let push = input.push(&frame);
+                                futures::pin_mut!(push);
+                                loop {
+                                    match future::select(push.as_mut(), tasks.next()).await {
+                                        Either::Left((result, _)) => break result,
+                                        Either::Right((Some(id), _)) => stopped.push(id),
+                                        Either::Right((None, _)) => break push.as_mut().await,
+                                    }
+                                }
+                            };
+
+                            if let Err(err) = result {
+                                debug!("session stopped before reading its input: {}", err);
+                            }
+
+                            for session in stopped.drain(..) {
+                                debug!("session {:?} stopped", session);
+                                sessions.remove(&session);
+                            }
+                        }
+                        Some(Err(err)) => error!("failed to decode message: {}", err),
+                        None => break,
+                    },
+                    session = tasks.select_next_some() => {
+                        debug!("session {:?} stopped", session);
+                        sessions.remove(&session);
+                    }
+                }
+            }
+
+            // Closing the inputs stops the remaining sessions, which flush their last messages.
+            sessions.clear();
+            while tasks.next().await.is_some() {}
+        };
+
+        futures::pin_mut!(read_input, write_output);
+        match future::select(read_input, write_output).await {
+            Either::Left(((), write_output)) => write_output.await,
+            Either::Right((result, _)) => result,
+        }
+        .map_err(|err| ServeError::OutputError(err.to_string()))
+    }
+}
+
+impl<I, O> Debug for Multiplexer<I, O> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(Multiplexer))
+            .field("session_header", &self.session_header)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Starts serving `service` for `session`, returning the input of the session along with a task
+/// which resolves to the session ID once the session has stopped.
+fn serve_session<T, L>(
+    session: Option<String>,
+    service: T,
+    socket: L,
+    messages_tx: mpsc::Sender<(Option<String>, Value)>,
+) -> (PipeWriter, BoxFuture<'static, Option<String>>)
+where
+    T: Service<Request, Response = Option<Response>> + Send + 'static,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    T::Future: Send,
+    L: Loopback + Send + 'static,
+    L::RequestStream: Send,
+    L::ResponseSink: Send,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    let (input_tx, input_rx) = pipe();
+    let (output_tx, output_rx) = pipe();
+
+    let task = async move {
+        let serve = Server::new(input_rx, output_tx, socket).serve(service);
+
+        // Messages written by the server are decoded again, so they can be tagged with the session
+        // ID. Failing to forward them means the output is gone, and so should the server soon.
+        let tagged = session.clone();
+        let messages = FramedRead::new(output_rx, LanguageServerCodec::<Value>::default())
+            .filter_map(|msg| future::ready(msg.ok()))
+            .map(move |msg| Ok((tagged.clone(), msg)));
+        let forward = messages.forward(messages_tx.sink_map_err(|_| ()));
+
+        let (result, _) = futures::join!(serve, forward);
+        match result {
+            Ok(()) | Err(ServeError::ClientDisconnected) => {}
+            Err(err) => warn!("session {:?} stopped: {}", session, err),
+        }
+
+        session
+    };
+
+    (input_tx, task.boxed())
+}
+
+/// Returns the error response to the message with `body`, if it is a request.
+fn reject(body: &Value) -> Option<Value> {
+    body.get("method")?;
+    let id: Id = serde_json::from_value(body.get("id")?.clone()).ok()?;
+    let error = Error::request_failed("too many sessions");
+    serde_json::to_value(Response::from_error(id, error)).ok()
+}
+
+/// Encodes a message with `body`, tagged with the `session` header name and ID if provided.
+fn encode_frame(
+    session: Option<(&str, &str)>,
+    body: &Value,
+    dst: &mut BytesMut,
+) -> Result<(), ParseError> {
+    let body = serde_json::to_vec(body)?;
+
+    let mut writer = dst.writer();
+    write!(writer, "Content-Length: {}\r\n", body.len())?;
+    if let Some((name, id)) = session {
+        write!(writer, "{name}: {id}\r\n")?;
+    }
+    writer.write_all(b"\r\n")?;
+    writer.write_all(&body)?;
+
+    Ok(())
+}
+
+/// Codec for messages tagged with the ID of the session they belong to.
+struct SessionCodec {
+    inner: LanguageServerCodec<Value>,
+    session_header: Cow<'static, str>,
+    /// Session ID of the message being decoded, set by the header callback of `inner`.
+    session: Arc<Mutex<Option<String>>>,
+}
+
+impl SessionCodec {
+    fn new(session_header: Cow<'static, str>) -> Self {
+        let session = Arc::new(Mutex::new(None));
+
+        let name = session_header.clone();
+        let found = session.clone();
+        let callback = HeaderCallback::new(move |headers: &[(String, String)]| {
+            let id = headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(&name));
+            *found.lock().unwrap() = id.map(|(_, id)| id.clone());
+        });
+
+        SessionCodec {
+            inner: LanguageServerCodec::with_header_callback(callback),
+            session_header,
+            session,
+        }
+    }
+
+    fn decode_message(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<(Option<String>, Value)>, ParseError> {
+        let body = self.inner.decode(src)?;
+        let session = self.session.lock().unwrap().take();
+        Ok(body.map(|body| (session, body)))
+    }
+
+    fn encode_message(
+        &mut self,
+        (session, body): (Option<String>, Value),
+        dst: &mut BytesMut,
+    ) -> Result<(), ParseError> {
+        let session = session.as_deref().map(|id| (&*self.session_header, id));
+        encode_frame(session, &body, dst)
+    }
+}
+
+#[cfg(feature = "runtime-agnostic")]
+impl Decoder for SessionCodec {
+    type Item = (Option<String>, Value);
+    type Error = ParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_message(src)
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Decoder for SessionCodec {
+    type Item = (Option<String>, Value);
+    type Error = ParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_message(src)
+    }
+}
+
+#[cfg(feature = "runtime-agnostic")]
+impl Encoder for SessionCodec {
+    type Item = (Option<String>, Value);
+    type Error = ParseError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_message(item, dst)
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Encoder<(Option<String>, Value)> for SessionCodec {
+    type Error = ParseError;
+
+    fn encode(
+        &mut self,
+        item: (Option<String>, Value),
+        dst: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        self.encode_message(item, dst)
+    }
+}
+
+/// Buffer shared by the two ends of an in-memory pipe.
+#[derive(Default)]
+struct Pipe {
+    buffer: BytesMut,
+    closed: bool,
+    reader_closed: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+/// Creates an in-memory pipe buffering up to [`PIPE_CAPACITY`] bytes, which is closed once the
+/// writing end is dropped.
+fn pipe() -> (PipeWriter, PipeReader) {
+    let pipe = Arc::new(Mutex::new(Pipe::default()));
+    (PipeWriter(pipe.clone()), PipeReader(pipe))
+}
+
+/// Writing end of a [`pipe`], which waits for the reader while the pipe is full.
+struct PipeWriter(Arc<Mutex<Pipe>>);
+
+impl PipeWriter {
+    /// Moves as many bytes of `src` as fit into the pipe, returning how many were moved.
+    fn poll_push(&self, cx: &mut Context, src: &[u8]) -> Poll<io::Result<usize>> {
+        let mut pipe = self.0.lock().unwrap();
+        if pipe.reader_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let room = PIPE_CAPACITY.saturating_sub(pipe.buffer.len());
+        let len = room.min(src.len());
+        if len == 0 && !src.is_empty() {
+            pipe.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        pipe.buffer.extend_from_slice(&src[..len]);
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(len))
+    }
+
+    /// Writes all of `bytes` to the pipe, waiting for the reader to make room as needed.
+    async fn push(&self, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            let len = future::poll_fn(|cx| self.poll_push(cx, bytes)).await?;
+            bytes = &bytes[len..];
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut pipe = self.0.lock().unwrap();
+        pipe.closed = true;
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_push(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    #[cfg(feature = "runtime-agnostic")]
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Reading end of a [`pipe`].
+struct PipeReader(Arc<Mutex<Pipe>>);
+
+impl PipeReader {
+    /// Moves as many buffered bytes as possible to `dst`, returning how many were moved.
+    fn poll_take(&self, cx: &mut Context, dst: &mut [u8]) -> Poll<usize> {
+        let mut pipe = self.0.lock().unwrap();
+        if pipe.buffer.is_empty() && !pipe.closed {
+            pipe.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = pipe.buffer.len().min(dst.len());
+        dst[..len].copy_from_slice(&pipe.buffer.split_to(len));
+        if let Some(waker) = pipe.writer.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(len)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut pipe = self.0.lock().unwrap();
+        pipe.reader_closed = true;
+        if let Some(waker) = pipe.writer.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(feature = "runtime-agnostic")]
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_take(cx, buf).map(Ok)
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let len = futures::ready!(self.poll_take(cx, buf.initialize_unfilled()));
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "runtime-agnostic")]
+    use futures::io::Cursor;
+    #[cfg(feature = "runtime-tokio")]
+    use std::io::Cursor;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::jsonrpc::Result;
+    use crate::lsp_types::*;
+    use crate::{LanguageServer, LspService};
+
+    struct Mock {
+        session: Option<String>,
+    }
+
+    #[crate::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult {
+                server_info: Some(ServerInfo {
+                    name: self.session.clone().unwrap_or_default(),
+                    version: None,
+                }),
+                ..InitializeResult::default()
+            })
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn encode(session: Option<&str>, body: Value) -> Vec<u8> {
+        let mut frame = BytesMut::new();
+        let session = session.map(|id| (DEFAULT_SESSION_HEADER, id));
+        encode_frame(session, &body, &mut frame).unwrap();
+        frame.to_vec()
+    }
+
+    fn initialize(id: i64) -> Value {
+        json!({"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":id})
+    }
+
+    fn initialized(name: &str, id: i64) -> Value {
+        json!({"jsonrpc":"2.0","result":{"capabilities":{},"serverInfo":{"name":name}},"id":id})
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn demultiplexes_sessions() {
+        let input = [
+            encode(Some("a"), initialize(1)),
+            encode(Some("b"), initialize(1)),
+            encode(None, initialize(2)),
+        ]
+        .concat();
+
+        let (mut stdin, mut stdout) = (Cursor::new(input), Vec::new());
+        let mut started = Vec::new();
+        let result = Multiplexer::new(&mut stdin, &mut stdout)
+            .serve(|session| {
+                started.push(session.map(str::to_owned));
+                let session = session.map(str::to_owned);
+                LspService::new(move |_| Mock { session })
+            })
+            .await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(started, [Some("a".into()), Some("b".into()), None]);
+
+        let mut output = BytesMut::from(&stdout[..]);
+        let mut codec = SessionCodec::new(DEFAULT_SESSION_HEADER.into());
+        let mut messages = Vec::new();
+        while let Some(msg) = codec.decode_message(&mut output).unwrap() {
+            messages.push(msg);
+        }
+
+        messages.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            messages,
+            [
+                (None, initialized("", 2)),
+                (Some("a".into()), initialized("a", 1)),
+                (Some("b".into()), initialized("b", 1)),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_sessions_past_limit() {
+        let input = [
+            encode(Some("a"), initialize(1)),
+            encode(Some("b"), initialize(1)),
+        ]
+        .concat();
+
+        let (mut stdin, mut stdout) = (Cursor::new(input), Vec::new());
+        let result = Multiplexer::new(&mut stdin, &mut stdout)
+            .max_sessions(1)
+            .serve(|session| {
+                let session = session.map(str::to_owned);
+                LspService::new(move |_| Mock { session })
+            })
+            .await;
+
+        assert_eq!(result, Ok(()));
+
+        let mut output = BytesMut::from(&stdout[..]);
+        let mut codec = SessionCodec::new(DEFAULT_SESSION_HEADER.into());
+        let mut messages = Vec::new();
+        while let Some(msg) = codec.decode_message(&mut output).unwrap() {
+            messages.push(msg);
+        }
+
+        messages.sort_by(|a, b| a.0.cmp(&b.0));
+        let rejected = json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32803, "message": "too many sessions" },
+            "id": 1
+        });
+        assert_eq!(
+            messages,
+            [
+                (Some("a".into()), initialized("a", 1)),
+                (Some("b".into()), rejected),
+            ]
+        );
+    }
+}
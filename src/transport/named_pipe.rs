@@ -0,0 +1,136 @@
+//! Connecting to clients listening on Windows named pipes.
+//!
+//! On Windows, clients launching a server with `--pipe=NAME` create the named pipe `NAME` (e.g.
+//! `\\.\pipe\lsp-1234`) and wait for the server to connect to it. The pipe may not exist yet when
+//! the server starts, and every instance of it may be busy for a short while, so [`connect`]
+//! retries with exponential backoff instead of failing right away.
+//!
+//! This module is only available on Windows with the `named-pipe` feature enabled.
+//! [`from_args`](super::from_args) uses it to handle the `--pipe` argument.
+
+use std::ffi::OsStr;
+use std::io;
+use std::time::Duration;
+
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient};
+use tokio::time::{self, Instant};
+use tracing::debug;
+
+const ERROR_FILE_NOT_FOUND: i32 = 2;
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Retry schedule used when the named pipe is missing or busy.
+///
+/// The delay between attempts starts at `initial_delay` and doubles after every failed attempt,
+/// up to `max_delay`. Once `timeout` has elapsed since the first attempt, the last error is
+/// returned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    timeout: Duration,
+}
+
+impl Backoff {
+    /// Creates a new retry schedule.
+    pub const fn new(initial_delay: Duration, max_delay: Duration, timeout: Duration) -> Self {
+        Backoff {
+            initial_delay,
+            max_delay,
+            timeout,
+        }
+    }
+}
+
+impl Default for Backoff {
+    /// Starts retrying after 10 ms, waits at most 1 s between attempts, and gives up after 10 s.
+    fn default() -> Self {
+        Backoff::new(
+            Duration::from_millis(10),
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+        )
+    }
+}
+
+/// Connects to the named pipe `name`, retrying with the default [`Backoff`].
+pub async fn connect(name: impl AsRef<OsStr>) -> io::Result<NamedPipeClient> {
+    connect_with_backoff(name, Backoff::default()).await
+}
+
+/// Connects to the named pipe `name`, retrying according to `backoff`.
+///
+/// Only attempts failing because the pipe does not exist yet or all of its instances are busy are
+/// retried. Any other error is returned immediately.
+pub async fn connect_with_backoff(
+    name: impl AsRef<OsStr>,
+    backoff: Backoff,
+) -> io::Result<NamedPipeClient> {
+    let name = name.as_ref();
+    let deadline = Instant::now() + backoff.timeout;
+    let mut delay = backoff.initial_delay;
+
+    loop {
+        match ClientOptions::new().open(name) {
+            Ok(client) => return Ok(client),
+            Err(err) if is_transient(&err) && Instant::now() + delay < deadline => {
+                debug!("named pipe {:?} unavailable, retrying: {}", name, err);
+            }
+            Err(err) => return Err(err),
+        }
+
+        time::sleep(delay).await;
+        delay = (delay * 2).min(backoff.max_delay);
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_FILE_NOT_FOUND) | Some(ERROR_PIPE_BUSY)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn waits_for_pipe_to_be_created() {
+        let name = format!(r"\\.\pipe\tower-lsp-test-{}", std::process::id());
+
+        let client = tokio::spawn({
+            let name = name.clone();
+            async move { connect(name).await }
+        });
+
+        time::sleep(Duration::from_millis(50)).await;
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&name)
+            .unwrap();
+        server.connect().await.unwrap();
+
+        let mut client = client.await.unwrap().unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn gives_up_after_timeout() {
+        let name = format!(r"\\.\pipe\tower-lsp-missing-{}", std::process::id());
+        let backoff = Backoff::new(
+            Duration::from_millis(1),
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+        );
+
+        let err = connect_with_backoff(name, backoff).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(ERROR_FILE_NOT_FOUND));
+    }
+}
@@ -0,0 +1,157 @@
+//! Halves of a connection which are read and written independently.
+
+#[cfg(feature = "runtime-agnostic")]
+use async_codec_lite::{FramedRead, FramedWrite};
+#[cfg(feature = "runtime-agnostic")]
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime-tokio")]
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::ErrInto;
+use futures::{Sink, Stream, TryStreamExt};
+
+use crate::codec::LanguageServerCodec;
+use crate::jsonrpc::Message;
+
+pub(super) type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Incoming half of a connection served by a [`Server`](super::Server).
+///
+/// This is implemented for byte streams, which are decoded with the codec of the server, and for
+/// [`MessageInput`], which yields messages decoded elsewhere.
+pub trait Input {
+    /// Stream of the messages read from this half.
+    type Stream: Stream<Item = Result<Message, BoxError>>;
+
+    /// Turns this half into a stream of messages, decoding bytes with `codec` if needed.
+    fn into_messages(self, codec: LanguageServerCodec<Message>) -> Self::Stream;
+}
+
+/// Outgoing half of a connection served by a [`Server`](super::Server).
+///
+/// This is implemented for byte sinks, which are encoded with the codec of the server, and for
+/// [`MessageOutput`], which accepts messages to be encoded elsewhere.
+pub trait Output {
+    /// Sink of the messages written to this half.
+    type Sink: Sink<Message, Error = BoxError>;
+
+    /// Turns this half into a sink of messages, encoding them with `codec` if needed.
+    fn into_messages(self, codec: LanguageServerCodec<Message>) -> Self::Sink;
+}
+
+impl<R: AsyncRead + Unpin> Input for R {
+    type Stream = ErrInto<FramedRead<R, LanguageServerCodec<Message>>, BoxError>;
+
+    fn into_messages(self, codec: LanguageServerCodec<Message>) -> Self::Stream {
+        FramedRead::new(self, codec).err_into()
+    }
+}
+
+impl<W: AsyncWrite> Output for W {
+    type Sink = BoxErrors<FramedWrite<W, LanguageServerCodec<Message>>>;
+
+    fn into_messages(self, codec: LanguageServerCodec<Message>) -> Self::Sink {
+        BoxErrors(Box::pin(FramedWrite::new(self, codec)))
+    }
+}
+
+/// Incoming half of a connection yielding already decoded messages.
+///
+/// This is created by [`Server::from_parts`](super::Server::from_parts), and may be returned
+/// directly by the `reconnect` callback of
+/// [`Server::serve_reconnecting`](super::Server::serve_reconnecting).
+#[derive(Debug)]
+pub struct MessageInput<S>(S);
+
+impl<S> MessageInput<S> {
+    /// Wraps `stream`, which yields the messages received from the client.
+    pub fn new(stream: S) -> Self {
+        MessageInput(stream)
+    }
+
+    /// Returns the wrapped stream.
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S, E> Input for MessageInput<S>
+where
+    S: Stream<Item = Result<Message, E>>,
+    E: Into<BoxError>,
+{
+    type Stream = ErrInto<S, BoxError>;
+
+    fn into_messages(self, _: LanguageServerCodec<Message>) -> Self::Stream {
+        self.0.err_into()
+    }
+}
+
+/// Outgoing half of a connection accepting messages to be encoded elsewhere.
+///
+/// This is created by [`Server::from_parts`](super::Server::from_parts), and may be returned
+/// directly by the `reconnect` callback of
+/// [`Server::serve_reconnecting`](super::Server::serve_reconnecting).
+#[derive(Debug)]
+pub struct MessageOutput<K>(K);
+
+impl<K> MessageOutput<K> {
+    /// Wraps `sink`, which receives the messages sent to the client.
+    pub fn new(sink: K) -> Self {
+        MessageOutput(sink)
+    }
+
+    /// Returns the wrapped sink.
+    pub fn into_inner(self) -> K {
+        self.0
+    }
+}
+
+impl<K> Output for MessageOutput<K>
+where
+    K: Sink<Message>,
+    K::Error: Into<BoxError>,
+{
+    type Sink = BoxErrors<K>;
+
+    fn into_messages(self, _: LanguageServerCodec<Message>) -> Self::Sink {
+        BoxErrors(Box::pin(self.0))
+    }
+}
+
+/// Sink converting the errors of the inner sink into [`BoxError`]s.
+///
+/// Unlike [`SinkExt::sink_err_into`](futures::SinkExt::sink_err_into), this keeps working after
+/// an error, which [`OutputErrorPolicy::Continue`](super::OutputErrorPolicy::Continue) relies on.
+#[derive(Debug)]
+pub struct BoxErrors<K>(Pin<Box<K>>);
+
+impl<K> Sink<Message> for BoxErrors<K>
+where
+    K: Sink<Message>,
+    K::Error: Into<BoxError>,
+{
+    type Error = BoxError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.0.as_mut().poll_ready(cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        self.0.as_mut().start_send(item).map_err(Into::into)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.0.as_mut().poll_flush(cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.0.as_mut().poll_close(cx).map_err(Into::into)
+    }
+}
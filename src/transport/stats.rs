@@ -0,0 +1,179 @@
+//! Counters describing the traffic of a [`Server`](super::Server) connection.
+
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Default)]
+struct Counters {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    frames_read: AtomicU64,
+    frames_written: AtomicU64,
+    decode_errors: AtomicU64,
+    desyncs: AtomicU64,
+    skipped_bytes: AtomicU64,
+    /// Milliseconds since the Unix epoch, or `0` if nothing was read yet.
+    last_read: AtomicU64,
+    /// Milliseconds since the Unix epoch, or `0` if nothing was written yet.
+    last_write: AtomicU64,
+}
+
+/// Shared handle to the traffic counters of a [`Server`](super::Server).
+///
+/// This handle is returned by [`Server::connection_stats`](super::Server::connection_stats) and
+/// [`ServeHandle::connection_stats`](super::ServeHandle::connection_stats). It is cheap to clone
+/// and keeps reflecting the traffic of the connection while the server is running, so it can be
+/// polled by health checks or exported to dashboards.
+///
+/// Incoming bytes are counted once they have been processed by the decoder, including any garbage
+/// skipped between messages. Outgoing bytes are counted once a message has been encoded, just
+/// before it is written to the output stream.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionStats(Arc<Counters>);
+
+impl ConnectionStats {
+    /// Returns a copy of the current counters.
+    pub fn snapshot(&self) -> ConnectionStatsSnapshot {
+        let counters = &self.0;
+        ConnectionStatsSnapshot {
+            bytes_read: counters.bytes_read.load(Ordering::Relaxed),
+            bytes_written: counters.bytes_written.load(Ordering::Relaxed),
+            frames_read: counters.frames_read.load(Ordering::Relaxed),
+            frames_written: counters.frames_written.load(Ordering::Relaxed),
+            decode_errors: counters.decode_errors.load(Ordering::Relaxed),
+            desyncs: counters.desyncs.load(Ordering::Relaxed),
+            skipped_bytes: counters.skipped_bytes.load(Ordering::Relaxed),
+            last_read: to_time(counters.last_read.load(Ordering::Relaxed)),
+            last_write: to_time(counters.last_write.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Records `consumed` input bytes processed by a single decoder call returning `result`.
+    pub(crate) fn record_read<T, E>(&self, consumed: usize, result: &Result<Option<T>, E>) {
+        let counters = &self.0;
+        if consumed > 0 {
+            counters
+                .bytes_read
+                .fetch_add(consumed as u64, Ordering::Relaxed);
+            counters.last_read.store(now(), Ordering::Relaxed);
+        }
+
+        match result {
+            Ok(Some(_)) => {
+                counters.frames_read.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(None) => {}
+            Err(_) => {
+                counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Records that the decoder discarded garbage to find the next message.
+    pub(crate) fn record_desync(&self, desync: &Desync) {
+        let counters = &self.0;
+        counters.desyncs.fetch_add(1, Ordering::Relaxed);
+        counters
+            .skipped_bytes
+            .fetch_add(desync.skipped_bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records an encoded frame of `len` bytes.
+    pub(crate) fn record_write(&self, len: usize) {
+        let counters = &self.0;
+        counters
+            .bytes_written
+            .fetch_add(len as u64, Ordering::Relaxed);
+        counters.frames_written.fetch_add(1, Ordering::Relaxed);
+        counters.last_write.store(now(), Ordering::Relaxed);
+    }
+}
+
+fn now() -> u64 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH);
+    elapsed.map_or(0, |d| d.as_millis() as u64).max(1)
+}
+
+fn to_time(millis: u64) -> Option<SystemTime> {
+    match millis {
+        0 => None,
+        millis => Some(UNIX_EPOCH + Duration::from_millis(millis)),
+    }
+}
+
+/// A point-in-time copy of the traffic counters of a [`Server`](super::Server).
+///
+/// This struct is created by [`ConnectionStats::snapshot`]. See its documentation for more.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ConnectionStatsSnapshot {
+    /// Number of bytes read from the input stream.
+    pub bytes_read: u64,
+    /// Number of bytes written to the output stream.
+    pub bytes_written: u64,
+    /// Number of messages successfully decoded from the input stream.
+    pub frames_read: u64,
+    /// Number of messages written to the output stream.
+    pub frames_written: u64,
+    /// Number of incoming messages which could not be decoded.
+    pub decode_errors: u64,
+    /// Number of times the decoder lost track of message boundaries and skipped input to find the
+    /// next message. See [`Desync`] for details.
+    pub desyncs: u64,
+    /// Number of input bytes discarded while resynchronizing, which are included in `bytes_read`.
+    pub skipped_bytes: u64,
+    /// Time at which input was last read, with millisecond precision.
+    pub last_read: Option<SystemTime>,
+    /// Time at which output was last written, with millisecond precision.
+    pub last_write: Option<SystemTime>,
+}
+
+impl ConnectionStatsSnapshot {
+    /// Returns the time at which any traffic was last seen on the connection, in either direction.
+    pub fn last_activity(&self) -> Option<SystemTime> {
+        self.last_read.max(self.last_write)
+    }
+}
+
+/// Garbage discarded by the decoder of a [`Server`](super::Server) to find the next message.
+///
+/// The decoder loses track of message boundaries when the input stream carries something other
+/// than well-formed messages, typically because the server or one of its dependencies printed to
+/// `stdout`. It then discards input up to the next `Content-Length` header. This struct is passed
+/// to the callback registered with [`Server::on_desync`](super::Server::on_desync), and is also
+/// counted in [`ConnectionStatsSnapshot::desyncs`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Desync {
+    /// Number of bytes discarded.
+    pub skipped_bytes: usize,
+    /// Why the discarded bytes could not be decoded.
+    pub reason: DesyncReason,
+}
+
+/// Reason for which the decoder discarded input, as reported by [`Desync`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DesyncReason {
+    /// The header section is malformed.
+    MalformedHeaders,
+    /// The header section exceeds the maximum supported size.
+    HeadersTooLarge,
+    /// The header section lacks the required `Content-Length` header.
+    MissingContentLength,
+    /// The `Content-Length` or `Content-Type` header has an invalid value.
+    InvalidHeaderValue,
+}
+
+impl Display for DesyncReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DesyncReason::MalformedHeaders => f.write_str("malformed headers"),
+            DesyncReason::HeadersTooLarge => f.write_str("headers too large"),
+            DesyncReason::MissingContentLength => f.write_str("missing `Content-Length` header"),
+            DesyncReason::InvalidHeaderValue => f.write_str("invalid header value"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Protection of the standard output stream while it carries the protocol.
+//!
+//! When a server communicates over standard I/O, every byte written to the process's standard
+//! output must belong to a framed message. A stray `println!`, whether for debugging or from a
+//! dependency, corrupts the stream, and clients typically report it with an unhelpful parse error
+//! or silently drop the connection.
+//!
+//! Redirecting the process's standard output would require manipulating file descriptors, which
+//! this crate cannot do without `unsafe` code. Instead, the [`print!`](crate::print) and
+//! [`println!`](crate::println) macros of this crate shadow the standard ones, and write to
+//! standard error while a [`Server`](super::Server) protecting standard output is running.
+
+use std::fmt::Arguments;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use tracing::warn;
+
+/// Number of servers currently protecting standard output.
+static PROTECTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a redirected write was already reported.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` if standard output is reserved for the protocol by a running server.
+///
+/// See [`Server::protect_stdout`](super::Server::protect_stdout) for details.
+pub fn is_stdout_protected() -> bool {
+    PROTECTED.load(Ordering::Acquire) > 0
+}
+
+/// Reserves standard output for the protocol until dropped.
+#[derive(Debug)]
+pub(crate) struct StdoutGuard(());
+
+impl StdoutGuard {
+    pub(crate) fn new() -> Self {
+        PROTECTED.fetch_add(1, Ordering::AcqRel);
+        StdoutGuard(())
+    }
+}
+
+impl Drop for StdoutGuard {
+    fn drop(&mut self) {
+        PROTECTED.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: Arguments, location: &'static str) {
+    if !is_stdout_protected() {
+        io::stdout()
+            .write_fmt(args)
+            .expect("failed printing to stdout");
+        return;
+    }
+
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "`print!` called at {} while standard output carries the protocol, writing to \
+             standard error instead",
+            location
+        );
+    }
+
+    io::stderr()
+        .write_fmt(args)
+        .expect("failed printing to stderr");
+}
+
+/// Prints to standard output, or to standard error while it carries the protocol.
+///
+/// This is a drop-in replacement for [`std::print!`], which can be brought into scope with
+/// `use tower_lsp::print;` to shadow the standard macro. See [`println!`](crate::println).
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::transport::_print(
+            ::std::format_args!($($arg)*),
+            ::std::concat!(::std::file!(), ":", ::std::line!()),
+        )
+    };
+}
+
+/// Prints to standard output with a newline, or to standard error while it carries the protocol.
+///
+/// This is a drop-in replacement for [`std::println!`]. Standard output is reserved for the
+/// protocol by a [`Server`] configured with [`Server::protect_stdout`], and while such a server
+/// is running, this macro writes to standard error instead, logging a warning the first time.
+///
+/// [`Server`]: crate::Server
+/// [`Server::protect_stdout`]: crate::Server::protect_stdout
+///
+/// # Examples
+///
+/// Importing the macro shadows [`std::println!`] in the importing module:
+///
+/// ```rust
+/// use tower_lsp::println;
+///
+/// println!("indexed {} files", 42);
+/// ```
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::transport::_print(
+            ::std::format_args!("{}\n", ::std::format_args!($($arg)*)),
+            ::std::concat!(::std::file!(), ":", ::std::line!()),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protects_while_guarded() {
+        assert!(!is_stdout_protected());
+
+        let first = StdoutGuard::new();
+        let second = StdoutGuard::new();
+        assert!(is_stdout_protected());
+
+        drop(first);
+        assert!(is_stdout_protected());
+        drop(second);
+        assert!(!is_stdout_protected());
+    }
+}
@@ -1,28 +1,114 @@
 //! Internal procedural macros for [`tower-lsp`](https://docs.rs/tower-lsp).
 //!
-//! This crate should not be used directly.
+//! This crate should not be used directly, except by forks and wrappers of `tower-lsp` which need
+//! to point the generated code at their own crate, see the `crate` keys of [`macro@rpc`],
+//! [`macro@dap`], [`macro@jsonrpc_protocol`], [`LspExtension`] and [`TelemetryEvent`].
 
 extern crate proc_macro;
 
+use std::collections::HashMap;
+
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, FnArg, ItemTrait, LitStr, ReturnType, TraitItem};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, FnArg, ItemTrait, LitInt, LitStr, ReturnType,
+    TraitItem,
+};
 
 /// Macro for generating LSP server implementation from [`lsp-types`](https://docs.rs/lsp-types).
 ///
 /// This procedural macro annotates the `tower_lsp::LanguageServer` trait and generates a
 /// corresponding `register_lsp_methods()` function which registers all the methods on that trait
-/// as RPC handlers.
+/// as RPC handlers. Each method of the trait must carry an `#[rpc(name = "...")]` attribute with
+/// its JSON-RPC method name.
+///
+/// A method can also be registered under additional, usually deprecated, names with one or more
+/// `alias` keys, e.g. `#[rpc(name = "textDocument/foo", alias = "textDocument/oldFoo")]`. Messages
+/// sent under an alias are handled exactly like those sent under the primary name.
+///
+/// The number of concurrent executions of a request handler can be limited by default with the
+/// `max_concurrency` key, optionally followed by the `latest_wins` flag, e.g.
+/// `#[rpc(name = "textDocument/semanticTokens/full", max_concurrency = 1, latest_wins)]`. These
+/// limits are listed in the generated `CONCURRENCY_LIMITS` constant.
+///
+/// Extra middleware can be applied to a method with the `layer` key, holding the path of a
+/// function taking no arguments and returning a `tower::Layer`, e.g.
+/// `#[rpc(name = "textDocument/hover", layer = "crate::cache::CacheLayer::new")]`. The layer
+/// wraps the handler inside the lifecycle middleware, so it only sees the messages which reach
+/// the handler. Since the router is generated in a private submodule, the path is resolved from
+/// there, and should start with `crate`, `super` or a crate name.
+///
+/// Methods without an `#[rpc]` attribute or a `name`, unknown keys, names or aliases used twice or
+/// shadowing the built-in `$/cancelRequest`, `$/setTrace` and `exit` handlers, methods taking more
+/// than one parameter besides `&self`, and parameters not implementing `serde::Deserialize` are
+/// all reported as compile errors pointing at the offending tokens.
+///
+/// The generated code refers to the crate defining the trait through the path given by the
+/// optional `crate` key, e.g. `#[rpc(crate = "::my_fork")]`, which defaults to `crate`. Since the
+/// router is generated in a private submodule, the path must not start with `self` or `super`.
+/// The crate must provide the following items, with the same signatures as in `tower-lsp`:
+///
+/// * `lsp_types`, a re-export of the `lsp-types` crate;
+/// * `jsonrpc::{Result, Router}`;
+/// * `service::{layers, Client, ExitedError, Pending, RequestContext, ServerState}`.
+///
+/// The `serde_json` and `tower` crates must also be dependencies of the crate invoking the macro.
 #[proc_macro_attribute]
 pub fn rpc(attr: TokenStream, item: TokenStream) -> TokenStream {
-    // Attribute will be parsed later in `parse_method_calls()`.
-    if !attr.is_empty() {
+    // Method attributes are parsed later in `parse_method_calls()`.
+    let mut krate = None;
+    let mut is_method = false;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            let path = parse_crate_path(meta.value()?.parse()?)?;
+            let first = &path.segments[0].ident;
+            if path.leading_colon.is_none() && (first == "self" || first == "super") {
+                let msg = "expected a path starting with `crate` or a crate name";
+                return Err(syn::Error::new_spanned(path, msg));
+            }
+            krate = Some(path);
+            Ok(())
+        } else if meta.path.is_ident("name") || meta.path.is_ident("alias") {
+            let _: LitStr = meta.value()?.parse()?;
+            is_method = true;
+            Ok(())
+        } else if meta.path.is_ident("max_concurrency") {
+            let _: LitInt = meta.value()?.parse()?;
+            is_method = true;
+            Ok(())
+        } else if meta.path.is_ident("latest_wins") {
+            is_method = true;
+            Ok(())
+        } else if meta.path.is_ident("layer") {
+            parse_layer_path(meta.value()?.parse()?)?;
+            is_method = true;
+            Ok(())
+        } else {
+            Err(meta.error(
+                "expected `crate`, `name`, `alias`, `max_concurrency`, `latest_wins` or `layer` \
+                 identifier in `#[rpc]`",
+            ))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    if is_method {
         return item;
     }
 
+    let krate = krate.unwrap_or_else(|| syn::parse_quote!(crate));
     let lang_server_trait = parse_macro_input!(item as ItemTrait);
-    let method_calls = parse_method_calls(&lang_server_trait);
-    let req_types_and_router_fn = gen_server_router(&lang_server_trait.ident, &method_calls);
+    let method_calls = match parse_method_calls(&lang_server_trait) {
+        Ok(method_calls) => method_calls,
+        Err(err) => {
+            // The trait is kept, so that the error is not buried under errors at its uses.
+            let err = err.to_compile_error();
+            return quote!(#lang_server_trait #err).into();
+        }
+    };
+    let req_types_and_router_fn =
+        gen_server_router(&krate, &lang_server_trait.ident, &method_calls);
 
     let tokens = quote! {
         #lang_server_trait
@@ -34,13 +120,21 @@ pub fn rpc(attr: TokenStream, item: TokenStream) -> TokenStream {
 
 struct MethodCall<'a> {
     rpc_name: String,
+    aliases: Vec<String>,
+    max_concurrency: Option<usize>,
+    latest_wins: bool,
+    layer: Option<syn::Path>,
     handler_name: &'a syn::Ident,
     params: Option<&'a syn::Type>,
     result: Option<&'a syn::Type>,
 }
 
-fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
+/// Methods handled by the service itself, rather than by the trait.
+const BUILTIN_METHODS: [&str; 3] = ["$/cancelRequest", "$/setTrace", "exit"];
+
+fn parse_method_calls(lang_server_trait: &ItemTrait) -> syn::Result<Vec<MethodCall>> {
     let mut calls = Vec::new();
+    let mut names: HashMap<String, LitStr> = HashMap::new();
 
     for item in &lang_server_trait.items {
         let method = match item {
@@ -52,19 +146,75 @@ fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
             .attrs
             .iter()
             .find(|attr| attr.meta.path().is_ident("rpc"))
-            .expect("expected `#[rpc(name = \"foo\")]` attribute");
+            .ok_or_else(|| {
+                let msg = "expected `#[rpc(name = \"foo\")]` attribute";
+                syn::Error::new_spanned(&method.sig, msg)
+            })?;
 
-        let mut rpc_name = String::new();
+        let mut rpc_name = None;
+        let mut aliases = Vec::new();
+        let mut max_concurrency = None;
+        let mut latest_wins = false;
+        let mut layer = None;
         attr.parse_nested_meta(|meta| {
             if meta.path.is_ident("name") {
-                let s: LitStr = meta.value().and_then(|v| v.parse())?;
-                rpc_name = s.value();
+                if rpc_name.is_some() {
+                    return Err(meta.error("duplicate `name` in `#[rpc]`"));
+                }
+                rpc_name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("alias") {
+                aliases.push(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("max_concurrency") {
+                let n: LitInt = meta.value().and_then(|v| v.parse())?;
+                max_concurrency = Some(n.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("latest_wins") {
+                latest_wins = true;
+                Ok(())
+            } else if meta.path.is_ident("layer") {
+                if layer.is_some() {
+                    return Err(meta.error("duplicate `layer` in `#[rpc]`"));
+                }
+                layer = Some(parse_layer_path(meta.value()?.parse()?)?);
                 Ok(())
             } else {
-                Err(meta.error("expected `name` identifier in `#[rpc]`"))
+                Err(meta.error(
+                    "expected `name`, `alias`, `max_concurrency`, `latest_wins` or `layer` \
+                     identifier in `#[rpc]`",
+                ))
             }
-        })
-        .unwrap();
+        })?;
+
+        let rpc_name = rpc_name.ok_or_else(|| {
+            syn::Error::new_spanned(attr, "expected `#[rpc(name = \"foo\")]` attribute")
+        })?;
+
+        if latest_wins && max_concurrency.is_none() {
+            let msg = "`latest_wins` requires `max_concurrency` in `#[rpc]`";
+            return Err(syn::Error::new_spanned(attr, msg));
+        }
+
+        for name in std::iter::once(&rpc_name).chain(&aliases) {
+            let value = name.value();
+            if BUILTIN_METHODS.contains(&value.as_str()) {
+                let msg = format!("`{value}` is handled by the service and cannot be overridden");
+                return Err(syn::Error::new_spanned(name, msg));
+            }
+
+            if let Some(first) = names.insert(value.clone(), name.clone()) {
+                let mut err =
+                    syn::Error::new_spanned(name, format!("duplicate rpc name `{value}`"));
+                err.combine(syn::Error::new_spanned(first, "first used here"));
+                return Err(err);
+            }
+        }
+
+        if let Some(extra) = method.sig.inputs.iter().nth(2) {
+            let msg = "expected at most one parameter besides `&self`";
+            return Err(syn::Error::new_spanned(extra, msg));
+        }
 
         let params = method.sig.inputs.iter().nth(1).and_then(|arg| match arg {
             FnArg::Typed(pat) => Some(&*pat.ty),
@@ -77,17 +227,39 @@ fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
         };
 
         calls.push(MethodCall {
-            rpc_name,
+            rpc_name: rpc_name.value(),
+            aliases: aliases.iter().map(LitStr::value).collect(),
+            max_concurrency,
+            latest_wins,
+            layer,
             handler_name: &method.sig.ident,
             params,
             result,
         });
     }
 
-    calls
+    Ok(calls)
+}
+
+/// Parses the value of a `layer = "..."` key into the path of the function creating the layer.
+fn parse_layer_path(lit: LitStr) -> syn::Result<syn::Path> {
+    lit.parse().map_err(|_| {
+        let msg = "expected the path of a function returning a layer, e.g. `MyLayer::new`";
+        syn::Error::new_spanned(&lit, msg)
+    })
+}
+
+/// Parses the value of a `crate = "..."` key into a path.
+fn parse_crate_path(lit: LitStr) -> syn::Result<syn::Path> {
+    lit.parse_with(syn::Path::parse_mod_style)
+        .map_err(|_| syn::Error::new_spanned(&lit, "expected a path, e.g. `::tower_lsp`"))
 }
 
-fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_macro2::TokenStream {
+fn gen_server_router(
+    krate: &syn::Path,
+    trait_name: &syn::Ident,
+    methods: &[MethodCall],
+) -> proc_macro2::TokenStream {
     let route_registrations: proc_macro2::TokenStream = methods
         .iter()
         .map(|method| {
@@ -99,6 +271,10 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
                 "shutdown" => quote! { layers::Shutdown::new(state.clone(), pending.clone()) },
                 _ => quote! { layers::Normal::new(state.clone(), pending.clone()) },
             };
+            let layer = match &method.layer {
+                Some(custom) => quote! { tower::layer::util::Stack::new(#custom(), #layer) },
+                None => layer,
+            };
 
             // NOTE: In a perfect world, we could simply loop over each `MethodCall` and emit
             // `router.method(#rpc_name, S::#handler);` for each. While such an approach
@@ -111,54 +287,147 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
             // passing it to `.method`, as documented in this GitHub issue:
             //
             // https://github.com/dtolnay/async-trait/issues/167
-            match (method.params, method.result) {
-                (Some(params), Some(result)) => quote! {
-                    async fn #handler<S: #trait_name>(server: &S, params: #params) -> #result {
-                        server.#handler(params).await
+            //
+            // Requests additionally run with a `RequestContext` exposing the progress tokens
+            // embedded in their params.
+            let wrapper = match (method.params, method.result) {
+                (Some(params), Some(result)) => {
+                    let (work_done, partial_result) = progress_params(params);
+                    let work_done = if work_done {
+                        quote! { params.work_done_progress_params.work_done_token.clone() }
+                    } else {
+                        quote! { None }
+                    };
+                    let partial_result = if partial_result {
+                        quote! { params.partial_result_params.partial_result_token.clone() }
+                    } else {
+                        quote! { None }
+                    };
+
+                    quote! {
+                        async fn #handler<S: #trait_name>(server: &S, params: #params) -> #result {
+                            let context = RequestContext::new(#work_done, #partial_result);
+                            context.scope(server.#handler(params)).await
+                        }
                     }
-                    router.method(#rpc_name, #handler, #layer);
-                },
+                }
                 (None, Some(result)) => quote! {
                     async fn #handler<S: #trait_name>(server: &S) -> #result {
-                        server.#handler().await
+                        RequestContext::default().scope(server.#handler()).await
                     }
-                    router.method(#rpc_name, #handler, #layer);
                 },
                 (Some(params), None) => quote! {
                     async fn #handler<S: #trait_name>(server: &S, params: #params) {
                         server.#handler(params).await
                     }
-                    router.method(#rpc_name, #handler, #layer);
                 },
                 (None, None) => quote! {
                     async fn #handler<S: #trait_name>(server: &S) {
                         server.#handler().await
                     }
-                    router.method(#rpc_name, #handler, #layer);
                 },
+            };
+
+            // Reported at the type of the params, rather than as an unsatisfied bound of the
+            // router.
+            let params_check = method.params.map(|params| {
+                quote_spanned! {params.span()=>
+                    const _: fn() = || {
+                        let _ = serde_json::from_value::<#params>;
+                    };
+                }
+            });
+
+            // Aliases share the handler of the primary name, including its layer.
+            let names = std::iter::once(rpc_name).chain(&method.aliases);
+            quote! {
+                #params_check
+                #wrapper
+                #(router.method(#names, #handler, #layer);)*
             }
         })
         .collect();
 
+    let method_names: Vec<&str> = methods
+        .iter()
+        .flat_map(|method| std::iter::once(&method.rpc_name).chain(&method.aliases))
+        .map(String::as_str)
+        .chain(BUILTIN_METHODS)
+        .collect();
+    let (displacements, method_table) = perfect_hash(&method_names);
+    let bucket_count = displacements.len();
+    let table_len = method_table.len();
+
+    let concurrency_limits = methods.iter().filter_map(|method| {
+        let max = method.max_concurrency?;
+        let latest_wins = method.latest_wins;
+        let names = std::iter::once(&method.rpc_name).chain(&method.aliases);
+        Some(quote! { #((#names, #max, #latest_wins),)* })
+    });
+
     quote! {
         mod generated {
             use std::sync::Arc;
             use std::future::{Future, Ready};
 
-            use lsp_types::*;
-            use lsp_types::notification::*;
-            use lsp_types::request::*;
+            use #krate::lsp_types::*;
+            use #krate::lsp_types::notification::*;
+            use #krate::lsp_types::request::*;
             use serde_json::Value;
 
             use super::#trait_name;
-            use crate::jsonrpc::{Result, Router};
-            use crate::service::{layers, Client, Pending, ServerState, State, ExitedError};
+            use #krate::jsonrpc::{Result, Router};
+            use #krate::service::{layers, Client, Pending, RequestContext, ServerState, ExitedError};
 
             fn cancel_request(params: CancelParams, p: &Pending) -> Ready<()> {
                 p.cancel(&params.id.into());
                 std::future::ready(())
             }
 
+            fn set_trace(params: SetTraceParams, s: &ServerState) -> Ready<()> {
+                s.set_trace(params.value);
+                std::future::ready(())
+            }
+
+            /// Default concurrency limits of the methods registered by `register_lsp_methods()`, as
+            /// `(name, max_concurrency, latest_wins)` tuples.
+            pub(crate) const CONCURRENCY_LIMITS: &[(&str, usize, bool)] = &[
+                #(#concurrency_limits)*
+            ];
+
+            /// Seeds of the perfect hash of the methods registered by `register_lsp_methods()`,
+            /// one per bucket of methods sharing the same unseeded hash.
+            const METHOD_DISPLACEMENTS: [u32; #bucket_count] = [#(#displacements),*];
+
+            /// The methods registered by `register_lsp_methods()`, at the slots given by their
+            /// perfect hash.
+            const METHOD_TABLE: [&str; #table_len] = [#(#method_table),*];
+
+            /// Must match `method_hash()` in `tower-lsp-macros`.
+            fn method_hash(seed: u32, name: &str) -> u64 {
+                let mut hash = 0xcbf2_9ce4_8422_2325 ^ u64::from(seed);
+                for byte in name.bytes() {
+                    hash ^= u64::from(byte);
+                    hash = hash.wrapping_mul(0x0100_0000_01b3);
+                }
+                hash ^= hash >> 32;
+                hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                hash ^ (hash >> 29)
+            }
+
+            /// Returns the `'static` name of the method `name`, if it is one of the LSP methods
+            /// registered by `register_lsp_methods()`.
+            ///
+            /// This hashes `name` twice and compares it to a single candidate, instead of
+            /// comparing it to every method name in turn.
+            pub(crate) fn lsp_method(name: &str) -> Option<&'static str> {
+                let bucket = method_hash(0, name) % METHOD_DISPLACEMENTS.len() as u64;
+                let seed = METHOD_DISPLACEMENTS[bucket as usize];
+                let slot = method_hash(seed, name) % METHOD_TABLE.len() as u64;
+                let candidate = METHOD_TABLE[slot as usize];
+                (candidate == name).then_some(candidate)
+            }
+
             pub(crate) fn register_lsp_methods<S>(
                 mut router: Router<S, ExitedError>,
                 state: Arc<ServerState>,
@@ -176,6 +445,12 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
                     move |_: &S, params| cancel_request(params, &p),
                     tower::layer::util::Identity::new(),
                 );
+                let s = state.clone();
+                router.method(
+                    "$/setTrace",
+                    move |_: &S, params| set_trace(params, &s),
+                    layers::Normal::new(state.clone(), pending.clone()),
+                );
                 router.method(
                     "exit",
                     |_: &S| std::future::ready(()),
@@ -187,3 +462,809 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
         }
     }
 }
+
+/// Hashes a method name with FNV-1a, followed by a finalizer mixing the high bits into the low
+/// ones.
+///
+/// Must match the `method_hash()` emitted by `gen_server_router()`.
+fn method_hash(seed: u32, name: &str) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ u64::from(seed);
+    for byte in name.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 32;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^ (hash >> 29)
+}
+
+/// Builds a minimal perfect hash of `names`, using the "hash and displace" scheme.
+///
+/// Names are first split into buckets by their unseeded hash. Going from the largest bucket to
+/// the smallest, each bucket is given the first seed which sends all of its names to distinct free
+/// slots of the table. Returns the seed of every bucket and the resulting table.
+fn perfect_hash<'a>(names: &[&'a str]) -> (Vec<u32>, Vec<&'a str>) {
+    let bucket_count = (names.len() + 3) / 4;
+    let mut buckets = vec![Vec::new(); bucket_count];
+    for &name in names {
+        buckets[(method_hash(0, name) % bucket_count as u64) as usize].push(name);
+    }
+
+    let mut order: Vec<usize> = (0..bucket_count).collect();
+    order.sort_by_key(|&bucket| std::cmp::Reverse(buckets[bucket].len()));
+
+    let mut displacements = vec![0; bucket_count];
+    let mut table: Vec<Option<&str>> = vec![None; names.len()];
+    for bucket in order {
+        let bucket_names = &buckets[bucket];
+        if bucket_names.is_empty() {
+            break;
+        }
+
+        'seeds: for seed in 0.. {
+            let mut slots = Vec::with_capacity(bucket_names.len());
+            for name in bucket_names {
+                let slot = (method_hash(seed, name) % table.len() as u64) as usize;
+                if table[slot].is_some() || slots.contains(&slot) {
+                    continue 'seeds;
+                }
+                slots.push(slot);
+            }
+
+            for (slot, &name) in slots.into_iter().zip(bucket_names) {
+                table[slot] = Some(name);
+            }
+            displacements[bucket] = seed;
+            break;
+        }
+    }
+
+    let table = table.into_iter().map(|name| name.unwrap()).collect();
+    (displacements, table)
+}
+
+/// Returns whether the LSP params type `ty` embeds `WorkDoneProgressParams` and
+/// `PartialResultParams`, respectively.
+///
+/// Types are matched by name, so this must be kept in sync with `lsp-types`.
+fn progress_params(ty: &syn::Type) -> (bool, bool) {
+    let name = match ty {
+        syn::Type::Path(path) => match path.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => return (false, false),
+        },
+        _ => return (false, false),
+    };
+
+    match &name[..] {
+        "CallHierarchyIncomingCallsParams"
+        | "CallHierarchyOutgoingCallsParams"
+        | "CodeActionParams"
+        | "CodeLensParams"
+        | "ColorPresentationParams"
+        | "CompletionParams"
+        | "DocumentColorParams"
+        | "DocumentDiagnosticParams"
+        | "DocumentHighlightParams"
+        | "DocumentLinkParams"
+        | "DocumentSymbolParams"
+        | "FoldingRangeParams"
+        | "GotoDeclarationParams"
+        | "GotoDefinitionParams"
+        | "GotoImplementationParams"
+        | "GotoTypeDefinitionParams"
+        | "MonikerParams"
+        | "ReferenceParams"
+        | "SelectionRangeParams"
+        | "SemanticTokensDeltaParams"
+        | "SemanticTokensParams"
+        | "SemanticTokensRangeParams"
+        | "TypeHierarchySubtypesParams"
+        | "TypeHierarchySupertypesParams"
+        | "WorkspaceDiagnosticParams"
+        | "WorkspaceSymbolParams" => (true, true),
+        "CallHierarchyPrepareParams"
+        | "DocumentFormattingParams"
+        | "DocumentRangeFormattingParams"
+        | "ExecuteCommandParams"
+        | "HoverParams"
+        | "InlayHintParams"
+        | "InlineValueParams"
+        | "LinkedEditingRangeParams"
+        | "RenameParams"
+        | "SignatureHelpParams"
+        | "TypeHierarchyPrepareParams" => (true, false),
+        _ => (false, false),
+    }
+}
+
+/// Macro for generating a Debug Adapter Protocol router from a debug adapter trait.
+///
+/// This procedural macro annotates the `tower_lsp::dap::DebugAdapter` trait and generates a
+/// corresponding `register_dap_commands()` function which registers all the methods on that trait
+/// as handlers of a `jsonrpc::Router`. Each method of the trait must carry a
+/// `#[dap(command = "...")]` attribute with its DAP command name, and return a `jsonrpc::Result`.
+/// Methods without arguments ignore those sent by the client, if any.
+///
+/// Like [`macro@rpc`], the generated code refers to the crate defining the trait through the path
+/// given by the optional `crate` key, which defaults to `crate`. The crate must provide
+/// `dap::types` and `jsonrpc::{Result, Router}`, and depend on `serde_json` and `tower`.
+#[proc_macro_attribute]
+pub fn dap(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut krate = None;
+    let mut is_method = false;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            krate = Some(parse_crate_path(meta.value()?.parse()?)?);
+            Ok(())
+        } else if meta.path.is_ident("command") {
+            let _: LitStr = meta.value()?.parse()?;
+            is_method = true;
+            Ok(())
+        } else {
+            Err(meta.error("expected `crate` or `command` identifier in `#[dap]`"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    if is_method {
+        return item;
+    }
+
+    let krate = krate.unwrap_or_else(|| syn::parse_quote!(crate));
+    let adapter_trait = parse_macro_input!(item as ItemTrait);
+    let commands = match parse_dap_commands(&adapter_trait) {
+        Ok(commands) => commands,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let router_fn = gen_dap_router(&krate, &adapter_trait.ident, &commands);
+
+    let tokens = quote! {
+        #adapter_trait
+        #router_fn
+    };
+
+    tokens.into()
+}
+
+struct DapCommand<'a> {
+    command: LitStr,
+    handler_name: &'a syn::Ident,
+    arguments: Option<&'a syn::Type>,
+    result: &'a syn::Type,
+}
+
+fn parse_dap_commands(adapter_trait: &ItemTrait) -> syn::Result<Vec<DapCommand<'_>>> {
+    let mut commands = Vec::new();
+
+    for item in &adapter_trait.items {
+        let method = match item {
+            TraitItem::Fn(m) => m,
+            _ => continue,
+        };
+
+        let attr = method
+            .attrs
+            .iter()
+            .find(|attr| attr.meta.path().is_ident("dap"))
+            .ok_or_else(|| {
+                let msg = "expected `#[dap(command = \"foo\")]` attribute";
+                syn::Error::new_spanned(&method.sig, msg)
+            })?;
+
+        let mut command = None;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("command") {
+                command = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `command` identifier in `#[dap]`"))
+            }
+        })?;
+
+        let command = command.ok_or_else(|| {
+            syn::Error::new_spanned(attr, "expected `#[dap(command = \"foo\")]` attribute")
+        })?;
+
+        let arguments = method.sig.inputs.iter().nth(1).and_then(|arg| match arg {
+            FnArg::Typed(pat) => Some(&*pat.ty),
+            _ => None,
+        });
+
+        let result = match &method.sig.output {
+            ReturnType::Type(_, ty) => &**ty,
+            ReturnType::Default => {
+                let msg = "DAP commands must return a `jsonrpc::Result`";
+                return Err(syn::Error::new_spanned(&method.sig, msg));
+            }
+        };
+
+        commands.push(DapCommand {
+            command,
+            handler_name: &method.sig.ident,
+            arguments,
+            result,
+        });
+    }
+
+    Ok(commands)
+}
+
+fn gen_dap_router(
+    krate: &syn::Path,
+    trait_name: &syn::Ident,
+    commands: &[DapCommand],
+) -> proc_macro2::TokenStream {
+    // Like in `gen_server_router()`, `async-trait` methods are wrapped in regular `async fn`s.
+    let registrations = commands.iter().map(|cmd| {
+        let DapCommand {
+            command,
+            handler_name: handler,
+            result,
+            ..
+        } = cmd;
+
+        let wrapper = match cmd.arguments {
+            Some(arguments) => quote! {
+                async fn #handler<A: #trait_name>(adapter: &A, arguments: #arguments) -> #result {
+                    adapter.#handler(arguments).await
+                }
+            },
+            None => quote! {
+                async fn #handler<A: #trait_name>(adapter: &A, _: Value) -> #result {
+                    adapter.#handler().await
+                }
+            },
+        };
+
+        quote! {
+            #wrapper
+            router.method(#command, #handler, tower::layer::util::Identity::new());
+        }
+    });
+
+    quote! {
+        mod generated {
+            use serde_json::Value;
+
+            use super::#trait_name;
+            use #krate::dap::types::*;
+            use #krate::jsonrpc::{Result, Router};
+
+            pub(crate) fn register_dap_commands<A: #trait_name>(router: &mut Router<A>) {
+                #(#registrations)*
+            }
+        }
+    }
+}
+
+/// Attribute macro implementing `Protocol` for a trait declaring the methods of a JSON-RPC
+/// protocol.
+///
+/// See the documentation of `tower_lsp::protocol::jsonrpc_protocol` for details.
+#[proc_macro_attribute]
+pub fn jsonrpc_protocol(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut krate = None;
+    let mut methods = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            krate = Some(parse_crate_path(meta.value()?.parse()?)?);
+            Ok(())
+        } else if meta.path.is_ident("methods") {
+            methods = Some(meta.value()?.parse::<syn::Expr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `crate` or `methods` identifier in `#[jsonrpc_protocol]`"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    let krate = krate.unwrap_or_else(|| syn::parse_quote!(::tower_lsp));
+    let methods = methods.unwrap_or_else(|| syn::parse_quote!(#krate::protocol::MethodTable::LSP));
+    let mut protocol_trait = parse_macro_input!(item as ItemTrait);
+
+    match gen_protocol(&krate, &methods, &mut protocol_trait) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn gen_protocol(
+    krate: &syn::Path,
+    methods: &syn::Expr,
+    protocol_trait: &mut ItemTrait,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let trait_name = &protocol_trait.ident;
+    let mut registrations = Vec::new();
+
+    for item in &mut protocol_trait.items {
+        let method = match item {
+            TraitItem::Fn(m) => m,
+            _ => continue,
+        };
+
+        // The `#[rpc]` attributes are consumed here, rather than expanded by `rpc()`.
+        let pos = method
+            .attrs
+            .iter()
+            .position(|a| a.meta.path().is_ident("rpc"));
+        let attr = match pos {
+            Some(pos) => method.attrs.remove(pos),
+            None => {
+                let msg = "expected `#[rpc(name = \"foo\")]` attribute";
+                return Err(syn::Error::new_spanned(&method.sig, msg));
+            }
+        };
+
+        let mut rpc_name = None;
+        let mut layer = None;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                rpc_name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("layer") {
+                layer = Some(parse_layer_path(meta.value()?.parse()?)?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name` or `layer` identifier in `#[rpc]`"))
+            }
+        })?;
+        let rpc_name = rpc_name.ok_or_else(|| {
+            syn::Error::new_spanned(&attr, "expected `#[rpc(name = \"foo\")]` attribute")
+        })?;
+
+        let handler = &method.sig.ident;
+        let params = method.sig.inputs.iter().nth(1).and_then(|arg| match arg {
+            FnArg::Typed(pat) => Some(&*pat.ty),
+            _ => None,
+        });
+
+        // Like in `gen_server_router()`, `async-trait` methods are wrapped in regular `async fn`s.
+        let wrapper = match (params, &method.sig.output) {
+            (Some(params), ReturnType::Type(_, result)) => quote! {
+                async fn #handler<S: #trait_name>(server: &S, params: #params) -> #result {
+                    server.#handler(params).await
+                }
+            },
+            (None, ReturnType::Type(_, result)) => quote! {
+                async fn #handler<S: #trait_name>(server: &S) -> #result {
+                    server.#handler().await
+                }
+            },
+            (Some(params), ReturnType::Default) => quote! {
+                async fn #handler<S: #trait_name>(server: &S, params: #params) {
+                    server.#handler(params).await
+                }
+            },
+            (None, ReturnType::Default) => quote! {
+                async fn #handler<S: #trait_name>(server: &S) {
+                    server.#handler().await
+                }
+            },
+        };
+
+        let register = match layer {
+            Some(layer) => quote! { router.method_with_layer(#rpc_name, #handler, #layer()); },
+            None => quote! { router.method(#rpc_name, #handler); },
+        };
+        registrations.push(quote! {
+            #wrapper
+            #register
+        });
+    }
+
+    let vis = &protocol_trait.vis;
+    let name = format_ident!("{}Protocol", trait_name);
+    let doc = format!("Registers the methods of [`{trait_name}`] on a `ProtocolRouter`.");
+
+    Ok(quote! {
+        #protocol_trait
+
+        #[doc = #doc]
+        #[derive(Clone, Copy, Debug, Default)]
+        #vis struct #name;
+
+        impl<S: #trait_name> #krate::protocol::Protocol<S> for #name {
+            fn methods(&self) -> #krate::protocol::MethodTable {
+                #methods
+            }
+
+            fn register(&self, router: &mut #krate::protocol::ProtocolRouter<S>) {
+                #(#registrations)*
+            }
+        }
+    })
+}
+
+/// Derive macro for declaring custom LSP protocol extensions.
+///
+/// See the documentation of `tower_lsp::LspExtension` for details.
+#[proc_macro_derive(LspExtension, attributes(lsp))]
+pub fn lsp_extension(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    match gen_extension(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct ExtensionMethod<'a> {
+    rpc_name: LitStr,
+    is_request: bool,
+    marker: syn::Ident,
+    handler_name: syn::Ident,
+    params: Option<&'a syn::Type>,
+    result: Option<&'a syn::Type>,
+    invalid_params: Option<syn::Ident>,
+    experimental: Option<LitStr>,
+}
+
+/// Returns the path given by the `#[lsp(crate = "...")]` attribute of `input`, if any, or
+/// `::tower_lsp` otherwise.
+fn parse_extension_crate(input: &DeriveInput) -> syn::Result<syn::Path> {
+    let mut krate = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("lsp"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = Some(parse_crate_path(meta.value()?.parse()?)?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `crate` identifier"))
+            }
+        })?;
+    }
+
+    Ok(krate.unwrap_or_else(|| syn::parse_quote!(::tower_lsp)))
+}
+
+fn parse_extension_methods(input: &DeriveInput) -> syn::Result<Vec<ExtensionMethod<'_>>> {
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            let msg = "`LspExtension` can only be derived for enums";
+            return Err(syn::Error::new_spanned(input, msg));
+        }
+    };
+
+    let mut methods = Vec::new();
+
+    for variant in variants {
+        let attr = variant
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("lsp"))
+            .ok_or_else(|| {
+                let msg =
+                    "expected `#[lsp(request = \"foo\")]` or `#[lsp(notification = \"foo\")]`";
+                syn::Error::new_spanned(variant, msg)
+            })?;
+
+        let mut kind = None;
+        let mut invalid_params = None;
+        let mut experimental = None;
+        attr.parse_nested_meta(|meta| {
+            let is_request = if meta.path.is_ident("request") {
+                true
+            } else if meta.path.is_ident("notification") {
+                false
+            } else if meta.path.is_ident("invalid_params") {
+                let policy: LitStr = meta.value()?.parse()?;
+                let variant = match &policy.value()[..] {
+                    "invalid_params" => "InvalidParams",
+                    "ignore" => "Ignore",
+                    "request_failed" => "RequestFailed",
+                    _ => {
+                        let msg = "expected `invalid_params`, `ignore` or `request_failed`";
+                        return Err(syn::Error::new_spanned(policy, msg));
+                    }
+                };
+                invalid_params = Some(format_ident!("{}", variant));
+                return Ok(());
+            } else if meta.path.is_ident("experimental") {
+                experimental = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else {
+                return Err(meta.error("expected `request` or `notification` identifier"));
+            };
+
+            let name: LitStr = meta.value()?.parse()?;
+            kind = Some((name, is_request));
+            Ok(())
+        })?;
+
+        let (rpc_name, is_request) =
+            kind.ok_or_else(|| syn::Error::new_spanned(attr, "missing method name"))?;
+
+        let fields: Vec<_> = match &variant.fields {
+            Fields::Unnamed(fields) => fields.unnamed.iter().map(|f| &f.ty).collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Named(_) => {
+                let msg = "expected a tuple or unit variant";
+                return Err(syn::Error::new_spanned(variant, msg));
+            }
+        };
+
+        let (params, result) = match (is_request, &fields[..]) {
+            (true, [params, result]) => (Some(*params), Some(*result)),
+            (true, _) => {
+                let msg = "expected request variant of the form `Variant(Params, Result)`";
+                return Err(syn::Error::new_spanned(variant, msg));
+            }
+            (false, []) => (None, None),
+            (false, [params]) => (Some(*params), None),
+            (false, _) => {
+                let msg =
+                    "expected notification variant of the form `Variant` or `Variant(Params)`";
+                return Err(syn::Error::new_spanned(variant, msg));
+            }
+        };
+
+        // Methods declared with `()` params are treated as parameter-less.
+        let params = params.filter(|ty| !matches!(ty, syn::Type::Tuple(t) if t.elems.is_empty()));
+
+        methods.push(ExtensionMethod {
+            rpc_name,
+            is_request,
+            marker: format_ident!("__{}{}", input.ident, variant.ident),
+            handler_name: format_ident!("{}", to_snake_case(&variant.ident.to_string())),
+            params,
+            result,
+            invalid_params,
+            experimental,
+        });
+    }
+
+    Ok(methods)
+}
+
+fn gen_extension(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let krate = parse_extension_crate(input)?;
+    let methods = parse_extension_methods(input)?;
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let handler_trait = format_ident!("{}Handler", name);
+    let client_trait = format_ident!("{}Client", name);
+
+    let mut marker_types = Vec::new();
+    let mut handler_fns = Vec::new();
+    let mut registrations = Vec::new();
+    let mut client_decls = Vec::new();
+    let mut client_fns = Vec::new();
+    let mut experimental_caps = Vec::new();
+
+    for method in &methods {
+        let rpc_name = &method.rpc_name;
+        let variant = &method.marker;
+        let handler = &method.handler_name;
+        let params_ty = method.params.map_or_else(|| quote!(()), |ty| quote!(#ty));
+        let (params_arg, params_val, params_fwd) = match method.params {
+            Some(ty) => (quote!(, params: #ty), quote!(params), quote!(params)),
+            None => (quote!(), quote!(()), quote!()),
+        };
+
+        // Experimental methods are only sent if the client advertised the capability.
+        let (request_guard, notification_guard) = match &method.experimental {
+            Some(capability) => {
+                experimental_caps.push(capability);
+                (
+                    quote! {
+                        if !self.has_experimental_capability(#capability) {
+                            return Err(#krate::jsonrpc::Error::method_not_found());
+                        }
+                    },
+                    quote! {
+                        if !self.has_experimental_capability(#capability) {
+                            return;
+                        }
+                    },
+                )
+            }
+            None => (quote!(), quote!()),
+        };
+
+        if let Some(policy) = &method.invalid_params {
+            registrations.push(quote! {
+                let builder = builder
+                    .on_invalid_params(#rpc_name, #krate::InvalidParamsPolicy::#policy);
+            });
+        }
+
+        if method.is_request {
+            let result = method.result.unwrap();
+            marker_types.push(quote! {
+                #[doc(hidden)]
+                #[allow(non_camel_case_types)]
+                enum #variant {}
+
+                impl #krate::lsp_types::request::Request for #variant {
+                    type Params = #params_ty;
+                    type Result = #result;
+                    const METHOD: &'static str = #rpc_name;
+                }
+            });
+            handler_fns.push(quote! {
+                #[doc = concat!("Handles the `", #rpc_name, "` request.")]
+                async fn #handler(&self #params_arg) -> #krate::jsonrpc::Result<#result> {
+                    let _ = #params_val;
+                    Err(#krate::jsonrpc::Error::method_not_found())
+                }
+            });
+            registrations.push(quote! {
+                async fn #handler<S: #handler_trait>(
+                    server: &S #params_arg
+                ) -> #krate::jsonrpc::Result<#result> {
+                    server.#handler(#params_fwd).await
+                }
+                let builder = builder.custom_method(#rpc_name, #handler);
+            });
+            client_decls.push(quote! {
+                #[doc = concat!("Sends the `", #rpc_name, "` request to the client.")]
+                async fn #handler(&self #params_arg) -> #krate::jsonrpc::Result<#result>;
+            });
+            client_fns.push(quote! {
+                async fn #handler(&self #params_arg) -> #krate::jsonrpc::Result<#result> {
+                    #request_guard
+                    self.send_request::<#variant>(#params_val).await
+                }
+            });
+        } else {
+            marker_types.push(quote! {
+                #[doc(hidden)]
+                #[allow(non_camel_case_types)]
+                enum #variant {}
+
+                impl #krate::lsp_types::notification::Notification for #variant {
+                    type Params = #params_ty;
+                    const METHOD: &'static str = #rpc_name;
+                }
+            });
+            handler_fns.push(quote! {
+                #[doc = concat!("Handles the `", #rpc_name, "` notification.")]
+                async fn #handler(&self #params_arg) {
+                    let _ = #params_val;
+                }
+            });
+            registrations.push(quote! {
+                async fn #handler<S: #handler_trait>(server: &S #params_arg) {
+                    server.#handler(#params_fwd).await
+                }
+                let builder = builder.custom_method(#rpc_name, #handler);
+            });
+            client_decls.push(quote! {
+                #[doc = concat!("Sends the `", #rpc_name, "` notification to the client.")]
+                async fn #handler(&self #params_arg);
+            });
+            client_fns.push(quote! {
+                async fn #handler(&self #params_arg) {
+                    #notification_guard
+                    self.send_notification::<#variant>(#params_val).await
+                }
+            });
+        }
+    }
+
+    let handler_doc = format!("Server-side handlers for the methods declared by [`{name}`].");
+    let client_doc = format!("Client-side helpers for the methods declared by [`{name}`].");
+
+    Ok(quote! {
+        #(#marker_types)*
+
+        #[doc = #handler_doc]
+        ///
+        /// Every method has a default implementation, which ignores notifications and answers
+        /// requests with a `Method not found` error.
+        #[#krate::async_trait]
+        #vis trait #handler_trait: #krate::LanguageServer {
+            #(#handler_fns)*
+        }
+
+        #[doc = #client_doc]
+        #[#krate::async_trait]
+        #vis trait #client_trait {
+            #(#client_decls)*
+        }
+
+        #[#krate::async_trait]
+        impl #client_trait for #krate::Client {
+            #(#client_fns)*
+        }
+
+        impl #name {
+            /// Registers a route for every method of this extension on `builder`.
+            #vis fn register<S>(
+                builder: #krate::LspServiceBuilder<S>,
+            ) -> #krate::LspServiceBuilder<S>
+            where
+                S: #handler_trait,
+            {
+                #(#registrations)*
+                builder
+            }
+
+            /// Returns the experimental capabilities of this extension, to be advertised in the
+            /// `experimental` field of `ServerCapabilities`.
+            #vis fn experimental_capabilities() -> #krate::lsp_types::LSPAny {
+                #[allow(unused_mut)]
+                let mut caps = #krate::lsp_types::LSPObject::new();
+                #(caps.insert(#experimental_caps.into(), true.into());)*
+                #krate::lsp_types::LSPAny::Object(caps)
+            }
+        }
+    })
+}
+
+/// Derive macro for typed `telemetry/event` payloads.
+///
+/// See the documentation of `tower_lsp::telemetry::TelemetryEvent` for details.
+#[proc_macro_derive(TelemetryEvent, attributes(telemetry))]
+pub fn telemetry_event(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    match gen_telemetry_event(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn gen_telemetry_event(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    match &input.data {
+        Data::Struct(data) if !matches!(data.fields, Fields::Unnamed(_)) => {}
+        _ => {
+            let msg = "`TelemetryEvent` can only be derived for structs with named fields";
+            return Err(syn::Error::new_spanned(input, msg));
+        }
+    }
+
+    let mut krate = None;
+    let mut event_name = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("telemetry"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                event_name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("crate") {
+                krate = Some(parse_crate_path(meta.value()?.parse()?)?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name` or `crate` identifier"))
+            }
+        })?;
+    }
+
+    let krate = krate.unwrap_or_else(|| syn::parse_quote!(::tower_lsp));
+    let name = &input.ident;
+    let event_name = event_name.unwrap_or_else(|| {
+        let snake = to_snake_case(&name.to_string());
+        LitStr::new(&snake, name.span())
+    });
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::telemetry::TelemetryEvent for #name #ty_generics #where_clause {
+            const NAME: &'static str = #event_name;
+        }
+    })
+}
+
+fn to_snake_case(ident: &str) -> String {
+    let mut snake = String::with_capacity(ident.len() + 4);
+    for (i, c) in ident.char_indices() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}